@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::{self, TextEdit, Uri};
+
+use crate::manifest;
+use crate::text::Source;
+
+/// Command adding a dependency to a manifest, arguments are [`AddArgs`].
+pub const ADD_DEPENDENCY: &str = "crates-io.addDependency";
+
+/// Sections a dependency can be added to.
+const SECTIONS: [&str; 4] = [
+    "dependencies",
+    "dev-dependencies",
+    "build-dependencies",
+    "workspace.dependencies",
+];
+
+/// Add the crate `name` to `section` of the manifest at `uri`.
+///
+/// Without `version` the locked version is required if the crate is in the `Cargo.lock`,
+/// otherwise the newest release.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddArgs {
+    pub uri: Uri,
+    pub name: String,
+    #[serde(default = "default_section")]
+    pub section: String,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+fn default_section() -> String {
+    "dependencies".into()
+}
+
+/// Result of [`ADD_DEPENDENCY`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddResult {
+    /// The inserted requirement
+    pub req: String,
+    /// The version in the `Cargo.lock` the requirement was taken from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked: Option<String>,
+    /// Whether the client applied the edit
+    pub applied: bool,
+}
+
+/// Whether dependencies can be added to `section`, target sections are not supported.
+pub fn is_section(section: &str) -> bool {
+    SECTIONS.contains(&section)
+}
+
+/// Edit inserting `name = "req"` at the end of `section`, which is appended if there is none.
+///
+/// `None` if the section already declares the crate, as a key or a `[section.name]` table.
+pub fn edits(source: &Source, section: &str, name: &str, req: &str) -> Option<Vec<TextEdit>> {
+    let text = source.text.as_str();
+    let headers = manifest::headers(text);
+    let table = format!("{section}.{name}");
+    if headers.iter().any(|(header, _, _)| *header == table) {
+        return None;
+    }
+    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let entry = format!("{name} = \"{req}\"");
+    let current = headers
+        .iter()
+        .position(|(header, _, array)| header == section && !array);
+    let (at, new_text) = match current {
+        Some(current) => {
+            let start = headers[current].1.end;
+            let end = headers
+                .get(current + 1)
+                .map_or(text.len(), |(_, next, _)| next.start);
+            let declared = text[start..end]
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .any(|(key, _)| key.trim().trim_matches(['"', '\'']) == name);
+            if declared {
+                return None;
+            }
+            let at = start + text[start..end].trim_end().len();
+            (at, format!("{eol}{entry}"))
+        }
+        None => {
+            let newline = if text.is_empty() || text.ends_with('\n') {
+                ""
+            } else {
+                eol
+            };
+            let blank = if text.trim().is_empty() { "" } else { eol };
+            (
+                text.len(),
+                format!("{newline}{blank}[{section}]{eol}{entry}{eol}"),
+            )
+        }
+    };
+    let at = source.position(at)?;
+    Some(vec![TextEdit {
+        range: lsp_types::Range { start: at, end: at },
+        new_text,
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dry_run::{self, Expect};
+    use crate::text::Encoding;
+
+    fn add(text: &str, section: &str, name: &str) -> Option<String> {
+        let source = Source::new(text, Encoding::Utf8);
+        let edits = edits(&source, section, name, "1.0")?;
+        dry_run::check(&source, &edits, Expect::Added).unwrap();
+        Some(dry_run::apply(&source, &edits).unwrap())
+    }
+
+    #[test]
+    fn appends_to_the_section() {
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n\n[dev-dependencies]\nrand = \"0.8\"\n";
+        assert_eq!(
+            add(text, "dependencies", "itoa").unwrap(),
+            "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\nitoa = \"1.0\"\n\n[dev-dependencies]\nrand = \"0.8\"\n"
+        );
+        assert_eq!(
+            add(text, "dev-dependencies", "itoa").unwrap(),
+            format!("{text}itoa = \"1.0\"\n")
+        );
+        // Empty sections and line breaks of Windows
+        assert_eq!(
+            add("[dependencies]\r\n", "dependencies", "itoa").unwrap(),
+            "[dependencies]\r\nitoa = \"1.0\"\r\n"
+        );
+    }
+
+    #[test]
+    fn appends_missing_sections() {
+        assert_eq!(
+            add("[package]\nname = \"app\"", "build-dependencies", "cc").unwrap(),
+            "[package]\nname = \"app\"\n\n[build-dependencies]\ncc = \"1.0\"\n"
+        );
+        assert_eq!(
+            add("", "dependencies", "cc").unwrap(),
+            "[dependencies]\ncc = \"1.0\"\n"
+        );
+        // Tables of dependencies are no section
+        assert_eq!(
+            add(
+                "[dependencies.serde]\nversion = \"1\"\n",
+                "dependencies",
+                "cc"
+            )
+            .unwrap(),
+            "[dependencies.serde]\nversion = \"1\"\n\n[dependencies]\ncc = \"1.0\"\n"
+        );
+    }
+
+    #[test]
+    fn declared_crates_are_not_added_again() {
+        for text in [
+            "[dependencies]\nserde = \"1\"\n",
+            "[dependencies]\n\"serde\" = { version = \"1\" }\n",
+            "[dependencies.serde]\nversion = \"1\"\n",
+        ] {
+            assert_eq!(add(text, "dependencies", "serde"), None, "{text}");
+        }
+        // In another section it is added
+        assert!(add(
+            "[dependencies]\nserde = \"1\"\n",
+            "dev-dependencies",
+            "serde"
+        )
+        .is_some());
+        assert!(is_section("workspace.dependencies"));
+        assert!(!is_section("target.'cfg(unix)'.dependencies"));
+    }
+}
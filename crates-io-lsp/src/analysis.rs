@@ -0,0 +1,839 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::{
+    CodeAction, CodeActionKind, CodeLens, Command, Diagnostic, NumberOrString, Range, TextEdit,
+    Uri, WorkspaceEdit,
+};
+
+#[cfg(feature = "changelog")]
+use crate::changelog::{ChangelogArgs, CHANGELOG};
+use crate::config::{Config, RequirementStyle};
+use crate::features::{self, ResolveArgs, RESOLVE_FEATURES};
+use crate::manifest::{table_headers, DependencyKind};
+use crate::messages::{Key, Language, Message};
+use crate::partial::PartialNotice;
+use crate::report::{ReportArgs, REPORT};
+use crate::text::Source;
+use crate::timing::Timings;
+use crate::version::{self, Classification, Jump, Release};
+use crate::workspace::Member;
+
+/// Command applying a version update, arguments are [`UpdateArgs`].
+pub const APPLY_UPDATE: &str = "crates-io.applyUpdate";
+/// Command listing the cached versions of a dependency, arguments are [`PickArgs`].
+pub const PICK_VERSION: &str = "crates-io.pickVersion";
+/// Command updating a dependency to the picked version, arguments are [`PickArgs`].
+pub const APPLY_VERSION: &str = "crates-io.applyVersion";
+
+/// Result of the last analysis of a document.
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub dependencies: Vec<AnalyzedDependency>,
+    /// Workspace members, if the manifest has a `[workspace]`
+    pub members: Vec<Member>,
+    /// Packages of the `[profile.*.package]` overrides with their key ranges
+    pub overrides: Vec<(String, Range)>,
+    /// Hovers of the package fields inherited from the workspace
+    pub inherited: Vec<(Range, String)>,
+    /// Hovers of the features and the optional dependencies
+    pub features: Vec<(Range, String)>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// `Cargo.lock` the requirements were compared with, whether it exists or not
+    pub lockfile: Option<PathBuf>,
+    /// Crates with feature hints whose feature maps are not fetched yet
+    pub unknown_features: Vec<String>,
+    /// Locked crates whose registry checksum is not fetched yet, with `showChecksums`
+    pub unknown_checksums: Vec<String>,
+    /// Declarations that are only partially analyzed
+    pub notices: Vec<PartialNotice>,
+    /// Time spent in the phases of the analysis
+    pub timings: Timings,
+    /// Requirements of the declarations of each registry crate, also the unresolved ones
+    pub requirements: BTreeMap<String, Vec<String>>,
+    /// Crates whose versions failed to fetch
+    pub failed: BTreeSet<String>,
+    /// Crates whose versions were still fetched at the deadline
+    pub pending: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalyzedDependency {
+    /// Name of the crate in the registry
+    pub package: String,
+    pub kind: DependencyKind,
+    /// Platform of a `[target]` table, like `cfg(unix)`
+    pub target: Option<String>,
+    pub name_range: Range,
+    pub req: String,
+    /// Range of the requirement without the quotes
+    pub req_range: Option<Range>,
+    /// From a git repository, the requirement only checks the version found there
+    pub git: bool,
+    /// `None` if the versions could not be fetched
+    pub classification: Option<Classification>,
+}
+
+impl AnalyzedDependency {
+    /// Newest version if the dependency is outdated.
+    pub fn target(&self) -> Option<&Version> {
+        match self.classification.as_ref()? {
+            Classification::OutdatedCompatible { latest, .. }
+            | Classification::OutdatedIncompatible { latest, .. } => Some(latest),
+            _ => None,
+        }
+    }
+
+    /// Requirement for the newest version if the dependency is outdated.
+    pub fn update(&self, style: RequirementStyle) -> Option<String> {
+        Some(version::requirement(self.target()?, style, &self.req))
+    }
+
+    /// Code actions updating the requirement, at most `maxUpdateActions` of them.
+    ///
+    /// Offers the newest version, the newest of the current series if that is
+    /// older, and a command to pick any of the cached `versions`.
+    /// For the diagnostic of a new major the newest version is titled as such.
+    /// The titles only name the target, so users can bind keys to them.
+    /// The edits are filled in on resolve if the client supports it (`lazy`).
+    /// Updates to a pre-release are never preferred.
+    pub fn update_actions(
+        &self,
+        uri: &Uri,
+        diagnostic: Diagnostic,
+        versions: &[Release],
+        config: &Config,
+        lazy: bool,
+    ) -> Vec<CodeAction> {
+        let (Some(latest), Some(range)) = (self.target(), self.req_range) else {
+            return Vec::new();
+        };
+        let style = config.requirement_style;
+        let major =
+            diagnostic.code == Some(NumberOrString::String(version::MAJOR_AVAILABLE.into()));
+        let title = Message::new(if major {
+            Key::UpdateToMajor
+        } else {
+            Key::UpdateToLatest
+        })
+        .arg(latest)
+        .render(config.language);
+        let mut targets = vec![(title, latest)];
+        if let Some(Classification::OutdatedIncompatible { compatible, .. }) = &self.classification
+        {
+            let newest = version::newest_in_series(versions, compatible)
+                .filter(|v| version::requirement(v, style, &self.req) != self.req);
+            if let Some(newest) = newest {
+                let title = Message::new(Key::UpdateToSeries)
+                    .arg(version::series(newest))
+                    .arg(newest)
+                    .render(config.language);
+                targets.push((title, newest));
+            }
+        }
+        let data = |target: &Version| UpdateActionData {
+            uri: uri.clone(),
+            range,
+            package: self.package.clone(),
+            req: self.req.clone(),
+            target: target.to_string(),
+            detail: None,
+        };
+        let mut actions = targets
+            .into_iter()
+            .map(|(title, target)| {
+                let data = data(target);
+                CodeAction {
+                    title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: if lazy { None } else { data.edit(style) },
+                    is_preferred: Some(target == latest && target.pre.is_empty()),
+                    data: serde_json::to_value(data).ok(),
+                    ..Default::default()
+                }
+            })
+            .collect::<Vec<_>>();
+        let args = PickArgs {
+            uri: uri.clone(),
+            range,
+            package: self.package.clone(),
+            req: self.req.clone(),
+            version: None,
+        };
+        actions.push(CodeAction {
+            title: "Choose version…".into(),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic]),
+            command: Some(Command {
+                title: "Choose version".into(),
+                command: PICK_VERSION.into(),
+                arguments: serde_json::to_value(args).ok().map(|a| vec![a]),
+            }),
+            ..Default::default()
+        });
+        actions.truncate(config.max_update_actions);
+        actions
+    }
+
+    /// Refactorings between an exact pin of the matched version (`=1.2.3`)
+    /// and a caret requirement in the precision of `style`.
+    ///
+    /// Only offered if the `versions` are known, never for git dependencies.
+    /// A `0` requirement is not pinned exactly, its diagnostic pins the minor instead.
+    pub fn pin_actions(
+        &self,
+        uri: &Uri,
+        versions: &[Release],
+        style: RequirementStyle,
+    ) -> Vec<CodeAction> {
+        let Some(range) = self.req_range.filter(|_| !self.git && !versions.is_empty()) else {
+            return Vec::new();
+        };
+        let pin = version::pin(&self.req, versions)
+            .filter(|_| version::zero_major(&self.req, versions).is_none())
+            .map(|req| (format!("Pin to exact version {}", &req[1..]), req));
+        let relax = version::relax(&self.req, style)
+            .map(|req| (format!("Relax to caret requirement {req}"), req));
+        pin.into_iter()
+            .chain(relax)
+            .map(|(title, new_text)| CodeAction {
+                title,
+                kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(
+                        uri.clone(),
+                        vec![TextEdit { range, new_text }],
+                    )])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Action showing the changes from the matched to the newest version.
+    #[cfg(feature = "changelog")]
+    pub fn changelog_action(
+        &self,
+        diagnostic: Diagnostic,
+        language: Language,
+    ) -> Option<CodeAction> {
+        let (from, to) = match self.classification.as_ref()? {
+            Classification::OutdatedCompatible { compatible, latest }
+            | Classification::OutdatedIncompatible { compatible, latest } => (compatible, latest),
+            _ => return None,
+        };
+        let args = ChangelogArgs {
+            package: self.package.clone(),
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        Some(CodeAction {
+            title: Message::new(Key::ShowChangelog)
+                .arg(&self.package)
+                .arg(from)
+                .arg(to)
+                .render(language),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic]),
+            command: Some(Command {
+                title: "Show changelog".into(),
+                command: CHANGELOG.into(),
+                arguments: serde_json::to_value(args).ok().map(|a| vec![a]),
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+/// How a dependency is named in its messages, `None` for those of `[dependencies]`
+/// and `[workspace.dependencies]`.
+pub fn kind_label(
+    kind: DependencyKind,
+    target: Option<&str>,
+    language: Language,
+) -> Option<String> {
+    let key = match kind {
+        DependencyKind::Dev => Key::DevDependency,
+        DependencyKind::Build => Key::BuildDependency,
+        DependencyKind::Normal if target.is_some() => Key::NormalDependency,
+        DependencyKind::Normal | DependencyKind::Workspace => return None,
+    };
+    let label = Message::new(key).render(language);
+    Some(match target {
+        Some(target) => Message::new(Key::TargetDependency)
+            .arg(label)
+            .arg(target)
+            .render(language),
+        None => label,
+    })
+}
+
+impl Analysis {
+    /// Number of (outdated, unknown, up to date) dependencies of a section.
+    pub fn counts(&self, kind: DependencyKind) -> (usize, usize, usize) {
+        self.section_counts(kind, None)
+    }
+
+    /// Like [`Self::counts`] for the section of a `target` platform.
+    fn section_counts(&self, kind: DependencyKind, target: Option<&str>) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for dep in self
+            .dependencies
+            .iter()
+            .filter(|d| d.kind == kind && d.target.as_deref() == target)
+        {
+            match &dep.classification {
+                Some(Classification::OutdatedCompatible { .. })
+                | Some(Classification::OutdatedIncompatible { .. }) => counts.0 += 1,
+                Some(Classification::Unknown) | None => counts.1 += 1,
+                Some(Classification::Any) | Some(Classification::Latest) => counts.2 += 1,
+            }
+        }
+        counts
+    }
+
+    /// Lenses summarizing each dependency section, optionally also for outdated dependencies.
+    ///
+    /// The commands are filled in by [`resolve_lens`].
+    pub fn code_lenses(&self, uri: &Uri, source: &Source, config: &Config) -> Vec<CodeLens> {
+        let headers = table_headers(&source.text);
+        let mut lenses = Vec::new();
+        let mut sections = DependencyKind::ALL.map(|kind| (kind, None)).to_vec();
+        // Each platform once, at its first section
+        for dep in &self.dependencies {
+            let section = (dep.kind, dep.target.as_deref());
+            if section.1.is_some() && !sections.contains(&section) {
+                sections.push(section);
+            }
+        }
+        for (kind, target) in sections {
+            let header = headers
+                .iter()
+                .find(|(name, _)| DependencyKind::of_section(name) == Some((kind, target)));
+            let Some((_, span)) = header else {
+                continue;
+            };
+            let Some(range) = source.range(span.clone()) else {
+                continue;
+            };
+            let (outdated, unknown, latest) = self.section_counts(kind, target);
+            let data = LensData::Section {
+                title: Message::new(Key::SectionCounts)
+                    .arg(outdated)
+                    .arg(unknown)
+                    .arg(latest)
+                    .render(config.language),
+            };
+            lenses.push(CodeLens {
+                range,
+                command: None,
+                data: serde_json::to_value(data).ok(),
+            });
+        }
+
+        if let Some((_, span)) = headers.iter().find(|(name, _)| name == "features") {
+            if let Some(range) = source.range(span.clone()) {
+                lenses.push(CodeLens {
+                    range,
+                    command: None,
+                    data: serde_json::to_value(LensData::Features { uri: uri.clone() }).ok(),
+                });
+            }
+        }
+
+        if let Some((_, span)) = headers.iter().find(|(name, _)| name == "workspace") {
+            if let Some(range) = source.range(span.clone()) {
+                lenses.push(CodeLens {
+                    range,
+                    command: None,
+                    data: serde_json::to_value(LensData::Report { uri: uri.clone() }).ok(),
+                });
+            }
+        }
+
+        if config.per_dependency_lens {
+            for dep in &self.dependencies {
+                let (Some(version), Some(range)) =
+                    (dep.update(config.requirement_style), dep.req_range)
+                else {
+                    continue;
+                };
+                let data = LensData::Update(UpdateArgs {
+                    uri: uri.clone(),
+                    range,
+                    from: dep.req.clone(),
+                    version,
+                });
+                lenses.push(CodeLens {
+                    range: dep.name_range,
+                    command: None,
+                    data: serde_json::to_value(data).ok(),
+                });
+            }
+        }
+        lenses
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum LensData {
+    Section {
+        title: String,
+    },
+    Update(UpdateArgs),
+    /// Preview of the default feature resolution
+    Features {
+        uri: Uri,
+    },
+    /// Health report of the workspace context of the manifest
+    Report {
+        uri: Uri,
+    },
+}
+
+/// Replace the requirement at `range` with `version`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateArgs {
+    pub uri: Uri,
+    pub range: Range,
+    pub from: String,
+    pub version: String,
+}
+
+/// A requirement at `range` to update, [`APPLY_VERSION`] needs the picked `version`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PickArgs {
+    pub uri: Uri,
+    pub range: Range,
+    pub package: String,
+    pub req: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// Result of [`PICK_VERSION`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PickResult {
+    /// Cached versions without yanked releases, newest first
+    pub versions: Vec<String>,
+    pub latest: Option<String>,
+    /// Whether the latest version was applied, for clients without a picker
+    pub applied: bool,
+}
+
+/// Fill in the command of a lens created by [`Analysis::code_lenses`].
+pub fn resolve_lens(mut lens: CodeLens, language: Language) -> CodeLens {
+    let Some(data) = lens.data.clone() else {
+        return lens;
+    };
+    lens.command = match serde_json::from_value(data) {
+        Ok(LensData::Section { title }) => Some(Command {
+            title,
+            command: String::new(),
+            arguments: None,
+        }),
+        Ok(LensData::Update(args)) => Some(Command {
+            title: Message::new(Key::LensUpdate)
+                .arg(&args.from)
+                .arg(&args.version)
+                .render(language),
+            command: APPLY_UPDATE.into(),
+            arguments: serde_json::to_value(args).ok().map(|a| vec![a]),
+        }),
+        Ok(LensData::Features { uri }) => {
+            let args = ResolveArgs {
+                uri,
+                features: Vec::new(),
+                default_features: None,
+                format: features::Format::Markdown,
+            };
+            Some(Command {
+                title: "Preview feature resolution…".into(),
+                command: RESOLVE_FEATURES.into(),
+                arguments: serde_json::to_value(args).ok().map(|a| vec![a]),
+            })
+        }
+        Ok(LensData::Report { uri }) => {
+            let args = ReportArgs {
+                scope: None,
+                context: Some(uri),
+            };
+            Some(Command {
+                title: "Workspace report…".into(),
+                command: REPORT.into(),
+                arguments: serde_json::to_value(args).ok().map(|a| vec![a]),
+            })
+        }
+        Err(_) => None,
+    };
+    lens
+}
+
+/// Data of an update code action, the `detail` is added when it is resolved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateActionData {
+    pub uri: Uri,
+    /// Range of the requirement without the quotes
+    pub range: Range,
+    pub package: String,
+    pub req: String,
+    pub target: String,
+    /// Markdown list of the skipped releases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl UpdateActionData {
+    /// Fill in the markdown detail from the cached `versions`, without fetching anything.
+    ///
+    /// The releases link to crates.io if `links` is set.
+    pub fn resolve(&mut self, versions: &[Release], links: bool) {
+        let Ok(target) = Version::parse(&self.target) else {
+            return;
+        };
+        let Some(jump) = version::jump(&self.req, &target, versions) else {
+            return;
+        };
+        self.detail = Some(self.render(&jump, links));
+    }
+
+    /// Edit replacing the requirement with one for the `target` in the given `style`.
+    pub fn edit(&self, style: RequirementStyle) -> Option<WorkspaceEdit> {
+        let target = Version::parse(&self.target).ok()?;
+        Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                self.uri.clone(),
+                vec![TextEdit {
+                    range: self.range,
+                    new_text: version::requirement(&target, style, &self.req),
+                }],
+            )])),
+            ..Default::default()
+        })
+    }
+
+    fn render(&self, jump: &Jump, links: bool) -> String {
+        let mut text = format!("**{}** `{}` → `{}`", self.package, self.req, self.target);
+        let summary = jump.summary();
+        if !summary.is_empty() {
+            text += &format!(": {summary}");
+        }
+        for (series, versions) in jump.groups() {
+            text += &format!("\n\n**{series}.x**\n");
+            for version in versions {
+                if links {
+                    text += &format!(
+                        "\n- [{version}](https://crates.io/crates/{}/{version})",
+                        self.package
+                    );
+                } else {
+                    text += &format!("\n- {version}");
+                }
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use semver::Version;
+    use tower_lsp_server::lsp_types::Position;
+
+    use super::*;
+    use crate::text::Encoding;
+
+    const TEXT: &str = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1.0.188\"\nrand = \"0.8\"\nlocal = \"1\"\n\n[dev-dependencies]\ntokio = \"1\"\n";
+
+    fn range(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    fn dependency(
+        kind: DependencyKind,
+        line: u32,
+        req: &str,
+        classification: Option<Classification>,
+    ) -> AnalyzedDependency {
+        AnalyzedDependency {
+            package: "crate".into(),
+            kind,
+            target: None,
+            name_range: range(line, 0, 5),
+            req: req.into(),
+            req_range: Some(range(line, 9, 9 + req.len() as u32)),
+            git: false,
+            classification,
+        }
+    }
+
+    fn analysis(rand: Option<Classification>) -> Analysis {
+        let outdated = Classification::OutdatedCompatible {
+            compatible: Version::new(1, 0, 215),
+            latest: Version::new(1, 0, 215),
+        };
+        Analysis {
+            dependencies: vec![
+                dependency(DependencyKind::Normal, 4, "1.0.188", Some(outdated)),
+                dependency(DependencyKind::Normal, 5, "0.8", rand),
+                dependency(
+                    DependencyKind::Normal,
+                    6,
+                    "1",
+                    Some(Classification::Unknown),
+                ),
+                dependency(DependencyKind::Dev, 9, "1", Some(Classification::Latest)),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn lenses(per_dependency_lens: bool) -> Config {
+        Config {
+            per_dependency_lens,
+            ..Default::default()
+        }
+    }
+
+    fn uri() -> Uri {
+        "file:///app/Cargo.toml".parse().unwrap()
+    }
+
+    #[test]
+    fn counts_per_section() {
+        let analysis = analysis(None);
+        assert_eq!(analysis.counts(DependencyKind::Normal), (1, 2, 0));
+        assert_eq!(analysis.counts(DependencyKind::Dev), (0, 0, 1));
+        assert_eq!(analysis.counts(DependencyKind::Build), (0, 0, 0));
+
+        let lenses =
+            analysis.code_lenses(&uri(), &Source::new(TEXT, Encoding::Utf8), &lenses(false));
+        assert_eq!(lenses.len(), 2, "only the present sections");
+        assert_eq!(lenses[0].range, range(3, 0, 14));
+        assert_eq!(lenses[1].range, range(8, 0, 18));
+        assert!(lenses.iter().all(|lens| lens.command.is_none()));
+
+        let titles = lenses
+            .into_iter()
+            .map(|lens| resolve_lens(lens, Language::default()).command.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(titles[0].title, "1 outdated · 2 unknown · 0 up to date");
+        assert_eq!(titles[1].title, "0 outdated · 0 unknown · 1 up to date");
+        assert!(titles.iter().all(|command| command.arguments.is_none()));
+    }
+
+    #[test]
+    fn dependency_lens_command() {
+        let lenses =
+            analysis(None).code_lenses(&uri(), &Source::new(TEXT, Encoding::Utf8), &lenses(true));
+        assert_eq!(lenses.len(), 3, "the sections and the outdated dependency");
+        let lens = resolve_lens(lenses[2].clone(), Language::default());
+        assert_eq!(lens.range, range(4, 0, 5));
+
+        let command = lens.command.unwrap();
+        assert_eq!(command.title, "1.0.188 → 1.0.215");
+        assert_eq!(command.command, APPLY_UPDATE);
+        let args = command.arguments.unwrap();
+        assert_eq!(args.len(), 1);
+        let args: UpdateArgs = serde_json::from_value(args[0].clone()).unwrap();
+        assert_eq!(args.uri, uri());
+        assert_eq!(args.range, range(4, 9, 16));
+        assert_eq!(args.from, "1.0.188");
+        assert_eq!(args.version, "1.0.215");
+    }
+
+    #[test]
+    fn report_lens_on_the_workspace_header() {
+        let text = "[package]\nname = \"app\"\n\n[workspace]\nmembers = [\"lib\"]\n";
+        let lenses = Analysis::default().code_lenses(
+            &uri(),
+            &Source::new(text, Encoding::Utf8),
+            &lenses(false),
+        );
+        assert_eq!(lenses.len(), 1);
+        let lens = resolve_lens(lenses[0].clone(), Language::default());
+        assert_eq!(lens.range, range(3, 0, 11));
+        let command = lens.command.unwrap();
+        assert_eq!(command.command, REPORT);
+        // The report is of the workspace of the manifest
+        let args = command.arguments.unwrap();
+        assert_eq!(args, [serde_json::json!({ "context": uri().as_str() })]);
+    }
+
+    #[test]
+    fn counts_change_after_a_fetch() {
+        // The lenses are refreshed if the counts differ from the stored analysis
+        let before = analysis(None);
+        let fetched = analysis(Some(Classification::OutdatedIncompatible {
+            compatible: Version::new(0, 8, 5),
+            latest: Version::new(0, 9, 0),
+        }));
+        assert_ne!(
+            before.counts(DependencyKind::Normal),
+            fetched.counts(DependencyKind::Normal)
+        );
+        assert_eq!(fetched.counts(DependencyKind::Normal), (2, 1, 0));
+        let again = analysis(Some(Classification::OutdatedCompatible {
+            compatible: Version::new(0, 8, 5),
+            latest: Version::new(0, 8, 5),
+        }));
+        assert_eq!(
+            fetched.counts(DependencyKind::Normal),
+            again.counts(DependencyKind::Normal)
+        );
+
+        let lenses = fetched.code_lenses(&uri(), &Source::new(TEXT, Encoding::Utf8), &lenses(true));
+        let titles = lenses
+            .into_iter()
+            .filter_map(|lens| resolve_lens(lens, Language::default()).command)
+            .map(|command| command.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles[0], "2 outdated · 1 unknown · 0 up to date");
+        assert!(
+            titles.contains(&"0.8 → 0.9".to_string()),
+            "the precision is kept"
+        );
+    }
+
+    fn update_actions(
+        dependency: &AnalyzedDependency,
+        versions: &[Release],
+        max: usize,
+        lazy: bool,
+    ) -> Vec<CodeAction> {
+        let config = Config {
+            requirement_style: RequirementStyle::Preserve,
+            max_update_actions: max,
+            ..Default::default()
+        };
+        dependency.update_actions(&uri(), Diagnostic::default(), versions, &config, lazy)
+    }
+
+    #[test]
+    fn titles_the_update_action() {
+        let versions = ["0.8.0", "0.8.5", "0.9.0", "0.10.0-rc.1", "0.10.0"]
+            .map(|v| Release::parse(v, false).unwrap());
+        let outdated = Classification::OutdatedIncompatible {
+            compatible: Version::new(0, 8, 5),
+            latest: Version::new(0, 10, 0),
+        };
+        let rand = dependency(DependencyKind::Normal, 5, "0.8", Some(outdated));
+        let actions = update_actions(&rand, &versions, 3, false);
+        let titles = actions.iter().map(|a| a.title.as_str()).collect::<Vec<_>>();
+        // `0.8` already allows the newest 0.8.x
+        assert_eq!(titles, ["Update to latest (0.10.0)", "Choose version…"]);
+        let action = actions[0].clone();
+        assert_eq!(action.is_preferred, Some(true));
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri()];
+        assert_eq!(edits[0].range, range(5, 9, 12));
+        assert_eq!(edits[0].new_text, "0.10");
+
+        let mut data: UpdateActionData = serde_json::from_value(action.data.unwrap()).unwrap();
+        assert_eq!(data.detail, None);
+        data.resolve(&versions, true);
+        assert_eq!(
+            data.detail.unwrap(),
+            "**crate** `0.8` → `0.10.0`: 2 majors, 2 releases skipped\n\n\
+             **0.8.x**\n\n- [0.8.5](https://crates.io/crates/crate/0.8.5)\n\n\
+             **0.9.x**\n\n- [0.9.0](https://crates.io/crates/crate/0.9.0)"
+        );
+    }
+
+    #[test]
+    fn offers_the_newest_of_each_target() {
+        let versions = ["1.2.0", "1.9.3", "2.0.0", "2.9.4", "3.0.0", "3.2.1"]
+            .map(|v| Release::parse(v, false).unwrap());
+        let outdated = Classification::OutdatedIncompatible {
+            compatible: Version::new(1, 9, 3),
+            latest: Version::new(3, 2, 1),
+        };
+        let old = dependency(DependencyKind::Normal, 4, "1.2", Some(outdated));
+        let actions = update_actions(&old, &versions, 3, true);
+        let titles = actions.iter().map(|a| a.title.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [
+                "Update to latest (3.2.1)",
+                "Update to latest 1.x (1.9.3)",
+                "Choose version…"
+            ]
+        );
+        assert_eq!(
+            actions.iter().map(|a| a.is_preferred).collect::<Vec<_>>(),
+            [Some(true), Some(false), None]
+        );
+        assert!(actions.iter().all(|a| a.edit.is_none()), "resolved lazily");
+
+        let command = actions[2].command.clone().unwrap();
+        assert_eq!(command.command, PICK_VERSION);
+        let args: PickArgs = serde_json::from_value(command.arguments.unwrap()[0].clone()).unwrap();
+        assert_eq!(args.range, range(4, 9, 12));
+        assert_eq!((args.req.as_str(), args.version), ("1.2", None));
+
+        let data: UpdateActionData =
+            serde_json::from_value(actions[1].data.clone().unwrap()).unwrap();
+        let edits = &data
+            .edit(RequirementStyle::Preserve)
+            .unwrap()
+            .changes
+            .unwrap()[&uri()];
+        assert_eq!(edits[0].new_text, "1.9");
+
+        // Capped to avoid a long menu
+        let capped = update_actions(&old, &versions, 1, true);
+        assert_eq!(capped.len(), 1);
+        assert_eq!(capped[0].title, "Update to latest (3.2.1)");
+    }
+
+    #[test]
+    fn does_not_prefer_pre_releases() {
+        let versions =
+            ["1.0.0", "1.0.1", "2.0.0-beta.2"].map(|v| Release::parse(v, false).unwrap());
+        let outdated = Classification::OutdatedIncompatible {
+            compatible: Version::new(1, 0, 1),
+            latest: Version::parse("2.0.0-beta.2").unwrap(),
+        };
+        let serde = dependency(DependencyKind::Normal, 4, "1.0.0", Some(outdated));
+        let actions = update_actions(&serde, &versions, 3, false);
+        assert_eq!(actions[0].is_preferred, Some(false));
+        assert_eq!(actions[0].title, "Update to latest (2.0.0-beta.2)");
+        assert_eq!(actions[1].title, "Update to latest 1.x (1.0.1)");
+
+        let latest = dependency(DependencyKind::Normal, 4, "1", Some(Classification::Latest));
+        assert!(update_actions(&latest, &versions, 3, false).is_empty());
+    }
+
+    #[test]
+    fn titles_the_new_major() {
+        let versions = ["0.9.1", "0.9.4", "1.0.3"].map(|v| Release::parse(v, false).unwrap());
+        let outdated = Classification::OutdatedIncompatible {
+            compatible: Version::new(0, 9, 4),
+            latest: Version::new(1, 0, 3),
+        };
+        let dep = dependency(DependencyKind::Normal, 4, "0.9.1", Some(outdated));
+        let diagnostic = Diagnostic {
+            code: Some(NumberOrString::String(version::MAJOR_AVAILABLE.into())),
+            ..Default::default()
+        };
+        let config = Config {
+            requirement_style: RequirementStyle::Preserve,
+            ..Default::default()
+        };
+        let actions = dep.update_actions(&uri(), diagnostic, &versions, &config, false);
+        let titles = actions.iter().map(|a| a.title.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [
+                "Update to 1.0.3 (new major)",
+                "Update to latest 0.9.x (0.9.4)",
+                "Choose version…"
+            ]
+        );
+        assert_eq!(actions[0].is_preferred, Some(true));
+    }
+}
@@ -1,37 +1,61 @@
-use anyhow::Result;
-use reqwest::Client;
-use serde::Deserialize;
+/// Longest crate name accepted by the registry.
+const MAX_NAME_LEN: usize = 64;
 
-pub async fn fetch_versions(
-    name: &str,
-    endpoint: &str,
-    token: &str,
-) -> Result<Vec<String>> {
-    let prefix = if name.len() <= 2 {
-        name.len().to_string()
-    } else if name.len() == 3 {
-        format!("{}/{}", name.len(), &name[0..1])
-    } else {
-        format!("{}/{}", &name[0..2], &name[2..4])
-    };
-    let mut request = Client::new().get(format!("{endpoint}/{prefix}/{name}"));
-    if !token.is_empty() {
-        request = request.bearer_auth(token);
-    }
+/// Whether the name only contains characters allowed by the registry.
+///
+/// Only valid names are interpolated into the index URL.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && name.len() <= MAX_NAME_LEN
+}
+
+/// Information about a crate from the crates.io API.
+#[cfg_attr(not(feature = "changelog"), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub struct CrateMetadata {
+    pub description: Option<String>,
+    pub downloads: u64,
+    /// Downloads of the last 90 days
+    pub recent_downloads: Option<u64>,
+    /// Number of published versions, including yanked ones
+    pub versions: usize,
+    /// Date of the newest not yanked release (RFC 3339)
+    pub released: Option<String>,
+    /// Median seconds between the newest not yanked releases
+    pub release_gap: Option<u64>,
+    /// Logins of the owning users and teams
+    pub owners: Vec<String>,
+    pub repository: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
 
-    let response = request.send().await?;
-    let mut versions = Vec::new();
-    for line in response.text().await?.lines() {
-        let data: Version = serde_json::from_str(line)?;
-        if !data.yanked {
-            versions.push(data.vers);
+    use super::*;
+
+    #[test]
+    fn validates_names() {
+        for name in ["serde", "a", "serde_json", "tokio-util", "R2D2"] {
+            assert!(is_valid_name(name), "{name}");
+        }
+        let long = "a".repeat(MAX_NAME_LEN + 1);
+        for name in [
+            "", "../serde", "a/b", "..", "_a", "1a", "a b", "a.b", "sérde", &long,
+        ] {
+            assert!(!is_valid_name(name), "{name}");
         }
     }
-    Ok(versions)
-}
 
-#[derive(Deserialize, Debug)]
-struct Version {
-    vers: String,
-    yanked: bool,
+    proptest! {
+        #[test]
+        fn valid_names_stay_in_the_path(name in "\\PC{0,70}") {
+            if is_valid_name(&name) {
+                prop_assert!(!name.contains(['/', '.', '\\', '?', '#', '%']));
+                prop_assert!(name.len() <= MAX_NAME_LEN);
+            }
+        }
+    }
 }
@@ -0,0 +1,225 @@
+use std::ops::Range;
+
+use toml::Spanned;
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString, TextEdit};
+
+use crate::config::{Config, Severity};
+use crate::keys;
+use crate::manifest::SpannedManifest;
+use crate::messages::{Key, Message};
+use crate::text::Source;
+use crate::unstable::Unstable;
+
+/// Diagnostic code of artifact dependencies, which need the unstable `bindeps`.
+pub const ARTIFACT_DEPENDENCY: &str = "artifact-dependency";
+/// Diagnostic code of invalid artifact keys, for misspelled kinds the data contains the edit.
+pub const INVALID_ARTIFACT: &str = "invalid-artifact";
+
+/// Artifact kinds besides `bin:<name>`.
+const KINDS: &[&str] = &["bin", "cdylib", "staticlib"];
+
+/// Check the artifact dependencies (`artifact = "bin"`) of a manifest.
+///
+/// They are noted as unstable unless the manifest enables `cargo-features = ["bindeps"]`.
+/// Unknown kinds and `lib` or `target` without `artifact` are errors of cargo.
+/// Their versions are checked like those of every other registry dependency.
+pub fn lint(
+    manifest: &SpannedManifest,
+    source: &Source,
+    unstable: &Unstable,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let enabled = unstable.enabled("bindeps");
+    let details = manifest
+        .dependencies()
+        .map(|(_, _, dep)| dep)
+        .chain(manifest.target_dependencies().map(|(_, _, _, dep)| dep))
+        .filter_map(|dep| dep.detail());
+
+    let diagnostic = |span: Range<usize>, severity: Severity, code: &str, message: Message| {
+        Some(Diagnostic {
+            range: source.range(span)?,
+            severity: Some(severity.into()),
+            code: Some(NumberOrString::String(code.into())),
+            source: Some(config.source(code)),
+            message: message.render(config.language),
+            ..Default::default()
+        })
+    };
+
+    let mut diagnostics = Vec::new();
+    for detail in details {
+        let Some(artifact) = &detail.artifact else {
+            let keys = [
+                ("lib", detail.lib.as_ref().map(Spanned::span)),
+                ("target", detail.target.as_ref().map(Spanned::span)),
+            ];
+            for (key, span) in keys {
+                if let Some(span) = span {
+                    diagnostics.extend(diagnostic(
+                        span,
+                        config.severity.invalid_artifact,
+                        INVALID_ARTIFACT,
+                        Message::new(Key::ArtifactKeyWithoutArtifact).arg(format!("`{key}`")),
+                    ));
+                }
+            }
+            continue;
+        };
+        if !enabled {
+            diagnostics.extend(diagnostic(
+                artifact.span(),
+                config.severity.artifact_dependency,
+                ARTIFACT_DEPENDENCY,
+                Message::new(Key::ArtifactDependency),
+            ));
+        }
+        for (kind, span) in detail.artifacts() {
+            if valid(kind) {
+                continue;
+            }
+            let edit = suggest(kind).and_then(|fixed| {
+                // Keep the quotes of the string
+                let quote = source.text.get(span.start..span.start + 1)?;
+                Some(TextEdit {
+                    range: source.range(span.clone())?,
+                    new_text: format!("{quote}{fixed}{quote}"),
+                })
+            });
+            let invalid = diagnostic(
+                span,
+                config.severity.invalid_artifact,
+                INVALID_ARTIFACT,
+                Message::new(Key::InvalidArtifact).arg(format!("`{kind}`")),
+            );
+            diagnostics.extend(invalid.map(|invalid| Diagnostic {
+                data: edit.and_then(|edit| serde_json::to_value(edit).ok()),
+                ..invalid
+            }));
+        }
+    }
+    diagnostics
+}
+
+/// Whether cargo knows the artifact `kind`.
+fn valid(kind: &str) -> bool {
+    KINDS.contains(&kind)
+        || kind
+            .strip_prefix("bin:")
+            .is_some_and(|name| !name.is_empty())
+}
+
+/// The kind one edit away from a misspelled one, keeping the name of `bin:<name>`.
+fn suggest(kind: &str) -> Option<String> {
+    match kind.split_once(':') {
+        Some((_, "")) => None,
+        Some((prefix, name)) => keys::suggest(prefix, &["bin"]).map(|bin| format!("{bin}:{name}")),
+        None => keys::suggest(kind, KINDS).map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest;
+    use crate::text::Encoding;
+
+    /// Code, text of the range, and the replacement of the quick fix of each diagnostic.
+    fn check(text: &str) -> Vec<(String, String, Option<String>)> {
+        let (manifest, None) = manifest::parse(text) else {
+            panic!("{text}");
+        };
+        let unstable = Unstable::new(&manifest.cargo_features);
+        let source = Source::new(text, Encoding::Utf8);
+        lint(&manifest, &source, &unstable, &Config::default())
+            .into_iter()
+            .map(|d| {
+                let NumberOrString::String(code) = d.code.unwrap() else {
+                    panic!("numeric code");
+                };
+                let line = text.lines().nth(d.range.start.line as usize).unwrap();
+                let value = &line[d.range.start.character as usize..d.range.end.character as usize];
+                let edit = d
+                    .data
+                    .map(|data| serde_json::from_value::<TextEdit>(data).unwrap().new_text);
+                (code, value.to_string(), edit)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn valid_bindeps_manifest() {
+        let text = "cargo-features = [\"bindeps\"]\n\n\
+            [package]\nname = \"app\"\n\n\
+            [build-dependencies]\n\
+            tool = { version = \"1\", artifact = \"bin\", target = \"target\" }\n\
+            native = { version = \"2\", artifact = [\"cdylib\", \"staticlib\", \"bin:gen\"], lib = true }\n\n\
+            [target.'cfg(unix)'.dependencies]\n\
+            helper = { version = \"1\", artifact = \"bin:helper\" }\n";
+        assert_eq!(check(text), []);
+    }
+
+    #[test]
+    fn flags_misspelled_kinds() {
+        let text = "cargo-features = [\"bindeps\"]\n\n\
+            [dependencies]\n\
+            tool = { version = \"1\", artifact = \"bni\" }\n\
+            native = { version = \"2\", artifact = [\"cdylib\", 'dylib', \"bim:gen\", \"bin:\", \"wasm\"] }\n";
+        let invalid = |value: &str, fix: Option<&str>| {
+            (
+                INVALID_ARTIFACT.to_string(),
+                value.to_string(),
+                fix.map(str::to_string),
+            )
+        };
+        assert_eq!(
+            check(text),
+            [
+                invalid("'dylib'", Some("'cdylib'")),
+                invalid("\"bim:gen\"", Some("\"bin:gen\"")),
+                invalid("\"bin:\"", None),
+                invalid("\"wasm\"", None),
+                invalid("\"bni\"", Some("\"bin\"")),
+            ]
+        );
+    }
+
+    #[test]
+    fn notes_missing_cargo_features() {
+        let text = "[dependencies]\n\
+            tool = { version = \"1\", artifact = \"bin\" }\n\
+            native = { version = \"2\", artifact = [\"cdylib\", \"bin:gen\"] }\n\
+            serde = \"1\"\n";
+        assert_eq!(
+            check(text),
+            [
+                (
+                    ARTIFACT_DEPENDENCY.to_string(),
+                    "[\"cdylib\", \"bin:gen\"]".to_string(),
+                    None
+                ),
+                (ARTIFACT_DEPENDENCY.to_string(), "\"bin\"".to_string(), None),
+            ]
+        );
+        // Other unstable features do not enable it
+        let other = format!("cargo-features = [\"edition2024\"]\n\n{text}");
+        assert_eq!(check(&other).len(), 2);
+    }
+
+    #[test]
+    fn artifact_keys_require_artifact() {
+        let text = "[dependencies]\n\
+            tool = { version = \"1\", lib = true, target = \"x86_64-unknown-linux-gnu\" }\n";
+        assert_eq!(
+            check(text),
+            [
+                (INVALID_ARTIFACT.to_string(), "true".to_string(), None),
+                (
+                    INVALID_ARTIFACT.to_string(),
+                    "\"x86_64-unknown-linux-gnu\"".to_string(),
+                    None
+                ),
+            ]
+        );
+    }
+}
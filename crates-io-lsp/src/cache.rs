@@ -0,0 +1,427 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cadence::{Bounds, Cadence};
+use crate::version::{Release, Releases};
+
+/// Fetched versions, only valid for the registry of the configuration `epoch`.
+///
+/// The versions are stored parsed, analyses of cached crates never parse them again.
+///
+/// The cache is bounded by the number of entries and their approximate size,
+/// evicting the least recently used crates that are not pinned.
+/// Each crate expires after a TTL adapted to how often its versions changed when it was refreshed.
+#[derive(Debug)]
+pub struct VersionCache {
+    pub epoch: u64,
+    pub capacity: usize,
+    pub max_bytes: usize,
+    /// Bounds of the TTLs
+    pub ttl: Bounds,
+    entries: HashMap<String, Entry>,
+    bytes: usize,
+    tick: AtomicU64,
+    evictions: u64,
+    /// Incremented by every invalidation
+    generation: u64,
+    /// Generation of the last invalidation of all or single crates
+    cleared: u64,
+    invalidated: HashMap<String, u64>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    versions: Releases,
+    /// Seconds since the Unix epoch when the versions were fetched
+    fetched: i64,
+    /// Kept while the crate is cached, invalidating it starts over
+    cadence: Cadence,
+    bytes: usize,
+    used: AtomicU64,
+}
+
+impl Default for VersionCache {
+    fn default() -> Self {
+        Self {
+            epoch: 0,
+            capacity: 4096,
+            max_bytes: 64 << 20,
+            ttl: Bounds::default(),
+            entries: HashMap::new(),
+            bytes: 0,
+            tick: AtomicU64::new(0),
+            evictions: 0,
+            generation: 0,
+            cleared: 0,
+            invalidated: HashMap::new(),
+        }
+    }
+}
+
+impl VersionCache {
+    /// Lookup the versions of a crate, marking it as recently used.
+    pub fn get(&self, name: &str) -> Option<&Releases> {
+        let entry = self.entries.get(name)?;
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed);
+        entry.used.fetch_max(tick, Ordering::Relaxed);
+        Some(&entry.versions)
+    }
+
+    /// Seconds since the Unix epoch when the versions of a crate were fetched.
+    pub fn fetched(&self, name: &str) -> Option<i64> {
+        self.entries.get(name).map(|entry| entry.fetched)
+    }
+
+    /// Seconds after which the versions of a crate expire.
+    pub fn ttl(&self, name: &str) -> Option<u64> {
+        let entry = self.entries.get(name)?;
+        Some(entry.cadence.ttl(&self.ttl))
+    }
+
+    /// TTLs of all cached crates.
+    pub fn ttls(&self) -> BTreeMap<String, u64> {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.cadence.ttl(&self.ttl)))
+            .collect()
+    }
+
+    /// Whether the versions of a crate are older than its TTL at `now`.
+    pub fn is_expired(&self, name: &str, now: i64) -> bool {
+        self.entries.get(name).is_some_and(|entry| {
+            let ttl = i64::try_from(entry.cadence.ttl(&self.ttl)).unwrap_or(i64::MAX);
+            now.saturating_sub(entry.fetched) >= ttl
+        })
+    }
+
+    /// Start the TTL of a cached crate over from the median `gap` between its releases.
+    #[cfg(feature = "metadata")]
+    pub fn seed_ttl(&mut self, name: &str, gap: u64) {
+        if let Some(entry) = self.entries.get_mut(name) {
+            entry.cadence = Cadence::from_release_gap(gap, &self.ttl);
+        }
+    }
+
+    /// Insert the versions of a crate fetched at `fetched` and evict entries until the limits
+    /// are met, returns the evicted crates.
+    ///
+    /// Replacing cached versions adapts the TTL of the crate to whether they changed.
+    /// Pinned crates (used by open documents) are never evicted.
+    pub fn insert(
+        &mut self,
+        name: String,
+        versions: Releases,
+        fetched: i64,
+        pinned: &HashSet<String>,
+    ) -> Vec<String> {
+        let bytes = name.len()
+            + versions
+                .iter()
+                .map(|v| v.raw.len() + size_of::<Release>())
+                .sum::<usize>();
+        let used = AtomicU64::new(self.tick.fetch_add(1, Ordering::Relaxed));
+        let cadence = match self.entries.get(&name) {
+            Some(old) => old.cadence.observe(old.versions != versions, &self.ttl),
+            None => Cadence::new(&self.ttl),
+        };
+        if let Some(old) = self.entries.insert(
+            name,
+            Entry {
+                versions,
+                fetched,
+                cadence,
+                bytes,
+                used,
+            },
+        ) {
+            self.bytes -= old.bytes;
+        }
+        self.bytes += bytes;
+
+        let mut evicted = Vec::new();
+        while self.entries.len() > self.capacity || self.bytes > self.max_bytes {
+            let Some(lru) = self
+                .entries
+                .iter()
+                .filter(|(name, _)| !pinned.contains(*name))
+                .min_by_key(|(_, entry)| entry.used.load(Ordering::Relaxed))
+                .map(|(name, _)| name.clone())
+            else {
+                break; // Everything is pinned
+            };
+            self.remove(&lru);
+            self.evictions += 1;
+            evicted.push(lru);
+        }
+        evicted
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Releases> {
+        let entry = self.entries.remove(name)?;
+        self.bytes -= entry.bytes;
+        Some(entry.versions)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    /// Remove the crate and reject fetches that started before, returns whether it was cached.
+    ///
+    /// Beyond `capacity` invalidated crates, they are forgotten and all fetches that started
+    /// before are rejected instead.
+    pub fn invalidate(&mut self, name: &str) -> bool {
+        self.generation += 1;
+        self.invalidated.insert(name.to_string(), self.generation);
+        if self.invalidated.len() > self.capacity {
+            self.cleared = self.generation;
+            self.invalidated.clear();
+        }
+        self.remove(name).is_some()
+    }
+
+    /// Remove all crates and reject fetches that started before, returns the number of entries.
+    pub fn invalidate_all(&mut self) -> usize {
+        self.generation += 1;
+        self.cleared = self.generation;
+        self.invalidated.clear();
+        let entries = self.entries.len();
+        self.clear();
+        entries
+    }
+
+    /// Current generation, to be passed to [`Self::is_invalidated`] after a fetch.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether the crate was invalidated after `generation`.
+    pub fn is_invalidated(&self, name: &str, generation: u64) -> bool {
+        self.cleared > generation || self.invalidated.get(name).is_some_and(|g| *g > generation)
+    }
+
+    /// Number of entries evicted since the start.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn releases(versions: impl IntoIterator<Item = String>) -> Releases {
+        versions
+            .into_iter()
+            .filter_map(|v| Release::parse(&v, false))
+            .collect()
+    }
+
+    fn versions() -> Releases {
+        releases(["1.0.0".into(), "1.0.1".into()])
+    }
+
+    fn cache(capacity: usize) -> VersionCache {
+        VersionCache {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let mut cache = cache(3);
+        let pinned = HashSet::new();
+        for name in ["a", "b", "c"] {
+            cache.insert(name.into(), versions(), 0, &pinned);
+        }
+        // `a` is used again, `b` is the least recently used now
+        assert!(cache.get("a").is_some());
+        cache.insert("d".into(), versions(), 0, &pinned);
+        assert!(cache.get("b").is_none());
+        for name in ["a", "c", "d"] {
+            assert!(cache.get(name).is_some(), "{name}");
+        }
+        assert_eq!(cache.evictions(), 1);
+    }
+
+    #[test]
+    fn keeps_pinned_entries() {
+        let mut cache = cache(2);
+        let pinned = HashSet::from(["a".to_string(), "b".to_string()]);
+        for name in ["a", "b", "c", "d", "e"] {
+            cache.insert(name.into(), versions(), 0, &pinned);
+        }
+        // Only the unpinned ones are dropped, even if the pinned ones were not used since
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_some());
+        assert!(cache.get("c").is_none());
+        assert!(cache.get("d").is_none());
+        assert!(cache.get("e").is_none());
+        assert_eq!(cache.evictions(), 3);
+    }
+
+    #[test]
+    fn bounds_the_bytes() {
+        let many = releases((0..10).map(|i| format!("1.0.{i}")));
+        let entry = 1 + 10 * (5 + size_of::<Release>());
+        let mut cache = VersionCache {
+            max_bytes: 2 * entry + entry / 2,
+            ..Default::default()
+        };
+        let pinned = HashSet::new();
+        for name in ["a", "b", "c", "d"] {
+            cache.insert(name.into(), many.clone(), 0, &pinned);
+        }
+        // Two of them fit
+        assert_eq!(cache.bytes, 2 * entry);
+        assert!(cache.get("c").is_some());
+        assert!(cache.get("d").is_some());
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_none());
+        // Replacing an entry does not count it twice
+        let bytes = cache.bytes;
+        cache.insert("d".into(), many, 0, &pinned);
+        assert_eq!(cache.bytes, bytes);
+        assert_eq!(cache.remove("d").map(|v| v.len()), Some(10));
+        cache.clear();
+        assert_eq!(cache.bytes, 0);
+    }
+
+    #[test]
+    fn counts_yanked_versions_against_the_bytes() {
+        // Like a crate that yanked hundreds of nightlies
+        let live = (0..5).filter_map(|i| Release::parse(&format!("1.0.{i}"), false));
+        let yanked = (5..505).filter_map(|i| Release::parse(&format!("1.0.{i}"), true));
+        let skewed = live.chain(yanked).collect::<Releases>();
+        assert_eq!(skewed.len(), 505);
+        let mut cache = VersionCache {
+            max_bytes: 64 << 10,
+            ..Default::default()
+        };
+        let pinned = HashSet::new();
+        for i in 0..10 {
+            cache.insert(format!("crate-{i}"), skewed.clone(), 0, &pinned);
+            assert!(cache.bytes <= cache.max_bytes, "{}", cache.bytes);
+        }
+        assert!(cache.evictions() > 0);
+        assert!(cache.get("crate-9").is_some());
+    }
+
+    #[test]
+    fn adapts_the_ttl_to_changes() {
+        let mut cache = cache(8);
+        let pinned = HashSet::new();
+        cache.insert("a".into(), versions(), 0, &pinned);
+        assert_eq!(cache.ttl("a"), Some(900));
+        assert!(!cache.is_expired("a", 899));
+        assert!(cache.is_expired("a", 900));
+        assert!(!cache.is_expired("b", 900));
+
+        // Nothing new doubles it, a new version halves it
+        cache.insert("a".into(), versions(), 900, &pinned);
+        assert_eq!(cache.ttl("a"), Some(1800));
+        assert!(!cache.is_expired("a", 2699));
+        assert!(cache.is_expired("a", 2700));
+        cache.insert("a".into(), versions(), 2700, &pinned);
+        let newer = releases(["1.0.0".into(), "1.0.1".into(), "1.1.0".into()]);
+        cache.insert("a".into(), newer, 6300, &pinned);
+        assert_eq!(cache.ttl("a"), Some(1800));
+        // So does a yanked one
+        let yanked = [
+            Release::parse("1.0.0", false),
+            Release::parse("1.0.1", true),
+        ];
+        let yanked = yanked.into_iter().flatten().collect::<Releases>();
+        cache.insert("a".into(), yanked, 8100, &pinned);
+        assert_eq!(cache.ttl("a"), Some(900));
+
+        // The bounds apply immediately
+        cache.insert("b".into(), versions(), 0, &pinned);
+        cache.insert("b".into(), versions(), 0, &pinned);
+        assert_eq!(
+            cache.ttls(),
+            BTreeMap::from([("a".into(), 900), ("b".into(), 1800)])
+        );
+        cache.ttl = Bounds { min: 60, max: 600 };
+        assert_eq!(
+            cache.ttls(),
+            BTreeMap::from([("a".into(), 600), ("b".into(), 600)])
+        );
+    }
+
+    #[test]
+    fn invalidating_starts_the_ttl_over() {
+        let mut cache = cache(8);
+        let pinned = HashSet::new();
+        for _ in 0..3 {
+            cache.insert("a".into(), versions(), 0, &pinned);
+        }
+        assert_eq!(cache.ttl("a"), Some(3600));
+        assert!(cache.invalidate("a"));
+        assert_eq!(cache.ttl("a"), None);
+        cache.insert("a".into(), versions(), 0, &pinned);
+        assert_eq!(cache.ttl("a"), Some(900));
+    }
+
+    #[cfg(feature = "metadata")]
+    #[test]
+    fn seeds_the_ttl_from_the_release_gap() {
+        let mut cache = cache(8);
+        let pinned = HashSet::new();
+        cache.insert("a".into(), versions(), 0, &pinned);
+        cache.seed_ttl("a", 86400);
+        cache.seed_ttl("b", 86400);
+        assert_eq!(cache.ttl("a"), Some(10800));
+        assert_eq!(cache.ttl("b"), None);
+        // Refreshes adapt it further
+        cache.insert("a".into(), versions(), 0, &pinned);
+        assert_eq!(cache.ttl("a"), Some(21600));
+    }
+
+    #[test]
+    fn rejects_fetches_started_before_an_invalidation() {
+        let mut cache = cache(8);
+        let pinned = HashSet::new();
+        cache.insert("a".into(), versions(), 0, &pinned);
+        let before = cache.generation();
+        assert!(cache.invalidate("a"));
+        assert!(!cache.invalidate("b"));
+        assert!(cache.get("a").is_none());
+        assert!(cache.is_invalidated("a", before) && cache.is_invalidated("b", before));
+        assert!(!cache.is_invalidated("c", before));
+
+        let after = cache.generation();
+        assert!(!cache.is_invalidated("a", after));
+        cache.insert("c".into(), versions(), 0, &pinned);
+        assert_eq!(cache.invalidate_all(), 1);
+        assert!(cache.is_invalidated("c", after));
+        assert!(!cache.is_invalidated("c", cache.generation()));
+    }
+
+    #[test]
+    fn bounds_the_invalidated_crates() {
+        let mut cache = cache(2);
+        let before = cache.generation();
+        cache.invalidate("a");
+        cache.invalidate("b");
+        let after = cache.generation();
+        assert_eq!(cache.invalidated.len(), 2);
+        assert!(!cache.is_invalidated("c", after));
+        // The third one exceeds the capacity, all fetches started before are rejected
+        cache.invalidate("c");
+        assert!(cache.invalidated.is_empty());
+        assert!(cache.is_invalidated("a", before) && cache.is_invalidated("d", after));
+        assert!(!cache.is_invalidated("a", cache.generation()));
+    }
+
+    #[test]
+    fn returns_the_evicted_crates() {
+        let mut cache = cache(2);
+        let pinned = HashSet::from(["a".to_string()]);
+        assert!(cache.insert("a".into(), versions(), 0, &pinned).is_empty());
+        assert!(cache.insert("b".into(), versions(), 0, &pinned).is_empty());
+        assert_eq!(cache.insert("c".into(), versions(), 0, &pinned), ["b"]);
+    }
+}
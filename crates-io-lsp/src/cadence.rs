@@ -0,0 +1,214 @@
+#[cfg(feature = "metadata")]
+use crate::format;
+
+/// Releases considered for the gap between releases.
+#[cfg(feature = "metadata")]
+const RECENT_RELEASES: usize = 6;
+/// Refreshes per typical gap between releases.
+#[cfg(feature = "metadata")]
+const REFRESHES_PER_RELEASE: u64 = 8;
+
+/// Bounds of the refresh interval in seconds, `minCacheTtl` and `maxCacheTtl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            min: 15 * 60,
+            max: 24 * 60 * 60,
+        }
+    }
+}
+
+impl Bounds {
+    /// `secs` within the bounds, a maximum below the minimum counts as the minimum.
+    pub fn clamp(&self, secs: u64) -> u64 {
+        secs.clamp(self.min, self.max.max(self.min))
+    }
+}
+
+/// Refresh interval of a cached crate, adapted to how often it changes.
+///
+/// Starts at the minimum, or at a fraction of the gap between releases if their dates are known.
+/// A refresh that found a change halves the interval, one that found nothing doubles it.
+/// The bounds are applied on every step, so changed bounds take effect immediately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cadence {
+    secs: u64,
+}
+
+impl Cadence {
+    /// Cadence of a crate that was not refreshed yet.
+    pub fn new(bounds: &Bounds) -> Self {
+        Self { secs: bounds.min }
+    }
+
+    /// Cadence of a crate that releases every `gap` seconds.
+    #[cfg(feature = "metadata")]
+    pub fn from_release_gap(gap: u64, bounds: &Bounds) -> Self {
+        Self {
+            secs: bounds.clamp(gap / REFRESHES_PER_RELEASE),
+        }
+    }
+
+    /// Adapt to the result of a refresh, `changed` if it found new or yanked versions.
+    pub fn observe(self, changed: bool, bounds: &Bounds) -> Self {
+        let secs = self.ttl(bounds);
+        let secs = match changed {
+            true => secs / 2,
+            false => secs.saturating_mul(2),
+        };
+        Self {
+            secs: bounds.clamp(secs),
+        }
+    }
+
+    /// Seconds after which the crate is refreshed.
+    pub fn ttl(&self, bounds: &Bounds) -> u64 {
+        bounds.clamp(self.secs)
+    }
+}
+
+/// Median gap in seconds between the newest releases, dated as RFC 3339.
+///
+/// Only the day of a release counts, releases of the same day are no gap apart.
+#[cfg(feature = "metadata")]
+pub fn release_gap<'a>(dates: impl IntoIterator<Item = &'a str>) -> Option<u64> {
+    let mut days = dates
+        .into_iter()
+        .filter_map(|date| format::days_since_epoch(date.get(..10)?))
+        .collect::<Vec<_>>();
+    days.sort_unstable_by(|a, b| b.cmp(a));
+    days.truncate(RECENT_RELEASES);
+    let mut gaps = days.windows(2).map(|w| w[0] - w[1]).collect::<Vec<_>>();
+    if gaps.is_empty() {
+        return None;
+    }
+    gaps.sort_unstable();
+    Some(gaps[gaps.len() / 2] as u64 * 86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: Bounds = Bounds {
+        min: 900,
+        max: 86400,
+    };
+
+    /// Interval after observing the `changes` from the start.
+    fn after(changes: &[bool]) -> u64 {
+        changes
+            .iter()
+            .fold(Cadence::new(&BOUNDS), |c, changed| {
+                c.observe(*changed, &BOUNDS)
+            })
+            .ttl(&BOUNDS)
+    }
+
+    #[test]
+    fn starts_at_the_minimum() {
+        assert_eq!(after(&[]), 900);
+        assert_eq!(after(&[true]), 900);
+    }
+
+    #[test]
+    fn adapts_to_changes() {
+        let cases: [(&[bool], u64); 7] = [
+            (&[false], 1800),
+            (&[false, false], 3600),
+            (&[false, false, true], 1800),
+            (&[false, false, true, true], 900),
+            (&[false, true, false], 1800),
+            (&[true, false, true, false], 1800),
+            (&[false; 6], 57600),
+        ];
+        for (changes, expected) in cases {
+            assert_eq!(after(changes), expected, "{changes:?}");
+        }
+    }
+
+    #[test]
+    fn converges_to_the_bounds() {
+        // Reached although it is no power of two of the minimum
+        assert_eq!(after(&[false; 7]), 86400);
+        assert_eq!(after(&[false; 100]), 86400);
+        // One change after the maximum halves it, not the overshoot
+        let mut changes = vec![false; 100];
+        changes.push(true);
+        assert_eq!(after(&changes), 43200);
+        // Alternating stays within one step of the start
+        let alternating = [false, true].repeat(50);
+        assert_eq!(after(&alternating), 900);
+        assert_eq!(after(&[true; 100]), 900);
+    }
+
+    #[test]
+    fn clamps_to_changed_bounds() {
+        let cadence = Cadence::new(&BOUNDS).observe(false, &BOUNDS);
+        let narrow = Bounds { min: 60, max: 600 };
+        assert_eq!(cadence.ttl(&narrow), 600);
+        // Halving starts from the clamped interval
+        assert_eq!(cadence.observe(true, &narrow).ttl(&narrow), 300);
+        let raised = Bounds {
+            min: 7200,
+            max: 86400,
+        };
+        assert_eq!(cadence.ttl(&raised), 7200);
+        // A maximum below the minimum pins the interval to the minimum
+        let inverted = Bounds { min: 600, max: 60 };
+        assert_eq!(cadence.ttl(&inverted), 600);
+        assert_eq!(cadence.observe(false, &inverted).ttl(&inverted), 600);
+        assert_eq!(cadence.observe(true, &inverted).ttl(&inverted), 600);
+        let zero = Bounds { min: 0, max: 0 };
+        assert_eq!(cadence.observe(true, &zero).ttl(&zero), 0);
+        let unbounded = Bounds {
+            min: 1,
+            max: u64::MAX,
+        };
+        let huge = Cadence { secs: u64::MAX };
+        assert_eq!(huge.observe(false, &unbounded).ttl(&unbounded), u64::MAX);
+        assert_eq!(huge.observe(true, &unbounded).ttl(&unbounded), u64::MAX / 2);
+    }
+
+    #[cfg(feature = "metadata")]
+    #[test]
+    fn starts_from_the_release_gap() {
+        // A weekly release is refreshed about every 21 hours
+        let weekly = Cadence::from_release_gap(7 * 86400, &BOUNDS);
+        assert_eq!(weekly.ttl(&BOUNDS), 75600);
+        assert_eq!(weekly.observe(true, &BOUNDS).ttl(&BOUNDS), 37800);
+        assert_eq!(Cadence::from_release_gap(0, &BOUNDS).ttl(&BOUNDS), 900);
+        let yearly = Cadence::from_release_gap(365 * 86400, &BOUNDS);
+        assert_eq!(yearly.ttl(&BOUNDS), 86400);
+    }
+
+    #[cfg(feature = "metadata")]
+    #[test]
+    fn gaps_between_the_newest_releases() {
+        let dates = [
+            "2024-01-01T08:00:00Z",
+            // Far apart, but older than the recent releases
+            "2020-06-01T00:00:00Z",
+            "2024-01-11T08:00:00Z",
+            "2024-01-13T08:00:00Z",
+            "2024-01-21T08:00:00Z",
+            "2024-01-22T23:00:00Z",
+            "2024-01-22T08:00:00Z",
+            "not a date",
+        ];
+        // Gaps of 0, 1, 8, 2, and 10 days
+        assert_eq!(release_gap(dates), Some(2 * 86400));
+        assert_eq!(
+            release_gap(["2024-01-01T00:00:00Z", "2024-01-04T00:00:00Z"]),
+            Some(3 * 86400)
+        );
+        assert_eq!(release_gap(["2024-01-01T00:00:00Z"]), None);
+        assert_eq!(release_gap([]), None);
+    }
+}
@@ -0,0 +1,263 @@
+use serde::Serialize;
+use serde_json::Value;
+use tower_lsp_server::lsp_types::{ClientCapabilities, MarkupKind};
+
+use crate::text::Encoding;
+
+/// Features of the client the server relies on, read once from its capabilities in
+/// `initialize`.
+///
+/// Missing capabilities select the fallbacks of the features, like plain text hovers or
+/// pushed diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientCaps {
+    /// Encoding of the positions, also advertised to the client
+    pub position_encoding: Encoding,
+    /// Hovers in markdown, otherwise plain text
+    pub hover_markdown: bool,
+    /// Documentation of completion items in markdown
+    pub completion_markdown: bool,
+    /// Snippets as the text of completion items
+    pub snippets: bool,
+    /// `textDocument/diagnostic`, otherwise the diagnostics are pushed
+    pub pull_diagnostics: bool,
+    /// Creating, renaming, and deleting files in workspace edits
+    pub resource_operations: bool,
+    /// Versioned `documentChanges` in workspace edits
+    pub document_changes: bool,
+    /// Registering `workspace/didChangeWatchedFiles` at runtime, otherwise configuration
+    /// files are reloaded when saved in the editor
+    pub watched_files_registration: bool,
+    /// Registering `workspace/didChangeConfiguration` at runtime
+    pub configuration_registration: bool,
+    /// Server initiated progress with `window/workDoneProgress/create`
+    pub work_done_progress: bool,
+    /// `workspace/codeLens/refresh`
+    pub code_lens_refresh: bool,
+    /// `window/showDocument`
+    pub show_document: bool,
+    /// Resolving the edits of code actions
+    pub resolve_edits: bool,
+    /// Experimental picker for the versions of `crates-io.pickVersion`
+    pub version_picker: bool,
+}
+
+impl Default for ClientCaps {
+    /// A client that declared no capabilities.
+    fn default() -> Self {
+        Self::new(&ClientCapabilities::default())
+    }
+}
+
+impl ClientCaps {
+    pub fn new(caps: &ClientCapabilities) -> Self {
+        let text_document = caps.text_document.as_ref();
+        let workspace = caps.workspace.as_ref();
+        let window = caps.window.as_ref();
+        // Clients listing their formats without markdown get plain text
+        let markdown = |formats: Option<&Vec<MarkupKind>>| {
+            formats.is_none_or(|f| f.contains(&MarkupKind::Markdown))
+        };
+        let completion_item = text_document
+            .and_then(|t| t.completion.as_ref())
+            .and_then(|c| c.completion_item.as_ref());
+        let workspace_edit = workspace.and_then(|w| w.workspace_edit.as_ref());
+        Self {
+            position_encoding: Encoding::negotiate(
+                caps.general
+                    .as_ref()
+                    .and_then(|g| g.position_encodings.as_deref()),
+            ),
+            hover_markdown: markdown(
+                text_document
+                    .and_then(|t| t.hover.as_ref())
+                    .and_then(|h| h.content_format.as_ref()),
+            ),
+            completion_markdown: markdown(
+                completion_item.and_then(|c| c.documentation_format.as_ref()),
+            ),
+            snippets: completion_item
+                .and_then(|c| c.snippet_support)
+                .unwrap_or_default(),
+            pull_diagnostics: text_document.is_some_and(|t| t.diagnostic.is_some()),
+            resource_operations: workspace_edit
+                .and_then(|e| e.resource_operations.as_ref())
+                .is_some_and(|r| !r.is_empty()),
+            document_changes: workspace_edit
+                .and_then(|e| e.document_changes)
+                .unwrap_or_default(),
+            watched_files_registration: workspace
+                .and_then(|w| w.did_change_watched_files.as_ref())
+                .and_then(|d| d.dynamic_registration)
+                .unwrap_or_default(),
+            configuration_registration: workspace
+                .and_then(|w| w.did_change_configuration.as_ref())
+                .and_then(|d| d.dynamic_registration)
+                .unwrap_or_default(),
+            work_done_progress: window
+                .and_then(|w| w.work_done_progress)
+                .unwrap_or_default(),
+            code_lens_refresh: workspace
+                .and_then(|w| w.code_lens.as_ref())
+                .and_then(|c| c.refresh_support)
+                .unwrap_or_default(),
+            show_document: window
+                .and_then(|w| w.show_document.as_ref())
+                .is_some_and(|s| s.support),
+            resolve_edits: text_document
+                .and_then(|t| t.code_action.as_ref())
+                .and_then(|c| c.resolve_support.as_ref())
+                .is_some_and(|r| r.properties.iter().any(|p| p == "edit")),
+            version_picker: caps
+                .experimental
+                .as_ref()
+                .and_then(|e| e.get("versionPicker"))
+                .and_then(Value::as_bool)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Capabilities of a client supporting everything the server uses.
+#[cfg(test)]
+pub fn full() -> ClientCapabilities {
+    serde_json::from_value(serde_json::json!({
+        "general": { "positionEncodings": ["utf-16", "utf-8"] },
+        "textDocument": {
+            "hover": { "contentFormat": ["markdown", "plaintext"] },
+            "completion": {
+                "completionItem": {
+                    "snippetSupport": true,
+                    "documentationFormat": ["markdown", "plaintext"]
+                }
+            },
+            "diagnostic": { "dynamicRegistration": false },
+            "codeAction": { "resolveSupport": { "properties": ["edit"] } }
+        },
+        "workspace": {
+            "workspaceEdit": {
+                "documentChanges": true,
+                "resourceOperations": ["create", "rename", "delete"]
+            },
+            "didChangeWatchedFiles": { "dynamicRegistration": true },
+            "didChangeConfiguration": { "dynamicRegistration": true },
+            "codeLens": { "refreshSupport": true }
+        },
+        "window": { "workDoneProgress": true, "showDocument": { "support": true } },
+        "experimental": { "versionPicker": true }
+    }))
+    .unwrap()
+}
+
+/// Capabilities of a client that only supports plain text.
+#[cfg(test)]
+pub fn minimal() -> ClientCapabilities {
+    serde_json::from_value(serde_json::json!({
+        "textDocument": {
+            "hover": { "contentFormat": ["plaintext"] },
+            "completion": { "completionItem": { "documentationFormat": ["plaintext"] } }
+        }
+    }))
+    .unwrap()
+}
+
+/// Capabilities like the ones of Zed, with markdown and progress but pushed diagnostics.
+#[cfg(test)]
+pub fn zed() -> ClientCapabilities {
+    serde_json::from_value(serde_json::json!({
+        "textDocument": {
+            "hover": { "contentFormat": ["markdown"] },
+            "completion": {
+                "completionItem": {
+                    "snippetSupport": true,
+                    "documentationFormat": ["markdown"]
+                }
+            },
+            "codeAction": { "resolveSupport": { "properties": ["edit", "command"] } }
+        },
+        "workspace": {
+            "workspaceEdit": { "documentChanges": true, "resourceOperations": [] },
+            "didChangeWatchedFiles": {
+                "dynamicRegistration": true,
+                "relativePatternSupport": true
+            },
+            "didChangeConfiguration": { "dynamicRegistration": false },
+            "codeLens": { "refreshSupport": true }
+        },
+        "window": { "workDoneProgress": true, "showDocument": { "support": true } }
+    }))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_the_profiles() {
+        let full = ClientCaps::new(&full());
+        assert_eq!(
+            full,
+            ClientCaps {
+                position_encoding: Encoding::Utf8,
+                hover_markdown: true,
+                completion_markdown: true,
+                snippets: true,
+                pull_diagnostics: true,
+                resource_operations: true,
+                document_changes: true,
+                watched_files_registration: true,
+                configuration_registration: true,
+                work_done_progress: true,
+                code_lens_refresh: true,
+                show_document: true,
+                resolve_edits: true,
+                version_picker: true,
+            }
+        );
+
+        let minimal = ClientCaps::new(&minimal());
+        assert_eq!(
+            minimal,
+            ClientCaps {
+                position_encoding: Encoding::Utf16,
+                hover_markdown: false,
+                completion_markdown: false,
+                snippets: false,
+                pull_diagnostics: false,
+                resource_operations: false,
+                document_changes: false,
+                watched_files_registration: false,
+                configuration_registration: false,
+                work_done_progress: false,
+                code_lens_refresh: false,
+                show_document: false,
+                resolve_edits: false,
+                version_picker: false,
+            }
+        );
+
+        let zed = ClientCaps::new(&zed());
+        assert_eq!(
+            zed,
+            ClientCaps {
+                position_encoding: Encoding::Utf16,
+                pull_diagnostics: false,
+                resource_operations: false,
+                configuration_registration: false,
+                version_picker: false,
+                ..full
+            }
+        );
+    }
+
+    #[test]
+    fn assumes_markdown_without_formats() {
+        let caps = ClientCaps::default();
+        assert!(caps.hover_markdown);
+        assert!(caps.completion_markdown);
+        assert!(!caps.snippets);
+        assert!(!caps.work_done_progress);
+    }
+}
@@ -0,0 +1,171 @@
+use std::io;
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tower_lsp_server::lsp_types::{
+    CodeAction, CodeActionKind, Command as LspCommand, Diagnostic, Uri,
+};
+
+use crate::api;
+
+/// Command running cargo in the workspace of a manifest, arguments are [`RunCargoArgs`].
+pub const RUN_CARGO: &str = "crates-io.runCargo";
+
+/// Run `command` in the workspace of the manifest `uri`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunCargoArgs {
+    pub uri: Uri,
+    #[serde(flatten)]
+    pub command: CargoCommand,
+}
+
+/// The cargo commands that may be run, their arguments are built here and never taken from
+/// the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "camelCase")]
+pub enum CargoCommand {
+    /// `cargo update`, or `cargo update -p <package>`
+    Update {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        package: Option<String>,
+    },
+}
+
+impl CargoCommand {
+    /// Arguments of cargo, `None` if a package is not a valid crate name.
+    pub fn args(&self) -> Option<Vec<String>> {
+        match self {
+            CargoCommand::Update { package: None } => Some(vec!["update".into()]),
+            CargoCommand::Update {
+                package: Some(package),
+            } => api::is_valid_name(package)
+                .then(|| vec!["update".into(), "-p".into(), package.clone()]),
+        }
+    }
+
+    /// Title of the code action, like ``Run `cargo update -p serde` ``.
+    pub fn title(&self) -> Option<String> {
+        Some(format!("Run `cargo {}`", self.args()?.join(" ")))
+    }
+}
+
+/// Code action running `command` for the manifest `uri`, `None` for invalid commands.
+pub fn action(
+    uri: &Uri,
+    command: CargoCommand,
+    diagnostics: Vec<Diagnostic>,
+) -> Option<CodeAction> {
+    let title = command.title()?;
+    let args = RunCargoArgs {
+        uri: uri.clone(),
+        command,
+    };
+    Some(CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(diagnostics),
+        command: Some(LspCommand {
+            title,
+            command: RUN_CARGO.into(),
+            arguments: serde_json::to_value(args).ok().map(|a| vec![a]),
+        }),
+        ..Default::default()
+    })
+}
+
+/// Result of [`RUN_CARGO`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunCargoResult {
+    /// The command line that was run
+    pub command: String,
+    pub success: bool,
+    /// `None` if cargo was terminated by a signal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+/// Run `program` with `args` in `dir`, sending each line of its stdout and stderr to `lines`.
+///
+/// The process is killed if the returned future is dropped.
+pub async fn run(
+    program: &Path,
+    args: &[String],
+    dir: &Path,
+    lines: mpsc::UnboundedSender<String>,
+) -> io::Result<ExitStatus> {
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    tokio::join!(forward(stdout, &lines), forward(stderr, &lines));
+    child.wait().await
+}
+
+async fn forward(output: Option<impl AsyncRead + Unpin>, lines: &mpsc::UnboundedSender<String>) {
+    let Some(output) = output else {
+        return;
+    };
+    let mut reader = BufReader::new(output).lines();
+    while let Ok(Some(line)) = reader.next_line().await {
+        let _ = lines.send(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_the_arguments() {
+        let all = CargoCommand::Update { package: None };
+        assert_eq!(all.args().unwrap(), ["update"]);
+        assert_eq!(all.title().unwrap(), "Run `cargo update`");
+        let one = CargoCommand::Update {
+            package: Some("serde".into()),
+        };
+        assert_eq!(one.args().unwrap(), ["update", "-p", "serde"]);
+        assert_eq!(one.title().unwrap(), "Run `cargo update -p serde`");
+
+        // No options or other arguments smuggled in as a package
+        for package in ["--manifest-path=/etc", "serde --locked", "-Zunstable", ""] {
+            let command = CargoCommand::Update {
+                package: Some(package.into()),
+            };
+            assert_eq!(command.args(), None, "{package}");
+        }
+    }
+
+    #[test]
+    fn accepts_only_known_commands() {
+        let parse = |value| serde_json::from_value::<RunCargoArgs>(value);
+        let args = parse(serde_json::json!({
+            "uri": "file:///ws/Cargo.toml",
+            "command": "update",
+            "package": "serde",
+        }))
+        .unwrap();
+        assert_eq!(
+            args.command,
+            CargoCommand::Update {
+                package: Some("serde".into())
+            }
+        );
+        let install = serde_json::json!({
+            "uri": "file:///ws/Cargo.toml",
+            "command": "install",
+            "package": "evil",
+        });
+        assert!(parse(install).is_err());
+    }
+}
@@ -0,0 +1,430 @@
+//! Carry the diagnostics of a document over its edits until a new analysis replaces them.
+//!
+//! The diagnostics of the analyzed text are mapped through the edits since,
+//! those on changed text are dropped.
+//! Dependencies whose name and requirement are unchanged keep their classification
+//! while their versions are fetched again, so they never lose their diagnostics in between.
+
+use std::ops::Range;
+
+use tower_lsp_server::lsp_types::{self, Diagnostic, NumberOrString};
+
+use crate::analysis::Analysis;
+use crate::text::LineIndex;
+
+/// Edits kept since the analyzed text, nothing is carried over beyond.
+const MAX_EDITS: usize = 4096;
+
+/// Replacement of the bytes in `range` by `len` new bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub len: usize,
+}
+
+impl Edit {
+    /// The edit turning `old` into `new`, replacing the text between their common prefix
+    /// and suffix, `None` if they are equal.
+    pub fn diff(old: &str, new: &str) -> Option<Self> {
+        if old == new {
+            return None;
+        }
+        let mut prefix = old
+            .bytes()
+            .zip(new.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
+            prefix -= 1;
+        }
+        let mut suffix = old[prefix..]
+            .bytes()
+            .rev()
+            .zip(new[prefix..].bytes().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix)
+        {
+            suffix -= 1;
+        }
+        Some(Self {
+            range: prefix..old.len() - suffix,
+            len: new.len() - suffix - prefix,
+        })
+    }
+}
+
+/// The `span` of the text before the `edits` in the text after them, applied in order.
+///
+/// `None` if an edit replaced anything within the span or inserted into it,
+/// insertions at its boundaries are outside of it.
+pub fn map_span<'a>(
+    span: Range<usize>,
+    edits: impl IntoIterator<Item = &'a Edit>,
+) -> Option<Range<usize>> {
+    let mut span = span;
+    for edit in edits {
+        if span.end <= edit.range.start {
+            continue; // Before the edit
+        }
+        if span.start < edit.range.end {
+            return None;
+        }
+        let start = span.start - edit.range.len() + edit.len;
+        span = start..start + span.len();
+    }
+    Some(span)
+}
+
+/// Edits of an open document since the text of its last published analysis.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    /// Version and lines of the analyzed text, `None` if there is nothing to carry over
+    base: Option<(i32, LineIndex)>,
+    /// Edits with the version of the document they lead to
+    edits: Vec<(i32, Edit)>,
+    /// Version of the latest edit dropped from a full history
+    lost: Option<i32>,
+}
+
+impl History {
+    /// Record an edit leading to `version`.
+    pub fn record(&mut self, version: i32, edit: Edit) {
+        if self.edits.len() >= MAX_EDITS {
+            self.base = None;
+            self.edits.clear();
+            self.lost = Some(version);
+            return;
+        }
+        self.edits.push((version, edit));
+    }
+
+    /// The analysis of the text with `lines` at `version` was published, later edits are kept.
+    pub fn analyzed(&mut self, version: Option<i32>, lines: &LineIndex) {
+        let Some(version) = version else {
+            *self = Self::default();
+            return;
+        };
+        self.edits.retain(|(v, _)| *v > version);
+        if self.lost.is_some_and(|lost| lost > version) {
+            self.base = None;
+        } else {
+            self.base = Some((version, lines.clone()));
+            self.lost = None;
+        }
+    }
+
+    /// Whether the text changed since the analysis.
+    pub fn changed(&self) -> bool {
+        !self.edits.is_empty()
+    }
+
+    /// The diagnostics of `analysis` in the current text with `lines`.
+    ///
+    /// `None` if the analyzed text is unknown, for example before the first analysis.
+    pub fn carry(&self, analysis: &Analysis, lines: &LineIndex) -> Option<Carried> {
+        let (_, base) = self.base.as_ref()?;
+        let map = |range: lsp_types::Range| {
+            let span = base.range_to_span(range)?;
+            let span = map_span(span, self.edits.iter().map(|(_, edit)| edit))?;
+            lines.span_to_range(span)
+        };
+        let mut carried = Carried::default();
+        // Index into the carried dependencies, `None` if the name or requirement changed
+        let slots = analysis
+            .dependencies
+            .iter()
+            .map(|dependency| {
+                let name = map(dependency.name_range)?;
+                if let Some(req) = dependency.req_range {
+                    map(req)?;
+                }
+                carried.dependencies.push((name, Vec::new()));
+                Some(carried.dependencies.len() - 1)
+            })
+            .collect::<Vec<_>>();
+        for diagnostic in &analysis.diagnostics {
+            let owner = analysis.dependencies.iter().position(|d| {
+                d.name_range == diagnostic.range || d.req_range == Some(diagnostic.range)
+            });
+            let slot = match owner.map(|i| slots[i]) {
+                Some(None) => continue, // The classification of a changed dependency is outdated
+                Some(slot) => slot,
+                None => None,
+            };
+            let Some(range) = map(diagnostic.range) else {
+                continue;
+            };
+            let diagnostic = Diagnostic {
+                range,
+                ..diagnostic.clone()
+            };
+            if let Some(slot) = slot {
+                carried.dependencies[slot].1.push(diagnostic.clone());
+            }
+            carried.diagnostics.push(diagnostic);
+        }
+        Some(carried)
+    }
+}
+
+/// Diagnostics carried over to a new version of a document.
+#[derive(Debug, Clone, Default)]
+pub struct Carried {
+    pub diagnostics: Vec<Diagnostic>,
+    /// Name ranges of the unchanged dependencies, with their carried diagnostics
+    dependencies: Vec<(lsp_types::Range, Vec<Diagnostic>)>,
+}
+
+impl Carried {
+    /// Replace the `fetch-pending` diagnostics of unchanged dependencies by their carried ones.
+    pub fn keep_pending(&self, diagnostics: &mut Vec<Diagnostic>) {
+        let pending =
+            |d: &Diagnostic| d.code == Some(NumberOrString::String("fetch-pending".into()));
+        if !diagnostics.iter().any(pending) {
+            return;
+        }
+        let mut kept = Vec::with_capacity(diagnostics.len());
+        for diagnostic in diagnostics.drain(..) {
+            let carried = self
+                .dependencies
+                .iter()
+                .find(|(name, carried)| *name == diagnostic.range && !carried.is_empty());
+            match carried {
+                Some((_, carried)) if pending(&diagnostic) => kept.extend(carried.iter().cloned()),
+                _ => kept.push(diagnostic),
+            }
+        }
+        kept.sort_by_key(|d| (d.range.start, d.range.end));
+        *diagnostics = kept;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use tower_lsp_server::lsp_types::Position;
+
+    use super::*;
+    use crate::analysis::AnalyzedDependency;
+    use crate::manifest::DependencyKind;
+    use crate::text::Encoding;
+
+    fn index(text: &str) -> LineIndex {
+        LineIndex::new(text, Encoding::Utf8)
+    }
+
+    fn edit(range: Range<usize>, len: usize) -> Edit {
+        Edit { range, len }
+    }
+
+    fn apply(text: &str, edit: &Edit, insert: &str) -> String {
+        format!(
+            "{}{insert}{}",
+            &text[..edit.range.start],
+            &text[edit.range.end..]
+        )
+    }
+
+    #[test]
+    fn maps_spans_around_edits() {
+        // "serde = \"1\"" with the requirement at 9..10
+        let req = 9..10;
+        assert_eq!(map_span(req.clone(), &[edit(0..0, 3)]), Some(12..13));
+        assert_eq!(map_span(req.clone(), &[edit(11..11, 5)]), Some(9..10));
+        // Insertions at the boundaries are outside
+        assert_eq!(map_span(req.clone(), &[edit(9..9, 2)]), Some(11..12));
+        assert_eq!(map_span(req.clone(), &[edit(10..10, 2)]), Some(9..10));
+        // Replacing the span or a part of it
+        assert_eq!(map_span(req.clone(), &[edit(9..10, 1)]), None);
+        assert_eq!(
+            map_span(req.clone(), &[edit(5..9, 0), edit(5..5, 4)]),
+            Some(9..10)
+        );
+        assert_eq!(map_span(0..4, &[edit(2..2, 1)]), None);
+        // Applied in order, the second edit is in the text after the first
+        assert_eq!(map_span(req, &[edit(0..2, 0), edit(8..8, 3)]), Some(7..8));
+    }
+
+    #[test]
+    fn diffs_texts() {
+        assert_eq!(Edit::diff("abc", "abc"), None);
+        assert_eq!(
+            Edit::diff("a = 1\nb = 2\n", "a = 1\nc = 3\nb = 2\n"),
+            Some(edit(6..6, 6))
+        );
+        assert_eq!(Edit::diff("abcd", "ad"), Some(edit(1..3, 0)));
+        // Never within a character
+        assert_eq!(Edit::diff("é", "è"), Some(edit(0..2, 2)));
+    }
+
+    fn dependency(line: u32, name: u32, req: Option<(u32, u32)>) -> AnalyzedDependency {
+        let range = |start, end| {
+            lsp_types::Range::new(Position::new(line, start), Position::new(line, end))
+        };
+        AnalyzedDependency {
+            package: String::new(),
+            kind: DependencyKind::Normal,
+            target: None,
+            name_range: range(0, name),
+            req: String::new(),
+            req_range: req.map(|(start, end)| range(start, end)),
+            git: false,
+            classification: None,
+        }
+    }
+
+    fn diagnostic(range: lsp_types::Range, code: &str) -> Diagnostic {
+        Diagnostic {
+            range,
+            code: Some(NumberOrString::String(code.into())),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn carries_unchanged_dependencies() {
+        let text = "[dependencies]\nalpha = \"1\"\nbeta = \"1\"\n";
+        let alpha = dependency(1, 5, Some((9, 10)));
+        let beta = dependency(2, 4, Some((8, 9)));
+        let analysis = Analysis {
+            diagnostics: vec![
+                diagnostic(alpha.name_range, "outdated-incompatible"),
+                diagnostic(beta.name_range, "outdated-incompatible"),
+                diagnostic(beta.req_range.unwrap(), "zero-major-requirement"),
+            ],
+            dependencies: vec![alpha, beta],
+            ..Default::default()
+        };
+        let mut history = History::default();
+        assert!(history.carry(&analysis, &index(text)).is_none());
+        history.analyzed(Some(1), &index(text));
+
+        // A comment above, and a new requirement of alpha
+        let mut changed = text.to_string();
+        for (range, insert) in [(15..15, "# pinned\n"), (33..34, "2")] {
+            let step = edit(range, insert.len());
+            changed = apply(&changed, &step, insert);
+            history.record(2, step);
+        }
+        assert_eq!(
+            changed,
+            "[dependencies]\n# pinned\nalpha = \"2\"\nbeta = \"1\"\n"
+        );
+        let carried = history.carry(&analysis, &index(&changed)).unwrap();
+        let lines = |diagnostics: &[Diagnostic]| {
+            diagnostics
+                .iter()
+                .map(|d| (d.range.start.line, d.range.start.character))
+                .collect::<Vec<_>>()
+        };
+        // Only beta is carried, one line further down
+        assert_eq!(lines(&carried.diagnostics), [(3, 0), (3, 8)]);
+
+        // Fetching beta again keeps its diagnostics
+        let beta = carried.diagnostics[0].range;
+        let mut fresh = vec![
+            diagnostic(
+                lsp_types::Range::new(Position::new(2, 0), Position::new(2, 5)),
+                "latest",
+            ),
+            diagnostic(beta, "fetch-pending"),
+        ];
+        carried.keep_pending(&mut fresh);
+        assert_eq!(lines(&fresh), [(2, 0), (3, 0), (3, 8)]);
+        assert_eq!(
+            fresh[1].code,
+            Some(NumberOrString::String("outdated-incompatible".into()))
+        );
+
+        // The edits up to the analyzed version are dropped
+        history.record(3, edit(0..0, 1));
+        history.analyzed(Some(2), &index(&changed));
+        assert_eq!(history.edits, [(3, edit(0..0, 1))]);
+        history.analyzed(None, &index(&changed));
+        assert!(history.base.is_none() && !history.changed());
+    }
+
+    #[test]
+    fn forgets_the_base_of_long_histories() {
+        let mut history = History::default();
+        history.analyzed(Some(1), &index("a"));
+        for version in 2..MAX_EDITS as i32 + 3 {
+            history.record(version, edit(0..0, 1));
+        }
+        assert!(history.base.is_none());
+        // An analysis of a version before the dropped edits cannot be carried over
+        history.analyzed(Some(3), &index("a"));
+        assert!(history.base.is_none());
+        history.analyzed(Some(MAX_EDITS as i32 + 2), &index("a"));
+        assert!(history.base.is_some());
+    }
+
+    /// Edits as start, length of the replaced text, and inserted text, within a text of `len`.
+    fn edits(len: usize) -> impl Strategy<Value = Vec<(usize, usize, String)>> {
+        prop::collection::vec((0..=len, 0..4usize, "[a-c\n]{0,4}"), 0..8)
+    }
+
+    proptest! {
+        #[test]
+        fn mapped_spans_cover_the_same_text(
+            text in "[a-c\n ]{0,30}",
+            span in (0..30usize, 0..30usize),
+            edits in edits(40),
+        ) {
+            let (start, end) = (span.0.min(span.1).min(text.len()), span.0.max(span.1).min(text.len()));
+            let mut changed = text.clone();
+            let mut applied = Vec::new();
+            for (at, len, insert) in edits {
+                let at = at.min(changed.len());
+                let step = edit(at..(at + len).min(changed.len()), insert.len());
+                changed = apply(&changed, &step, &insert);
+                applied.push(step);
+            }
+            if let Some(mapped) = map_span(start..end, &applied) {
+                prop_assert_eq!(&changed[mapped], &text[start..end]);
+            }
+        }
+
+        #[test]
+        fn edits_around_spans_keep_them(
+            text in "[a-c\n ]{0,30}",
+            span in (0..30usize, 0..30usize),
+            edits in prop::collection::vec((any::<bool>(), 0..40usize, 0..4usize, "[a-c\n]{0,4}"), 0..8),
+        ) {
+            let (start, end) = (span.0.min(span.1).min(text.len()), span.0.max(span.1).min(text.len()));
+            let mut changed = text.clone();
+            let mut applied = Vec::new();
+            let mut current = start..end;
+            for (before, at, len, insert) in edits {
+                // Insertions at an empty span are after it
+                let before = before && !current.is_empty();
+                let step = if before {
+                    let at = at % (current.start + 1);
+                    edit(at..(at + len).min(current.start), insert.len())
+                } else {
+                    let at = current.end + at % (changed.len() - current.end + 1);
+                    edit(at..(at + len).min(changed.len()), insert.len())
+                };
+                changed = apply(&changed, &step, &insert);
+                if before {
+                    let shifted = current.start - step.range.len() + step.len;
+                    current = shifted..shifted + current.len();
+                }
+                applied.push(step);
+            }
+            prop_assert_eq!(map_span(start..end, &applied), Some(current));
+        }
+
+        #[test]
+        fn diffs_apply(old in "\\PC{0,20}", new in "\\PC{0,20}") {
+            match Edit::diff(&old, &new) {
+                None => prop_assert_eq!(&old, &new),
+                Some(edit) => {
+                    let insert = &new[edit.range.start..edit.range.start + edit.len];
+                    prop_assert_eq!(apply(&old, &edit, insert), new);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use reqwest::header::{AUTHORIZATION, USER_AGENT};
+use reqwest::{Client, StatusCode};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, ServerError};
+
+/// Command showing the changes of a crate between two versions, arguments are [`ChangelogArgs`].
+pub const CHANGELOG: &str = "crates-io.changelog";
+
+const GITHUB_API: &str = "https://api.github.com";
+/// Service named in errors.
+const GITHUB: &str = "GitHub API";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangelogArgs {
+    pub package: String,
+    /// Currently matched version
+    pub from: String,
+    /// Newest version
+    pub to: String,
+}
+
+/// A release of the GitHub API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub name: Option<String>,
+    pub body: Option<String>,
+    pub html_url: String,
+    #[serde(default)]
+    pub draft: bool,
+    pub published_at: Option<String>,
+}
+
+/// Owner and name of a GitHub repository URL.
+pub fn github_repo(url: &str) -> Option<(&str, &str)> {
+    let url = url.trim();
+    let path = url.split_once("://").map_or(url, |(_, path)| path);
+    let path = path.strip_prefix("www.").unwrap_or(path);
+    let path = path.strip_prefix("github.com/")?;
+    let mut parts = path.split('/');
+    let owner = parts.next().filter(|o| !o.is_empty())?;
+    let repo = parts.next()?.trim_end_matches(".git");
+    (!repo.is_empty()).then_some((owner, repo))
+}
+
+/// Version of a release tag of `package`.
+///
+/// Accepts `1.2.3`, `v1.2.3`, and the monorepo styles `package-1.2.3`, `package-v1.2.3`,
+/// `package@1.2.3`, and `package/v1.2.3`. Tags of other packages are rejected.
+pub fn tag_version(tag: &str, package: &str) -> Option<Version> {
+    let version = tag
+        .strip_prefix(package)
+        .and_then(|rest| rest.strip_prefix(['-', '_', '@', '/']))
+        .unwrap_or(tag);
+    let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+    Version::parse(version).ok()
+}
+
+/// Releases of versions after `from` up to `to`, newest first.
+pub fn select<'a>(
+    releases: &'a [Release],
+    package: &str,
+    from: &Version,
+    to: &Version,
+) -> Vec<(Version, &'a Release)> {
+    let mut selected = releases
+        .iter()
+        .filter(|r| !r.draft)
+        .filter_map(|r| Some((tag_version(&r.tag_name, package)?, r)))
+        .filter(|(v, _)| from < v && v <= to)
+        .collect::<Vec<_>>();
+    selected.sort_by(|(a, _), (b, _)| b.cmp(a));
+    // Monorepos might tag a version twice, keep the first
+    selected.dedup_by(|(a, _), (b, _)| a == b);
+    selected
+}
+
+/// The latest releases of a GitHub repository, only the first page is fetched.
+pub async fn fetch_releases(
+    owner: &str,
+    repo: &str,
+    user_agent: &str,
+    token: Option<&str>,
+) -> Result<Vec<Release>> {
+    let mut request = Client::new()
+        .get(format!(
+            "{GITHUB_API}/repos/{owner}/{repo}/releases?per_page=100"
+        ))
+        .header(USER_AGENT, user_agent)
+        .header("Accept", "application/vnd.github+json");
+    if let Some(token) = token {
+        request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let response = request.send().await?;
+    // GitHub answers exceeded rate limits with 403 as well
+    if response.status() == StatusCode::FORBIDDEN {
+        return Err(ServerError::RateLimited {
+            service: GITHUB,
+            retry_after: None,
+        });
+    }
+    let repository = format!("The repository {owner}/{repo}");
+    if let Some(err) = ServerError::from_response(&response, GITHUB, &repository) {
+        return Err(err);
+    }
+    let text = response.text().await?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+/// Markdown document of the changes between the versions.
+///
+/// Without releases the published `versions` in the range are listed,
+/// `note` explains why the releases are missing.
+pub fn render(
+    args: &ChangelogArgs,
+    repository: Option<&str>,
+    releases: &[(Version, &Release)],
+    versions: &[Version],
+    note: Option<&str>,
+) -> String {
+    let mut text = format!("# {} {} → {}\n", args.package, args.from, args.to);
+    if let Some(repository) = repository {
+        text += &format!("\nRepository: {repository}\n");
+    }
+    if let Some(note) = note {
+        text += &format!("\n_{note}_\n");
+    }
+    if releases.is_empty() {
+        text += "\n## Versions\n\n";
+        for version in versions.iter().rev() {
+            text += &format!("- {version}\n");
+        }
+        return text;
+    }
+    for (version, release) in releases {
+        let title = release
+            .name
+            .as_deref()
+            .filter(|n| !n.trim().is_empty())
+            .unwrap_or(&release.tag_name);
+        text += &format!("\n## [{title}]({})", release.html_url);
+        if let Some(date) = release.published_at.as_deref().and_then(|d| d.get(..10)) {
+            text += &format!(" ({date})");
+        }
+        if !title.contains(&version.to_string()) {
+            text += &format!(" `{version}`");
+        }
+        text += "\n";
+        if let Some(body) = release.body.as_deref().filter(|b| !b.trim().is_empty()) {
+            text += &format!("\n{}\n", body.trim());
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(tag: &str) -> Release {
+        Release {
+            tag_name: tag.into(),
+            name: None,
+            body: None,
+            html_url: format!("https://github.com/o/r/releases/tag/{tag}"),
+            draft: false,
+            published_at: None,
+        }
+    }
+
+    fn v(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    fn tags(selected: &[(Version, &Release)]) -> Vec<String> {
+        selected.iter().map(|(_, r)| r.tag_name.clone()).collect()
+    }
+
+    #[test]
+    fn github_repos() {
+        for url in [
+            "https://github.com/serde-rs/serde",
+            "https://github.com/serde-rs/serde/",
+            "https://www.github.com/serde-rs/serde.git",
+            "github.com/serde-rs/serde/tree/master/serde",
+        ] {
+            assert_eq!(github_repo(url), Some(("serde-rs", "serde")), "{url}");
+        }
+        assert_eq!(github_repo("https://gitlab.com/o/r"), None);
+        assert_eq!(github_repo("https://github.com/serde-rs"), None);
+        assert_eq!(github_repo("https://github.com//serde"), None);
+    }
+
+    #[test]
+    fn tag_versions() {
+        for tag in [
+            "1.2.3",
+            "v1.2.3",
+            "V1.2.3",
+            "tokio-1.2.3",
+            "tokio-v1.2.3",
+            "tokio_1.2.3",
+            "tokio@1.2.3",
+            "tokio/v1.2.3",
+        ] {
+            assert_eq!(tag_version(tag, "tokio"), Some(v("1.2.3")), "{tag}");
+        }
+        assert_eq!(tag_version("v1.0.0-rc.1", "tokio"), Some(v("1.0.0-rc.1")));
+        // Other packages of a monorepo
+        assert_eq!(tag_version("tokio-util-0.7.0", "tokio"), None);
+        assert_eq!(tag_version("tokio-macros-v2.0.0", "tokio"), None);
+        assert_eq!(tag_version("release-1.2", "tokio"), None);
+        assert_eq!(tag_version("nightly", "tokio"), None);
+    }
+
+    #[test]
+    fn selects_the_range() {
+        let releases = [
+            "v1.0.0",
+            "v1.1.0",
+            "v1.2.0",
+            "v2.0.0-rc.1",
+            "v2.0.0",
+            "v2.1.0",
+        ]
+        .map(release);
+        let selected = select(&releases, "serde", &v("1.1.0"), &v("2.0.0"));
+        assert_eq!(tags(&selected), ["v2.0.0", "v2.0.0-rc.1", "v1.2.0"]);
+        assert!(select(&releases, "serde", &v("2.1.0"), &v("2.1.0")).is_empty());
+    }
+
+    #[test]
+    fn selects_monorepo_releases() {
+        let mut releases = [
+            "tokio-1.40.0",
+            "tokio-util-0.7.12",
+            "tokio-1.41.0",
+            "tokio-macros-2.4.0",
+            "v1.41.0",
+            "tokio-1.41.1",
+            "tokio-1.42.0",
+        ]
+        .map(release);
+        releases[5].draft = true;
+        let selected = select(&releases, "tokio", &v("1.40.0"), &v("1.42.0"));
+        // Drafts are skipped, the first tag of a version wins
+        assert_eq!(tags(&selected), ["tokio-1.42.0", "tokio-1.41.0"]);
+    }
+
+    #[test]
+    fn renders_versions_without_releases() {
+        let args = ChangelogArgs {
+            package: "foo".into(),
+            from: "1.0.0".into(),
+            to: "1.2.0".into(),
+        };
+        let text = render(
+            &args,
+            Some("https://example.com/foo"),
+            &[],
+            &[v("1.1.0"), v("1.2.0")],
+            Some("No GitHub releases match the versions"),
+        );
+        assert_eq!(
+            text,
+            "# foo 1.0.0 → 1.2.0\n\nRepository: https://example.com/foo\n\n\
+             _No GitHub releases match the versions_\n\n## Versions\n\n- 1.2.0\n- 1.1.0\n"
+        );
+
+        let mut named = release("v1.2.0");
+        named.name = Some("Foo 1.2".into());
+        named.body = Some("- Faster\n".into());
+        named.published_at = Some("2024-05-01T12:00:00Z".into());
+        let text = render(&args, None, &[(v("1.2.0"), &named)], &[], None);
+        assert_eq!(
+            text,
+            "# foo 1.0.0 → 1.2.0\n\n\
+             ## [Foo 1.2](https://github.com/o/r/releases/tag/v1.2.0) (2024-05-01) `1.2.0`\n\n- Faster\n"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::format;
+use crate::registry::BoxFuture;
+
+/// Source of the time for deadlines, timeouts, TTLs, and rate limits.
+///
+/// Tests replace it with virtual time to run time dependent behavior deterministically.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()>;
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.sleep_until(self.now() + duration)
+    }
+
+    /// The current day in days since the Unix epoch, for the age of releases.
+    #[cfg(feature = "metadata")]
+    fn today(&self) -> i64 {
+        format::today()
+    }
+
+    /// The current time in seconds since the Unix epoch, for the age of fetched versions.
+    fn unix_time(&self) -> i64 {
+        format::unix_time()
+    }
+}
+
+/// The time of the tokio runtime, which `tokio::time::pause` also makes controllable.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+/// The time of tokio with a wall clock that only moves when it is set, for tests.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct TestClock(std::sync::atomic::AtomicI64);
+
+#[cfg(test)]
+impl TestClock {
+    /// Move the wall clock to `unix_time`, in seconds since the Unix epoch.
+    pub fn set(&self, unix_time: i64) {
+        self.0
+            .store(unix_time, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        TokioClock.now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        TokioClock.sleep_until(deadline)
+    }
+
+    #[cfg(feature = "metadata")]
+    fn today(&self) -> i64 {
+        self.unix_time().div_euclid(24 * 60 * 60)
+    }
+
+    fn unix_time(&self) -> i64 {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,653 @@
+//! Completion of the keys of detailed dependencies,
+//! within inline tables (`serde = { … }`) and `[dependencies.serde]` tables,
+//! and of the names of crates that are already in the `Cargo.lock`.
+//!
+//! The context is read from the text around the cursor, as the manifest is rarely valid
+//! while a key is typed.
+
+use semver::Version;
+use tower_lsp_server::lsp_types::{
+    self, CompletionItem, CompletionItemKind, CompletionTextEdit, Documentation, InsertTextFormat,
+    TextEdit,
+};
+
+use crate::config::FeatureHint;
+use crate::keys::{dependency_key, DependencyKey, Value, DEPENDENCY_KEYS, DEPENDENCY_SECTIONS};
+use crate::lockfile::Lockfile;
+use crate::manifest;
+use crate::text::Source;
+use crate::unstable::Unstable;
+
+/// Characters starting a key of an inline table.
+pub const TRIGGER_CHARACTERS: [&str; 2] = ["{", ","];
+
+/// Keys that can be added to the dependency table at `offset`, empty elsewhere.
+///
+/// Keys already in the table are left out, as well as keys of another source,
+/// like `git` if there is a `version`, and unstable keys unless the manifest declares their
+/// feature in `cargo-features`.
+/// With `snippets` the items place the cursor into the value.
+/// `features` is pre-filled with the features `suggested` for the dependency.
+pub fn keys(
+    source: &Source,
+    offset: usize,
+    snippets: bool,
+    suggested: impl Fn(&str) -> Option<FeatureHint>,
+) -> Vec<CompletionItem> {
+    let text = source.text.as_str();
+    let Some(table) = table(text, offset) else {
+        return Vec::new();
+    };
+    let Some(range) = source.range(table.start..offset) else {
+        return Vec::new();
+    };
+    let present = table
+        .keys
+        .iter()
+        .filter_map(|k| dependency_key(k))
+        .collect::<Vec<_>>();
+    let hint = table.dependency.as_deref().and_then(suggested);
+    let unstable = Unstable::of(text);
+    DEPENDENCY_KEYS
+        .iter()
+        .enumerate()
+        .filter(|(_, key)| key.stable || unstable.legalizes(key.name))
+        .filter(|(_, key)| !table.keys.iter().any(|k| k == key.name))
+        .filter(|(_, key)| !present.iter().any(|p| key.excludes(p)))
+        .map(|(order, key)| {
+            let hint = hint.as_ref().filter(|_| key.name == "features");
+            item(key, order, range, snippets, hint)
+        })
+        .collect()
+}
+
+/// Crates of the `lockfile` that can be added at `offset`, a key typed on a line of its own
+/// in a dependency section, empty elsewhere.
+///
+/// The crates declared in the section are left out. The items insert the `requirement`
+/// of the locked version, so that adding them keeps a single version in the tree.
+pub fn names(
+    source: &Source,
+    offset: usize,
+    lockfile: &Lockfile,
+    requirement: impl Fn(&Version) -> String,
+) -> Vec<CompletionItem> {
+    let text = source.text.as_str();
+    let Some(line_start) = text
+        .get(..offset)
+        .map(|t| t.rfind('\n').map_or(0, |i| i + 1))
+    else {
+        return Vec::new();
+    };
+    let line_end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+    let typed = text[line_start..offset].trim_start();
+    if !typed.chars().all(is_key_char) || !text[offset..line_end].trim().is_empty() {
+        return Vec::new();
+    }
+    let headers = manifest::headers(text);
+    let Some(current) = headers
+        .iter()
+        .rposition(|(_, span, _)| span.end <= line_start)
+    else {
+        return Vec::new();
+    };
+    let (name, span, array) = &headers[current];
+    if *array || !in_section(&name.split('.').collect::<Vec<_>>()) {
+        return Vec::new();
+    }
+    let Some(range) = source.range(offset - typed.len()..offset) else {
+        return Vec::new();
+    };
+    let end = headers
+        .get(current + 1)
+        .map_or(text.len(), |(_, next, _)| next.start);
+    let declared = text[span.end..end]
+        .lines()
+        .filter_map(key)
+        .chain(
+            // `[dependencies.name]` tables of the section
+            headers
+                .iter()
+                .filter_map(|(header, _, _)| header.strip_prefix(name.as_str())?.strip_prefix('.'))
+                .map(str::to_string),
+        )
+        .collect::<Vec<_>>();
+    lockfile
+        .placements()
+        .filter(|p| p.path.last().is_some_and(|n| !declared.contains(n)))
+        .enumerate()
+        .filter_map(|(order, placement)| {
+            let name = placement.path.last()?.clone();
+            Some(CompletionItem {
+                label: name.clone(),
+                kind: Some(CompletionItemKind::MODULE),
+                detail: Some(format!(
+                    "already in dependency tree at {} via {}",
+                    placement.version, placement.via
+                )),
+                documentation: Some(Documentation::String(placement.path())),
+                // The nearest crates first
+                sort_text: Some(format!("{order:04}")),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: format!("{name} = \"{}\"", requirement(&placement.version)),
+                })),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+fn item(
+    key: &DependencyKey,
+    order: usize,
+    range: lsp_types::Range,
+    snippets: bool,
+    hint: Option<&FeatureHint>,
+) -> CompletionItem {
+    if let Some(hint) = hint {
+        let features = hint.features.iter().map(|f| format!("{f:?}"));
+        let features = features.collect::<Vec<_>>().join(", ");
+        let value = if snippets {
+            format!("[{features}$1]")
+        } else {
+            format!("[{features}]")
+        };
+        return CompletionItem {
+            detail: (!hint.reason.is_empty()).then(|| hint.reason.clone()),
+            ..item_with(key, order, range, snippets, value)
+        };
+    }
+    let value = match (key.value, snippets) {
+        (Value::String, true) => "\"$1\"".into(),
+        (Value::String, false) => "\"\"".into(),
+        (Value::Bool(usual), true) => format!("${{1|{usual},{}|}}", !usual),
+        (Value::Bool(usual), false) => usual.to_string(),
+        (Value::Array, true) => "[\"$1\"]".into(),
+        (Value::Array, false) => "[]".into(),
+    };
+    item_with(key, order, range, snippets, value)
+}
+
+fn item_with(
+    key: &DependencyKey,
+    order: usize,
+    range: lsp_types::Range,
+    snippets: bool,
+    value: String,
+) -> CompletionItem {
+    CompletionItem {
+        label: key.name.into(),
+        kind: Some(CompletionItemKind::PROPERTY),
+        // Keep the order of the table, the most common keys first
+        sort_text: Some(format!("{order:02}")),
+        insert_text_format: Some(if snippets {
+            InsertTextFormat::SNIPPET
+        } else {
+            InsertTextFormat::PLAIN_TEXT
+        }),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: format!("{} = {value}", key.name),
+        })),
+        ..Default::default()
+    }
+}
+
+/// A dependency table with the cursor at the position of a key.
+#[derive(Debug, PartialEq)]
+struct Table {
+    /// Start of the key typed so far
+    start: usize,
+    /// The other keys of the table
+    keys: Vec<String>,
+    /// Key of the dependency, without quotes
+    dependency: Option<String>,
+}
+
+/// The dependency table around `offset`, `None` if the cursor is not at a key.
+fn table(text: &str, offset: usize) -> Option<Table> {
+    let line_start = text.get(..offset)?.rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+    let headers = manifest::headers(text);
+    let current = headers
+        .iter()
+        .rposition(|(_, span, _)| span.end <= line_start)?;
+    let (name, span, array) = &headers[current];
+    if *array {
+        return None;
+    }
+    let path = name.split('.').collect::<Vec<_>>();
+    if in_section(&path) {
+        let line = &text[line_start..line_end];
+        inline(line, offset - line_start).map(|table| Table {
+            start: line_start + table.start,
+            dependency: key(line),
+            ..table
+        })
+    } else if path
+        .split_last()
+        .is_some_and(|(_, section)| in_section(section))
+    {
+        let end = headers
+            .get(current + 1)
+            .map_or(text.len(), |(_, next, _)| next.start);
+        let typed = text[line_start..offset].trim_start();
+        if !typed.chars().all(is_key_char) {
+            return None;
+        }
+        let keys = text[span.end..end]
+            .split_inclusive('\n')
+            .scan(span.end, |start, line| {
+                let range = *start..*start + line.len();
+                *start = range.end;
+                Some((range, line))
+            })
+            .filter(|(range, _)| !range.contains(&line_start))
+            .filter_map(|(_, line)| key(line))
+            .collect();
+        Some(Table {
+            start: offset - typed.len(),
+            keys,
+            dependency: path.last().map(|k| k.trim_matches(['"', '\'']).into()),
+        })
+    } else {
+        None
+    }
+}
+
+/// Whether the table path is a dependency section.
+fn in_section(path: &[&str]) -> bool {
+    match path {
+        [section] | ["target", _, section] => DEPENDENCY_SECTIONS.contains(section),
+        ["workspace", "dependencies"] => true,
+        _ => false,
+    }
+}
+
+/// The inline dependency table of `line`, if `cursor` is at one of its keys.
+fn inline(line: &str, cursor: usize) -> Option<Table> {
+    let (tops, _) = top_level(line);
+    let mut after_eq = tops
+        .iter()
+        .skip_while(|(_, c)| *c != '=')
+        .skip(1)
+        .filter(|(_, c)| !c.is_whitespace());
+    let open = after_eq.next().filter(|(_, c)| *c == '{')?.0 + 1;
+    let content = &line[open..];
+    let (tops, end) = top_level(content);
+    let close = tops
+        .iter()
+        .find(|(_, c)| *c == '}')
+        .map_or(end, |(i, _)| *i);
+    let cursor = cursor.checked_sub(open).filter(|c| *c <= close)?;
+
+    let mut keys = Vec::new();
+    let mut start = None;
+    let commas = tops.iter().filter(|(i, c)| *c == ',' && *i < close);
+    let bounds = commas.map(|(i, _)| *i).chain([close]);
+    let mut from = 0;
+    for to in bounds {
+        if (from..=to).contains(&cursor) && start.is_none() {
+            let typed = &content[from..cursor];
+            if typed.contains('=') {
+                return None;
+            }
+            let typed = typed.trim_start();
+            if !typed.chars().all(is_key_char) {
+                return None;
+            }
+            start = Some(open + cursor - typed.len());
+        } else if let Some(key) = key(&content[from..to]) {
+            keys.push(key);
+        }
+        from = to + 1;
+    }
+    Some(Table {
+        start: start?,
+        keys,
+        dependency: None,
+    })
+}
+
+/// The key of a `key = value` pair, without quotes.
+fn key(pair: &str) -> Option<String> {
+    let (tops, _) = top_level(pair);
+    let (eq, _) = tops.iter().find(|(_, c)| *c == '=')?;
+    let key = pair[..*eq].trim().trim_matches(['"', '\'']);
+    (!key.is_empty()).then(|| key.into())
+}
+
+/// Characters outside of strings and brackets with their offsets, and the end of the code
+/// before a comment.
+///
+/// Opening brackets are included, as well as closing ones without an opening bracket.
+fn top_level(text: &str) -> (Vec<(usize, char)>, usize) {
+    let mut tops = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && q == '"' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '#' => return (tops, i),
+            '[' | '{' => {
+                if depth == 0 {
+                    tops.push((i, c));
+                }
+                depth += 1;
+            }
+            ']' | '}' if depth > 0 => depth -= 1,
+            _ if depth == 0 => tops.push((i, c)),
+            _ => {}
+        }
+    }
+    (tops, text.len())
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use super::*;
+    use crate::text::Encoding;
+
+    /// Span of the key typed so far.
+    fn typed(text: &str, offset: usize) -> Option<Range<usize>> {
+        table(text, offset).map(|t| t.start..offset)
+    }
+
+    /// Labels and inserted texts of the completions at `|`.
+    fn complete(text: &str, snippets: bool) -> Vec<(String, String)> {
+        let offset = text.find('|').unwrap();
+        let text = text.replace('|', "");
+        keys(
+            &Source::new(&text, Encoding::Utf8),
+            offset,
+            snippets,
+            |_| None,
+        )
+        .into_iter()
+        .map(|item| {
+            let Some(CompletionTextEdit::Edit(edit)) = item.text_edit else {
+                panic!("no edit");
+            };
+            (item.label, edit.new_text)
+        })
+        .collect()
+    }
+
+    fn labels(text: &str) -> Vec<String> {
+        complete(text, true).into_iter().map(|(l, _)| l).collect()
+    }
+
+    #[test]
+    fn completes_keys_of_inline_tables() {
+        assert_eq!(
+            labels("[dependencies]\nserde = { version = \"1\", | }\n"),
+            [
+                "features",
+                "optional",
+                "default-features",
+                "package",
+                "registry"
+            ]
+        );
+        // After the opening brace and in target and workspace sections
+        for header in [
+            "dependencies",
+            "target.'cfg(unix)'.dev-dependencies",
+            "workspace.dependencies",
+        ] {
+            let text = format!("[{header}]\nserde = {{|}}\n");
+            assert_eq!(labels(&text).len(), 12, "{header}");
+        }
+    }
+
+    #[test]
+    fn completes_declared_unstable_keys() {
+        let text = "[dependencies]\nserde = { version = \"1\", | }\n";
+        let declared = format!("cargo-features = [\"bindeps\", \"public-dependency\"]\n\n{text}");
+        assert_eq!(
+            labels(&declared)[labels(text).len()..],
+            ["artifact", "lib", "target", "public"]
+        );
+        // Not for other features
+        let other = format!("cargo-features = [\"trim-paths\"]\n\n{text}");
+        assert_eq!(labels(&other), labels(text));
+    }
+
+    #[test]
+    fn completes_keys_of_dependency_tables() {
+        let text = "[dependencies.serde]\ngit = \"https://github.com/serde-rs/serde\"\nfeatures = [\n    \"derive\",\n]\n|\n\n[features]\n";
+        assert_eq!(
+            labels(text),
+            [
+                "optional",
+                "default-features",
+                "package",
+                "branch",
+                "tag",
+                "rev"
+            ]
+        );
+    }
+
+    #[test]
+    fn replaces_the_typed_key() {
+        let text = "[dependencies]\nserde = { version = \"1\", default-f }\n";
+        let offset = text.find("default-f").unwrap();
+        assert_eq!(typed(text, offset + 9), Some(offset..offset + 9));
+        let text = "[dependencies.serde]\n  opt\n";
+        assert_eq!(typed(text, text.len() - 1), Some(23..26));
+    }
+
+    #[test]
+    fn nothing_at_values_or_elsewhere() {
+        for text in [
+            "[dependencies]\nserde = { version = | }\n",
+            "[dependencies]\nserde = { version = \"1|\" }\n",
+            "[dependencies]\nserde = { features = [\"a\",|] }\n",
+            "[dependencies]\nserde = { version = \"1\" }|\n",
+            "[dependencies]\nserde = \"1|\"\n",
+            "[dependencies]\n|\n",
+            "[dependencies.serde]\nversion = |\n",
+            "[package]\nmetadata = { | }\n",
+            "[dependencies]\nserde = { version = \"1\" } # {|\n",
+        ] {
+            assert_eq!(labels(text), Vec::<String>::new(), "{text}");
+        }
+    }
+
+    #[test]
+    fn snippets_or_plain_text() {
+        let text = "[dependencies]\nserde = { workspace = true, | }\n";
+        assert_eq!(
+            complete(text, true),
+            [
+                ("features".into(), "features = [\"$1\"]".into()),
+                ("optional".into(), "optional = ${1|true,false|}".into()),
+                (
+                    "default-features".into(),
+                    "default-features = ${1|false,true|}".into()
+                ),
+                ("package".into(), "package = \"$1\"".into()),
+            ]
+        );
+        assert_eq!(
+            complete(text, false),
+            [
+                ("features".into(), "features = []".into()),
+                ("optional".into(), "optional = true".into()),
+                ("default-features".into(), "default-features = false".into()),
+                ("package".into(), "package = \"\"".into()),
+            ]
+        );
+        let item = &keys(
+            &Source::new(&text.replace('|', ""), Encoding::Utf8),
+            text.find('|').unwrap(),
+            false,
+            |_| None,
+        )[0];
+        assert_eq!(item.insert_text_format, Some(InsertTextFormat::PLAIN_TEXT));
+    }
+
+    const LOCK: &str = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde_json", "toml"]
+
+[[package]]
+name = "serde_json"
+version = "1.0.100"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["serde"]
+
+[[package]]
+name = "toml"
+version = "0.8.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["serde"]
+
+[[package]]
+name = "serde"
+version = "1.0.188"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    /// Labels, details, and inserted texts of the crate names at `|`.
+    fn complete_names(text: &str) -> Vec<(String, String, String)> {
+        let lockfile = Lockfile::parse(LOCK).unwrap();
+        let offset = text.find('|').unwrap();
+        let text = text.replace('|', "");
+        let source = Source::new(&text, Encoding::Utf8);
+        names(&source, offset, &lockfile, |v| format!("^{v}"))
+            .into_iter()
+            .map(|item| {
+                let Some(CompletionTextEdit::Edit(edit)) = item.text_edit else {
+                    panic!("no edit");
+                };
+                (item.label, item.detail.unwrap(), edit.new_text)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn completes_locked_crate_names() {
+        let completed = complete_names("[dependencies]\nserde_json = \"1\"\n  se|\n");
+        assert_eq!(
+            completed,
+            [
+                (
+                    "toml".into(),
+                    "already in dependency tree at 0.8.0 via app".into(),
+                    "toml = \"^0.8.0\"".into()
+                ),
+                (
+                    "serde".into(),
+                    "already in dependency tree at 1.0.188 via serde_json".into(),
+                    "serde = \"^1.0.188\"".into()
+                ),
+            ]
+        );
+        // Only the typed key is replaced
+        let text = "[dev-dependencies]\nse\n";
+        let source = Source::new(text, Encoding::Utf8);
+        let items = names(&source, 21, &Lockfile::parse(LOCK).unwrap(), |v| {
+            v.to_string()
+        });
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("no edit");
+        };
+        assert_eq!(
+            edit.range,
+            lsp_types::Range::new(
+                lsp_types::Position::new(1, 0),
+                lsp_types::Position::new(1, 2)
+            )
+        );
+        assert_eq!(
+            items[0].documentation,
+            Some(Documentation::String("app → serde_json".into()))
+        );
+        // Tables of the section are declared too
+        let completed =
+            complete_names("[dependencies]\n|\n\n[dependencies.toml]\nversion = \"0.8\"\n");
+        assert_eq!(completed.len(), 2);
+        assert!(completed.iter().all(|(label, ..)| label != "toml"));
+    }
+
+    #[test]
+    fn no_crate_names_elsewhere() {
+        for text in [
+            "[dependencies]\nserde = |\n",
+            "[dependencies]\nserde = { | }\n",
+            "[dependencies]\nse| = \"1\"\n",
+            "[dependencies.serde]\n|\n",
+            "[package]\n|\n",
+            "|\n[dependencies]\n",
+        ] {
+            assert_eq!(complete_names(text), Vec::new(), "{text}");
+        }
+    }
+
+    #[test]
+    fn prefills_suggested_features() {
+        let suggested = |name: &str| {
+            (name == "serde").then(|| FeatureHint {
+                features: vec!["derive".into()],
+                reason: "for derives".into(),
+            })
+        };
+        for (text, snippets, expected) in [
+            (
+                "[dependencies]\nserde = { version = \"1\", | }\n",
+                true,
+                "features = [\"derive\"$1]",
+            ),
+            (
+                "[dependencies.'serde']\nversion = \"1\"\n|\n",
+                false,
+                "features = [\"derive\"]",
+            ),
+            (
+                "[dependencies]\ntokio = { version = \"1\", | }\n",
+                false,
+                "features = []",
+            ),
+        ] {
+            let offset = text.find('|').unwrap();
+            let source = Source::new(&text.replace('|', ""), Encoding::Utf8);
+            let items = keys(&source, offset, snippets, suggested);
+            let item = items.iter().find(|i| i.label == "features").unwrap();
+            let Some(CompletionTextEdit::Edit(edit)) = &item.text_edit else {
+                panic!("no edit");
+            };
+            assert_eq!(edit.new_text, expected, "{text}");
+        }
+        let text = "[dependencies]\nserde = { | }\n";
+        let items = keys(
+            &Source::new(&text.replace('|', ""), Encoding::Utf8),
+            text.find('|').unwrap(),
+            true,
+            suggested,
+        );
+        let item = items.iter().find(|i| i.label == "features").unwrap();
+        assert_eq!(item.detail.as_deref(), Some("for derives"));
+    }
+}
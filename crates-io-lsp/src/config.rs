@@ -0,0 +1,467 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::DiagnosticSeverity;
+
+use crate::artifact::{ARTIFACT_DEPENDENCY, INVALID_ARTIFACT};
+use crate::dev_only::DEV_ONLY_DEPENDENCY;
+use crate::features::FEATURE_CYCLE;
+use crate::keys::{MISTYPED_VALUE, UNKNOWN_KEY};
+use crate::links::LINKS_WITHOUT_BUILD;
+use crate::manifest::DependencyKind;
+use crate::messages::Language;
+use crate::partial::PARTIALLY_ANALYZED;
+use crate::resolver::RESOLVER_FEATURES;
+use crate::suggest::MISSING_FEATURE;
+use crate::unstable::{STABILIZED_CARGO_FEATURE, UNKNOWN_CARGO_FEATURE};
+use crate::version::Classification;
+use crate::workspace::{EXCLUDED_DEFAULT_MEMBER, PREFER_WORKSPACE};
+
+/// Settings passed by the client as `initialization_options` or configuration change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct Config {
+    /// Registry endpoint, overrides the command line argument
+    pub endpoint: Option<String>,
+    /// Registry endpoints tried in order, mirrors after the primary, override `endpoint`
+    pub endpoints: Vec<String>,
+    /// Registry token, overrides the command line argument
+    pub token: Option<String>,
+    pub severity: Severities,
+    /// Severities of `[dev-dependencies]`, also of a platform
+    pub dev_dependencies: KindSeverities,
+    /// Severities of `[build-dependencies]`, also of a platform
+    pub build_dependencies: KindSeverities,
+    /// Check the `[package]` table for missing fields and invalid licenses
+    pub package_lints: bool,
+    /// Flag keys that cargo does not know, like misspelled dependency fields
+    pub strict_keys: bool,
+    /// Flag `[profile.*.package.name]` overrides of packages that are not dependencies
+    pub profile_packages: bool,
+    /// Flag `[lints] workspace = true` without `[workspace.lints]`
+    pub workspace_lints: bool,
+    /// Flag crates whose requirements in different sections no version satisfies together
+    pub conflicting_requirements: bool,
+    /// Flag requirements holding back the newest version the other sections allow
+    pub held_back_requirements: bool,
+    /// Flag `0` requirements, which span the incompatible `0.x` minors
+    pub zero_major_requirements: bool,
+    /// Flag dependencies that only the tests, examples, and benchmarks import
+    pub check_dev_only: bool,
+    /// Hint at well-known crates declared without the features they are usually needed with
+    pub missing_features: bool,
+    /// Pre-fill the usual features when completing `features` of a well-known crate
+    pub suggest_features: bool,
+    /// Usual features by crate, added to the built-in ones, without features they are removed
+    pub feature_hints: BTreeMap<String, FeatureHint>,
+    /// Show a code lens with the update above each outdated dependency
+    pub per_dependency_lens: bool,
+    /// Precision of the versions written by updates
+    pub requirement_style: RequirementStyle,
+    /// Suggest updates to newer pre-releases
+    pub include_prerelease: bool,
+    /// Show the checksums of locked versions and compare those of `Cargo.lock` with the registry
+    pub show_checksums: bool,
+    /// Summarize the declarations that are only partially analyzed in one diagnostic
+    pub partial_analysis: bool,
+    /// Maximum number of update actions of an outdated dependency
+    pub max_update_actions: usize,
+    /// Show downloads, releases and owners from crates.io in hovers
+    #[cfg(feature = "metadata")]
+    pub metadata: bool,
+    /// User-Agent for the crates.io API, which asks for a contact
+    #[cfg(feature = "metadata")]
+    pub user_agent: Option<String>,
+    /// Flag dependencies whose newest release is older than `stale_crate_years`
+    #[cfg(feature = "metadata")]
+    pub stale_crates: bool,
+    /// Age of the newest release in years after which a crate is stale
+    #[cfg(feature = "metadata")]
+    pub stale_crate_years: f64,
+    /// Crates that are never reported as stale, like finished ones
+    #[cfg(feature = "metadata")]
+    pub ignore_stale: Vec<String>,
+    /// Token for the GitHub API, raising its rate limit for changelogs
+    #[cfg(feature = "changelog")]
+    pub github_token: Option<String>,
+    /// Sort dependencies separated by blank lines on their own
+    pub sort_within_groups: bool,
+    /// Also sort `[dependencies.name]` tables, moving whole tables
+    pub sort_tables: bool,
+    /// Maximum width of inline dependency tables when formatting
+    pub inline_table_width: usize,
+    /// Formatting converts wider inline tables to `[dependencies.name]` tables
+    pub allow_table_conversion: bool,
+    /// Dependencies that may have another version than the workspace
+    pub allow_version_override: Vec<String>,
+    /// Globs of manifests that are never analyzed, relative to the workspace folder
+    pub exclude_paths: Vec<String>,
+    /// Members of a workspace that are read without being opened,
+    /// `None` for its `default-members` if it has them, otherwise all `members`
+    pub scan_scope: Option<ScanScope>,
+    /// Maximum number of concurrent registry requests of all analyses
+    pub fetch_concurrency: usize,
+    /// Milliseconds after which the results of an analysis are published,
+    /// the remaining crates follow when they are fetched
+    pub analysis_deadline: u64,
+    /// Milliseconds after which a single fetch is abandoned
+    pub fetch_timeout: u64,
+    /// Show a diagnostic for crates that are still being fetched after the deadline
+    pub show_pending: bool,
+    /// Use `crates-io` as the source of all diagnostics instead of one per category
+    pub legacy_source: bool,
+    /// Index files larger than this are rejected
+    pub max_index_bytes: usize,
+    /// Maximum number of crates in the version cache
+    pub cache_capacity: usize,
+    /// Approximate maximum size of the version cache in bytes
+    pub cache_bytes: usize,
+    /// Seconds after which cached versions are refreshed at least, for crates releasing often
+    pub min_cache_ttl: u64,
+    /// Seconds after which cached versions are refreshed at most, for crates releasing rarely
+    pub max_cache_ttl: u64,
+    /// Interval in milliseconds in which informational log messages are sent
+    pub log_interval: u64,
+    /// Least severe log messages that are sent
+    pub log_level: LogLevel,
+    /// Milliseconds after which the timings of an analysis are logged as information
+    pub slow_analysis_threshold: u64,
+    /// Allow `crates-io.runCargo` to run cargo in the workspace
+    pub allow_cargo_commands: bool,
+    /// Count usage statistics in a local file for `crates-io.stats`
+    pub stats: bool,
+    /// Send `crates-io/event` notifications when the dependencies of a document change
+    pub emit_events: bool,
+    /// Language of the messages if the client does not send a supported locale
+    pub locale: Option<String>,
+    /// Plain-language messages for screen readers, naming the severity and topic of each
+    /// diagnostic in words and describing versions instead of listing them
+    pub message_accessibility: bool,
+    /// Negotiated from the client locale and `locale`, with `messageAccessibility`
+    #[serde(skip)]
+    pub language: Language,
+    /// Incremented whenever the registry changes
+    #[serde(skip)]
+    pub epoch: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            endpoints: Vec::new(),
+            token: None,
+            severity: Default::default(),
+            dev_dependencies: Default::default(),
+            build_dependencies: Default::default(),
+            package_lints: false,
+            strict_keys: true,
+            profile_packages: true,
+            workspace_lints: true,
+            conflicting_requirements: true,
+            held_back_requirements: false,
+            zero_major_requirements: true,
+            check_dev_only: false,
+            missing_features: true,
+            suggest_features: true,
+            feature_hints: BTreeMap::new(),
+            per_dependency_lens: false,
+            requirement_style: RequirementStyle::Preserve,
+            include_prerelease: false,
+            show_checksums: false,
+            partial_analysis: false,
+            max_update_actions: 3,
+            #[cfg(feature = "metadata")]
+            metadata: true,
+            #[cfg(feature = "metadata")]
+            user_agent: None,
+            #[cfg(feature = "metadata")]
+            stale_crates: false,
+            #[cfg(feature = "metadata")]
+            stale_crate_years: 3.0,
+            #[cfg(feature = "metadata")]
+            ignore_stale: Vec::new(),
+            #[cfg(feature = "changelog")]
+            github_token: None,
+            sort_within_groups: true,
+            sort_tables: false,
+            inline_table_width: 100,
+            allow_table_conversion: false,
+            allow_version_override: Vec::new(),
+            exclude_paths: Vec::new(),
+            scan_scope: None,
+            fetch_concurrency: 16,
+            analysis_deadline: 5000,
+            fetch_timeout: 30000,
+            show_pending: true,
+            legacy_source: false,
+            max_index_bytes: 32 << 20,
+            cache_capacity: 4096,
+            cache_bytes: 64 << 20,
+            min_cache_ttl: 15 * 60,
+            max_cache_ttl: 24 * 60 * 60,
+            log_interval: 250,
+            log_level: LogLevel::Debug,
+            slow_analysis_threshold: 3000,
+            allow_cargo_commands: false,
+            stats: false,
+            emit_events: false,
+            locale: None,
+            message_accessibility: false,
+            language: Language::default(),
+            epoch: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Severity of the `classification` of a dependency of `kind`.
+    ///
+    /// Outdated dev and build dependencies are one level below normal ones unless configured,
+    /// unknown versions are as severe for every kind.
+    pub fn classification_severity(
+        &self,
+        kind: DependencyKind,
+        classification: &Classification,
+    ) -> Severity {
+        let severity = self.severity.classification(classification);
+        match classification {
+            Classification::OutdatedCompatible { .. }
+            | Classification::OutdatedIncompatible { .. } => self.outdated_severity(kind, severity),
+            Classification::Any | Classification::Latest | Classification::Unknown => severity,
+        }
+    }
+
+    /// Severity of an outdated finding of a dependency of `kind`, `severity` for normal ones.
+    pub fn outdated_severity(&self, kind: DependencyKind, severity: Severity) -> Severity {
+        let overrides = match kind {
+            DependencyKind::Dev => &self.dev_dependencies,
+            DependencyKind::Build => &self.build_dependencies,
+            DependencyKind::Normal | DependencyKind::Workspace => return severity,
+        };
+        overrides.outdated.unwrap_or(severity.lower())
+    }
+
+    /// `source` of the diagnostics with `code`, like `crates-io.versions`.
+    pub fn source(&self, code: &str) -> String {
+        if self.legacy_source {
+            "crates-io".to_string()
+        } else {
+            format!("crates-io.{}", category(code))
+        }
+    }
+}
+
+/// Diagnostic severity for every category.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct Severities {
+    pub any: Severity,
+    pub latest: Severity,
+    pub outdated_compatible: Severity,
+    pub outdated_incompatible: Severity,
+    pub major_available: Severity,
+    pub unknown: Severity,
+    pub fetch_failed: Severity,
+    pub invalid_crate_name: Severity,
+    pub package_missing_field: Severity,
+    pub package_recommended_field: Severity,
+    pub invalid_license: Severity,
+    pub missing_member: Severity,
+    pub excluded_default_member: Severity,
+    pub parse_error: Severity,
+    pub fetch_pending: Severity,
+    pub prefer_workspace_dependency: Severity,
+    pub unknown_key: Severity,
+    pub unknown_profile_package: Severity,
+    pub missing_workspace_lints: Severity,
+    pub resolver_features: Severity,
+    pub missing_workspace_field: Severity,
+    pub prerelease_requirement: Severity,
+    pub zero_major_requirement: Severity,
+    pub shadowed_crate: Severity,
+    pub conflicting_requirements: Severity,
+    pub held_back_requirement: Severity,
+    pub feature_cycle: Severity,
+    pub artifact_dependency: Severity,
+    pub invalid_artifact: Severity,
+    pub links_without_build: Severity,
+    pub stale_crate: Severity,
+    pub dev_only_dependency: Severity,
+    pub lock_behind: Severity,
+    pub checksum_mismatch: Severity,
+    pub partially_analyzed: Severity,
+    pub missing_feature: Severity,
+    pub unknown_cargo_feature: Severity,
+    pub stabilized_cargo_feature: Severity,
+}
+
+impl Default for Severities {
+    fn default() -> Self {
+        Self {
+            any: Severity::Information,
+            latest: Severity::Hint,
+            outdated_compatible: Severity::Hint,
+            outdated_incompatible: Severity::Warning,
+            unknown: Severity::Error,
+            fetch_failed: Severity::Error,
+            invalid_crate_name: Severity::Error,
+            package_missing_field: Severity::Warning,
+            package_recommended_field: Severity::Hint,
+            invalid_license: Severity::Error,
+            missing_member: Severity::Warning,
+            excluded_default_member: Severity::Warning,
+            parse_error: Severity::Error,
+            fetch_pending: Severity::Hint,
+            prefer_workspace_dependency: Severity::Hint,
+            unknown_key: Severity::Warning,
+            unknown_profile_package: Severity::Warning,
+            missing_workspace_lints: Severity::Error,
+            resolver_features: Severity::Warning,
+            missing_workspace_field: Severity::Error,
+            prerelease_requirement: Severity::Warning,
+            zero_major_requirement: Severity::Warning,
+            shadowed_crate: Severity::Warning,
+            conflicting_requirements: Severity::Warning,
+            held_back_requirement: Severity::Hint,
+            feature_cycle: Severity::Error,
+            artifact_dependency: Severity::Information,
+            invalid_artifact: Severity::Error,
+            links_without_build: Severity::Error,
+            stale_crate: Severity::Information,
+            dev_only_dependency: Severity::Hint,
+            lock_behind: Severity::Hint,
+            checksum_mismatch: Severity::Error,
+            partially_analyzed: Severity::Hint,
+            missing_feature: Severity::Information,
+            unknown_cargo_feature: Severity::Warning,
+            stabilized_cargo_feature: Severity::Information,
+            major_available: Severity::Warning,
+        }
+    }
+}
+
+impl Severities {
+    pub fn classification(&self, classification: &Classification) -> Severity {
+        match classification {
+            Classification::Any => self.any,
+            Classification::Latest => self.latest,
+            Classification::OutdatedCompatible { .. } => self.outdated_compatible,
+            Classification::OutdatedIncompatible { .. } => self.outdated_incompatible,
+            Classification::Unknown => self.unknown,
+        }
+    }
+}
+
+/// Severities of the dependencies of one kind.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct KindSeverities {
+    /// Outdated requirements and available majors, one level below normal dependencies if unset
+    pub outdated: Option<Severity>,
+}
+
+/// Category of the diagnostics with `code`, like `versions` for `outdated-compatible`.
+pub fn category(code: &str) -> &'static str {
+    match code {
+        RESOLVER_FEATURES | FEATURE_CYCLE | MISSING_FEATURE => "features",
+        "package-missing-field"
+        | "package-recommended-field"
+        | "invalid-license"
+        | "missing-member"
+        | EXCLUDED_DEFAULT_MEMBER
+        | "missing-workspace-field"
+        | "missing-workspace-lints"
+        | "unknown-profile-package"
+        | "parse-error"
+        | MISTYPED_VALUE
+        | "invalid-config"
+        | ARTIFACT_DEPENDENCY
+        | INVALID_ARTIFACT
+        | LINKS_WITHOUT_BUILD
+        | PARTIALLY_ANALYZED
+        | UNKNOWN_CARGO_FEATURE
+        | STABILIZED_CARGO_FEATURE => "package",
+        UNKNOWN_KEY | PREFER_WORKSPACE | DEV_ONLY_DEPENDENCY => "style",
+        _ => "versions",
+    }
+}
+
+/// Features a crate is usually needed with, see [`crate::suggest`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureHint {
+    pub features: Vec<String>,
+    /// Why the features are needed, like "for `#[derive(Serialize, Deserialize)]`"
+    pub reason: String,
+}
+
+/// Workspace members that are read in the background and visited by the workspace commands.
+///
+/// Opened manifests are always analyzed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanScope {
+    /// `workspace.default-members`, the packages cargo builds without `--workspace`
+    DefaultMembers,
+    /// All of `workspace.members`
+    Members,
+    /// Only the opened manifests
+    OpenOnly,
+}
+
+/// How versions are written into requirements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequirementStyle {
+    /// `1.0.215`
+    Full,
+    /// `1.0`
+    Minor,
+    /// `1`
+    Major,
+    /// Same precision as the current requirement
+    Preserve,
+}
+
+/// Least severe log messages that are sent, from the most to the least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warning,
+    Info,
+    /// Also the details like the timings of each analysis
+    Debug,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    /// The next less severe level, hints stay hints.
+    pub fn lower(self) -> Self {
+        match self {
+            Severity::Error => Severity::Warning,
+            Severity::Warning => Severity::Information,
+            Severity::Information | Severity::Hint => Severity::Hint,
+        }
+    }
+}
+
+impl From<Severity> for DiagnosticSeverity {
+    fn from(value: Severity) -> Self {
+        match value {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Information => DiagnosticSeverity::INFORMATION,
+            Severity::Hint => DiagnosticSeverity::HINT,
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tower_lsp_server::lsp_types::{self, Diagnostic, DiagnosticSeverity, NumberOrString, Uri};
+
+use crate::config::Config;
+use crate::keys::Map;
+use crate::messages::{Key, Message};
+use crate::schema::{self, Problem};
+use crate::text::Source;
+
+/// Names of the checked-in configuration file, in order of preference.
+pub const FILE_NAMES: [&str; 2] = [".crates-io-lsp.toml", "crates-io-lsp.toml"];
+
+/// Glob for watching the configuration files.
+pub const GLOB: &str = "**/{.crates-io-lsp.toml,crates-io-lsp.toml}";
+
+pub fn is_config_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| FILE_NAMES.contains(&n))
+}
+
+pub fn is_config_uri(uri: &Uri) -> bool {
+    uri.path()
+        .segments()
+        .next_back()
+        .is_some_and(|n| FILE_NAMES.iter().any(|f| n == *f))
+}
+
+/// Nearest configuration file, walking up from `dir` but not past `root`.
+///
+/// Fails if a directory or file cannot be inspected, like in an unreadable snapshot,
+/// missing ones are skipped.
+pub fn find(dir: &Path, root: Option<&Path>) -> io::Result<Option<PathBuf>> {
+    for dir in dir.ancestors() {
+        for name in FILE_NAMES {
+            let path = dir.join(name);
+            match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => return Ok(Some(path)),
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if Some(dir) == root {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/// Settings of a configuration file.
+#[derive(Debug)]
+pub struct Parsed {
+    /// In the same representation as the LSP settings, without the values the schema rejects
+    pub options: Value,
+    /// Rejected settings with the span of their key
+    pub problems: Vec<(Problem, Range<usize>)>,
+}
+
+/// Parse a configuration file into the same representation as the LSP settings.
+///
+/// The settings are validated, but unset fields are kept unset for merging.
+/// Only malformed TOML fails, unknown and mistyped settings are skipped as [`Parsed::problems`].
+pub fn parse(text: &str) -> Result<Parsed, String> {
+    let table: toml::Table = toml::from_str(text).map_err(|e| e.message().to_string())?;
+    let mut options = serde_json::to_value(table).map_err(|e| e.to_string())?;
+    let problems = schema::validate(&mut options);
+    serde_json::from_value::<Config>(options.clone()).map_err(|e| e.to_string())?;
+    let keys = toml::from_str::<Keys>(text).unwrap_or_default();
+    let problems = problems
+        .into_iter()
+        .map(|problem| {
+            let span = keys.span(&problem.path);
+            (problem, span)
+        })
+        .collect();
+    Ok(Parsed { options, problems })
+}
+
+/// Keys of the tables of a file with their spans, locating the [`Problem`]s.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+struct Keys(Map<Keys>);
+
+impl Keys {
+    /// Span of the innermost key of `path` that is written out,
+    /// empty at the top for the file itself.
+    fn span(&self, path: &[String]) -> Range<usize> {
+        let Some((first, rest)) = path.split_first() else {
+            return 0..0;
+        };
+        // The last definition wins, like in the parsed table
+        match self
+            .0
+             .0
+            .iter()
+            .rev()
+            .find(|(key, _)| key.get_ref() == first)
+        {
+            Some((key, _)) if rest.is_empty() => key.span(),
+            Some((key, keys)) => match keys.span(rest) {
+                span if span.is_empty() => key.span(),
+                span => span,
+            },
+            None => 0..0,
+        }
+    }
+}
+
+/// Recursively merge `over` into `base`, values of `over` take precedence.
+pub fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Object(base), Value::Object(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(base) => merge(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (_, Value::Null) => {}
+        (base, over) => *base = over,
+    }
+}
+
+/// Diagnostics of a configuration file: an error at the top if it is malformed,
+/// otherwise the rejected settings at their keys.
+pub fn diagnostics(
+    source: &Source,
+    parsed: &Result<Parsed, String>,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    match parsed {
+        Err(err) => vec![invalid(err, config)],
+        Ok(parsed) => parsed
+            .problems
+            .iter()
+            .map(|(problem, span)| {
+                let range = source.range(span.clone()).unwrap_or_default();
+                warning(range, problem.message(config.language), config)
+            })
+            .collect(),
+    }
+}
+
+/// Diagnostic at the top of a malformed configuration file.
+pub fn invalid(err: &str, config: &Config) -> Diagnostic {
+    let message = Message::new(Key::InvalidConfig)
+        .arg(err)
+        .render(config.language);
+    warning(Default::default(), message, config)
+}
+
+fn warning(range: lsp_types::Range, message: String, config: &Config) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String("invalid-config".into())),
+        source: Some(config.source("invalid-config")),
+        message,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::text::Encoding;
+
+    /// Nested workspace tree in a temporary directory, removed on drop.
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("ws/crates/a/src")).unwrap();
+            fs::create_dir_all(root.join("ws/crates/b")).unwrap();
+            fs::write(root.join("crates-io-lsp.toml"), "").unwrap();
+            fs::write(root.join("ws/crates-io-lsp.toml"), "").unwrap();
+            fs::write(root.join("ws/.crates-io-lsp.toml"), "").unwrap();
+            fs::write(root.join("ws/crates/b/crates-io-lsp.toml"), "").unwrap();
+            Self(root)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn finds_the_nearest_file() {
+        let fixture = Fixture::new("crates-io-lsp-config-find");
+        let ws = fixture.0.join("ws");
+        // The hidden name is preferred
+        assert_eq!(
+            find(&ws.join("crates/a/src"), Some(&ws)).unwrap(),
+            Some(ws.join(".crates-io-lsp.toml"))
+        );
+        assert_eq!(
+            find(&ws.join("crates/b"), Some(&ws)).unwrap(),
+            Some(ws.join("crates/b/crates-io-lsp.toml"))
+        );
+        // Not past the workspace root
+        fs::remove_file(ws.join(".crates-io-lsp.toml")).unwrap();
+        fs::remove_file(ws.join("crates-io-lsp.toml")).unwrap();
+        assert_eq!(find(&ws.join("crates/a"), Some(&ws)).unwrap(), None);
+        assert_eq!(
+            find(&ws.join("crates/a"), None).unwrap(),
+            Some(fixture.0.join("crates-io-lsp.toml"))
+        );
+        // Missing directories are no failure
+        assert_eq!(find(&ws.join("crates/c"), Some(&ws)).unwrap(), None);
+        // A file in place of a directory is
+        let file = fixture.0.join("crates-io-lsp.toml");
+        assert!(find(&file.join("sub"), None).is_err());
+    }
+
+    #[test]
+    fn recognizes_the_names() {
+        assert!(is_config_file(Path::new("/ws/.crates-io-lsp.toml")));
+        assert!(is_config_file(Path::new("/ws/crates-io-lsp.toml")));
+        assert!(!is_config_file(Path::new("/ws/Cargo.toml")));
+        assert!(!is_config_file(Path::new("/ws/crates-io-lsp.toml/x")));
+
+        let uri: Uri = "file:///ws/.crates-io-lsp.toml".parse().unwrap();
+        assert!(is_config_uri(&uri));
+        let uri: Uri = "file:///ws/Cargo.toml".parse().unwrap();
+        assert!(!is_config_uri(&uri));
+    }
+
+    #[test]
+    fn parses_only_the_set_fields() {
+        let text = "packageLints = true\n[severity]\nunknown = \"warning\"\n";
+        assert_eq!(
+            parse(text).unwrap().options,
+            json!({ "packageLints": true, "severity": { "unknown": "warning" } })
+        );
+
+        assert!(parse("packageLints = ").is_err());
+    }
+
+    #[test]
+    fn locates_the_rejected_settings() {
+        let text = "packageLints = \"yes\"\nstrictKeys = false\n\
+            [severity]\nunknown = \"loud\"\nlatset = \"hint\"\n\
+            [featureHints.serde]\nfeatures = [\"derive\"]\nreson = \"derive\"\n";
+        let parsed = parse(text).unwrap();
+        assert_eq!(
+            parsed.options,
+            json!({
+                "strictKeys": false,
+                "severity": {},
+                "featureHints": { "serde": { "features": ["derive"] } },
+            })
+        );
+        let located = parsed
+            .problems
+            .iter()
+            .map(|(problem, span)| (problem.key(), &text[span.clone()]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            located,
+            [
+                ("featureHints.serde.reson".to_string(), "reson"),
+                ("packageLints".to_string(), "packageLints"),
+                ("severity.latset".to_string(), "latset"),
+                ("severity.unknown".to_string(), "unknown"),
+            ]
+        );
+
+        let source = Source::new(text, Encoding::Utf8);
+        let diagnostics = diagnostics(&source, &Ok(parsed), &Config::default());
+        let messages = diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                (
+                    7,
+                    "Unknown setting `featureHints.serde.reson`, did you mean `featureHints.serde.reason`?"
+                ),
+                (0, "`packageLints` must be `true` or `false`, it is ignored"),
+                (4, "Unknown setting `severity.latset`, did you mean `severity.latest`?"),
+                (
+                    3,
+                    "`severity.unknown` must be one of `error`, `warning`, `information`, `hint`, it is ignored"
+                ),
+            ]
+        );
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::WARNING)));
+
+        // Dotted keys are located at the written part
+        let text = "severity.unknwon = \"hint\"\n";
+        let (_, span) = &parse(text).unwrap().problems[0];
+        assert_eq!(&text[span.clone()], "unknwon");
+    }
+
+    #[test]
+    fn client_settings_take_precedence() {
+        let mut options = parse(
+            "packageLints = true\nperDependencyLens = true\n\
+            [severity]\nunknown = \"warning\"\nlatest = \"information\"\n",
+        )
+        .unwrap()
+        .options;
+        merge(
+            &mut options,
+            json!({ "perDependencyLens": false, "severity": { "latest": "error" }, "packageLints": null }),
+        );
+        assert_eq!(
+            options,
+            json!({
+                "packageLints": true,
+                "perDependencyLens": false,
+                "severity": { "unknown": "warning", "latest": "error" },
+            })
+        );
+        let config: Config = serde_json::from_value(options).unwrap();
+        assert!(config.package_lints);
+        assert!(!config.per_dependency_lens);
+        assert_eq!(config.severity.unknown, crate::config::Severity::Warning);
+        assert_eq!(config.severity.latest, crate::config::Severity::Error);
+        assert_eq!(config.severity.any, crate::config::Severity::Information);
+    }
+
+    #[test]
+    fn reports_at_the_top() {
+        let diagnostic = invalid("expected a value", &Config::default());
+        assert_eq!(diagnostic.range, Default::default());
+        assert_eq!(diagnostic.source.as_deref(), Some("crates-io.package"));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostic.message,
+            "Invalid configuration, it is ignored: expected a value"
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use semver::VersionReq;
+use tower_lsp_server::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, Location, NumberOrString, Uri,
+};
+
+use crate::config::Config;
+use crate::manifest::{DependencyKind, SpannedManifest};
+use crate::messages::{Key, Message};
+use crate::text::Source;
+use crate::version::{self, Releases};
+
+/// A declaration of a crate in one of the sections resolved together.
+struct Occurrence<'a> {
+    /// Like `dev-dependencies` or `target.'cfg(unix)'.dependencies`
+    section: String,
+    req: &'a str,
+    span: Range<usize>,
+}
+
+/// Check the requirements of crates declared in several sections of a manifest.
+///
+/// Cargo resolves the normal, dev, and build dependencies of a package together.
+/// Requirements that no published version satisfies together are flagged,
+/// with `heldBackRequirements` also those holding back the newest version the others allow.
+pub fn lint(
+    manifest: &SpannedManifest,
+    source: &Source,
+    uri: &Uri,
+    versions: &HashMap<String, Releases>,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let dependencies = manifest
+        .dependencies()
+        .filter(|(kind, _, _)| *kind != DependencyKind::Workspace)
+        .map(|(kind, name, dep)| (kind.section().to_string(), name, dep))
+        .chain(
+            manifest
+                .target_dependencies()
+                .map(|(platform, kind, name, dep)| (kind.target_section(platform), name, dep)),
+        );
+    let mut groups = BTreeMap::<&str, Vec<Occurrence>>::new();
+    for (section, name, dep) in dependencies {
+        let registry = !dep.inherited()
+            && dep
+                .detail()
+                .is_none_or(|d| d.path.is_none() && d.git.is_none());
+        let Some(span) = dep.req_span().filter(|_| registry) else {
+            continue;
+        };
+        groups
+            .entry(dep.package().unwrap_or(name.get_ref()))
+            .or_default()
+            .push(Occurrence {
+                section,
+                req: dep.req(),
+                span,
+            });
+    }
+
+    let mut diagnostics = Vec::new();
+    for (package, occurrences) in groups {
+        if occurrences.len() < 2 {
+            continue;
+        }
+        let Some(versions) = versions.get(package) else {
+            continue;
+        };
+        let reqs = occurrences
+            .iter()
+            .map(|o| VersionReq::parse(o.req).ok())
+            .collect::<Option<Vec<_>>>();
+        let Some(reqs) = reqs else {
+            continue;
+        };
+        let versions = version::available(versions).collect::<Vec<_>>();
+        // Newest version each requirement accepts on its own
+        let newest = reqs
+            .iter()
+            .map(|req| versions.iter().filter(|v| req.matches(v)).max())
+            .collect::<Option<Vec<_>>>();
+        let Some(newest) = newest else {
+            continue; // Unknown versions are reported elsewhere
+        };
+        let together = versions
+            .iter()
+            .filter(|v| reqs.iter().all(|req| req.matches(v)))
+            .max();
+
+        for (i, occurrence) in occurrences.iter().enumerate() {
+            let (key, code, severity, version) = match together {
+                None if config.conflicting_requirements => (
+                    Key::ConflictingRequirements,
+                    "conflicting-requirements",
+                    config.severity.conflicting_requirements,
+                    newest[i],
+                ),
+                Some(together) if config.held_back_requirements => {
+                    // The newest version allowed without this requirement
+                    let others = versions
+                        .iter()
+                        .filter(|v| {
+                            reqs.iter()
+                                .enumerate()
+                                .all(|(j, req)| i == j || req.matches(v))
+                        })
+                        .max();
+                    if others.is_none_or(|o| o <= together) {
+                        continue;
+                    }
+                    (
+                        Key::HeldBackRequirement,
+                        "held-back-requirement",
+                        config.severity.held_back_requirement,
+                        together,
+                    )
+                }
+                _ => continue,
+            };
+            let Some(range) = source.string_range(occurrence.span.clone()) else {
+                continue;
+            };
+            let related = occurrences
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .filter_map(|(j, other)| {
+                    Some(DiagnosticRelatedInformation {
+                        location: Location {
+                            uri: uri.clone(),
+                            range: source.string_range(other.span.clone())?,
+                        },
+                        message: format!(
+                            "[{}] {package} = \"{}\" accepts {}",
+                            other.section, other.req, newest[j]
+                        ),
+                    })
+                })
+                .collect();
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(severity.into()),
+                code: Some(NumberOrString::String(code.into())),
+                source: Some(config.source(code)),
+                message: Message::new(key)
+                    .arg(format!("[{}]", occurrence.section))
+                    .arg(format!("`{package}`"))
+                    .arg(version)
+                    .render(config.language),
+                related_information: Some(related),
+                ..Default::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest;
+    use crate::text::Encoding;
+    use crate::version::Release;
+
+    const VERSIONS: &[&str] = &["1.0.100", "1.0.150", "1.0.215", "2.0.0"];
+
+    fn check(text: &str, config: &Config) -> Vec<Diagnostic> {
+        let (manifest, None) = manifest::parse(text) else {
+            panic!("{text}");
+        };
+        let uri = "file:///app/Cargo.toml".parse().unwrap();
+        let versions = HashMap::from([(
+            "serde".to_string(),
+            VERSIONS
+                .iter()
+                .filter_map(|v| Release::parse(v, false))
+                .collect(),
+        )]);
+        let source = Source::new(text, Encoding::Utf8);
+        lint(&manifest, &source, &uri, &versions, config)
+    }
+
+    /// Line, code, and message of each diagnostic with the messages of the related information.
+    fn summary(diagnostics: &[Diagnostic]) -> Vec<(u32, String, String, Vec<String>)> {
+        diagnostics
+            .iter()
+            .map(|d| {
+                let Some(NumberOrString::String(code)) = &d.code else {
+                    panic!("{d:?}");
+                };
+                let related = d.related_information.iter().flatten();
+                let related = related.map(|r| r.message.clone()).collect();
+                (d.range.start.line, code.clone(), d.message.clone(), related)
+            })
+            .collect()
+    }
+
+    fn held_back() -> Config {
+        Config {
+            held_back_requirements: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn two_conflicting_requirements() {
+        let text = "[dependencies]\nserde = \"2\"\n\n[dev-dependencies]\nserde = \"=1.0.100\"\n";
+        assert_eq!(
+            summary(&check(text, &Config::default())),
+            [
+                (
+                    1,
+                    "conflicting-requirements".into(),
+                    "No version of `serde` satisfies its requirements in all sections, \
+                    the one in [dependencies] alone accepts 2.0.0"
+                        .into(),
+                    vec!["[dev-dependencies] serde = \"=1.0.100\" accepts 1.0.100".into()]
+                ),
+                (
+                    4,
+                    "conflicting-requirements".into(),
+                    "No version of `serde` satisfies its requirements in all sections, \
+                    the one in [dev-dependencies] alone accepts 1.0.100"
+                        .into(),
+                    vec!["[dependencies] serde = \"2\" accepts 2.0.0".into()]
+                ),
+            ]
+        );
+        let config = Config {
+            conflicting_requirements: false,
+            ..held_back()
+        };
+        assert!(check(text, &config).is_empty());
+    }
+
+    #[test]
+    fn three_requirements_with_a_target() {
+        let text = "[dependencies]\nserde = \"1.0\"\n\n\
+            [build-dependencies]\nserde = { version = \"2\" }\n\n\
+            [target.'cfg(unix)'.dev-dependencies]\nserde = \"~1.0.150\"\n";
+        let diagnostics = check(text, &Config::default());
+        let lines = summary(&diagnostics)
+            .into_iter()
+            .map(|(line, _, _, related)| (line, related.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(1, 2), (4, 2), (7, 2)]);
+        assert!(diagnostics[2]
+            .message
+            .contains("the one in [target.'cfg(unix)'.dev-dependencies] alone accepts 1.0.215"));
+        assert_eq!(diagnostics[2].range.start.character, 9);
+
+        // Compatible requirements are silent
+        let text = text.replace("\"2\"", "\"1.0.100\"");
+        assert!(check(&text, &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn requirements_holding_others_back() {
+        let text = "[dependencies]\nserde = \"1.0\"\n\n\
+            [dev-dependencies]\nserde = \"=1.0.150\"\n\n\
+            [target.'cfg(unix)'.build-dependencies]\nserde = \"1\"\n";
+        assert!(check(text, &Config::default()).is_empty());
+        assert_eq!(
+            summary(&check(text, &held_back())),
+            [(
+                4,
+                "held-back-requirement".into(),
+                "The requirement in [dev-dependencies] holds `serde` back to 1.0.150".into(),
+                vec![
+                    "[dependencies] serde = \"1.0\" accepts 1.0.215".into(),
+                    "[target.'cfg(unix)'.build-dependencies] serde = \"1\" accepts 1.0.215".into()
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn skips_single_and_local_declarations() {
+        let text = "[dependencies]\nserde = \"2\"\n\n\
+            [dev-dependencies]\nserde = { path = \"../serde\" }\n\n\
+            [build-dependencies]\nserde = { workspace = true }\n\n\
+            [workspace.dependencies]\nserde = \"=1.0.100\"\n";
+        assert!(check(text, &held_back()).is_empty());
+        // Unknown crates and requirements no version matches
+        let text = "[dependencies]\nrand = \"2\"\nserde = \"3\"\n\n\
+            [dev-dependencies]\nrand = \"1\"\nserde = \"1\"\n";
+        assert!(check(text, &held_back()).is_empty());
+    }
+}
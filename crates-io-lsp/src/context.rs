@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tower_lsp_server::lsp_types::Uri;
+use tower_lsp_server::UriExt;
+
+use crate::config::ScanScope;
+use crate::manifest::{Inheritable, SpannedManifest};
+
+/// Command listing the workspace contexts as [`ContextSummary`]s.
+pub const CONTEXTS: &str = "crates-io.contexts";
+
+/// The workspace roots of the project with the manifests they contain.
+///
+/// Each manifest belongs to exactly one context, the one of its nearest workspace root,
+/// or of its own package if no workspace contains it.
+/// A context is created with its first manifest and dropped with its last one.
+#[derive(Debug, Default)]
+pub struct Contexts {
+    /// Root directory of the context of each manifest
+    roots: HashMap<Uri, PathBuf>,
+    /// Manifests of each context by root directory
+    manifests: BTreeMap<PathBuf, HashSet<Uri>>,
+}
+
+impl Contexts {
+    /// Move a manifest to the context at `root`, creating it if needed.
+    pub fn attach(&mut self, uri: &Uri, root: &Path) {
+        if self.roots.get(uri).is_some_and(|r| r == root) {
+            return;
+        }
+        self.detach(uri);
+        self.roots.insert(uri.clone(), root.to_path_buf());
+        self.manifests
+            .entry(root.to_path_buf())
+            .or_default()
+            .insert(uri.clone());
+    }
+
+    /// Remove a manifest from its context, dropping the context if it was the last one.
+    pub fn detach(&mut self, uri: &Uri) {
+        let Some(root) = self.roots.remove(uri) else {
+            return;
+        };
+        if let Some(manifests) = self.manifests.get_mut(&root) {
+            manifests.remove(uri);
+            if manifests.is_empty() {
+                self.manifests.remove(&root);
+            }
+        }
+    }
+
+    /// Remove the manifests at or below a deleted or renamed file or directory.
+    pub fn detach_below(&mut self, uri: &Uri) {
+        let dir = format!("{}/", uri.as_str().trim_end_matches('/'));
+        let below = self
+            .roots
+            .keys()
+            .filter(|u| *u == uri || u.as_str().starts_with(&dir))
+            .cloned()
+            .collect::<Vec<_>>();
+        for uri in below {
+            self.detach(&uri);
+        }
+    }
+
+    /// Root directory of the context of a manifest.
+    pub fn root(&self, uri: &Uri) -> Option<&Path> {
+        self.roots.get(uri).map(PathBuf::as_path)
+    }
+
+    /// The manifests of the context at `root`, none if there is no such context.
+    pub fn manifests(&self, root: &Path) -> impl Iterator<Item = &Uri> {
+        self.manifests.get(root).into_iter().flatten()
+    }
+
+    /// Root directories of all contexts, ordered by path.
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        self.manifests.keys().map(PathBuf::as_path)
+    }
+
+    /// The context selected by a manifest or any file or directory within a context,
+    /// the one of the nearest root containing it.
+    pub fn select(&self, selector: &Uri) -> Option<&Path> {
+        if let Some(root) = self.root(selector) {
+            return Some(root);
+        }
+        let path = selector.to_file_path()?;
+        self.roots()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+    }
+}
+
+/// Root directory of the context of the manifest at `path`, given its workspace root manifest.
+pub fn root_dir(path: &Path, workspace_root: Option<&Path>) -> Option<PathBuf> {
+    workspace_root
+        .unwrap_or(path)
+        .parent()
+        .map(Path::to_path_buf)
+}
+
+/// A context as listed by [`CONTEXTS`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextSummary {
+    /// The root directory, which selects the context in the workspace commands
+    pub root: Uri,
+    /// Whether the root declares a `[workspace]`, otherwise it is a single package
+    pub workspace: bool,
+    /// Whether the active document belongs to the context
+    pub active: bool,
+    /// Members read in the background with the settings of the root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_scope: Option<ScanScope>,
+    /// The `rust-version` of the root package or `[workspace.package]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rust_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lockfile: Option<Uri>,
+    /// Open and indexed manifests, ordered by URI
+    pub manifests: Vec<Uri>,
+}
+
+/// The `rust-version` of a root manifest, preferring the one of its workspace.
+pub fn rust_version(root: &SpannedManifest) -> Option<String> {
+    let workspace = root
+        .workspace
+        .as_ref()
+        .and_then(|ws| ws.package.as_ref())
+        .and_then(|package| {
+            package
+                .iter()
+                .find(|(key, _)| key.get_ref() == "rust-version")
+        })
+        .and_then(|(_, value)| value.as_str().map(str::to_string));
+    let package = root
+        .package
+        .as_ref()
+        .and_then(|package| package.get_ref().rust_version.as_ref())
+        .and_then(|version| match version.get_ref() {
+            Inheritable::Value(version) => Some(version.clone()),
+            Inheritable::Workspace { .. } => None,
+        });
+    workspace.or(package)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest;
+
+    fn uri(path: &str) -> Uri {
+        Uri::from_file_path(path).unwrap()
+    }
+
+    fn manifests(contexts: &Contexts, root: &str) -> Vec<String> {
+        let mut manifests = contexts
+            .manifests(Path::new(root))
+            .map(|u| u.to_string())
+            .collect::<Vec<_>>();
+        manifests.sort();
+        manifests
+    }
+
+    #[test]
+    fn maps_each_manifest_to_one_context() {
+        let mut contexts = Contexts::default();
+        contexts.attach(&uri("/repo/tools/Cargo.toml"), Path::new("/repo"));
+        contexts.attach(&uri("/repo/Cargo.toml"), Path::new("/repo"));
+        contexts.attach(
+            &uri("/repo/firmware/Cargo.toml"),
+            Path::new("/repo/firmware"),
+        );
+        contexts.attach(
+            &uri("/repo/firmware/hal/Cargo.toml"),
+            Path::new("/repo/firmware"),
+        );
+        assert_eq!(
+            manifests(&contexts, "/repo"),
+            ["file:///repo/Cargo.toml", "file:///repo/tools/Cargo.toml"]
+        );
+        assert_eq!(manifests(&contexts, "/repo/firmware").len(), 2);
+
+        // Moving a manifest leaves the other context
+        contexts.attach(&uri("/repo/tools/Cargo.toml"), Path::new("/repo/tools"));
+        assert_eq!(manifests(&contexts, "/repo"), ["file:///repo/Cargo.toml"]);
+        assert_eq!(
+            contexts.root(&uri("/repo/tools/Cargo.toml")),
+            Some(Path::new("/repo/tools"))
+        );
+    }
+
+    #[test]
+    fn selects_the_nearest_root() {
+        let mut contexts = Contexts::default();
+        contexts.attach(&uri("/repo/Cargo.toml"), Path::new("/repo"));
+        contexts.attach(
+            &uri("/repo/firmware/Cargo.toml"),
+            Path::new("/repo/firmware"),
+        );
+        let select = |path: &str| contexts.select(&uri(path)).map(Path::to_path_buf);
+        assert_eq!(select("/repo/firmware"), Some("/repo/firmware".into()));
+        assert_eq!(
+            select("/repo/firmware/hal/src/lib.rs"),
+            Some("/repo/firmware".into())
+        );
+        assert_eq!(select("/repo/tools/Cargo.toml"), Some("/repo".into()));
+        assert_eq!(select("/elsewhere/Cargo.toml"), None);
+        assert_eq!(
+            root_dir(
+                Path::new("/repo/tools/Cargo.toml"),
+                Some(Path::new("/repo/Cargo.toml"))
+            ),
+            Some("/repo".into())
+        );
+        assert_eq!(
+            root_dir(Path::new("/single/Cargo.toml"), None),
+            Some("/single".into())
+        );
+    }
+
+    #[test]
+    fn drops_contexts_without_manifests() {
+        let mut contexts = Contexts::default();
+        contexts.attach(&uri("/repo/a/Cargo.toml"), Path::new("/repo"));
+        contexts.attach(&uri("/repo/b/Cargo.toml"), Path::new("/repo"));
+        contexts.attach(&uri("/other/Cargo.toml"), Path::new("/other"));
+        contexts.detach(&uri("/repo/a/Cargo.toml"));
+        assert_eq!(contexts.roots().count(), 2);
+        contexts.detach_below(&uri("/repo"));
+        assert_eq!(contexts.roots().collect::<Vec<_>>(), [Path::new("/other")]);
+        assert_eq!(contexts.root(&uri("/repo/b/Cargo.toml")), None);
+    }
+
+    #[test]
+    fn prefers_the_workspace_rust_version() {
+        let (root, _) = manifest::parse(
+            "[package]\nname = \"app\"\nrust-version = { workspace = true }\n\n\
+            [workspace.package]\nrust-version = \"1.70\"\n",
+        );
+        assert_eq!(rust_version(&root).as_deref(), Some("1.70"));
+        let (single, _) = manifest::parse("[package]\nname = \"app\"\nrust-version = \"1.80\"\n");
+        assert_eq!(rust_version(&single).as_deref(), Some("1.80"));
+        let (none, _) = manifest::parse("[package]\nname = \"app\"\n");
+        assert_eq!(rust_version(&none), None);
+    }
+}
@@ -0,0 +1,625 @@
+//! Dependencies of `[dependencies]` that only the tests, examples, and benchmarks import.
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString, TextEdit};
+
+use crate::config::Config;
+use crate::manifest::{self, sections, table_headers, Dependency, SpannedManifest};
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// Diagnostic code of [`lint`], the data contains the edits moving the entry.
+pub const DEV_ONLY_DEPENDENCY: &str = "dev-only-dependency";
+
+/// Rust files read at most by a scan, the sources of the library and binaries go first.
+const MAX_FILES: usize = 2000;
+/// Larger files are skipped, like generated code.
+const MAX_FILE_BYTES: u64 = 1 << 20;
+
+/// Dependencies of `[dependencies]` imported by the targets of a package, by manifest key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Imported by the library or a binary
+    pub src: HashSet<String>,
+    /// Imported by a test, example, or benchmark
+    pub dev: HashSet<String>,
+    /// The library and binaries were not read completely, nothing can be flagged
+    pub truncated: bool,
+}
+
+/// Targets of the manifest that may live outside the standard layout.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Targets {
+    lib: Option<Target>,
+    bin: Vec<Target>,
+    test: Vec<Target>,
+    example: Vec<Target>,
+    bench: Vec<Target>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Target {
+    name: Option<String>,
+    path: Option<String>,
+}
+
+/// Find the imports of the dependencies in the sources of the package in `dir`.
+///
+/// Reads `src` and the `[lib]` and `[[bin]]` paths, then `tests`, `examples`, `benches`,
+/// and the paths of `[[test]]`, `[[example]]`, and `[[bench]]`.
+/// Build directories, hidden directories, and nested packages are skipped.
+/// Blocks on the file system, at most [`MAX_FILES`] files are read.
+pub fn scan(dir: &Path, text: &str) -> Usage {
+    let (manifest, _) = manifest::parse(text);
+    let libs = lib_names(dir, &manifest);
+    if libs.is_empty() {
+        return Usage::default();
+    }
+    let targets = toml::from_str::<Targets>(text).unwrap_or_default();
+    let paths = |targets: &[Target]| {
+        targets
+            .iter()
+            .filter_map(|t| t.path.as_deref().map(|p| dir.join(p)))
+            .collect::<Vec<_>>()
+    };
+    let mut dev_roots = ["tests", "examples", "benches"]
+        .map(|d| dir.join(d))
+        .to_vec();
+    let explicit = [&targets.test, &targets.example, &targets.bench]
+        .into_iter()
+        .flat_map(|t| paths(t))
+        .collect::<Vec<_>>();
+    dev_roots.extend(explicit.iter().map(|path| target_root(dir, path)));
+    let mut src_roots = vec![dir.join("src")];
+    src_roots.extend(
+        targets
+            .lib
+            .iter()
+            .filter_map(|t| t.path.as_ref())
+            .map(|p| dir.join(p)),
+    );
+    src_roots.extend(
+        paths(&targets.bin)
+            .iter()
+            .map(|path| target_root(dir, path)),
+    );
+
+    let mut walker = Walker {
+        budget: MAX_FILES,
+        skip: explicit.into_iter().collect(),
+    };
+    let mut src = Vec::new();
+    // Only the sources decide whether anything is flagged
+    let truncated = !src_roots.iter().all(|root| walker.collect(root, &mut src));
+    // The explicit dev targets are only hidden from the sources
+    walker.skip.clear();
+    let mut dev = Vec::new();
+    for root in &dev_roots {
+        if !walker.collect(root, &mut dev) {
+            break;
+        }
+    }
+    Usage {
+        src: imports(&src, &libs),
+        dev: imports(&dev, &libs),
+        truncated,
+    }
+}
+
+/// Keys of the dependencies imported by the `files`.
+fn imports(files: &[PathBuf], libs: &[(String, String)]) -> HashSet<String> {
+    let mut used = HashSet::new();
+    for file in files {
+        let Ok(source) = std::fs::read_to_string(file) else {
+            continue;
+        };
+        let roots = path_roots(&source);
+        used.extend(
+            libs.iter()
+                .filter(|(_, lib)| roots.contains(lib.as_str()))
+                .map(|(key, _)| key.clone()),
+        );
+    }
+    used
+}
+
+/// Directory of the modules of a target at `path`, like `tests/it` for `tests/it/main.rs`.
+///
+/// Targets beside the manifest in `dir` are read on their own.
+fn target_root(dir: &Path, path: &Path) -> PathBuf {
+    let module_root = path
+        .file_name()
+        .is_some_and(|name| name == "main.rs" || name == "lib.rs" || name == "mod.rs");
+    match path.parent() {
+        Some(parent) if module_root && parent != dir => parent.to_path_buf(),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Manifest keys of the dependencies that could move, with the name the code imports them by.
+///
+/// Optional dependencies are features and cannot be dev-dependencies.
+/// Renamed dependencies are imported by their key, path dependencies by their `lib.name`.
+fn lib_names(dir: &Path, manifest: &SpannedManifest) -> Vec<(String, String)> {
+    manifest
+        .dependencies
+        .iter()
+        .filter(|(_, dep)| {
+            dep.detail()
+                .and_then(|d| d.optional.as_ref())
+                .is_none_or(|o| !o.get_ref())
+        })
+        .map(|(name, dep)| {
+            let key = name.get_ref();
+            let lib = match dep.detail().and_then(|d| d.path.as_ref()) {
+                Some(path) if dep.package().is_none() => path_lib_name(&dir.join(path)),
+                _ => None,
+            };
+            let lib = lib.unwrap_or_else(|| key.clone()).replace('-', "_");
+            (key.clone(), lib)
+        })
+        .collect()
+}
+
+/// `[lib] name` of the package in `dir`, if it sets one.
+fn path_lib_name(dir: &Path) -> Option<String> {
+    let text = std::fs::read_to_string(dir.join("Cargo.toml")).ok()?;
+    toml::from_str::<Targets>(&text).ok()?.lib?.name
+}
+
+/// Collects the Rust files below the roots within the file budget.
+struct Walker {
+    budget: usize,
+    /// Files that belong to other targets
+    skip: HashSet<PathBuf>,
+}
+
+impl Walker {
+    /// Add the Rust files of `path` to `files`, returns `false` if the budget ran out.
+    fn collect(&mut self, path: &Path, files: &mut Vec<PathBuf>) -> bool {
+        // Links are not followed, they might form cycles
+        let Ok(metadata) = std::fs::symlink_metadata(path) else {
+            return true; // Targets of the standard layout are optional
+        };
+        if metadata.is_symlink() {
+            return true;
+        }
+        if metadata.is_file() {
+            if path.extension().is_none_or(|e| e != "rs")
+                || metadata.len() > MAX_FILE_BYTES
+                || self.skip.contains(path)
+                || files.iter().any(|f| f == path)
+            {
+                return true;
+            }
+            if self.budget == 0 {
+                return false;
+            }
+            self.budget -= 1;
+            files.push(path.to_path_buf());
+            return true;
+        }
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+        // Build directories are tagged by cargo
+        let build = path.join("CACHEDIR.TAG").exists();
+        if hidden || build || path.join("Cargo.toml").exists() {
+            return true;
+        }
+        let Ok(entries) = std::fs::read_dir(path) else {
+            return true;
+        };
+        let mut entries = entries.flatten().map(|e| e.path()).collect::<Vec<_>>();
+        entries.sort();
+        entries.iter().all(|entry| self.collect(entry, files))
+    }
+}
+
+/// First segments of the paths of a Rust source, like `serde` of `use serde::Serialize`,
+/// `serde_json::json!`, or `extern crate log`.
+///
+/// A token scan that skips comments and strings, so imports generated by macros are missed.
+fn path_roots(source: &str) -> HashSet<&str> {
+    let mut roots = HashSet::new();
+    // The last two tokens, identifiers or punctuation
+    let mut prev = ["", ""];
+    let push = |prev: &mut [&'static str; 2], token| *prev = [prev[1], token];
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("//") {
+            rest = rest.find('\n').map_or("", |i| &rest[i..]);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.find("*/").map_or("", |i| &comment[i + 2..]);
+        } else if let Some(string) = rest.strip_prefix('"') {
+            let mut escaped = false;
+            let end = string.find(|c| {
+                let end = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                end
+            });
+            rest = end.map_or("", |i| &string[i + 1..]);
+            push(&mut prev, "\"");
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let ident = &rest[..len];
+            rest = &rest[len..];
+            // `::serde`, but not `crate::serde`, `Self::serde`, or `Vec::<T>::serde`
+            let qualified = prev[1] == "::" && (prev[0] == "ident" || prev[0] == ">");
+            let path = rest.trim_start().starts_with("::")
+                && !qualified
+                && prev[1] != "."
+                && ident != "use";
+            let import = prev[1] == "use" || prev == ["extern", "crate"];
+            if path || import {
+                roots.insert(ident);
+            }
+            let token = match ident {
+                "use" => "use",
+                "extern" => "extern",
+                "crate" if prev[1] == "extern" => "crate",
+                _ => "ident",
+            };
+            push(&mut prev, token);
+        } else if let Some(after) = rest.strip_prefix("::") {
+            rest = after;
+            push(&mut prev, "::");
+        } else {
+            rest = &rest[c.len_utf8()..];
+            if !c.is_whitespace() {
+                let token = match c {
+                    '>' => ">",
+                    '.' => ".",
+                    _ => "punct",
+                };
+                push(&mut prev, token);
+            }
+        }
+    }
+    roots
+}
+
+/// Hint at dependencies of `[dependencies]` that only the tests, examples, and benchmarks use.
+///
+/// Dependencies imported anywhere in the library or binaries are never flagged, neither are
+/// those not imported at all, their use might be generated by a macro.
+pub fn lint(
+    manifest: &SpannedManifest,
+    source: &Source,
+    usage: &Usage,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let text = source.text.as_str();
+    if usage.truncated {
+        return Vec::new();
+    }
+    let mut diagnostics = Vec::new();
+    for (name, dep) in &manifest.dependencies {
+        let key = name.get_ref();
+        if !usage.dev.contains(key) || usage.src.contains(key) {
+            continue;
+        }
+        let Some(range) = source.range(name.span()) else {
+            continue;
+        };
+        let in_dev = manifest.dev_dependencies.keys().any(|k| k.get_ref() == key);
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(config.severity.dev_only_dependency.into()),
+            code: Some(NumberOrString::String(DEV_ONLY_DEPENDENCY.into())),
+            source: Some(config.source(DEV_ONLY_DEPENDENCY)),
+            message: Message::new(Key::DevOnlyDependency)
+                .arg(format!("`{key}`"))
+                .render(config.language),
+            data: move_edits(
+                source,
+                key,
+                manifest::quoted_key(text, name.span()),
+                dep,
+                in_dev,
+            )
+            .and_then(|edits| serde_json::to_value(edits).ok()),
+            ..Default::default()
+        });
+    }
+    diagnostics
+}
+
+/// Edits moving a dependency to `[dev-dependencies]`, keeping its value and comment.
+///
+/// `[dependencies.name]` tables are renamed, entries move to the end of the section,
+/// which is appended to the manifest if there is none.
+/// If `in_dev` declares it there already, the entry is only removed.
+fn move_edits(
+    source: &Source,
+    key: &str,
+    key_span: Range<usize>,
+    dep: &Dependency,
+    in_dev: bool,
+) -> Option<Vec<TextEdit>> {
+    let text = source.text.as_str();
+    let sections = sections(text);
+    let headers = table_headers(text);
+    if let Some((_, header)) = headers
+        .iter()
+        .find(|(name, _)| *name == format!("dependencies.{key}"))
+    {
+        if in_dev {
+            let section = sections.iter().find(|s| s.contains(&header.start))?;
+            return Some(vec![TextEdit {
+                range: source.range(section.clone())?,
+                new_text: String::new(),
+            }]);
+        }
+        let at = header.start + text[header.clone()].find("dependencies")?;
+        return Some(vec![TextEdit {
+            range: source.range(at..at)?,
+            new_text: "dev-".into(),
+        }]);
+    }
+
+    // `name = value` on lines of its own
+    let line = text[..key_span.start].rfind('\n').map_or(0, |i| i + 1);
+    let next = text[dep.span.end..]
+        .find('\n')
+        .map_or(text.len(), |i| dep.span.end + i + 1);
+    if !text[line..key_span.start].trim().is_empty()
+        || text[key_span.end..dep.span.start].trim() != "="
+    {
+        return None;
+    }
+    let mut edits = vec![TextEdit {
+        range: source.range(line..next)?,
+        new_text: String::new(),
+    }];
+    if in_dev {
+        return Some(edits);
+    }
+    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let entry = text[line..next].trim_end_matches(['\r', '\n']);
+    let dev = headers
+        .iter()
+        .find(|(name, _)| name == "dev-dependencies")
+        .and_then(|(_, header)| sections.iter().find(|s| s.contains(&header.start)));
+    let insert = match dev {
+        Some(section) => {
+            let at = section.start + text[section.clone()].trim_end().len();
+            (at, format!("{eol}{entry}"))
+        }
+        None => {
+            // The entry was the last line, the line before keeps its break
+            let newline = if text.is_empty() || text.ends_with('\n') || next == text.len() {
+                ""
+            } else {
+                eol
+            };
+            (
+                text.len(),
+                format!("{newline}{eol}[dev-dependencies]{eol}{entry}{eol}"),
+            )
+        }
+    };
+    edits.push(TextEdit {
+        range: source.range(insert.0..insert.0)?,
+        new_text: insert.1,
+    });
+    Some(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::text::Encoding;
+
+    /// Package with dependencies used by every kind of target, removed on drop.
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str, manifest: &str, files: &[(&str, &str)]) -> Self {
+            let root = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            fs::write(root.join("Cargo.toml"), manifest).unwrap();
+            for (path, content) in files {
+                let path = root.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            }
+            Self(root)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const MANIFEST: &str = r#"[package]
+name = "app"
+
+[dependencies]
+serde = "1"
+tempfile = "3" # scratch files
+clap = { version = "4", features = ["derive"] }
+json = { package = "serde_json", version = "1" }
+criterion = "0.5"
+local = { path = "local" }
+log = { version = "0.4", optional = true }
+unused = "1"
+
+[[bench]]
+name = "speed"
+path = "perf/speed.rs"
+"#;
+
+    fn fixture(name: &str) -> Fixture {
+        Fixture::new(
+            name,
+            MANIFEST,
+            &[
+                (
+                    "src/lib.rs",
+                    "use serde::Serialize;\n// tempfile::tempdir()\nconst HELP: &str = \"clap::Parser\";\n",
+                ),
+                ("src/bin/tool.rs", "fn main() { app::run() }\n"),
+                (
+                    "tests/it/main.rs",
+                    "use tempfile::tempdir;\nextern crate json;\nuse local_lib::Helper;\nmod common;\n",
+                ),
+                ("tests/it/common.rs", "pub fn log() { log::info!(\"\") }\n"),
+                ("examples/demo.rs", "use clap::Parser;\nuse serde::Deserialize;\n"),
+                ("perf/speed.rs", "fn bench() { criterion::black_box(1); }\n"),
+                ("local/Cargo.toml", "[package]\nname = \"local\"\n\n[lib]\nname = \"local_lib\"\n"),
+                ("tests/target/CACHEDIR.TAG", ""),
+                ("tests/target/debug/gen.rs", "use unused::Gen;\n"),
+            ],
+        )
+    }
+
+    fn sorted(set: &HashSet<String>) -> Vec<&str> {
+        let mut names = set.iter().map(String::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn scans_the_targets_of_the_package() {
+        let fixture = fixture("crates-io-lsp-dev-only-scan");
+        let usage = scan(&fixture.0, MANIFEST);
+        assert!(!usage.truncated);
+        assert_eq!(sorted(&usage.src), ["serde"]);
+        assert_eq!(
+            sorted(&usage.dev),
+            ["clap", "criterion", "json", "local", "serde", "tempfile"]
+        );
+
+        let (manifest, None) = manifest::parse(MANIFEST) else {
+            panic!("{MANIFEST}");
+        };
+        let mut flagged = lint(
+            &manifest,
+            &Source::new(MANIFEST, Encoding::Utf8),
+            &usage,
+            &Config::default(),
+        )
+        .into_iter()
+        .map(|d| (d.range.start.line, d.message))
+        .collect::<Vec<_>>();
+        flagged.sort();
+        assert_eq!(
+            flagged[0],
+            (
+                5,
+                "`tempfile` is only used by tests, examples, or benchmarks, consider moving it to `[dev-dependencies]`".to_string()
+            )
+        );
+        let lines = flagged.iter().map(|(line, _)| *line).collect::<Vec<_>>();
+        assert_eq!(lines, [5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn skips_the_sources_of_explicit_dev_targets() {
+        let manifest = "[package]\nname = \"app\"\n\n[dependencies]\nproptest = \"1\"\n\n[[test]]\nname = \"props\"\npath = \"src/props.rs\"\n";
+        let fixture = Fixture::new(
+            "crates-io-lsp-dev-only-explicit",
+            manifest,
+            &[
+                ("src/lib.rs", "pub fn add() {}\n"),
+                ("src/props.rs", "use proptest::prelude::*;\n"),
+            ],
+        );
+        let usage = scan(&fixture.0, manifest);
+        assert_eq!(sorted(&usage.src), Vec::<&str>::new());
+        assert_eq!(sorted(&usage.dev), ["proptest"]);
+    }
+
+    #[test]
+    fn finds_path_roots() {
+        let source = r#"
+            use ::rand::Rng;
+            use std::{fmt, io};
+            #[macro_use]
+            extern crate log;
+            fn main() {
+                let v = serde_json::json!({});
+                let w = crate::regex::x();
+                let x = Vec::<u8>::anyhow::new();
+                let y = self.tokio::spawn;
+                let z = "itertools::Itertools";
+                /* once_cell::sync */
+            }
+        "#;
+        let mut roots = path_roots(source).into_iter().collect::<Vec<_>>();
+        roots.sort_unstable();
+        assert_eq!(roots, ["Vec", "crate", "log", "rand", "serde_json", "std"]);
+    }
+
+    fn moved(text: &str, key: &str) -> String {
+        let (manifest, None) = manifest::parse(text) else {
+            panic!("{text}");
+        };
+        let usage = Usage {
+            dev: HashSet::from([key.to_string()]),
+            ..Default::default()
+        };
+        let source = Source::new(text, Encoding::Utf8);
+        let diagnostics = lint(&manifest, &source, &usage, &Config::default());
+        let data = diagnostics[0].data.clone().unwrap();
+        let edits = serde_json::from_value::<Vec<TextEdit>>(data).unwrap();
+        crate::dry_run::apply(&source, &edits).unwrap()
+    }
+
+    #[test]
+    fn moves_entries_to_the_dev_dependencies() {
+        let text = "[dependencies]\nserde = \"1\"\nclap = { version = \"4\" } # cli\n\n[dev-dependencies]\nproptest = \"1\"\n\n[features]\n";
+        assert_eq!(
+            moved(text, "clap"),
+            "[dependencies]\nserde = \"1\"\n\n[dev-dependencies]\nproptest = \"1\"\nclap = { version = \"4\" } # cli\n\n[features]\n"
+        );
+        // Without the section it is appended
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nclap = \"4\"";
+        assert_eq!(
+            moved(text, "clap"),
+            "[package]\nname = \"app\"\n\n[dependencies]\n\n[dev-dependencies]\nclap = \"4\"\n"
+        );
+        // Tables are renamed
+        let text = "[dependencies.clap]\nversion = \"4\"\n";
+        assert_eq!(
+            moved(text, "clap"),
+            "[dev-dependencies.clap]\nversion = \"4\"\n"
+        );
+        // Already a dev-dependency
+        let text = "[dependencies]\nclap = \"4\"\n\n[dev-dependencies]\nclap = \"4\"\n";
+        assert_eq!(
+            moved(text, "clap"),
+            "[dependencies]\n\n[dev-dependencies]\nclap = \"4\"\n"
+        );
+    }
+
+    #[test]
+    fn flags_nothing_if_the_sources_were_truncated() {
+        let text = "[dependencies]\nclap = \"4\"\n";
+        let (manifest, None) = manifest::parse(text) else {
+            panic!("{text}");
+        };
+        let usage = Usage {
+            dev: HashSet::from(["clap".to_string()]),
+            truncated: true,
+            ..Default::default()
+        };
+        let source = Source::new(text, Encoding::Utf8);
+        assert_eq!(lint(&manifest, &source, &usage, &Config::default()), []);
+    }
+}
@@ -0,0 +1,208 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serde_json::{Map, Value};
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString};
+use tower_lsp_server::UriExt;
+
+use crate::analysis::AnalyzedDependency;
+use crate::messages::{self, Key, Language, Message};
+
+/// Sort the diagnostics by range and give each a stable `data.fingerprint`.
+///
+/// Fetches complete in any order, this makes repeated analyses of the same input identical.
+/// Diagnostics of `dependencies` are identified by crate, section, code, and requirement,
+/// all others by code and message, both also by source.
+/// Equal identities are numbered in document order.
+pub fn prepare(diagnostics: &mut [Diagnostic], dependencies: &[AnalyzedDependency]) {
+    diagnostics.sort_by(|a, b| {
+        let key = |d: &Diagnostic| (d.range.start, d.range.end);
+        key(a)
+            .cmp(&key(b))
+            .then_with(|| code(a).cmp(code(b)))
+            .then_with(|| a.source.cmp(&b.source))
+            .then_with(|| a.message.cmp(&b.message))
+    });
+
+    let mut seen = Vec::<u64>::new();
+    for diagnostic in diagnostics {
+        let mut hasher = DefaultHasher::new();
+        (code(diagnostic), &diagnostic.source).hash(&mut hasher);
+        match dependencies
+            .iter()
+            .find(|d| d.name_range == diagnostic.range)
+        {
+            Some(dep) => (&dep.package, dep.kind.section(), &dep.req).hash(&mut hasher),
+            None => diagnostic.message.hash(&mut hasher),
+        }
+        let identity = hasher.finish();
+        let occurrence = seen.iter().filter(|i| **i == identity).count();
+        seen.push(identity);
+
+        let mut hasher = DefaultHasher::new();
+        (identity, occurrence).hash(&mut hasher);
+        let fingerprint = format!("{:016x}", hasher.finish());
+        match &mut diagnostic.data {
+            Some(Value::Object(data)) => {
+                data.insert("fingerprint".into(), fingerprint.into());
+            }
+            // Other payloads are kept as they are
+            Some(_) => {}
+            None => {
+                let data = Map::from_iter([("fingerprint".to_string(), fingerprint.into())]);
+                diagnostic.data = Some(Value::Object(data));
+            }
+        }
+    }
+}
+
+/// Prefix the messages with their severity and topic for screen readers,
+/// and spell out where the related locations are.
+///
+/// Called after [`prepare`] so that the fingerprints do not depend on the mode.
+pub fn make_accessible(diagnostics: &mut [Diagnostic], language: Language) {
+    if !language.accessible {
+        return;
+    }
+    for diagnostic in diagnostics {
+        let prefix = messages::diagnostic_prefix(diagnostic.severity, code(diagnostic), language);
+        diagnostic.message.insert_str(0, &prefix);
+        for related in diagnostic.related_information.iter_mut().flatten() {
+            let uri = &related.location.uri;
+            let file = uri
+                .to_file_path()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| uri.as_str().to_string());
+            related.message = Message::new(Key::RelatedLocation)
+                .arg(&related.message)
+                .arg(related.location.range.start.line + 1)
+                .arg(file)
+                .render(language);
+        }
+    }
+}
+
+fn code(diagnostic: &Diagnostic) -> &str {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => code,
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types::{Position, Range};
+
+    use super::*;
+
+    fn diagnostic(line: u32, code: &str, message: &str) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 5)),
+            code: Some(NumberOrString::String(code.into())),
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    fn fingerprint(diagnostic: &Diagnostic) -> &str {
+        diagnostic.data.as_ref().unwrap()["fingerprint"]
+            .as_str()
+            .unwrap()
+    }
+
+    #[test]
+    fn sorts_by_range() {
+        let mut diagnostics = vec![
+            diagnostic(3, "latest", "b"),
+            diagnostic(1, "unknown", "a"),
+            diagnostic(3, "any", "c"),
+        ];
+        prepare(&mut diagnostics, &[]);
+        let order = diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, code(d)))
+            .collect::<Vec<_>>();
+        assert_eq!(order, [(1, "unknown"), (3, "any"), (3, "latest")]);
+    }
+
+    #[test]
+    fn fingerprints_are_stable() {
+        let diagnostics = || {
+            vec![
+                diagnostic(1, "unknown", "a"),
+                diagnostic(2, "unknown", "a"),
+                diagnostic(4, "latest", "a"),
+            ]
+        };
+        let mut first = diagnostics();
+        prepare(&mut first, &[]);
+        let mut second = diagnostics();
+        second.reverse();
+        prepare(&mut second, &[]);
+        assert_eq!(first, second);
+
+        // Equal identities are told apart by their order
+        assert_ne!(fingerprint(&first[0]), fingerprint(&first[1]));
+        assert_ne!(fingerprint(&first[0]), fingerprint(&first[2]));
+        assert!(fingerprint(&first[0]).len() == 16);
+
+        // Moving a diagnostic keeps its fingerprint
+        let mut moved = vec![diagnostic(7, "latest", "a")];
+        prepare(&mut moved, &[]);
+        assert_eq!(fingerprint(&moved[0]), fingerprint(&first[2]));
+
+        // A changed source is a different diagnostic
+        let mut sourced = diagnostics();
+        sourced[2].source = Some("crates-io".into());
+        prepare(&mut sourced, &[]);
+        assert_ne!(fingerprint(&sourced[2]), fingerprint(&first[2]));
+    }
+
+    #[test]
+    fn keeps_the_data() {
+        let mut object = diagnostic(0, "latest", "a");
+        object.data = Some(serde_json::json!({ "version": "1.0.0" }));
+        let mut other = diagnostic(1, "latest", "b");
+        other.data = Some(Value::from("text"));
+        let mut diagnostics = vec![object, other];
+        prepare(&mut diagnostics, &[]);
+        let data = diagnostics[0].data.as_ref().unwrap();
+        assert_eq!(data["version"], "1.0.0");
+        assert!(data["fingerprint"].is_string());
+        assert_eq!(diagnostics[1].data, Some(Value::from("text")));
+    }
+
+    #[test]
+    fn prefixes_accessible_messages() {
+        use tower_lsp_server::lsp_types::{
+            DiagnosticRelatedInformation, DiagnosticSeverity, Location, Uri,
+        };
+
+        use crate::messages::Locale;
+
+        let mut warning = diagnostic(2, "outdated-compatible", "Outdated");
+        warning.severity = Some(DiagnosticSeverity::WARNING);
+        let uri = "file:///repo/Cargo.toml".parse::<Uri>().unwrap();
+        warning.related_information = Some(vec![DiagnosticRelatedInformation {
+            location: Location::new(uri, Range::new(Position::new(9, 0), Position::new(9, 4))),
+            message: "workspace requirement".into(),
+        }]);
+        let mut diagnostics = vec![warning.clone()];
+        make_accessible(&mut diagnostics, Locale::En.into());
+        assert_eq!(diagnostics, [warning.clone()]);
+
+        let language = Language {
+            locale: Locale::En,
+            accessible: true,
+        };
+        make_accessible(&mut diagnostics, language);
+        assert_eq!(
+            diagnostics[0].message,
+            "Warning, outdated dependency: Outdated"
+        );
+        let related = &diagnostics[0].related_information.as_ref().unwrap()[0];
+        assert_eq!(
+            related.message,
+            "workspace requirement, at line 10 of /repo/Cargo.toml"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use tower_lsp_server::lsp_types::Uri;
+
+/// The open documents, each behind its own lock.
+///
+/// The map is only locked to look up, insert, or remove documents, so requests on different
+/// documents never wait for each other. Documents are only reached within synchronous
+/// closures, which makes it impossible to hold their locks across an `.await`: the guards of
+/// `std::sync` are not `Send`, so the futures of the handlers would not compile, and clippy's
+/// `await_holding_lock` rejects them everywhere else.
+///
+/// The closures must not access the documents again, the locks are not reentrant.
+#[derive(Debug)]
+pub struct Documents<T> {
+    docs: RwLock<HashMap<Uri, Arc<RwLock<T>>>>,
+}
+
+impl<T> Default for Documents<T> {
+    fn default() -> Self {
+        Self {
+            docs: Default::default(),
+        }
+    }
+}
+
+impl<T> Documents<T> {
+    /// Run `f` with the document `uri`, `None` if it is not open.
+    ///
+    /// Closing the document waits for `f`, but `f` may still run after a concurrent close
+    /// if it started before.
+    pub fn with<R>(&self, uri: &Uri, f: impl FnOnce(&T) -> R) -> Option<R> {
+        let doc = self.get(uri)?;
+        let doc = read(&doc);
+        Some(f(&doc))
+    }
+
+    /// Run `f` with the document `uri` to change it, `None` if it is not open.
+    pub fn with_mut<R>(&self, uri: &Uri, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let doc = self.get(uri)?;
+        let mut doc = write(&doc);
+        Some(f(&mut doc))
+    }
+
+    fn get(&self, uri: &Uri) -> Option<Arc<RwLock<T>>> {
+        read(&self.docs).get(uri).cloned()
+    }
+
+    /// The results of `f` for the open documents, in no particular order.
+    ///
+    /// Documents opened or closed in the meantime may be missed or visited.
+    pub fn filter_map<R>(&self, mut f: impl FnMut(&Uri, &T) -> Option<R>) -> Vec<R> {
+        let docs = read(&self.docs)
+            .iter()
+            .map(|(uri, doc)| (uri.clone(), doc.clone()))
+            .collect::<Vec<_>>();
+        docs.iter()
+            .filter_map(|(uri, doc)| f(uri, &read(doc)))
+            .collect()
+    }
+
+    pub fn contains(&self, uri: &Uri) -> bool {
+        read(&self.docs).contains_key(uri)
+    }
+
+    pub fn uris(&self) -> Vec<Uri> {
+        read(&self.docs).keys().cloned().collect()
+    }
+
+    /// Open `doc` as `uri`, replacing the open one.
+    #[cfg(test)]
+    pub fn insert(&self, uri: Uri, doc: T) {
+        self.insert_with(uri, doc, || ());
+    }
+
+    /// Like [`Self::insert`], running `then` before any other document is opened or closed.
+    ///
+    /// `then` must not access the documents, they are locked until it returns.
+    pub fn insert_with<R>(&self, uri: Uri, doc: T, then: impl FnOnce() -> R) -> R {
+        let mut docs = write(&self.docs);
+        docs.insert(uri, Arc::new(RwLock::new(doc)));
+        then()
+    }
+
+    /// Open `doc` as `uri` unless it is open, returns whether it was inserted.
+    pub fn insert_new(&self, uri: Uri, doc: T) -> bool {
+        let mut docs = write(&self.docs);
+        if docs.contains_key(&uri) {
+            return false;
+        }
+        docs.insert(uri, Arc::new(RwLock::new(doc)));
+        true
+    }
+
+    /// Close `uri`, running `then` before any other document is opened or closed,
+    /// and after the running closures of the document returned.
+    ///
+    /// Returns the closed document, `None` if it was not open, and the result of `then`.
+    /// Like for [`Self::insert_with`], `then` must not access the documents.
+    pub fn remove_with<R>(&self, uri: &Uri, then: impl FnOnce() -> R) -> (Option<T>, R)
+    where
+        T: Clone,
+    {
+        let mut docs = write(&self.docs);
+        let doc = docs.remove(uri).map(|doc| {
+            // Closures that looked it up just before may still lock it later
+            let doc = write(&doc);
+            doc.clone()
+        });
+        (doc, then())
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        read(&self.docs).len()
+    }
+
+    #[cfg(test)]
+    pub fn clear(&self) {
+        write(&self.docs).clear();
+    }
+}
+
+// A panic within a closure leaves the document as it was, the others may still use it
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn uri(name: &str) -> Uri {
+        format!("file:///{name}/Cargo.toml").parse().unwrap()
+    }
+
+    #[test]
+    fn opens_changes_and_closes() {
+        let docs = Documents::default();
+        let (a, b) = (uri("a"), uri("b"));
+        assert_eq!(docs.with(&a, |doc: &String| doc.clone()), None);
+        docs.insert(a.clone(), "a".to_string());
+        assert!(!docs.insert_new(a.clone(), "other".into()));
+        assert!(docs.insert_new(b.clone(), "b".into()));
+        assert_eq!(docs.with_mut(&a, |doc| doc.push('1')), Some(()));
+        assert_eq!(docs.with(&a, |doc| doc.clone()).unwrap(), "a1");
+        let mut all = docs.filter_map(|uri, doc| Some(format!("{}={doc}", uri.as_str())));
+        all.sort();
+        assert_eq!(all, ["file:///a/Cargo.toml=a1", "file:///b/Cargo.toml=b"]);
+
+        let (closed, then) = docs.remove_with(&a, || "then");
+        assert_eq!((closed.as_deref(), then), (Some("a1"), "then"));
+        assert_eq!(docs.remove_with(&a, || ()).0, None);
+        assert_eq!(docs.uris(), std::slice::from_ref(&b));
+        assert!(docs.contains(&b) && !docs.contains(&a));
+    }
+
+    #[test]
+    fn documents_do_not_wait_for_each_other() {
+        let docs = Documents::default();
+        let (a, b) = (uri("a"), uri("b"));
+        docs.insert(a.clone(), 0);
+        docs.insert(b.clone(), 0);
+        let inside = Barrier::new(2);
+        let changed = Barrier::new(2);
+        thread::scope(|scope| {
+            // A long hover of b
+            scope.spawn(|| {
+                docs.with(&b, |doc| {
+                    inside.wait();
+                    changed.wait();
+                    assert_eq!(*doc, 0);
+                });
+            });
+            // Changes a and reads b meanwhile, a deadlock would block the barrier forever
+            inside.wait();
+            docs.with_mut(&a, |doc| *doc += 1);
+            assert_eq!(docs.with(&b, |doc| *doc), Some(0));
+            changed.wait();
+        });
+        assert_eq!(docs.with(&a, |doc| *doc), Some(1));
+    }
+
+    #[test]
+    fn concurrent_changes_stay_isolated() {
+        let docs = Documents::default();
+        let uris = (0..8).map(|i| uri(&i.to_string())).collect::<Vec<_>>();
+        for uri in &uris {
+            docs.insert(uri.clone(), Vec::new());
+        }
+        thread::scope(|scope| {
+            for (i, uri) in uris.iter().enumerate() {
+                let docs = &docs;
+                // A writer per document
+                scope.spawn(move || {
+                    for n in 0..500 {
+                        docs.with_mut(uri, |doc: &mut Vec<usize>| doc.push(i * 1000 + n));
+                    }
+                });
+                // Readers of all documents, and opening and closing of others
+                scope.spawn(move || {
+                    for _ in 0..200 {
+                        let lens = docs.filter_map(|_, doc| Some(doc.len()));
+                        assert!(lens.iter().all(|len| *len <= 500));
+                        docs.with(uri, |doc| assert!(doc.windows(2).all(|w| w[0] < w[1])));
+                        let temporary = self::uri(&format!("tmp{i}"));
+                        docs.insert(temporary.clone(), Vec::new());
+                        docs.remove_with(&temporary, || ());
+                        thread::sleep(Duration::from_micros(10));
+                    }
+                });
+            }
+        });
+        for (i, uri) in uris.iter().enumerate() {
+            let expected = (0..500).map(|n| i * 1000 + n).collect::<Vec<_>>();
+            assert_eq!(docs.with(uri, |doc| doc.clone()), Some(expected));
+        }
+        assert_eq!(docs.len(), uris.len());
+    }
+}
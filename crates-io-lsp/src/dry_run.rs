@@ -0,0 +1,672 @@
+//! Dry runs of the edits of the server on a copy of the manifest.
+//!
+//! A mistake in the span arithmetic of an action must not corrupt a manifest, so its edits
+//! are applied to the text first, the result is parsed strictly, and its dependencies are
+//! compared with the ones before, see [`check`].
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use tower_lsp_server::lsp_types::{CodeAction, CodeActionDisabled, TextEdit, Uri};
+
+use crate::manifest::{self, table_headers, DependencyKind};
+use crate::text::{string_content, Source};
+
+/// What the edits of an action may change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expect {
+    /// The edits replace requirements, which become the edited strings
+    Requirement,
+    /// The edited dependencies are inherited from the workspace (`workspace = true`)
+    Inherited,
+    /// Only the edited dependencies change, they may also move or disappear.
+    /// Edits within requirements are checked like [`Expect::Requirement`].
+    Edited,
+    /// The manifest means the same afterwards, only its layout changes, like when sorting
+    Unchanged,
+    /// The edited manifest parses, for renames that may move whole sections
+    Parses,
+    /// Dependencies are added, the others stay the same
+    Added,
+}
+
+/// A failed dry run, with the affected lines before and after the edits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub reason: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl Violation {
+    /// Message for the log, with only the affected lines of the manifest.
+    pub fn report(&self, uri: &Uri) -> String {
+        format!(
+            "Rejected an edit of {}: {}\nBefore:\n{}\nAfter:\n{}",
+            uri.as_str(),
+            self.reason,
+            self.before,
+            self.after
+        )
+    }
+}
+
+/// Disable `action` if its edits of `source` fail the dry run, instead of returning them.
+///
+/// The action stays in the list, so that its reason is shown and the bug is reported.
+pub fn guard(
+    action: &mut CodeAction,
+    uri: &Uri,
+    source: &Source,
+    expect: Expect,
+) -> Option<Violation> {
+    let edits = action.edit.as_ref()?.changes.as_ref()?.get(uri)?;
+    let violation = check(source, edits, expect).err()?;
+    action.edit = None;
+    action.data = None;
+    action.disabled = Some(CodeActionDisabled {
+        reason: "The edit failed its dry run, see the log of the server".into(),
+    });
+    Some(violation)
+}
+
+/// The text of `source` with the `edits` applied, an error if they are out of range or overlap.
+#[cfg(test)]
+pub fn apply(source: &Source, edits: &[TextEdit]) -> Result<String, String> {
+    Ok(splice(&source.text, &spans(source, edits)?))
+}
+
+/// Apply the `edits` to a copy of the text of `source` and verify that they did what is
+/// expected.
+///
+/// The edited manifest must parse strictly and keep every dependency the edits do not touch.
+/// Manifests that are no valid TOML before the edits are not checked, there is nothing to
+/// compare with.
+pub fn check(source: &Source, edits: &[TextEdit], expect: Expect) -> Result<(), Violation> {
+    let text = source.text.as_str();
+    let spans = spans(source, edits).map_err(|reason| Violation {
+        reason,
+        before: String::new(),
+        after: String::new(),
+    })?;
+    let after = splice(text, &spans);
+    let violation = |reason: String| {
+        let mut moved = 0isize;
+        let edited = spans.iter().map(|(span, new_text)| {
+            let start = span.start.saturating_add_signed(moved);
+            moved += new_text.len() as isize - span.len() as isize;
+            start..start + new_text.len()
+        });
+        Violation {
+            reason,
+            before: lines(text, spans.iter().map(|(span, _)| span.clone())),
+            after: lines(&after, edited.collect::<Vec<_>>().into_iter()),
+        }
+    };
+
+    let Ok(before_table) = text.parse::<toml::Table>() else {
+        return Ok(());
+    };
+    let after_table = after.parse::<toml::Table>().map_err(|e| {
+        violation(format!(
+            "The edited manifest does not parse: {}",
+            e.message()
+        ))
+    })?;
+    let before = dependencies(&before_table);
+    let after = dependencies(&after_table);
+    match expect {
+        Expect::Parses => return Ok(()),
+        Expect::Added => {
+            let changed = before
+                .iter()
+                .find(|(key, value)| after.get(*key) != Some(*value));
+            if let Some(((section, name), _)) = changed {
+                return Err(violation(format!(
+                    "The dependency `{name}` of `[{section}]` changed"
+                )));
+            }
+            if after.len() <= before.len() {
+                return Err(violation("No dependency was added".into()));
+            }
+            return Ok(());
+        }
+        Expect::Unchanged if before_table == after_table => return Ok(()),
+        Expect::Unchanged => {
+            let changed = before
+                .iter()
+                .find(|(key, value)| after.get(*key) != Some(*value))
+                .map(|(key, _)| key)
+                .or_else(|| after.keys().find(|key| !before.contains_key(*key)));
+            return Err(violation(match changed {
+                Some((section, name)) => {
+                    format!("The dependency `{name}` of `[{section}]` changed")
+                }
+                None => "The manifest changed outside of the dependencies".into(),
+            }));
+        }
+        _ => {}
+    }
+
+    let targets = targets(text, &spans);
+    let in_requirement = |span: &Range<usize>| {
+        targets.iter().any(|t| {
+            t.requirement
+                .as_ref()
+                .is_some_and(|r| r.start <= span.start && span.end <= r.end)
+        })
+    };
+    let requirements = spans.iter().all(|(span, _)| in_requirement(span));
+    if expect == Expect::Requirement && !requirements {
+        return Err(violation("An edit is outside of the requirements".into()));
+    }
+    if requirements {
+        for target in &targets {
+            let Some(range) = &target.requirement else {
+                continue;
+            };
+            let inside = spans
+                .iter()
+                .filter(|(span, _)| range.start <= span.start && span.end <= range.end)
+                .map(|(span, new_text)| {
+                    (span.start - range.start..span.end - range.start, *new_text)
+                })
+                .collect::<Vec<_>>();
+            if inside.is_empty() {
+                continue;
+            }
+            let expected = splice(&text[range.clone()], &inside);
+            let found = after.get(&target.key).and_then(requirement);
+            if found != Some(expected.as_str()) {
+                let (section, name) = &target.key;
+                return Err(violation(format!(
+                    "The requirement of `{name}` in `[{section}]` is {found:?} instead of {expected:?}"
+                )));
+            }
+        }
+    }
+    if expect == Expect::Inherited {
+        for target in &targets {
+            let inherited = after
+                .get(&target.key)
+                .and_then(|value| value.get("workspace"))
+                .and_then(toml::Value::as_bool);
+            if inherited != Some(true) {
+                let (section, name) = &target.key;
+                return Err(violation(format!(
+                    "`{name}` in `[{section}]` is not inherited from the workspace"
+                )));
+            }
+        }
+    }
+
+    let targeted = |key: &(String, String)| targets.iter().any(|t| t.key == *key);
+    for (key, value) in &before {
+        if !targeted(key) && after.get(key) != Some(value) {
+            let (section, name) = key;
+            return Err(violation(format!(
+                "The dependency `{name}` of `[{section}]` changed"
+            )));
+        }
+    }
+    // Edited dependencies may move to another section
+    for key in after.keys().filter(|key| !before.contains_key(*key)) {
+        if !targets.iter().any(|t| t.key.1 == key.1) {
+            let (section, name) = key;
+            return Err(violation(format!(
+                "The dependency `{name}` was added to `[{section}]`"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Byte spans of the `edits` in `source` with their new text, sorted by their start.
+///
+/// Like LSP requires, all ranges refer to the original text and must not overlap,
+/// insertions at the same position keep their order.
+fn spans<'a>(
+    source: &Source,
+    edits: &'a [TextEdit],
+) -> Result<Vec<(Range<usize>, &'a str)>, String> {
+    let mut spans = edits
+        .iter()
+        .map(|edit| {
+            source
+                .lines
+                .range_to_span(edit.range)
+                .map(|span| (span, edit.new_text.as_str()))
+                .ok_or_else(|| format!("The range {:?} is not in the document", edit.range))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    spans.sort_by_key(|(span, _)| span.start);
+    for pair in spans.windows(2) {
+        if pair[0].0.end > pair[1].0.start {
+            return Err(format!(
+                "The edits of the bytes {:?} and {:?} overlap",
+                pair[0].0, pair[1].0
+            ));
+        }
+    }
+    Ok(spans)
+}
+
+/// `text` with the sorted and disjoint `spans` replaced.
+fn splice(text: &str, spans: &[(Range<usize>, &str)]) -> String {
+    let mut spliced = String::with_capacity(text.len());
+    let mut last = 0;
+    for (span, new_text) in spans {
+        spliced.push_str(&text[last..span.start]);
+        spliced.push_str(new_text);
+        last = span.end;
+    }
+    spliced.push_str(&text[last..]);
+    spliced
+}
+
+/// The lines of `text` containing the sorted `spans`, the others are left out.
+fn lines(text: &str, spans: impl Iterator<Item = Range<usize>>) -> String {
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for span in spans {
+        // Replaced lines end at the start of the next one
+        let end = match text[..span.end].strip_suffix('\n') {
+            Some(line) if span.start < span.end => line.len(),
+            _ => span.end,
+        };
+        let start = text[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+        match merged.last_mut() {
+            Some(last) if start <= last.end + 1 => last.end = last.end.max(end),
+            _ => merged.push(start..end),
+        }
+    }
+    let lines = merged.into_iter().map(|range| &text[range]);
+    lines.collect::<Vec<_>>().join("\n…\n")
+}
+
+/// A dependency touched by an edit.
+struct Target {
+    /// Section and name
+    key: (String, String),
+    /// Span of its requirement without the quotes
+    requirement: Option<Range<usize>>,
+}
+
+/// The dependencies that the edits at `spans` touch.
+fn targets(text: &str, spans: &[(Range<usize>, &str)]) -> Vec<Target> {
+    let (manifest, _) = manifest::parse(text);
+    let headers = table_headers(text);
+    let dependencies = manifest
+        .dependencies()
+        .map(|(kind, name, dep)| (kind.section().to_string(), name, dep))
+        .chain(
+            manifest
+                .target_dependencies()
+                .map(|(platform, kind, name, dep)| (kind.target_section(platform), name, dep)),
+        );
+    let mut targets = Vec::new();
+    for (section, name, dep) in dependencies {
+        let key = manifest::quoted_key(text, name.span());
+        let line_start = text[..key.start].rfind('\n').map_or(0, |i| i + 1);
+        // `[dependencies.name]` tables reach to the next header
+        let extent = if text[line_start..key.start].trim_start().starts_with('[') {
+            let end = headers
+                .iter()
+                .map(|(_, header)| header.start)
+                .find(|start| *start > key.start)
+                .unwrap_or(text.len());
+            key.start..end
+        } else {
+            key.start.min(dep.span.start)..key.end.max(dep.span.end)
+        };
+        let touched = spans.iter().any(|(span, _)| {
+            if span.is_empty() {
+                extent.start <= span.start && span.start <= extent.end
+            } else {
+                span.start < extent.end && extent.start < span.end
+            }
+        });
+        if touched {
+            targets.push(Target {
+                key: (section, name.get_ref().clone()),
+                requirement: dep.req_span().and_then(|span| string_content(text, span)),
+            });
+        }
+    }
+    targets
+}
+
+/// Parsed values of the dependencies by section and name.
+fn dependencies(manifest: &toml::Table) -> BTreeMap<(String, String), toml::Value> {
+    let mut dependencies = BTreeMap::new();
+    let mut add = |section: String, table: Option<&toml::Value>| {
+        let entries = table.and_then(toml::Value::as_table).into_iter().flatten();
+        for (name, value) in entries {
+            dependencies.insert((section.clone(), name.clone()), value.clone());
+        }
+    };
+    let kinds = [
+        DependencyKind::Normal,
+        DependencyKind::Dev,
+        DependencyKind::Build,
+    ];
+    for kind in kinds {
+        add(kind.section().into(), manifest.get(kind.section()));
+    }
+    let workspace = manifest
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"));
+    add(DependencyKind::Workspace.section().into(), workspace);
+    let targets = manifest.get("target").and_then(toml::Value::as_table);
+    for (platform, target) in targets.into_iter().flatten() {
+        for kind in kinds {
+            add(kind.target_section(platform), target.get(kind.section()));
+        }
+    }
+    dependencies
+}
+
+/// Requirement of a parsed dependency, `*` if it has none.
+fn requirement(value: &toml::Value) -> Option<&str> {
+    match value {
+        toml::Value::String(req) => Some(req),
+        toml::Value::Table(table) => Some(table.get("version").map_or(Some("*"), |v| v.as_str())?),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use semver::Version;
+    use tower_lsp_server::lsp_types::{Position, Range};
+
+    use super::*;
+    use crate::config::{Config, RequirementStyle};
+    use crate::manifest::SpannedManifest;
+    use crate::text::Encoding;
+    use crate::version::{self, Release};
+    use crate::{sort, tidy, workspace};
+
+    /// Manifests the rewriting actions are run on.
+    const FIXTURES: [&str; 4] = [
+        "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n\
+         [dependencies]\nserde = \"1.0.100\"\nlog = { version = \"0.4\", features = [\"std\"] }\n\
+         rand = { version = \"0.8.5\", optional = true } # rng\n\n\
+         [dev-dependencies]\ntokio = { version = \"1\", features = [\"macros\", \"rt\"] }\n",
+        "[dependencies.serde]\nversion = \"1\"\nfeatures = [\"derive\"]\n\n\
+         [target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n\
+         \"my.dep\" = { version = \"2.1\", package = \"dep\" }\n\n\
+         [build-dependencies]\ncc = '1.0'\n",
+        "[workspace]\nmembers = [\"a\"]\n\n[workspace.dependencies]\n\
+         anyhow = \"1.0.80\"\nthiserror = { version = \"2\" }\n\n\
+         [dependencies]\nanyhow = { workspace = true }\nthiserror = \"2.0.1\"\nitoa = \"1\"\n",
+        "# Ünïcode — comment\r\n[dependencies]\r\n# Formats\r\nserde_json = \"1.0\" # ✓\r\n\
+         zeta = \"0.1\"\r\nalpha = { version = \"~0.3.1\", default-features = false }\r\n",
+    ];
+
+    /// Positions in UTF-16, which the non-ASCII fixture tells apart from bytes.
+    fn source(text: &str) -> Source {
+        Source::new(text, Encoding::Utf16)
+    }
+
+    fn check(text: &str, edits: &[TextEdit], expect: Expect) -> Result<(), Violation> {
+        super::check(&source(text), edits, expect)
+    }
+
+    fn apply(text: &str, edits: &[TextEdit]) -> Result<String, String> {
+        super::apply(&source(text), edits)
+    }
+
+    fn edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+            new_text: new_text.into(),
+        }
+    }
+
+    /// The registry dependencies of a manifest.
+    fn registry_dependencies(manifest: &SpannedManifest) -> Vec<&manifest::Dependency> {
+        let all = manifest.dependencies().map(|(_, _, dep)| dep);
+        let targets = manifest.target_dependencies().map(|(_, _, _, dep)| dep);
+        all.chain(targets).filter(|dep| !dep.inherited()).collect()
+    }
+
+    /// Edit replacing the requirement of `dep` like the update actions.
+    fn requirement_edit(text: &str, dep: &manifest::Dependency, req: String) -> TextEdit {
+        TextEdit {
+            range: source(text).string_range(dep.req_span().unwrap()).unwrap(),
+            new_text: req,
+        }
+    }
+
+    #[test]
+    fn applies_edits_like_lsp() {
+        let text = "a = 1\nb = 2\nc = 3\n";
+        // In any order, insertions at the same position in the order given
+        let edits = [
+            edit((2, 4), (2, 5), "30"),
+            edit((0, 0), (0, 0), "# x\n"),
+            edit((0, 0), (0, 0), "# y\n"),
+            edit((1, 0), (2, 0), ""),
+        ];
+        assert_eq!(apply(text, &edits).unwrap(), "# x\n# y\na = 1\nc = 30\n");
+        // Characters beyond the end of a line are clamped
+        assert_eq!(
+            apply(text, &[edit((0, 5), (0, 99), " # one")]).unwrap(),
+            "a = 1 # one\nb = 2\nc = 3\n"
+        );
+        let overlapping = [edit((0, 0), (1, 1), ""), edit((1, 0), (1, 0), "x")];
+        assert!(apply(text, &overlapping).is_err());
+        assert!(apply(text, &[edit((9, 0), (9, 0), "x")]).is_err());
+    }
+
+    #[test]
+    fn checks_requirement_edits() {
+        let text = "[dependencies]\nserde = \"1.0\"\nlog = { version = \"0.4\" }\n";
+        assert_eq!(
+            check(
+                text,
+                &[edit((1, 9), (1, 12), "1.0.200")],
+                Expect::Requirement
+            ),
+            Ok(())
+        );
+        // Off by one the quote is lost
+        let violation = check(
+            text,
+            &[edit((1, 8), (1, 12), "1.0.200")],
+            Expect::Requirement,
+        )
+        .unwrap_err();
+        assert!(violation
+            .reason
+            .starts_with("The edited manifest does not parse"));
+        assert_eq!(violation.before, "serde = \"1.0\"");
+        assert_eq!(violation.after, "serde = 1.0.200\"");
+        // Or the edit hits the other dependency
+        let violation =
+            check(text, &[edit((2, 0), (2, 3), "0.4.22")], Expect::Requirement).unwrap_err();
+        assert_eq!(violation.reason, "An edit is outside of the requirements");
+        assert_eq!(violation.before, "log = { version = \"0.4\" }");
+        assert_eq!(violation.after, "0.4.22 = { version = \"0.4\" }");
+    }
+
+    #[test]
+    fn only_the_edited_dependencies_change() {
+        let text = "[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n\n[dev-dependencies]\n";
+        // Moving a dependency to another section
+        let moved = [
+            edit((2, 0), (3, 0), ""),
+            edit((5, 0), (5, 0), "log = \"0.4\"\n"),
+        ];
+        assert_eq!(check(text, &moved, Expect::Edited), Ok(()));
+        let violation = check(
+            text,
+            &[edit((1, 8), (2, 11), "{ version = \"1\" }\nlog = \"0.5\"")],
+            Expect::Edited,
+        );
+        assert_eq!(violation, Ok(()), "both are edited");
+        let added = [edit((5, 0), (5, 0), "rand = \"0.8\"\n")];
+        let violation = check(text, &added, Expect::Edited).unwrap_err();
+        assert_eq!(
+            violation.reason,
+            "The dependency `rand` was added to `[dev-dependencies]`"
+        );
+        assert_eq!(violation.after, "rand = \"0.8\"");
+        assert_eq!(check(text, &added, Expect::Added), Ok(()));
+        let replaced = [edit((2, 0), (3, 0), "rand = \"0.8\"\n")];
+        let violation = check(text, &replaced, Expect::Added).unwrap_err();
+        assert_eq!(
+            violation.reason,
+            "The dependency `log` of `[dependencies]` changed"
+        );
+        let comment = [edit((5, 0), (5, 0), "# rand = \"0.8\"\n")];
+        let violation = check(text, &comment, Expect::Added).unwrap_err();
+        assert_eq!(violation.reason, "No dependency was added");
+
+        // Requirements are checked within any edit
+        let swapped = check(text, &[edit((1, 9), (1, 10), "2")], Expect::Edited);
+        assert_eq!(swapped, Ok(()));
+        let inherited = [edit((2, 6), (2, 11), "{ workspace = true }")];
+        assert_eq!(check(text, &inherited, Expect::Inherited), Ok(()));
+        let violation = check(text, &[edit((2, 6), (2, 11), "{}")], Expect::Inherited).unwrap_err();
+        assert_eq!(
+            violation.reason,
+            "`log` in `[dependencies]` is not inherited from the workspace"
+        );
+    }
+
+    #[test]
+    fn layout_changes_keep_the_manifest() {
+        let text = "[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n";
+        let sorted = [edit((1, 0), (3, 0), "log = \"0.4\"\nserde = \"1\"\n")];
+        assert_eq!(check(text, &sorted, Expect::Unchanged), Ok(()));
+        let lost = [edit((1, 0), (3, 0), "log = \"0.4\"\nserde = \"1.0\"\n")];
+        let violation = check(text, &lost, Expect::Unchanged).unwrap_err();
+        assert_eq!(
+            violation.reason,
+            "The dependency `serde` of `[dependencies]` changed"
+        );
+        // A renamed section may move its dependencies
+        let text = "[dependancies]\nserde = \"1\"\n";
+        let renamed = [edit((0, 1), (0, 13), "dependencies")];
+        assert_eq!(check(text, &renamed, Expect::Parses), Ok(()));
+        assert!(check(text, &renamed, Expect::Edited).is_err());
+        // Nothing to compare with
+        let broken = "[dependencies\nserde = \"1\"\n";
+        assert_eq!(
+            check(broken, &[edit((1, 0), (1, 5), "")], Expect::Unchanged),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn fixtures_parse() {
+        for text in FIXTURES {
+            let (manifest, err) = manifest::parse(text);
+            assert!(err.is_none(), "{text}");
+            assert!(!registry_dependencies(&manifest).is_empty());
+        }
+    }
+
+    fn style() -> impl Strategy<Value = RequirementStyle> {
+        prop_oneof![
+            Just(RequirementStyle::Full),
+            Just(RequirementStyle::Minor),
+            Just(RequirementStyle::Major),
+            Just(RequirementStyle::Preserve),
+        ]
+    }
+
+    fn versions() -> impl Strategy<Value = Vec<Release>> {
+        proptest::collection::vec((0u64..3, 0u64..12, 0u64..12), 1..8).prop_map(|versions| {
+            versions
+                .into_iter()
+                .filter_map(|(major, minor, patch)| {
+                    Release::parse(&format!("{major}.{minor}.{patch}"), false)
+                })
+                .collect()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn updates_only_change_their_requirement(
+            fixture in 0..FIXTURES.len(),
+            version in (0u64..3, 0u64..30, 0u64..30),
+            style in style(),
+        ) {
+            let text = FIXTURES[fixture];
+            let (manifest, _) = manifest::parse(text);
+            let version = Version::new(version.0, version.1, version.2);
+            for dep in registry_dependencies(&manifest) {
+                let req = version::requirement(&version, style, dep.req());
+                let edit = requirement_edit(text, dep, req);
+                prop_assert_eq!(check(text, &[edit], Expect::Requirement), Ok(()));
+            }
+        }
+
+        #[test]
+        fn pins_and_relaxes_only_change_their_requirement(
+            fixture in 0..FIXTURES.len(),
+            versions in versions(),
+            style in style(),
+        ) {
+            let text = FIXTURES[fixture];
+            let (manifest, _) = manifest::parse(text);
+            for dep in registry_dependencies(&manifest) {
+                let reqs = version::pin(dep.req(), &versions)
+                    .into_iter()
+                    .chain(version::relax(dep.req(), style));
+                for req in reqs {
+                    let edit = requirement_edit(text, dep, req);
+                    prop_assert_eq!(check(text, &[edit], Expect::Requirement), Ok(()));
+                }
+            }
+        }
+
+        #[test]
+        fn formatting_and_sorting_keep_the_manifest(
+            fixture in 0..FIXTURES.len(),
+            width in 10usize..120,
+            allow_table_conversion in any::<bool>(),
+            sort_within_groups in any::<bool>(),
+            sort_tables in any::<bool>(),
+        ) {
+            let text = FIXTURES[fixture];
+            let config = Config {
+                inline_table_width: width,
+                allow_table_conversion,
+                sort_within_groups,
+                sort_tables,
+                ..Default::default()
+            };
+            let formatted = tidy::edits(&source(text), 0..text.len(), &config);
+            prop_assert_eq!(check(text, &formatted, Expect::Unchanged), Ok(()));
+            let sorted = sort::edits(&source(text), &config).unwrap_or_default();
+            prop_assert_eq!(check(text, &sorted, Expect::Unchanged), Ok(()));
+        }
+    }
+
+    #[test]
+    fn inheriting_only_changes_the_dependency() {
+        for text in FIXTURES {
+            let (manifest, _) = manifest::parse(text);
+            let members = manifest
+                .dependencies()
+                .filter(|(kind, _, _)| *kind != DependencyKind::Workspace);
+            for (_, name, dep) in members.filter(|(_, _, dep)| !dep.inherited()) {
+                let Some(edit) = workspace::inherit_edit(dep, &source(text)) else {
+                    continue;
+                };
+                assert_eq!(
+                    check(text, &[edit], Expect::Inherited),
+                    Ok(()),
+                    "{} of {text}",
+                    name.get_ref()
+                );
+            }
+        }
+    }
+}
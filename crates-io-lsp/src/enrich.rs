@@ -0,0 +1,163 @@
+//! Metadata of the dependencies fetched in the background for `staleCrates`,
+//! one crate after another as the rate limit of the crates.io API allows.
+//!
+//! The documents keep a note on the dependencies until their metadata landed,
+//! the status tells the client how long that takes.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::notification::Notification;
+use tower_lsp_server::lsp_types::Uri;
+
+use crate::metadata::REQUESTS_PER_CRATE;
+use crate::rate_limit::Budget;
+
+/// Command returning the [`Status`] of the background work.
+pub const STATUS: &str = "crates-io.status";
+
+/// Sent with the [`Status`] whenever the pending work changes.
+pub enum StatusNotification {}
+
+impl Notification for StatusNotification {
+    type Params = Status;
+    const METHOD: &'static str = "crates-io/status";
+}
+
+/// Background work of the open documents.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    /// Documents with pending work, by URI
+    pub documents: Vec<DocumentStatus>,
+    /// Requests waiting for the rate limit
+    pub queued: usize,
+    /// Milliseconds between two requests the rate limit allows
+    pub refill_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentStatus {
+    pub uri: Uri,
+    /// Dependencies whose metadata is still fetched
+    pub awaiting_metadata: usize,
+    /// Estimated milliseconds until the rate limit allows fetching them
+    pub wait_ms: u64,
+}
+
+/// Crates awaiting their metadata by document.
+#[derive(Debug, Default)]
+pub struct Pending(HashMap<Uri, BTreeSet<String>>);
+
+impl Pending {
+    /// Replace the pending crates of a document, returns whether they changed.
+    pub fn start(&mut self, uri: &Uri, crates: BTreeSet<String>) -> bool {
+        if crates.is_empty() {
+            return self.remove(uri);
+        }
+        self.0.insert(uri.clone(), crates.clone()) != Some(crates)
+    }
+
+    /// The metadata of a crate landed, or failed, for all documents.
+    ///
+    /// Returns whether any document was waiting for it.
+    pub fn landed(&mut self, name: &str) -> bool {
+        let mut changed = false;
+        for crates in self.0.values_mut() {
+            changed |= crates.remove(name);
+        }
+        self.0.retain(|_, crates| !crates.is_empty());
+        changed
+    }
+
+    /// Forget a closed document, returns whether it had pending crates.
+    pub fn remove(&mut self, uri: &Uri) -> bool {
+        self.0.remove(uri).is_some()
+    }
+
+    /// Forget all documents, once the enrichment is disabled.
+    pub fn clear(&mut self) -> bool {
+        let changed = !self.0.is_empty();
+        self.0.clear();
+        changed
+    }
+
+    pub fn contains(&self, uri: &Uri, name: &str) -> bool {
+        self.0.get(uri).is_some_and(|crates| crates.contains(name))
+    }
+
+    /// The status with the estimates of the rate limit `budget`.
+    pub fn status(&self, budget: Budget) -> Status {
+        let mut documents = self
+            .0
+            .iter()
+            .map(|(uri, crates)| DocumentStatus {
+                uri: uri.clone(),
+                awaiting_metadata: crates.len(),
+                wait_ms: budget.wait(crates.len() * REQUESTS_PER_CRATE).as_millis() as u64,
+            })
+            .collect::<Vec<_>>();
+        documents.sort_by(|a, b| a.uri.as_str().cmp(b.uri.as_str()));
+        Status {
+            documents,
+            queued: budget.queued,
+            refill_ms: budget.interval.as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn crates(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn tracks_the_pending_crates() {
+        let (a, b): (Uri, Uri) = (
+            "file:///a/Cargo.toml".parse().unwrap(),
+            "file:///b/Cargo.toml".parse().unwrap(),
+        );
+        let mut pending = Pending::default();
+        assert!(pending.start(&a, crates(&["serde", "tokio"])));
+        assert!(!pending.start(&a, crates(&["serde", "tokio"])));
+        assert!(pending.start(&b, crates(&["serde"])));
+        assert!(pending.contains(&a, "tokio") && !pending.contains(&b, "tokio"));
+
+        let budget = Budget {
+            tokens: 1,
+            queued: 1,
+            next: Duration::from_millis(400),
+            interval: Duration::from_secs(1),
+        };
+        let status = pending.status(budget);
+        let waits = status
+            .documents
+            .iter()
+            .map(|d| (d.uri.as_str(), d.awaiting_metadata, d.wait_ms))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            waits,
+            [
+                ("file:///a/Cargo.toml", 2, 2400),
+                ("file:///b/Cargo.toml", 1, 400)
+            ]
+        );
+        assert_eq!((status.queued, status.refill_ms), (1, 1000));
+
+        // Landed metadata is shared by the documents
+        assert!(pending.landed("serde"));
+        assert!(!pending.landed("serde"));
+        assert!(pending.contains(&a, "tokio") && !pending.contains(&a, "serde"));
+        // Documents without pending crates are dropped
+        assert_eq!(pending.status(budget).documents.len(), 1);
+        assert!(pending.remove(&a));
+        assert!(!pending.clear());
+        assert_eq!(pending.status(budget).documents, []);
+    }
+}
@@ -0,0 +1,486 @@
+//! Dependency events of the open documents for `emitEvents`, sent to tools like dashboards
+//! that follow the dependencies without reading the diagnostics.
+//!
+//! Each published analysis is observed as the [`Status`] of every declared crate.
+//! A change of a crate is only sent once it held for [`DEBOUNCE`], so that typing
+//! or the partial results before a deadline send nothing, and failing fetches only
+//! count once they failed for [`PERSISTENT_FAILURE`].
+//! The first analysis of a document is its baseline, which [`SNAPSHOT`] replays.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tower_lsp_server::lsp_types::notification::Notification;
+use tower_lsp_server::lsp_types::Uri;
+
+use crate::analysis::Analysis;
+
+/// Command returning the events that rebuild the current state of every document,
+/// without arguments.
+pub const SNAPSHOT: &str = "crates-io.events.snapshot";
+
+/// Time a change of a crate has to hold before it is sent.
+pub const DEBOUNCE: Duration = Duration::from_secs(1);
+/// Time after which failing fetches of a crate are sent.
+pub const PERSISTENT_FAILURE: Duration = Duration::from_secs(60);
+
+/// Sent with each [`Event`].
+pub enum EventNotification {}
+
+impl Notification for EventNotification {
+    type Params = Event;
+    const METHOD: &'static str = "crates-io/event";
+}
+
+/// A meaningful change of a crate declared by a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    pub uri: Uri,
+    #[serde(rename = "crate")]
+    pub package: String,
+    pub kind: EventKind,
+    /// Requirement or newest version before the change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    /// Requirement or newest version after the change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+    /// ISO 8601 time the change was sent
+    pub timestamp: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EventKind {
+    /// Declared, `new` is the requirement
+    Added,
+    /// No longer declared, `old` is the requirement
+    Removed,
+    RequirementChanged,
+    /// A version outside the requirement is the newest, `new`, or a newer one than `old`
+    Outdated,
+    /// The requirement allows the newest version again, `old` was the newest before
+    UpToDate,
+    FetchFailing,
+    FetchRecovered,
+}
+
+/// What the analyses know of a crate declared by a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Requirements of its declarations, separated by commas
+    pub req: String,
+    /// Newest version if it is outdated
+    pub outdated: Option<String>,
+    pub failing: bool,
+}
+
+/// The status of the crates of `analysis`, along with the ones that are still fetched.
+pub fn statuses(analysis: &Analysis) -> (BTreeMap<String, Status>, &BTreeSet<String>) {
+    let statuses = analysis
+        .requirements
+        .iter()
+        .map(|(package, reqs)| {
+            let mut unique = Vec::new();
+            for req in reqs.iter().map(String::as_str) {
+                if !unique.contains(&req) {
+                    unique.push(req);
+                }
+            }
+            let outdated = analysis
+                .dependencies
+                .iter()
+                .filter(|d| d.package == *package)
+                .find_map(|d| d.target())
+                .map(ToString::to_string);
+            let status = Status {
+                req: unique.join(", "),
+                outdated,
+                failing: analysis.failed.contains(package),
+            };
+            (package.clone(), status)
+        })
+        .collect();
+    (statuses, &analysis.pending)
+}
+
+/// The sent and the observed state of the documents.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    docs: HashMap<Uri, Document>,
+}
+
+#[derive(Debug, Default)]
+struct Document {
+    sent: BTreeMap<String, Status>,
+    /// Also the crates that are no longer declared, until that is sent
+    observed: BTreeMap<String, Observed>,
+    /// Time of the flush that is waited for
+    scheduled: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Observed {
+    /// `None` if the crate is no longer declared
+    status: Option<Status>,
+    /// When the status last changed
+    changed: Instant,
+    /// When the fetches started failing
+    failing_since: Option<Instant>,
+}
+
+impl Observed {
+    /// The status to send at `now`, failing only after [`PERSISTENT_FAILURE`].
+    fn effective(&self, now: Instant) -> Option<Status> {
+        let failing = self
+            .failing_since
+            .is_some_and(|since| now >= since + PERSISTENT_FAILURE);
+        let status = self.status.as_ref()?;
+        Some(Status {
+            failing,
+            ..status.clone()
+        })
+    }
+}
+
+impl Tracker {
+    /// Observe the status of the crates of a document, `pending` ones keep their last status.
+    ///
+    /// Returns the time of a flush to schedule, `None` if an earlier one is already scheduled.
+    pub fn observe(
+        &mut self,
+        uri: &Uri,
+        statuses: BTreeMap<String, Status>,
+        pending: &BTreeSet<String>,
+        now: Instant,
+    ) -> Option<Instant> {
+        let baseline = !self.docs.contains_key(uri);
+        let doc = self.docs.entry(uri.clone()).or_default();
+        for (package, observed) in &mut doc.observed {
+            if observed.status.is_some() && !statuses.contains_key(package) {
+                *observed = Observed {
+                    status: None,
+                    changed: now,
+                    failing_since: None,
+                };
+            }
+        }
+        for (package, mut status) in statuses {
+            let old = doc.observed.get(&package);
+            let old_status = old.and_then(|o| o.status.as_ref());
+            if let (true, Some(old)) = (pending.contains(&package), old_status) {
+                status.outdated.clone_from(&old.outdated);
+                status.failing = old.failing;
+            }
+            if old_status == Some(&status) {
+                continue;
+            }
+            let failing_since = status
+                .failing
+                .then(|| old.and_then(|o| o.failing_since).unwrap_or(now));
+            let observed = Observed {
+                status: Some(status),
+                changed: now,
+                failing_since,
+            };
+            doc.observed.insert(package, observed);
+        }
+        if baseline {
+            doc.sent = doc
+                .observed
+                .iter()
+                .filter_map(|(package, o)| Some((package.clone(), o.effective(now)?)))
+                .collect();
+        }
+        let due = doc.due(now)?;
+        if doc.scheduled.is_some_and(|scheduled| scheduled <= due) {
+            return None;
+        }
+        doc.scheduled = Some(due);
+        Some(due)
+    }
+
+    /// Take the changes that held long enough by `now`.
+    ///
+    /// Returns `None` if the flush of `scheduled` was replaced by an earlier one,
+    /// otherwise the events with the time of the next flush.
+    pub fn flush(
+        &mut self,
+        uri: &Uri,
+        scheduled: Instant,
+        now: Instant,
+        timestamp: &str,
+    ) -> Option<(Vec<Event>, Option<Instant>)> {
+        let doc = self.docs.get_mut(uri)?;
+        if doc.scheduled != Some(scheduled) {
+            return None;
+        }
+        let mut events = Vec::new();
+        for (package, observed) in &doc.observed {
+            if now < observed.changed + DEBOUNCE {
+                continue;
+            }
+            let current = observed.effective(now);
+            let sent = doc.sent.get(package);
+            if sent == current.as_ref() {
+                continue;
+            }
+            let event = |kind, old: Option<&String>, new: Option<&String>| Event {
+                uri: uri.clone(),
+                package: package.clone(),
+                kind,
+                old: old.cloned(),
+                new: new.cloned(),
+                timestamp: timestamp.to_string(),
+            };
+            events.extend(changes(sent, current.as_ref(), event));
+            match current {
+                Some(current) => doc.sent.insert(package.clone(), current),
+                None => doc.sent.remove(package),
+            };
+        }
+        let sent = &doc.sent;
+        doc.observed
+            .retain(|package, o| o.status.is_some() || sent.contains_key(package));
+        doc.scheduled = doc.due(now);
+        Some((events, doc.scheduled))
+    }
+
+    /// Events that rebuild the sent state of every document.
+    pub fn snapshot(&self, timestamp: &str) -> Vec<Event> {
+        let mut docs = self.docs.iter().collect::<Vec<_>>();
+        docs.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        let mut events = Vec::new();
+        for (uri, doc) in docs {
+            for (package, status) in &doc.sent {
+                let event = |kind, old: Option<&String>, new: Option<&String>| Event {
+                    uri: uri.clone(),
+                    package: package.clone(),
+                    kind,
+                    old: old.cloned(),
+                    new: new.cloned(),
+                    timestamp: timestamp.to_string(),
+                };
+                events.extend(changes(None, Some(status), event));
+            }
+        }
+        events
+    }
+
+    /// Forget a closed document, without sending anything.
+    pub fn remove(&mut self, uri: &Uri) {
+        self.docs.remove(uri);
+    }
+
+    pub fn clear(&mut self) {
+        self.docs.clear();
+    }
+}
+
+impl Document {
+    /// Time of the next flush after `now` that sends a change, `None` if nothing changed.
+    fn due(&self, now: Instant) -> Option<Instant> {
+        let due = self.observed.iter().filter_map(|(package, observed)| {
+            let sent = self.sent.get(package);
+            let at = (observed.changed + DEBOUNCE).max(now);
+            if sent != observed.effective(at).as_ref() {
+                return Some(at);
+            }
+            // Failing, but not for long enough yet
+            let failing = observed.failing_since? + PERSISTENT_FAILURE;
+            sent.is_some_and(|s| !s.failing).then_some(failing)
+        });
+        due.min()
+    }
+}
+
+/// The events from the status `old` to `new`, `None` if the crate is not declared.
+fn changes<F>(old: Option<&Status>, new: Option<&Status>, event: F) -> Vec<Event>
+where
+    F: Fn(EventKind, Option<&String>, Option<&String>) -> Event,
+{
+    let (old, new) = match (old, new) {
+        (None, None) => return Vec::new(),
+        (Some(old), None) => return vec![event(EventKind::Removed, Some(&old.req), None)],
+        (old, Some(new)) => (old, new),
+    };
+    let mut events = Vec::new();
+    let none = Status::default();
+    match old {
+        None => events.push(event(EventKind::Added, None, Some(&new.req))),
+        Some(old) if old.req != new.req => events.push(event(
+            EventKind::RequirementChanged,
+            Some(&old.req),
+            Some(&new.req),
+        )),
+        Some(_) => {}
+    }
+    let old = old.unwrap_or(&none);
+    match (&old.outdated, &new.outdated) {
+        (before, Some(newest)) if before.as_ref() != Some(newest) => {
+            events.push(event(EventKind::Outdated, before.as_ref(), Some(newest)));
+        }
+        (Some(before), None) => events.push(event(EventKind::UpToDate, Some(before), None)),
+        _ => {}
+    }
+    match (old.failing, new.failing) {
+        (false, true) => events.push(event(EventKind::FetchFailing, None, None)),
+        (true, false) => events.push(event(EventKind::FetchRecovered, None, None)),
+        _ => {}
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(req: &str, outdated: Option<&str>, failing: bool) -> Status {
+        Status {
+            req: req.into(),
+            outdated: outdated.map(str::to_string),
+            failing,
+        }
+    }
+
+    fn crates<const N: usize>(statuses: [(&str, Status); N]) -> BTreeMap<String, Status> {
+        statuses.map(|(p, s)| (p.to_string(), s)).into()
+    }
+
+    fn changes(events: Vec<Event>) -> Vec<(String, EventKind, Option<String>, Option<String>)> {
+        events
+            .into_iter()
+            .map(|e| (e.package, e.kind, e.old, e.new))
+            .collect()
+    }
+
+    fn change(
+        package: &str,
+        kind: EventKind,
+        old: Option<&str>,
+        new: Option<&str>,
+    ) -> (String, EventKind, Option<String>, Option<String>) {
+        (
+            package.into(),
+            kind,
+            old.map(str::to_string),
+            new.map(str::to_string),
+        )
+    }
+
+    #[test]
+    fn debounces_the_changes() {
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let none = BTreeSet::new();
+        let mut tracker = Tracker::default();
+        // The baseline sends nothing
+        let serde = status("1", None, false);
+        let baseline = crates([("serde", serde.clone()), ("libc", serde.clone())]);
+        assert_eq!(tracker.observe(&uri, baseline, &none, at(0)), None);
+
+        // Typed, only the last requirement is sent
+        let typed = |req| crates([("serde", status(req, None, false)), ("libc", serde.clone())]);
+        let due = tracker.observe(&uri, typed("1."), &none, at(100));
+        assert_eq!(due, Some(at(1100)));
+        assert_eq!(tracker.observe(&uri, typed("1.0"), &none, at(300)), None);
+        let early = tracker.flush(&uri, at(1100), at(1100), "t");
+        assert_eq!(early, Some((Vec::new(), Some(at(1300)))));
+        let (events, next) = tracker.flush(&uri, at(1300), at(1300), "t").unwrap();
+        assert_eq!(
+            changes(events),
+            [change(
+                "serde",
+                EventKind::RequirementChanged,
+                Some("1"),
+                Some("1.0")
+            )]
+        );
+        assert_eq!(next, None);
+        // Published again without a change
+        assert_eq!(tracker.observe(&uri, typed("1.0"), &none, at(2000)), None);
+
+        // Pending crates keep their status
+        let outdated = crates([("serde", status("1.0", Some("2.0.0"), false))]);
+        let due = tracker.observe(&uri, outdated, &none, at(3000)).unwrap();
+        let pending = BTreeSet::from(["serde".to_string()]);
+        let replaced = crates([("serde", status("1.0", None, false))]);
+        assert_eq!(tracker.observe(&uri, replaced, &pending, at(3500)), None);
+        let (events, _) = tracker.flush(&uri, due, at(4000), "t").unwrap();
+        assert_eq!(
+            changes(events),
+            [
+                change("libc", EventKind::Removed, Some("1"), None),
+                change("serde", EventKind::Outdated, None, Some("2.0.0")),
+            ]
+        );
+        // A flush replaced by an earlier one sends nothing
+        assert_eq!(tracker.flush(&uri, at(9000), at(9000), "t"), None);
+
+        let snapshot = tracker.snapshot("2024-11-03T08:00:00Z");
+        assert_eq!(
+            changes(snapshot.clone()),
+            [
+                change("serde", EventKind::Added, None, Some("1.0")),
+                change("serde", EventKind::Outdated, None, Some("2.0.0")),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&snapshot[0]).unwrap(),
+            serde_json::json!({
+                "uri": "file:///app/Cargo.toml",
+                "crate": "serde",
+                "kind": "added",
+                "new": "1.0",
+                "timestamp": "2024-11-03T08:00:00Z",
+            })
+        );
+        tracker.remove(&uri);
+        assert_eq!(tracker.snapshot("t"), []);
+    }
+
+    #[test]
+    fn sends_only_persistent_failures() {
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let start = Instant::now();
+        let at = |s| start + Duration::from_secs(s);
+        let none = BTreeSet::new();
+        let mut tracker = Tracker::default();
+        let failing = |failing| crates([("serde", status("1", None, failing))]);
+        assert_eq!(tracker.observe(&uri, failing(false), &none, at(0)), None);
+
+        // Recovered before it counted
+        let due = tracker.observe(&uri, failing(true), &none, at(10)).unwrap();
+        assert_eq!(due, at(70));
+        tracker.observe(&uri, failing(false), &none, at(20));
+        assert_eq!(tracker.flush(&uri, at(21), at(21), "t"), None);
+        assert_eq!(
+            tracker.flush(&uri, at(70), at(70), "t"),
+            Some((Vec::new(), None))
+        );
+
+        let due = tracker
+            .observe(&uri, failing(true), &none, at(100))
+            .unwrap();
+        // Still failing when analyzed again
+        assert_eq!(tracker.observe(&uri, failing(true), &none, at(130)), None);
+        let (events, next) = tracker.flush(&uri, due, at(160), "t").unwrap();
+        assert_eq!(
+            changes(events),
+            [change("serde", EventKind::FetchFailing, None, None)]
+        );
+        assert_eq!(next, None);
+        let due = tracker
+            .observe(&uri, failing(false), &none, at(200))
+            .unwrap();
+        let (events, _) = tracker.flush(&uri, due, at(201), "t").unwrap();
+        assert_eq!(
+            changes(events),
+            [change("serde", EventKind::FetchRecovered, None, None)]
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::{
+    DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, TextDocumentEdit, TextEdit,
+    Uri, WorkspaceEdit,
+};
+
+use crate::config::{RequirementStyle, ScanScope};
+use crate::manifest;
+use crate::text::Source;
+use crate::version;
+
+/// Command updating a crate in every known manifest, arguments are [`EverywhereArgs`].
+pub const UPDATE_EVERYWHERE: &str = "crates-io.updateEverywhere";
+
+/// Update the crate `name` to `version`, the newest release if missing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EverywhereArgs {
+    pub name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Overrides the `scanScope` of the configuration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<ScanScope>,
+    /// A manifest, file, or directory selecting the workspace context,
+    /// by default the one of the active document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Uri>,
+}
+
+/// Result of [`UPDATE_EVERYWHERE`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EverywhereResult {
+    /// Version the requirements were updated to
+    pub version: String,
+    /// Whether the client applied the edit, `false` if there was nothing to edit
+    pub applied: bool,
+    /// Why the client rejected the edit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
+    pub updated: Vec<Updated>,
+    /// Declarations that were not edited
+    pub skipped: Vec<Skipped>,
+}
+
+/// An edited requirement.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Updated {
+    pub uri: Uri,
+    pub section: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// A declaration or a whole manifest that could not be edited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Skipped {
+    pub uri: Uri,
+    /// `None` if the whole manifest was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    pub reason: SkipReason,
+    /// Details of the reason, like the error reading the manifest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SkipReason {
+    /// `workspace = true`, the version is updated in the workspace root
+    Inherited,
+    /// Git dependencies are pinned by the repository
+    Git,
+    /// Declarations without `version`, like path dependencies
+    NoVersion,
+    /// The manifest is not valid TOML, its spans can not be trusted
+    ParseError,
+    /// The manifest could not be read
+    Unreadable,
+}
+
+/// Edits of a manifest with the outcome of each declaration.
+#[derive(Debug, Default)]
+pub struct Rewrite {
+    pub edits: Vec<TextEdit>,
+    pub updated: Vec<Updated>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Rewrite the requirements of the crate `name` in a manifest to `version`.
+///
+/// Renamed declarations (`package = "name"`) are included, requirements that already
+/// render as the same text are left alone.
+pub fn rewrite(
+    uri: &Uri,
+    source: &Source,
+    name: &str,
+    version: &Version,
+    style: RequirementStyle,
+) -> Rewrite {
+    let text = source.text.as_str();
+    let mut rewrite = Rewrite::default();
+    let (parsed, err) = manifest::parse(text);
+    if let Some(err) = err {
+        rewrite.skipped.push(Skipped {
+            uri: uri.clone(),
+            section: None,
+            reason: SkipReason::ParseError,
+            message: Some(err.message().to_string()),
+        });
+        return rewrite;
+    }
+    let dependencies = parsed
+        .dependencies()
+        .map(|(kind, key, dep)| (kind.section().to_string(), key, dep))
+        .chain(
+            parsed
+                .target_dependencies()
+                .map(|(platform, kind, key, dep)| (kind.target_section(platform), key, dep)),
+        );
+    for (section, key, dep) in dependencies {
+        if dep.package().unwrap_or(key.get_ref()) != name {
+            continue;
+        }
+        let mut skip = |reason| {
+            rewrite.skipped.push(Skipped {
+                uri: uri.clone(),
+                section: Some(section.clone()),
+                reason,
+                message: None,
+            })
+        };
+        if dep.inherited() {
+            skip(SkipReason::Inherited);
+            continue;
+        }
+        if dep.detail().is_some_and(|d| d.git.is_some()) {
+            skip(SkipReason::Git);
+            continue;
+        }
+        let Some(range) = dep.req_span().and_then(|span| source.string_range(span)) else {
+            skip(SkipReason::NoVersion);
+            continue;
+        };
+        let from = dep.req().to_string();
+        let to = version::requirement(version, style, &from);
+        if to == from {
+            continue;
+        }
+        rewrite.edits.push(TextEdit {
+            range,
+            new_text: to.clone(),
+        });
+        rewrite.updated.push(Updated {
+            uri: uri.clone(),
+            section,
+            from,
+            to,
+        });
+    }
+    rewrite
+}
+
+/// A single edit of all manifests.
+///
+/// `documents` are the edits of each manifest with the version of open documents,
+/// closed files have none and are edited on disk.
+/// Without `document_changes` support of the client the versions are dropped.
+pub fn workspace_edit(
+    documents: Vec<(Uri, Option<i32>, Vec<TextEdit>)>,
+    document_changes: bool,
+) -> WorkspaceEdit {
+    if !document_changes {
+        return WorkspaceEdit {
+            changes: Some(
+                documents
+                    .into_iter()
+                    .map(|(uri, _, edits)| (uri, edits))
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+    }
+    let edits = documents
+        .into_iter()
+        .map(|(uri, version, edits)| TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+            edits: edits.into_iter().map(OneOf::Left).collect(),
+        })
+        .collect();
+    WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(edits)),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types::{Position, Range};
+
+    use super::*;
+    use crate::text::Encoding;
+
+    fn source(text: &str) -> Source {
+        Source::new(text, Encoding::Utf8)
+    }
+
+    fn uri() -> Uri {
+        "file:///ws/app/Cargo.toml".parse().unwrap()
+    }
+
+    #[test]
+    fn rewrites_each_declaration() {
+        let text = "[dependencies]\n\
+            serde = \"1.0.100\"\n\
+            json = { package = \"serde\", version = \"~1.0\" }\n\
+            local = { path = \"../serde\", package = \"serde\" }\n\n\
+            [dev-dependencies]\n\
+            serde = { git = \"https://github.com/serde-rs/serde\" }\n\n\
+            [target.'cfg(unix)'.build-dependencies]\n\
+            serde = { workspace = true }\n";
+        let rewrite = rewrite(
+            &uri(),
+            &source(text),
+            "serde",
+            &Version::new(1, 0, 215),
+            RequirementStyle::Preserve,
+        );
+        // `~1.0` already allows 1.0.215
+        assert_eq!(
+            rewrite.edits,
+            [TextEdit::new(
+                Range::new(Position::new(1, 9), Position::new(1, 16)),
+                "1.0.215".into()
+            )]
+        );
+        assert_eq!(
+            rewrite
+                .updated
+                .iter()
+                .map(|u| (u.section.as_str(), u.from.as_str(), u.to.as_str()))
+                .collect::<Vec<_>>(),
+            [("dependencies", "1.0.100", "1.0.215")]
+        );
+        let skipped = rewrite
+            .skipped
+            .iter()
+            .map(|s| (s.section.clone().unwrap(), s.reason.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            skipped,
+            [
+                ("dependencies".into(), SkipReason::NoVersion),
+                ("dev-dependencies".into(), SkipReason::Git),
+                (
+                    "target.'cfg(unix)'.build-dependencies".into(),
+                    SkipReason::Inherited
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn respects_the_requirement_style() {
+        let text = "[dependencies]\nserde = \"1.0.100\"\n";
+        let version = Version::new(1, 0, 215);
+        let minor = rewrite(
+            &uri(),
+            &source(text),
+            "serde",
+            &version,
+            RequirementStyle::Minor,
+        );
+        assert_eq!(minor.edits[0].new_text, "1.0");
+        let version = Version::new(2, 1, 0);
+        let major = rewrite(
+            &uri(),
+            &source(text),
+            "serde",
+            &version,
+            RequirementStyle::Major,
+        );
+        assert_eq!(major.edits[0].new_text, "2");
+    }
+
+    #[test]
+    fn skips_manifests_with_errors() {
+        let text = "[dependencies]\nserde = \"1.0\"\n\n[features\n";
+        let rewrite = rewrite(
+            &uri(),
+            &source(text),
+            "serde",
+            &Version::new(1, 0, 215),
+            RequirementStyle::Preserve,
+        );
+        assert!(rewrite.edits.is_empty());
+        assert_eq!(rewrite.skipped.len(), 1);
+        assert_eq!(rewrite.skipped[0].section, None);
+        assert_eq!(rewrite.skipped[0].reason, SkipReason::ParseError);
+    }
+
+    #[test]
+    fn versions_open_documents() {
+        let edit = TextEdit::new(Range::default(), "2".into());
+        let open: Uri = "file:///ws/Cargo.toml".parse().unwrap();
+        let documents = vec![
+            (open.clone(), Some(7), vec![edit.clone()]),
+            (uri(), None, vec![edit.clone()]),
+        ];
+        let Some(DocumentChanges::Edits(edits)) =
+            workspace_edit(documents.clone(), true).document_changes
+        else {
+            panic!("no document changes");
+        };
+        let versions = edits
+            .iter()
+            .map(|e| (e.text_document.uri.clone(), e.text_document.version))
+            .collect::<Vec<_>>();
+        assert_eq!(versions, [(open.clone(), Some(7)), (uri(), None)]);
+
+        // Clients without document changes get plain changes
+        let plain = workspace_edit(documents, false);
+        assert_eq!(plain.document_changes, None);
+        assert_eq!(plain.changes.unwrap().get(&open), Some(&vec![edit]));
+    }
+}
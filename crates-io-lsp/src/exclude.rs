@@ -0,0 +1,195 @@
+use std::path::Path;
+
+use ignore::gitignore::Gitignore;
+use ignore::Match;
+
+/// Why a manifest is analyzed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Opened by the user
+    Open,
+    /// Read without the user asking, like the manifests of workspace members
+    Background,
+}
+
+/// Whether the manifest at `path` is analyzed.
+///
+/// Copies in cargo's `target` directory, vendored sources, and the `exclude` globs
+/// (relative to the workspace folder `root`) are always skipped.
+/// Git-ignored manifests are only analyzed if they were opened.
+pub fn should_analyze(
+    path: &Path,
+    origin: Origin,
+    root: Option<&Path>,
+    exclude: &[String],
+) -> bool {
+    let Some(dir) = path.parent() else {
+        return true;
+    };
+    if in_target(dir) || vendored(dir) {
+        return false;
+    }
+    if root.is_some_and(|root| excluded(path, root, exclude)) {
+        return false;
+    }
+    origin == Origin::Open || !ignored(path, root)
+}
+
+/// Inside a `target` directory tagged with `CACHEDIR.TAG` or beside a manifest.
+fn in_target(dir: &Path) -> bool {
+    dir.ancestors().any(|dir| {
+        dir.file_name().is_some_and(|name| name == "target")
+            && (dir.join("CACHEDIR.TAG").is_file()
+                || dir
+                    .parent()
+                    .is_some_and(|parent| parent.join("Cargo.toml").is_file()))
+    })
+}
+
+/// Inside a directory that replaces a source in `.cargo/config.toml`, like `cargo vendor` sets up.
+fn vendored(dir: &Path) -> bool {
+    dir.ancestors().any(|base| {
+        let config = ["config.toml", "config"]
+            .iter()
+            .find_map(|name| std::fs::read_to_string(base.join(".cargo").join(name)).ok());
+        let Some(config) = config.and_then(|c| c.parse::<toml::Table>().ok()) else {
+            return false;
+        };
+        let Some(sources) = config.get("source").and_then(|s| s.as_table()) else {
+            return false;
+        };
+        sources
+            .values()
+            .filter_map(|source| source.get("directory")?.as_str())
+            .any(|directory| dir.starts_with(base.join(directory)))
+    })
+}
+
+/// Matches one of the `exclude` globs, or is inside a directory that does.
+fn excluded(path: &Path, root: &Path, exclude: &[String]) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    exclude
+        .iter()
+        .filter_map(|pattern| glob::Pattern::new(pattern).ok())
+        .any(|pattern| relative.ancestors().any(|p| pattern.matches_path(p)))
+}
+
+/// Ignored by a `.gitignore` between the manifest and the repository or workspace folder.
+///
+/// The closest `.gitignore` with a matching rule decides.
+fn ignored(path: &Path, root: Option<&Path>) -> bool {
+    let Some(dir) = path.parent() else {
+        return false;
+    };
+    for dir in dir.ancestors() {
+        let file = dir.join(".gitignore");
+        if file.is_file() {
+            let (gitignore, _) = Gitignore::new(&file);
+            match gitignore.matched_path_or_any_parents(path, false) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        if dir.join(".git").exists() || Some(dir) == root {
+            break;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Workspace with every excluded kind of manifest, removed on drop.
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            for dir in [
+                "",
+                "crates/a",
+                "crates/target",
+                "target/package/foo-1.2.3",
+                "tools",
+                "tools/target/debug/build",
+                "vendor/serde",
+                "third_party/serde",
+                "tests/fixtures/broken",
+                "examples/demo",
+            ] {
+                fs::create_dir_all(root.join(dir)).unwrap();
+                fs::write(root.join(dir).join("Cargo.toml"), "[package]\n").unwrap();
+            }
+            fs::write(root.join("target/CACHEDIR.TAG"), "").unwrap();
+            fs::create_dir_all(root.join(".cargo")).unwrap();
+            fs::write(
+                root.join(".cargo/config.toml"),
+                "[source.crates-io]\nreplace-with = \"vendored-sources\"\n\n[source.vendored-sources]\ndirectory = \"vendor\"\n",
+            )
+            .unwrap();
+            fs::write(root.join(".gitignore"), "/tests/fixtures/\n").unwrap();
+            Self(root)
+        }
+
+        fn analyzed(&self, dir: &str, origin: Origin) -> bool {
+            let path = self.0.join(dir).join("Cargo.toml");
+            should_analyze(&path, origin, Some(&self.0), &["examples/*".into()])
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn analyzes_members() {
+        let fixture = Fixture::new("crates-io-lsp-exclude-members");
+        for dir in ["", "crates/a", "third_party/serde"] {
+            assert!(fixture.analyzed(dir, Origin::Open), "{dir}");
+            assert!(fixture.analyzed(dir, Origin::Background), "{dir}");
+        }
+        // Not a target directory of cargo
+        assert!(fixture.analyzed("crates/target", Origin::Background));
+    }
+
+    #[test]
+    fn skips_targets_vendored_and_excluded() {
+        let fixture = Fixture::new("crates-io-lsp-exclude-skipped");
+        for dir in [
+            "target/package/foo-1.2.3",
+            "tools/target/debug/build",
+            "vendor/serde",
+            "examples/demo",
+        ] {
+            assert!(!fixture.analyzed(dir, Origin::Open), "{dir}");
+            assert!(!fixture.analyzed(dir, Origin::Background), "{dir}");
+        }
+    }
+
+    #[test]
+    fn analyzes_ignored_only_if_opened() {
+        let fixture = Fixture::new("crates-io-lsp-exclude-ignored");
+        assert!(fixture.analyzed("tests/fixtures/broken", Origin::Open));
+        assert!(!fixture.analyzed("tests/fixtures/broken", Origin::Background));
+
+        // The globs are relative to the workspace folder
+        let path = fixture.0.join("examples/demo/Cargo.toml");
+        assert!(should_analyze(
+            &path,
+            Origin::Background,
+            None,
+            &["examples/*".into()]
+        ));
+    }
+}
@@ -0,0 +1,349 @@
+//! Failover between the endpoints of one registry, like a mirror of the sparse index in front of
+//! the public one.
+//!
+//! The endpoints are tried in order. Network errors, server errors, and timeouts move on to the
+//! next one, a missing crate does not, as all of them serve the same index.
+//! An endpoint failing [`FAILURES`] times in a row is skipped for a [`COOLDOWN`],
+//! so that a dead mirror stops delaying every request, and is tried again afterwards.
+
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::error::{Result, ServerError};
+use crate::registry::{self, BoxFuture, IndexConfig, IndexVersions, RegistryClient};
+
+/// Consecutive failures after which an endpoint is skipped.
+const FAILURES: u32 = 3;
+/// Time a failing endpoint is skipped before it is tried again.
+const COOLDOWN: Duration = Duration::from_secs(60);
+/// Time an endpoint gets to respond before the next one is tried.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Change of the endpoint serving the requests, shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notice {
+    /// The first endpoint failed with `reason` and `to` serves the requests instead
+    FailedOver {
+        from: String,
+        to: String,
+        reason: String,
+    },
+    /// The first endpoint serves the requests again
+    Recovered { endpoint: String },
+}
+
+/// Client for a registry served by `endpoints`, in the order they are tried.
+///
+/// The token is only sent to the first endpoint, the others are usually public mirrors
+/// that must not see it.
+pub fn client(endpoints: &[&str], token: &str, max_bytes: usize) -> Arc<dyn RegistryClient> {
+    if let [endpoint] = endpoints {
+        return registry::client(endpoint, token, max_bytes);
+    }
+    let endpoints = endpoints
+        .iter()
+        .enumerate()
+        .map(|(i, endpoint)| {
+            let token = if i == 0 { token } else { "" };
+            (
+                endpoint.to_string(),
+                registry::client(endpoint, token, max_bytes),
+            )
+        })
+        .collect();
+    Arc::new(Failover::new(endpoints))
+}
+
+/// Whether another endpoint might succeed where one failed with `err`.
+fn fails_over(err: &ServerError) -> bool {
+    matches!(
+        err,
+        ServerError::Network(_) | ServerError::Timeout(_) | ServerError::Io { .. }
+    )
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    /// Failures since the last success
+    failures: u32,
+    /// Skipped until then after [`FAILURES`] failures
+    down_until: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    health: Vec<Health>,
+    /// Index of the endpoint that served the last request
+    serving: usize,
+    /// Last error of the first endpoint, the reason of the failover
+    reason: Option<String>,
+    notices: Vec<Notice>,
+}
+
+struct Failover {
+    endpoints: Vec<(String, Arc<dyn RegistryClient>)>,
+    state: Mutex<State>,
+    cooldown: Duration,
+    timeout: Duration,
+}
+
+impl Failover {
+    fn new(endpoints: Vec<(String, Arc<dyn RegistryClient>)>) -> Self {
+        let state = State {
+            health: endpoints.iter().map(|_| Health::default()).collect(),
+            ..Default::default()
+        };
+        Self {
+            endpoints,
+            state: Mutex::new(state),
+            cooldown: COOLDOWN,
+            timeout: ATTEMPT_TIMEOUT,
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The endpoints to try, the skipped ones last in case all others fail too.
+    fn order(&self, now: Instant) -> Vec<usize> {
+        let state = self.state();
+        let (up, down): (Vec<_>, Vec<_>) = (0..self.endpoints.len())
+            .partition(|&i| state.health[i].down_until.is_none_or(|until| until <= now));
+        up.into_iter().chain(down).collect()
+    }
+
+    fn failed(&self, i: usize, err: &ServerError, now: Instant) {
+        let mut state = self.state();
+        let health = &mut state.health[i];
+        health.failures += 1;
+        if health.failures >= FAILURES {
+            health.down_until = Some(now + self.cooldown);
+        }
+        if i == 0 {
+            state.reason = Some(err.to_string());
+        }
+    }
+
+    /// Record that endpoint `i` responded, noting when it takes over or the first one is back.
+    fn served(&self, i: usize) {
+        let mut state = self.state();
+        state.health[i] = Health::default();
+        let previous = mem::replace(&mut state.serving, i);
+        if i == 0 && previous != 0 {
+            let endpoint = self.endpoints[0].0.clone();
+            state.notices.push(Notice::Recovered { endpoint });
+        } else if i != 0 && previous == 0 {
+            let notice = Notice::FailedOver {
+                from: self.endpoints[0].0.clone(),
+                to: self.endpoints[i].0.clone(),
+                reason: state.reason.clone().unwrap_or_default(),
+            };
+            state.notices.push(notice);
+        }
+    }
+
+    fn serving(&self) -> &dyn RegistryClient {
+        &*self.endpoints[self.state().serving].1
+    }
+}
+
+impl RegistryClient for Failover {
+    fn index<'a>(&'a self, name: &'a str, details: bool) -> BoxFuture<'a, Result<IndexVersions>> {
+        Box::pin(async move {
+            let mut last = None;
+            for i in self.order(Instant::now()) {
+                let client = &self.endpoints[i].1;
+                let result = tokio::time::timeout(self.timeout, client.index(name, details))
+                    .await
+                    .unwrap_or(Err(ServerError::Timeout(self.timeout)));
+                match result {
+                    Err(err) if fails_over(&err) => {
+                        self.failed(i, &err, Instant::now());
+                        last = Some(err);
+                    }
+                    result => {
+                        self.served(i);
+                        return result;
+                    }
+                }
+            }
+            Err(last.unwrap_or_else(|| ServerError::Internal("No registry endpoint".into())))
+        })
+    }
+
+    fn modified_since<'a>(&'a self, name: &'a str, fetched: i64) -> BoxFuture<'a, bool> {
+        self.serving().modified_since(name, fetched)
+    }
+
+    fn config(&self) -> BoxFuture<'_, Option<IndexConfig>> {
+        self.serving().config()
+    }
+
+    fn notices(&self) -> Vec<Notice> {
+        mem::take(&mut self.state().notices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const OK: u8 = 0;
+    const UNAVAILABLE: u8 = 1;
+    const HANGING: u8 = 2;
+
+    /// Sparse index serving `version` for every crate but `missing`, unless its mode says
+    /// otherwise.
+    struct Server {
+        endpoint: String,
+        mode: Arc<AtomicU8>,
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl Server {
+        async fn start(version: &'static str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            let mode = Arc::new(AtomicU8::new(OK));
+            let requests = Arc::new(AtomicUsize::new(0));
+            let (m, r) = (mode.clone(), requests.clone());
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let mut request = [0; 1024];
+                    let n = stream.read(&mut request).await.unwrap_or(0);
+                    r.fetch_add(1, Ordering::SeqCst);
+                    let body = format!("{{\"vers\":\"{version}\",\"yanked\":false}}\n");
+                    let status = match m.load(Ordering::SeqCst) {
+                        HANGING => {
+                            // Keep the connection open without responding
+                            tokio::spawn(async move {
+                                tokio::time::sleep(Duration::from_secs(60)).await;
+                                drop(stream);
+                            });
+                            continue;
+                        }
+                        UNAVAILABLE => "503 Service Unavailable",
+                        _ if String::from_utf8_lossy(&request[..n]).contains("/missing ") => {
+                            "404 Not Found"
+                        }
+                        _ => "200 OK",
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            });
+            Self {
+                endpoint,
+                mode,
+                requests,
+            }
+        }
+
+        fn set(&self, mode: u8) {
+            self.mode.store(mode, Ordering::SeqCst);
+        }
+
+        fn requests(&self) -> usize {
+            self.requests.load(Ordering::SeqCst)
+        }
+    }
+
+    fn failover(servers: &[&Server]) -> Failover {
+        let endpoints = servers
+            .iter()
+            .map(|s| {
+                (
+                    s.endpoint.clone(),
+                    registry::client(&s.endpoint, "", 1 << 20),
+                )
+            })
+            .collect();
+        Failover {
+            cooldown: Duration::from_millis(300),
+            timeout: Duration::from_millis(200),
+            ..Failover::new(endpoints)
+        }
+    }
+
+    async fn version(client: &Failover, name: &str) -> Result<String> {
+        let index = client.versions(name).await?;
+        Ok(index.versions[0].raw.clone())
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_the_next_endpoint() {
+        let primary = Server::start("1.0.0").await;
+        let mirror = Server::start("2.0.0").await;
+        let client = failover(&[&primary, &mirror]);
+
+        assert_eq!(version(&client, "serde").await.unwrap(), "1.0.0");
+        // A missing crate is missing everywhere
+        assert!(matches!(
+            version(&client, "missing").await,
+            Err(ServerError::NotFound(_))
+        ));
+        assert_eq!((primary.requests(), mirror.requests()), (2, 0));
+        assert_eq!(client.notices(), []);
+
+        primary.set(UNAVAILABLE);
+        assert_eq!(version(&client, "serde").await.unwrap(), "2.0.0");
+        let [Notice::FailedOver { from, to, reason }] = &client.notices()[..] else {
+            panic!("no failover notice");
+        };
+        assert_eq!((from, to), (&primary.endpoint, &mirror.endpoint));
+        assert!(reason.contains("503"), "{reason}");
+
+        // Noticed once, then skipped after consecutive failures
+        for _ in 1..FAILURES {
+            assert_eq!(version(&client, "serde").await.unwrap(), "2.0.0");
+        }
+        assert_eq!(client.notices(), []);
+        assert_eq!(primary.requests(), 2 + FAILURES as usize);
+        assert_eq!(version(&client, "serde").await.unwrap(), "2.0.0");
+        assert_eq!(primary.requests(), 2 + FAILURES as usize);
+
+        // Tried again after the cooldown
+        primary.set(OK);
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert_eq!(version(&client, "serde").await.unwrap(), "1.0.0");
+        assert_eq!(
+            client.notices(),
+            [Notice::Recovered {
+                endpoint: primary.endpoint.clone()
+            }]
+        );
+        assert_eq!(version(&client, "serde").await.unwrap(), "1.0.0");
+        assert_eq!(client.notices(), []);
+    }
+
+    #[tokio::test]
+    async fn fails_over_on_timeouts() {
+        let primary = Server::start("1.0.0").await;
+        let mirror = Server::start("2.0.0").await;
+        let client = failover(&[&primary, &mirror]);
+
+        primary.set(HANGING);
+        assert_eq!(version(&client, "serde").await.unwrap(), "2.0.0");
+        let [Notice::FailedOver { reason, .. }] = &client.notices()[..] else {
+            panic!("no failover notice");
+        };
+        assert!(reason.starts_with("Timed out"), "{reason}");
+
+        // Both failing is the error of the last one
+        mirror.set(UNAVAILABLE);
+        let err = version(&client, "serde").await.unwrap_err();
+        assert!(err.to_string().contains("503"), "{err}");
+    }
+}
@@ -0,0 +1,746 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::{self, Diagnostic, NumberOrString, Uri};
+
+use crate::config::Config;
+use crate::manifest::{DependencyKind, SpannedManifest};
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// Diagnostic code of features that enable themselves.
+pub const FEATURE_CYCLE: &str = "feature-cycle";
+
+/// Command resolving the dependencies of a feature selection, arguments are [`ResolveArgs`].
+pub const RESOLVE_FEATURES: &str = "crates-io.resolveFeatures";
+
+/// Nesting limit of the rendered trees.
+const MAX_DEPTH: usize = 8;
+
+/// What a feature value like `"dep:serde"` or `"std"` activates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Node<'a> {
+    Feature(&'a str),
+    /// An optional dependency by its key
+    Dependency(&'a str),
+}
+
+/// A feature of a dependency requested by a value like `"serde/derive"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Forward<'a> {
+    /// Key of the dependency
+    pub dep: &'a str,
+    pub feature: &'a str,
+    /// `dep?/feature` only applies if the dependency is enabled otherwise
+    pub weak: bool,
+}
+
+/// Activations between the `[features]` and the optional dependencies of a manifest.
+///
+/// Optional dependencies that no `dep:` value refers to have an implicit feature of
+/// the same name, which is resolved directly to the dependency.
+#[derive(Debug, Default)]
+pub struct FeatureGraph<'a> {
+    /// Direct activations of each declared feature
+    edges: BTreeMap<&'a str, Vec<Node<'a>>>,
+    /// Features of dependencies requested by each declared feature
+    forwards: BTreeMap<&'a str, Vec<Forward<'a>>>,
+    optional: BTreeSet<&'a str>,
+    /// Optional dependencies referred to by `dep:`, they have no implicit feature
+    explicit: BTreeSet<&'a str>,
+}
+
+impl<'a> FeatureGraph<'a> {
+    pub fn new(manifest: &'a SpannedManifest) -> Self {
+        let optional = manifest
+            .dependencies()
+            .chain(
+                manifest
+                    .target_dependencies()
+                    .map(|(_, kind, name, dep)| (kind, name, dep)),
+            )
+            .filter(|(kind, _, dep)| {
+                *kind != DependencyKind::Workspace
+                    && dep
+                        .detail()
+                        .and_then(|d| d.optional.as_ref())
+                        .is_some_and(|o| *o.get_ref())
+            })
+            .map(|(_, name, _)| name.get_ref().as_str())
+            .collect::<BTreeSet<_>>();
+        let explicit = manifest
+            .features
+            .values()
+            .flatten()
+            .filter_map(|v| v.get_ref().strip_prefix("dep:"))
+            .collect::<BTreeSet<_>>();
+        let mut graph = Self {
+            edges: manifest
+                .features
+                .keys()
+                .map(|f| (f.get_ref().as_str(), Vec::new()))
+                .collect(),
+            forwards: BTreeMap::new(),
+            optional,
+            explicit,
+        };
+
+        for (feature, values) in &manifest.features {
+            let mut targets = BTreeSet::new();
+            let mut forwards = BTreeSet::new();
+            for value in values {
+                let (node, forward) = graph.activation(value.get_ref());
+                targets.extend(node);
+                forwards.extend(forward);
+            }
+            let feature = feature.get_ref().as_str();
+            graph.edges.insert(feature, targets.into_iter().collect());
+            graph
+                .forwards
+                .insert(feature, forwards.into_iter().collect());
+        }
+        graph
+    }
+
+    /// What a feature value activates directly and which feature of a dependency it requests.
+    fn activation(&self, value: &'a str) -> (Option<Node<'a>>, Option<Forward<'a>>) {
+        if let Some(dep) = value.strip_prefix("dep:") {
+            (
+                self.optional.contains(dep).then_some(Node::Dependency(dep)),
+                None,
+            )
+        } else if let Some((dep, feature)) = value.split_once('/') {
+            match dep.strip_suffix('?') {
+                Some(dep) => (
+                    None,
+                    Some(Forward {
+                        dep,
+                        feature,
+                        weak: true,
+                    }),
+                ),
+                None => (
+                    self.optional.contains(dep).then_some(Node::Dependency(dep)),
+                    Some(Forward {
+                        dep,
+                        feature,
+                        weak: false,
+                    }),
+                ),
+            }
+        } else if self.edges.contains_key(value) {
+            (Some(Node::Feature(value)), None)
+        } else if self.optional.contains(value) && !self.explicit.contains(value) {
+            (Some(Node::Dependency(value)), None)
+        } else {
+            (None, None)
+        }
+    }
+
+    /// The features directly activating `node`.
+    pub fn enabled_by(&self, node: Node) -> Vec<&'a str> {
+        self.edges
+            .iter()
+            .filter(|(_, targets)| targets.contains(&node))
+            .map(|(feature, _)| *feature)
+            .collect()
+    }
+
+    /// A path from `feature` back to itself, like `["a", "b", "a"]`.
+    pub fn cycle(&self, feature: &'a str) -> Option<Vec<&'a str>> {
+        let mut parents = BTreeMap::new();
+        let mut queue = vec![feature];
+        while let Some(current) = queue.pop() {
+            for target in self.edges.get(current).into_iter().flatten() {
+                let Node::Feature(next) = *target else {
+                    continue;
+                };
+                if next == feature {
+                    let mut path = vec![feature, current];
+                    let mut at = current;
+                    while at != feature {
+                        at = parents[at];
+                        path.push(at);
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                if !parents.contains_key(next) {
+                    parents.insert(next, current);
+                    queue.push(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Markdown tree of what a feature activates, with the optional dependencies marked.
+    pub fn activations(&self, feature: &'a str) -> String {
+        let mut lines = Vec::new();
+        self.activation_tree(feature, &mut vec![feature], &mut lines);
+        if lines.is_empty() {
+            format!("**Feature `{feature}`** enables nothing else")
+        } else {
+            format!("**Feature `{feature}`** enables\n\n{}", lines.join("\n"))
+        }
+    }
+
+    fn activation_tree(&self, feature: &'a str, path: &mut Vec<&'a str>, lines: &mut Vec<String>) {
+        let indent = "  ".repeat(path.len() - 1);
+        for target in self.edges.get(feature).into_iter().flatten() {
+            match *target {
+                Node::Dependency(dep) => {
+                    lines.push(format!("{indent}- `{dep}` (optional dependency)"));
+                }
+                Node::Feature(next) if path.contains(&next) => {
+                    lines.push(format!("{indent}- `{next}` (cycle)"));
+                }
+                Node::Feature(next) => {
+                    lines.push(format!("{indent}- `{next}`"));
+                    if path.len() < MAX_DEPTH {
+                        path.push(next);
+                        self.activation_tree(next, path, lines);
+                        path.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Markdown tree of the features activating an optional dependency, directly or transitively.
+    pub fn enablers(&self, dep: &'a str) -> Option<String> {
+        if !self.optional.contains(dep) {
+            return None;
+        }
+        let mut lines = Vec::new();
+        for feature in self.enabled_by(Node::Dependency(dep)) {
+            lines.push(format!("- `{feature}`"));
+            self.enabler_tree(feature, &mut vec![feature], &mut lines);
+        }
+        Some(if lines.is_empty() {
+            "Optional, no feature enables it".to_string()
+        } else {
+            format!("Optional, enabled by\n\n{}", lines.join("\n"))
+        })
+    }
+
+    fn enabler_tree(&self, feature: &'a str, path: &mut Vec<&'a str>, lines: &mut Vec<String>) {
+        let indent = "  ".repeat(path.len());
+        for parent in self.enabled_by(Node::Feature(feature)) {
+            if path.contains(&parent) {
+                lines.push(format!("{indent}- `{parent}` (cycle)"));
+            } else {
+                lines.push(format!("{indent}- `{parent}`"));
+                if path.len() < MAX_DEPTH {
+                    path.push(parent);
+                    self.enabler_tree(parent, path, lines);
+                    path.pop();
+                }
+            }
+        }
+    }
+}
+
+/// Features of the package at `uri` assumed enabled, like `cargo build --features`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveArgs {
+    pub uri: Uri,
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Enable the `default` feature, `true` if missing
+    #[serde(default)]
+    pub default_features: Option<bool>,
+    #[serde(default)]
+    pub format: Format,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Markdown,
+}
+
+/// The features and dependencies enabled by a feature selection.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Resolution {
+    /// Enabled features of the package
+    pub features: Vec<String>,
+    pub dependencies: Vec<ResolvedDependency>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedDependency {
+    /// Key of the dependency
+    pub name: String,
+    /// Name of the crate in the registry
+    pub package: String,
+    /// Like `dependencies` or `target.'cfg(unix)'.build-dependencies`
+    pub section: String,
+    pub optional: bool,
+    pub enabled: bool,
+    /// Requirement of registry dependencies
+    #[serde(skip)]
+    pub req: Option<String>,
+    /// Features requested by the manifest, including `default` unless it is disabled
+    pub requested: Vec<String>,
+    /// Version whose features expanded the requested ones
+    pub version: Option<String>,
+    /// Features enabled in the dependency, `None` if its features are unknown
+    pub features: Option<Vec<String>>,
+}
+
+/// Resolve the features and dependencies enabled by `features` like cargo.
+///
+/// Non-optional dependencies are always enabled, optional ones if a feature activates them.
+/// Weak `dep?/feature` values only request features of dependencies enabled otherwise.
+/// The features of the dependencies are not expanded, see [`expand`].
+pub fn resolve<'a>(
+    manifest: &'a SpannedManifest,
+    features: &'a [String],
+    default_features: bool,
+) -> Resolution {
+    let graph = FeatureGraph::new(manifest);
+    let mut pending = Vec::new();
+    let mut forwards = BTreeSet::new();
+    let defaults = default_features.then_some("default");
+    for value in features.iter().map(String::as_str).chain(defaults) {
+        let (node, forward) = graph.activation(value);
+        pending.extend(node);
+        forwards.extend(forward);
+    }
+    let mut enabled = BTreeSet::new();
+    let mut activated = BTreeSet::new();
+    while let Some(node) = pending.pop() {
+        match node {
+            Node::Feature(feature) => {
+                if enabled.insert(feature) {
+                    pending.extend(graph.edges.get(feature).into_iter().flatten());
+                    forwards.extend(graph.forwards.get(feature).into_iter().flatten());
+                }
+            }
+            Node::Dependency(dep) => {
+                activated.insert(dep);
+            }
+        }
+    }
+
+    let dependencies = manifest
+        .dependencies()
+        .filter(|(kind, _, _)| *kind != DependencyKind::Workspace)
+        .map(|(kind, name, dep)| (kind.section().to_string(), name, dep))
+        .chain(
+            manifest
+                .target_dependencies()
+                .map(|(platform, kind, name, dep)| (kind.target_section(platform), name, dep)),
+        );
+    let mut resolved = Vec::new();
+    for (section, name, dep) in dependencies {
+        let name = name.get_ref().as_str();
+        let optional = graph.optional.contains(name);
+        let enabled = !optional || activated.contains(name);
+        let detail = dep.detail();
+        let registry =
+            !dep.inherited() && detail.is_none_or(|d| d.path.is_none() && d.git.is_none());
+        let mut requested = detail
+            .and_then(|d| d.features.as_ref())
+            .into_iter()
+            .flat_map(|f| f.get_ref())
+            .map(|f| f.get_ref().as_str())
+            .collect::<BTreeSet<_>>();
+        if detail
+            .and_then(|d| d.default_features.as_ref())
+            .is_none_or(|d| *d.get_ref())
+        {
+            requested.insert("default");
+        }
+        requested.extend(
+            forwards
+                .iter()
+                .filter(|f| f.dep == name && (enabled || !f.weak))
+                .map(|f| f.feature),
+        );
+        resolved.push(ResolvedDependency {
+            name: name.to_string(),
+            package: dep.package().unwrap_or(name).to_string(),
+            section,
+            optional,
+            enabled,
+            req: registry.then(|| dep.req().to_string()),
+            requested: requested.into_iter().map(str::to_string).collect(),
+            version: None,
+            features: None,
+        });
+    }
+    Resolution {
+        features: enabled.into_iter().map(str::to_string).collect(),
+        dependencies: resolved,
+    }
+}
+
+/// Features of a dependency enabled by `requested`, given its feature map.
+///
+/// Values that enable optional dependencies of the dependency or their features are skipped.
+pub fn expand(requested: &[String], map: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    let mut enabled = BTreeSet::new();
+    let mut pending = requested.iter().map(String::as_str).collect::<Vec<_>>();
+    while let Some(feature) = pending.pop() {
+        if feature.starts_with("dep:") || feature.contains('/') {
+            continue;
+        }
+        // Without a `default` feature requesting it has no effect
+        if (map.contains_key(feature) || feature != "default") && enabled.insert(feature) {
+            pending.extend(map.get(feature).into_iter().flatten().map(String::as_str));
+        }
+    }
+    enabled.into_iter().map(str::to_string).collect()
+}
+
+impl Resolution {
+    /// Markdown list of the dependencies with their enabled features.
+    pub fn markdown(&self) -> String {
+        let features = if self.features.is_empty() {
+            "none".to_string()
+        } else {
+            quoted(&self.features)
+        };
+        let mut text = format!("**Enabled features:** {features}\n");
+        for dep in &self.dependencies {
+            let mut line = format!("\n- `{}`", dep.name);
+            if dep.section != "dependencies" {
+                line += &format!(" [{}]", dep.section);
+            }
+            if !dep.enabled {
+                text += &format!("{line} (optional, not enabled)");
+                continue;
+            }
+            if let Some(version) = &dep.version {
+                line += &format!(" `{version}`");
+            }
+            let features = dep.features.as_ref().unwrap_or(&dep.requested);
+            if !features.is_empty() {
+                line += &format!(": {}", quoted(features));
+            }
+            text += &line;
+        }
+        text
+    }
+}
+
+/// Names in backticks, separated by commas.
+pub fn quoted(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|n| format!("`{n}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Hovers of the feature keys and the optional dependency keys.
+pub fn hovers(manifest: &SpannedManifest, source: &Source) -> Vec<(lsp_types::Range, String)> {
+    let graph = FeatureGraph::new(manifest);
+    let features = manifest.features.keys().filter_map(|feature| {
+        let range = source.range(feature.span())?;
+        Some((range, graph.activations(feature.get_ref())))
+    });
+    let dependencies = manifest
+        .dependencies()
+        .map(|(_, name, _)| name)
+        .chain(manifest.target_dependencies().map(|(_, _, name, _)| name))
+        .filter_map(|name| {
+            let hover = graph.enablers(name.get_ref())?;
+            Some((source.range(name.span())?, hover))
+        });
+    features.chain(dependencies).collect()
+}
+
+/// Report the features that enable themselves through other features, which cargo rejects.
+pub fn lint(manifest: &SpannedManifest, source: &Source, config: &Config) -> Vec<Diagnostic> {
+    let graph = FeatureGraph::new(manifest);
+    let mut diagnostics = Vec::new();
+    for feature in manifest.features.keys() {
+        let Some(cycle) = graph.cycle(feature.get_ref()) else {
+            continue;
+        };
+        let Some(range) = source.range(feature.span()) else {
+            continue;
+        };
+        let cycle = cycle
+            .iter()
+            .map(|f| format!("`{f}`"))
+            .collect::<Vec<_>>()
+            .join(" → ");
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(config.severity.feature_cycle.into()),
+            code: Some(NumberOrString::String(FEATURE_CYCLE.into())),
+            source: Some(config.source(FEATURE_CYCLE)),
+            message: Message::new(Key::FeatureCycle)
+                .arg(format!("`{}`", feature.get_ref()))
+                .arg(cycle)
+                .render(config.language),
+            ..Default::default()
+        });
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest;
+    use crate::text::Encoding;
+
+    const TEXT: &str = r#"[dependencies]
+serde = { version = "1", optional = true }
+tokio = { version = "1", optional = true }
+rand = "0.8"
+
+[target.'cfg(unix)'.dependencies]
+nix = { version = "0.29", optional = true }
+
+[features]
+default = ["full"]
+full = ["json", "tokio", "rand", "nix/fs"]
+json = ["dep:serde", "missing"]
+std = ["serde?/std"]
+"#;
+
+    fn parse(text: &str) -> SpannedManifest {
+        let (manifest, None) = manifest::parse(text) else {
+            panic!("{text}");
+        };
+        manifest
+    }
+
+    #[test]
+    fn builds_the_activations() {
+        let manifest = parse(TEXT);
+        let graph = FeatureGraph::new(&manifest);
+        assert_eq!(graph.enabled_by(Node::Feature("full")), ["default"]);
+        // Implicit features and `dep/feature` values enable the dependencies
+        assert_eq!(graph.enabled_by(Node::Dependency("tokio")), ["full"]);
+        assert_eq!(graph.enabled_by(Node::Dependency("nix")), ["full"]);
+        // `dep?/feature` does not
+        assert_eq!(graph.enabled_by(Node::Dependency("serde")), ["json"]);
+        // Neither do unknown values and required dependencies
+        assert_eq!(
+            graph.edges["full"],
+            [
+                Node::Feature("json"),
+                Node::Dependency("nix"),
+                Node::Dependency("tokio")
+            ]
+        );
+        assert_eq!(graph.edges["json"], [Node::Dependency("serde")]);
+        assert!(graph.cycle("default").is_none());
+    }
+
+    #[test]
+    fn explicit_dependencies_have_no_implicit_feature() {
+        let text = "[dependencies]\nserde = { version = \"1\", optional = true }\n\n\
+            [features]\nserde = [\"dep:serde\"]\nderive = [\"serde\"]\n";
+        let manifest = parse(text);
+        let graph = FeatureGraph::new(&manifest);
+        assert_eq!(graph.enabled_by(Node::Dependency("serde")), ["serde"]);
+        // The value refers to the feature of the same name
+        assert_eq!(graph.edges["derive"], [Node::Feature("serde")]);
+    }
+
+    #[test]
+    fn renders_the_trees() {
+        let manifest = parse(TEXT);
+        let graph = FeatureGraph::new(&manifest);
+        assert_eq!(
+            graph.activations("default"),
+            "**Feature `default`** enables\n\n- `full`\n  - `json`\n    - `serde` (optional dependency)\n  \
+             - `nix` (optional dependency)\n  - `tokio` (optional dependency)"
+        );
+        assert_eq!(
+            graph.activations("std"),
+            "**Feature `std`** enables nothing else"
+        );
+        assert_eq!(
+            graph.enablers("serde").unwrap(),
+            "Optional, enabled by\n\n- `json`\n  - `full`\n    - `default`"
+        );
+        assert_eq!(graph.enablers("rand"), None);
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let text = "[features]\na = [\"b\"]\nb = [\"c\"]\nc = [\"a\", \"b\"]\nd = [\"a\"]\n";
+        let manifest = parse(text);
+        let graph = FeatureGraph::new(&manifest);
+        assert_eq!(graph.cycle("a").unwrap(), ["a", "b", "c", "a"]);
+        assert_eq!(graph.cycle("c").unwrap(), ["c", "b", "c"]);
+        assert_eq!(graph.cycle("d"), None);
+        // The trees stop at the cycle
+        assert_eq!(
+            graph.activations("d"),
+            "**Feature `d`** enables\n\n- `a`\n  - `b`\n    - `c`\n      - `a` (cycle)\n      - `b` (cycle)"
+        );
+
+        let diagnostics = lint(
+            &manifest,
+            &Source::new(text, Encoding::Utf8),
+            &Config::default(),
+        );
+        let lines = diagnostics
+            .iter()
+            .map(|d| d.range.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [1, 2, 3]);
+        assert_eq!(
+            diagnostics[0].message,
+            "The feature `a` enables itself: `a` → `b` → `c` → `a`"
+        );
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String(FEATURE_CYCLE.into()))
+        );
+    }
+
+    #[test]
+    fn hovers_features_and_optional_dependencies() {
+        let hovers = hovers(&parse(TEXT), &Source::new(TEXT, Encoding::Utf8));
+        let lines = hovers
+            .iter()
+            .map(|(range, _)| (range.start.line, range.start.character))
+            .collect::<Vec<_>>();
+        // The features in order, then serde, tokio, and nix
+        assert_eq!(
+            lines,
+            [(9, 0), (10, 0), (11, 0), (12, 0), (1, 0), (2, 0), (6, 0)]
+        );
+        assert_eq!(
+            hovers[5].1,
+            "Optional, enabled by\n\n- `full`\n  - `default`"
+        );
+    }
+
+    const RESOLVED: &str = r#"[dependencies]
+serde = { version = "1", optional = true, default-features = false }
+tokio = { version = "1", optional = true }
+log = "0.4"
+rand = { version = "0.8", optional = true }
+
+[target.'cfg(unix)'.dependencies]
+nix = { version = "0.29", optional = true }
+
+[features]
+default = ["std"]
+std = ["serde?/std", "log/std", "nix?/fs"]
+derive = ["dep:serde", "serde/derive"]
+async = ["tokio", "nix"]
+"#;
+
+    /// Selected features, default features, and the enabled features and dependencies,
+    /// the dependencies with their features ordered by section and name.
+    type Case = (
+        &'static [&'static str],
+        bool,
+        &'static [&'static str],
+        &'static [&'static str],
+    );
+
+    #[test]
+    fn resolves_feature_selections() {
+        let manifest = parse(RESOLVED);
+        let cases: &[Case] = &[
+            (
+                &[],
+                true,
+                &["default", "std"],
+                // Weak values do not enable serde
+                &["log: default std"],
+            ),
+            (
+                &["derive"],
+                true,
+                &["default", "derive", "std"],
+                // but forward their features once it is enabled
+                &["log: default std", "serde: derive std"],
+            ),
+            (
+                &["derive"],
+                false,
+                &["derive"],
+                &["log: default", "serde: derive"],
+            ),
+            // Optional dependencies used with `dep:` have no implicit feature
+            (&["serde"], false, &[], &["log: default"]),
+            (&["rand"], false, &[], &["log: default", "rand: default"]),
+            (&["serde?/derive"], false, &[], &["log: default"]),
+            (
+                &["async", "tokio/full"],
+                true,
+                &["async", "default", "std"],
+                &["log: default std", "tokio: default full", "nix: default fs"],
+            ),
+        ];
+        for (selected, default_features, features, dependencies) in cases {
+            let selected = selected.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+            let resolution = resolve(&manifest, &selected, *default_features);
+            assert_eq!(resolution.features, *features, "{selected:?}");
+            let enabled = resolution
+                .dependencies
+                .iter()
+                .filter(|d| d.enabled)
+                .map(|d| format!("{}: {}", d.name, d.requested.join(" ")))
+                .collect::<Vec<_>>();
+            assert_eq!(enabled, *dependencies, "{selected:?}");
+        }
+
+        let resolution = resolve(&manifest, &[], false);
+        let nix = &resolution.dependencies[4];
+        assert_eq!(nix.section, "target.'cfg(unix)'.dependencies");
+        assert_eq!(nix.req.as_deref(), Some("0.29"));
+        assert!(nix.optional && !nix.enabled);
+    }
+
+    #[test]
+    fn expands_the_features_of_dependencies() {
+        let map = BTreeMap::from(
+            [
+                ("default", &["std"][..]),
+                ("std", &["alloc", "dep:libc"]),
+                ("alloc", &[]),
+                ("derive", &["serde_derive?/std", "serde_derive"]),
+                ("serde_derive", &["dep:serde_derive"]),
+            ]
+            .map(|(k, v)| (k.to_string(), v.iter().map(|v| v.to_string()).collect())),
+        );
+        let expand = |requested: &[&str], map| {
+            let requested = requested.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+            expand(&requested, map)
+        };
+        assert_eq!(
+            expand(&["default", "derive"], &map),
+            ["alloc", "default", "derive", "serde_derive", "std"]
+        );
+        assert_eq!(expand(&["alloc"], &map), ["alloc"]);
+        // A missing `default` feature is not enabled, other unknown features are kept
+        assert_eq!(expand(&["default", "extra"], &BTreeMap::new()), ["extra"]);
+    }
+
+    #[test]
+    fn renders_the_resolution() {
+        let manifest = parse(RESOLVED);
+        let mut resolution = resolve(&manifest, &["derive".to_string()], false);
+        resolution.dependencies[2].version = Some("1.0.215".into());
+        resolution.dependencies[2].features = Some(vec!["derive".into(), "std".into()]);
+        assert_eq!(
+            resolution.markdown(),
+            "**Enabled features:** `derive`\n\n\
+             - `log`: `default`\n\
+             - `rand` (optional, not enabled)\n\
+             - `serde` `1.0.215`: `derive`, `std`\n\
+             - `tokio` (optional, not enabled)\n\
+             - `nix` [target.'cfg(unix)'.dependencies] (optional, not enabled)"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Human readable numbers and dates for hovers and completions.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Abbreviate large numbers, e.g. `12.4M`.
+pub fn count(n: u64) -> String {
+    const UNITS: [(u64, &str); 3] = [(1_000_000_000, "B"), (1_000_000, "M"), (1_000, "K")];
+    for (size, unit) in UNITS {
+        if n >= size {
+            let value = n as f64 / size as f64;
+            return if value < 100.0 {
+                format!("{:.1}{unit}", (value * 10.0).floor() / 10.0)
+            } else {
+                format!("{}{unit}", value.floor())
+            };
+        }
+    }
+    n.to_string()
+}
+
+/// Relative date with the absolute date, e.g. `8 days ago (2024-11-03)`.
+///
+/// `date` starts with `YYYY-MM-DD` (RFC 3339), `today` is in days since the Unix epoch.
+pub fn relative_date(date: &str, today: i64) -> Option<String> {
+    let day = date.get(..10)?;
+    let days = today - days_since_epoch(day)?;
+    let relative = match days {
+        ..=0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        2..60 => format!("{days} days ago"),
+        60..730 => format!("{} months ago", days / 30),
+        _ => format!("{} years ago", days / 365),
+    };
+    Some(format!("{relative} ({day})"))
+}
+
+/// Time since an event, e.g. `12 minutes ago`.
+pub fn age(seconds: i64) -> String {
+    let plural = |n: i64, unit: &str| match n {
+        1 => format!("1 {unit} ago"),
+        _ => format!("{n} {unit}s ago"),
+    };
+    match seconds {
+        ..60 => "less than a minute ago".to_string(),
+        60..3600 => plural(seconds / 60, "minute"),
+        3600..86400 => plural(seconds / 3600, "hour"),
+        _ => plural(seconds / 86400, "day"),
+    }
+}
+
+/// Rounded duration of `seconds` in the largest unit that fits, e.g. `4 h`.
+pub fn duration(seconds: u64) -> String {
+    match seconds {
+        ..60 => format!("{seconds} s"),
+        60..3600 => format!("{} min", (seconds + 30) / 60),
+        3600..86400 => format!("{} h", (seconds + 1800) / 3600),
+        _ => format!("{} d", (seconds + 43200) / 86400),
+    }
+}
+
+/// ISO 8601 time in UTC of seconds since the Unix epoch, e.g. `2024-11-03T08:00:00Z`.
+pub fn timestamp(seconds: i64) -> String {
+    let (days, secs) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    // Civil from days, the inverse of `days_since_epoch`
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    format!(
+        "{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// The current time in seconds since the Unix epoch.
+pub fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// The current day in days since the Unix epoch.
+pub fn today() -> i64 {
+    unix_time() / 86400
+}
+
+/// Days since the Unix epoch of a `YYYY-MM-DD` date.
+pub fn days_since_epoch(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    // Days from civil, shifting the year to start in March
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts() {
+        let cases = [
+            (0, "0"),
+            (999, "999"),
+            (1_000, "1.0K"),
+            (1_999, "1.9K"),
+            (12_400_000, "12.4M"),
+            (99_990_000, "99.9M"),
+            (123_456_789, "123M"),
+            (4_200_000_000, "4.2B"),
+        ];
+        for (n, expected) in cases {
+            assert_eq!(count(n), expected, "{n}");
+        }
+    }
+
+    #[test]
+    fn days() {
+        assert_eq!(days_since_epoch("1970-01-01"), Some(0));
+        assert_eq!(days_since_epoch("2000-03-01"), Some(11017));
+        assert_eq!(days_since_epoch("2024-02-29"), Some(19782));
+        assert_eq!(days_since_epoch("2024-13-01"), None);
+        assert_eq!(days_since_epoch("2024-01"), None);
+        assert_eq!(days_since_epoch("yesterday"), None);
+    }
+
+    #[test]
+    fn relative_dates() {
+        let today = days_since_epoch("2024-11-11").unwrap();
+        let cases = [
+            ("2024-11-11T08:00:00Z", "today (2024-11-11)"),
+            ("2024-11-12T08:00:00Z", "today (2024-11-12)"),
+            ("2024-11-10T08:00:00Z", "yesterday (2024-11-10)"),
+            ("2024-11-03T08:00:00Z", "8 days ago (2024-11-03)"),
+            ("2024-06-01T08:00:00Z", "5 months ago (2024-06-01)"),
+            ("2021-01-01T08:00:00Z", "3 years ago (2021-01-01)"),
+        ];
+        for (date, expected) in cases {
+            assert_eq!(relative_date(date, today).as_deref(), Some(expected));
+        }
+        assert_eq!(relative_date("2024-11", today), None);
+    }
+
+    #[test]
+    fn ages() {
+        let cases = [
+            (0, "less than a minute ago"),
+            (59, "less than a minute ago"),
+            (60, "1 minute ago"),
+            (12 * 60 + 30, "12 minutes ago"),
+            (3600, "1 hour ago"),
+            (5 * 3600 + 59, "5 hours ago"),
+            (86400, "1 day ago"),
+            (3 * 86400, "3 days ago"),
+        ];
+        for (seconds, expected) in cases {
+            assert_eq!(age(seconds), expected, "{seconds}");
+        }
+    }
+
+    #[test]
+    fn durations() {
+        let cases = [
+            (0, "0 s"),
+            (59, "59 s"),
+            (60, "1 min"),
+            (15 * 60, "15 min"),
+            (3599, "60 min"),
+            (3600, "1 h"),
+            (4 * 3600 + 1799, "4 h"),
+            (4 * 3600 + 1800, "5 h"),
+            (86400, "1 d"),
+            (3 * 86400 + 50000, "4 d"),
+        ];
+        for (seconds, expected) in cases {
+            assert_eq!(duration(seconds), expected, "{seconds}");
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        let day = days_since_epoch("2024-11-03").unwrap();
+        assert_eq!(
+            timestamp(day * 86400 + 8 * 3600 + 61),
+            "2024-11-03T08:01:01Z"
+        );
+        for date in ["2000-03-01", "2024-02-29", "2024-12-31", "1969-12-31"] {
+            let days = days_since_epoch(date).unwrap();
+            assert_eq!(timestamp(days * 86400), format!("{date}T00:00:00Z"));
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::Uri;
+
+use crate::manifest::{SpannedManifest, SpannedWorkspace};
+
+/// Command exporting the dependency graph, arguments are [`ExportArgs`].
+pub const EXPORT_GRAPH: &str = "crates-io.exportGraph";
+
+/// Export the packages of the manifest at `uri`, or of its whole workspace.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportArgs {
+    pub uri: Uri,
+    #[serde(default)]
+    pub workspace: bool,
+    #[serde(default)]
+    pub format: Format,
+    /// Also write the graph to this file
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Dot,
+    Json,
+    Mermaid,
+}
+
+/// Direct dependencies declared by local packages.
+#[derive(Debug, Default, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Node {
+    pub name: String,
+    /// Package of the workspace, otherwise a crate from the registry
+    pub local: bool,
+    /// Newest known version of external crates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Edge {
+    /// Index of the depending node
+    pub from: usize,
+    pub to: usize,
+    /// `normal`, `dev`, or `build`
+    pub kind: &'static str,
+    pub req: String,
+    /// `cfg` or target triple of platform specific dependencies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+impl Graph {
+    /// Add a local package with its declared dependencies.
+    ///
+    /// Inherited requirements are looked up in the `workspace`.
+    pub fn add_package(
+        &mut self,
+        name: &str,
+        manifest: &SpannedManifest,
+        workspace: Option<&SpannedWorkspace>,
+    ) {
+        let from = self.node(name);
+        self.nodes[from].local = true;
+        let deps = manifest
+            .dependencies()
+            .map(|(kind, name, dep)| (None, kind, name, dep))
+            .chain(
+                manifest
+                    .target_dependencies()
+                    .map(|(target, kind, name, dep)| (Some(target), kind, name, dep)),
+            );
+        for (target, kind, name, dep) in deps {
+            let inherited = workspace
+                .filter(|_| dep.inherited())
+                .and_then(|w| w.dependencies.get(name.get_ref().as_str()));
+            let dep = inherited.unwrap_or(dep);
+            let to = self.node(dep.package().unwrap_or(name.get_ref()));
+            self.edges.push(Edge {
+                from,
+                to,
+                kind: kind.name(),
+                req: dep.req().to_string(),
+                target: target.map(str::to_string),
+            });
+        }
+    }
+
+    /// Index of the node, which is added if necessary.
+    fn node(&mut self, name: &str) -> usize {
+        if let Some(i) = self.nodes.iter().position(|n| n.name == name) {
+            return i;
+        }
+        self.nodes.push(Node {
+            name: name.to_string(),
+            local: false,
+            latest: None,
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Dot => dot(self),
+            Format::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            Format::Mermaid => mermaid(self),
+        }
+    }
+}
+
+/// Label of an edge, like `1.0 (dev, cfg(unix))`.
+fn edge_label(edge: &Edge) -> String {
+    let notes = [
+        (edge.kind != "normal").then_some(edge.kind),
+        edge.target.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    if notes.is_empty() {
+        edge.req.clone()
+    } else {
+        format!("{} ({})", edge.req, notes.join(", "))
+    }
+}
+
+fn node_label(node: &Node) -> String {
+    match &node.latest {
+        Some(latest) => format!("{}\n{latest}", node.name),
+        None => node.name.clone(),
+    }
+}
+
+/// Graphviz `digraph`, local packages are boxes.
+fn dot(graph: &Graph) -> String {
+    // Quoted ids allow names with hyphens
+    let quote = |s: &str| {
+        format!(
+            "\"{}\"",
+            s.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        )
+    };
+    let mut out = String::from("digraph dependencies {\n");
+    for node in &graph.nodes {
+        let shape = if node.local { "box" } else { "ellipse" };
+        out += &format!(
+            "    {} [label={}, shape={shape}];\n",
+            quote(&node.name),
+            quote(&node_label(node))
+        );
+    }
+    for edge in &graph.edges {
+        let style = match edge.kind {
+            "dev" => "dashed",
+            "build" => "dotted",
+            _ => "solid",
+        };
+        out += &format!(
+            "    {} -> {} [label={}, style={style}];\n",
+            quote(&graph.nodes[edge.from].name),
+            quote(&graph.nodes[edge.to].name),
+            quote(&edge_label(edge))
+        );
+    }
+    out += "}\n";
+    out
+}
+
+/// Mermaid flowchart, local packages are rectangles.
+fn mermaid(graph: &Graph) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "#quot;").replace('\n', "<br>"));
+    let mut out = String::from("graph LR\n");
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let label = quote(&node_label(node));
+        if node.local {
+            out += &format!("    n{i}[{label}]\n");
+        } else {
+            out += &format!("    n{i}({label})\n");
+        }
+    }
+    for edge in &graph.edges {
+        let arrow = if edge.kind == "normal" { "-->" } else { "-.->" };
+        out += &format!(
+            "    n{} {arrow}|{}| n{}\n",
+            edge.from,
+            quote(&edge_label(edge)),
+            edge.to
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> Graph {
+        let root = "[workspace.dependencies]\ntokio = \"1.45\"\n";
+        let root = toml::from_str::<SpannedManifest>(root).unwrap();
+        let member = r#"
+[package]
+name = "my-app"
+
+[dependencies]
+serde = "1"
+my-lib = { path = "../my-lib", version = "0.1" }
+
+[dev-dependencies]
+tokio = { workspace = true }
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+"#;
+        let member = toml::from_str::<SpannedManifest>(member).unwrap();
+        let mut graph = Graph::default();
+        graph.add_package("my-app", &member, root.workspace.as_ref());
+        graph.add_package("my-lib", &SpannedManifest::default(), None);
+        let serde = graph.node("serde");
+        graph.nodes[serde].latest = Some("1.0.215".into());
+        graph
+    }
+
+    #[test]
+    fn renders_dot() {
+        assert_eq!(
+            graph().render(Format::Dot),
+            r#"digraph dependencies {
+    "my-app" [label="my-app", shape=box];
+    "my-lib" [label="my-lib", shape=box];
+    "serde" [label="serde\n1.0.215", shape=ellipse];
+    "tokio" [label="tokio", shape=ellipse];
+    "libc" [label="libc", shape=ellipse];
+    "my-app" -> "my-lib" [label="0.1", style=solid];
+    "my-app" -> "serde" [label="1", style=solid];
+    "my-app" -> "tokio" [label="1.45 (dev)", style=dashed];
+    "my-app" -> "libc" [label="0.2 (cfg(unix))", style=solid];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn escapes_dot_ids() {
+        let mut graph = Graph::default();
+        graph.node("a\"b\\c");
+        assert_eq!(
+            graph.render(Format::Dot),
+            "digraph dependencies {\n    \"a\\\"b\\\\c\" [label=\"a\\\"b\\\\c\", shape=ellipse];\n}\n"
+        );
+    }
+
+    #[test]
+    fn renders_mermaid() {
+        assert_eq!(
+            graph().render(Format::Mermaid),
+            r#"graph LR
+    n0["my-app"]
+    n1["my-lib"]
+    n2("serde<br>1.0.215")
+    n3("tokio")
+    n4("libc")
+    n0 -->|"0.1"| n1
+    n0 -->|"1"| n2
+    n0 -.->|"1.45 (dev)"| n3
+    n0 -->|"0.2 (cfg(unix))"| n4
+"#
+        );
+    }
+
+    #[test]
+    fn renders_json() {
+        let json = serde_json::from_str::<serde_json::Value>(&graph().render(Format::Json));
+        assert_eq!(
+            json.unwrap(),
+            serde_json::json!({
+                "nodes": [
+                    { "name": "my-app", "local": true },
+                    { "name": "my-lib", "local": true },
+                    { "name": "serde", "local": false, "latest": "1.0.215" },
+                    { "name": "tokio", "local": false },
+                    { "name": "libc", "local": false },
+                ],
+                "edges": [
+                    { "from": 0, "to": 1, "kind": "normal", "req": "0.1" },
+                    { "from": 0, "to": 2, "kind": "normal", "req": "1" },
+                    { "from": 0, "to": 3, "kind": "dev", "req": "1.45" },
+                    { "from": 0, "to": 4, "kind": "normal", "req": "0.2", "target": "cfg(unix)" },
+                ],
+            })
+        );
+    }
+}
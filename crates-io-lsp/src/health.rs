@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Reads of files beside a document that are disabled in a workspace folder once they failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Probe {
+    /// Manifests of the parent directories, for the workspace root
+    RootManifest,
+    /// The `Cargo.lock` of the package or workspace
+    Lockfile,
+    /// The nearest `.crates-io-lsp.toml` and the directories up to it
+    ConfigFile,
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::RootManifest => "workspace root resolution",
+            Self::Lockfile => "Cargo.lock hints",
+            Self::ConfigFile => "configuration files",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    /// Failed reading `path`, nothing is read until it changes
+    Disabled { path: PathBuf },
+    /// The path changed since, the next read decides whether it works again
+    Probation { path: PathBuf },
+}
+
+/// Health of the file system of each workspace folder, like that of a read-only snapshot or a
+/// remote worktree where the files of the documents are unreadable.
+///
+/// The first IO failure of a probe in a folder disables it there, so that unreadable files are
+/// neither read nor reported on every analysis. A change of the failed path or a refresh puts
+/// it on probation, the next read enables it again or disables it without another report.
+#[derive(Debug, Default)]
+pub struct FsHealth {
+    states: HashMap<(PathBuf, Probe), State>,
+}
+
+impl FsHealth {
+    /// Whether `probe` may read files in `folder`.
+    pub fn allows(&self, folder: &Path, probe: Probe) -> bool {
+        !matches!(
+            self.states.get(&(folder.to_path_buf(), probe)),
+            Some(State::Disabled { .. })
+        )
+    }
+
+    /// Record that `probe` failed reading `path`, returns whether it was enabled until now.
+    pub fn failed(&mut self, folder: &Path, probe: Probe, path: &Path) -> bool {
+        let key = (folder.to_path_buf(), probe);
+        let state = State::Disabled {
+            path: path.to_path_buf(),
+        };
+        match self.states.insert(key, state) {
+            None => true,
+            Some(State::Probation { .. } | State::Disabled { .. }) => false,
+        }
+    }
+
+    /// Record that `probe` read its files, returns whether it was on probation.
+    pub fn succeeded(&mut self, folder: &Path, probe: Probe) -> bool {
+        let key = (folder.to_path_buf(), probe);
+        match self.states.get(&key) {
+            Some(State::Probation { .. }) => {
+                self.states.remove(&key);
+                true
+            }
+            Some(State::Disabled { .. }) | None => false,
+        }
+    }
+
+    /// Put the probes that failed reading `path` on probation, returns whether there were any.
+    pub fn changed(&mut self, path: &Path) -> bool {
+        let mut any = false;
+        for state in self.states.values_mut() {
+            if let State::Disabled { path: failed } = state {
+                if failed == path {
+                    *state = State::Probation {
+                        path: failed.clone(),
+                    };
+                    any = true;
+                }
+            }
+        }
+        any
+    }
+
+    /// Put all disabled probes on probation, returns their number.
+    pub fn retry_all(&mut self) -> usize {
+        let mut retried = 0;
+        for state in self.states.values_mut() {
+            if let State::Disabled { path } = state {
+                *state = State::Probation { path: path.clone() };
+                retried += 1;
+            }
+        }
+        retried
+    }
+}
+
+/// The workspace folder of `path`, or its directory outside of the workspace folders.
+pub fn folder<'a>(roots: &'a [PathBuf], path: &'a Path) -> &'a Path {
+    roots
+        .iter()
+        .filter(|root| path.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .map(|root| root.as_path())
+        .or(path.parent())
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOLDER: &str = "/snapshot";
+    const LOCK: &str = "/snapshot/Cargo.lock";
+
+    fn health() -> FsHealth {
+        FsHealth::default()
+    }
+
+    #[test]
+    fn disables_after_the_first_failure() {
+        let mut health = health();
+        let folder = Path::new(FOLDER);
+        assert!(health.allows(folder, Probe::Lockfile));
+        assert!(health.failed(folder, Probe::Lockfile, Path::new(LOCK)));
+        assert!(!health.allows(folder, Probe::Lockfile));
+        // Reported once
+        assert!(!health.failed(folder, Probe::Lockfile, Path::new(LOCK)));
+        // Only this probe in this folder
+        assert!(health.allows(folder, Probe::RootManifest));
+        assert!(health.allows(folder, Probe::ConfigFile));
+        assert!(health.allows(Path::new("/other"), Probe::Lockfile));
+        // Successes of disabled probes do not happen, but do not enable them either
+        assert!(!health.succeeded(folder, Probe::Lockfile));
+        assert!(!health.allows(folder, Probe::Lockfile));
+    }
+
+    #[test]
+    fn enables_after_a_successful_probation() {
+        let mut health = health();
+        let folder = Path::new(FOLDER);
+        health.failed(folder, Probe::Lockfile, Path::new(LOCK));
+        // Other paths do not matter
+        assert!(!health.changed(Path::new("/snapshot/Cargo.toml")));
+        assert!(!health.allows(folder, Probe::Lockfile));
+
+        assert!(health.changed(Path::new(LOCK)));
+        assert!(health.allows(folder, Probe::Lockfile));
+        assert!(health.succeeded(folder, Probe::Lockfile));
+        assert!(health.allows(folder, Probe::Lockfile));
+        // Enabled again, a new failure is reported again
+        assert!(!health.succeeded(folder, Probe::Lockfile));
+        assert!(health.failed(folder, Probe::Lockfile, Path::new(LOCK)));
+    }
+
+    #[test]
+    fn failed_probations_disable_silently() {
+        let mut health = health();
+        let folder = Path::new(FOLDER);
+        health.failed(folder, Probe::Lockfile, Path::new(LOCK));
+        health.changed(Path::new(LOCK));
+        assert!(!health.failed(folder, Probe::Lockfile, Path::new(LOCK)));
+        assert!(!health.allows(folder, Probe::Lockfile));
+        // Probations are repeated on every change
+        assert!(health.changed(Path::new(LOCK)));
+        assert!(!health.changed(Path::new(LOCK)));
+        assert!(health.succeeded(folder, Probe::Lockfile));
+    }
+
+    #[test]
+    fn retries_everything_on_refresh() {
+        let mut health = health();
+        let (a, b) = (Path::new("/a"), Path::new("/b"));
+        health.failed(a, Probe::Lockfile, Path::new("/a/Cargo.lock"));
+        health.failed(a, Probe::ConfigFile, Path::new("/a/.crates-io-lsp.toml"));
+        health.failed(b, Probe::RootManifest, Path::new("/Cargo.toml"));
+        assert_eq!(health.retry_all(), 3);
+        assert_eq!(health.retry_all(), 0);
+        for (folder, probe) in [
+            (a, Probe::Lockfile),
+            (a, Probe::ConfigFile),
+            (b, Probe::RootManifest),
+        ] {
+            assert!(health.allows(folder, probe), "{probe}");
+        }
+        assert!(health.succeeded(a, Probe::Lockfile));
+        assert!(!health.failed(a, Probe::ConfigFile, Path::new("/a/.crates-io-lsp.toml")));
+        assert!(health.allows(a, Probe::Lockfile));
+        assert!(!health.allows(a, Probe::ConfigFile));
+    }
+
+    #[test]
+    fn folders_of_paths() {
+        let roots = [PathBuf::from("/ws"), PathBuf::from("/ws/nested")];
+        let folder = |path: &str| folder(&roots, Path::new(path)).to_path_buf();
+        assert_eq!(folder("/ws/app/Cargo.toml"), Path::new("/ws"));
+        assert_eq!(folder("/ws/nested/Cargo.toml"), Path::new("/ws/nested"));
+        assert_eq!(
+            folder("/elsewhere/app/Cargo.toml"),
+            Path::new("/elsewhere/app")
+        );
+        assert_eq!(folder("/"), Path::new("/"));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::analysis::AnalyzedDependency;
+use crate::api::CrateMetadata;
+use semver::{Version, VersionReq};
+
+use crate::format;
+use crate::lockfile;
+use crate::version::{self, Classification, Release};
+
+/// Markdown hover of a dependency, enriched with the metadata if available.
+pub fn render(dep: &AnalyzedDependency, metadata: Option<&CrateMetadata>) -> String {
+    let status = match &dep.classification {
+        Some(Classification::Any) => "matches any version".to_string(),
+        Some(Classification::Latest) => "latest version".to_string(),
+        Some(Classification::OutdatedCompatible { compatible, .. }) => {
+            format!("compatible update to `{compatible}`")
+        }
+        Some(Classification::OutdatedIncompatible { latest, .. }) => {
+            format!("`{latest}` requires a manifest change")
+        }
+        Some(Classification::Unknown) => "unknown version".to_string(),
+        None => "versions could not be fetched".to_string(),
+    };
+    let text = format!("**{}** `{}`: {status}", dep.package, dep.req);
+    match metadata {
+        Some(metadata) => with_metadata(text, metadata),
+        None => text,
+    }
+}
+
+/// Markdown hover of a package that is not a dependency of the document.
+pub fn render_package(name: &str, metadata: Option<&CrateMetadata>) -> String {
+    let text = format!("**{name}**");
+    match metadata {
+        Some(metadata) => with_metadata(text, metadata),
+        None => text,
+    }
+}
+
+/// Markdown hover of a requirement with the range of the published `versions` it matches.
+pub fn render_requirement(req: &str, versions: &[Release]) -> String {
+    let parsed = match VersionReq::parse(req) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            return format!(
+                "**Invalid requirement** `{req}`: {err}\n\n\
+                Requirements look like `1.2.3`, `~1.2`, `=1.2.3` or `>=1.2, <1.5`, see the \
+                [Cargo reference](https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html)."
+            );
+        }
+    };
+    let mut text = format!("`{req}`: {}", version::describe(&parsed));
+    match version::major_only(&parsed) {
+        Some(0) => text += ", spanning `>=0.0.0, <1.0.0` whose minors are mutually incompatible",
+        Some(major) => {
+            text += &format!(
+                ", spanning `>={major}.0.0, <{}.0.0`",
+                major.saturating_add(1)
+            );
+        }
+        None => {}
+    }
+    if parsed.comparators.len() > 1 {
+        text += "\n";
+        for comparator in &parsed.comparators {
+            text += &format!(
+                "\n- `{comparator}`: {}",
+                version::describe_comparator(comparator)
+            );
+        }
+    }
+    let matched = version::available(versions)
+        .filter(|v| parsed.matches(v))
+        .collect::<Vec<_>>();
+    let verbatim = |v: &&semver::Version| version::verbatim(versions, v);
+    match (
+        matched.iter().min().map(verbatim),
+        matched.iter().max().map(verbatim),
+    ) {
+        (Some(min), Some(max)) if min == max => {
+            text += &format!("\n\nMatches only `{min}` of the published versions");
+        }
+        (Some(min), Some(max)) => {
+            text += &format!(
+                "\n\nMatches {} published versions, from `{min}` to `{max}`",
+                matched.len()
+            );
+        }
+        _ if !versions.is_empty() => text += "\n\nNo published version matches",
+        _ => {}
+    }
+    text
+}
+
+fn with_metadata(mut text: String, metadata: &CrateMetadata) -> String {
+    if let Some(description) = &metadata.description {
+        text += &format!("\n\n{}", description.trim());
+    }
+    text += &format!("\n\n- {} downloads", format::count(metadata.downloads));
+    if let Some(recent) = metadata.recent_downloads {
+        text += &format!(" ({} recent)", format::count(recent));
+    }
+    text += &format!("\n- {} versions", metadata.versions);
+    if let Some(released) = metadata
+        .released
+        .as_deref()
+        .and_then(|r| format::relative_date(r, format::today()))
+    {
+        text += &format!(", latest released {released}");
+    }
+    if !metadata.owners.is_empty() {
+        text += &format!("\n- Owners: {}", metadata.owners.join(", "));
+    }
+    text
+}
+
+/// Paragraph of the locked `version` with its abbreviated checksum,
+/// followed by the full one in a code block to copy it.
+pub fn checksum(version: &Version, checksum: &str) -> String {
+    format!(
+        "\n\nLocked `{version}`, checksum `{}`\n\n```\n{checksum}\n```",
+        lockfile::abbreviate(checksum)
+    )
+}
+
+/// Muted last paragraph of a hover, like `_registry data from 12 minutes ago_`.
+pub fn footer(note: &str) -> String {
+    format!("\n\n_{note}_")
+}
+
+/// Hover for clients without markdown support.
+///
+/// Emphasis and code spans lose their markers, links keep their target in parentheses.
+pub fn plain_text(markdown: &str) -> String {
+    // Footers are whole lines in underscores, which crate names never are
+    let text = markdown
+        .split('\n')
+        .map(|line| {
+            line.strip_prefix('_')
+                .and_then(|l| l.strip_suffix('_'))
+                .filter(|l| !l.is_empty())
+                .unwrap_or(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = text.replace("**", "").replace('`', "");
+    let mut plain = String::new();
+    let mut rest = text.as_str();
+    while let Some(at) = rest.find("](") {
+        // The link text may contain balanced brackets
+        let mut depth = 0;
+        let start = rest[..at].rfind(|c| {
+            match c {
+                ']' => depth += 1,
+                '[' if depth == 0 => return true,
+                '[' => depth -= 1,
+                _ => {}
+            }
+            false
+        });
+        let (Some(start), Some(end)) = (start, rest[at..].find(')')) else {
+            plain += &rest[..at + 2];
+            rest = &rest[at + 2..];
+            continue;
+        };
+        let end = at + end;
+        plain += &format!(
+            "{}{} ({})",
+            &rest[..start],
+            &rest[start + 1..at],
+            &rest[at + 2..end]
+        );
+        rest = &rest[end + 1..];
+    }
+    plain + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::DependencyKind;
+
+    fn dependency() -> AnalyzedDependency {
+        AnalyzedDependency {
+            package: "serde".into(),
+            kind: DependencyKind::Normal,
+            target: None,
+            name_range: Default::default(),
+            req: "1.0.188".into(),
+            req_range: None,
+            git: false,
+            classification: Some(Classification::OutdatedCompatible {
+                compatible: Version::new(1, 0, 215),
+                latest: Version::new(1, 0, 215),
+            }),
+        }
+    }
+
+    #[test]
+    fn versions_only() {
+        assert_eq!(
+            render(&dependency(), None),
+            "**serde** `1.0.188`: compatible update to `1.0.215`"
+        );
+        let unknown = AnalyzedDependency {
+            classification: None,
+            ..dependency()
+        };
+        assert_eq!(
+            render(&unknown, None),
+            "**serde** `1.0.188`: versions could not be fetched"
+        );
+    }
+
+    #[test]
+    fn with_metadata() {
+        let metadata = CrateMetadata {
+            description: Some("A serialization framework\n".into()),
+            downloads: 512_300_000,
+            recent_downloads: Some(64_000_000),
+            versions: 300,
+            released: None,
+            release_gap: None,
+            repository: None,
+            owners: vec!["dtolnay".into(), "github:serde-rs:publish".into()],
+        };
+        assert_eq!(
+            render(&dependency(), Some(&metadata)),
+            "**serde** `1.0.188`: compatible update to `1.0.215`\n\n\
+            A serialization framework\n\n\
+            - 512M downloads (64.0M recent)\n\
+            - 300 versions\n\
+            - Owners: dtolnay, github:serde-rs:publish"
+        );
+
+        let released = CrateMetadata {
+            description: None,
+            recent_downloads: None,
+            released: Some("2001-01-01T00:00:00Z".into()),
+            owners: Vec::new(),
+            ..metadata
+        };
+        let text = render(&dependency(), Some(&released));
+        assert!(text.contains("- 300 versions, latest released "), "{text}");
+        assert!(text.ends_with(" years ago (2001-01-01)"), "{text}");
+    }
+
+    #[test]
+    fn abbreviates_the_locked_checksum() {
+        let full = "af150ab688ff2122fcef229be89cb50dd66af9e01a4ff320cc137eecc9bacc38";
+        assert_eq!(
+            checksum(&Version::new(1, 0, 9), full),
+            format!("\n\nLocked `1.0.9`, checksum `af150ab688ff…`\n\n```\n{full}\n```")
+        );
+    }
+
+    #[test]
+    fn explains_requirements() {
+        let releases = |versions: &[&str]| {
+            versions
+                .iter()
+                .filter_map(|v| Release::parse(v, false))
+                .collect::<Vec<_>>()
+        };
+        let versions = releases(&["1.1.0", "1.2.0", "1.4.9", "1.5.0", "2.0.0"]);
+        assert_eq!(
+            render_requirement(">=1.2, <1.5", &versions),
+            "`>=1.2, <1.5`: at least 1.2.0, and older than 1.5.0\n\n\
+             - `>=1.2`: at least 1.2.0\n\
+             - `<1.5`: older than 1.5.0\n\n\
+             Matches 2 published versions, from `1.2.0` to `1.4.9`"
+        );
+        assert_eq!(
+            render_requirement("=2.0.0", &versions),
+            "`=2.0.0`: exactly 2.0.0\n\nMatches only `2.0.0` of the published versions"
+        );
+        assert_eq!(
+            render_requirement("3", &versions),
+            "`3`: any 3.x version, spanning `>=3.0.0, <4.0.0`\n\nNo published version matches"
+        );
+        let zero = releases(&["0.7.3", "0.8.5", "1.0.0"]);
+        assert_eq!(
+            render_requirement("0", &zero),
+            "`0`: any 0.x version, spanning `>=0.0.0, <1.0.0` whose minors are mutually incompatible\n\n\
+             Matches 2 published versions, from `0.7.3` to `0.8.5`"
+        );
+        assert_eq!(
+            render_requirement("0.8", &zero),
+            "`0.8`: any 0.8.x version\n\nMatches only `0.8.5` of the published versions"
+        );
+        // Versions that are not strict semver as published
+        let odd = releases(&["0.1.0", "0.1.01.3", "0.1.2+a+b"]);
+        assert_eq!(
+            render_requirement("0.1", &odd),
+            "`0.1`: any 0.1.x version\n\nMatches 3 published versions, from `0.1.0` to `0.1.2+a+b`"
+        );
+        assert_eq!(
+            render_requirement("=0.1.1", &odd),
+            "`=0.1.1`: exactly 0.1.1\n\nMatches only `0.1.01.3` of the published versions"
+        );
+        // Without cached versions
+        assert_eq!(render_requirement("~1.2", &[]), "`~1.2`: any 1.2.x version");
+        let invalid = render_requirement("1.2.x.4", &versions);
+        assert!(
+            invalid.starts_with("**Invalid requirement** `1.2.x.4`: "),
+            "{invalid}"
+        );
+        assert!(invalid.contains("`>=1.2, <1.5`"));
+    }
+
+    #[test]
+    fn strips_markdown() {
+        assert_eq!(
+            plain_text(&render(&dependency(), None)),
+            "serde 1.0.188: compatible update to 1.0.215"
+        );
+        assert_eq!(
+            plain_text(
+                "`version = \"1\"`\n\nInherited from [`[workspace.package]`](file:///ws/Cargo.toml#L7)"
+            ),
+            "version = \"1\"\n\nInherited from [workspace.package] (file:///ws/Cargo.toml#L7)"
+        );
+        assert_eq!(plain_text("a ](b"), "a ](b");
+    }
+}
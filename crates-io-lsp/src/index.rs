@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::{self, Uri};
+
+use crate::manifest::SpannedManifest;
+use crate::text::Source;
+
+/// Command listing the manifests depending on a crate, the argument is the crate name.
+///
+/// Without argument it returns the [`WorkspaceSummary`].
+pub const WHO_USES: &str = "crates-io.whoUses";
+
+/// A dependency declaration of a crate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Occurrence {
+    pub uri: Uri,
+    /// Table of the declaration, like `dev-dependencies` or `target.'cfg(unix)'.dependencies`
+    pub section: String,
+    /// `None` if the declaration has no version, e.g. path dependencies
+    pub req: Option<String>,
+    /// Range of the dependency key
+    pub range: lsp_types::Range,
+}
+
+/// Crates of all known manifests with the number of declarations.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WorkspaceSummary {
+    pub manifests: usize,
+    pub crates: BTreeMap<String, usize>,
+}
+
+/// Dependencies of all manifests seen in the project, open or read for a workspace.
+///
+/// Unlike the references of the open documents, manifests stay indexed until they are deleted.
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    /// Declarations of each manifest by crate name
+    manifests: HashMap<Uri, Vec<(String, Occurrence)>>,
+    documents: HashMap<String, HashSet<Uri>>,
+}
+
+impl WorkspaceIndex {
+    /// Replace the declarations of a manifest.
+    pub fn update(&mut self, uri: &Uri, source: &Source, manifest: &SpannedManifest) {
+        let dependencies = manifest
+            .dependencies()
+            .map(|(kind, name, dep)| (kind.section().to_string(), name, dep))
+            .chain(
+                manifest
+                    .target_dependencies()
+                    .map(|(platform, kind, name, dep)| (kind.target_section(platform), name, dep)),
+            );
+        let mut occurrences = Vec::new();
+        for (section, name, dep) in dependencies {
+            let (Some(start), Some(end)) = (
+                source.position(name.span().start),
+                source.position(name.span().end),
+            ) else {
+                continue;
+            };
+            let package = dep.package().unwrap_or(name.get_ref());
+            let versioned = dep.detail().is_none_or(|d| d.version.is_some());
+            occurrences.push((
+                package.to_string(),
+                Occurrence {
+                    uri: uri.clone(),
+                    section,
+                    req: versioned.then(|| dep.req().to_string()),
+                    range: lsp_types::Range { start, end },
+                },
+            ));
+        }
+        self.insert(uri.clone(), occurrences);
+    }
+
+    /// Replace the declarations of a manifest by ones indexed before, like by an earlier scan.
+    pub fn restore(&mut self, uri: &Uri, occurrences: Vec<(String, Occurrence)>) {
+        self.insert(uri.clone(), occurrences);
+    }
+
+    /// The declarations of a manifest with their crate names.
+    pub fn declarations(&self, uri: &Uri) -> &[(String, Occurrence)] {
+        self.manifests.get(uri).map_or(&[], Vec::as_slice)
+    }
+
+    fn insert(&mut self, uri: Uri, occurrences: Vec<(String, Occurrence)>) {
+        self.remove(&uri);
+        for (name, _) in &occurrences {
+            self.documents
+                .entry(name.clone())
+                .or_default()
+                .insert(uri.clone());
+        }
+        self.manifests.insert(uri, occurrences);
+    }
+
+    fn remove(&mut self, uri: &Uri) -> Option<Vec<(String, Occurrence)>> {
+        let occurrences = self.manifests.remove(uri)?;
+        for (name, _) in &occurrences {
+            if let Some(documents) = self.documents.get_mut(name) {
+                documents.remove(uri);
+                if documents.is_empty() {
+                    self.documents.remove(name);
+                }
+            }
+        }
+        Some(occurrences)
+    }
+
+    /// Drop the manifests at or below a deleted file or directory.
+    pub fn remove_below(&mut self, uri: &Uri) {
+        for uri in self.below(uri) {
+            self.remove(&uri);
+        }
+    }
+
+    /// Move the manifests at or below a renamed file or directory.
+    pub fn rename_below(&mut self, old: &Uri, new: &Uri) {
+        for uri in self.below(old) {
+            let Some(occurrences) = self.remove(&uri) else {
+                continue;
+            };
+            let moved = uri.as_str().replacen(old.as_str(), new.as_str(), 1);
+            let Ok(moved) = moved.parse::<Uri>() else {
+                continue;
+            };
+            let occurrences = occurrences
+                .into_iter()
+                .map(|(name, o)| {
+                    (
+                        name,
+                        Occurrence {
+                            uri: moved.clone(),
+                            ..o
+                        },
+                    )
+                })
+                .collect();
+            self.insert(moved, occurrences);
+        }
+    }
+
+    fn below(&self, uri: &Uri) -> Vec<Uri> {
+        let dir = format!("{}/", uri.as_str().trim_end_matches('/'));
+        self.manifests
+            .keys()
+            .filter(|u| *u == uri || u.as_str().starts_with(&dir))
+            .cloned()
+            .collect()
+    }
+
+    pub fn contains(&self, uri: &Uri) -> bool {
+        self.manifests.contains_key(uri)
+    }
+
+    /// The part of the index with the manifests `within` a workspace context.
+    pub fn slice(&self, within: impl Fn(&Uri) -> bool) -> WorkspaceIndex {
+        let mut slice = WorkspaceIndex::default();
+        for (uri, occurrences) in &self.manifests {
+            if within(uri) {
+                slice.insert(uri.clone(), occurrences.clone());
+            }
+        }
+        slice
+    }
+
+    /// Manifests depending on a crate.
+    pub fn documents_using(&self, name: &str) -> impl Iterator<Item = &Uri> {
+        self.documents.get(name).into_iter().flatten()
+    }
+
+    /// All declarations of a crate, ordered by manifest and position.
+    pub fn all_requirements(&self, name: &str) -> Vec<&Occurrence> {
+        let mut occurrences = self
+            .documents_using(name)
+            .filter_map(|uri| self.manifests.get(uri))
+            .flatten()
+            .filter(|(n, _)| n == name)
+            .map(|(_, o)| o)
+            .collect::<Vec<_>>();
+        occurrences
+            .sort_by(|a, b| (a.uri.as_str(), a.range.start).cmp(&(b.uri.as_str(), b.range.start)));
+        occurrences
+    }
+
+    /// Declarations of every manifest by crate name, ordered by manifest and position.
+    pub fn manifests(&self) -> Vec<(&Uri, Vec<(&str, &Occurrence)>)> {
+        let mut manifests = self
+            .manifests
+            .iter()
+            .map(|(uri, occurrences)| {
+                let mut occurrences = occurrences
+                    .iter()
+                    .map(|(name, o)| (name.as_str(), o))
+                    .collect::<Vec<_>>();
+                occurrences.sort_by_key(|(_, o)| o.range.start);
+                (uri, occurrences)
+            })
+            .collect::<Vec<_>>();
+        manifests.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        manifests
+    }
+
+    pub fn workspace_summary(&self) -> WorkspaceSummary {
+        let mut crates = BTreeMap::new();
+        for (name, _) in self.manifests.values().flatten() {
+            *crates.entry(name.clone()).or_default() += 1;
+        }
+        WorkspaceSummary {
+            manifests: self.manifests.len(),
+            crates,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest;
+    use crate::text::Encoding;
+
+    fn uri(path: &str) -> Uri {
+        format!("file:///{path}").parse().unwrap()
+    }
+
+    fn update(index: &mut WorkspaceIndex, path: &str, text: &str) {
+        let (manifest, None) = manifest::parse(text) else {
+            panic!("{text}");
+        };
+        index.update(&uri(path), &Source::new(text, Encoding::Utf8), &manifest);
+    }
+
+    /// Manifest, section, and requirement of the declarations of a crate.
+    fn requirements(index: &WorkspaceIndex, name: &str) -> Vec<(String, String, Option<String>)> {
+        index
+            .all_requirements(name)
+            .into_iter()
+            .map(|o| (o.uri.to_string(), o.section.clone(), o.req.clone()))
+            .collect()
+    }
+
+    fn documents(index: &WorkspaceIndex, name: &str) -> Vec<String> {
+        let mut documents = index
+            .documents_using(name)
+            .map(|u| u.to_string())
+            .collect::<Vec<_>>();
+        documents.sort();
+        documents
+    }
+
+    #[test]
+    fn indexes_the_declarations() {
+        let mut index = WorkspaceIndex::default();
+        update(
+            &mut index,
+            "ws/app/Cargo.toml",
+            "[dependencies]\nserde = \"1\"\njson = { package = \"serde_json\", version = \"1.0\" }\n\
+            local = { path = \"../local\" }\n\n\
+            [target.'cfg(unix)'.dev-dependencies]\nserde = { version = \"1.0.100\" }\n",
+        );
+        update(
+            &mut index,
+            "ws/lib/Cargo.toml",
+            "[build-dependencies]\nserde = \"1.0\"\n",
+        );
+        assert_eq!(
+            requirements(&index, "serde"),
+            [
+                (
+                    "file:///ws/app/Cargo.toml".into(),
+                    "dependencies".into(),
+                    Some("1".into())
+                ),
+                (
+                    "file:///ws/app/Cargo.toml".into(),
+                    "target.'cfg(unix)'.dev-dependencies".into(),
+                    Some("1.0.100".into())
+                ),
+                (
+                    "file:///ws/lib/Cargo.toml".into(),
+                    "build-dependencies".into(),
+                    Some("1.0".into())
+                ),
+            ]
+        );
+        // Renamed dependencies are indexed by their package
+        assert_eq!(
+            documents(&index, "serde_json"),
+            ["file:///ws/app/Cargo.toml"]
+        );
+        assert!(documents(&index, "json").is_empty());
+        assert_eq!(
+            requirements(&index, "local"),
+            [(
+                "file:///ws/app/Cargo.toml".into(),
+                "dependencies".into(),
+                None
+            )]
+        );
+        let occurrence = index.all_requirements("serde_json")[0];
+        assert_eq!(occurrence.range.start, lsp_types::Position::new(2, 0));
+        assert_eq!(occurrence.range.end, lsp_types::Position::new(2, 4));
+
+        let summary = index.workspace_summary();
+        assert_eq!(summary.manifests, 2);
+        assert_eq!(
+            summary.crates,
+            BTreeMap::from([
+                ("local".into(), 1),
+                ("serde".into(), 3),
+                ("serde_json".into(), 1)
+            ])
+        );
+    }
+
+    #[test]
+    fn updates_incrementally() {
+        let mut index = WorkspaceIndex::default();
+        update(
+            &mut index,
+            "a/Cargo.toml",
+            "[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n",
+        );
+        update(
+            &mut index,
+            "b/Cargo.toml",
+            "[dependencies]\nlog = \"0.4\"\n",
+        );
+        update(
+            &mut index,
+            "a/Cargo.toml",
+            "[dependencies]\nserde = \"1.0\"\n",
+        );
+        assert_eq!(
+            requirements(&index, "serde"),
+            [(
+                "file:///a/Cargo.toml".into(),
+                "dependencies".into(),
+                Some("1.0".into())
+            )]
+        );
+        assert_eq!(documents(&index, "log"), ["file:///b/Cargo.toml"]);
+
+        update(&mut index, "b/Cargo.toml", "[package]\nname = \"b\"\n");
+        assert!(documents(&index, "log").is_empty());
+        assert!(!index.workspace_summary().crates.contains_key("log"));
+        assert_eq!(index.workspace_summary().manifests, 2);
+    }
+
+    #[test]
+    fn prunes_renamed_and_deleted_manifests() {
+        let mut index = WorkspaceIndex::default();
+        let text = "[dependencies]\nserde = \"1\"\n";
+        for path in ["ws/a/Cargo.toml", "ws/b/Cargo.toml", "wsx/Cargo.toml"] {
+            update(&mut index, path, text);
+        }
+        index.rename_below(&uri("ws"), &uri("moved"));
+        assert_eq!(
+            documents(&index, "serde"),
+            [
+                "file:///moved/a/Cargo.toml",
+                "file:///moved/b/Cargo.toml",
+                "file:///wsx/Cargo.toml"
+            ]
+        );
+        let moved = index.all_requirements("serde")[0].uri.clone();
+        assert_eq!(moved, uri("moved/a/Cargo.toml"));
+
+        index.remove_below(&uri("moved/a"));
+        index.remove_below(&uri("wsx/Cargo.toml"));
+        assert_eq!(documents(&index, "serde"), ["file:///moved/b/Cargo.toml"]);
+        assert_eq!(index.workspace_summary().manifests, 1);
+    }
+
+    #[test]
+    fn slices_a_context() {
+        let mut index = WorkspaceIndex::default();
+        update(
+            &mut index,
+            "repo/Cargo.toml",
+            "[dependencies]\nlog = \"0.4\"\n",
+        );
+        update(
+            &mut index,
+            "repo/firmware/Cargo.toml",
+            "[dependencies]\nlog = \"0.3\"\nserde = \"1\"\n",
+        );
+        let slice = index.slice(|u| u.as_str().starts_with("file:///repo/firmware/"));
+        assert!(slice.contains(&uri("repo/firmware/Cargo.toml")));
+        assert!(!slice.contains(&uri("repo/Cargo.toml")));
+        assert_eq!(
+            documents(&slice, "log"),
+            ["file:///repo/firmware/Cargo.toml"]
+        );
+        assert_eq!(slice.workspace_summary().manifests, 1);
+        assert_eq!(index.workspace_summary().crates["log"], 2);
+    }
+}
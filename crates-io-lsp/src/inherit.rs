@@ -0,0 +1,288 @@
+use std::ops::Range;
+
+use serde::Deserialize;
+use toml::Spanned;
+use tower_lsp_server::lsp_types::{
+    self, Diagnostic, DiagnosticRelatedInformation, Location, NumberOrString, Uri,
+};
+
+use crate::config::Config;
+use crate::manifest::{
+    table_headers, Inheritable, Publish, SpannedManifest, SpannedPackage, WorkspacePackage,
+};
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// A package field with the value inherited from the workspace resolved.
+#[derive(Debug, Clone)]
+pub struct Field<T> {
+    /// `None` if the field is inherited, but the workspace does not define it or is unknown
+    pub value: Option<T>,
+    /// Span of the value in the manifest
+    pub span: Range<usize>,
+}
+
+/// A `field.workspace = true` reference.
+#[derive(Debug, Clone)]
+pub struct Inherited {
+    pub key: &'static str,
+    /// Span of the reference in the manifest
+    pub span: Range<usize>,
+    /// Span of the key in `[workspace.package]` with its value, `None` if it is not defined
+    pub declaration: Option<(Range<usize>, toml::Value)>,
+}
+
+/// The `[package]` table with the `field.workspace = true` references resolved.
+///
+/// The package-level checks use this, so inherited fields count with their workspace value.
+#[derive(Debug, Clone, Default)]
+pub struct EffectivePackage {
+    pub version: Option<Field<String>>,
+    pub description: Option<Field<String>>,
+    pub license: Option<Field<String>>,
+    pub license_file: Option<Field<String>>,
+    pub repository: Option<Field<String>>,
+    pub publish: Option<Field<Publish>>,
+    pub edition: Option<Field<String>>,
+    /// References resolved against the workspace root, empty if the root is unknown
+    pub inherited: Vec<Inherited>,
+}
+
+/// Resolve the inherited fields of `package` against the `[workspace.package]` of its `root`,
+/// which may be the manifest itself.
+pub fn resolve(package: &SpannedPackage, root: Option<&SpannedManifest>) -> EffectivePackage {
+    let table = root.map(|r| r.workspace.as_ref().and_then(|w| w.package.as_ref()));
+    let mut inherited = Vec::new();
+    let mut field = |key, value| resolve_field(key, value, table, &mut inherited);
+    // Only resolved for the inheritance check and hover, no check uses the value yet
+    field("rust-version", &package.rust_version);
+    EffectivePackage {
+        version: field("version", &package.version),
+        description: field("description", &package.description),
+        license: field("license", &package.license),
+        license_file: field("license-file", &package.license_file),
+        repository: field("repository", &package.repository),
+        edition: field("edition", &package.edition),
+        publish: resolve_field("publish", &package.publish, table, &mut inherited),
+        inherited,
+    }
+}
+
+/// `table` is `None` if the root is unknown, `Some(None)` if it has no `[workspace.package]`.
+fn resolve_field<T: for<'de> Deserialize<'de> + Clone>(
+    key: &'static str,
+    value: &Option<Spanned<Inheritable<T>>>,
+    table: Option<Option<&WorkspacePackage>>,
+    inherited: &mut Vec<Inherited>,
+) -> Option<Field<T>> {
+    let value = value.as_ref()?;
+    let span = value.span();
+    let value = match value.get_ref() {
+        Inheritable::Value(value) => Some(value.clone()),
+        Inheritable::Workspace { .. } => {
+            let declaration = table.map(|table| {
+                table?
+                    .iter()
+                    .find(|(k, _)| k.get_ref() == key)
+                    .map(|(k, v)| (k.span(), v.clone()))
+            });
+            let value = declaration
+                .clone()
+                .flatten()
+                .and_then(|(_, v)| v.try_into().ok());
+            if let Some(declaration) = declaration {
+                inherited.push(Inherited {
+                    key,
+                    span: span.clone(),
+                    declaration,
+                });
+            }
+            value
+        }
+    };
+    Some(Field { value, span })
+}
+
+/// Flag inherited fields that the `[workspace.package]` of the root does not define.
+///
+/// Cargo refuses to build the workspace, but its error does not point at the member.
+pub fn lint(
+    package: &EffectivePackage,
+    source: &Source,
+    root_uri: &Uri,
+    root: &Source,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let headers = table_headers(&root.text);
+    let header = ["workspace.package", "workspace"]
+        .iter()
+        .find_map(|table| headers.iter().find(|(name, _)| name == table))
+        .and_then(|(_, header)| root.range(header.clone()));
+    package
+        .inherited
+        .iter()
+        .filter(|i| i.declaration.is_none())
+        .filter_map(|i| {
+            Some(Diagnostic {
+                range: source.range(i.span.clone())?,
+                severity: Some(config.severity.missing_workspace_field.into()),
+                code: Some(NumberOrString::String("missing-workspace-field".into())),
+                source: Some(config.source("missing-workspace-field")),
+                message: Message::new(Key::MissingWorkspaceField)
+                    .arg(format!("`{}`", i.key))
+                    .render(config.language),
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: root_uri.clone(),
+                        range: header.unwrap_or_default(),
+                    },
+                    message: Message::new(Key::WorkspaceRoot).render(config.language),
+                }]),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Hovers of the inherited fields: the value and a link to its declaration in the root.
+pub fn hovers(
+    package: &EffectivePackage,
+    source: &Source,
+    root_uri: &Uri,
+    root: &Source,
+) -> Vec<(lsp_types::Range, String)> {
+    package
+        .inherited
+        .iter()
+        .filter_map(|i| {
+            let (span, value) = i.declaration.as_ref()?;
+            let line = root.position(span.start)?.line + 1;
+            let markdown = format!(
+                "`{} = {value}`\n\nInherited from [`[workspace.package]`]({}#L{line})",
+                i.key,
+                root_uri.as_str()
+            );
+            Some((source.range(i.span.clone())?, markdown))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::parse;
+    use crate::text::Encoding;
+
+    const ROOT: &str = "[workspace]\nmembers = [\"app\"]\n\n[workspace.package]\n\
+        edition = \"2021\"\nlicense = \"MIT\"\nrust-version = \"1.80\"\n";
+    const MEMBER: &str = "[package]\nname = \"app\"\nedition = { workspace = true }\n\
+        license = { workspace = true }\nrust-version = { workspace = true }\n\
+        version = { workspace = true }\n";
+
+    fn effective(member: &str, root: Option<&str>) -> EffectivePackage {
+        let root = root.map(|root| parse(root).0);
+        let package = parse(member).0.package.unwrap().into_inner();
+        resolve(&package, root.as_ref())
+    }
+
+    fn value(field: &Option<Field<String>>) -> Option<&str> {
+        field.as_ref()?.value.as_deref()
+    }
+
+    #[test]
+    fn resolves_inherited_fields() {
+        let package = effective(MEMBER, Some(ROOT));
+        assert_eq!(value(&package.edition), Some("2021"));
+        assert_eq!(value(&package.license), Some("MIT"));
+        assert_eq!(value(&package.version), None);
+        assert!(package.description.is_none());
+
+        let inherited = package
+            .inherited
+            .iter()
+            .map(|i| (i.key, i.declaration.as_ref().map(|(_, v)| v.to_string())))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inherited,
+            [
+                ("rust-version", Some("\"1.80\"".into())),
+                ("version", None),
+                ("license", Some("\"MIT\"".into())),
+                ("edition", Some("\"2021\"".into())),
+            ]
+        );
+        let rust_version = &package.inherited[0];
+        assert_eq!(&MEMBER[rust_version.span.clone()], "{ workspace = true }");
+        let (span, _) = rust_version.declaration.as_ref().unwrap();
+        assert_eq!(&ROOT[span.clone()], "rust-version");
+    }
+
+    #[test]
+    fn keeps_own_values() {
+        let member = "[package]\nname = \"app\"\nedition = \"2018\"\nlicense = \"Apache-2.0\"\n";
+        let package = effective(member, Some(ROOT));
+        assert_eq!(value(&package.edition), Some("2018"));
+        assert_eq!(value(&package.license), Some("Apache-2.0"));
+        assert!(package.inherited.is_empty());
+    }
+
+    #[test]
+    fn unknown_roots_are_not_checked() {
+        let package = effective(MEMBER, None);
+        assert_eq!(value(&package.edition), None);
+        assert!(package.edition.is_some());
+        assert!(package.inherited.is_empty());
+
+        // A root without [workspace.package] defines nothing
+        let package = effective(MEMBER, Some("[workspace]\nmembers = [\"app\"]\n"));
+        assert_eq!(package.inherited.len(), 4);
+        assert!(package.inherited.iter().all(|i| i.declaration.is_none()));
+    }
+
+    #[test]
+    fn flags_missing_root_fields() {
+        let package = effective(MEMBER, Some(ROOT));
+        let root_uri: Uri = "file:///ws/Cargo.toml".parse().unwrap();
+        let diagnostics = lint(
+            &package,
+            &Source::new(MEMBER, Encoding::Utf8),
+            &root_uri,
+            &Source::new(ROOT, Encoding::Utf8),
+            &Config::default(),
+        );
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.range.start, lsp_types::Position::new(5, 10));
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String("missing-workspace-field".into()))
+        );
+        assert_eq!(
+            diagnostic.message,
+            "`version` is inherited, but [workspace.package] of the workspace root does not define it"
+        );
+        let related = &diagnostic.related_information.as_ref().unwrap()[0];
+        assert_eq!(related.location.uri, root_uri);
+        assert_eq!(related.location.range.start, lsp_types::Position::new(3, 0));
+    }
+
+    #[test]
+    fn hovers_link_the_declaration() {
+        let package = effective(MEMBER, Some(ROOT));
+        let root_uri: Uri = "file:///ws/Cargo.toml".parse().unwrap();
+        let hovers = hovers(
+            &package,
+            &Source::new(MEMBER, Encoding::Utf8),
+            &root_uri,
+            &Source::new(ROOT, Encoding::Utf8),
+        );
+        assert_eq!(hovers.len(), 3);
+        let (range, markdown) = &hovers[0];
+        assert_eq!(range.start, lsp_types::Position::new(4, 15));
+        assert_eq!(
+            markdown,
+            "`rust-version = \"1.80\"`\n\n\
+             Inherited from [`[workspace.package]`](file:///ws/Cargo.toml#L7)"
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp_server::lsp_types::{Diagnostic, Uri};
+use tower_lsp_server::UriExt;
+
+use crate::partial::PartialNotice;
+
+/// Request analyzing a manifest that is not open, for tools like pre-commit hooks.
+pub const ANALYZE: &str = "crates-io/analyze";
+
+/// Document of manifests analyzed without a path, which never touch the file system.
+const UNTITLED: &str = "untitled:Cargo.toml";
+
+/// Parameters of [`ANALYZE`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzeParams {
+    /// Content of the `Cargo.toml`
+    pub text: String,
+    /// Absolute path the manifest would have, its workspace and configuration file are used
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Settings of this request, merged over the settings of the client
+    #[serde(default)]
+    pub options: Value,
+}
+
+/// Result of [`ANALYZE`], the diagnostics the document would get when opened.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnalyzeResult {
+    pub uri: Uri,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Declarations that are only partially analyzed, whether they are reported or not
+    pub notices: Vec<PartialNotice>,
+}
+
+impl AnalyzeParams {
+    /// Document the manifest is analyzed as, `None` if the path is relative.
+    pub fn uri(&self) -> Option<Uri> {
+        match &self.path {
+            Some(path) if path.is_absolute() => Uri::from_file_path(path),
+            Some(_) => None,
+            None => UNTITLED.parse().ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(path: Option<&str>) -> AnalyzeParams {
+        AnalyzeParams {
+            text: String::new(),
+            path: path.map(PathBuf::from),
+            options: Value::Null,
+        }
+    }
+
+    #[test]
+    fn analyzed_as_the_path() {
+        let uri = params(Some("/ws/app/Cargo.toml")).uri().unwrap();
+        assert_eq!(uri.as_str(), "file:///ws/app/Cargo.toml");
+        assert_eq!(params(None).uri().unwrap().as_str(), UNTITLED);
+        // Relative to what?
+        assert_eq!(params(Some("app/Cargo.toml")).uri(), None);
+    }
+}
@@ -0,0 +1,952 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use toml::Spanned;
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString, Range, TextEdit};
+
+use crate::config::Config;
+use crate::manifest::{Notice, Unsupported};
+use crate::messages::{Key, Message};
+use crate::unstable::Unstable;
+
+/// Diagnostic code of unknown keys, the data contains the edit renaming a misspelled key.
+pub const UNKNOWN_KEY: &str = "unknown-key";
+
+/// Diagnostic code of strings given for array or boolean dependency keys,
+/// like `features = "derive"`, the data contains the edit to the right type if there is one.
+pub const MISTYPED_VALUE: &str = "mistyped-value";
+
+/// Top-level tables and keys of a manifest.
+const SECTIONS: &[&str] = &[
+    "cargo-features",
+    "package",
+    "project",
+    "lib",
+    "bin",
+    "example",
+    "test",
+    "bench",
+    "dependencies",
+    "dev-dependencies",
+    "build-dependencies",
+    "target",
+    "features",
+    "workspace",
+    "patch",
+    "replace",
+    "profile",
+    "badges",
+    "lints",
+];
+
+/// Sections containing dependencies, also within `[target.'cfg(...)']`.
+pub(crate) const DEPENDENCY_SECTIONS: &[&str] =
+    &["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Type of the value of a dependency key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    String,
+    /// A boolean, usually written with the given value
+    Bool(bool),
+    Array,
+}
+
+/// Where a dependency comes from, keys of different sources exclude each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// Valid for every dependency
+    Any,
+    Registry,
+    Git,
+    Path,
+    /// Inherited from `[workspace.dependencies]`
+    Workspace,
+}
+
+/// A key of a detailed dependency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DependencyKey {
+    pub name: &'static str,
+    pub value: Value,
+    pub source: Source,
+    /// Offered as completion, the others are unstable
+    pub stable: bool,
+}
+
+impl DependencyKey {
+    const fn new(name: &'static str, value: Value, source: Source) -> Self {
+        Self {
+            name,
+            value,
+            source,
+            stable: true,
+        }
+    }
+
+    const fn unstable(self) -> Self {
+        Self {
+            stable: false,
+            ..self
+        }
+    }
+
+    /// Whether the keys select different sources, like `git` and `version`.
+    pub fn excludes(&self, other: &DependencyKey) -> bool {
+        self.source != Source::Any && other.source != Source::Any && self.source != other.source
+    }
+}
+
+/// Keys of a detailed dependency.
+pub const DEPENDENCY_KEYS: &[DependencyKey] = &[
+    DependencyKey::new("version", Value::String, Source::Registry),
+    DependencyKey::new("features", Value::Array, Source::Any),
+    DependencyKey::new("optional", Value::Bool(true), Source::Any),
+    DependencyKey::new("default-features", Value::Bool(false), Source::Any),
+    DependencyKey::new("package", Value::String, Source::Any),
+    DependencyKey::new("registry", Value::String, Source::Registry),
+    DependencyKey::new("registry-index", Value::String, Source::Registry).unstable(),
+    DependencyKey::new("git", Value::String, Source::Git),
+    DependencyKey::new("branch", Value::String, Source::Git),
+    DependencyKey::new("tag", Value::String, Source::Git),
+    DependencyKey::new("rev", Value::String, Source::Git),
+    DependencyKey::new("path", Value::String, Source::Path),
+    DependencyKey::new("workspace", Value::Bool(true), Source::Workspace),
+    DependencyKey::new("artifact", Value::String, Source::Any).unstable(),
+    DependencyKey::new("lib", Value::Bool(true), Source::Any).unstable(),
+    DependencyKey::new("target", Value::String, Source::Any).unstable(),
+    DependencyKey::new("public", Value::Bool(true), Source::Any).unstable(),
+];
+
+/// Names of the keys of a detailed dependency.
+fn dependency_key_names() -> Vec<&'static str> {
+    DEPENDENCY_KEYS.iter().map(|k| k.name).collect()
+}
+
+/// The dependency key called `name`.
+pub fn dependency_key(name: &str) -> Option<&'static DependencyKey> {
+    DEPENDENCY_KEYS.iter().find(|k| k.name == name)
+}
+
+/// Keys of a table with their spans and values, other values have no keys.
+///
+/// Only the checked tables are deserialized with spans, the rest is skipped,
+/// so free-form tables with datetimes or anything else never fail.
+#[derive(Debug)]
+pub(crate) struct Map<T>(pub(crate) Vec<(Spanned<String>, T)>);
+
+/// Keys and values of a detailed dependency.
+type Dependency = Map<Spanned<toml::Value>>;
+/// Dependencies of a section by name.
+type Section = Map<Spanned<Entry>>;
+
+/// The value of a dependency.
+#[derive(Debug)]
+enum Entry {
+    Requirement,
+    Table(Dependency),
+    /// Any other value, with its TOML type
+    Other(&'static str),
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = Entry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("any value")
+            }
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<Self::Value, E> {
+                Ok(Entry::Other("boolean"))
+            }
+            fn visit_i64<E: de::Error>(self, _: i64) -> Result<Self::Value, E> {
+                Ok(Entry::Other("integer"))
+            }
+            fn visit_u64<E: de::Error>(self, _: u64) -> Result<Self::Value, E> {
+                Ok(Entry::Other("integer"))
+            }
+            fn visit_f64<E: de::Error>(self, _: f64) -> Result<Self::Value, E> {
+                Ok(Entry::Other("float"))
+            }
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<Self::Value, E> {
+                Ok(Entry::Requirement)
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(Entry::Other("array"))
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(key) = map.next_key::<Spanned<String>>()? {
+                    entries.push((key, map.next_value()?));
+                }
+                Ok(Entry::Table(Map(entries)))
+            }
+        }
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+impl Section {
+    /// The detailed dependencies with their names.
+    fn tables(&self) -> impl Iterator<Item = (&Spanned<String>, &Dependency)> {
+        self.0
+            .iter()
+            .filter_map(|(name, entry)| match entry.get_ref() {
+                Entry::Table(dependency) => Some((name, dependency)),
+                _ => None,
+            })
+    }
+}
+
+impl<T> Default for Map<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Map<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MapVisitor<T>(PhantomData<T>);
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for MapVisitor<T> {
+            type Value = Map<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("any value")
+            }
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<Self::Value, E> {
+                Ok(Map::default())
+            }
+            fn visit_i64<E: de::Error>(self, _: i64) -> Result<Self::Value, E> {
+                Ok(Map::default())
+            }
+            fn visit_u64<E: de::Error>(self, _: u64) -> Result<Self::Value, E> {
+                Ok(Map::default())
+            }
+            fn visit_f64<E: de::Error>(self, _: f64) -> Result<Self::Value, E> {
+                Ok(Map::default())
+            }
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<Self::Value, E> {
+                Ok(Map::default())
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(Map::default())
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(key) = map.next_key::<Spanned<String>>()? {
+                    entries.push((key, map.next_value()?));
+                }
+                Ok(Map(entries))
+            }
+        }
+        deserializer.deserialize_any(MapVisitor(PhantomData))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Workspace {
+    dependencies: Section,
+}
+
+/// The checked parts of a manifest.
+#[derive(Debug, Default)]
+struct Manifest {
+    /// Top-level keys that are not sections
+    unknown: Vec<Spanned<String>>,
+    /// Dependency sections, including misspelled ones
+    dependencies: Vec<Section>,
+    /// Sections of the `[target]` tables by platform
+    target: Map<Map<Section>>,
+}
+
+impl<'de> Deserialize<'de> for Manifest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ManifestVisitor;
+        impl<'de> Visitor<'de> for ManifestVisitor {
+            type Value = Manifest;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a manifest")
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Manifest, A::Error> {
+                let mut manifest = Manifest::default();
+                while let Some(key) = map.next_key::<Spanned<String>>()? {
+                    let name = key.get_ref().as_str();
+                    if name == "target" {
+                        manifest.target = map.next_value()?;
+                    } else if name == "workspace" {
+                        let workspace: Workspace = map.next_value()?;
+                        manifest.dependencies.push(workspace.dependencies);
+                    } else if DEPENDENCY_SECTIONS.contains(&name) {
+                        manifest.dependencies.push(map.next_value()?);
+                    } else if SECTIONS.contains(&name) {
+                        map.next_value::<IgnoredAny>()?;
+                    } else {
+                        // Cargo still reads the deprecated `dev_dependencies`
+                        if underscored(name, DEPENDENCY_SECTIONS).is_some() {
+                            manifest.dependencies.push(map.next_value()?);
+                        } else {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                        manifest.unknown.push(key);
+                    }
+                }
+                Ok(manifest)
+            }
+        }
+        deserializer.deserialize_map(ManifestVisitor)
+    }
+}
+
+/// Flag keys that cargo does not know in their context.
+///
+/// Checked are the keys of dependencies and the names of the top-level and target sections,
+/// misspelled sections are only reported if they are one edit away from a known one.
+/// Free-form tables like `[package.metadata]` are never checked.
+/// Nothing is reported for invalid TOML, which has its own diagnostic.
+pub fn lint(source: &crate::text::Source, unstable: &Unstable, config: &Config) -> Vec<Diagnostic> {
+    let text = source.text.as_str();
+    let Ok(manifest) = toml::from_str::<Manifest>(text) else {
+        return Vec::new();
+    };
+    let mut diagnostics = Vec::new();
+    let mut push = |key: &Spanned<String>, known: &[&str], always: bool| {
+        if let Some(diagnostic) = unknown(source, key, known, always, config) {
+            diagnostics.push(diagnostic);
+        }
+    };
+
+    for key in &manifest.unknown {
+        push(key, SECTIONS, false);
+    }
+    for (_, platform) in &manifest.target.0 {
+        for (key, _) in &platform.0 {
+            push(key, DEPENDENCY_SECTIONS, false);
+        }
+    }
+    for section in manifest.sections() {
+        dependencies(section, unstable, &mut push);
+    }
+    for section in manifest.sections() {
+        diagnostics.extend(undeclared(source, section, unstable, config));
+    }
+    diagnostics
+}
+
+impl Manifest {
+    /// The dependency sections, including those of the targets and the misspelled ones.
+    fn sections(&self) -> impl Iterator<Item = &Section> {
+        let targets = self.target.0.iter().flat_map(|(_, platform)| &platform.0);
+        let targets = targets
+            .filter(|(key, _)| {
+                underscored(key.get_ref(), DEPENDENCY_SECTIONS).is_some()
+                    || DEPENDENCY_SECTIONS.contains(&key.get_ref().as_str())
+            })
+            .map(|(_, section)| section);
+        self.dependencies.iter().chain(targets)
+    }
+}
+
+/// A string given for an array or boolean dependency key, like `features = "derive"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mistyped {
+    /// Span of the key
+    pub key: std::ops::Range<usize>,
+    /// Span of the string, with the quotes
+    pub value: std::ops::Range<usize>,
+    /// The expected type
+    pub expected: Value,
+    /// The value with the expected type, like `["derive"]` or `false`
+    pub fix: Option<String>,
+}
+
+/// The strings given for array or boolean keys of dependencies.
+///
+/// Such a value fails the deserialization of the whole manifest,
+/// it is found even then, as long as the TOML itself is valid.
+/// A list of features in one string, like `"derive, rc"`, is split at the commas.
+pub fn mistyped(text: &str) -> Vec<Mistyped> {
+    let Ok(manifest) = toml::from_str::<Manifest>(text) else {
+        return Vec::new();
+    };
+    let names = dependency_key_names();
+    let mut found = Vec::new();
+    for section in manifest.sections() {
+        for (_, dependency) in section.tables() {
+            for (key, value) in &dependency.0 {
+                let name = key.get_ref().as_str();
+                let Some(known) = dependency_key(name)
+                    .or_else(|| underscored(name, &names).and_then(dependency_key))
+                else {
+                    continue;
+                };
+                let toml::Value::String(string) = value.get_ref() else {
+                    continue;
+                };
+                let fix = match known.value {
+                    Value::String => continue,
+                    Value::Array => {
+                        let items = string
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|item| !item.is_empty())
+                            .map(|item| format!("\"{item}\""))
+                            .collect::<Vec<_>>();
+                        Some(format!("[{}]", items.join(", ")))
+                    }
+                    Value::Bool(_) => ["true", "false"]
+                        .into_iter()
+                        .find(|b| string.trim().eq_ignore_ascii_case(b))
+                        .map(str::to_string),
+                };
+                found.push(Mistyped {
+                    key: key.span(),
+                    value: value.span(),
+                    expected: known.value,
+                    fix,
+                });
+            }
+        }
+    }
+    found
+}
+
+/// Keys of a detailed dependency that cargo knows, but the analysis does not model.
+const IGNORED: &[&str] = &["public"];
+
+/// The declarations of dependencies that [`crate::manifest::parse`] only models in part,
+/// with the span of the key-value pair it has to drop, if any.
+///
+/// Nothing is found for invalid TOML, the sections are checked one by one for that.
+pub fn unsupported(text: &str) -> Vec<(Notice, Option<std::ops::Range<usize>>)> {
+    let Ok(manifest) = toml::from_str::<Manifest>(text) else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for section in manifest.sections() {
+        for (name, entry) in &section.0 {
+            let dependency = match entry.get_ref() {
+                Entry::Requirement => continue,
+                Entry::Table(dependency) => dependency,
+                Entry::Other(found_type) => {
+                    let reason = Unsupported::Dependency {
+                        name: name.get_ref().clone(),
+                        found: found_type,
+                    };
+                    let notice = Notice {
+                        span: name.span(),
+                        reason,
+                    };
+                    found.push((notice, Some(name.span().start..entry.span().end)));
+                    continue;
+                }
+            };
+            for (key, value) in &dependency.0 {
+                let (dependency, key_name) = (name.get_ref().clone(), key.get_ref().clone());
+                let reason = match (key_name.as_str(), value.get_ref()) {
+                    (ignored, _) if IGNORED.contains(&ignored) => Unsupported::IgnoredKey {
+                        name: dependency,
+                        key: key_name,
+                    },
+                    ("workspace", toml::Value::Boolean(false)) => {
+                        Unsupported::NotInherited { name: dependency }
+                    }
+                    (known, value) if !understood(known, value) => Unsupported::Value {
+                        name: dependency,
+                        key: key_name,
+                        found: value.type_str(),
+                    },
+                    _ => continue,
+                };
+                let drop = matches!(reason, Unsupported::Value { .. })
+                    .then(|| key.span().start..value.span().end);
+                found.push((
+                    Notice {
+                        span: key.span(),
+                        reason,
+                    },
+                    drop,
+                ));
+            }
+        }
+    }
+    found
+}
+
+/// Whether the dependency key `name` has a value of the type the analysis models,
+/// unknown keys are ignored anyway.
+fn understood(name: &str, value: &toml::Value) -> bool {
+    let strings = |value: &toml::Value| {
+        value
+            .as_array()
+            .is_some_and(|items| items.iter().all(toml::Value::is_str))
+    };
+    let known = match name {
+        "default_features" => dependency_key("default-features"),
+        "artifact" => return value.is_str() || strings(value),
+        name => dependency_key(name),
+    };
+    match known.map(|known| known.value) {
+        None => true,
+        Some(Value::String) => value.is_str(),
+        Some(Value::Bool(_)) => value.is_bool(),
+        Some(Value::Array) => strings(value),
+    }
+}
+
+/// Diagnostics of the [`mistyped`] values of `text`, with the edits to the expected type.
+pub fn mistyped_lint(
+    source: &crate::text::Source,
+    mistyped: &[Mistyped],
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let text = source.text.as_str();
+    mistyped
+        .iter()
+        .filter_map(|m| {
+            let range = Range {
+                start: source.position(m.value.start)?,
+                end: source.position(m.value.end)?,
+            };
+            let key = format!("`{}`", &text[m.key.clone()].trim_matches(['"', '\'']));
+            let message = match m.expected {
+                Value::Array => Message::new(Key::ArrayExpected).arg(key),
+                _ => Message::new(Key::BoolExpected).arg(key),
+            };
+            let data = m.fix.clone().map(|new_text| TextEdit { range, new_text });
+            Some(Diagnostic {
+                range,
+                severity: Some(config.severity.parse_error.into()),
+                code: Some(NumberOrString::String(MISTYPED_VALUE.into())),
+                source: Some(config.source(MISTYPED_VALUE)),
+                message: message.render(config.language),
+                data: data.and_then(|edit| serde_json::to_value(edit).ok()),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Check the keys of the detailed dependencies in a section,
+/// unstable keys are only suggested if the manifest declares their feature.
+fn dependencies(
+    section: &Section,
+    unstable: &Unstable,
+    push: &mut impl FnMut(&Spanned<String>, &[&str], bool),
+) {
+    let known = DEPENDENCY_KEYS
+        .iter()
+        .filter(|k| unstable.missing(k.name).is_none())
+        .map(|k| k.name)
+        .collect::<Vec<_>>();
+    for (_, dependency) in section.tables() {
+        for (key, _) in &dependency.0 {
+            if dependency_key(key.get_ref()).is_none() {
+                push(key, &known, true);
+            }
+        }
+    }
+}
+
+/// Keys of the detailed dependencies in a section that require an undeclared cargo feature,
+/// unless the feature has a check of its own, like `artifact` without `bindeps`.
+fn undeclared(
+    source: &crate::text::Source,
+    section: &Section,
+    unstable: &Unstable,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (_, dependency) in section.tables() {
+        for (key, _) in &dependency.0 {
+            let Some(feature) = unstable
+                .missing(key.get_ref())
+                .filter(|f| f.check == UNKNOWN_KEY)
+            else {
+                continue;
+            };
+            let Some(range) = source
+                .position(key.span().start)
+                .zip(source.position(key.span().end))
+                .map(|(start, end)| Range { start, end })
+            else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(config.severity.unknown_key.into()),
+                code: Some(NumberOrString::String(UNKNOWN_KEY.into())),
+                source: Some(config.source(UNKNOWN_KEY)),
+                message: Message::new(Key::UnstableField)
+                    .arg(format!("`{}`", key.get_ref()))
+                    .arg(feature.name)
+                    .render(config.language),
+                ..Default::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Diagnostic for a key that is not `known`, `None` if it is known.
+///
+/// Keys without a close match are only reported if `always` is set.
+fn unknown(
+    source: &crate::text::Source,
+    key: &Spanned<String>,
+    known: &[&str],
+    always: bool,
+    config: &Config,
+) -> Option<Diagnostic> {
+    let name = key.get_ref();
+    if known.contains(&name.as_str()) {
+        return None;
+    }
+    let quoted = format!("`{name}`");
+    let (message, suggestion) = if let Some(fixed) = underscored(name, known) {
+        let message = Message::new(Key::UnderscoreField)
+            .arg(&quoted)
+            .arg(format!("`{fixed}`"));
+        (message, Some(fixed))
+    } else if let Some(fixed) = suggest(name, known) {
+        let message = Message::new(Key::UnknownFieldSuggestion)
+            .arg(&quoted)
+            .arg(format!("`{fixed}`"));
+        (message, Some(fixed))
+    } else if always {
+        (Message::new(Key::UnknownField).arg(&quoted), None)
+    } else {
+        return None;
+    };
+    let range = Range {
+        start: source.position(key.span().start)?,
+        end: source.position(key.span().end)?,
+    };
+    let data = suggestion.map(|fixed| TextEdit {
+        range,
+        new_text: fixed.into(),
+    });
+    Some(Diagnostic {
+        range,
+        severity: Some(config.severity.unknown_key.into()),
+        code: Some(NumberOrString::String(UNKNOWN_KEY.into())),
+        source: Some(config.source(UNKNOWN_KEY)),
+        message: message.render(config.language),
+        data: data.and_then(|edit| serde_json::to_value(edit).ok()),
+        ..Default::default()
+    })
+}
+
+/// The known key written with underscores instead of hyphens, like `dev_dependencies`.
+fn underscored<'a>(name: &str, known: &[&'a str]) -> Option<&'a str> {
+    let hyphenated = name.replace('_', "-");
+    known
+        .iter()
+        .find(|k| name.contains('_') && **k == hyphenated)
+        .copied()
+}
+
+/// The known key one edit away from `name`, if there is exactly one.
+pub(crate) fn suggest<'a>(name: &str, known: &[&'a str]) -> Option<&'a str> {
+    let mut close = known.iter().filter(|k| distance(name, k) == 1);
+    let first = close.next()?;
+    close.next().is_none().then_some(*first)
+}
+
+/// Number of insertions, deletions, substitutions, and swaps of adjacent characters
+/// turning `a` into `b` (optimal string alignment distance).
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    // Rows of the two previous and the current prefix of `a`
+    let mut before = vec![0; b.len() + 1];
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (prev[j] + 1).min(row[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut prev, row);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types::Position;
+
+    use super::*;
+    use crate::text::Encoding;
+
+    /// Messages of the diagnostics with the line and suggested name.
+    fn lints(text: &str) -> Vec<(u32, String, Option<String>)> {
+        let source = crate::text::Source::new(text, Encoding::Utf8);
+        lint(&source, &Unstable::of(text), &Config::default())
+            .into_iter()
+            .map(|d| {
+                let edit = d
+                    .data
+                    .map(|data| serde_json::from_value::<TextEdit>(data).unwrap().new_text);
+                (d.range.start.line, d.message, edit)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(distance("version", "version"), 0);
+        assert_eq!(distance("featuers", "features"), 1);
+        assert_eq!(distance("depenencies", "dependencies"), 1);
+        assert_eq!(distance("optinal", "optional"), 1);
+        assert_eq!(distance("tags", "tag"), 1);
+        assert_eq!(distance("gti", "git"), 1);
+        assert_eq!(distance("", "rev"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(
+            suggest("featuers", &dependency_key_names()),
+            Some("features")
+        );
+        assert_eq!(suggest("packages", SECTIONS), Some("package"));
+        assert_eq!(suggest("colour", &dependency_key_names()), None);
+        // Ambiguous: `lib` and `git`
+        assert_eq!(suggest("lit", &dependency_key_names()), None);
+        assert_eq!(
+            underscored("dev_dependencies", DEPENDENCY_SECTIONS),
+            Some("dev-dependencies")
+        );
+        assert_eq!(
+            underscored("default_features", &dependency_key_names()),
+            Some("default-features")
+        );
+        assert_eq!(underscored("dependencies", DEPENDENCY_SECTIONS), None);
+    }
+
+    #[test]
+    fn known_keys_pass() {
+        let keys = DEPENDENCY_KEYS
+            .iter()
+            .map(|key| format!("{} = \"x\"", key.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sections = SECTIONS
+            .iter()
+            .filter(|s| !["cargo-features", "target"].contains(s))
+            .map(|s| format!("[{s}]\n"))
+            .collect::<String>();
+        let text = format!(
+            "cargo-features = [\"bindeps\", \"public-dependency\"]\n{sections}a = {{ {keys} }}\n"
+        );
+        assert_eq!(lints(&text), []);
+    }
+
+    #[test]
+    fn gates_unstable_keys() {
+        let text = "[dependencies]\n\
+            a = { version = \"1\", public = true }\n\
+            b = { version = \"1\", publc = true }\n\
+            c = { version = \"1\", artifact = \"bin\", lib = true }\n";
+        // The artifact keys are noted by their own check
+        assert_eq!(
+            lints(text),
+            [
+                (2, "Unknown key `publc`, cargo ignores it".into(), None),
+                (
+                    1,
+                    "`public` is unstable and requires `cargo-features = [\"public-dependency\"]`, \
+                     cargo ignores it otherwise"
+                        .into(),
+                    None
+                ),
+            ]
+        );
+
+        let declared = format!("cargo-features = [\"public-dependency\"]\n\n{text}");
+        assert_eq!(
+            lints(&declared),
+            [(
+                4,
+                "Unknown key `publc`, did you mean `public`?".into(),
+                Some("public".into())
+            )]
+        );
+    }
+
+    #[test]
+    fn flags_dependency_keys() {
+        let text = r#"
+[dependencies]
+serde = { version = "1", featuers = ["derive"] }
+tokio = { version = "1", colour = "blue" }
+
+[dev-dependencies.anyhow]
+version = "1"
+optinal = true
+
+[workspace.dependencies]
+log = { default_features = false }
+
+[target.'cfg(unix)'.build-dependencies]
+cc = { gti = "https://github.com/rust-lang/cc-rs" }
+"#;
+        assert_eq!(
+            lints(text),
+            [
+                (
+                    2,
+                    "Unknown key `featuers`, did you mean `features`?".into(),
+                    Some("features".into())
+                ),
+                (3, "Unknown key `colour`, cargo ignores it".into(), None),
+                (
+                    7,
+                    "Unknown key `optinal`, did you mean `optional`?".into(),
+                    Some("optional".into())
+                ),
+                (
+                    10,
+                    "`default_features` is deprecated and rejected by the 2024 edition, use `default-features`".into(),
+                    Some("default-features".into())
+                ),
+                (
+                    13,
+                    "Unknown key `gti`, did you mean `git`?".into(),
+                    Some("git".into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn flags_sections() {
+        let text = r#"
+[depenencies]
+serde = { version = "1", featuers = [] }
+
+[dev_dependencies]
+anyhow = "1"
+
+[target.'cfg(unix)'.dev_dependencies]
+libc = "0.2"
+
+[my-tool]
+anything = true
+"#;
+        assert_eq!(
+            lints(text),
+            [
+                (
+                    1,
+                    "Unknown key `depenencies`, did you mean `dependencies`?".into(),
+                    Some("dependencies".into())
+                ),
+                (
+                    4,
+                    "`dev_dependencies` is deprecated and rejected by the 2024 edition, use `dev-dependencies`".into(),
+                    Some("dev-dependencies".into())
+                ),
+                (
+                    7,
+                    "`dev_dependencies` is deprecated and rejected by the 2024 edition, use `dev-dependencies`".into(),
+                    Some("dev-dependencies".into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_free_form_tables() {
+        let text = r#"
+[package]
+name = "app"
+
+[package.metadata.docs.rs]
+featuers = ["all"]
+released = 2024-01-01T00:00:00Z
+
+[workspace.metadata.tool]
+dependencies = { serde = { colour = "red" } }
+
+[lints.rust]
+unsafe_code = "forbid"
+"#;
+        assert_eq!(lints(text), []);
+        assert_eq!(lints("[dependencies\n"), []);
+    }
+
+    /// Key, value, and fix of each mistyped value.
+    fn mistypes(text: &str) -> Vec<(&str, &str, Option<String>)> {
+        mistyped(text)
+            .into_iter()
+            .map(|m| (&text[m.key], &text[m.value], m.fix))
+            .collect()
+    }
+
+    #[test]
+    fn finds_mistyped_values() {
+        let text = r#"
+[dependencies]
+serde = { version = "1", features = "derive" }
+tokio = { version = "1", features = "rt, macros ,", default-features = "false" }
+
+[dev-dependencies.anyhow]
+version = "1"
+default_features = "True"
+optional = "maybe"
+
+[target.'cfg(unix)'.build-dependencies]
+cc = { features = 'parallel' }
+"#;
+        assert_eq!(
+            mistypes(text),
+            [
+                ("features", r#""derive""#, Some(r#"["derive"]"#.into())),
+                (
+                    "features",
+                    r#""rt, macros ,""#,
+                    Some(r#"["rt", "macros"]"#.into())
+                ),
+                ("default-features", r#""false""#, Some("false".into())),
+                ("default_features", r#""True""#, Some("true".into())),
+                ("optional", r#""maybe""#, None),
+                ("features", "'parallel'", Some(r#"["parallel"]"#.into())),
+            ]
+        );
+        let source = crate::text::Source::new(text, Encoding::Utf8);
+        let lints = mistyped_lint(&source, &mistyped(text), &Config::default());
+        assert_eq!(
+            lints[2].message,
+            "`default-features` must be `true` or `false`, not a string"
+        );
+        assert_eq!(lints[2].range.start, Position::new(3, 71));
+        assert!(lints[4].data.is_none());
+    }
+
+    #[test]
+    fn valid_values_are_not_mistyped() {
+        let text = r#"
+[dependencies]
+serde = { version = "1", features = ["derive"], default-features = false }
+rand = "0.8"
+local = { path = "../local", package = "features" }
+
+[package.metadata]
+features = "not a dependency"
+"#;
+        assert_eq!(mistypes(text), []);
+    }
+}
@@ -0,0 +1,67 @@
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString};
+
+use crate::config::Config;
+use crate::manifest::{Build, SpannedManifest};
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// Diagnostic code of [`lint`].
+pub const LINKS_WITHOUT_BUILD: &str = "links-without-build";
+
+/// Flag `links` of a package whose build script is disabled with `build = false`.
+///
+/// Cargo rejects these, the native library is configured by the build script.
+/// Whether a `build.rs` exists without `build` is left to cargo.
+pub fn lint(manifest: &SpannedManifest, source: &Source, config: &Config) -> Option<Diagnostic> {
+    let package = manifest.package.as_ref()?.get_ref();
+    let links = package.links.as_ref()?;
+    if package.build.as_ref()?.get_ref() != &Build::Flag(false) {
+        return None;
+    }
+    Some(Diagnostic {
+        range: source.range(links.span())?,
+        severity: Some(config.severity.links_without_build.into()),
+        code: Some(NumberOrString::String(LINKS_WITHOUT_BUILD.into())),
+        source: Some(config.source(LINKS_WITHOUT_BUILD)),
+        message: Message::new(Key::LinksWithoutBuild)
+            .arg(format!("`{}`", links.get_ref()))
+            .render(config.language),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types;
+
+    use super::*;
+    use crate::manifest;
+    use crate::text::Encoding;
+
+    fn check(text: &str) -> Option<Diagnostic> {
+        let (manifest, None) = manifest::parse(text) else {
+            panic!("{text}");
+        };
+        lint(
+            &manifest,
+            &Source::new(text, Encoding::Utf8),
+            &Config::default(),
+        )
+    }
+
+    #[test]
+    fn links_need_a_build_script() {
+        let package = "[package]\nname = \"openssl-sys\"\nlinks = \"ssl\"\n";
+        let diagnostic = check(&format!("{package}build = false\n")).unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "The package links to `ssl`, but `build = false` disables its build script"
+        );
+        assert_eq!(diagnostic.range.start, lsp_types::Position::new(2, 8));
+
+        assert_eq!(check(package), None);
+        assert_eq!(check(&format!("{package}build = \"src/build.rs\"\n")), None);
+        assert_eq!(check(&format!("{package}build = true\n")), None);
+        assert_eq!(check("[package]\nname = \"app\"\nbuild = false\n"), None);
+    }
+}
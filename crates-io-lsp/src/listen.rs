@@ -0,0 +1,117 @@
+//! Connections of a server shared by several clients, over TCP or a Unix domain socket.
+
+use std::io;
+#[cfg(unix)]
+use std::path::Path;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+/// A bidirectional stream to a client or server.
+pub trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Connection for T {}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub async fn tcp(addr: &str) -> io::Result<Self> {
+        Ok(Self::Tcp(TcpListener::bind(addr).await?))
+    }
+
+    /// Listen at `path`, replacing the socket a stopped server left behind.
+    #[cfg(unix)]
+    pub fn unix(path: &Path) -> io::Result<Self> {
+        match UnixListener::bind(path) {
+            Err(err)
+                if err.kind() == io::ErrorKind::AddrInUse
+                    && std::os::unix::net::UnixStream::connect(path).is_err() =>
+            {
+                std::fs::remove_file(path)?;
+                Ok(Self::Unix(UnixListener::bind(path)?))
+            }
+            listener => Ok(Self::Unix(listener?)),
+        }
+    }
+
+    pub async fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        Ok(match self {
+            Self::Tcp(listener) => Box::new(listener.accept().await?.0),
+            #[cfg(unix)]
+            Self::Unix(listener) => Box::new(listener.accept().await?.0),
+        })
+    }
+}
+
+/// Connect to a server at a TCP address or, on Unix, at the path of a socket.
+pub async fn connect(addr: &str) -> io::Result<Box<dyn Connection>> {
+    #[cfg(unix)]
+    if addr.contains('/') {
+        return Ok(Box::new(UnixStream::connect(addr).await?));
+    }
+    Ok(Box::new(TcpStream::connect(addr).await?))
+}
+
+/// Forward stdin to the connection and its responses to stdout, until either side closes.
+pub async fn bridge(connection: Box<dyn Connection>) -> io::Result<()> {
+    let (mut read, mut write) = tokio::io::split(connection);
+    let to_server = async {
+        tokio::io::copy(&mut tokio::io::stdin(), &mut write).await?;
+        write.shutdown().await
+    };
+    let to_client = async {
+        tokio::io::copy(&mut read, &mut tokio::io::stdout()).await?;
+        Ok(())
+    };
+    tokio::select! {
+        result = to_server => result,
+        result = to_client => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    /// Send a message from a client connecting to `addr`.
+    async fn roundtrip(listener: &Listener, addr: &str) {
+        let (accepted, connected) = tokio::join!(listener.accept(), connect(addr));
+        let (mut server, mut client) = (accepted.unwrap(), connected.unwrap());
+        client.write_all(b"ping").await.unwrap();
+        let mut buffer = [0; 4];
+        server.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"ping");
+    }
+
+    #[tokio::test]
+    async fn connects_over_tcp() {
+        let listener = Listener::tcp("127.0.0.1:0").await.unwrap();
+        let Listener::Tcp(tcp) = &listener else {
+            unreachable!()
+        };
+        let addr = tcp.local_addr().unwrap().to_string();
+        roundtrip(&listener, &addr).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn replaces_stale_sockets() {
+        let path = std::env::temp_dir().join(format!("crates-io-lsp-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // Left behind by a stopped server
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let listener = Listener::unix(&path).unwrap();
+        roundtrip(&listener, path.to_str().unwrap()).await;
+        // A running server is not replaced
+        assert!(Listener::unix(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+use tower_lsp_server::lsp_types::Uri;
+use tower_lsp_server::UriExt;
+
+/// Features that read files beside the document, unlike the analysis of its text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFeature {
+    /// Finding the workspace root and reading the member manifests
+    Workspace,
+    /// The nearest `.crates-io-lsp.toml`
+    ConfigFile,
+    /// Skipping `target`, vendored, excluded, and git-ignored manifests
+    Exclusion,
+    /// Reloading configuration files on `workspace/didChangeWatchedFiles`
+    WatchedFiles,
+    /// Scanning the Rust sources of the package for `checkDevOnly`
+    Sources,
+}
+
+/// Where a document lives, deciding which features may touch the file system.
+///
+/// The dependencies, hovers, and code actions only need the text of the document
+/// and work for any scheme.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocumentLocality {
+    /// A `file` URI on this machine
+    Local(PathBuf),
+    /// A buffer that was never saved, like `untitled:Cargo.toml`
+    Untitled,
+    /// Any other scheme, like the worktrees of remote projects or virtual documents of
+    /// other extensions, only the text of open documents is known
+    Remote,
+}
+
+impl DocumentLocality {
+    pub fn from_uri(uri: &Uri) -> Self {
+        let scheme = uri.scheme().map(|s| s.as_str().to_ascii_lowercase());
+        match scheme.as_deref() {
+            Some("file") => match uri.to_file_path() {
+                Some(path) if path.is_absolute() => Self::Local(path.into_owned()),
+                _ => Self::Remote,
+            },
+            Some("untitled") => Self::Untitled,
+            _ => Self::Remote,
+        }
+    }
+
+    /// Path of a local document, `None` if the file system must not be touched.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Local(path) => Some(path),
+            Self::Untitled | Self::Remote => None,
+        }
+    }
+
+    pub fn supports(&self, feature: FileFeature) -> bool {
+        match feature {
+            FileFeature::Workspace
+            | FileFeature::ConfigFile
+            | FileFeature::Exclusion
+            | FileFeature::WatchedFiles
+            | FileFeature::Sources => self.path().is_some(),
+        }
+    }
+
+    /// Path of the document if `feature` may read the files beside it.
+    pub fn path_for(&self, feature: FileFeature) -> Option<&Path> {
+        self.path().filter(|_| self.supports(feature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEATURES: [FileFeature; 5] = [
+        FileFeature::Workspace,
+        FileFeature::ConfigFile,
+        FileFeature::Exclusion,
+        FileFeature::WatchedFiles,
+        FileFeature::Sources,
+    ];
+
+    fn locality(uri: &str) -> DocumentLocality {
+        DocumentLocality::from_uri(&uri.parse().unwrap())
+    }
+
+    #[test]
+    fn local_files_support_everything() {
+        let local = locality("file:///project/app/Cargo.toml");
+        assert_eq!(local.path(), Some(Path::new("/project/app/Cargo.toml")));
+        for feature in FEATURES {
+            assert!(local.supports(feature), "{feature:?}");
+            assert_eq!(local.path_for(feature), local.path());
+        }
+        // Schemes are case-insensitive
+        assert!(locality("FILE:///project/Cargo.toml").path().is_some());
+    }
+
+    #[test]
+    fn untitled_buffers_only_analyze_the_text() {
+        let untitled = locality("untitled:Cargo.toml");
+        assert_eq!(untitled, DocumentLocality::Untitled);
+        assert_eq!(untitled.path(), None);
+        for feature in FEATURES {
+            assert!(!untitled.supports(feature), "{feature:?}");
+            assert_eq!(untitled.path_for(feature), None);
+        }
+    }
+
+    #[test]
+    fn other_schemes_never_touch_the_file_system() {
+        for uri in [
+            "ssh://user@host/project/Cargo.toml",
+            "zed-remote://host/project/Cargo.toml",
+            "vscode-vfs://github/owner/repo/Cargo.toml",
+        ] {
+            let remote = locality(uri);
+            assert_eq!(remote, DocumentLocality::Remote, "{uri}");
+            for feature in FEATURES {
+                assert!(!remote.supports(feature), "{uri} {feature:?}");
+            }
+        }
+    }
+}
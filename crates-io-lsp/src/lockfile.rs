@@ -0,0 +1,764 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString};
+
+use crate::analysis::AnalyzedDependency;
+use crate::config::Config;
+use crate::error::{Result, ServerError};
+use crate::messages::{Key, Message};
+use crate::version::{self, Release};
+
+/// Diagnostic code of [`lint`].
+pub const LOCK_BEHIND: &str = "lock-behind";
+/// Diagnostic code of [`verify`].
+pub const CHECKSUM_MISMATCH: &str = "checksum-mismatch";
+/// Watched lockfiles.
+pub const GLOB: &str = "**/Cargo.lock";
+
+const FILE_NAME: &str = "Cargo.lock";
+/// Packages of the example path of [`Placement`].
+const MAX_PATH: usize = 3;
+
+pub fn is_lockfile(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == FILE_NAME)
+}
+
+/// The `Cargo.lock` of the manifest at `path`, next to the root manifest of its workspace.
+pub fn path_for(path: &Path, root: Option<&Path>) -> Option<PathBuf> {
+    Some(root.unwrap_or(path).parent()?.join(FILE_NAME))
+}
+
+/// Locked versions of the registry packages in a `Cargo.lock`, and how the workspace reaches them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lockfile {
+    packages: HashMap<String, Vec<Version>>,
+    /// Recorded checksums of the registry packages, of the package or the legacy `[metadata]`
+    checksums: HashMap<String, HashMap<Version, String>>,
+    /// All packages with the dependency graph, in the order of the lockfile
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Node {
+    name: String,
+    version: String,
+    registry: bool,
+    /// The package depending on it on a shortest path from a local package,
+    /// `None` for local and unreachable packages
+    parent: Option<usize>,
+    /// Packages from the nearest local package, `None` if it is unreachable
+    depth: Option<usize>,
+}
+
+/// Where a registry package is in the dependency tree of the workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub version: Version,
+    /// The package depending on it, on a shortest path from a local package
+    pub via: String,
+    /// The last packages of that path up to the package, at most [`MAX_PATH`]
+    pub path: Vec<String>,
+    /// Whether the path starts behind its local package
+    pub truncated: bool,
+}
+
+impl Placement {
+    /// The path like `app → serde_json → serde`, starting with `…` if it is truncated.
+    pub fn path(&self) -> String {
+        let path = self.path.join(" → ");
+        match self.truncated {
+            true => format!("… → {path}"),
+            false => path,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawLockfile {
+    #[serde(default)]
+    package: Vec<RawPackage>,
+    /// Lockfiles before version 2 have the checksums here,
+    /// as `"checksum name version (source)" = "hash"`
+    #[serde(default)]
+    metadata: HashMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+struct RawPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+    checksum: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+impl Lockfile {
+    pub fn parse(text: &str) -> Result<Self> {
+        let raw: RawLockfile = toml::from_str(text)?;
+        let mut packages = HashMap::<_, Vec<_>>::new();
+        let mut checksums = HashMap::<_, HashMap<_, _>>::new();
+        let mut nodes = Vec::with_capacity(raw.package.len());
+        for package in &raw.package {
+            // Local packages have no source, git ones are not in the registry
+            let registry = package.source.as_deref().is_some_and(is_registry);
+            nodes.push(Node {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                registry,
+                parent: None,
+                depth: package.source.is_none().then_some(0),
+            });
+            if !registry {
+                continue;
+            }
+            if let Ok(version) = Version::parse(&package.version) {
+                if let Some(checksum) = &package.checksum {
+                    checksums
+                        .entry(package.name.clone())
+                        .or_default()
+                        .insert(version.clone(), checksum.clone());
+                }
+                packages
+                    .entry(package.name.clone())
+                    .or_default()
+                    .push(version);
+            }
+        }
+        for (key, value) in &raw.metadata {
+            let (Some((name, version, source)), Some(checksum)) = (legacy_key(key), value.as_str())
+            else {
+                continue;
+            };
+            // Packages without a checksum are recorded as `<none>`
+            if !is_registry(source) || !checksum.bytes().all(|b| b.is_ascii_hexdigit()) {
+                continue;
+            }
+            if let Ok(version) = Version::parse(version) {
+                checksums
+                    .entry(name.to_string())
+                    .or_default()
+                    .entry(version)
+                    .or_insert_with(|| checksum.to_string());
+            }
+        }
+
+        // Shortest paths from all local packages at once, breadth-first
+        let mut by_name = HashMap::<&str, Vec<usize>>::new();
+        for (i, node) in nodes.iter().enumerate() {
+            by_name.entry(&node.name).or_default().push(i);
+        }
+        let edges = raw
+            .package
+            .iter()
+            .map(|package| {
+                let deps = package.dependencies.iter();
+                deps.filter_map(|dep| resolve(&by_name, &nodes, dep))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut queue = (0..nodes.len())
+            .filter(|i| nodes[*i].depth == Some(0))
+            .collect::<VecDeque<_>>();
+        while let Some(i) = queue.pop_front() {
+            let depth = nodes[i].depth.map(|d| d + 1);
+            for &dep in &edges[i] {
+                if nodes[dep].depth.is_none() {
+                    nodes[dep].depth = depth;
+                    nodes[dep].parent = Some(i);
+                    queue.push_back(dep);
+                }
+            }
+        }
+        Ok(Self {
+            packages,
+            checksums,
+            nodes,
+        })
+    }
+
+    /// Checksum recorded for the registry package `name` at exactly `version`.
+    pub fn checksum(&self, name: &str, version: &Version) -> Option<&str> {
+        self.checksums.get(name)?.get(version).map(String::as_str)
+    }
+
+    /// Newest locked version of `name` matching `req`, several majors may be locked.
+    pub fn locked(&self, name: &str, req: &str) -> Option<&Version> {
+        let req = VersionReq::parse(req).ok()?;
+        self.packages
+            .get(name)?
+            .iter()
+            .filter(|v| req.matches(v))
+            .max()
+    }
+
+    /// Where the newest locked version of the registry package `name` that the workspace
+    /// depends on is in its tree.
+    pub fn placement(&self, name: &str) -> Option<Placement> {
+        let (version, i) = self.newest(|node| node.name == name).pop()?;
+        self.place(version, i)
+    }
+
+    /// Placements of the registry packages the workspace depends on,
+    /// the newest locked version of each, the nearest first.
+    pub fn placements(&self) -> impl Iterator<Item = Placement> + '_ {
+        let mut newest = self.newest(|_| true);
+        newest.sort_by_key(|(_, i)| (self.nodes[*i].depth, &self.nodes[*i].name));
+        newest
+            .into_iter()
+            .filter_map(|(version, i)| self.place(version, i))
+    }
+
+    /// Newest version and node of each registry package the workspace depends on.
+    fn newest(&self, filter: impl Fn(&Node) -> bool) -> Vec<(Version, usize)> {
+        let mut newest = HashMap::<&str, (Version, usize)>::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !node.registry || node.depth.is_none() || !filter(node) {
+                continue;
+            }
+            let Ok(version) = Version::parse(&node.version) else {
+                continue;
+            };
+            match newest.get(node.name.as_str()) {
+                Some((known, _)) if *known >= version => {}
+                _ => {
+                    newest.insert(&node.name, (version, i));
+                }
+            }
+        }
+        newest.into_values().collect()
+    }
+
+    /// Placement of the node `i`, following only the end of its path back.
+    fn place(&self, version: Version, i: usize) -> Option<Placement> {
+        let via = self.nodes[self.nodes[i].parent?].name.clone();
+        let mut path = VecDeque::new();
+        let mut at = Some(i);
+        while let Some(node) = at.filter(|_| path.len() < MAX_PATH) {
+            path.push_front(self.nodes[node].name.clone());
+            at = self.nodes[node].parent;
+        }
+        Some(Placement {
+            version,
+            via,
+            path: path.into(),
+            truncated: at.is_some(),
+        })
+    }
+}
+
+fn is_registry(source: &str) -> bool {
+    source.starts_with("registry+") || source.starts_with("sparse+")
+}
+
+/// Name, version, and source of a legacy `[metadata]` key,
+/// `checksum name version (source)`.
+fn legacy_key(key: &str) -> Option<(&str, &str, &str)> {
+    let (package, source) = key.strip_prefix("checksum ")?.split_once(" (")?;
+    let (name, version) = package.split_once(' ')?;
+    Some((name, version, source.strip_suffix(')')?))
+}
+
+/// The package of an entry of `dependencies`, `name`, `name version`, or
+/// `name version (source)`, which only name as much as is ambiguous.
+fn resolve(by_name: &HashMap<&str, Vec<usize>>, nodes: &[Node], dep: &str) -> Option<usize> {
+    let mut parts = dep.split_whitespace();
+    let candidates = by_name.get(parts.next()?)?;
+    match parts.next() {
+        Some(version) => candidates
+            .iter()
+            .copied()
+            .find(|i| nodes[*i].version == version),
+        None => candidates.first().copied(),
+    }
+}
+
+/// Parsed lockfiles, read again when their modification time changes.
+#[derive(Debug, Default)]
+pub struct Cache {
+    entries: HashMap<PathBuf, (SystemTime, Arc<Lockfile>)>,
+}
+
+impl Cache {
+    /// The lockfile at `path`, `None` if there is none.
+    pub async fn get(&mut self, path: &Path) -> Result<Option<Arc<Lockfile>>> {
+        let modified = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata.modified().map_err(|e| ServerError::io(path, e))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.entries.remove(path);
+                return Ok(None);
+            }
+            Err(err) => return Err(ServerError::io(path, err)),
+        };
+        if let Some((cached, lockfile)) = self.entries.get(path) {
+            if *cached == modified {
+                return Ok(Some(lockfile.clone()));
+            }
+        }
+        let text = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| ServerError::io(path, e))?;
+        let lockfile = Arc::new(Lockfile::parse(&text)?);
+        self.entries
+            .insert(path.to_path_buf(), (modified, lockfile.clone()));
+        Ok(Some(lockfile))
+    }
+
+    /// Forget the lockfile at `path`, even if its modification time did not change.
+    pub fn remove(&mut self, path: &Path) {
+        self.entries.remove(path);
+    }
+}
+
+/// Hint at the requirements whose locked version is older than the newest version they match.
+///
+/// Uses the cached `versions` of the dependencies, crates without them are skipped.
+/// The hint is on the requirement, its data contains the locked version.
+pub fn lint<'a>(
+    dependencies: &[AnalyzedDependency],
+    lockfile: &Lockfile,
+    versions: impl Fn(&str) -> Option<&'a [Release]>,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    dependencies
+        .iter()
+        .filter(|dep| !dep.git)
+        .filter_map(|dep| {
+            let range = dep.req_range?;
+            let locked = lockfile.locked(&dep.package, &dep.req)?;
+            let req = VersionReq::parse(&dep.req).ok()?;
+            let newest = version::available(versions(&dep.package)?)
+                .filter(|v| req.matches(v))
+                .filter(|v| v.pre.is_empty() || config.include_prerelease)
+                .max()?;
+            (locked < newest).then(|| Diagnostic {
+                range,
+                severity: Some(config.severity.lock_behind.into()),
+                code: Some(NumberOrString::String(LOCK_BEHIND.into())),
+                source: Some(config.source(LOCK_BEHIND)),
+                message: Message::new(Key::LockBehind)
+                    .arg(&dep.package)
+                    .arg(locked)
+                    .arg(newest)
+                    .render(config.language),
+                data: Some(serde_json::json!({ "locked": locked.to_string() })),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Flag the dependencies whose locked version has another checksum in `Cargo.lock` than
+/// in the registry.
+///
+/// `checksums` are those of the registry by crate and exact version. Returns the flags and
+/// the crates whose registry checksum of the locked version is unknown, to be fetched.
+pub fn verify<'a>(
+    dependencies: &[AnalyzedDependency],
+    lockfile: &Lockfile,
+    checksums: impl Fn(&str, &Version) -> Option<&'a str>,
+    config: &Config,
+) -> (Vec<Diagnostic>, Vec<String>) {
+    let mut diagnostics = Vec::new();
+    let mut unknown = Vec::new();
+    for dep in dependencies.iter().filter(|dep| !dep.git) {
+        let Some(range) = dep.req_range else {
+            continue;
+        };
+        let Some(locked) = lockfile.locked(&dep.package, &dep.req) else {
+            continue;
+        };
+        let Some(recorded) = lockfile.checksum(&dep.package, locked) else {
+            continue;
+        };
+        let Some(published) = checksums(&dep.package, locked) else {
+            if !unknown.contains(&dep.package) {
+                unknown.push(dep.package.clone());
+            }
+            continue;
+        };
+        if recorded.eq_ignore_ascii_case(published) {
+            continue;
+        }
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(config.severity.checksum_mismatch.into()),
+            code: Some(NumberOrString::String(CHECKSUM_MISMATCH.into())),
+            source: Some(config.source(CHECKSUM_MISMATCH)),
+            message: Message::new(Key::ChecksumMismatch)
+                .arg(&dep.package)
+                .arg(locked)
+                .arg(abbreviate(recorded))
+                .arg(abbreviate(published))
+                .render(config.language),
+            data: Some(serde_json::json!({
+                "locked": locked.to_string(),
+                "lockfile": recorded,
+                "registry": published,
+            })),
+            ..Default::default()
+        });
+    }
+    (diagnostics, unknown)
+}
+
+/// The first 12 digits of a checksum, enough to tell them apart at a glance.
+pub fn abbreviate(checksum: &str) -> String {
+    match checksum.get(..12) {
+        Some(start) if start.len() < checksum.len() => format!("{start}…"),
+        _ => checksum.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types::{self, Position, Range};
+
+    use super::*;
+    use crate::manifest::DependencyKind;
+
+    const LOCK: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["rand 0.7.3", "rand 0.8.4", "serde"]
+
+[[package]]
+name = "rand"
+version = "0.7.3"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "rand"
+version = "0.8.4"
+source = "sparse+https://index.crates.io/"
+
+[[package]]
+name = "serde"
+version = "1.0.100"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+
+[[package]]
+name = "forked"
+version = "1.0.0"
+source = "git+https://github.com/user/forked#0123456789abcdef"
+"#;
+
+    /// Two workspace members, with `serde` reached through both `serde_json` and `toml`.
+    const DIAMOND: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde_json", "toml"]
+
+[[package]]
+name = "xtask"
+version = "0.1.0"
+dependencies = ["app"]
+
+[[package]]
+name = "serde_json"
+version = "1.0.100"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["itoa", "serde 1.0.188"]
+
+[[package]]
+name = "toml"
+version = "0.8.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["serde 1.0.188 (registry+https://github.com/rust-lang/crates.io-index)"]
+
+[[package]]
+name = "itoa"
+version = "1.0.9"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde"
+version = "1.0.188"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["serde_derive"]
+
+[[package]]
+name = "serde"
+version = "2.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde_derive"
+version = "1.0.188"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["proc-macro2"]
+
+[[package]]
+name = "proc-macro2"
+version = "1.0.66"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    fn dependency(package: &str, req: &str) -> AnalyzedDependency {
+        AnalyzedDependency {
+            package: package.into(),
+            kind: DependencyKind::Normal,
+            target: None,
+            name_range: Range::default(),
+            req: req.into(),
+            req_range: Some(Range::new(Position::new(1, 0), Position::new(1, 1))),
+            git: false,
+            classification: None,
+        }
+    }
+
+    fn releases(versions: &[&str]) -> Vec<Release> {
+        versions
+            .iter()
+            .map(|v| Release::parse(v, false).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn locked_registry_versions() {
+        let lockfile = Lockfile::parse(LOCK).unwrap();
+        assert_eq!(lockfile.locked("rand", "0.7").unwrap().to_string(), "0.7.3");
+        assert_eq!(lockfile.locked("rand", "0.8").unwrap().to_string(), "0.8.4");
+        assert_eq!(lockfile.locked("rand", "*").unwrap().to_string(), "0.8.4");
+        assert_eq!(lockfile.locked("rand", "0.9"), None);
+        // Neither local nor git packages
+        assert_eq!(lockfile.locked("app", "*"), None);
+        assert_eq!(lockfile.locked("forked", "*"), None);
+        assert!(Lockfile::parse("[[package]]\nname = ").is_err());
+    }
+
+    #[test]
+    fn places_packages_on_a_shortest_path() {
+        let lockfile = Lockfile::parse(DIAMOND).unwrap();
+        let serde = lockfile.placement("serde").unwrap();
+        // The first of both paths, the unreachable newer version does not count
+        assert_eq!(
+            serde,
+            Placement {
+                version: Version::new(1, 0, 188),
+                via: "serde_json".into(),
+                path: vec!["app".into(), "serde_json".into(), "serde".into()],
+                truncated: false,
+            }
+        );
+        assert_eq!(serde.path(), "app → serde_json → serde");
+        let direct = lockfile.placement("toml").unwrap();
+        assert_eq!(
+            (direct.via.as_str(), direct.path()),
+            ("app", "app → toml".into())
+        );
+        // Only the end of longer paths
+        let deep = lockfile.placement("proc-macro2").unwrap();
+        assert_eq!(deep.via, "serde_derive");
+        assert_eq!(deep.path(), "… → serde → serde_derive → proc-macro2");
+        // Neither local packages nor unknown ones
+        assert_eq!(lockfile.placement("app"), None);
+        assert_eq!(lockfile.placement("xtask"), None);
+        assert_eq!(lockfile.placement("rand"), None);
+        // The newest of several locked versions
+        let rand = Lockfile::parse(LOCK).unwrap().placement("rand").unwrap();
+        assert_eq!(
+            (rand.path(), rand.version),
+            ("app → rand".into(), Version::new(0, 8, 4))
+        );
+    }
+
+    #[test]
+    fn nearest_placements_first() {
+        let lockfile = Lockfile::parse(DIAMOND).unwrap();
+        let names = lockfile
+            .placements()
+            .map(|p| p.path.last().unwrap().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "serde_json",
+                "toml",
+                "itoa",
+                "serde",
+                "serde_derive",
+                "proc-macro2"
+            ]
+        );
+    }
+
+    #[test]
+    fn hints_at_locked_versions_behind_the_requirement() {
+        let lockfile = Lockfile::parse(LOCK).unwrap();
+        let serde = releases(&["1.0.100", "1.0.200", "2.0.0-rc.1"]);
+        let rand = releases(&["0.7.3", "0.8.4"]);
+        let versions = |name: &str| match name {
+            "serde" => Some(&serde[..]),
+            "rand" => Some(&rand[..]),
+            _ => None,
+        };
+        let dependencies = [
+            dependency("serde", "1"),
+            dependency("rand", "0.8"),
+            dependency("unlocked", "1"),
+        ];
+        let diagnostics = lint(&dependencies, &lockfile, versions, &Config::default());
+        let [diagnostic] = &diagnostics[..] else {
+            panic!("{diagnostics:?}");
+        };
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String(LOCK_BEHIND.into()))
+        );
+        assert_eq!(
+            diagnostic.message,
+            "Cargo.lock has serde 1.0.100, `cargo update -p serde` updates it to 1.0.200"
+        );
+        assert_eq!(
+            diagnostic.data,
+            Some(serde_json::json!({ "locked": "1.0.100" }))
+        );
+
+        // Git dependencies only check the version found there
+        let git = AnalyzedDependency {
+            git: true,
+            ..dependency("serde", "1")
+        };
+        assert!(lint(&[git], &lockfile, versions, &Config::default()).is_empty());
+    }
+
+    /// A lockfile of version 1, with the checksums in `[metadata]`.
+    const LEGACY: &str = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["itoa 1.0.9 (registry+https://github.com/rust-lang/crates.io-index)"]
+
+[[package]]
+name = "itoa"
+version = "1.0.9"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "forked"
+version = "1.0.0"
+source = "git+https://github.com/user/forked#0123456789abcdef"
+
+[metadata]
+"checksum itoa 1.0.9 (registry+https://github.com/rust-lang/crates.io-index)" = "af150ab688ff2122fcef229be89cb50dd66af9e01a4ff320cc137eecc9bacc38"
+"checksum forked 1.0.0 (git+https://github.com/user/forked#0123456789abcdef)" = "<none>"
+"#;
+
+    #[test]
+    fn parses_both_checksum_formats() {
+        let lockfile = Lockfile::parse(LOCK).unwrap();
+        assert_eq!(
+            lockfile.checksum("serde", &Version::new(1, 0, 100)),
+            Some("0".repeat(64).as_str())
+        );
+        assert_eq!(lockfile.checksum("serde", &Version::new(1, 0, 101)), None);
+        assert_eq!(lockfile.checksum("rand", &Version::new(0, 8, 4)), None);
+
+        let legacy = Lockfile::parse(LEGACY).unwrap();
+        assert_eq!(
+            legacy.checksum("itoa", &Version::new(1, 0, 9)),
+            Some("af150ab688ff2122fcef229be89cb50dd66af9e01a4ff320cc137eecc9bacc38")
+        );
+        assert_eq!(legacy.checksum("forked", &Version::new(1, 0, 0)), None);
+        assert_eq!(
+            legacy_key("checksum a 1.0.0 (sparse+https://index.crates.io/)"),
+            Some(("a", "1.0.0", "sparse+https://index.crates.io/"))
+        );
+        assert_eq!(legacy_key("checksum a 1.0.0"), None);
+    }
+
+    #[test]
+    fn flags_checksums_differing_from_the_registry() {
+        let published = "1".repeat(64);
+        let dependencies = [dependency("serde", "1"), dependency("rand", "0.8")];
+        let verify_with = |lock: &str, checksum: &str| {
+            let lockfile = Lockfile::parse(lock).unwrap();
+            let checksums = |name: &str, version: &Version| {
+                (name == "serde" && *version == Version::new(1, 0, 100)).then_some(checksum)
+            };
+            verify(&dependencies, &lockfile, checksums, &Config::default())
+        };
+
+        // A corrupted lockfile
+        let (diagnostics, unknown) = verify_with(LOCK, &published);
+        let [diagnostic] = &diagnostics[..] else {
+            panic!("{diagnostics:?}");
+        };
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String(CHECKSUM_MISMATCH.into()))
+        );
+        assert_eq!(
+            diagnostic.severity,
+            Some(lsp_types::DiagnosticSeverity::ERROR)
+        );
+        assert_eq!(
+            diagnostic.message,
+            "The checksum of serde 1.0.100 in Cargo.lock (000000000000…) differs from the \
+            registry (111111111111…), either file may have been altered: compare them before \
+            building"
+        );
+        assert_eq!(diagnostic.data.as_ref().unwrap()["registry"], published);
+        // Without a recorded checksum, nothing is compared or fetched
+        assert!(unknown.is_empty());
+
+        // A matching lockfile, in any case
+        let matching = LOCK.replace(&"0".repeat(64), &"A".repeat(64));
+        let (diagnostics, _) = verify_with(&matching, &"a".repeat(64));
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+
+        // Unknown registry checksums are reported once
+        let lockfile = Lockfile::parse(LOCK).unwrap();
+        let twice = [dependency("serde", "1"), dependency("serde", "1.0")];
+        let (diagnostics, unknown) = verify(&twice, &lockfile, |_, _| None, &Config::default());
+        assert!(diagnostics.is_empty());
+        assert_eq!(unknown, ["serde"]);
+    }
+
+    #[test]
+    fn abbreviates_checksums() {
+        assert_eq!(abbreviate(&"ab".repeat(32)), "abababababab…");
+        assert_eq!(abbreviate("abcd"), "abcd");
+    }
+
+    #[tokio::test]
+    async fn reads_changed_lockfiles_again() {
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-lockfile-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("Cargo.lock");
+        let mut cache = Cache::default();
+        assert_eq!(cache.get(&path).await.unwrap(), None);
+
+        std::fs::write(&path, LOCK).unwrap();
+        let first = cache.get(&path).await.unwrap().unwrap();
+        assert!(Arc::ptr_eq(
+            &first,
+            &cache.get(&path).await.unwrap().unwrap()
+        ));
+
+        let updated = LOCK.replace("1.0.100", "1.0.200");
+        std::fs::write(&path, updated).unwrap();
+        cache.remove(&path);
+        let second = cache.get(&path).await.unwrap().unwrap();
+        assert_eq!(second.locked("serde", "1").unwrap().to_string(), "1.0.200");
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cache.get(&path).await.unwrap(), None);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
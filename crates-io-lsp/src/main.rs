@@ -1,56 +1,552 @@
-use std::collections::{BTreeMap, HashMap};
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::future::Future;
+use std::mem;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use anyhow::Result;
-use cargo_manifest::Dependency;
-use clap::Parser;
-use tokio::sync::RwLock;
-use toml::Spanned;
+use clap::{Parser, Subcommand};
+use futures::FutureExt;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::AsyncReadExt;
+use tokio::sync::{watch, Mutex, RwLock};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tower_lsp_server::lsp_types::request::WorkDoneProgressCreate;
 use tower_lsp_server::lsp_types::{
-    self, DiagnosticServerCapabilities, DiagnosticSeverity, DidChangeTextDocumentParams,
+    self, CodeAction, CodeActionKind, CodeActionOptions, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CodeActionResponse, CodeLens, CodeLensOptions, CodeLensParams,
+    CompletionOptions, CompletionParams, CompletionResponse, DeleteFilesParams, DiagnosticOptions,
+    DiagnosticServerCapabilities, DidChangeConfigurationParams, DidChangeTextDocumentParams,
+    DidChangeWatchedFilesParams, DidChangeWatchedFilesRegistrationOptions,
     DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
-    InitializeParams, InitializeResult, MessageType, Position, ServerCapabilities,
-    TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+    DocumentDiagnosticParams, DocumentDiagnosticReport, DocumentDiagnosticReportResult,
+    DocumentOnTypeFormattingOptions, DocumentOnTypeFormattingParams, DocumentRangeFormattingParams,
+    DocumentSymbolParams, DocumentSymbolResponse, ExecuteCommandOptions, ExecuteCommandParams,
+    FileChangeType, FileEvent, FileOperationFilter, FileOperationPattern, FileOperationPatternKind,
+    FileOperationRegistrationOptions, FileSystemWatcher, FullDocumentDiagnosticReport, GlobPattern,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, InitializeParams, InitializeResult, InitializedParams, MarkupContent,
+    MarkupKind, MessageType, NumberOrString, OneOf, Registration,
+    RelatedFullDocumentDiagnosticReport, RenameFilesParams, SaveOptions, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions, TextEdit, Uri,
+    WorkDoneProgressBegin, WorkDoneProgressCancelParams, WorkDoneProgressCreateParams,
+    WorkDoneProgressEnd, WorkDoneProgressReport, WorkspaceEdit,
+    WorkspaceFileOperationsServerCapabilities, WorkspaceServerCapabilities,
 };
-use tower_lsp_server::{jsonrpc, Client, LanguageServer, LspService, Server};
+use tower_lsp_server::{jsonrpc, ClientSocket, LanguageServer, LspService, Server, UriExt};
 
+use add::{AddArgs, AddResult};
+use analysis::{Analysis, AnalyzedDependency, PickArgs, PickResult, UpdateActionData, UpdateArgs};
+use api::CrateMetadata;
+use cache::VersionCache;
+use caps::ClientCaps;
+use cargo::{CargoCommand, RunCargoArgs, RunCargoResult};
+#[cfg(feature = "changelog")]
+use changelog::ChangelogArgs;
+use clock::{Clock, TokioClock};
+use config::{Config, ScanScope};
+use context::{ContextSummary, Contexts};
+use documents::Documents;
+use dry_run::Expect;
+use error::{Result, ServerError};
+use everywhere::{EverywhereArgs, EverywhereResult};
+use exclude::Origin;
+use features::{Resolution, ResolveArgs};
+use graph::{ExportArgs, Graph};
+use health::{FsHealth, Probe};
+use index::WorkspaceIndex;
+use locality::{DocumentLocality, FileFeature};
+use manifest::{DependencyKind, SpannedManifest, SpannedWorkspace};
+use messages::{Key, Language, Locale, Message};
+use references::References;
+use registry::{IndexVersions, RegistryClient};
+use revert::Requirements;
+use scheduler::{Job, Priority, Scheduler};
+use stats::Stats;
+use text::{strip_bom, Encoding, Source};
+use throttle::ThrottledClient;
+use timing::Timings;
+use unstable::Unstable;
+use version::Releases;
+
+mod add;
+mod analysis;
 mod api;
+mod artifact;
+mod cache;
+mod cadence;
+mod caps;
+mod cargo;
+mod carry;
+#[cfg(feature = "changelog")]
+mod changelog;
+mod clock;
+mod complete;
+mod config;
+mod config_file;
+mod conflict;
+mod context;
+mod dev_only;
+mod diagnostics;
+mod documents;
+mod dry_run;
+#[cfg(feature = "metadata")]
+mod enrich;
+mod error;
+mod events;
+mod everywhere;
+mod exclude;
+mod failover;
+mod features;
+mod format;
+mod graph;
+mod health;
+mod hover;
+mod index;
+mod inherit;
+mod inline;
+mod keys;
+mod links;
+mod listen;
+mod locality;
+mod lockfile;
+mod manifest;
+mod messages;
+#[cfg(feature = "metadata")]
+mod metadata;
+mod package;
+mod partial;
+mod profile;
+#[cfg(feature = "metadata")]
+mod rate_limit;
+mod references;
+mod registry;
+mod report;
+mod resolver;
+mod revert;
+mod scan;
+mod scheduler;
+mod schema;
+mod shadow;
+mod sort;
+#[cfg(feature = "metadata")]
+mod stale;
+mod stats;
+mod suggest;
+mod text;
+mod throttle;
+mod tidy;
+mod timing;
+mod trace;
+mod unstable;
+mod version;
+mod workspace;
+
+const CRATES_IO_INDEX: &str = "https://index.crates.io";
+/// API of crates.io in the `config.json` of its index and mirrors
+const CRATES_IO: &str = "https://crates.io";
+/// Command invalidating the cached versions, optionally only of the crate given as argument
+const REFRESH: &str = "crates-io.refresh";
+/// Token of the combined progress of all analyses
+const PROGRESS_TOKEN: &str = "crates-io/analysis";
+/// Downloads and owners change slowly
+#[cfg(feature = "metadata")]
+const METADATA_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+/// Document of the scheduler job fetching the crates of a report
+const REPORT_URI: &str = "crates-io:report";
+/// Document of the scheduler job fetching the newest version of `crates-io.updateEverywhere`
+const EVERYWHERE_URI: &str = "crates-io:update-everywhere";
+/// Document of the scheduler job fetching the newest version of `crates-io.addDependency`
+const ADD_URI: &str = "crates-io:add-dependency";
+/// Document of the scheduler job fetching the crates of the workspace scan
+const SCAN_URI: &str = "crates-io:scan";
+/// Code of the diagnostic of a document whose analysis panicked
+const INTERNAL_ERROR: &str = "internal-error";
+/// Time the running analyses get to stop on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[derive(Parser, Debug, Clone)]
 struct Args {
-    #[arg(short, long, default_value = "https://index.crates.io")]
-    endpoint: String,
+    /// Registry endpoint, repeated for mirrors that are tried in order when it fails
+    #[arg(short, long, default_value = CRATES_IO_INDEX)]
+    endpoint: Vec<String>,
     #[arg(short, long, default_value = "")]
     token: String,
+    /// Print the version and the compiled features as JSON and exit
+    #[arg(long)]
+    health_check: bool,
+    /// Record the messages of the session as JSON lines to this file
+    #[arg(long)]
+    trace_file: Option<PathBuf>,
+    /// Characters of document texts kept in the trace, all by default
+    #[arg(long)]
+    trace_text_limit: Option<usize>,
+    /// Serve clients connecting to this TCP address, like `127.0.0.1:9257`, instead of stdio
+    #[arg(long)]
+    listen: Option<String>,
+    /// Serve clients connecting to this Unix domain socket instead of stdio
+    #[arg(long)]
+    socket: Option<PathBuf>,
+    /// Forward stdio to a server started with `--listen` or `--socket` at this address
+    #[arg(long)]
+    connect: Option<String>,
+    /// Cargo run by `crates-io.runCargo`, looked up on the PATH unless it is a path
+    #[arg(long, default_value = "cargo")]
+    cargo: PathBuf,
+    /// Directory of the usage statistics and the scan checkpoints, the user's cache directory
+    /// by default
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Replay the client messages of a trace and print the published diagnostics as JSON lines
+    Replay { trace: PathBuf },
+    /// Analyze a manifest and print its diagnostics as JSON lines, fails if there are errors
+    Check {
+        /// Read the manifest from stdin, `path` is only where it would be
+        #[arg(long)]
+        stdin: bool,
+        /// Manifest to analyze
+        path: Option<PathBuf>,
+    },
 }
 
+/// Optional features compiled into this binary.
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "metadata")]
+    "metadata",
+    #[cfg(feature = "changelog")]
+    "changelog",
+];
+
 struct CratesIoBackend {
-    client: Client,
-    endpoint: String,
+    client: ThrottledClient,
+    endpoints: Vec<String>,
     token: String,
-    open_docs: RwLock<HashMap<Uri, FileInfo>>,
-    cache: RwLock<HashMap<String, Vec<String>>>,
+    /// Source of the versions, replaced when the registry changes
+    registry: Arc<RwLock<Arc<dyn RegistryClient>>>,
+    /// Shared with other clients, which have to use the same registry
+    fixed_registry: bool,
+    /// Time of deadlines, timeouts, and TTLs
+    clock: Arc<dyn Clock>,
+    /// Replaced as a whole, analyses keep the configuration they started with
+    config: watch::Sender<Arc<Config>>,
+    /// Settings of the client, merged over the configuration files
+    options: RwLock<Value>,
+    /// Workspace folders, configuration files are searched up to them
+    roots: RwLock<Vec<PathBuf>>,
+    /// Parsed configuration files, `None` if malformed
+    config_files: RwLock<HashMap<PathBuf, Option<Value>>>,
+    /// Configuration files open in the editor
+    open_configs: RwLock<HashSet<Uri>>,
+    /// Open manifests, only accessed within synchronous closures
+    open_docs: Documents<FileInfo>,
+    /// Crates used by the open documents, pinned in the cache
+    references: RwLock<References>,
+    /// Dependencies of all manifests seen so far, including closed workspace members
+    index: Arc<RwLock<WorkspaceIndex>>,
+    /// Workspace context of the open and indexed manifests
+    contexts: Arc<RwLock<Contexts>>,
+    /// Document last opened, edited, or saved, whose context the workspace commands default to
+    active: RwLock<Option<Uri>>,
+    cache: Arc<RwLock<VersionCache>>,
+    /// Results of the last analysis of the open documents
+    analyses: RwLock<HashMap<Uri, Arc<Analysis>>>,
+    /// Imports in the sources of the packages of the open documents, for `checkDevOnly`
+    dev_usage: RwLock<HashMap<Uri, dev_only::Usage>>,
+    /// Phases of the last analysis of the open documents that published after a change
+    timings: RwLock<HashMap<Uri, Timings>>,
+    /// Dependency events of the open documents, with `emitEvents`
+    events: Arc<Mutex<events::Tracker>>,
+    /// Parsed `Cargo.lock` files by path
+    lockfiles: Mutex<lockfile::Cache>,
+    /// Reads beside the documents that failed in a workspace folder and are skipped since
+    fs_health: Mutex<FsHealth>,
+    /// Documents for which the BOM was already reported
+    bom_reported: RwLock<HashSet<Uri>>,
+    /// Documents outside the local file system for which the skipped features were logged
+    remote_reported: RwLock<HashSet<Uri>>,
+    /// Number of malformed index lines skipped so far
+    malformed_lines: Arc<AtomicUsize>,
+    /// Queue of the analyses, limiting concurrent fetches
+    scheduler: Scheduler,
+    /// Features of the client, selecting the fallbacks of the server
+    caps: watch::Sender<ClientCaps>,
+    /// Diagnostics of the documents for clients pulling them
+    pulled: RwLock<HashMap<Uri, Vec<lsp_types::Diagnostic>>>,
+    /// Last sequence of the diagnostics of a document, also serializing their sending
+    published: Mutex<HashMap<Uri, u64>>,
+    /// Source of the sequences, later ones replace the diagnostics of earlier ones
+    publish_seq: AtomicU64,
+    /// Whether the combined analysis progress is shown
+    progress_active: Mutex<bool>,
+    /// Locale sent by the client in `initialize`
+    client_locale: OnceLock<String>,
+    /// Crate metadata for hovers with the time it was fetched, dropped with the cached versions
+    metadata: Arc<RwLock<HashMap<String, (Instant, CrateMetadata)>>>,
+    /// Source of the crate metadata
+    #[cfg(feature = "metadata")]
+    api: Arc<dyn metadata::MetadataClient>,
+    /// Crates of the open documents whose metadata is fetched in the background
+    #[cfg(feature = "metadata")]
+    enrichment: RwLock<enrich::Pending>,
+    /// Feature maps of the crates with feature hints, fetched once they are declared and
+    /// dropped with the cached versions
+    feature_maps: Arc<RwLock<HashMap<String, suggest::FeatureMaps>>>,
+    /// Registry checksums by crate and version, fetched with the feature maps
+    checksums: Arc<RwLock<HashMap<String, HashMap<String, String>>>>,
+    /// Usage statistics of the process, only counted with `stats`
+    stats: Arc<Stats>,
+    /// Number of `crates-io/analyze` requests, each has a job of its own
+    inline_requests: AtomicU64,
+    /// Cargo binary of `crates-io.runCargo`
+    cargo: PathBuf,
+    /// Serializes the cargo commands of each workspace root
+    cargo_runs: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+    /// Directory of the scan checkpoints, scans are not resumed across sessions without one
+    cache_dir: Option<PathBuf>,
+    /// The workspace scan, see [`CratesIoBackend::scan`]
+    scanner: Mutex<scan::Scanner>,
+    /// End of the pause of the scan after the last edit of a document
+    typing_until: RwLock<Option<Instant>>,
+    /// Cancelled by the `shutdown` request, stops the analyses and background tasks
+    shutdown: CancellationToken,
+}
+
+/// State of the connections of one server process, the rest is per client.
+#[derive(Clone)]
+struct Shared {
+    registry: Arc<RwLock<Arc<dyn RegistryClient>>>,
+    index: Arc<RwLock<WorkspaceIndex>>,
+    contexts: Arc<RwLock<Contexts>>,
+    cache: Arc<RwLock<VersionCache>>,
+    malformed_lines: Arc<AtomicUsize>,
+    metadata: Arc<RwLock<HashMap<String, (Instant, CrateMetadata)>>>,
+    #[cfg(feature = "metadata")]
+    api: Arc<dyn metadata::MetadataClient>,
+    feature_maps: Arc<RwLock<HashMap<String, suggest::FeatureMaps>>>,
+    checksums: Arc<RwLock<HashMap<String, HashMap<String, String>>>>,
+    stats: Arc<Stats>,
+}
+
+impl Shared {
+    fn new(registry: Arc<dyn RegistryClient>) -> Self {
+        Self {
+            registry: Arc::new(RwLock::new(registry)),
+            index: Default::default(),
+            contexts: Default::default(),
+            cache: Default::default(),
+            malformed_lines: Default::default(),
+            metadata: Default::default(),
+            #[cfg(feature = "metadata")]
+            api: Arc::new(metadata::CratesIoApi),
+            feature_maps: Default::default(),
+            checksums: Default::default(),
+            stats: Default::default(),
+        }
+    }
 }
 
 impl LanguageServer for CratesIoBackend {
     async fn initialize(&self, params: InitializeParams) -> jsonrpc::Result<InitializeResult> {
-        self.client
-            .log_message(
-                MessageType::INFO,
-                format!("Init {:?}", params.initialization_options),
-            )
-            .await;
-        Ok(InitializeResult {
-            capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
-                )),
-                diagnostic_provider: Some(
-                    DiagnosticServerCapabilities::Options(Default::default()),
-                ),
+        self.guarded("initialize", None, async move {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("Init {:?}", params.initialization_options),
+                )
+                .await;
+            if let Some(locale) = params.locale {
+                let _ = self.client_locale.set(locale);
+            }
+            if let Some(options) = params.initialization_options {
+                self.update_config(options).await;
+            } else {
+                let language = self.language(&self.config.borrow());
+                self.config
+                    .send_modify(|config| Arc::make_mut(config).language = language);
+            }
+            let folders = params.workspace_folders.unwrap_or_default();
+            #[allow(deprecated)]
+            let roots = folders
+                .iter()
+                .map(|f| &f.uri)
+                .chain(params.root_uri.as_ref())
+                .filter_map(|uri| match DocumentLocality::from_uri(uri) {
+                    DocumentLocality::Local(path) => Some(path),
+                    _ => None,
+                })
+                .collect();
+            *self.roots.write().await = roots;
+            let caps = ClientCaps::new(&params.capabilities);
+            self.caps.send_replace(caps);
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("Client capabilities: {}", serde_json::json!(caps)),
+                )
+                .await;
+            Ok(InitializeResult {
+                capabilities: ServerCapabilities {
+                    position_encoding: Some(caps.position_encoding.kind()),
+                    text_document_sync: Some(if caps.watched_files_registration {
+                        TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)
+                    } else {
+                        // Saved configuration files are reloaded instead of watched ones
+                        TextDocumentSyncCapability::Options(TextDocumentSyncOptions {
+                            open_close: Some(true),
+                            change: Some(TextDocumentSyncKind::FULL),
+                            save: Some(
+                                SaveOptions {
+                                    include_text: Some(true),
+                                }
+                                .into(),
+                            ),
+                            ..Default::default()
+                        })
+                    }),
+                    diagnostic_provider: caps.pull_diagnostics.then(|| {
+                        DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                            identifier: Some("crates-io".into()),
+                            // Members inherit from the workspace root
+                            inter_file_dependencies: true,
+                            ..Default::default()
+                        })
+                    }),
+                    workspace: Some(WorkspaceServerCapabilities {
+                        workspace_folders: None,
+                        file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                            did_rename: Some(file_operations()),
+                            did_delete: Some(file_operations()),
+                            ..Default::default()
+                        }),
+                    }),
+                    code_action_provider: Some(CodeActionProviderCapability::Options(
+                        CodeActionOptions {
+                            resolve_provider: Some(true),
+                            ..Default::default()
+                        },
+                    )),
+                    hover_provider: Some(HoverProviderCapability::Simple(true)),
+                    completion_provider: Some(CompletionOptions {
+                        trigger_characters: Some(
+                            complete::TRIGGER_CHARACTERS
+                                .iter()
+                                .map(|c| c.to_string())
+                                .collect(),
+                        ),
+                        ..Default::default()
+                    }),
+                    document_symbol_provider: Some(OneOf::Left(true)),
+                    definition_provider: Some(OneOf::Left(true)),
+                    document_range_formatting_provider: Some(OneOf::Left(true)),
+                    document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                        first_trigger_character: tidy::TRIGGER_CHARACTERS[0].into(),
+                        more_trigger_character: Some(
+                            tidy::TRIGGER_CHARACTERS[1..]
+                                .iter()
+                                .map(|c| c.to_string())
+                                .collect(),
+                        ),
+                    }),
+                    code_lens_provider: Some(CodeLensOptions {
+                        resolve_provider: Some(true),
+                    }),
+                    execute_command_provider: Some(ExecuteCommandOptions {
+                        commands: vec![
+                            analysis::APPLY_UPDATE.into(),
+                            analysis::PICK_VERSION.into(),
+                            analysis::APPLY_VERSION.into(),
+                            REFRESH.into(),
+                            graph::EXPORT_GRAPH.into(),
+                            index::WHO_USES.into(),
+                            context::CONTEXTS.into(),
+                            schema::CONFIG.into(),
+                            scan::SCAN_START.into(),
+                            scan::SCAN_CANCEL.into(),
+                            scan::SCAN_STATUS.into(),
+                            features::RESOLVE_FEATURES.into(),
+                            report::REPORT.into(),
+                            everywhere::UPDATE_EVERYWHERE.into(),
+                            add::ADD_DEPENDENCY.into(),
+                            cargo::RUN_CARGO.into(),
+                            stats::STATS.into(),
+                            stats::RESET_STATS.into(),
+                            timing::TIMINGS.into(),
+                            events::SNAPSHOT.into(),
+                            #[cfg(feature = "changelog")]
+                            changelog::CHANGELOG.into(),
+                            #[cfg(feature = "metadata")]
+                            enrich::STATUS.into(),
+                        ],
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
                 ..Default::default()
-            },
-            ..Default::default()
+            })
         })
+        .await
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        self.spawn_stats_flush();
+        let caps = *self.caps.borrow();
+        let mut registrations = Vec::new();
+        if caps.watched_files_registration {
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: vec![
+                    FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(config_file::GLOB.into()),
+                        kind: None,
+                    },
+                    FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(lockfile::GLOB.into()),
+                        kind: None,
+                    },
+                ],
+            };
+            registrations.push(Registration {
+                id: "crates-io-config-files".into(),
+                method: "workspace/didChangeWatchedFiles".into(),
+                register_options: serde_json::to_value(options).ok(),
+            });
+        } else {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    "Configuration files are reloaded when saved in the editor",
+                )
+                .await;
+        }
+        if caps.configuration_registration {
+            registrations.push(Registration {
+                id: "crates-io-configuration".into(),
+                method: "workspace/didChangeConfiguration".into(),
+                register_options: None,
+            });
+        }
+        if registrations.is_empty() {
+            return;
+        }
+        if let Err(err) = self.client.register_capability(registrations).await {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("Configuration files are not watched: {err}"),
+                )
+                .await;
+        }
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
@@ -64,22 +560,41 @@ impl LanguageServer for CratesIoBackend {
                 ),
             )
             .await;
+        let uri = &params.text_document.uri;
+        if config_file::is_config_uri(uri) {
+            self.open_configs.write().await.insert(uri.clone());
+            let text = &params.text_document.text;
+            self.report_config_file(uri, text, &config_file::parse(text))
+                .await;
+            return;
+        }
         if !is_cargo_toml(&params.text_document.uri) {
             return;
         }
+        *self.active.write().await = Some(uri.clone());
 
-        self.open_docs.write().await.insert(
-            params.text_document.uri.clone(),
-            FileInfo::new(
+        let source;
+        {
+            // Held until the sequence is recorded, a close sending its clear waits for it
+            let mut published = self.published.lock().await;
+            let doc = FileInfo::new(
                 params.text_document.text.clone(),
                 params.text_document.version,
-            ),
-        );
+                self.caps.borrow().position_encoding,
+            );
+            source = doc.source.clone();
+            // Cancels the clear of a close that was not sent yet
+            let seq = self
+                .open_docs
+                .insert_with(uri.clone(), doc, || self.next_publish());
+            published.insert(uri.clone(), seq);
+        }
 
         self.update_diagnostics(
             &params.text_document.uri,
             Some(params.text_document.version),
-            &params.text_document.text,
+            &source,
+            Priority::Interactive,
         )
         .await;
     }
@@ -98,19 +613,39 @@ impl LanguageServer for CratesIoBackend {
         if !is_cargo_toml(&params.text_document.uri) {
             return;
         }
+        *self.active.write().await = Some(params.text_document.uri.clone());
+        *self.typing_until.write().await = Some(self.clock.now() + scan::TYPING_PAUSE);
 
-        let mut open_docs = self.open_docs.write().await;
-        let doc = open_docs.get_mut(&params.text_document.uri).unwrap();
-        for change in params.content_changes {
-            if let Some(range) = change.range {
-                let start = pos_to_offset(&doc.text, range.start).unwrap();
-                let end = pos_to_offset(&doc.text, range.end).unwrap();
-                doc.text.replace_range(start..end, &change.text);
-            } else {
-                doc.text = change.text;
+        let changed = self.open_docs.with_mut(&params.text_document.uri, |doc| {
+            // The edits are recorded with the version they lead to
+            doc.version = params.text_document.version;
+            for change in params.content_changes {
+                if let Some(range) = change.range {
+                    let lines = &doc.source.lines;
+                    let (Some(start), Some(end)) = (
+                        lines.position_to_offset(range.start),
+                        lines.position_to_offset(range.end),
+                    ) else {
+                        return Err(range);
+                    };
+                    doc.replace(Some(start..end.max(start)), &change.text);
+                } else {
+                    doc.replace(None, &change.text);
+                }
             }
-        }
-        doc.version = params.text_document.version;
+            Ok(())
+        });
+        let message = match changed {
+            Some(Ok(())) => return,
+            Some(Err(range)) => {
+                format!("Change out of range {range:?}, the document is out of sync")
+            }
+            None => format!(
+                "Change of a closed document {}",
+                params.text_document.uri.as_str()
+            ),
+        };
+        self.client.log_message(MessageType::ERROR, message).await;
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
@@ -120,22 +655,62 @@ impl LanguageServer for CratesIoBackend {
                 format!("DidSave: {}", params.text_document.uri.as_str()),
             )
             .await;
-        if !is_cargo_toml(&params.text_document.uri) {
+        let uri = &params.text_document.uri;
+        if config_file::is_config_uri(uri) && !self.caps.borrow().watched_files_registration {
+            let changes = vec![FileEvent::new(uri.clone(), FileChangeType::CHANGED)];
+            self.did_change_watched_files(DidChangeWatchedFilesParams { changes })
+                .await;
+            return;
+        }
+        if !is_cargo_toml(uri) {
             return;
         }
+        *self.active.write().await = Some(uri.clone());
 
-        let mut open_docs = self.open_docs.write().await;
-        let doc = open_docs.get_mut(&params.text_document.uri);
-        let (text, version) = if let (Some(doc), Some(text)) = (doc, &params.text) {
-            doc.text = text.clone();
-            (&doc.text, Some(doc.version))
-        } else if let Some(text) = &params.text {
-            (text, None)
-        } else {
+        let saved = self.open_docs.with_mut(&params.text_document.uri, |doc| {
+            if let Some(text) = &params.text {
+                doc.replace(None, text);
+            }
+            doc.save();
+            (doc.version, doc.source.clone())
+        });
+        let Some(text) = params.text else {
             return;
         };
-        self.update_diagnostics(&params.text_document.uri, version, text)
-            .await;
+        let (version, source) = match saved {
+            Some((version, source)) => (Some(version), source),
+            None => (None, Arc::new(self.source(&text))),
+        };
+        self.update_diagnostics(
+            &params.text_document.uri,
+            version,
+            &source,
+            Priority::Interactive,
+        )
+        .await;
+
+        // Members inherit from the workspace root
+        if workspace::is_root(&text) {
+            let locality = DocumentLocality::from_uri(&params.text_document.uri);
+            let dir = locality
+                .path_for(FileFeature::Workspace)
+                .and_then(Path::parent);
+            if let Some((root, dir)) = dir.and_then(|root| Some((root, Uri::from_file_path(root)?)))
+            {
+                // Nested workspaces below the root are contexts of their own
+                let mut members = Vec::new();
+                for uri in self.open_below(&dir).await {
+                    if uri != params.text_document.uri
+                        && self.context_root(&uri).await.as_deref() == Some(root)
+                    {
+                        members.push(uri);
+                    }
+                }
+                if !members.is_empty() {
+                    self.reanalyze(Some(&members), Priority::Background).await;
+                }
+            }
+        }
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
@@ -145,229 +720,10514 @@ impl LanguageServer for CratesIoBackend {
                 format!("DidClose: {}", params.text_document.uri.as_str()),
             )
             .await;
-        if !is_cargo_toml(&params.text_document.uri) {
+        if config_file::is_config_uri(&params.text_document.uri) {
+            let mut open_configs = self.open_configs.write().await;
+            open_configs.remove(&params.text_document.uri);
+        } else if !is_cargo_toml(&params.text_document.uri) {
             return;
         }
 
-        // Clear diagnostics for the closed document
-        self.client
-            .publish_diagnostics(params.text_document.uri.clone(), Vec::new(), None)
-            .await;
-
-        let mut open_docs = self.open_docs.write().await;
-        open_docs.remove(&params.text_document.uri);
+        self.forget(&params.text_document.uri).await;
+        let uri = &params.text_document.uri;
+        let mut active = self.active.write().await;
+        if active.as_ref() == Some(uri) {
+            *active = None;
+        }
+        // Indexed manifests stay in their context until they are deleted
+        if !self.index.read().await.contains(uri) {
+            self.contexts.write().await.detach(uri);
+        }
     }
 
-    async fn shutdown(&self) -> jsonrpc::Result<()> {
-        self.client.log_message(MessageType::INFO, "Shutdown").await;
-        Ok(())
+    async fn did_rename_files(&self, params: RenameFilesParams) {
+        for rename in params.files {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("DidRename: {} -> {}", rename.old_uri, rename.new_uri),
+                )
+                .await;
+            let (Ok(old), Ok(new)) = (rename.old_uri.parse::<Uri>(), rename.new_uri.parse::<Uri>())
+            else {
+                continue;
+            };
+            self.index.write().await.rename_below(&old, &new);
+            // Attached again once they are analyzed or scanned
+            self.contexts.write().await.detach_below(&old);
+            // Directory renames move all documents below them
+            for uri in self.open_below(&old).await {
+                let moved = uri.as_str().replacen(old.as_str(), new.as_str(), 1);
+                let Ok(moved) = moved.parse::<Uri>() else {
+                    continue;
+                };
+                self.rename(&uri, moved).await;
+            }
+        }
     }
-}
 
-impl CratesIoBackend {
-    async fn update_diagnostics(&self, uri: &Uri, version: Option<i32>, text: &str) {
-        match self.collect_diagnostics(text).await {
-            Ok(diagnostics) => {
-                self.client
-                    .publish_diagnostics(uri.clone(), diagnostics, version)
-                    .await
-            }
-            Err(err) => {
-                self.client
-                    .log_message(MessageType::ERROR, format!("Failed diagnostics: {err}"))
-                    .await
+    async fn did_delete_files(&self, params: DeleteFilesParams) {
+        for delete in params.files {
+            self.client
+                .log_message(MessageType::INFO, format!("DidDelete: {}", delete.uri))
+                .await;
+            let Ok(deleted) = delete.uri.parse::<Uri>() else {
+                continue;
+            };
+            self.index.write().await.remove_below(&deleted);
+            self.contexts.write().await.detach_below(&deleted);
+            for uri in self.open_below(&deleted).await {
+                self.forget(&uri).await;
             }
         }
     }
 
-    async fn collect_diagnostics(&self, text: &str) -> Result<Vec<lsp_types::Diagnostic>> {
-        let parsed: SpannedManifest = toml::from_str(text)?;
-        let deps = parsed
-            .dependencies
-            .iter()
-            .chain(parsed.build_dependencies.iter())
-            .chain(parsed.dev_dependencies.iter())
-            // Filter out relative dependencies
-            .filter(|d| !d.1.detail().is_some_and(|d| d.path.is_some()))
-            .collect::<Vec<_>>();
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!("DidChangeConfiguration: {:?}", params.settings),
+            )
+            .await;
+        // Settings might be namespaced by the server name
+        let settings = match params.settings {
+            Value::Object(mut map) if map.contains_key("crates-io") => {
+                map.remove("crates-io").unwrap()
+            }
+            settings => settings,
+        };
+        self.update_config(settings).await;
+        self.restart_analyses().await;
+    }
 
-        let dep_names = deps
-            .iter()
-            .map(|(name, _)| name.as_ref().to_string())
-            .collect::<Vec<_>>();
-        // Fetch versions for dependencies (in parallel)
-        let dep_versions = self.get_versions(dep_names).await;
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let mut changed = false;
+        let mut lockfiles = Vec::new();
+        for change in params.changes {
+            let locality = DocumentLocality::from_uri(&change.uri);
+            let Some(path) = locality.path_for(FileFeature::WatchedFiles) else {
+                continue;
+            };
+            self.fs_health.lock().await.changed(path);
+            if lockfile::is_lockfile(path) {
+                // Read again even if the modification time is the same
+                self.lockfiles.lock().await.remove(path);
+                lockfiles.push(path.to_path_buf());
+                continue;
+            }
+            if !config_file::is_config_file(path) {
+                continue;
+            }
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("Configuration changed: {}", path.display()),
+                )
+                .await;
+            self.config_files.write().await.remove(path);
+            if change.typ != FileChangeType::DELETED {
+                // Reload now to report errors
+                self.load_config_file(path).await;
+            }
+            changed = true;
+        }
+        if changed {
+            self.restart_analyses().await;
+        } else {
+            for path in lockfiles {
+                self.relock(&path).await;
+            }
+        }
+    }
 
-        let mut diagnostics = Vec::new();
-        for (name, mut versions) in dep_versions {
-            versions.reverse();
+    async fn code_lens(&self, params: CodeLensParams) -> jsonrpc::Result<Option<Vec<CodeLens>>> {
+        let uri = params.text_document.uri.clone();
+        self.guarded("textDocument/codeLens", Some(&uri), async move {
+            let uri = params.text_document.uri;
+            let (Some(source), Some(analysis)) = (
+                self.open_docs.with(&uri, |doc| doc.source.clone()),
+                self.analyses.read().await.get(&uri).cloned(),
+            ) else {
+                return Ok(None);
+            };
+            let config = self.document_config(&uri).await;
+            Ok(Some(analysis.code_lenses(&uri, &source, &config)))
+        })
+        .await
+    }
 
-            let (name, info) = deps.iter().find(|(n, _)| n.as_ref() == &name).unwrap();
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> jsonrpc::Result<DocumentDiagnosticReportResult> {
+        let uri = params.text_document.uri.clone();
+        self.guarded("textDocument/diagnostic", Some(&uri), async move {
+            let items = self
+                .pulled
+                .read()
+                .await
+                .get(&params.text_document.uri)
+                .cloned()
+                .unwrap_or_default();
+            let report = RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: None,
+                    items,
+                },
+            };
+            Ok(DocumentDiagnosticReport::Full(report).into())
+        })
+        .await
+    }
 
-            let range = if let (Some(start), Some(end)) = (
-                offset_to_pos(text, name.span().start),
-                offset_to_pos(text, name.span().end),
-            ) {
-                lsp_types::Range { start, end }
+    async fn hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
+        let uri = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .clone();
+        self.guarded("textDocument/hover", Some(&uri), async move {
+            let position = params.text_document_position_params;
+            let Some(analysis) = self
+                .analyses
+                .read()
+                .await
+                .get(&position.text_document.uri)
+                .cloned()
+            else {
+                return Ok(None);
+            };
+            let pos = position.position;
+            let language = self
+                .document_config(&position.text_document.uri)
+                .await
+                .language;
+            let contains = |range: &lsp_types::Range| range.start <= pos && pos <= range.end;
+            if let Some((range, value)) = analysis.inherited.iter().find(|(r, _)| contains(r)) {
+                return Ok(Some(Hover {
+                    contents: self.hover_contents(value.clone()),
+                    range: Some(*range),
+                }));
+            }
+            let on_req = |d: &&AnalyzedDependency| d.req_range.is_some_and(|r| contains(&r));
+            if let Some(dep) = analysis.dependencies.iter().find(on_req) {
+                let cache = self.cache.read().await;
+                let versions = cache.get(&dep.package).map_or(&[][..], |v| &v[..]);
+                let mut value = hover::render_requirement(&dep.req, versions);
+                value += &self.freshness(&cache, &dep.package, language);
+                return Ok(Some(Hover {
+                    contents: self.hover_contents(value),
+                    range: dep.req_range,
+                }));
+            }
+            let features = analysis.features.iter().find(|(r, _)| contains(r));
+            // Profile overrides show the same information as the dependency
+            let (package, range) = if let Some(dep) = analysis
+                .dependencies
+                .iter()
+                .find(|d| contains(&d.name_range))
+            {
+                (dep.package.as_str(), dep.name_range)
+            } else if let Some((name, range)) = analysis.overrides.iter().find(|(_, r)| contains(r))
+            {
+                (name.as_str(), *range)
+            } else if let Some((range, value)) = features {
+                return Ok(Some(Hover {
+                    contents: self.hover_contents(value.clone()),
+                    range: Some(*range),
+                }));
             } else {
-                continue; // Outside the document?
+                return Ok(None);
             };
-
-            let (message, severity) = if !versions.is_empty() {
-                let (prefix, severity) = if info.req() == "*" {
-                    ("Matches any Version", DiagnosticSeverity::INFORMATION)
-                } else if let Some(pos) = versions.iter().position(|v| v.starts_with(info.req())) {
-                    if pos == 0 {
-                        ("Latest Version", DiagnosticSeverity::HINT)
-                    } else {
-                        ("Outdated Version", DiagnosticSeverity::WARNING)
-                    }
+            #[cfg(not(feature = "metadata"))]
+            let metadata = None;
+            #[cfg(feature = "metadata")]
+            let metadata = {
+                let config = self.document_config(&position.text_document.uri).await;
+                if config.metadata && self.crates_io(&config).await && api::is_valid_name(package) {
+                    self.crate_metadata(package, &config, Priority::Interactive)
+                        .await
                 } else {
-                    ("Unknown Version", DiagnosticSeverity::ERROR)
+                    None
+                }
+            };
+            let mut value = match analysis.dependencies.iter().find(|d| d.package == package) {
+                Some(dep) => hover::render(dep, metadata.as_ref()),
+                None => hover::render_package(package, metadata.as_ref()),
+            };
+            #[cfg(feature = "metadata")]
+            if let Some(released) = metadata.as_ref().and_then(|m| m.released.as_deref()) {
+                let config = self.document_config(&position.text_document.uri).await;
+                if let Some(note) = stale::hover(package, released, self.clock.today(), &config) {
+                    value += &format!("\n\n{note}");
+                }
+            }
+            #[cfg(feature = "metadata")]
+            if metadata.is_none() {
+                let uri = &position.text_document.uri;
+                let pending = self.enrichment.read().await.contains(uri, package);
+                let status = self.enrichment_status().await;
+                let wait = status.documents.iter().find(|d| d.uri == *uri);
+                if let (true, Some(wait)) = (pending, wait) {
+                    let note = Message::new(Key::MetadataPendingHover)
+                        .arg(format::duration(wait.wait_ms.div_ceil(1000)))
+                        .render(language);
+                    value += &hover::footer(&note);
+                }
+            }
+            if let Some((_, features)) = features.filter(|(r, _)| *r == range) {
+                value += &format!("\n\n{features}");
+            }
+            let locked = analysis
+                .dependencies
+                .iter()
+                .find(|d| d.package == package && !d.git);
+            if let Some(dep) = locked {
+                let config = self.document_config(&position.text_document.uri).await;
+                let lockfile = match config.show_checksums {
+                    true => self.lockfile(analysis.lockfile.as_deref()).await,
+                    false => None,
                 };
-                let message = format!(
-                    "{prefix}\n\n{} ({})\n{}",
-                    name.as_ref(),
-                    info.req(),
-                    versions.join("\n")
-                );
+                if let Some(lockfile) = lockfile {
+                    let version = lockfile.locked(package, &dep.req);
+                    let recorded = version.and_then(|v| Some((v, lockfile.checksum(package, v)?)));
+                    if let Some((version, checksum)) = recorded {
+                        value += &hover::checksum(version, checksum);
+                    }
+                }
+            }
+            value += &self.freshness(&*self.cache.read().await, package, language);
+            Ok(Some(Hover {
+                contents: self.hover_contents(value),
+                range: Some(range),
+            }))
+        })
+        .await
+    }
 
-                (message, severity)
-            } else {
-                self.client
-                    .log_message(
-                        MessageType::ERROR,
-                        format!(
-                            "Failed to fetch versions for {}:\n{:?}",
-                            name.as_ref(),
-                            info
-                        ),
-                    )
-                    .await;
-                (
-                    format!("Failed to fetch versions for {}", name.as_ref()),
-                    DiagnosticSeverity::ERROR,
-                )
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> jsonrpc::Result<Option<DocumentSymbolResponse>> {
+        let uri = params.text_document.uri.clone();
+        self.guarded("textDocument/documentSymbol", Some(&uri), async move {
+            let analyses = self.analyses.read().await;
+            let Some(analysis) = analyses.get(&params.text_document.uri) else {
+                return Ok(None);
             };
+            Ok(Some(DocumentSymbolResponse::Nested(workspace::symbols(
+                &analysis.members,
+            ))))
+        })
+        .await
+    }
 
-            diagnostics.push(lsp_types::Diagnostic {
-                range,
-                severity: Some(severity),
-                source: Some("crates-io".into()),
-                message,
-                ..Default::default()
-            });
-        }
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> jsonrpc::Result<Option<GotoDefinitionResponse>> {
+        let uri = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .clone();
+        self.guarded("textDocument/definition", Some(&uri), async move {
+            let position = params.text_document_position_params;
+            let analyses = self.analyses.read().await;
+            let Some(analysis) = analyses.get(&position.text_document.uri) else {
+                return Ok(None);
+            };
+            let locations = workspace::definition(&analysis.members, position.position);
+            Ok((!locations.is_empty()).then_some(GotoDefinitionResponse::Array(locations)))
+        })
+        .await
+    }
 
-        Ok(diagnostics)
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri.clone();
+        self.guarded("textDocument/rangeFormatting", Some(&uri), async move {
+            let uri = params.text_document.uri;
+            let config = self.document_config(&uri).await;
+            let Some((source, span)) = self.open_docs.with(&uri, |d| {
+                let span = d.source.lines.range_to_span(params.range);
+                (d.source.clone(), span)
+            }) else {
+                return Ok(None);
+            };
+            let Some(span) = span else {
+                return Ok(None);
+            };
+            let edits = tidy::edits(&source, span, &config);
+            Ok(self.guard_formatting(&uri, &source, edits).await)
+        })
+        .await
     }
 
-    pub async fn get_versions(&self, names: Vec<String>) -> Vec<(String, Vec<String>)> {
-        let mut set = tokio::task::JoinSet::new();
-        let mut results = Vec::new();
-        {
-            // Read access
-            let cache = self.cache.read().await;
-            for name in names {
-                if let Some(versions) = cache.get(&name) {
-                    results.push((name, versions.clone()));
-                } else {
-                    let endpoint = self.endpoint.clone();
-                    let token = self.token.clone();
-                    set.spawn(async move {
-                        let versions = api::fetch_versions(&name, &endpoint, &token).await;
-                        (name, versions)
-                    });
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document_position.text_document.uri.clone();
+        self.guarded("textDocument/onTypeFormatting", Some(&uri), async move {
+            let position = params.text_document_position;
+            let uri = position.text_document.uri;
+            let config = self.document_config(&uri).await;
+            let Some((source, offset)) = self.open_docs.with(&uri, |d| {
+                let offset = d.source.lines.position_to_offset(position.position);
+                (d.source.clone(), offset)
+            }) else {
+                return Ok(None);
+            };
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+            let edits = tidy::edits(&source, offset..offset, &config);
+            Ok(self.guard_formatting(&uri, &source, edits).await)
+        })
+        .await
+    }
+
+    async fn completion(
+        &self,
+        params: CompletionParams,
+    ) -> jsonrpc::Result<Option<CompletionResponse>> {
+        let uri = params.text_document_position.text_document.uri.clone();
+        self.guarded("textDocument/completion", Some(&uri), async move {
+            let position = params.text_document_position;
+            let Some((source, offset)) = self.open_docs.with(&position.text_document.uri, |d| {
+                let offset = d.source.lines.position_to_offset(position.position);
+                (d.source.clone(), offset)
+            }) else {
+                return Ok(None);
+            };
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+            let config = self.document_config(&position.text_document.uri).await;
+            let snippets = self.caps.borrow().snippets;
+            let suggested = |package: &str| suggest::completion(package, &config);
+            let mut items = complete::keys(&source, offset, snippets, suggested);
+            if items.is_empty() {
+                let uri = &position.text_document.uri;
+                if let Some(lockfile) = self.document_lockfile(uri, &source.text).await {
+                    let style = config.requirement_style;
+                    let requirement =
+                        |locked: &semver::Version| version::requirement(locked, style, "");
+                    items = complete::names(&source, offset, &lockfile, requirement);
                 }
             }
-        }
-        let joined = set.join_all().await;
-        if !joined.is_empty() {
-            // Lock only if necessary
-            let mut cache = self.cache.write().await;
-            for (name, versions) in joined {
-                match versions {
-                    Ok(versions) => {
-                        cache.insert(name.clone(), versions.clone());
-                        results.push((name, versions));
+            Ok((!items.is_empty()).then_some(CompletionResponse::Array(items)))
+        })
+        .await
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> jsonrpc::Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri.clone();
+        self.guarded("textDocument/codeAction", Some(&uri), async move {
+            let uri = params.text_document.uri;
+            let config = self.document_config(&uri).await;
+            let analysis = self.analyses.read().await.get(&uri).cloned();
+            let cache = self.cache.read().await;
+            let mut actions = Vec::new();
+            // Dependencies whose update actions come with their new major
+            let majors = params
+                .context
+                .diagnostics
+                .iter()
+                .filter(|d| d.code == Some(NumberOrString::String(version::MAJOR_AVAILABLE.into())))
+                .map(|d| d.range)
+                .collect::<Vec<_>>();
+            let cargo_allowed = config.allow_cargo_commands
+                && DocumentLocality::from_uri(&uri)
+                    .path_for(FileFeature::Workspace)
+                    .is_some();
+            // Diagnostics of `cargo update`, offered once after the other actions
+            let mut compatible = Vec::new();
+            for d in params.context.diagnostics {
+                let Some(NumberOrString::String(code)) = &d.code else {
+                    continue;
+                };
+                // Quick fixes with the edit in the diagnostic data
+                let fix = [
+                    workspace::PREFER_WORKSPACE,
+                    keys::UNKNOWN_KEY,
+                    keys::MISTYPED_VALUE,
+                    resolver::RESOLVER_FEATURES,
+                    version::PRERELEASE_REQUIREMENT,
+                    version::ZERO_MAJOR_REQUIREMENT,
+                    shadow::SHADOWED_CRATE,
+                    artifact::INVALID_ARTIFACT,
+                    unstable::UNKNOWN_CARGO_FEATURE,
+                ]
+                .contains(&code.as_str());
+                if fix {
+                    let Some(edit) = d
+                        .data
+                        .clone()
+                        .and_then(|data| serde_json::from_value::<TextEdit>(data).ok())
+                    else {
+                        continue;
+                    };
+                    let title = match code.as_str() {
+                        workspace::PREFER_WORKSPACE => {
+                            "Inherit from the workspace (`workspace = true`)".to_string()
+                        }
+                        keys::UNKNOWN_KEY => format!("Rename to `{}`", edit.new_text),
+                        keys::MISTYPED_VALUE => format!("Change to `{}`", edit.new_text),
+                        version::PRERELEASE_REQUIREMENT => {
+                            format!("Use the stable release `{}`", edit.new_text)
+                        }
+                        version::ZERO_MAJOR_REQUIREMENT => {
+                            format!("Pin the minor `{}`", edit.new_text)
+                        }
+                        shadow::SHADOWED_CRATE => "Use the workspace member (`path`)".to_string(),
+                        artifact::INVALID_ARTIFACT => format!("Use the artifact {}", edit.new_text),
+                        unstable::UNKNOWN_CARGO_FEATURE => {
+                            format!("Use the cargo feature {}", edit.new_text)
+                        }
+                        _ => "Use resolver 2 (`resolver = \"2\"`)".to_string(),
+                    };
+                    let expect = match code.as_str() {
+                        workspace::PREFER_WORKSPACE => Expect::Inherited,
+                        version::PRERELEASE_REQUIREMENT | version::ZERO_MAJOR_REQUIREMENT => {
+                            Expect::Requirement
+                        }
+                        // Misspelled sections take their dependencies along
+                        keys::UNKNOWN_KEY => Expect::Parses,
+                        _ => Expect::Edited,
+                    };
+                    let action = CodeAction {
+                        title,
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![d]),
+                        edit: Some(WorkspaceEdit {
+                            changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                            ..Default::default()
+                        }),
+                        is_preferred: Some(true),
+                        ..Default::default()
+                    };
+                    actions.push((CodeActionOrCommand::CodeAction(action), expect));
+                } else if code == suggest::MISSING_FEATURE {
+                    let Some(fix) = d
+                        .data
+                        .clone()
+                        .and_then(|data| serde_json::from_value::<suggest::FeatureFix>(data).ok())
+                    else {
+                        continue;
+                    };
+                    let action = CodeAction {
+                        title: suggest::title(&fix.features),
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![d]),
+                        edit: Some(WorkspaceEdit {
+                            changes: Some(HashMap::from([(uri.clone(), vec![fix.edit])])),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    };
+                    actions.push((CodeActionOrCommand::CodeAction(action), Expect::Edited));
+                } else if code == dev_only::DEV_ONLY_DEPENDENCY {
+                    let Some(edits) = d
+                        .data
+                        .clone()
+                        .and_then(|data| serde_json::from_value::<Vec<TextEdit>>(data).ok())
+                    else {
+                        continue;
+                    };
+                    let action = CodeAction {
+                        title: "Move to `[dev-dependencies]`".into(),
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![d]),
+                        edit: Some(WorkspaceEdit {
+                            changes: Some(HashMap::from([(uri.clone(), edits)])),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    };
+                    actions.push((CodeActionOrCommand::CodeAction(action), Expect::Edited));
+                } else if [
+                    "outdated-compatible",
+                    "outdated-incompatible",
+                    version::MAJOR_AVAILABLE,
+                ]
+                .contains(&code.as_str())
+                {
+                    let Some(dep) = analysis
+                        .iter()
+                        .flat_map(|a| &a.dependencies)
+                        .find(|dep| dep.name_range == d.range)
+                    else {
+                        continue;
+                    };
+                    let versions = cache.get(&dep.package).map_or(&[][..], |v| &v[..]);
+                    // Once for the dependency, not again for its new major
+                    #[cfg(feature = "changelog")]
+                    if code != version::MAJOR_AVAILABLE && self.crates_io(&config).await {
+                        if let Some(action) = dep.changelog_action(d.clone(), config.language) {
+                            actions.push((CodeActionOrCommand::CodeAction(action), Expect::Edited));
+                        }
                     }
-                    Err(e) => {
-                        self.client
-                            .log_message(MessageType::ERROR, format!("Failed fetching {name}: {e}"))
-                            .await
+                    if cargo_allowed && code == "outdated-compatible" {
+                        let command = CargoCommand::Update {
+                            package: Some(dep.package.clone()),
+                        };
+                        actions.extend(cargo::action(&uri, command, vec![d.clone()]).map(
+                            |action| (CodeActionOrCommand::CodeAction(action), Expect::Edited),
+                        ));
+                        compatible.push(d.clone());
                     }
+                    // The minor is pinned by the fix of the `0` requirement instead
+                    if config.zero_major_requirements
+                        && version::zero_major(&dep.req, versions).is_some()
+                    {
+                        continue;
+                    }
+                    if code != version::MAJOR_AVAILABLE && majors.contains(&d.range) {
+                        continue;
+                    }
+                    let lazy = self.caps.borrow().resolve_edits;
+                    actions.extend(
+                        dep.update_actions(&uri, d, versions, &config, lazy)
+                            .into_iter()
+                            .map(|action| {
+                                (CodeActionOrCommand::CodeAction(action), Expect::Requirement)
+                            }),
+                    );
                 }
             }
-        }
-        results
-    }
-}
-
-#[tokio::main]
-async fn main() {
-    let args = Args::parse();
-
-    let (service, socket) = LspService::new(|client| CratesIoBackend {
-        client,
-        endpoint: args.endpoint,
-        token: args.token,
-        cache: Default::default(),
-        open_docs: Default::default(),
-    });
-
-    Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
-        .serve(service)
-        .await;
-}
-
-fn is_cargo_toml(uri: &Uri) -> bool {
-    uri.path()
-        .segments()
-        .next_back()
-        .is_some_and(|n| n == "Cargo.toml")
-}
+            if !compatible.is_empty() {
+                let command = CargoCommand::Update { package: None };
+                actions.extend(
+                    cargo::action(&uri, command, compatible)
+                        .map(|action| (CodeActionOrCommand::CodeAction(action), Expect::Edited)),
+                );
+            }
 
-fn pos_to_offset(text: &str, pos: Position) -> Option<usize> {
-    let line = text.lines().nth(pos.line as _)?;
-    let line_start = unsafe { line.as_ptr().offset_from(text.as_ptr()) };
-    assert!(line_start >= 0);
-    Some(line_start as usize + pos.character as usize)
-}
+            let Some((source, saved)) = self
+                .open_docs
+                .with(&uri, |d| (d.source.clone(), d.saved.clone()))
+            else {
+                drop(cache);
+                return Ok(Some(self.guard_actions(&uri, actions).await));
+            };
+            let only = params.context.only.as_deref();
+            if requested(only, &CodeActionKind::REFACTOR_REWRITE) {
+                actions.extend(
+                    revert::actions(&uri, &source, &saved, params.range)
+                        .into_iter()
+                        .map(|action| (CodeActionOrCommand::CodeAction(action), Expect::Edited)),
+                );
+                let on_req = |dep: &&AnalyzedDependency| {
+                    dep.req_range
+                        .is_some_and(|r| r.start <= params.range.end && params.range.start <= r.end)
+                };
+                for dep in analysis.iter().flat_map(|a| &a.dependencies).filter(on_req) {
+                    let versions = cache.get(&dep.package).map_or(&[][..], |v| &v[..]);
+                    actions.extend(
+                        dep.pin_actions(&uri, versions, config.requirement_style)
+                            .into_iter()
+                            .map(|action| {
+                                (CodeActionOrCommand::CodeAction(action), Expect::Requirement)
+                            }),
+                    );
+                }
+            }
+            let sort = CodeActionKind::new(sort::SORT_DEPENDENCIES);
+            if requested(only, &sort) {
+                let edits = sort::edits(&source, &config).filter(|e| !e.is_empty());
+                if let Some(edits) = edits {
+                    let action = CodeAction {
+                        title: "Sort dependencies".into(),
+                        kind: Some(sort),
+                        edit: Some(WorkspaceEdit {
+                            changes: Some(HashMap::from([(uri.clone(), edits)])),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    };
+                    actions.push((CodeActionOrCommand::CodeAction(action), Expect::Unchanged));
+                }
+            }
+            drop(cache);
+            Ok(Some(self.guard_actions(&uri, actions).await))
+        })
+        .await
+    }
 
-fn offset_to_pos(text: &str, offset: usize) -> Option<Position> {
-    if offset + 1 > text.len() {
-        return None;
+    /// Add the skipped releases to an update action, only from the cache,
+    /// and its edit if it was left to the resolve.
+    async fn code_action_resolve(&self, mut action: CodeAction) -> jsonrpc::Result<CodeAction> {
+        let uri = data_uri(action.data.as_ref());
+        self.guarded("codeAction/resolve", uri.as_ref(), async move {
+            let Some(mut data) = action
+                .data
+                .clone()
+                .and_then(|data| serde_json::from_value::<UpdateActionData>(data).ok())
+            else {
+                return Ok(action);
+            };
+            let config = self.document_config(&data.uri).await;
+            let links = self.crates_io(&config).await;
+            if let Some(versions) = self.cache.read().await.get(&data.package) {
+                data.resolve(versions, links);
+            }
+            if action.edit.is_none() {
+                action.edit = data.edit(config.requirement_style);
+            }
+            if let Ok(source) = self.read_document(&data.uri).await {
+                if let Some(violation) =
+                    dry_run::guard(&mut action, &data.uri, &source, Expect::Requirement)
+                {
+                    self.client
+                        .log_message(MessageType::ERROR, violation.report(&data.uri))
+                        .await;
+                    return Ok(action);
+                }
+            }
+            // Clients resolve the action the user picked
+            self.stats.action_applied();
+            action.data = serde_json::to_value(data).ok();
+            Ok(action)
+        })
+        .await
     }
-    // Unfortunate lines does not handle empty last line
-    let (line, column) = if text[..offset].ends_with("\n") {
-        (text[..offset].lines().count(), 0)
-    } else {
-        (
-            text[..offset].lines().count() - 1,
-            text[..offset].lines().last().unwrap().len(),
-        )
-    };
-    Some(Position {
-        line: line as _,
-        character: column as _,
-    })
-}
 
-#[derive(Debug, Clone)]
-struct FileInfo {
-    text: String,
-    version: i32,
-}
-impl FileInfo {
-    fn new(text: String, version: i32) -> Self {
-        Self { text, version }
+    async fn code_lens_resolve(&self, params: CodeLens) -> jsonrpc::Result<CodeLens> {
+        let uri = data_uri(params.data.as_ref());
+        self.guarded("codeLens/resolve", uri.as_ref(), async move {
+            let language = self.config.borrow().language;
+            Ok(analysis::resolve_lens(params, language))
+        })
+        .await
     }
-}
 
-#[derive(Debug, Clone, Default, serde::Deserialize)]
-#[serde(default, rename_all = "kebab-case")]
-struct SpannedManifest {
-    dependencies: BTreeMap<Spanned<String>, Dependency>,
-    build_dependencies: BTreeMap<Spanned<String>, Dependency>,
-    dev_dependencies: BTreeMap<Spanned<String>, Dependency>,
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> jsonrpc::Result<Option<Value>> {
+        let uri = data_uri(params.arguments.first());
+        self.guarded("workspace/executeCommand", uri.as_ref(), async move {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("ExecuteCommand: {}", params.command),
+                )
+                .await;
+            self.stats.command_executed();
+            match params.command.as_str() {
+                analysis::APPLY_UPDATE => {
+                    let args: UpdateArgs = params
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .ok_or_else(|| {
+                            jsonrpc::Error::invalid_params("Expected update arguments")
+                        })?;
+                    let edit = TextEdit {
+                        range: args.range,
+                        new_text: args.version,
+                    };
+                    let edits = std::slice::from_ref(&edit);
+                    self.check_edits(&args.uri, edits, Expect::Requirement)
+                        .await?;
+                    let edit = WorkspaceEdit {
+                        changes: Some(HashMap::from([(args.uri, vec![edit])])),
+                        ..Default::default()
+                    };
+                    let response = self.client.apply_edit(edit).await?;
+                    if response.applied {
+                        self.stats.action_applied();
+                    }
+                    Ok(Some(Value::Bool(response.applied)))
+                }
+                analysis::PICK_VERSION => {
+                    let args: PickArgs = params
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .ok_or_else(|| jsonrpc::Error::invalid_params("Expected pick arguments"))?;
+                    let result = self.pick_version(&args).await?;
+                    Ok(serde_json::to_value(result).ok())
+                }
+                analysis::APPLY_VERSION => {
+                    let args: PickArgs = params
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .ok_or_else(|| jsonrpc::Error::invalid_params("Expected pick arguments"))?;
+                    let Some(version) = args
+                        .version
+                        .as_deref()
+                        .and_then(|v| semver::Version::parse(v).ok())
+                    else {
+                        return Err(jsonrpc::Error::invalid_params("Expected a version"));
+                    };
+                    let applied = self.apply_version(&args, &version).await?;
+                    Ok(Some(Value::Bool(applied)))
+                }
+                cargo::RUN_CARGO => {
+                    let args: RunCargoArgs = params
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .ok_or_else(|| {
+                            jsonrpc::Error::invalid_params("Expected cargo arguments")
+                        })?;
+                    let result = self.run_cargo(&args).await?;
+                    Ok(serde_json::to_value(result).ok())
+                }
+                REFRESH => {
+                    let name = match params.arguments.first() {
+                        None => None,
+                        Some(Value::String(name)) if api::is_valid_name(name) => Some(name.clone()),
+                        Some(_) => {
+                            return Err(jsonrpc::Error::invalid_params("Expected a crate name"));
+                        }
+                    };
+                    let summary = self.refresh(name.as_deref()).await;
+                    Ok(serde_json::to_value(summary).ok())
+                }
+                #[cfg(feature = "changelog")]
+                changelog::CHANGELOG => {
+                    let args: ChangelogArgs = params
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .ok_or_else(|| {
+                            jsonrpc::Error::invalid_params("Expected changelog arguments")
+                        })?;
+                    let changelog = self.changelog(&args).await?;
+                    Ok(Some(Value::String(changelog)))
+                }
+                index::WHO_USES => {
+                    // The optional second argument selects a workspace context
+                    let root = match params.arguments.get(1) {
+                        None => None,
+                        Some(Value::String(selector)) => {
+                            let selector = selector.parse::<Uri>().map_err(|_| {
+                                jsonrpc::Error::invalid_params("Expected a context URI")
+                            })?;
+                            self.select_context(Some(&selector)).await?
+                        }
+                        Some(_) => {
+                            return Err(jsonrpc::Error::invalid_params("Expected a context URI"))
+                        }
+                    };
+                    let all = self.index.read().await;
+                    let slice;
+                    let index = match &root {
+                        Some(root) => {
+                            let contexts = self.contexts.read().await;
+                            slice = all.slice(|uri| contexts.root(uri) == Some(root.as_path()));
+                            &slice
+                        }
+                        None => &*all,
+                    };
+                    match params.arguments.first() {
+                        None | Some(Value::Null) => {
+                            Ok(serde_json::to_value(index.workspace_summary()).ok())
+                        }
+                        Some(Value::String(name)) => {
+                            Ok(serde_json::to_value(index.all_requirements(name)).ok())
+                        }
+                        Some(_) => Err(jsonrpc::Error::invalid_params("Expected a crate name")),
+                    }
+                }
+                context::CONTEXTS => Ok(serde_json::to_value(self.context_summaries().await).ok()),
+                schema::CONFIG => {
+                    let uri = match params.arguments.first() {
+                        None | Some(Value::Null) => None,
+                        Some(Value::String(uri)) => Some(uri.parse::<Uri>().map_err(|_| {
+                            jsonrpc::Error::invalid_params("Expected a document URI")
+                        })?),
+                        Some(_) => {
+                            return Err(jsonrpc::Error::invalid_params("Expected a document URI"))
+                        }
+                    };
+                    let config = self.effective_config(uri).await;
+                    Ok(serde_json::to_value(config).ok())
+                }
+                features::RESOLVE_FEATURES => {
+                    let args: ResolveArgs = params
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .ok_or_else(|| {
+                            jsonrpc::Error::invalid_params("Expected resolve arguments")
+                        })?;
+                    let resolution = self.resolve_features(&args).await?;
+                    Ok(match args.format {
+                        features::Format::Json => serde_json::to_value(resolution).ok(),
+                        features::Format::Markdown => Some(Value::String(resolution.markdown())),
+                    })
+                }
+                scan::SCAN_START => {
+                    let status = self.scan().await;
+                    Ok(serde_json::to_value(status).ok())
+                }
+                scan::SCAN_CANCEL => {
+                    let status = self.cancel_scan().await;
+                    Ok(serde_json::to_value(status).ok())
+                }
+                scan::SCAN_STATUS => {
+                    let status = self.scanner.lock().await.status.clone();
+                    Ok(serde_json::to_value(status).ok())
+                }
+                #[cfg(feature = "metadata")]
+                enrich::STATUS => {
+                    let status = self.enrichment_status().await;
+                    Ok(serde_json::to_value(status).ok())
+                }
+                stats::STATS => {
+                    let stats = self.stats().await?;
+                    Ok(Some(stats))
+                }
+                stats::RESET_STATS => {
+                    if !self.stats.enabled() {
+                        return Ok(Some(Value::Bool(false)));
+                    }
+                    self.stats.reset().await.map_err(ServerError::from)?;
+                    Ok(Some(Value::Bool(true)))
+                }
+                events::SNAPSHOT => {
+                    if !self.config.borrow().emit_events {
+                        let disabled =
+                            "Dependency events are disabled, enable them with `emitEvents`";
+                        return Err(ServerError::Disabled(disabled.into()).into());
+                    }
+                    let timestamp = format::timestamp(self.clock.unix_time());
+                    let events = self.events.lock().await.snapshot(&timestamp);
+                    Ok(serde_json::to_value(events).ok())
+                }
+                timing::TIMINGS => {
+                    let timings = self.timings.read().await;
+                    let snapshots = timings
+                        .iter()
+                        .map(|(uri, timings)| (uri.as_str(), timings.snapshot()))
+                        .collect::<BTreeMap<_, _>>();
+                    Ok(serde_json::to_value(snapshots).ok())
+                }
+                report::REPORT => {
+                    let args: report::ReportArgs = match params.arguments.into_iter().next() {
+                        Some(args) => serde_json::from_value(args)
+                            .map_err(|e| jsonrpc::Error::invalid_params(e.to_string()))?,
+                        None => Default::default(),
+                    };
+                    let report = self.report(args.scope, args.context.as_ref()).await?;
+                    Ok(Some(Value::String(report)))
+                }
+                everywhere::UPDATE_EVERYWHERE => {
+                    let args: EverywhereArgs = params
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .ok_or_else(|| jsonrpc::Error::invalid_params("Expected a crate name"))?;
+                    let result = self.update_everywhere(&args).await?;
+                    Ok(serde_json::to_value(result).ok())
+                }
+                add::ADD_DEPENDENCY => {
+                    let args: AddArgs = params
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .ok_or_else(|| jsonrpc::Error::invalid_params("Expected add arguments"))?;
+                    let result = self.add_dependency(&args).await?;
+                    Ok(serde_json::to_value(result).ok())
+                }
+                graph::EXPORT_GRAPH => {
+                    let args: ExportArgs = params
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .and_then(|a| serde_json::from_value(a).ok())
+                        .ok_or_else(|| {
+                            jsonrpc::Error::invalid_params("Expected export arguments")
+                        })?;
+                    let graph = self.export_graph(args).await?;
+                    Ok(Some(Value::String(graph)))
+                }
+                _ => Err(jsonrpc::Error::invalid_params(format!(
+                    "Unknown command {}",
+                    params.command
+                ))),
+            }
+        })
+        .await
+    }
+
+    async fn shutdown(&self) -> jsonrpc::Result<()> {
+        self.guarded("shutdown", None, async move {
+            self.client.log_message(MessageType::INFO, "Shutdown").await;
+            self.shutdown.cancel();
+            // Cancelled analyses stop at their next fetch or publish
+            tokio::select! {
+                _ = self.scheduler.idle() => {}
+                _ = self.clock.sleep(SHUTDOWN_TIMEOUT) => {}
+            }
+            if self.stats.enabled() {
+                if let Err(err) = self.stats.flush(self.clock.unix_time()).await {
+                    self.client
+                        .log_message(MessageType::ERROR, format!("Failed writing stats: {err}"))
+                        .await;
+                }
+            }
+            self.client.flush().await;
+            Ok(())
+        })
+        .await
+    }
+}
+
+impl CratesIoBackend {
+    /// Backend fetching from `registry` until the configuration selects another one.
+    fn new(
+        client: tower_lsp_server::Client,
+        args: Args,
+        shared: Shared,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let config = Config::default();
+        let shutdown = CancellationToken::new();
+        Self {
+            client: ThrottledClient::new(
+                client,
+                config.log_interval,
+                clock.clone(),
+                shutdown.child_token(),
+            ),
+            registry: shared.registry,
+            fixed_registry: args.listen.is_some() || args.socket.is_some(),
+            clock,
+            endpoints: args.endpoint,
+            token: args.token,
+            config: watch::Sender::new(Default::default()),
+            options: Default::default(),
+            roots: Default::default(),
+            config_files: Default::default(),
+            open_configs: Default::default(),
+            references: Default::default(),
+            index: shared.index,
+            contexts: shared.contexts,
+            active: Default::default(),
+            cache: shared.cache,
+            analyses: Default::default(),
+            dev_usage: Default::default(),
+            timings: Default::default(),
+            events: Default::default(),
+            lockfiles: Default::default(),
+            fs_health: Default::default(),
+            bom_reported: Default::default(),
+            remote_reported: Default::default(),
+            malformed_lines: shared.malformed_lines,
+            metadata: shared.metadata,
+            #[cfg(feature = "metadata")]
+            api: shared.api,
+            #[cfg(feature = "metadata")]
+            enrichment: Default::default(),
+            feature_maps: shared.feature_maps,
+            checksums: shared.checksums,
+            stats: shared.stats,
+            client_locale: OnceLock::new(),
+            scheduler: Scheduler::new(config.fetch_concurrency, shutdown.child_token()),
+            caps: watch::Sender::new(Default::default()),
+            pulled: Default::default(),
+            published: Default::default(),
+            publish_seq: Default::default(),
+            progress_active: Default::default(),
+            open_docs: Default::default(),
+            inline_requests: Default::default(),
+            cargo: args.cargo,
+            cargo_runs: Default::default(),
+            cache_dir: args.cache_dir,
+            scanner: Default::default(),
+            typing_until: Default::default(),
+            shutdown,
+        }
+    }
+
+    /// Footer of a hover with the age and TTL of the cached versions of `package`,
+    /// if there are any.
+    fn freshness(&self, cache: &VersionCache, package: &str, language: Language) -> String {
+        match (cache.fetched(package), cache.ttl(package)) {
+            (Some(fetched), Some(ttl)) => {
+                let note = Message::new(Key::RegistryFreshness)
+                    .arg(format::age(self.clock.unix_time() - fetched))
+                    .arg(format::duration(ttl))
+                    .render(language);
+                hover::footer(&note)
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Markdown hover, as plain text for clients without markdown support.
+    fn hover_contents(&self, markdown: String) -> HoverContents {
+        let content = if self.caps.borrow().hover_markdown {
+            MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown,
+            }
+        } else {
+            MarkupContent {
+                kind: MarkupKind::PlainText,
+                value: hover::plain_text(&markdown),
+            }
+        };
+        HoverContents::Markup(content)
+    }
+
+    /// Registry endpoints and token, the configuration overrides the command line.
+    fn registry<'a>(&'a self, config: &'a Config) -> (Vec<&'a str>, &'a str) {
+        let endpoints = match (&config.endpoints[..], &config.endpoint) {
+            ([], Some(endpoint)) => vec![endpoint.as_str()],
+            ([], None) => self.endpoints.iter().map(String::as_str).collect(),
+            (endpoints, _) => endpoints.iter().map(String::as_str).collect(),
+        };
+        (endpoints, config.token.as_deref().unwrap_or(&self.token))
+    }
+
+    /// Whether the registry is crates.io, or a mirror whose `config.json` names its API.
+    ///
+    /// The metadata, changelogs, and links to crates.io are only used for it.
+    async fn crates_io(&self, config: &Config) -> bool {
+        if self.registry(config).0.contains(&CRATES_IO_INDEX) {
+            return true;
+        }
+        let registry = self.registry.read().await.clone();
+        let api = registry.config().await.and_then(|config| config.api);
+        api.is_some_and(|api| api.trim_end_matches('/') == CRATES_IO)
+    }
+
+    /// Language of the messages, the client locale takes precedence over the configuration.
+    fn language(&self, config: &Config) -> Language {
+        Language {
+            locale: Locale::negotiate(
+                self.client_locale.get().map(String::as_str),
+                config.locale.as_deref(),
+            ),
+            accessible: config.message_accessibility,
+        }
+    }
+
+    /// Swap in a new configuration.
+    ///
+    /// Changing the registry starts a new epoch, which clears the cache and
+    /// cancels all analyses of the previous epoch.
+    async fn update_config(&self, mut options: Value) {
+        // Invalid settings are dropped one by one instead of failing all of them
+        let problems = schema::validate(&mut options);
+        let mut config: Config = match serde_json::from_value(options.clone()) {
+            Ok(config) => config,
+            Err(err) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("Invalid options: {err}"))
+                    .await;
+                return;
+            }
+        };
+        let endpoints = config.endpoint.is_some() || !config.endpoints.is_empty();
+        if self.fixed_registry && (endpoints || config.token.is_some()) {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    "The registry of a shared server is set by its arguments, `endpoint`, `endpoints`, and `token` are ignored",
+                )
+                .await;
+            config.endpoint = None;
+            config.endpoints.clear();
+            config.token = None;
+        }
+        if self.fixed_registry {
+            // The caches, fetches, and registry of all clients stay at the defaults
+            let defaults = Config::default();
+            let limits = |config: &Config| {
+                (
+                    config.cache_capacity,
+                    config.cache_bytes,
+                    config.min_cache_ttl,
+                    config.max_cache_ttl,
+                    config.fetch_concurrency,
+                    config.max_index_bytes,
+                )
+            };
+            if limits(&config) != limits(&defaults) {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        "The caches of a shared server are shared by its clients, `cacheCapacity`, `cacheBytes`, `minCacheTtl`, `maxCacheTtl`, `fetchConcurrency`, and `maxIndexBytes` are ignored",
+                    )
+                    .await;
+            }
+            config.cache_capacity = defaults.cache_capacity;
+            config.cache_bytes = defaults.cache_bytes;
+            config.min_cache_ttl = defaults.min_cache_ttl;
+            config.max_cache_ttl = defaults.max_cache_ttl;
+            config.fetch_concurrency = defaults.fetch_concurrency;
+            config.max_index_bytes = defaults.max_index_bytes;
+        }
+        let old = self.config.borrow().clone();
+        config.epoch = old.epoch;
+        config.language = self.language(&config);
+        if !problems.is_empty() {
+            let list = problems
+                .iter()
+                .map(|problem| format!("- {}", problem.message(config.language)))
+                .collect::<Vec<_>>();
+            let message = Message::new(Key::InvalidSettings)
+                .arg(list.join("\n"))
+                .render(config.language);
+            self.client
+                .show_message(MessageType::WARNING, message)
+                .await;
+        }
+        let mut cache = self.cache.write().await;
+        cache.capacity = config.cache_capacity;
+        cache.max_bytes = config.cache_bytes;
+        cache.ttl = cadence::Bounds {
+            min: config.min_cache_ttl,
+            max: config.max_cache_ttl,
+        };
+        self.client.set_interval(config.log_interval);
+        self.client.set_level(config.log_level);
+        self.scheduler.set_limit(config.fetch_concurrency);
+        let changed = self.registry(&old) != self.registry(&config);
+        if changed || old.max_index_bytes != config.max_index_bytes {
+            let (endpoints, token) = self.registry(&config);
+            *self.registry.write().await =
+                failover::client(&endpoints, token, config.max_index_bytes);
+        }
+        if changed {
+            config.epoch += 1;
+            // Clear the cache first, so that no new analysis sees stale versions
+            cache.clear();
+            cache.epoch = config.epoch;
+            self.feature_maps.write().await.clear();
+            self.checksums.write().await.clear();
+        }
+        drop(cache);
+        self.stats.set_enabled(config.stats);
+        if !config.emit_events {
+            self.events.lock().await.clear();
+        }
+        // The analyses restarted with the new configuration enrich the documents again
+        #[cfg(feature = "metadata")]
+        if !(config.stale_crates && config.metadata) && self.enrichment.write().await.clear() {
+            self.send_enrichment_status().await;
+        }
+        *self.options.write().await = options;
+        self.config.send_replace(Arc::new(config));
+    }
+
+    /// Write the counted usage statistics every [`stats::FLUSH_INTERVAL`] until the shutdown,
+    /// which writes them a last time.
+    fn spawn_stats_flush(&self) {
+        let stats = self.stats.clone();
+        let clock = self.clock.clone();
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = clock.sleep(stats::FLUSH_INTERVAL) => {}
+                    _ = shutdown.cancelled() => break,
+                }
+                if stats.enabled() {
+                    // Failed writes are retried with the next flush
+                    let _ = stats.flush(clock.unix_time()).await;
+                }
+            }
+        });
+    }
+
+    /// The usage statistics as markdown and numbers for `crates-io.stats`.
+    async fn stats(&self) -> Result<Value> {
+        if !self.stats.enabled() {
+            return Err(ServerError::Disabled(
+                "Usage statistics are disabled, enable them with `stats`".into(),
+            ));
+        }
+        let now = self.clock.unix_time();
+        let file = self.stats.flush(now).await?;
+        let month = &format::timestamp(now)[..7];
+        let markdown = file.markdown(month, self.stats.path().as_deref());
+        self.show_markdown("crates-io-stats.md", &markdown).await;
+        let ttls = self.cache.read().await.ttls();
+        Ok(serde_json::json!({ "markdown": markdown, "months": file.months, "cacheTtls": ttls }))
+    }
+
+    /// Restart the analysis of all open documents.
+    async fn restart_analyses(&self) {
+        self.reanalyze(None, Priority::Background).await;
+    }
+
+    /// Analyze the open documents again, all or only those in `uris`.
+    ///
+    /// Returns the number of analyzed documents.
+    async fn reanalyze(&self, uris: Option<&[Uri]>, priority: Priority) -> usize {
+        let docs = self.open_docs.filter_map(|uri, doc| {
+            let visited = uris.is_none_or(|uris| uris.contains(uri));
+            visited.then(|| (uri.clone(), doc.clone()))
+        });
+        // Each analysis is a job of the scheduler, which limits the concurrent fetches
+        let analyses = docs.iter().map(|(uri, doc)| {
+            self.update_diagnostics(uri, Some(doc.version), &doc.source, priority)
+        });
+        futures::future::join_all(analyses).await;
+        docs.len()
+    }
+
+    /// Invalidate the cached versions and metadata of one or all crates,
+    /// and analyze the documents using them again.
+    async fn refresh(&self, name: Option<&str>) -> RefreshSummary {
+        // Unreadable files may have become readable
+        self.fs_health.lock().await.retry_all();
+        // Looked up before the caches are locked, not while holding them
+        let uris = match name {
+            Some(name) => Some(
+                self.index
+                    .read()
+                    .await
+                    .documents_using(name)
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            ),
+            None => None,
+        };
+        let invalidated = {
+            let mut cache = self.cache.write().await;
+            let mut metadata = self.metadata.write().await;
+            match name {
+                Some(name) => {
+                    metadata.remove(name);
+                    cache.invalidate(name) as usize
+                }
+                None => {
+                    metadata.clear();
+                    cache.invalidate_all()
+                }
+            }
+        };
+        let reanalyzed = self.reanalyze(uris.as_deref(), Priority::Interactive).await;
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!(
+                    "Refreshed {}: {invalidated} cache entries, {reanalyzed} documents",
+                    name.unwrap_or("all crates")
+                ),
+            )
+            .await;
+        RefreshSummary {
+            invalidated,
+            reanalyzed,
+        }
+    }
+
+    /// Render the dependency graph of a package or its whole workspace.
+    ///
+    /// External crates are annotated with the newest cached version, nothing is fetched.
+    async fn export_graph(&self, args: ExportArgs) -> Result<String> {
+        let path = DocumentLocality::from_uri(&args.uri)
+            .path_for(FileFeature::Workspace)
+            .ok_or_else(|| {
+                ServerError::Invalid(format!("Not a local file: {}", args.uri.as_str()))
+            })?
+            .to_path_buf();
+        let text = self.read_manifest(&path).await?;
+        let (manifest, _) = manifest::parse(strip_bom(&text));
+        let root = self.workspace_root(&path, &text).await;
+        let root = root.map(|(path, text)| {
+            let (manifest, _) = manifest::parse(strip_bom(&text));
+            (path, self.source(&text), manifest)
+        });
+        let workspace = root.as_ref().and_then(|(_, _, r)| r.workspace.as_ref());
+
+        // Manifests of the exported packages
+        let mut manifests = vec![(path.clone(), manifest)];
+        if let (true, Some(ws), Some((root_path, root_source, root))) =
+            (args.workspace, workspace, &root)
+        {
+            manifests = vec![(root_path.clone(), root.clone())];
+            let config = self.document_config(&args.uri).await;
+            let members = self.member_manifests(ws, root_path, root_source, &config);
+            manifests.extend(members.await);
+        }
+
+        let mut graph = Graph::default();
+        for (path, manifest) in &manifests {
+            // Virtual manifests have no package
+            let Some(package) = &manifest.package else {
+                continue;
+            };
+            let name = match &package.get_ref().name {
+                Some(name) => name.get_ref().clone(),
+                None => path
+                    .parent()
+                    .and_then(Path::file_name)
+                    .map_or_else(String::new, |n| n.to_string_lossy().into_owned()),
+            };
+            graph.add_package(&name, manifest, workspace);
+        }
+
+        let cache = self.cache.read().await;
+        for node in graph.nodes.iter_mut().filter(|n| !n.local) {
+            if let Some(versions) = cache.get(&node.name) {
+                node.latest = version::newest(versions).map(ToString::to_string);
+            }
+        }
+        drop(cache);
+
+        let out = graph.render(args.format);
+        if let Some(output) = &args.output {
+            tokio::fs::write(output, &out).await?;
+        }
+        Ok(out)
+    }
+
+    /// Dependencies enabled by a feature selection, with the features of the
+    /// registry dependencies expanded by the newest version matching their requirement.
+    async fn resolve_features(&self, args: &ResolveArgs) -> Result<Resolution> {
+        let source = self.read_document(&args.uri).await?;
+        let (manifest, _) = manifest::parse(&source.text);
+        let mut resolution = features::resolve(
+            &manifest,
+            &args.features,
+            args.default_features.unwrap_or(true),
+        );
+
+        // The feature maps are not cached, the index files are fetched again
+        let registry = self.registry.read().await.clone();
+        let mut fetches = JoinSet::new();
+        for (i, resolved) in resolution.dependencies.iter().enumerate() {
+            let req = resolved
+                .req
+                .as_deref()
+                .and_then(|req| semver::VersionReq::parse(req).ok());
+            let Some(req) = req.filter(|_| resolved.enabled) else {
+                continue;
+            };
+            if !api::is_valid_name(&resolved.package) {
+                continue;
+            }
+            let registry = registry.clone();
+            let package = resolved.package.clone();
+            fetches.spawn(async move {
+                let index = registry.index(&package, true).await?;
+                let newest = index
+                    .versions
+                    .iter()
+                    .filter(|v| !v.yanked && req.matches(&v.version))
+                    .max_by(|a, b| a.version.cmp(&b.version))
+                    .ok_or_else(|| {
+                        ServerError::Manifest(format!("No version of {package} matches {req}"))
+                    })?;
+                let map = index.features.get(&newest.raw).cloned().unwrap_or_default();
+                Ok::<_, ServerError>((i, newest.raw.clone(), map))
+            });
+        }
+        while let Some(fetched) = fetches.join_next().await {
+            match fetched? {
+                Ok((i, version, map)) => {
+                    let dep = &mut resolution.dependencies[i];
+                    dep.features = Some(features::expand(&dep.requested, &map));
+                    dep.version = Some(version);
+                }
+                Err(e) => {
+                    self.client
+                        .log_message(MessageType::WARNING, format!("Features unknown: {e}"))
+                        .await
+                }
+            }
+        }
+        Ok(resolution)
+    }
+
+    /// Markdown of the changes of a crate between two versions.
+    ///
+    /// The releases are taken from GitHub if the repository is hosted there,
+    /// otherwise the cached versions are listed.
+    /// The document is also shown if the client supports it.
+    #[cfg(feature = "changelog")]
+    async fn changelog(&self, args: &ChangelogArgs) -> Result<String> {
+        if !api::is_valid_name(&args.package) {
+            return Err(ServerError::InvalidName(args.package.clone()));
+        }
+        let from = semver::Version::parse(&args.from)?;
+        let to = semver::Version::parse(&args.to)?;
+        let config = self.config.borrow().clone();
+
+        let metadata = if self.crates_io(&config).await {
+            self.crate_metadata(&args.package, &config, Priority::Interactive)
+                .await
+        } else {
+            None
+        };
+        let repository = metadata.and_then(|m| m.repository);
+        let versions = self
+            .cache
+            .read()
+            .await
+            .get(&args.package)
+            .map(|versions| {
+                version::available(versions)
+                    .filter(|v| from < **v && **v <= to)
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let user_agent = config
+            .user_agent
+            .as_deref()
+            .unwrap_or(metadata::DEFAULT_USER_AGENT);
+        let (releases, note) = match repository.as_deref().and_then(changelog::github_repo) {
+            Some((owner, repo)) => {
+                let token = config.github_token.as_deref();
+                match changelog::fetch_releases(owner, repo, user_agent, token).await {
+                    Ok(releases) => (releases, None),
+                    Err(err @ ServerError::RateLimited { .. }) if token.is_none() => (
+                        Vec::new(),
+                        Some(format!("{err}, a `githubToken` raises it")),
+                    ),
+                    Err(err) => (Vec::new(), Some(err.to_string())),
+                }
+            }
+            None => (Vec::new(), None),
+        };
+        let selected = changelog::select(&releases, &args.package, &from, &to);
+        let note = note.or_else(|| {
+            (selected.is_empty() && !releases.is_empty())
+                .then(|| "No GitHub releases match the versions".to_string())
+        });
+        let markdown = changelog::render(
+            args,
+            repository.as_deref(),
+            &selected,
+            &versions,
+            note.as_deref(),
+        );
+
+        let name = format!(
+            "crates-io-changelog-{}-{}-{}.md",
+            args.package, args.from, args.to
+        );
+        self.show_markdown(&name, &markdown).await;
+        Ok(markdown)
+    }
+
+    /// Markdown report of the dependencies of the indexed manifests in `scope` of the
+    /// selected context, see [`Self::select_context`].
+    ///
+    /// Only crates that are not cached are fetched, in a background job showing the progress.
+    /// The document is also shown if the client supports it.
+    async fn report(&self, scope: Option<ScanScope>, context: Option<&Uri>) -> Result<String> {
+        let context = self.select_context(context).await?;
+        let config = self.context_config(context.as_deref()).await;
+        let scope = self.command_scope(scope, context.as_deref(), &config).await;
+        let names = {
+            let index = self.index.read().await;
+            let mut names = index
+                .manifests()
+                .into_iter()
+                .filter(|(uri, _)| scope.visits(uri))
+                .flat_map(|(_, occurrences)| occurrences.into_iter().map(|(name, _)| name))
+                .filter(|name| api::is_valid_name(name))
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            names.sort();
+            names.dedup();
+            names
+        };
+        let resolved = self
+            .fetch_all(&config, names, REPORT_URI, Priority::Background)
+            .await;
+
+        let report = {
+            let index = self.index.read().await;
+            let mut manifests = index.manifests();
+            manifests.retain(|(uri, _)| scope.visits(uri));
+            report::build(&manifests, &resolved, config.include_prerelease)
+        };
+        let markdown = report.markdown();
+        self.show_markdown("crates-io-report.md", &markdown).await;
+        Ok(markdown)
+    }
+
+    /// Root of the workspace context a command runs in, `None` for all contexts.
+    ///
+    /// The `selector` is a manifest, file, or directory in the context, by default it is the
+    /// active document. A selected workspace that was not seen yet is found on disk, its
+    /// context is created when its members are scanned.
+    async fn select_context(&self, selector: Option<&Uri>) -> Result<Option<PathBuf>> {
+        let Some(selector) = selector else {
+            let active = self.active.read().await.clone();
+            let contexts = self.contexts.read().await;
+            let root = active.and_then(|uri| contexts.root(&uri).map(Path::to_path_buf));
+            return Ok(root);
+        };
+        if let Some(root) = self.contexts.read().await.select(selector) {
+            return Ok(Some(root.to_path_buf()));
+        }
+        let not_found = || ServerError::NotFound(format!("A workspace at {}", selector.as_str()));
+        let path = DocumentLocality::from_uri(selector)
+            .path_for(FileFeature::Workspace)
+            .ok_or_else(not_found)?
+            .to_path_buf();
+        let path = match path.file_name() {
+            Some(name) if name == "Cargo.toml" => path,
+            _ => path.join("Cargo.toml"),
+        };
+        let text = self.read_manifest(&path).await.map_err(|_| not_found())?;
+        let root = self.workspace_root(&path, &text).await;
+        let root = context::root_dir(&path, root.as_ref().map(|(p, _)| p.as_path()));
+        root.map(Some).ok_or_else(not_found)
+    }
+
+    /// The workspace contexts with their effective settings, see [`context::CONTEXTS`].
+    async fn context_summaries(&self) -> Vec<ContextSummary> {
+        let (contexts, active) = {
+            let contexts = self.contexts.read().await;
+            let active = self.active.read().await.clone();
+            let active = active.and_then(|uri| contexts.root(&uri).map(Path::to_path_buf));
+            let roots = contexts
+                .roots()
+                .map(|root| {
+                    let manifests = contexts.manifests(root).cloned().collect::<Vec<_>>();
+                    (root.to_path_buf(), manifests)
+                })
+                .collect::<Vec<_>>();
+            (roots, active)
+        };
+        let mut summaries = Vec::new();
+        for (root, manifests) in contexts {
+            let Some(uri) = Uri::from_file_path(&root) else {
+                continue;
+            };
+            let root_path = root.join("Cargo.toml");
+            let root_manifest = match self.read_manifest(&root_path).await {
+                Ok(text) => Some(manifest::parse(strip_bom(&text)).0),
+                Err(_) => None,
+            };
+            let config = self.context_config(Some(&root)).await;
+            let workspace = root_manifest.as_ref().and_then(|m| m.workspace.as_ref());
+            let lockfile = lockfile::path_for(&root_path, None);
+            let locked = self.lockfile(lockfile.as_deref()).await.is_some();
+            let mut manifests = manifests;
+            manifests.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            summaries.push(ContextSummary {
+                active: active.as_ref() == Some(&root),
+                root: uri,
+                workspace: workspace.is_some(),
+                scan_scope: workspace.map(|ws| workspace::scope(ws, config.scan_scope)),
+                rust_version: root_manifest.as_ref().and_then(context::rust_version),
+                lockfile: lockfile
+                    .filter(|_| locked)
+                    .and_then(|path| Uri::from_file_path(&path)),
+                manifests,
+            });
+        }
+        summaries
+    }
+
+    /// Effective configuration of a context, the one of its root manifest.
+    async fn context_config(&self, root: Option<&Path>) -> Arc<Config> {
+        match root.and_then(|root| Uri::from_file_path(root.join("Cargo.toml"))) {
+            Some(uri) => self.document_config(&uri).await,
+            None => self.config.borrow().clone(),
+        }
+    }
+
+    /// Versions of the crates `names`, waiting for every fetch in a job of the document `job_uri`.
+    async fn fetch_all(
+        &self,
+        config: &Config,
+        names: Vec<String>,
+        job_uri: &str,
+        priority: Priority,
+    ) -> HashMap<String, Releases> {
+        let job = self.scheduler.start(&job_uri.parse().unwrap(), priority);
+        self.update_progress().await;
+        let mut resolved = HashMap::new();
+        let versions = self
+            .get_versions(config, names, &job, Fetch::Until(self.clock.now()))
+            .await;
+        if let Some(mut versions) = versions {
+            // Every fetch is bounded by the fetch timeout
+            if let Some(joined) = self
+                .join_fetches(config, &job, &mut versions.fetches, None)
+                .await
+            {
+                versions
+                    .resolved
+                    .extend(self.store_versions(config, joined).await);
+            }
+            resolved = versions.resolved;
+        }
+        drop(job);
+        self.update_progress().await;
+        resolved
+    }
+
+    /// Update the requirements of a crate in the manifests of the selected context at once,
+    /// see [`Self::select_context`].
+    ///
+    /// Open documents are edited at their current version, closed files on disk.
+    /// Declarations that can not be edited are listed in the result.
+    async fn update_everywhere(&self, args: &EverywhereArgs) -> Result<EverywhereResult> {
+        let name = &args.name;
+        if !api::is_valid_name(name) {
+            return Err(ServerError::InvalidName(name.clone()));
+        }
+        let context = self.select_context(args.context.as_ref()).await?;
+        let config = self.context_config(context.as_deref()).await;
+        let version = match &args.version {
+            Some(version) => semver::Version::parse(version)?,
+            None => {
+                let resolved = self
+                    .fetch_all(
+                        &config,
+                        vec![name.clone()],
+                        EVERYWHERE_URI,
+                        Priority::Interactive,
+                    )
+                    .await;
+                resolved
+                    .get(name)
+                    .and_then(|versions| version::newest(versions))
+                    .cloned()
+                    .ok_or_else(|| {
+                        ServerError::NotFound(format!("A published version of {name}"))
+                    })?
+            }
+        };
+
+        let scope = self
+            .command_scope(args.scope, context.as_deref(), &config)
+            .await;
+        let mut uris = self
+            .index
+            .read()
+            .await
+            .documents_using(name)
+            .filter(|uri| scope.visits(uri))
+            .cloned()
+            .collect::<Vec<_>>();
+        uris.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        let mut result = EverywhereResult {
+            version: version.to_string(),
+            ..Default::default()
+        };
+        let mut documents = Vec::new();
+        for uri in uris {
+            let open = self
+                .open_docs
+                .with(&uri, |doc| (doc.source.clone(), doc.version));
+            let (source, doc_version) = match open {
+                Some((source, doc_version)) => (source, Some(doc_version)),
+                None => match self.read_document(&uri).await {
+                    Ok(source) => (source, None),
+                    Err(err) => {
+                        result.skipped.push(everywhere::Skipped {
+                            uri,
+                            section: None,
+                            reason: everywhere::SkipReason::Unreadable,
+                            message: Some(err.to_string()),
+                        });
+                        continue;
+                    }
+                },
+            };
+            let style = self.document_config(&uri).await.requirement_style;
+            let rewrite = everywhere::rewrite(&uri, &source, name, &version, style);
+            if let Err(violation) = dry_run::check(&source, &rewrite.edits, Expect::Requirement) {
+                self.client
+                    .log_message(MessageType::ERROR, violation.report(&uri))
+                    .await;
+                return Err(ServerError::Internal(format!(
+                    "The edit of {} was rejected: {}",
+                    uri.as_str(),
+                    violation.reason
+                )));
+            }
+            result.updated.extend(rewrite.updated);
+            result.skipped.extend(rewrite.skipped);
+            if !rewrite.edits.is_empty() {
+                documents.push((uri, doc_version, rewrite.edits));
+            }
+        }
+        if !documents.is_empty() {
+            let document_changes = self.caps.borrow().document_changes;
+            let edit = everywhere::workspace_edit(documents, document_changes);
+            let response = self.client.apply_edit(edit).await.map_err(|e| {
+                ServerError::Internal(format!("Failed to apply the edit: {}", e.message))
+            })?;
+            result.applied = response.applied;
+            result.failure_reason = response.failure_reason;
+            if response.applied {
+                self.stats.action_applied();
+            }
+        }
+        Ok(result)
+    }
+
+    /// Run an allowed cargo command in the workspace of a manifest, one at a time per workspace.
+    ///
+    /// The output is logged line by line, and the open manifests of the workspace are analyzed
+    /// again after cargo exits.
+    async fn run_cargo(&self, args: &RunCargoArgs) -> Result<RunCargoResult> {
+        let config = self.document_config(&args.uri).await;
+        if !config.allow_cargo_commands {
+            return Err(ServerError::Disabled(
+                "Cargo commands are disabled, enable them with `allowCargoCommands`".into(),
+            ));
+        }
+        let cargo_args = args.command.args().ok_or_else(|| {
+            ServerError::Invalid(format!("Invalid cargo command {:?}", args.command))
+        })?;
+        let path = DocumentLocality::from_uri(&args.uri)
+            .path_for(FileFeature::Workspace)
+            .ok_or_else(|| {
+                ServerError::Invalid(format!("Not a local file: {}", args.uri.as_str()))
+            })?
+            .to_path_buf();
+        let text = self.read_manifest(&path).await?;
+        let root = match self.workspace_root(&path, &text).await {
+            Some((root, _)) => root,
+            None => path,
+        };
+        let dir = root
+            .parent()
+            .ok_or_else(|| ServerError::Manifest(format!("No directory of {}", root.display())))?;
+
+        let lock = self
+            .cargo_runs
+            .lock()
+            .await
+            .entry(dir.to_path_buf())
+            .or_default()
+            .clone();
+        let _running = lock.lock().await;
+        let command = format!("cargo {}", cargo_args.join(" "));
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!("Running `{command}` in {}", dir.display()),
+            )
+            .await;
+        let (lines, mut output) = tokio::sync::mpsc::unbounded_channel();
+        let (status, ()) = tokio::join!(cargo::run(&self.cargo, &cargo_args, dir, lines), async {
+            while let Some(line) = output.recv().await {
+                self.client.log_message(MessageType::LOG, line).await;
+            }
+        });
+        self.client.flush().await;
+        let status = status.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ServerError::Internal(format!(
+                "`{}` was not found, is cargo installed and on the PATH?",
+                self.cargo.display()
+            )),
+            _ => ServerError::Internal(format!("Failed to run `{command}`: {e}")),
+        })?;
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!("`{command}` exited with {status}"),
+            )
+            .await;
+
+        // Cargo may have edited the manifests or fetched new releases
+        if let Some(uri) = Uri::from_file_path(dir) {
+            let docs = self.open_below(&uri).await;
+            self.reanalyze(Some(&docs), Priority::Interactive).await;
+        }
+        Ok(RunCargoResult {
+            command,
+            success: status.success(),
+            exit_code: status.code(),
+        })
+    }
+
+    /// The cached versions of a dependency for a picker of the client.
+    ///
+    /// Clients without a picker get the latest version applied instead.
+    async fn pick_version(&self, args: &PickArgs) -> Result<PickResult> {
+        let Some(versions) = self.cache.read().await.get(&args.package).cloned() else {
+            return Err(ServerError::Invalid(format!(
+                "No versions of {} are cached",
+                args.package
+            )));
+        };
+        let mut result = PickResult {
+            versions: versions
+                .iter()
+                .rev()
+                .filter(|r| !r.yanked)
+                .map(|r| r.version.to_string())
+                .collect(),
+            latest: version::newest(&versions).map(semver::Version::to_string),
+            applied: false,
+        };
+        if !self.caps.borrow().version_picker {
+            if let Some(latest) = version::newest(&versions) {
+                result.applied = self.apply_version(args, latest).await?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Replace the requirement of a picked version in the style of the document.
+    async fn apply_version(&self, args: &PickArgs, version: &semver::Version) -> Result<bool> {
+        let style = self.document_config(&args.uri).await.requirement_style;
+        let edit = TextEdit {
+            range: args.range,
+            new_text: version::requirement(version, style, &args.req),
+        };
+        self.check_edits(&args.uri, std::slice::from_ref(&edit), Expect::Requirement)
+            .await?;
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(args.uri.clone(), vec![edit])])),
+            ..Default::default()
+        };
+        let response = self.client.apply_edit(edit).await.map_err(|e| {
+            ServerError::Internal(format!("Failed to apply the edit: {}", e.message))
+        })?;
+        if response.applied {
+            self.stats.action_applied();
+        }
+        Ok(response.applied)
+    }
+
+    /// Add a crate to a manifest, requiring its locked version if the workspace depends on it.
+    async fn add_dependency(&self, args: &AddArgs) -> Result<AddResult> {
+        let name = &args.name;
+        if !api::is_valid_name(name) {
+            return Err(ServerError::InvalidName(name.clone()));
+        }
+        if !add::is_section(&args.section) {
+            return Err(ServerError::Invalid(format!(
+                "Dependencies can not be added to [{}]",
+                args.section
+            )));
+        }
+        let config = self.document_config(&args.uri).await;
+        let source = self.read_document(&args.uri).await?;
+        let placement = match &args.version {
+            Some(_) => None,
+            None => self
+                .document_lockfile(&args.uri, &source.text)
+                .await
+                .and_then(|lockfile| lockfile.placement(name)),
+        };
+        let version = match (&args.version, &placement) {
+            (Some(version), _) => semver::Version::parse(version)?,
+            (None, Some(placement)) => placement.version.clone(),
+            (None, None) => {
+                let resolved = self
+                    .fetch_all(&config, vec![name.clone()], ADD_URI, Priority::Interactive)
+                    .await;
+                resolved
+                    .get(name)
+                    .and_then(|versions| version::newest(versions))
+                    .cloned()
+                    .ok_or_else(|| {
+                        ServerError::NotFound(format!("A published version of {name}"))
+                    })?
+            }
+        };
+        let req = version::requirement(&version, config.requirement_style, "");
+        let edits = add::edits(&source, &args.section, name, &req).ok_or_else(|| {
+            ServerError::Invalid(format!("{name} is already in [{}]", args.section))
+        })?;
+        self.check_edits(&args.uri, &edits, Expect::Added).await?;
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(args.uri.clone(), edits)])),
+            ..Default::default()
+        };
+        let response = self.client.apply_edit(edit).await.map_err(|e| {
+            ServerError::Internal(format!("Failed to apply the edit: {}", e.message))
+        })?;
+        if response.applied {
+            self.stats.action_applied();
+        }
+        Ok(AddResult {
+            req,
+            locked: placement.map(|p| p.version.to_string()),
+            applied: response.applied,
+        })
+    }
+
+    /// Write a generated markdown document to a temporary file and show it,
+    /// if the client supports it.
+    async fn show_markdown(&self, file_name: &str, markdown: &str) {
+        if !self.caps.borrow().show_document {
+            return;
+        }
+        let path = std::env::temp_dir().join(file_name);
+        if tokio::fs::write(&path, markdown).await.is_ok() {
+            if let Some(uri) = Uri::from_file_path(&path) {
+                let params = lsp_types::ShowDocumentParams {
+                    uri,
+                    external: Some(false),
+                    take_focus: Some(true),
+                    selection: None,
+                };
+                if let Err(err) = self.client.show_document(params).await {
+                    self.client
+                        .log_message(
+                            MessageType::WARNING,
+                            format!("Failed showing {file_name}: {err}"),
+                        )
+                        .await;
+                }
+            }
+        }
+    }
+
+    /// Text of a document of any scheme with its lines, only open ones if it is not a local file.
+    ///
+    /// A byte order mark is not part of the text, see [`Source`].
+    async fn read_document(&self, uri: &Uri) -> Result<Arc<Source>> {
+        if let Some(source) = self.open_docs.with(uri, |d| d.source.clone()) {
+            return Ok(source);
+        }
+        match DocumentLocality::from_uri(uri) {
+            DocumentLocality::Local(path) => tokio::fs::read_to_string(&path)
+                .await
+                .map(|text| Arc::new(self.source(&text)))
+                .map_err(|err| ServerError::io(path, err)),
+            _ => Err(ServerError::Invalid(format!(
+                "Not open and not a local file: {}",
+                uri.as_str()
+            ))),
+        }
+    }
+
+    /// Dry run of the edits of the `actions` on the current text of `uri`, see [`dry_run::guard`].
+    async fn guard_actions(
+        &self,
+        uri: &Uri,
+        actions: Vec<(CodeActionOrCommand, Expect)>,
+    ) -> Vec<CodeActionOrCommand> {
+        let source = self.read_document(uri).await.ok();
+        let mut guarded = Vec::with_capacity(actions.len());
+        for (mut action, expect) in actions {
+            if let (CodeActionOrCommand::CodeAction(action), Some(source)) = (&mut action, &source)
+            {
+                if let Some(violation) = dry_run::guard(action, uri, source, expect) {
+                    self.client
+                        .log_message(MessageType::ERROR, violation.report(uri))
+                        .await;
+                }
+            }
+            guarded.push(action);
+        }
+        guarded
+    }
+
+    /// The formatting `edits` of `source`, `None` if they would change what the manifest means.
+    async fn guard_formatting(
+        &self,
+        uri: &Uri,
+        source: &Source,
+        edits: Vec<TextEdit>,
+    ) -> Option<Vec<TextEdit>> {
+        if let Err(violation) = dry_run::check(source, &edits, Expect::Unchanged) {
+            self.client
+                .log_message(MessageType::ERROR, violation.report(uri))
+                .await;
+            return None;
+        }
+        Some(edits)
+    }
+
+    /// Dry run of `edits` of the current text of `uri` before a command applies them.
+    async fn check_edits(&self, uri: &Uri, edits: &[TextEdit], expect: Expect) -> Result<()> {
+        let source = self.read_document(uri).await?;
+        if let Err(violation) = dry_run::check(&source, edits, expect) {
+            self.client
+                .log_message(MessageType::ERROR, violation.report(uri))
+                .await;
+            return Err(ServerError::Internal(format!(
+                "The edit of {} was rejected: {}",
+                uri.as_str(),
+                violation.reason
+            )));
+        }
+        Ok(())
+    }
+
+    /// Text of a manifest, preferring the unsaved state of an open document.
+    async fn read_manifest(&self, path: &Path) -> Result<String> {
+        if let Some(uri) = Uri::from_file_path(path) {
+            if let Some(text) = self.open_docs.with(&uri, |doc| doc.source.text.clone()) {
+                return Ok(text);
+            }
+        }
+        tokio::fs::read_to_string(path)
+            .await
+            .map_err(|err| ServerError::io(path, err))
+    }
+
+    /// Configuration of a document, including the nearest configuration file.
+    ///
+    /// Registry and cache settings are only taken from the client settings.
+    async fn document_config(&self, uri: &Uri) -> Arc<Config> {
+        let global = self.config.borrow().clone();
+        self.config_with(uri, Value::Null).await.unwrap_or(global)
+    }
+
+    /// Configuration of the document with the `overrides` of a single request merged over it.
+    async fn config_with(&self, uri: &Uri, overrides: Value) -> serde_json::Result<Arc<Config>> {
+        let global = self.config.borrow().clone();
+        let file_options = match self.find_config_file(uri).await {
+            Some(file) => self.load_config_file(&file).await,
+            None => None,
+        };
+        if file_options.is_none() && overrides.is_null() {
+            return Ok(global);
+        }
+        let mut options = file_options.unwrap_or_default();
+        config_file::merge(&mut options, self.options.read().await.clone());
+        config_file::merge(&mut options, overrides);
+        serde_json::from_value::<Config>(options).map(|config| {
+            let language = Language {
+                accessible: config.message_accessibility,
+                ..global.language
+            };
+            Arc::new(Config {
+                endpoint: global.endpoint.clone(),
+                endpoints: global.endpoints.clone(),
+                token: global.token.clone(),
+                cache_capacity: global.cache_capacity,
+                cache_bytes: global.cache_bytes,
+                min_cache_ttl: global.min_cache_ttl,
+                max_cache_ttl: global.max_cache_ttl,
+                log_interval: global.log_interval,
+                allow_cargo_commands: global.allow_cargo_commands,
+                stats: global.stats,
+                locale: global.locale.clone(),
+                language,
+                epoch: global.epoch,
+                ..config
+            })
+        })
+    }
+
+    /// Nearest configuration file of a document within its workspace folder.
+    async fn find_config_file(&self, uri: &Uri) -> Option<PathBuf> {
+        let locality = DocumentLocality::from_uri(uri);
+        let dir = locality
+            .path_for(FileFeature::ConfigFile)
+            .and_then(Path::parent)?;
+        if !self.fs_allows(dir, Probe::ConfigFile).await {
+            return None;
+        }
+        let root = {
+            let roots = self.roots.read().await;
+            roots.iter().find(|r| dir.starts_with(r)).cloned()
+        };
+        match config_file::find(dir, root.as_deref()) {
+            Ok(file) => {
+                self.fs_succeeded(dir, Probe::ConfigFile).await;
+                file
+            }
+            Err(err) => {
+                let err = ServerError::io(dir, err);
+                self.fs_failed(dir, Probe::ConfigFile, dir, &err).await;
+                None
+            }
+        }
+    }
+
+    /// Effective settings of a document, or of the active one, with where each comes from.
+    async fn effective_config(&self, uri: Option<Uri>) -> schema::EffectiveConfig {
+        let uri = match uri {
+            Some(uri) => Some(uri),
+            None => self.active.read().await.clone(),
+        };
+        let file = match &uri {
+            Some(uri) => self.find_config_file(uri).await,
+            None => None,
+        };
+        let file_options = match &file {
+            Some(file) => self.load_config_file(file).await,
+            None => None,
+        };
+        let config = match &uri {
+            Some(uri) => self.document_config(uri).await,
+            None => self.config.borrow().clone(),
+        };
+        let effective = serde_json::to_value(&*config).unwrap_or_default();
+        let client = self.options.read().await.clone();
+        let settings = schema::provenance(&effective, &file_options.unwrap_or_default(), &client);
+        schema::EffectiveConfig {
+            uri,
+            file: file.as_deref().and_then(Uri::from_file_path),
+            settings,
+        }
+    }
+
+    /// Load and cache a configuration file, reporting if it is malformed.
+    async fn load_config_file(&self, path: &Path) -> Option<Value> {
+        if let Some(options) = self.config_files.read().await.get(path) {
+            return options.clone();
+        }
+        let (text, result) = match tokio::fs::read_to_string(path).await {
+            Ok(text) => {
+                let result = config_file::parse(&text);
+                (text, result)
+            }
+            Err(err) => (String::new(), Err(err.to_string())),
+        };
+        if let Some(uri) = Uri::from_file_path(path) {
+            self.report_config_file(&uri, &text, &result).await;
+        }
+        let options = result.ok().map(|parsed| parsed.options);
+        self.config_files
+            .write()
+            .await
+            .insert(path.to_path_buf(), options.clone());
+        options
+    }
+
+    /// Show the error or rejected settings in the configuration file if it is open,
+    /// or as a message otherwise.
+    async fn report_config_file(
+        &self,
+        uri: &Uri,
+        text: &str,
+        parsed: &std::result::Result<config_file::Parsed, String>,
+    ) {
+        let config = self.config.borrow().clone();
+        if self.open_configs.read().await.contains(uri) {
+            let mut diagnostics = config_file::diagnostics(&self.source(text), parsed, &config);
+            diagnostics::prepare(&mut diagnostics, &[]);
+            diagnostics::make_accessible(&mut diagnostics, config.language);
+            self.publish_diagnostics(uri, diagnostics, None).await;
+            return;
+        }
+        let error = match parsed {
+            Err(err) => err.clone(),
+            Ok(parsed) if parsed.problems.is_empty() => return,
+            Ok(parsed) => {
+                let messages = parsed
+                    .problems
+                    .iter()
+                    .map(|(problem, _)| problem.message(config.language))
+                    .collect::<Vec<_>>();
+                messages.join("; ")
+            }
+        };
+        self.client
+            .show_message(
+                MessageType::WARNING,
+                format!("Invalid configuration {}: {error}", uri.as_str()),
+            )
+            .await;
+    }
+
+    /// Handle a request, a panicking handler only fails the request with an internal error.
+    async fn guarded<T>(
+        &self,
+        method: &str,
+        uri: Option<&Uri>,
+        handler: impl Future<Output = jsonrpc::Result<T>>,
+    ) -> jsonrpc::Result<T> {
+        let panic = match AssertUnwindSafe(handler).catch_unwind().await {
+            Ok(result) => return result,
+            Err(panic) => panic,
+        };
+        let message = match uri {
+            Some(uri) => format!(
+                "Panicked handling {method} of {}: {}",
+                uri.as_str(),
+                panic_message(&*panic)
+            ),
+            None => format!("Panicked handling {method}: {}", panic_message(&*panic)),
+        };
+        self.client
+            .log_message(MessageType::ERROR, message.clone())
+            .await;
+        let mut error = jsonrpc::Error::internal_error();
+        error.message = message.into();
+        Err(error)
+    }
+
+    /// Analyze and publish the diagnostics of a document.
+    ///
+    /// A panicking analysis only fails this document, it gets a single internal error.
+    async fn update_diagnostics(
+        &self,
+        uri: &Uri,
+        version: Option<i32>,
+        source: &Source,
+        priority: Priority,
+    ) {
+        let update = AssertUnwindSafe(self.try_update_diagnostics(uri, version, source, priority));
+        let Err(panic) = update.catch_unwind().await else {
+            return;
+        };
+        self.client
+            .log_message(
+                MessageType::ERROR,
+                format!(
+                    "Panicked analyzing {}: {}",
+                    uri.as_str(),
+                    panic_message(&*panic)
+                ),
+            )
+            .await;
+        // The job was dropped while unwinding
+        self.update_progress().await;
+        if !self.open_docs.contains(uri) {
+            return;
+        }
+        let config = self.config.borrow().clone();
+        let mut diagnostic = lsp_types::Diagnostic {
+            severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(INTERNAL_ERROR.into())),
+            source: Some(config.source(INTERNAL_ERROR)),
+            message: Message::new(Key::InternalError).render(config.language),
+            ..Default::default()
+        };
+        diagnostics::make_accessible(std::slice::from_mut(&mut diagnostic), config.language);
+        self.publish_diagnostics(uri, vec![diagnostic.clone()], version)
+            .await;
+        let analysis = Analysis {
+            diagnostics: vec![diagnostic],
+            ..Default::default()
+        };
+        if self.store_analysis(uri, Arc::new(analysis)).await {
+            self.refresh_code_lenses().await;
+        }
+    }
+
+    async fn try_update_diagnostics(
+        &self,
+        uri: &Uri,
+        version: Option<i32>,
+        source: &Source,
+        priority: Priority,
+    ) {
+        if self.shutdown.is_cancelled() {
+            return; // No new analyses after the shutdown request
+        }
+        if source.bom() && self.bom_reported.write().await.insert(uri.clone()) {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!(
+                        "{} starts with a byte order mark (BOM), consider removing it",
+                        uri.as_str()
+                    ),
+                )
+                .await;
+        }
+        let locality = DocumentLocality::from_uri(uri);
+        if locality.path().is_none() && self.remote_reported.write().await.insert(uri.clone()) {
+            self.client
+                .log_message(
+                    MessageType::LOG,
+                    format!(
+                        "{} is not a local file, only its text is analyzed",
+                        uri.as_str()
+                    ),
+                )
+                .await;
+        }
+        let config = self.document_config(uri).await;
+        if let Some(path) = locality.path_for(FileFeature::Exclusion) {
+            if !self.should_analyze(path, Origin::Open, &config).await {
+                self.publish_diagnostics(uri, Vec::new(), version).await;
+                return;
+            }
+        }
+        let job = self.scheduler.start(uri, priority);
+        self.update_progress().await;
+        let carried = self.carry_over(uri, version).await;
+        let deadline = self.clock.now() + Duration::from_millis(config.analysis_deadline);
+        let mut analysis = self
+            .analyze(uri, &config, source, &job, Fetch::Until(deadline), true)
+            .await;
+        let mut timings = None;
+        if let Ok(Some((analysis, _))) = &mut analysis {
+            carried.keep_pending(&mut analysis.diagnostics);
+            timings = Some(analysis.timings.clone());
+        }
+        let start = self.clock.now();
+        let fetches = self
+            .publish(uri, version, source, &config, &job, analysis)
+            .await;
+        if let (Some(mut timings), false) = (timings, job.is_cancelled()) {
+            timings.publish = self.clock.now() - start;
+            self.report_timings(uri, &config, timings).await;
+        }
+        if let Some(mut fetches) = fetches {
+            // Fill in the crates that missed the deadline
+            if let Some(joined) = self.join_fetches(&config, &job, &mut fetches, None).await {
+                self.store_versions(&config, joined).await;
+                let analysis = self
+                    .analyze(uri, &config, source, &job, Fetch::CacheOnly, true)
+                    .await;
+                self.publish(uri, version, source, &config, &job, analysis)
+                    .await;
+            }
+        }
+        // Feature maps and checksums are only needed for some crates, fetched after publishing
+        let mut unknown = self
+            .analyses
+            .read()
+            .await
+            .get(uri)
+            .map(|a| [&a.unknown_features[..], &a.unknown_checksums[..]].concat())
+            .unwrap_or_default();
+        unknown.sort();
+        unknown.dedup();
+        if !unknown.is_empty() && !job.is_cancelled() && self.fetch_details(&unknown).await {
+            let analysis = self
+                .analyze(uri, &config, source, &job, Fetch::CacheOnly, true)
+                .await;
+            self.publish(uri, version, source, &config, &job, analysis)
+                .await;
+        }
+        #[cfg(feature = "metadata")]
+        self.enrich(uri, version, source, &config, &job).await;
+        let sources = locality
+            .path_for(FileFeature::Sources)
+            .and_then(Path::parent);
+        if let (true, Some(dir)) = (config.check_dev_only, sources) {
+            // Scanned after publishing, the results are flagged by the next analysis
+            let (dir, owned) = (dir.to_path_buf(), source.text.clone());
+            let scan = tokio::task::spawn_blocking(move || dev_only::scan(&dir, &owned));
+            if let (Ok(usage), false) = (scan.await, job.is_cancelled()) {
+                let old = self
+                    .dev_usage
+                    .write()
+                    .await
+                    .insert(uri.clone(), usage.clone());
+                if old.as_ref() != Some(&usage) {
+                    let analysis = self
+                        .analyze(uri, &config, source, &job, Fetch::CacheOnly, true)
+                        .await;
+                    self.publish(uri, version, source, &config, &job, analysis)
+                        .await;
+                }
+            }
+        }
+        drop(job);
+        self.update_progress().await;
+    }
+
+    /// Keep the timings of the published analysis and log their summary,
+    /// as information with a hint if the analysis took longer than `slowAnalysisThreshold`.
+    async fn report_timings(&self, uri: &Uri, config: &Config, timings: Timings) {
+        let total = timings.total();
+        let slow = total > Duration::from_millis(config.slow_analysis_threshold);
+        let level = if slow {
+            MessageType::INFO
+        } else {
+            MessageType::LOG
+        };
+        if self.client.logs(level) {
+            let mut message = format!("Timings of {}: {}", uri.as_str(), timings.summary());
+            if slow {
+                message += &format!(
+                    "; slow analysis ({}ms), {}",
+                    total.as_millis(),
+                    timings.hint()
+                );
+            }
+            self.client.log_message(level, message).await;
+        }
+        // Only kept for the documents that are still open
+        let mut all = self.timings.write().await;
+        if self.open_docs.contains(uri) {
+            all.insert(uri.clone(), timings);
+        }
+    }
+
+    /// Answer `crates-io/analyze` with the diagnostics of a manifest that is not open.
+    ///
+    /// Nothing is published or remembered for the document, only the fetched versions
+    /// are cached like for any analysis.
+    async fn analyze_inline(
+        &self,
+        params: inline::AnalyzeParams,
+    ) -> jsonrpc::Result<inline::AnalyzeResult> {
+        let Some(uri) = params.uri() else {
+            return Err(jsonrpc::Error::invalid_params("The path must be absolute"));
+        };
+        let config = self
+            .config_with(&uri, params.options)
+            .await
+            .map_err(|err| jsonrpc::Error::invalid_params(format!("Invalid options: {err}")))?;
+        // Concurrent requests for the same path must not cancel each other
+        let id = self.inline_requests.fetch_add(1, Ordering::Relaxed);
+        let job_uri = format!("crates-io:analyze/{id}").parse().unwrap();
+        let source = &self.source(&params.text);
+        let analyze = async {
+            let job = self.scheduler.start(&job_uri, Priority::Interactive);
+            self.update_progress().await;
+            let deadline = self.clock.now() + Duration::from_millis(config.analysis_deadline);
+            let analysis = self
+                .analyze(&uri, &config, source, &job, Fetch::Until(deadline), false)
+                .await;
+            let analysis = match analysis {
+                Ok(Some((analysis, mut fetches))) if !fetches.is_empty() => {
+                    // The result has no partial state, wait for the remaining crates
+                    match self.join_fetches(&config, &job, &mut fetches, None).await {
+                        Some(joined) => {
+                            self.store_versions(&config, joined).await;
+                            self.analyze(&uri, &config, source, &job, Fetch::CacheOnly, false)
+                                .await
+                        }
+                        None => Ok(None),
+                    }
+                    .map(|fresh| fresh.or(Some((analysis, fetches))))
+                }
+                analysis => analysis,
+            };
+            drop(job);
+            self.update_progress().await;
+            analysis
+        };
+        let analysis = match AssertUnwindSafe(analyze).catch_unwind().await {
+            Ok(analysis) => analysis,
+            Err(panic) => {
+                self.update_progress().await;
+                let message = format!(
+                    "Panicked analyzing {}: {}",
+                    uri.as_str(),
+                    panic_message(&*panic)
+                );
+                self.client
+                    .log_message(MessageType::ERROR, message.clone())
+                    .await;
+                let mut error = jsonrpc::Error::internal_error();
+                error.message = message.into();
+                return Err(error);
+            }
+        };
+        match analysis {
+            Ok(Some((analysis, _))) => Ok(inline::AnalyzeResult {
+                uri,
+                diagnostics: analysis.diagnostics,
+                notices: analysis.notices,
+            }),
+            Ok(None) => Err(jsonrpc::Error::request_cancelled()),
+            Err(err) => {
+                let mut error = jsonrpc::Error::internal_error();
+                error.message = format!("Failed diagnostics: {err}").into();
+                Err(error)
+            }
+        }
+    }
+
+    /// Publish the analysis of `source`, unless it is outdated.
+    ///
+    /// Returns the fetches that are still running.
+    async fn publish(
+        &self,
+        uri: &Uri,
+        version: Option<i32>,
+        source: &Source,
+        config: &Config,
+        job: &Job,
+        analysis: Result<Option<(Analysis, Fetches)>>,
+    ) -> Option<Fetches> {
+        if self.config.borrow().epoch != config.epoch {
+            return None; // Outdated, restarted with the new configuration
+        }
+        // Closing or renaming the document cancels the job before its clear is sequenced,
+        // outdated analyses are replaced by a newer one
+        let current = |_: &FileInfo| !job.is_cancelled();
+        match analysis {
+            Ok(Some((analysis, fetches))) => {
+                let seq = self.open_docs.with_mut(uri, |doc| {
+                    current(doc).then(|| {
+                        // Later diagnostics are carried over from this text
+                        doc.history.analyzed(version, &source.lines);
+                        self.next_publish()
+                    })
+                });
+                let seq = seq.flatten()?;
+                self.send_diagnostics(uri, analysis.diagnostics.clone(), version, seq)
+                    .await;
+                let analysis = Arc::new(analysis);
+                let changed = self.store_analysis(uri, analysis.clone()).await;
+                if job.is_cancelled() {
+                    // Closed while storing, unless it was opened and analyzed again since
+                    let mut analyses = self.analyses.write().await;
+                    if analyses.get(uri).is_some_and(|a| Arc::ptr_eq(a, &analysis)) {
+                        analyses.remove(uri);
+                    }
+                    return None;
+                }
+                if config.emit_events {
+                    self.observe_events(uri, &analysis).await;
+                }
+                self.refresh_diagnostics().await;
+                if changed {
+                    self.refresh_code_lenses().await;
+                }
+                (!fetches.is_empty()).then_some(fetches)
+            }
+            Ok(None) => None,
+            Err(err) if self.open_docs.with(uri, current) == Some(true) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("Failed diagnostics: {err}"))
+                    .await;
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Publish the diagnostics of the last analysis in the text of `version`,
+    /// until the analysis of that version replaces them.
+    ///
+    /// Returns the carried diagnostics, empty if there are none or the version is unknown.
+    async fn carry_over(&self, uri: &Uri, version: Option<i32>) -> carry::Carried {
+        let Some(analysis) = self.analyses.read().await.get(uri).cloned() else {
+            return Default::default();
+        };
+        // Sequenced with the text, so that a later change carries over after it
+        let carried = self.open_docs.with(uri, |doc| {
+            if Some(doc.version) != version {
+                return None;
+            }
+            let carried = doc.history.carry(&analysis, &doc.source.lines)?;
+            let seq = doc.history.changed().then(|| self.next_publish());
+            Some((carried, seq))
+        });
+        let Some((carried, seq)) = carried.flatten() else {
+            return Default::default();
+        };
+        if let Some(seq) = seq {
+            self.send_diagnostics(uri, carried.diagnostics.clone(), version, seq)
+                .await;
+            self.refresh_diagnostics().await;
+        }
+        carried
+    }
+
+    /// Observe the published analysis for the dependency events,
+    /// sending the changes once they held for [`events::DEBOUNCE`].
+    async fn observe_events(&self, uri: &Uri, analysis: &Analysis) {
+        let (statuses, pending) = events::statuses(analysis);
+        let now = self.clock.now();
+        let Some(mut due) = self
+            .events
+            .lock()
+            .await
+            .observe(uri, statuses, pending, now)
+        else {
+            return; // Flushed by the scheduled task
+        };
+        let tracker = self.events.clone();
+        let client = (*self.client).clone();
+        let clock = self.clock.clone();
+        let shutdown = self.shutdown.clone();
+        let uri = uri.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = clock.sleep_until(due) => {}
+                    _ = shutdown.cancelled() => break,
+                }
+                let timestamp = format::timestamp(clock.unix_time());
+                let flushed = tracker
+                    .lock()
+                    .await
+                    .flush(&uri, due, clock.now(), &timestamp);
+                // Replaced by an earlier flush or forgotten with the document
+                let Some((events, next)) = flushed else {
+                    break;
+                };
+                for event in events {
+                    client
+                        .send_notification::<events::EventNotification>(event)
+                        .await;
+                }
+                match next {
+                    Some(next) => due = next,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Remember the analysis for code lenses, returns whether the counts changed.
+    ///
+    /// Diagnostics that were not in the previous analysis are counted in the statistics.
+    async fn store_analysis(&self, uri: &Uri, analysis: Arc<Analysis>) -> bool {
+        let counts = DependencyKind::ALL.map(|k| analysis.counts(k));
+        let old = self
+            .analyses
+            .write()
+            .await
+            .insert(uri.clone(), analysis.clone());
+        if self.stats.enabled() {
+            let before = old.as_deref().map(|old| flags(&self.stats, old));
+            for flag in flags(&self.stats, &analysis) {
+                if before.as_ref().is_none_or(|before| !before.contains(&flag)) {
+                    self.stats.flagged(flag.0);
+                }
+            }
+        }
+        old.is_none_or(|old| DependencyKind::ALL.map(|k| old.counts(k)) != counts)
+    }
+
+    /// `text` with its lines indexed in the position encoding negotiated with the client.
+    fn source(&self, text: &str) -> Source {
+        Source::new(text, self.caps.borrow().position_encoding)
+    }
+
+    /// Sequence of diagnostics decided now, replacing those of all earlier sequences.
+    fn next_publish(&self) -> u64 {
+        self.publish_seq.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Push the diagnostics of a document, or keep them for the next pull of the client.
+    async fn set_diagnostics(
+        &self,
+        uri: &Uri,
+        diagnostics: Vec<lsp_types::Diagnostic>,
+        version: Option<i32>,
+    ) {
+        let seq = self.next_publish();
+        self.send_diagnostics(uri, diagnostics, version, seq).await;
+    }
+
+    /// Like [`Self::set_diagnostics`] with the sequence `seq` taken earlier.
+    ///
+    /// Dropped if the diagnostics of a later sequence were already sent, so a slow clear of a
+    /// closed document never overwrites the fresh diagnostics of its reopening.
+    async fn send_diagnostics(
+        &self,
+        uri: &Uri,
+        diagnostics: Vec<lsp_types::Diagnostic>,
+        version: Option<i32>,
+        seq: u64,
+    ) {
+        // Held while sending, the client gets them in the order of their sequences
+        let mut published = self.published.lock().await;
+        let last = published.entry(uri.clone()).or_default();
+        if *last > seq {
+            return;
+        }
+        *last = seq;
+        if !self.caps.borrow().pull_diagnostics {
+            self.client
+                .publish_diagnostics(uri.clone(), diagnostics, version)
+                .await;
+        } else if diagnostics.is_empty() {
+            self.pulled.write().await.remove(uri);
+        } else {
+            self.pulled.write().await.insert(uri.clone(), diagnostics);
+        }
+    }
+
+    /// Ask a client pulling the diagnostics to pull them again.
+    async fn refresh_diagnostics(&self) {
+        if self.caps.borrow().pull_diagnostics {
+            if let Err(err) = self.client.workspace_diagnostic_refresh().await {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Failed diagnostic refresh: {err}"),
+                    )
+                    .await
+            }
+        }
+    }
+
+    async fn publish_diagnostics(
+        &self,
+        uri: &Uri,
+        diagnostics: Vec<lsp_types::Diagnostic>,
+        version: Option<i32>,
+    ) {
+        self.set_diagnostics(uri, diagnostics, version).await;
+        self.refresh_diagnostics().await;
+    }
+
+    async fn refresh_code_lenses(&self) {
+        if self.caps.borrow().code_lens_refresh {
+            if let Err(err) = self.client.code_lens_refresh().await {
+                self.client
+                    .log_message(MessageType::ERROR, format!("Failed lens refresh: {err}"))
+                    .await
+            }
+        }
+    }
+
+    /// Open documents that are `uri` or inside the directory `uri`.
+    async fn open_below(&self, uri: &Uri) -> Vec<Uri> {
+        let dir = format!("{}/", uri.as_str().trim_end_matches('/'));
+        self.open_docs
+            .uris()
+            .into_iter()
+            .filter(|u| u == uri || u.as_str().starts_with(&dir))
+            .collect()
+    }
+
+    /// Drop all state of a closed or deleted document and clear its diagnostics.
+    async fn forget(&self, uri: &Uri) -> Option<FileInfo> {
+        let (doc, seq) = self.open_docs.remove_with(uri, || {
+            // A running analysis cannot publish anymore
+            self.scheduler.cancel(uri);
+            self.next_publish()
+        });
+        // Skipped if the document was opened again in the meantime
+        self.send_diagnostics(uri, Vec::new(), None, seq).await;
+        self.refresh_diagnostics().await;
+        self.references.write().await.remove(uri);
+        self.analyses.write().await.remove(uri);
+        self.dev_usage.write().await.remove(uri);
+        self.timings.write().await.remove(uri);
+        self.events.lock().await.remove(uri);
+        #[cfg(feature = "metadata")]
+        if self.enrichment.write().await.remove(uri) {
+            self.send_enrichment_status().await;
+        }
+        self.bom_reported.write().await.remove(uri);
+        self.remote_reported.write().await.remove(uri);
+        doc
+    }
+
+    /// Move the state of a renamed document and analyze it under the new URI.
+    async fn rename(&self, old: &Uri, new: Uri) {
+        let analysis = self.analyses.read().await.get(old).cloned();
+        let references = self.references.read().await.get(old).cloned();
+        let Some(doc) = self.forget(old).await else {
+            return;
+        };
+        if !self.open_docs.insert_new(new.clone(), doc.clone()) {
+            return; // Already opened by the client under the new name
+        }
+        if let Some(analysis) = analysis {
+            self.analyses.write().await.insert(new.clone(), analysis);
+        }
+        if let Some(references) = references {
+            self.references
+                .write()
+                .await
+                .insert(new.clone(), references);
+        }
+        self.update_diagnostics(&new, Some(doc.version), &doc.source, Priority::Interactive)
+            .await;
+    }
+
+    /// Analyze the dependencies of a manifest.
+    ///
+    /// Only `track`ed analyses record the dependencies of the document in the index and pin
+    /// them in the cache.
+    /// Returns `None` if the analysis was cancelled by a configuration change.
+    async fn analyze(
+        &self,
+        uri: &Uri,
+        config: &Config,
+        source: &Source,
+        job: &Job,
+        fetch: Fetch,
+        track: bool,
+    ) -> Result<Option<(Analysis, Fetches)>> {
+        let text = source.text.as_str();
+        let start = self.clock.now();
+        // Continue with the valid sections of a broken manifest
+        let (parsed, parse_error) = manifest::parse(text);
+        let parse = self.clock.now() - start;
+        if track && parse_error.is_none() {
+            self.index.write().await.update(uri, source, &parsed);
+        }
+        let deps = parsed
+            .dependencies()
+            .map(|(kind, name, dep)| (kind, None, name, dep))
+            .chain(
+                parsed
+                    .target_dependencies()
+                    .map(|(platform, kind, name, dep)| (kind, Some(platform), name, dep)),
+            )
+            // Filter out relative dependencies
+            .filter(|(_, _, _, d)| d.detail().is_none_or(|d| d.path.is_none()))
+            // The requirement of inherited dependencies is in the workspace
+            .filter(|(_, _, _, d)| !d.inherited())
+            .collect::<Vec<_>>();
+
+        let mut dep_names = deps
+            .iter()
+            .map(|(_, _, name, info)| info.package().unwrap_or(name.as_ref()))
+            // Never request invalid names
+            .filter(|name| api::is_valid_name(name))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        dep_names.sort_unstable();
+        dep_names.dedup();
+        // Fetch versions for dependencies (in parallel)
+        if track {
+            self.references
+                .write()
+                .await
+                .insert(uri.clone(), dep_names.iter().cloned().collect());
+        }
+        let Some(mut dep_versions) = self.get_versions(config, dep_names, job, fetch).await else {
+            return Ok(None);
+        };
+
+        let classifying = self.clock.now();
+        let mut analysis = Analysis {
+            timings: mem::take(&mut dep_versions.timings),
+            ..Default::default()
+        };
+        analysis.timings.parse = parse;
+        for (kind, target, name, info) in deps {
+            let Some(range) = source.range(name.span()) else {
+                continue; // Outside the document?
+            };
+            let package = info.package().unwrap_or(name.as_ref());
+            if !api::is_valid_name(package) {
+                analysis.diagnostics.push(lsp_types::Diagnostic {
+                    range,
+                    severity: Some(config.severity.invalid_crate_name.into()),
+                    code: Some(NumberOrString::String("invalid-crate-name".into())),
+                    source: Some(config.source("invalid-crate-name")),
+                    message: Message::new(Key::InvalidCrateName)
+                        .arg(format!("{package:?}"))
+                        .render(config.language),
+                    ..Default::default()
+                });
+                analysis.dependencies.push(AnalyzedDependency {
+                    package: package.to_string(),
+                    kind,
+                    target: target.map(str::to_string),
+                    name_range: range,
+                    req: info.req().to_string(),
+                    req_range: None,
+                    git: info.detail().is_some_and(|d| d.git.is_some()),
+                    classification: None,
+                });
+                continue;
+            }
+            analysis
+                .requirements
+                .entry(package.to_string())
+                .or_default()
+                .push(info.req().to_string());
+            if dep_versions.pending.contains(package) {
+                analysis.pending.insert(package.to_string());
+            }
+            if config.show_pending && dep_versions.pending.contains(package) {
+                analysis.diagnostics.push(lsp_types::Diagnostic {
+                    range,
+                    severity: Some(config.severity.fetch_pending.into()),
+                    code: Some(NumberOrString::String("fetch-pending".into())),
+                    source: Some(config.source("fetch-pending")),
+                    message: Message::new(Key::FetchPending)
+                        .arg(package)
+                        .render(config.language),
+                    ..Default::default()
+                });
+                continue;
+            }
+            let Some(versions) = dep_versions.resolved.get(package) else {
+                if !dep_versions.pending.contains(package) {
+                    analysis.failed.insert(package.to_string());
+                }
+                continue; // Fetching failed, already logged
+            };
+            let versions = &versions[..];
+
+            let (message, severity, code, classification) = if !versions.is_empty() {
+                let classification =
+                    version::classify(info.req(), versions, config.include_prerelease);
+                let prefix = classification.message(versions).render(config.language);
+                // The versions are listed by the hover of the crate instead
+                let message = if config.language.accessible {
+                    prefix
+                } else {
+                    let declared = match analysis::kind_label(kind, target, config.language) {
+                        Some(label) => format!("{}, {label}", info.req()),
+                        None => info.req().to_string(),
+                    };
+                    format!(
+                        "{prefix}\n\n{} ({declared})\n{}",
+                        name.as_ref(),
+                        version::listing(versions, config.language).join("\n")
+                    )
+                };
+
+                (
+                    message,
+                    config.classification_severity(kind, &classification),
+                    classification.code(),
+                    Some(classification),
+                )
+            } else {
+                analysis.failed.insert(package.to_string());
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!(
+                            "Failed to fetch versions for {}:\n{:?}",
+                            name.as_ref(),
+                            info
+                        ),
+                    )
+                    .await;
+                (
+                    Message::new(Key::FetchFailed)
+                        .arg(name.as_ref())
+                        .render(config.language),
+                    config.severity.fetch_failed,
+                    "fetch-failed",
+                    None,
+                )
+            };
+
+            let mut data = serde_json::json!({ "kind": kind.name() });
+            if let Some(target) = target {
+                data["target"] = target.into();
+            }
+            if let Some(fetched) = dep_versions.fetched.get(package) {
+                data["fetchedAt"] = format::timestamp(*fetched).into();
+            }
+            analysis.diagnostics.push(lsp_types::Diagnostic {
+                range,
+                severity: Some(severity.into()),
+                code: Some(NumberOrString::String(code.into())),
+                source: Some(config.source(code)),
+                message,
+                data: Some(data),
+                ..Default::default()
+            });
+            let major = classification
+                .as_ref()
+                .and_then(|c| version::major_available(info.req(), c));
+            if let Some(major) = major {
+                analysis.diagnostics.push(lsp_types::Diagnostic {
+                    range,
+                    severity: Some(
+                        config
+                            .outdated_severity(kind, config.severity.major_available)
+                            .into(),
+                    ),
+                    code: Some(NumberOrString::String(version::MAJOR_AVAILABLE.into())),
+                    source: Some(config.source(version::MAJOR_AVAILABLE)),
+                    message: major.message().render(config.language),
+                    data: serde_json::to_value(major).ok(),
+                    ..Default::default()
+                });
+            }
+            let req_range = info.req_span().and_then(|span| source.string_range(span));
+            if let (Some(req_range), Some((pre, stable))) =
+                (req_range, version::stable_release(info.req(), versions))
+            {
+                let edit = TextEdit {
+                    range: req_range,
+                    new_text: version::requirement(&stable, config.requirement_style, info.req()),
+                };
+                analysis.diagnostics.push(lsp_types::Diagnostic {
+                    range: req_range,
+                    severity: Some(config.severity.prerelease_requirement.into()),
+                    code: Some(NumberOrString::String(
+                        version::PRERELEASE_REQUIREMENT.into(),
+                    )),
+                    source: Some(config.source(version::PRERELEASE_REQUIREMENT)),
+                    message: Message::new(Key::PrereleaseRequirement)
+                        .arg(pre)
+                        .arg(stable)
+                        .render(config.language),
+                    data: serde_json::to_value(edit).ok(),
+                    ..Default::default()
+                });
+            }
+            let zero_major = version::zero_major(info.req(), versions);
+            if let (true, Some(req_range), Some(newest)) =
+                (config.zero_major_requirements, req_range, zero_major)
+            {
+                let pinned = version::requirement(&newest, config.requirement_style, info.req());
+                analysis.diagnostics.push(lsp_types::Diagnostic {
+                    range: req_range,
+                    severity: Some(config.severity.zero_major_requirement.into()),
+                    code: Some(NumberOrString::String(
+                        version::ZERO_MAJOR_REQUIREMENT.into(),
+                    )),
+                    source: Some(config.source(version::ZERO_MAJOR_REQUIREMENT)),
+                    message: Message::new(Key::ZeroMajorRequirement)
+                        .arg(&pinned)
+                        .render(config.language),
+                    data: serde_json::to_value(TextEdit {
+                        range: req_range,
+                        new_text: pinned,
+                    })
+                    .ok(),
+                    ..Default::default()
+                });
+            }
+            analysis.dependencies.push(AnalyzedDependency {
+                package: package.to_string(),
+                kind,
+                target: target.map(str::to_string),
+                name_range: range,
+                req: info.req().to_string(),
+                req_range,
+                git: info.detail().is_some_and(|d| d.git.is_some()),
+                classification,
+            });
+        }
+
+        let rendering = self.clock.now();
+        analysis.timings.classify = rendering - classifying;
+
+        // Only from cached metadata, the missing one is fetched after publishing
+        #[cfg(feature = "metadata")]
+        if config.stale_crates && self.crates_io(config).await {
+            let now = self.clock.now();
+            let metadata = self.metadata.read().await;
+            // Outdated metadata may miss a release since
+            let released = |name: &str| {
+                let (fetched, metadata) = metadata.get(name)?;
+                (now.duration_since(*fetched) < METADATA_TTL)
+                    .then(|| metadata.released.clone())
+                    .flatten()
+            };
+            analysis.diagnostics.extend(stale::lint(
+                &analysis.dependencies,
+                released,
+                self.clock.today(),
+                config,
+            ));
+            // The note goes away with the analysis after the metadata landed
+            let pending = self.enrichment.read().await;
+            let note = Message::new(Key::MetadataPending).render(config.language);
+            for dep in &analysis.dependencies {
+                if !pending.contains(uri, &dep.package) {
+                    continue;
+                }
+                let mut diagnostics = analysis.diagnostics.iter_mut();
+                if let Some(diagnostic) = diagnostics.find(|d| d.range == dep.name_range) {
+                    diagnostic.message += &format!("\n{note}");
+                }
+            }
+        }
+        analysis.diagnostics.extend(conflict::lint(
+            &parsed,
+            source,
+            uri,
+            &dep_versions.resolved,
+            config,
+        ));
+        analysis
+            .diagnostics
+            .extend(features::lint(&parsed, source, config));
+        let maps = self.feature_maps.read().await;
+        let (hints, unknown) =
+            suggest::lint(&parsed, source, &dep_versions.resolved, &maps, config);
+        drop(maps);
+        analysis.diagnostics.extend(hints);
+        analysis.unknown_features = unknown;
+        analysis.features = features::hovers(&parsed, source);
+
+        let path = DocumentLocality::from_uri(uri)
+            .path_for(FileFeature::Workspace)
+            .map(Path::to_path_buf);
+        if let (Some(ws), Some(path)) = (&parsed.workspace, &path) {
+            if let Some(root) = path.parent() {
+                analysis.members = workspace::members(ws, root, source);
+                analysis
+                    .diagnostics
+                    .extend(workspace::missing_members(&analysis.members, config));
+                analysis
+                    .diagnostics
+                    .extend(workspace::excluded_default_members(
+                        ws, root, source, config,
+                    ));
+            }
+        }
+
+        let root = match &path {
+            Some(path) => self.workspace_root(path, text).await,
+            None => None,
+        };
+        let root = root.map(|(path, text)| {
+            let (manifest, _) = manifest::parse(strip_bom(&text));
+            (path, self.source(&text), manifest)
+        });
+        let context = path
+            .as_deref()
+            .and_then(|path| context::root_dir(path, root.as_ref().map(|(p, ..)| p.as_path())));
+        if let Some(context) = context.filter(|_| track) {
+            self.contexts.write().await.attach(uri, &context);
+        }
+        let mut notices = parsed.notices.clone();
+        notices.extend(partial::unresolved(
+            &parsed,
+            root.as_ref().map(|(_, _, root)| root),
+        ));
+        analysis.notices = partial::notices(source, &notices, config.language);
+        if config.partial_analysis {
+            analysis
+                .diagnostics
+                .extend(partial::lint(uri, &analysis.notices, config));
+        }
+        analysis.lockfile = path
+            .as_deref()
+            .and_then(|path| lockfile::path_for(path, root.as_ref().map(|(p, ..)| p.as_path())));
+        if let Some(lockfile) = self.lockfile(analysis.lockfile.as_deref()).await {
+            let versions = |name: &str| dep_versions.resolved.get(name).map(|v| &v[..]);
+            analysis.diagnostics.extend(lockfile::lint(
+                &analysis.dependencies,
+                &lockfile,
+                versions,
+                config,
+            ));
+            if config.show_checksums {
+                let (mismatches, unknown) = self.verify(&analysis, &lockfile, config).await;
+                analysis.diagnostics.extend(mismatches);
+                analysis.unknown_checksums = unknown;
+            }
+        }
+        if let Some((root_path, root_source, root)) = &root {
+            if let Some(root_uri) = Uri::from_file_path(root_path) {
+                analysis.diagnostics.extend(workspace::prefer_workspace(
+                    &parsed,
+                    source,
+                    &root_uri,
+                    root,
+                    root_source,
+                    config,
+                ));
+            }
+        }
+        if config.workspace_lints {
+            let root = root.as_ref().map(|(_, _, root)| root);
+            analysis
+                .diagnostics
+                .extend(workspace::inherited_lints(&parsed, source, root, config));
+        }
+
+        analysis.overrides = profile::overrides(&parsed, source);
+        let check_profiles = config.profile_packages && !analysis.overrides.is_empty();
+        let mut members = Vec::new();
+        if let (Some(ws), Some((root_path, root_source, _))) = (&parsed.workspace, &root) {
+            // Only read the members if a check needs them
+            if check_profiles || resolver::resolver(&parsed) == "1" {
+                members = self
+                    .member_manifests(ws, root_path, root_source, config)
+                    .await;
+            }
+        }
+        if check_profiles {
+            let workspace = root.as_ref().map(|(_, _, root)| root);
+            let declared = [&parsed]
+                .into_iter()
+                .chain(workspace)
+                .chain(members.iter().map(|(_, m)| m))
+                .flat_map(profile::declared)
+                .collect::<Vec<_>>();
+            analysis
+                .diagnostics
+                .extend(profile::lint(&analysis.overrides, &declared, config));
+        }
+
+        // Packages of the workspace, read unless the checks above already did
+        let mut local = Vec::new();
+        if let (true, Some((root_path, root_source, root))) = (members.is_empty(), &root) {
+            if let Some(ws) = &root.workspace {
+                local = self
+                    .member_manifests(ws, root_path, root_source, config)
+                    .await;
+            }
+        }
+        let packages = if local.is_empty() { &members } else { &local }
+            .iter()
+            .filter_map(|(path, manifest)| {
+                let name = manifest.package.as_ref()?.get_ref().name.as_ref()?;
+                Some((name.get_ref().clone(), path.parent()?.to_path_buf()))
+            })
+            .collect::<Vec<_>>();
+        let dir = path.as_deref().and_then(Path::parent);
+        analysis
+            .diagnostics
+            .extend(shadow::lint(&parsed, source, dir, &packages, config));
+        let cargo_features = Unstable::new(&parsed.cargo_features);
+        analysis
+            .diagnostics
+            .extend(unstable::lint(&parsed.cargo_features, source, config));
+        analysis
+            .diagnostics
+            .extend(artifact::lint(&parsed, source, &cargo_features, config));
+        analysis
+            .diagnostics
+            .extend(links::lint(&parsed, source, config));
+        // From the last scan of the sources, which follows the publishing
+        if config.check_dev_only {
+            if let Some(usage) = self.dev_usage.read().await.get(uri) {
+                analysis
+                    .diagnostics
+                    .extend(dev_only::lint(&parsed, source, usage, config));
+            }
+        }
+
+        // The root of another manifest, otherwise it is its own root
+        let other_root = match (&root, &path) {
+            (Some((root_path, root_source, root)), Some(path)) if root_path != path => {
+                Uri::from_file_path(root_path).map(|root_uri| (root_uri, root, root_source))
+            }
+            _ => None,
+        };
+        let members = members.iter().map(|(_, m)| m).collect::<Vec<_>>();
+        analysis.diagnostics.extend(resolver::lint(
+            &parsed,
+            source,
+            other_root
+                .as_ref()
+                .map(|(uri, root, source)| (uri, *root, *source)),
+            &members,
+            config,
+        ));
+
+        let package = parsed.package.as_ref().map(|package| {
+            let root = root.as_ref().map(|(_, _, root)| root);
+            inherit::resolve(package.get_ref(), root)
+        });
+        if let (Some(package), Some((root_path, root_source, _))) = (&package, &root) {
+            if let Some(root_uri) = Uri::from_file_path(root_path) {
+                analysis.diagnostics.extend(inherit::lint(
+                    package,
+                    source,
+                    &root_uri,
+                    root_source,
+                    config,
+                ));
+                analysis.inherited = inherit::hovers(package, source, &root_uri, root_source);
+            }
+        }
+        if let Some(package) = &package {
+            analysis
+                .diagnostics
+                .extend(package::lint(&parsed, package, source, config));
+        }
+
+        if config.strict_keys {
+            analysis
+                .diagnostics
+                .extend(keys::lint(source, &cargo_features, config));
+        }
+
+        if let Some(err) = parse_error.map(ServerError::from) {
+            let span = match &err {
+                ServerError::Parse { span, .. } => span.clone().unwrap_or_default(),
+                _ => Default::default(),
+            };
+            let mistyped = keys::mistyped(text);
+            // The targeted diagnostics explain the error better
+            let explained = mistyped
+                .iter()
+                .any(|m| m.value.start <= span.start && span.end <= m.value.end);
+            analysis
+                .diagnostics
+                .extend(keys::mistyped_lint(source, &mistyped, config));
+            let start = source.position(span.start).unwrap_or_default();
+            let end = source.position(span.end).unwrap_or(start);
+            if !explained {
+                analysis.diagnostics.push(lsp_types::Diagnostic {
+                    range: lsp_types::Range { start, end },
+                    severity: Some(err.severity(&config.severity).into()),
+                    code: Some(NumberOrString::String(err.code().into())),
+                    source: Some(config.source(err.code())),
+                    message: err.to_string(),
+                    ..Default::default()
+                });
+            }
+        }
+
+        diagnostics::prepare(&mut analysis.diagnostics, &analysis.dependencies);
+        diagnostics::make_accessible(&mut analysis.diagnostics, config.language);
+        analysis.timings.render = self.clock.now() - rendering;
+        Ok(Some((analysis, dep_versions.fetches)))
+    }
+
+    /// Versions of the crates, fetching the ones that are not cached.
+    ///
+    /// Returns `None` if the configuration epoch changed or the job was cancelled while fetching.
+    async fn get_versions(
+        &self,
+        config: &Config,
+        names: Vec<String>,
+        job: &Job,
+        fetch: Fetch,
+    ) -> Option<Versions> {
+        let mut versions = Versions {
+            timings: Timings::new(self.client.logs(MessageType::LOG)),
+            ..Default::default()
+        };
+        // Fetch times of the crates, only collected if they are logged
+        let durations = versions
+            .timings
+            .crates
+            .is_some()
+            .then(|| Arc::new(std::sync::Mutex::new(Vec::new())));
+        let start = self.clock.now();
+        {
+            // Read access
+            let cache = self.cache.read().await;
+            let registry = self.registry.read().await.clone();
+            let timeout = Duration::from_millis(config.fetch_timeout);
+            let clock = self.clock.clone();
+            // Results of fetches started before an invalidation are not cached
+            let generation = cache.generation();
+            let now = clock.unix_time();
+            for name in names {
+                let cached = cache.get(&name).filter(|_| cache.epoch == config.epoch);
+                let fetched = cache.fetched(&name);
+                // Local indices tell cheaply whether the file changed since
+                let modified = match (cached, fetched, fetch) {
+                    (Some(_), Some(fetched), Fetch::Until(_)) => {
+                        registry.modified_since(&name, fetched).await
+                    }
+                    _ => false,
+                };
+                let refetch = if let (Some(cached), false) = (cached, modified) {
+                    if let Some(fetched) = fetched {
+                        versions.fetched.insert(name.clone(), fetched);
+                    }
+                    versions.resolved.insert(name.clone(), cached.clone());
+                    // Expired versions are used until the refetch replaces them
+                    cache.is_expired(&name, now)
+                } else {
+                    true
+                };
+                if let (true, Fetch::Until(_)) = (refetch, fetch) {
+                    if !versions.resolved.contains_key(&name) {
+                        versions.pending.insert(name.clone());
+                    }
+                    let registry = registry.clone();
+                    let expired = clock.sleep(timeout);
+                    let permit = job.permit();
+                    let (clock, durations) = (clock.clone(), durations.clone());
+                    versions.fetches.spawn(async move {
+                        let Some(_permit) = permit.await else {
+                            return (name, generation, Err(ServerError::Cancelled));
+                        };
+                        let start = clock.now();
+                        let versions = tokio::select! {
+                            versions = registry.versions(&name) => versions,
+                            _ = expired => Err(ServerError::Timeout(timeout)),
+                        };
+                        if let Some(durations) = durations {
+                            let duration = clock.now() - start;
+                            durations.lock().unwrap().push((name.clone(), duration));
+                        }
+                        (name, generation, versions)
+                    });
+                }
+            }
+        }
+        let deadline = match fetch {
+            Fetch::Until(deadline) => Some(deadline),
+            Fetch::CacheOnly => None,
+        };
+        let fetching = self.clock.now();
+        versions.timings.cache = fetching - start;
+        let joined = self
+            .join_fetches(config, job, &mut versions.fetches, deadline)
+            .await?;
+        versions.timings.fetch = self.clock.now() - fetching;
+        if let Some(durations) = durations {
+            versions.timings.crates = Some(durations.lock().unwrap().clone());
+        }
+        for (name, _, _) in &joined {
+            versions.pending.remove(name);
+        }
+        versions.timings.pending = versions.pending.len();
+        let now = self.clock.unix_time();
+        for (name, releases) in self.store_versions(config, joined).await {
+            versions.fetched.insert(name.clone(), now);
+            versions.resolved.insert(name, releases);
+        }
+        Some(versions)
+    }
+
+    /// Collect the finished fetches until the deadline.
+    ///
+    /// Returns `None` if the configuration epoch changed or the job was cancelled.
+    async fn join_fetches(
+        &self,
+        config: &Config,
+        job: &Job,
+        fetches: &mut Fetches,
+        deadline: Option<Instant>,
+    ) -> Option<Vec<Fetched>> {
+        let mut changes = self.config.subscribe();
+        // Dropping the set aborts the pending fetches
+        let collect = async {
+            let mut joined = Vec::new();
+            loop {
+                let next = match deadline {
+                    Some(deadline) => tokio::select! {
+                        next = fetches.join_next() => next,
+                        _ = self.clock.sleep_until(deadline) => break, // The rest is collected later
+                    },
+                    None => fetches.join_next().await,
+                };
+                let fetched = match next {
+                    Some(Ok(fetched)) => fetched,
+                    // Fails the analysis waiting for it
+                    Some(Err(err)) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+                    Some(Err(_)) => continue, // Aborted
+                    None => break,
+                };
+                joined.push(fetched);
+                self.update_progress().await;
+            }
+            joined
+        };
+        tokio::select! {
+            joined = collect => Some(joined),
+            _ = changes.wait_for(|c| c.epoch != config.epoch) => None,
+            _ = job.cancelled() => None,
+        }
+    }
+
+    /// Cache the fetched versions, returning the successful fetches.
+    /// Tell the user when a mirror of the registry takes over and when the primary is back.
+    async fn show_failover_notices(&self, config: &Config) {
+        let notices = self.registry.read().await.notices();
+        for notice in notices {
+            let (typ, message) = match notice {
+                failover::Notice::FailedOver { from, to, reason } => (
+                    MessageType::WARNING,
+                    Message::new(Key::RegistryFailedOver)
+                        .arg(from)
+                        .arg(reason)
+                        .arg(to),
+                ),
+                failover::Notice::Recovered { endpoint } => (
+                    MessageType::INFO,
+                    Message::new(Key::RegistryRecovered).arg(endpoint),
+                ),
+            };
+            self.client
+                .show_message(typ, message.render(config.language))
+                .await;
+        }
+    }
+
+    async fn store_versions(
+        &self,
+        config: &Config,
+        joined: Vec<Fetched>,
+    ) -> Vec<(String, Releases)> {
+        let mut results = Vec::new();
+        if joined.is_empty() {
+            return results; // Lock only if necessary
+        }
+        self.show_failover_notices(config).await;
+        let mut cache = self.cache.write().await;
+        // Results of an old registry must not end up in the cache
+        let current = cache.epoch == config.epoch;
+        let pinned = self.pinned().await;
+        let evictions = cache.evictions();
+        let mut evicted = Vec::new();
+        let now = self.clock.unix_time();
+        for (name, generation, versions) in joined {
+            match versions {
+                Ok(index) => {
+                    if index.malformed > 0 {
+                        let total = self
+                            .malformed_lines
+                            .fetch_add(index.malformed, Ordering::Relaxed)
+                            + index.malformed;
+                        self.client
+                            .log_message(
+                                MessageType::LOG,
+                                format!(
+                                    "Skipped {} malformed index lines of {name} ({total} total)",
+                                    index.malformed
+                                ),
+                            )
+                            .await;
+                    }
+                    let versions = Releases::from(index.versions);
+                    if current && !cache.is_invalidated(&name, generation) {
+                        evicted.extend(cache.insert(name.clone(), versions.clone(), now, &pinned));
+                    }
+                    results.push((name, versions));
+                    self.stats.fetched(true);
+                }
+                Err(e) => {
+                    self.stats.fetched(false);
+                    // Missing crates are expected while a name is typed
+                    let level = match e {
+                        ServerError::NotFound(_) => MessageType::WARNING,
+                        _ => MessageType::ERROR,
+                    };
+                    self.client
+                        .log_message(level, format!("Failed fetching {name}: {e}"))
+                        .await;
+                }
+            }
+        }
+        if cache.evictions() > evictions {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!(
+                        "Evicted {} cache entries ({} total)",
+                        cache.evictions() - evictions,
+                        cache.evictions()
+                    ),
+                )
+                .await;
+        }
+        drop(cache);
+        self.drop_details(&evicted).await;
+        results
+    }
+
+    /// Drop the metadata, feature maps, and checksums of crates evicted from the version cache.
+    async fn drop_details(&self, evicted: &[String]) {
+        if evicted.is_empty() {
+            return;
+        }
+        let mut metadata = self.metadata.write().await;
+        let mut feature_maps = self.feature_maps.write().await;
+        let mut checksums = self.checksums.write().await;
+        for name in evicted {
+            metadata.remove(name);
+            feature_maps.remove(name);
+            checksums.remove(name);
+        }
+    }
+
+    /// Root directory of the context of an open document, see [`Contexts`].
+    async fn context_root(&self, uri: &Uri) -> Option<PathBuf> {
+        let path = DocumentLocality::from_uri(uri)
+            .path_for(FileFeature::Workspace)?
+            .to_path_buf();
+        let text = self.open_docs.with(uri, |doc| doc.source.text.clone())?;
+        let root = self.workspace_root(&path, &text).await;
+        context::root_dir(&path, root.as_ref().map(|(p, _)| p.as_path()))
+    }
+
+    /// Path and text of the workspace root of the manifest at `path`, which may be itself.
+    ///
+    /// Prefers the unsaved state of an open root manifest.
+    async fn workspace_root(&self, path: &Path, text: &str) -> Option<(PathBuf, String)> {
+        if !workspace::is_root(text) && !self.fs_allows(path, Probe::RootManifest).await {
+            return None;
+        }
+        let found = match workspace::find_root(path, text) {
+            Ok(found) => {
+                self.fs_succeeded(path, Probe::RootManifest).await;
+                found
+            }
+            Err(err) => {
+                let failed = match &err {
+                    ServerError::Io {
+                        path: Some(failed), ..
+                    } => failed.clone(),
+                    _ => path.to_path_buf(),
+                };
+                self.fs_failed(path, Probe::RootManifest, &failed, &err)
+                    .await;
+                None
+            }
+        };
+        let (root_path, root_text) = found?;
+        let Some(root_text) = root_text else {
+            return Some((root_path, text.to_string()));
+        };
+        let root_text = match Uri::from_file_path(&root_path) {
+            Some(uri) => match self.open_docs.with(&uri, |doc| doc.source.text.clone()) {
+                Some(text) => text,
+                None => root_text,
+            },
+            None => root_text,
+        };
+        Some((root_path, root_text))
+    }
+
+    /// Fetch the feature maps and checksums of `names`, returns whether any arrived.
+    async fn fetch_details(&self, names: &[String]) -> bool {
+        let registry = self.registry.read().await.clone();
+        let mut fetches = JoinSet::new();
+        for name in names {
+            let (registry, name) = (registry.clone(), name.clone());
+            fetches.spawn(async move {
+                let index = registry.index(&name, true).await;
+                (name, index)
+            });
+        }
+        let mut fetched = false;
+        while let Some(joined) = fetches.join_next().await {
+            match joined {
+                Ok((name, Ok(index))) => {
+                    if !index.checksums.is_empty() {
+                        let checksums = index.checksums;
+                        self.checksums.write().await.insert(name.clone(), checksums);
+                    }
+                    self.feature_maps.write().await.insert(name, index.features);
+                    fetched = true;
+                }
+                Ok((name, Err(err))) => {
+                    self.client
+                        .log_message(
+                            MessageType::WARNING,
+                            format!("Features and checksums of {name} unknown: {err}"),
+                        )
+                        .await;
+                }
+                Err(err) => {
+                    self.client
+                        .log_message(MessageType::ERROR, format!("Detail fetch failed: {err}"))
+                        .await;
+                }
+            }
+        }
+        fetched
+    }
+
+    /// The parsed `Cargo.lock` at `path`, `None` if there is none or it is invalid.
+    /// [`lockfile::verify`] with the fetched registry checksums.
+    async fn verify(
+        &self,
+        analysis: &Analysis,
+        lockfile: &lockfile::Lockfile,
+        config: &Config,
+    ) -> (Vec<lsp_types::Diagnostic>, Vec<String>) {
+        let fetched = self.checksums.read().await;
+        let checksums = |name: &str, version: &semver::Version| {
+            let checksum = fetched.get(name)?.get(&version.to_string())?;
+            Some(checksum.as_str())
+        };
+        lockfile::verify(&analysis.dependencies, lockfile, checksums, config)
+    }
+
+    async fn lockfile(&self, path: Option<&Path>) -> Option<Arc<lockfile::Lockfile>> {
+        let path = path?;
+        if !self.fs_allows(path, Probe::Lockfile).await {
+            return None;
+        }
+        match self.lockfiles.lock().await.get(path).await {
+            Ok(lockfile) => {
+                self.fs_succeeded(path, Probe::Lockfile).await;
+                lockfile
+            }
+            Err(err @ ServerError::Io { .. }) => {
+                self.fs_failed(path, Probe::Lockfile, path, &err).await;
+                None
+            }
+            Err(err) => {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Failed to read {}: {err}", path.display()),
+                    )
+                    .await;
+                None
+            }
+        }
+    }
+
+    /// The `Cargo.lock` of the manifest `uri`, the one of its last analysis or the one next
+    /// to its workspace root.
+    async fn document_lockfile(&self, uri: &Uri, text: &str) -> Option<Arc<lockfile::Lockfile>> {
+        let analyzed = self
+            .analyses
+            .read()
+            .await
+            .get(uri)
+            .and_then(|analysis| analysis.lockfile.clone());
+        let path = match analyzed {
+            Some(path) => path,
+            None => {
+                let locality = DocumentLocality::from_uri(uri);
+                let path = locality.path_for(FileFeature::Workspace)?;
+                let root = self.workspace_root(path, text).await;
+                lockfile::path_for(path, root.as_ref().map(|(p, _)| p.as_path()))?
+            }
+        };
+        self.lockfile(Some(&path)).await
+    }
+
+    /// Whether `probe` may read the files beside `path`, see [`FsHealth`].
+    async fn fs_allows(&self, path: &Path, probe: Probe) -> bool {
+        let roots = self.roots.read().await;
+        let folder = health::folder(&roots, path);
+        self.fs_health.lock().await.allows(folder, probe)
+    }
+
+    /// Enable `probe` again if the read of the files beside `path` was a probation.
+    async fn fs_succeeded(&self, path: &Path, probe: Probe) {
+        let roots = self.roots.read().await;
+        let folder = health::folder(&roots, path);
+        if self.fs_health.lock().await.succeeded(folder, probe) {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("Enabled {probe} in {} again", folder.display()),
+                )
+                .await;
+        }
+    }
+
+    /// Disable `probe` for the folder of `path` after reading `failed` failed,
+    /// explaining it once.
+    async fn fs_failed(&self, path: &Path, probe: Probe, failed: &Path, err: &ServerError) {
+        let roots = self.roots.read().await;
+        let folder = health::folder(&roots, path);
+        if self.fs_health.lock().await.failed(folder, probe, failed) {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!(
+                        "Disabled {probe} in {}: {err}. \
+                         Enabled again when the file changes or by `{REFRESH}`",
+                        folder.display()
+                    ),
+                )
+                .await;
+        }
+    }
+
+    /// Compare the open manifests using the changed `Cargo.lock` at `path` with it again.
+    ///
+    /// Only the `lock-behind` hints of the last analyses are replaced, from the cached versions,
+    /// without fetching or parsing the manifests again. A deleted lockfile removes them.
+    async fn relock(&self, path: &Path) {
+        let lockfile = self.lockfile(Some(path)).await;
+        let affected = self
+            .analyses
+            .read()
+            .await
+            .iter()
+            .filter(|(_, analysis)| analysis.lockfile.as_deref() == Some(path))
+            .map(|(uri, analysis)| (uri.clone(), analysis.clone()))
+            .collect::<Vec<_>>();
+        for (uri, analysis) in affected {
+            let config = self.document_config(&uri).await;
+            let mut analysis = (*analysis).clone();
+            let is_lock_hint = |d: &lsp_types::Diagnostic| {
+                [lockfile::LOCK_BEHIND, lockfile::CHECKSUM_MISMATCH]
+                    .iter()
+                    .any(|code| d.code == Some(NumberOrString::String(code.to_string())))
+            };
+            let before = analysis.diagnostics.len();
+            analysis.diagnostics.retain(|d| !is_lock_hint(d));
+            let removed = before - analysis.diagnostics.len();
+            if let Some(lockfile) = &lockfile {
+                let cache = self.cache.read().await;
+                let versions = |name: &str| cache.get(name).map(|v| &v[..]);
+                analysis.diagnostics.extend(lockfile::lint(
+                    &analysis.dependencies,
+                    lockfile,
+                    versions,
+                    &config,
+                ));
+                drop(cache);
+                // Checksums of newly locked versions wait for the next analysis
+                if config.show_checksums {
+                    let (mismatches, _) = self.verify(&analysis, lockfile, &config).await;
+                    analysis.diagnostics.extend(mismatches);
+                }
+            }
+            if removed == 0 && analysis.diagnostics.len() == before {
+                continue; // Nothing to hint at before or after
+            }
+            let Some(unchanged) = self
+                .open_docs
+                .with(&uri, |doc| (!doc.history.changed()).then_some(doc.version))
+            else {
+                continue;
+            };
+            // Edited since, the analysis of the edits publishes them
+            if let Some(version) = unchanged {
+                self.set_diagnostics(&uri, analysis.diagnostics.clone(), Some(version))
+                    .await;
+            }
+            self.store_analysis(&uri, Arc::new(analysis)).await;
+        }
+        self.refresh_diagnostics().await;
+    }
+
+    /// Manifests of the workspace members, members without one or excluded ones are skipped.
+    ///
+    /// All members are read for the checks, closed ones are only indexed within the `scanScope`.
+    async fn member_manifests(
+        &self,
+        workspace: &SpannedWorkspace,
+        root_path: &Path,
+        root_source: &Source,
+        config: &Config,
+    ) -> Vec<(PathBuf, SpannedManifest)> {
+        let Some(root_dir) = root_path.parent() else {
+            return Vec::new();
+        };
+        let scope = workspace::scope(workspace, config.scan_scope);
+        let scoped = workspace::scoped_dirs(workspace, root_dir, scope);
+        let mut manifests = Vec::new();
+        for member in workspace::members(workspace, root_dir, root_source) {
+            for dir in member.dirs {
+                let indexed = scoped.contains(&dir);
+                let path = dir.join("Cargo.toml");
+                let read = self.read_member(&path, root_dir, indexed, config).await;
+                if let Some(manifest) = read {
+                    manifests.push((path, manifest));
+                }
+            }
+        }
+        manifests
+    }
+
+    /// Read the manifest of a workspace member, `None` if it is excluded or unreadable.
+    ///
+    /// With `indexed` it is added to the workspace index unless it is open,
+    /// open documents are indexed by their analysis.
+    async fn read_member(
+        &self,
+        path: &Path,
+        root_dir: &Path,
+        indexed: bool,
+        config: &Config,
+    ) -> Option<SpannedManifest> {
+        if !self.should_analyze(path, Origin::Background, config).await {
+            return None;
+        }
+        let text = self.read_manifest(path).await.ok()?;
+        let (manifest, error) = manifest::parse(strip_bom(&text));
+        if let Some(uri) = Uri::from_file_path(path).filter(|_| indexed && error.is_none()) {
+            if !self.open_docs.contains(&uri) {
+                self.index_closed(&uri, root_dir, &text, &manifest).await;
+            }
+        }
+        Some(manifest)
+    }
+
+    /// Index a closed manifest in the context of the workspace at `root_dir`.
+    async fn index_closed(
+        &self,
+        uri: &Uri,
+        root_dir: &Path,
+        text: &str,
+        manifest: &SpannedManifest,
+    ) {
+        let source = self.source(text);
+        self.index.write().await.update(uri, &source, manifest);
+        self.contexts.write().await.attach(uri, root_dir);
+    }
+
+    /// Index the closed manifest at `path` of the workspace at `root_dir` for a workspace
+    /// command.
+    async fn index_member(&self, path: &Path, root_dir: &Path, config: &Config) {
+        let Some(uri) = Uri::from_file_path(path) else {
+            return;
+        };
+        if !self.should_analyze(path, Origin::Background, config).await {
+            return;
+        }
+        let Ok(text) = self.read_manifest(path).await else {
+            return;
+        };
+        if !self.open_docs.contains(&uri) {
+            self.index_text(&uri, root_dir, &text).await;
+        }
+    }
+
+    /// Index the text of a closed manifest, `false` if it is malformed.
+    ///
+    /// Manifests without dependency sections are indexed without parsing them.
+    async fn index_text(&self, uri: &Uri, root_dir: &Path, text: &str) -> bool {
+        if !manifest::may_declare_dependencies(text) {
+            self.stats.manifest_skipped();
+            let empty = SpannedManifest::default();
+            self.index_closed(uri, root_dir, text, &empty).await;
+            return true;
+        }
+        let (manifest, error) = manifest::parse(strip_bom(text));
+        if error.is_some() {
+            return false;
+        }
+        self.index_closed(uri, root_dir, text, &manifest).await;
+        true
+    }
+
+    /// Manifests visited by a workspace command in `scope`, or else in the `scanScope`.
+    ///
+    /// The members in scope of the workspace of the `context`, or without one of the workspaces
+    /// at the workspace folders, are read and indexed, closed manifests of the other members
+    /// are skipped. With a `context` no manifest of another context is visited.
+    async fn command_scope(
+        &self,
+        scope: Option<ScanScope>,
+        context: Option<&Path>,
+        config: &Config,
+    ) -> CommandScope {
+        let mut visited = CommandScope {
+            open: self.open_docs.uris().into_iter().collect(),
+            skipped: None,
+            context: None,
+        };
+        if let Some(root) = context {
+            let contexts = self.contexts.read().await;
+            visited.open.retain(|uri| contexts.root(uri) == Some(root));
+        }
+        if scope.or(config.scan_scope) == Some(ScanScope::OpenOnly) {
+            return visited;
+        }
+        let mut skipped = Vec::new();
+        let roots = match context {
+            Some(root) => vec![root.to_path_buf()],
+            None => self.roots.read().await.clone(),
+        };
+        for root_dir in roots {
+            let root_path = root_dir.join("Cargo.toml");
+            let Ok(root_text) = self.read_manifest(&root_path).await else {
+                continue;
+            };
+            let (root, _) = manifest::parse(strip_bom(&root_text));
+            let Some(ws) = &root.workspace else {
+                continue;
+            };
+            // The root is always visited, it declares the workspace dependencies
+            if let Some(uri) = Uri::from_file_path(&root_path) {
+                if !self.open_docs.contains(&uri) {
+                    self.index_closed(&uri, &root_dir, &root_text, &root).await;
+                }
+            }
+            let scope = workspace::scope(ws, scope.or(config.scan_scope));
+            let scoped = workspace::scoped_dirs(ws, &root_dir, scope);
+            for member in workspace::members(ws, &root_dir, &self.source(&root_text)) {
+                for dir in member.dirs {
+                    let path = dir.join("Cargo.toml");
+                    if scoped.contains(&dir) {
+                        self.index_member(&path, &root_dir, config).await;
+                    } else if let Some(uri) = Uri::from_file_path(&path) {
+                        skipped.push(uri);
+                    }
+                }
+            }
+        }
+        visited.skipped = Some(skipped.into_iter().collect());
+        if let Some(root) = context {
+            let contexts = self.contexts.read().await;
+            visited.context = Some(contexts.manifests(root).cloned().collect());
+        }
+        visited
+    }
+
+    /// Whether the manifest is analyzed, see [`exclude::should_analyze`].
+    async fn should_analyze(&self, path: &Path, origin: Origin, config: &Config) -> bool {
+        let roots = self.roots.read().await;
+        let root = roots.iter().find(|r| path.starts_with(r));
+        exclude::should_analyze(
+            path,
+            origin,
+            root.map(PathBuf::as_path),
+            &config.exclude_paths,
+        )
+    }
+
+    /// Scan the workspace folders as one resumable job, see [`scan::SCAN_START`].
+    ///
+    /// The closed manifests in the `scanScope` of each workspace are indexed, those unchanged
+    /// since the checkpoint without parsing them, then the crates missing in the cache are
+    /// fetched in the background. The scan pauses while the user is typing and stops when
+    /// cancelled, the checkpoint is saved either way.
+    async fn scan(&self) -> scan::Status {
+        let roots = self.roots.read().await.clone();
+        let (cancel, mut checkpoint) = {
+            let mut scanner = self.scanner.lock().await;
+            if scanner.status.is_active() {
+                return scanner.status.clone();
+            }
+            scanner.status = scan::Status {
+                state: scan::State::Running,
+                ..Default::default()
+            };
+            scanner.cancel = self.shutdown.child_token();
+            let checkpoint = match scanner.checkpoint.take() {
+                Some(checkpoint) => checkpoint,
+                None => scan::Checkpoint::load(self.cache_dir.as_deref(), &roots).await,
+            };
+            (scanner.cancel.clone(), checkpoint)
+        };
+        let _guard = ScanGuard(&self.scanner);
+        let progress = self.begin_scan_progress().await;
+        self.scanner.lock().await.progress = progress;
+
+        let finished = self.run_scan(&roots, &mut checkpoint, &cancel).await;
+        if let Err(err) = checkpoint.save(self.cache_dir.as_deref(), &roots).await {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    format!("Failed to save the scan checkpoint: {err}"),
+                )
+                .await;
+        }
+        let status = {
+            let mut scanner = self.scanner.lock().await;
+            scanner.checkpoint = Some(checkpoint);
+            scanner.status.state = if finished {
+                scan::State::Finished
+            } else {
+                scan::State::Cancelled
+            };
+            scanner.status.clone()
+        };
+        if progress {
+            let token = NumberOrString::String(scan::PROGRESS_TOKEN.into());
+            let end = WorkDoneProgressEnd {
+                message: Some(status.message()),
+            };
+            self.client.end_progress(token, end).await;
+        }
+        status
+    }
+
+    /// Index the manifests and fetch their crates, `false` if cancelled before the end.
+    async fn run_scan(
+        &self,
+        roots: &[PathBuf],
+        checkpoint: &mut scan::Checkpoint,
+        cancel: &CancellationToken,
+    ) -> bool {
+        if !self.scan_pause(cancel).await {
+            return false;
+        }
+        let mut manifests = Vec::new();
+        for root_dir in roots {
+            manifests.extend(self.scan_manifests(root_dir).await);
+        }
+        self.report_scan(|status| status.total = manifests.len())
+            .await;
+        let mut names = BTreeSet::new();
+        for (path, root_dir) in &manifests {
+            if !self.scan_pause(cancel).await {
+                return false;
+            }
+            let unchanged = self
+                .scan_manifest(path, root_dir, checkpoint, &mut names)
+                .await;
+            self.report_scan(|status| {
+                status.scanned += 1;
+                status.unchanged += usize::from(unchanged);
+            })
+            .await;
+        }
+        checkpoint.retain(|path| manifests.iter().any(|(p, _)| p == path));
+
+        let missing = {
+            let cache = self.cache.read().await;
+            names
+                .into_iter()
+                .filter(|name| cache.get(name).is_none())
+                .collect::<Vec<_>>()
+        };
+        self.report_scan(|status| status.crates = Some(missing.len()))
+            .await;
+        let config = self.config.borrow().clone();
+        for batch in missing.chunks(scan::FETCH_BATCH) {
+            if !self.scan_pause(cancel).await {
+                return false;
+            }
+            let fetch = self.fetch_all(&config, batch.to_vec(), SCAN_URI, Priority::Background);
+            tokio::select! {
+                _ = fetch => {}
+                _ = cancel.cancelled() => return false,
+            }
+            self.report_scan(|status| status.fetched += batch.len())
+                .await;
+        }
+        true
+    }
+
+    /// Manifests of the workspace or package at a workspace folder in its `scanScope`,
+    /// with the root directory of their context.
+    async fn scan_manifests(&self, root_dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+        let root_path = root_dir.join("Cargo.toml");
+        let Ok(root_text) = self.read_manifest(&root_path).await else {
+            return Vec::new();
+        };
+        let config = self.context_config(Some(root_dir)).await;
+        if config.scan_scope == Some(ScanScope::OpenOnly) {
+            return Vec::new();
+        }
+        let mut manifests = vec![(root_path, root_dir.to_path_buf())];
+        let (root, _) = manifest::parse(strip_bom(&root_text));
+        if let Some(ws) = &root.workspace {
+            let scope = workspace::scope(ws, config.scan_scope);
+            for dir in workspace::scoped_dirs(ws, root_dir, scope) {
+                manifests.push((dir.join("Cargo.toml"), root_dir.to_path_buf()));
+            }
+        }
+        manifests
+    }
+
+    /// Index a manifest for the scan and add its crates with a version to `names`,
+    /// returning whether it was restored from the checkpoint.
+    ///
+    /// Open documents are indexed by their analysis and not read.
+    async fn scan_manifest(
+        &self,
+        path: &Path,
+        root_dir: &Path,
+        checkpoint: &mut scan::Checkpoint,
+        names: &mut BTreeSet<String>,
+    ) -> bool {
+        let Some(uri) = Uri::from_file_path(path) else {
+            return false;
+        };
+        let config = self.context_config(Some(root_dir)).await;
+        let mut unchanged = false;
+        if !self.open_docs.contains(&uri)
+            && self.should_analyze(path, Origin::Background, &config).await
+        {
+            if let Ok(text) = self.read_manifest(path).await {
+                let hash = scan::content_hash(&text);
+                if let Some(dependencies) = checkpoint.unchanged(path, &hash) {
+                    self.index
+                        .write()
+                        .await
+                        .restore(&uri, dependencies.to_vec());
+                    self.contexts.write().await.attach(&uri, root_dir);
+                    unchanged = true;
+                } else if self.index_text(&uri, root_dir, &text).await {
+                    let dependencies = self.index.read().await.declarations(&uri).to_vec();
+                    checkpoint.record(path.to_path_buf(), hash, dependencies);
+                }
+            }
+        }
+        let index = self.index.read().await;
+        names.extend(
+            index
+                .declarations(&uri)
+                .iter()
+                .filter(|(_, occurrence)| occurrence.req.is_some())
+                .map(|(name, _)| name.clone()),
+        );
+        unchanged
+    }
+
+    /// Wait until no document was edited for [`scan::TYPING_PAUSE`], `false` if the scan was
+    /// cancelled meanwhile.
+    async fn scan_pause(&self, cancel: &CancellationToken) -> bool {
+        loop {
+            if cancel.is_cancelled() {
+                return false;
+            }
+            let now = self.clock.now();
+            let until = self.typing_until.read().await.filter(|until| *until > now);
+            let Some(until) = until else {
+                self.report_scan(|status| status.state = scan::State::Running)
+                    .await;
+                return true;
+            };
+            self.report_scan(|status| status.state = scan::State::Paused)
+                .await;
+            tokio::select! {
+                _ = self.clock.sleep_until(until) => {}
+                _ = cancel.cancelled() => return false,
+            }
+        }
+    }
+
+    /// Stop the running scan, see [`scan::SCAN_CANCEL`].
+    ///
+    /// The scan saves its checkpoint and ends right after, its status is already cancelled.
+    async fn cancel_scan(&self) -> scan::Status {
+        let scanner = self.scanner.lock().await;
+        let mut status = scanner.status.clone();
+        if status.is_active() {
+            scanner.cancel.cancel();
+            status.state = scan::State::Cancelled;
+        }
+        status
+    }
+
+    /// Cancel the scan from its progress, the analysis progress is not cancellable.
+    async fn cancel_progress(&self, params: WorkDoneProgressCancelParams) {
+        if params.token == NumberOrString::String(scan::PROGRESS_TOKEN.into()) {
+            self.cancel_scan().await;
+        }
+    }
+
+    /// Begin the cancellable progress of the scan, `false` if the client does not show it.
+    async fn begin_scan_progress(&self) -> bool {
+        if !self.caps.borrow().work_done_progress {
+            return false;
+        }
+        let token = NumberOrString::String(scan::PROGRESS_TOKEN.into());
+        let create = WorkDoneProgressCreateParams {
+            token: token.clone(),
+        };
+        if let Err(err) = self
+            .client
+            .send_request::<WorkDoneProgressCreate>(create)
+            .await
+        {
+            self.client
+                .log_message(MessageType::INFO, format!("No scan progress: {err}"))
+                .await;
+            return false;
+        }
+        let begin = WorkDoneProgressBegin {
+            title: "Scanning the workspace".into(),
+            cancellable: Some(true),
+            ..Default::default()
+        };
+        self.client.begin_progress(token, begin).await;
+        true
+    }
+
+    /// Update the status of the running scan and report it to the client.
+    async fn report_scan(&self, update: impl FnOnce(&mut scan::Status)) {
+        let mut scanner = self.scanner.lock().await;
+        update(&mut scanner.status);
+        if !scanner.progress {
+            return;
+        }
+        let status = &scanner.status;
+        let paused = status.state == scan::State::Paused;
+        self.client.report_progress(
+            NumberOrString::String(scan::PROGRESS_TOKEN.into()),
+            WorkDoneProgressReport {
+                message: Some(if paused {
+                    format!("{} (paused while typing)", status.message())
+                } else {
+                    status.message()
+                }),
+                percentage: Some(status.percentage()),
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Show the combined progress of all analyses.
+    async fn update_progress(&self) {
+        if !self.caps.borrow().work_done_progress {
+            return;
+        }
+        let token = NumberOrString::String(PROGRESS_TOKEN.into());
+        let mut active = self.progress_active.lock().await;
+        let progress = self.scheduler.progress();
+        if progress.jobs == 0 {
+            if mem::take(&mut *active) {
+                self.client
+                    .end_progress(token, WorkDoneProgressEnd::default())
+                    .await;
+            }
+            return;
+        }
+        if !*active {
+            let create = WorkDoneProgressCreateParams {
+                token: token.clone(),
+            };
+            if let Err(err) = self
+                .client
+                .send_request::<WorkDoneProgressCreate>(create)
+                .await
+            {
+                self.client
+                    .log_message(MessageType::INFO, format!("No progress: {err}"))
+                    .await;
+                return;
+            }
+            *active = true;
+            let begin = WorkDoneProgressBegin {
+                title: "Checking dependencies".into(),
+                ..Default::default()
+            };
+            self.client.begin_progress(token.clone(), begin).await;
+        }
+        let percentage = (progress.fetched * 100)
+            .checked_div(progress.fetches)
+            .map(|p| p.min(100) as u32);
+        self.client.report_progress(
+            token,
+            WorkDoneProgressReport {
+                message: Some(format!(
+                    "{} manifests, {}/{} crates",
+                    progress.jobs, progress.fetched, progress.fetches
+                )),
+                percentage,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Metadata of a crate from crates.io, `None` if unavailable.
+    ///
+    /// Outdated metadata is used if the API is rate limited.
+    #[cfg(feature = "metadata")]
+    async fn crate_metadata(
+        &self,
+        name: &str,
+        config: &Config,
+        priority: Priority,
+    ) -> Option<CrateMetadata> {
+        let cached = self.metadata.read().await.get(name).cloned();
+        if let Some((fetched, metadata)) = &cached {
+            if self.clock.now().duration_since(*fetched) < METADATA_TTL {
+                return Some(metadata.clone());
+            }
+        }
+        let user_agent = config
+            .user_agent
+            .as_deref()
+            .unwrap_or(metadata::DEFAULT_USER_AGENT);
+        let fetch = self
+            .api
+            .fetch(name, user_agent, priority, self.clock.as_ref());
+        match fetch.await {
+            Ok(metadata) => {
+                if let Some(gap) = metadata.release_gap {
+                    self.cache.write().await.seed_ttl(name, gap);
+                }
+                self.metadata
+                    .write()
+                    .await
+                    .insert(name.to_string(), (self.clock.now(), metadata.clone()));
+                Some(metadata)
+            }
+            Err(err) => {
+                self.client
+                    .log_message(
+                        MessageType::INFO,
+                        format!("Failed fetching metadata of {name}: {err}"),
+                    )
+                    .await;
+                cached.map(|(_, metadata)| metadata)
+            }
+        }
+    }
+
+    /// Fetch the metadata `staleCrates` misses for the dependencies of a document,
+    /// one crate after another as the rate limit allows.
+    ///
+    /// The crates are pending until their metadata landed, the analysis notes them meanwhile
+    /// and is published again as each one lands.
+    #[cfg(feature = "metadata")]
+    async fn enrich(
+        &self,
+        uri: &Uri,
+        version: Option<i32>,
+        source: &Source,
+        config: &Config,
+        job: &Job,
+    ) {
+        let enabled = config.stale_crates && config.metadata && self.crates_io(config).await;
+        let analysis = self.analyses.read().await.get(uri).cloned();
+        let mut missing = BTreeSet::new();
+        if let (true, Some(analysis)) = (enabled, analysis) {
+            let now = self.clock.now();
+            let metadata = self.metadata.read().await;
+            for dep in &analysis.dependencies {
+                let fresh = metadata
+                    .get(&dep.package)
+                    .is_some_and(|(fetched, _)| now.duration_since(*fetched) < METADATA_TTL);
+                if !dep.git && !fresh && api::is_valid_name(&dep.package) {
+                    missing.insert(dep.package.clone());
+                }
+            }
+        }
+        if job.is_cancelled() || !self.enrichment.write().await.start(uri, missing.clone()) {
+            return;
+        }
+        self.send_enrichment_status().await;
+        let mut changes = self.config.subscribe();
+        // Disabling the enrichment stops it before the analyses restart
+        let disabled =
+            changes.wait_for(|c| c.epoch != config.epoch || !(c.stale_crates && c.metadata));
+        tokio::pin!(disabled);
+        let mut landed = None;
+        for name in missing.iter().map(Some).chain([None]) {
+            // Drop the notes of the landed crate, and initially show them
+            let analysis = self
+                .analyze(uri, config, source, job, Fetch::CacheOnly, true)
+                .await;
+            self.publish(uri, version, source, config, job, analysis)
+                .await;
+            if landed.take().is_some() {
+                self.send_enrichment_status().await;
+            }
+            let Some(name) = name else {
+                break;
+            };
+            tokio::select! {
+                _ = self.crate_metadata(name, config, Priority::Background) => {}
+                _ = job.cancelled() => return,
+                _ = &mut disabled => return,
+            }
+            if self.enrichment.write().await.landed(name) {
+                landed = Some(name);
+            }
+        }
+    }
+
+    /// Pending background work of the open documents, for `crates-io.status`.
+    #[cfg(feature = "metadata")]
+    async fn enrichment_status(&self) -> enrich::Status {
+        let budget = self.api.budget(self.clock.as_ref());
+        self.enrichment.read().await.status(budget)
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn send_enrichment_status(&self) {
+        let status = self.enrichment_status().await;
+        self.client
+            .send_notification::<enrich::StatusNotification>(status)
+            .await;
+    }
+
+    /// Crates referenced by the open documents.
+    async fn pinned(&self) -> HashSet<String> {
+        let references = self.references.read().await;
+        references.crates().cloned().collect()
+    }
+}
+
+/// How an analysis gets the versions that are not cached.
+#[derive(Debug, Clone, Copy)]
+enum Fetch {
+    /// Fetch them, but do not wait past the deadline
+    Until(Instant),
+    /// Only use the cache
+    CacheOnly,
+}
+
+/// Result of the [`REFRESH`] command.
+#[derive(Debug, Serialize)]
+struct RefreshSummary {
+    /// Cache entries removed
+    invalidated: usize,
+    /// Documents analyzed again
+    reanalyzed: usize,
+}
+
+/// Manifests visited by a workspace command, see [`CratesIoBackend::command_scope`].
+struct CommandScope {
+    open: HashSet<Uri>,
+    /// Closed members out of scope, `None` if only open documents are visited
+    skipped: Option<HashSet<Uri>>,
+    /// Manifests of the selected context, `None` if every context is visited
+    context: Option<HashSet<Uri>>,
+}
+
+impl CommandScope {
+    fn visits(&self, uri: &Uri) -> bool {
+        let within = self.context.as_ref().is_none_or(|c| c.contains(uri));
+        within
+            && (self.open.contains(uri) || self.skipped.as_ref().is_some_and(|s| !s.contains(uri)))
+    }
+}
+
+/// Marks the scan cancelled if its request is dropped before the scan ends.
+struct ScanGuard<'a>(&'a Mutex<scan::Scanner>);
+
+impl Drop for ScanGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut scanner) = self.0.try_lock() {
+            if scanner.status.is_active() {
+                scanner.status.state = scan::State::Cancelled;
+            }
+        }
+    }
+}
+
+/// Result of a fetch with the cache generation it started in.
+type Fetched = (String, u64, Result<IndexVersions>);
+/// Fetches of versions that are still running.
+type Fetches = JoinSet<Fetched>;
+
+/// Versions collected for an analysis.
+#[derive(Default)]
+struct Versions {
+    resolved: HashMap<String, Releases>,
+    /// Seconds since the Unix epoch when the resolved versions were fetched
+    fetched: HashMap<String, i64>,
+    /// Crates that did not finish before the deadline
+    pending: HashSet<String>,
+    fetches: Fetches,
+    /// Time of the cache lookup and the fetches
+    timings: Timings,
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = Args::parse();
+    if args.health_check {
+        let health = serde_json::json!({
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "features": FEATURES,
+        });
+        println!("{health}");
+        return;
+    }
+
+    if let Some(addr) = &args.connect {
+        let result = match listen::connect(addr).await {
+            Ok(connection) => listen::bridge(connection).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            eprintln!("Connection to {addr} failed: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    args.cache_dir = args.cache_dir.or_else(stats::default_dir);
+    let endpoints = args.endpoint.iter().map(String::as_str).collect::<Vec<_>>();
+    let registry = failover::client(&endpoints, &args.token, Config::default().max_index_bytes);
+    let mut shared = Shared::new(registry);
+    shared.stats = Arc::new(Stats::new(args.cache_dir.clone()));
+    if let Some(Command::Replay { trace }) = &args.command {
+        let entries = match trace::read(trace) {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        };
+        let (service, socket) = lsp_service(args.clone(), shared, Arc::new(TokioClock));
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (server_read, server_write) = tokio::io::split(server);
+        let (client_read, client_write) = tokio::io::split(client);
+        // The server ends with `exit` or when the replay drops its side of the connection
+        let (diagnostics, _) = tokio::join!(
+            trace::replay(&entries, client_read, client_write),
+            Server::new(server_read, server_write, socket).serve(service),
+        );
+        match diagnostics {
+            Ok(diagnostics) => diagnostics.iter().for_each(|d| println!("{d}")),
+            Err(err) => {
+                eprintln!("Replay failed: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(Command::Check { stdin, path }) = args.command.clone() {
+        std::process::exit(check(args, shared, stdin, path).await);
+    }
+
+    let listener = match (&args.listen, &args.socket) {
+        (Some(addr), _) => Some(listen::Listener::tcp(addr).await),
+        #[cfg(unix)]
+        (None, Some(path)) => Some(listen::Listener::unix(path)),
+        #[cfg(not(unix))]
+        (None, Some(_)) => {
+            eprintln!("Unix domain sockets are not supported on this platform");
+            std::process::exit(1);
+        }
+        (None, None) => None,
+    };
+    match listener {
+        Some(Ok(listener)) => serve_connections(listener, args, shared).await,
+        Some(Err(err)) => {
+            eprintln!("Failed to listen: {err}");
+            std::process::exit(1);
+        }
+        None => {}
+    }
+
+    let recorder = match &args.trace_file {
+        Some(path) => match trace::Recorder::create(path, args.trace_text_limit) {
+            Ok(recorder) => Some(recorder),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let (service, socket) = lsp_service(args, shared, Arc::new(TokioClock));
+    let shutdown = service.inner().shutdown.clone();
+
+    match recorder {
+        Some(recorder) => {
+            let stdin = trace::Tap::new(tokio::io::stdin(), trace::Direction::In, recorder.clone());
+            let stdout = trace::Tap::new(tokio::io::stdout(), trace::Direction::Out, recorder);
+            Server::new(stdin, stdout, socket).serve(service).await;
+        }
+        None => {
+            Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+                .serve(service)
+                .await
+        }
+    }
+    // The `exit` notification ends the server, without a prior `shutdown` this is an error
+    std::process::exit(if shutdown.is_cancelled() { 0 } else { 1 });
+}
+
+/// Analyze a manifest like `crates-io/analyze` and print its diagnostics as JSON lines.
+///
+/// Returns the exit code, which is 1 if the check failed or found errors.
+async fn check(args: Args, shared: Shared, stdin: bool, path: Option<PathBuf>) -> i32 {
+    let text = match (stdin, &path) {
+        (true, _) => {
+            let mut text = String::new();
+            tokio::io::stdin()
+                .read_to_string(&mut text)
+                .await
+                .map(|_| text)
+        }
+        (false, Some(path)) => tokio::fs::read_to_string(path).await,
+        (false, None) => {
+            eprintln!("Expected the path of a manifest or `--stdin`");
+            return 2;
+        }
+    };
+    let text = match text {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Failed to read the manifest: {err}");
+            return 1;
+        }
+    };
+    let path = match path.map(std::path::absolute).transpose() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Invalid path: {err}");
+            return 1;
+        }
+    };
+    let (service, _) = lsp_service(args, shared, Arc::new(TokioClock));
+    let params = inline::AnalyzeParams {
+        text,
+        path,
+        options: Value::Null,
+    };
+    match service.inner().analyze_inline(params).await {
+        Ok(result) => {
+            let mut failed = false;
+            for diagnostic in result.diagnostics {
+                failed |= diagnostic.severity == Some(lsp_types::DiagnosticSeverity::ERROR);
+                if let Ok(line) = serde_json::to_string(&diagnostic) {
+                    println!("{line}");
+                }
+            }
+            i32::from(failed)
+        }
+        Err(err) => {
+            eprintln!("Check failed: {err}");
+            1
+        }
+    }
+}
+
+/// Service of a client with the custom requests of the server.
+fn lsp_service(
+    args: Args,
+    shared: Shared,
+    clock: Arc<dyn Clock>,
+) -> (LspService<CratesIoBackend>, ClientSocket) {
+    LspService::build(|client| CratesIoBackend::new(client, args, shared, clock))
+        .custom_method(inline::ANALYZE, CratesIoBackend::analyze_inline)
+        .custom_method(
+            "window/workDoneProgress/cancel",
+            CratesIoBackend::cancel_progress,
+        )
+        .finish()
+}
+
+/// Serve each connecting client with its own documents and settings, sharing the caches.
+///
+/// Runs until the process is stopped, clients may disconnect at any time.
+async fn serve_connections(listener: listen::Listener, args: Args, shared: Shared) -> ! {
+    loop {
+        let connection = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                eprintln!("Failed to accept a connection: {err}");
+                // Like too many open files, give the other connections time to close
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let (service, socket) = lsp_service(args.clone(), shared.clone(), Arc::new(TokioClock));
+        let shutdown = service.inner().shutdown.clone();
+        tokio::spawn(async move {
+            let (read, write) = tokio::io::split(connection);
+            Server::new(read, write, socket).serve(service).await;
+            // Stop the analyses and background tasks of clients disconnecting without `shutdown`
+            shutdown.cancel();
+        });
+    }
+}
+
+/// Codes and fingerprints of the diagnostics of `analysis` counted in the usage statistics.
+fn flags<'a>(stats: &Stats, analysis: &'a Analysis) -> HashSet<(&'a str, &'a str)> {
+    analysis
+        .diagnostics
+        .iter()
+        .filter_map(|d| {
+            let Some(NumberOrString::String(code)) = &d.code else {
+                return None;
+            };
+            let fingerprint = d.data.as_ref()?.get("fingerprint")?.as_str()?;
+            stats
+                .counts_code(code)
+                .then_some((code.as_str(), fingerprint))
+        })
+        .collect()
+}
+
+/// Whether a code action of `kind` is requested by the client filter `only`.
+fn requested(only: Option<&[CodeActionKind]>, kind: &CodeActionKind) -> bool {
+    only.is_none_or(|only| {
+        only.iter().any(|k| {
+            kind.as_str() == k.as_str() || kind.as_str().starts_with(&format!("{}.", k.as_str()))
+        })
+    })
+}
+
+/// Manifests and the directories that might contain them.
+fn file_operations() -> FileOperationRegistrationOptions {
+    let filter = |glob: &str, matches| FileOperationFilter {
+        scheme: Some("file".into()),
+        pattern: FileOperationPattern {
+            glob: glob.into(),
+            matches: Some(matches),
+            options: None,
+        },
+    };
+    FileOperationRegistrationOptions {
+        filters: vec![
+            filter("**/Cargo.toml", FileOperationPatternKind::File),
+            filter("**", FileOperationPatternKind::Folder),
+        ],
+    }
+}
+
+/// Whether the document is a manifest, whatever its scheme.
+fn is_cargo_toml(uri: &Uri) -> bool {
+    uri.path()
+        .segments()
+        .next_back()
+        .is_some_and(|n| n.decode().into_string_lossy() == "Cargo.toml")
+}
+
+/// Message of a caught panic, panics with other payloads than strings have none.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "no message"
+    }
+}
+
+/// Document of the data of a request or the arguments of a command, if they name one.
+fn data_uri(data: Option<&Value>) -> Option<Uri> {
+    data?.get("uri")?.as_str()?.parse().ok()
+}
+
+#[derive(Debug, Clone)]
+struct FileInfo {
+    /// Text with its lines, replaced with each change
+    source: Arc<Source>,
+    version: i32,
+    /// Requirements when the document was last saved or opened
+    saved: Requirements,
+    /// Edits since the text of the published diagnostics
+    history: carry::History,
+}
+impl FileInfo {
+    fn new(text: String, version: i32, encoding: Encoding) -> Self {
+        let source = Source::new(&text, encoding);
+        Self {
+            saved: revert::requirements(&source.text),
+            source: Arc::new(source),
+            version,
+            history: Default::default(),
+        }
+    }
+
+    /// Replace the text of `span`, or all of it, and index the lines again.
+    fn replace(&mut self, span: Option<std::ops::Range<usize>>, text: &str) {
+        let source = match span {
+            Some(span) => {
+                let edit = carry::Edit {
+                    range: span.clone(),
+                    len: text.len(),
+                };
+                self.history.record(self.version, edit);
+                let mut changed = self.source.text.clone();
+                changed.replace_range(span, text);
+                self.source.edited(changed)
+            }
+            None => {
+                let source = Source::new(text, self.source.lines.encoding());
+                let Some(edit) = carry::Edit::diff(&self.source.text, &source.text) else {
+                    return; // The same text
+                };
+                self.history.record(self.version, edit);
+                source
+            }
+        };
+        self.source = Arc::new(source);
+    }
+
+    fn save(&mut self) {
+        self.saved = revert::requirements(&self.source.text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::Notify;
+    use tower_lsp_server::lsp_types::{
+        ClientCapabilities, CodeActionContext, FileDelete, FileRename, Position,
+        PublishDiagnosticsParams, Range, TextDocumentContentChangeEvent, TextDocumentIdentifier,
+        TextDocumentItem, TextDocumentPositionParams, VersionedTextDocumentIdentifier,
+    };
+
+    use super::*;
+    use clock::TestClock;
+
+    /// Sparse index serving the same versions of every crate, after `release` if given.
+    async fn registry(versions: &'static [&'static str], release: Option<Arc<Notify>>) -> String {
+        registry_holding(versions, "", release).await
+    }
+
+    /// Sparse index where only the crates whose name ends with `held` wait for `release`.
+    async fn registry_holding(
+        versions: &'static [&'static str],
+        held: &'static str,
+        release: Option<Arc<Notify>>,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let release = release.clone();
+                tokio::spawn(async move {
+                    let mut request = [0; 1024];
+                    let len = stream.read(&mut request).await.unwrap_or_default();
+                    let request = String::from_utf8_lossy(&request[..len]);
+                    let path = request.split(' ').nth(1).unwrap_or_default();
+                    if let Some(release) = release.filter(|_| path.ends_with(held)) {
+                        release.notified().await;
+                    }
+                    let body = versions
+                        .iter()
+                        .map(|v| format!("{{\"vers\":\"{v}\",\"yanked\":false}}\n"))
+                        .collect::<String>();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        endpoint
+    }
+
+    /// Backend using the registry at `endpoint`, without the socket the log messages are dropped.
+    fn service(endpoint: &str) -> LspService<CratesIoBackend> {
+        let registry = registry::client(endpoint, "", Config::default().max_index_bytes);
+        let args = Args {
+            endpoint: vec![endpoint.into()],
+            token: String::new(),
+            health_check: false,
+            trace_file: None,
+            trace_text_limit: None,
+            command: None,
+            listen: None,
+            socket: None,
+            connect: None,
+            cargo: "cargo".into(),
+            cache_dir: None,
+        };
+        let (service, _) = lsp_service(args, Shared::new(registry), Arc::new(TokioClock));
+        service
+    }
+
+    /// Analysis of `text` in a job of its own.
+    async fn analyze(
+        backend: &CratesIoBackend,
+        uri: &Uri,
+        config: &Config,
+        text: &str,
+    ) -> Result<Option<Analysis>> {
+        let job = backend.scheduler.start(uri, Priority::Interactive);
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let analysis = backend
+            .analyze(
+                uri,
+                config,
+                &backend.source(text),
+                &job,
+                Fetch::Until(deadline),
+                true,
+            )
+            .await?;
+        Ok(analysis.map(|(analysis, _)| analysis))
+    }
+
+    #[tokio::test]
+    async fn discards_the_versions_of_an_old_registry() {
+        let release = Arc::new(Notify::new());
+        let old = registry(&["1.0.0"], Some(release.clone())).await;
+        let new = registry(&["2.0.0"], None).await;
+
+        let service = service(&old);
+        let backend = service.inner();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text =
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1\"\n";
+        let config = backend.config.borrow().clone();
+        let (analysis, _) = tokio::join!(analyze(backend, &uri, &config, text), async {
+            // Switched while the fetch of the old registry is in flight
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            backend
+                .update_config(serde_json::json!({ "endpoint": new }))
+                .await;
+            release.notify_waiters();
+        });
+        assert!(analysis.unwrap().is_none(), "cancelled by the switch");
+        assert!(backend.cache.read().await.get("serde").is_none());
+
+        let config = backend.config.borrow().clone();
+        assert_eq!(config.epoch, 1);
+        let diagnostics = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap()
+            .diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("2.0.0"));
+        assert!(!diagnostics[0].message.contains("1.0.0"));
+        assert_eq!(
+            backend.cache.read().await.get("serde"),
+            Some(&releases(&["2.0.0"]))
+        );
+
+        // The same registry keeps the epoch and the cache
+        backend
+            .update_config(serde_json::json!({ "endpoint": new, "severity": {} }))
+            .await;
+        assert_eq!(backend.config.borrow().epoch, 1);
+        assert!(backend.cache.read().await.get("serde").is_some());
+    }
+
+    #[tokio::test]
+    async fn reads_manifests_with_bom_and_crlf() {
+        let endpoint = registry(&["0.11.0", "0.12.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "\u{feff}dependencies.rand = \"0.11\"\r\n[package]\r\nname = \"app\"\r\n\
+            version = \"0.1.0\"\r\n\r\n[dev-dependencies]\r\nrand = \"0.11\"\r\n";
+        open(backend, &uri, text).await;
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        let diagnostics = analysis.diagnostics.clone();
+        let codes = diagnostics
+            .iter()
+            .map(|d| (d.range, d.code.clone()))
+            .collect::<Vec<_>>();
+        let outdated = Some(NumberOrString::String("outdated-incompatible".into()));
+        // The BOM is one UTF-16 code unit before the dependency on the first line
+        assert_eq!(
+            codes,
+            [
+                (
+                    Range::new(Position::new(0, 14), Position::new(0, 18)),
+                    outdated.clone()
+                ),
+                (
+                    Range::new(Position::new(6, 0), Position::new(6, 4)),
+                    outdated
+                ),
+            ]
+        );
+
+        // The update keeps the BOM and the dependency on the first line
+        backend
+            .analyses
+            .write()
+            .await
+            .insert(uri.clone(), Arc::new(analysis));
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: diagnostics[0].range,
+            context: CodeActionContext {
+                diagnostics: vec![diagnostics[0].clone()],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        let edits = actions
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.edit?.changes?.remove(&uri),
+                CodeActionOrCommand::Command(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            edits.contains(&vec![TextEdit::new(
+                Range::new(Position::new(0, 22), Position::new(0, 26)),
+                "0.12".into()
+            )]),
+            "{edits:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn severities_depend_on_the_kind() {
+        let endpoint = registry(&["0.11.0", "0.12.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n\
+            [dependencies]\nrand = \"0.11\"\n\n\
+            [dev-dependencies]\nrand = \"0.11\"\nunknown = \"5\"\n\n\
+            [target.'cfg(unix)'.build-dependencies]\nrand = \"0.11\"\n";
+        fn severities(
+            analysis: &Analysis,
+        ) -> Vec<(u32, lsp_types::DiagnosticSeverity, &str, Option<&str>)> {
+            analysis
+                .diagnostics
+                .iter()
+                .map(|d| {
+                    let data = d.data.as_ref().unwrap();
+                    let target = data.get("target").and_then(|t| t.as_str());
+                    let kind = data["kind"].as_str().unwrap();
+                    (d.range.start.line, d.severity.unwrap(), kind, target)
+                })
+                .collect()
+        }
+
+        // Outdated dev and build dependencies are less severe, unknown versions are not
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            severities(&analysis),
+            [
+                (5, lsp_types::DiagnosticSeverity::WARNING, "normal", None),
+                (8, lsp_types::DiagnosticSeverity::INFORMATION, "dev", None),
+                (9, lsp_types::DiagnosticSeverity::ERROR, "dev", None),
+                (
+                    12,
+                    lsp_types::DiagnosticSeverity::INFORMATION,
+                    "build",
+                    Some("cfg(unix)")
+                ),
+            ]
+        );
+        let messages = analysis
+            .diagnostics
+            .iter()
+            .map(|d| {
+                d.message
+                    .split("\n\n")
+                    .nth(1)
+                    .unwrap()
+                    .lines()
+                    .next()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "rand (0.11)",
+                "rand (0.11, dev-dependency)",
+                "unknown (5, dev-dependency)",
+                "rand (0.11, build-dependency for cfg(unix))"
+            ]
+        );
+        // Each section counts its own dependencies
+        let lenses = analysis
+            .code_lenses(&uri, &backend.source(text), &config)
+            .into_iter()
+            .map(|lens| {
+                let lens = analysis::resolve_lens(lens, config.language);
+                (lens.range.start.line, lens.command.unwrap().title)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lenses,
+            [
+                (4, "1 outdated · 0 unknown · 0 up to date".to_string()),
+                (7, "1 outdated · 1 unknown · 0 up to date".to_string()),
+                (11, "1 outdated · 0 unknown · 0 up to date".to_string()),
+            ]
+        );
+
+        // Configured like normal dependencies
+        let config = serde_json::from_value::<Config>(serde_json::json!({
+            "devDependencies": { "outdated": "warning" },
+            "buildDependencies": { "outdated": "warning" },
+        }))
+        .unwrap();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        let severities = severities(&analysis)
+            .into_iter()
+            .map(|(line, severity, _, _)| (line, severity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            severities,
+            [
+                (5, lsp_types::DiagnosticSeverity::WARNING),
+                (8, lsp_types::DiagnosticSeverity::WARNING),
+                (9, lsp_types::DiagnosticSeverity::ERROR),
+                (12, lsp_types::DiagnosticSeverity::WARNING),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn analyzes_virtual_manifests() {
+        let endpoint = registry(&["1.0.0", "2.0.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-virtual-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        let uri = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        let text = "[workspace]\nmembers = [\"app\", \"lib\"]\n\n[workspace.dependencies]\nserde = \"1.0.0\"\n";
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let codes = analysis
+            .diagnostics
+            .iter()
+            .filter_map(|d| d.code.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            [
+                NumberOrString::String("missing-member".into()),
+                NumberOrString::String("major-available".into()),
+                NumberOrString::String("outdated-incompatible".into()),
+            ]
+        );
+        assert_eq!(analysis.counts(DependencyKind::Workspace), (1, 0, 0));
+
+        backend.store_analysis(&uri, Arc::new(analysis)).await;
+        let params = DocumentSymbolParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let Some(DocumentSymbolResponse::Nested(symbols)) =
+            backend.document_symbol(params).await.unwrap()
+        else {
+            panic!("expected nested symbols");
+        };
+        let names = symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["app", "lib"]);
+    }
+
+    #[tokio::test]
+    async fn analyzes_only_the_text_of_other_schemes() {
+        let endpoint = registry(&["1.0.0", "2.0.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-schemes-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        let text = "[workspace]\nmembers = [\"app\", \"lib\"]\n\n[workspace.dependencies]\nserde = \"1.0.0\"\n";
+        let config = backend.config.borrow().clone();
+        let local = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        // The same path on another machine, which must not be read here
+        let remote: Uri = format!("ssh://host{}", local.path().as_str())
+            .parse()
+            .unwrap();
+        let untitled: Uri = "untitled:Cargo.toml".parse().unwrap();
+        assert!(is_cargo_toml(&remote) && is_cargo_toml(&untitled));
+
+        let mut codes = Vec::new();
+        for uri in [&local, &remote, &untitled] {
+            let analysis = analyze(backend, uri, &config, text).await.unwrap().unwrap();
+            codes.push(
+                analysis
+                    .diagnostics
+                    .iter()
+                    .filter_map(|d| d.code.clone())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let outdated = NumberOrString::String("outdated-incompatible".into());
+        let major = NumberOrString::String(version::MAJOR_AVAILABLE.into());
+        let missing = NumberOrString::String("missing-member".into());
+        assert_eq!(codes[0], [missing, major.clone(), outdated.clone()]);
+        assert_eq!(codes[1], codes[2]);
+        assert_eq!(codes[2], [major, outdated]);
+    }
+
+    #[tokio::test]
+    async fn reloads_configuration_files() {
+        let service = service("http://127.0.0.1:1");
+        let backend = service.inner();
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-reload-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        *backend.roots.write().await = vec![root.clone()];
+        let file = root.join(".crates-io-lsp.toml");
+        let uri = Uri::from_file_path(root.join("app/Cargo.toml")).unwrap();
+        let changed = |typ| DidChangeWatchedFilesParams {
+            changes: vec![lsp_types::FileEvent {
+                uri: Uri::from_file_path(&file).unwrap(),
+                typ,
+            }],
+        };
+
+        std::fs::write(&file, "packageLints = true\nperDependencyLens = true\n").unwrap();
+        let config = backend.document_config(&uri).await;
+        assert!(config.package_lints && config.per_dependency_lens);
+
+        // The client settings take precedence, except for unset fields
+        backend
+            .update_config(serde_json::json!({ "perDependencyLens": false }))
+            .await;
+        let config = backend.document_config(&uri).await;
+        assert!(config.package_lints && !config.per_dependency_lens);
+
+        // Cached until the file is reported as changed
+        std::fs::write(&file, "packageLints = false\n").unwrap();
+        assert!(backend.document_config(&uri).await.package_lints);
+        backend
+            .did_change_watched_files(changed(FileChangeType::CHANGED))
+            .await;
+        assert!(!backend.document_config(&uri).await.package_lints);
+
+        // Malformed files are ignored
+        std::fs::write(&file, "packageLints = \n").unwrap();
+        backend
+            .did_change_watched_files(changed(FileChangeType::CHANGED))
+            .await;
+        assert_eq!(backend.config_files.read().await.get(&file), Some(&None));
+        assert!(!backend.document_config(&uri).await.package_lints);
+
+        std::fs::write(&file, "packageLints = true\n").unwrap();
+        backend
+            .did_change_watched_files(changed(FileChangeType::CHANGED))
+            .await;
+        assert!(backend.document_config(&uri).await.package_lints);
+        std::fs::remove_file(&file).unwrap();
+        backend
+            .did_change_watched_files(changed(FileChangeType::DELETED))
+            .await;
+        assert!(!backend.document_config(&uri).await.package_lints);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn drops_only_the_invalid_settings() {
+        use futures::StreamExt;
+
+        let registry = registry::MockClient::new(HashMap::new());
+        let (service, mut socket) = socket_service(Shared::new(Arc::new(registry)));
+        let (messages, mut shown) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                if request.method() == "window/showMessage" {
+                    let params = request.params().unwrap();
+                    let _ = messages.send(params["message"].as_str().unwrap().to_string());
+                }
+            }
+        });
+        let backend = service.inner();
+        backend
+            .update_config(serde_json::json!({
+                "packageLints": true,
+                "severity": { "unknwon": "hint", "latest": "warning" },
+                "fetchTimeout": "long",
+                "env": { "RUST_LOG": "debug" },
+            }))
+            .await;
+        assert_eq!(
+            shown.recv().await.unwrap(),
+            "Invalid settings of crates-io:\n\
+             - `fetchTimeout` must be a non-negative integer, it is ignored\n\
+             - Unknown setting `severity.unknwon`, did you mean `severity.unknown`?"
+        );
+        let config = backend.config.borrow().clone();
+        assert!(config.package_lints);
+        assert_eq!(config.severity.latest, config::Severity::Warning);
+        assert_eq!(config.fetch_timeout, Config::default().fetch_timeout);
+        assert_eq!(
+            *backend.options.read().await,
+            serde_json::json!({ "packageLints": true, "severity": { "latest": "warning" } })
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_where_the_settings_come_from() {
+        let service = service("http://127.0.0.1:1");
+        let backend = service.inner();
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-provenance-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        *backend.roots.write().await = vec![root.clone()];
+        let file = root.join(".crates-io-lsp.toml");
+        std::fs::write(
+            &file,
+            "perDependencyLens = true\ncacheCapacity = 1\nsortTabels = true\n\
+             [severity]\nlatest = \"warning\"\nunknown = \"hint\"\n",
+        )
+        .unwrap();
+        backend
+            .update_config(serde_json::json!({
+                "severity": { "unknown": "error" },
+                "cacheCapacity": 5,
+            }))
+            .await;
+        let uri = Uri::from_file_path(root.join("app/Cargo.toml")).unwrap();
+        let config = |arguments| async {
+            let params = ExecuteCommandParams {
+                command: schema::CONFIG.into(),
+                arguments,
+                work_done_progress_params: Default::default(),
+            };
+            backend.execute_command(params).await.unwrap().unwrap()
+        };
+
+        let result = config(vec![uri.as_str().into()]).await;
+        assert_eq!(result["uri"], uri.as_str());
+        assert_eq!(result["file"], Uri::from_file_path(&file).unwrap().as_str());
+        let setting = |key: &str| {
+            let setting = &result["settings"][key];
+            (setting["value"].clone(), setting["source"].clone())
+        };
+        assert_eq!(
+            setting("perDependencyLens"),
+            (true.into(), "workspaceFile".into())
+        );
+        assert_eq!(
+            setting("severity.latest"),
+            ("warning".into(), "workspaceFile".into())
+        );
+        assert_eq!(
+            setting("severity.unknown"),
+            ("error".into(), "clientSettings".into())
+        );
+        assert_eq!(setting("packageLints"), (false.into(), "default".into()));
+        // Only the client settings change the cache, the misspelled key is dropped
+        assert_eq!(
+            setting("cacheCapacity"),
+            (5.into(), "clientSettings".into())
+        );
+        assert_eq!(setting("sortTables"), (false.into(), "default".into()));
+        assert!(result["settings"].get("sortTabels").is_none());
+
+        // Without a document only the client settings apply
+        let result = config(Vec::new()).await;
+        assert_eq!(
+            (&result["uri"], &result["file"]),
+            (&Value::Null, &Value::Null)
+        );
+        assert_eq!(result["settings"]["perDependencyLens"]["source"], "default");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn diagnoses_broken_manifests() {
+        let endpoint = registry(&["1.0.0", "2.0.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\nrand = {\n\n\
+            [dev-dependencies]\ntokio = \"1\"\n\n[profile.release]\nlto = \n";
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        let codes = analysis
+            .diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.code.clone().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            [
+                (5, NumberOrString::String("parse-error".into())),
+                (8, NumberOrString::String("major-available".into())),
+                (8, NumberOrString::String("outdated-incompatible".into())),
+            ]
+        );
+    }
+
+    fn releases(versions: &[&str]) -> Releases {
+        versions
+            .iter()
+            .filter_map(|v| version::Release::parse(v, false))
+            .collect()
+    }
+
+    /// Track `text` as open, only open documents are published.
+    async fn open(backend: &CratesIoBackend, uri: &Uri, text: &str) {
+        backend.open_docs.insert(
+            uri.clone(),
+            FileInfo::new(text.into(), 0, backend.caps.borrow().position_encoding),
+        );
+    }
+
+    /// Codes of the diagnostics of the stored analysis, waiting until there is one.
+    async fn stored_codes(backend: &CratesIoBackend, uri: &Uri) -> Vec<(u32, String)> {
+        loop {
+            if let Some(analysis) = backend.analyses.write().await.remove(uri) {
+                let mut codes = analysis
+                    .diagnostics
+                    .iter()
+                    .filter_map(|d| match &d.code {
+                        Some(NumberOrString::String(code)) => {
+                            Some((d.range.start.line, code.clone()))
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                codes.sort();
+                return codes;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn publishes_partial_results() {
+        let release = Arc::new(Notify::new());
+        let endpoint = registry_holding(&["1.0.0", "2.0.0"], "/hung", Some(release.clone())).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+        backend
+            .update_config(serde_json::json!({ "analysisDeadline": 1000 }))
+            .await;
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\nhung = \"1\"\n";
+        open(backend, &uri, text).await;
+        let start = Instant::now();
+        let source = backend.source(text);
+        let update = backend.update_diagnostics(&uri, None, &source, Priority::Interactive);
+        let check = async {
+            // The crates resolved before the deadline
+            let codes = stored_codes(backend, &uri).await;
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(1000), "{elapsed:?}");
+            assert!(elapsed < Duration::from_secs(5), "{elapsed:?}");
+            assert_eq!(
+                codes,
+                [
+                    (4, "major-available".into()),
+                    (4, "outdated-incompatible".into()),
+                    (5, "fetch-pending".into())
+                ]
+            );
+
+            // Followed by the crates that missed it
+            release.notify_waiters();
+            let codes = stored_codes(backend, &uri).await;
+            assert_eq!(
+                codes,
+                [
+                    (4, "major-available".into()),
+                    (4, "outdated-incompatible".into()),
+                    (5, "major-available".into()),
+                    (5, "outdated-incompatible".into())
+                ]
+            );
+        };
+        tokio::join!(update, check);
+    }
+
+    #[tokio::test]
+    async fn skips_the_follow_up_of_changed_documents() {
+        let release = Arc::new(Notify::new());
+        let endpoint = registry_holding(&["1.0.0", "2.0.0"], "/hung", Some(release.clone())).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+        backend
+            .update_config(serde_json::json!({ "analysisDeadline": 1000, "showPending": false }))
+            .await;
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nhung = \"1\"\n";
+        open(backend, &uri, text).await;
+        let source = backend.source(text);
+        let first = backend.update_diagnostics(&uri, None, &source, Priority::Interactive);
+        let check = async {
+            assert_eq!(stored_codes(backend, &uri).await, []);
+            // The document changed, replacing the job of the first analysis
+            let changed = format!("{text}serde = \"1\"\n");
+            let changed = backend.source(&changed);
+            let second = backend.update_diagnostics(&uri, None, &changed, Priority::Interactive);
+            let check = async {
+                assert_eq!(
+                    stored_codes(backend, &uri).await,
+                    [
+                        (5, "major-available".into()),
+                        (5, "outdated-incompatible".into())
+                    ]
+                );
+                release.notify_waiters();
+            };
+            tokio::join!(second, check);
+        };
+        tokio::join!(first, check);
+        // Only the second analysis published its follow-up
+        assert_eq!(
+            stored_codes(backend, &uri).await,
+            [
+                (4, "major-available".into()),
+                (4, "outdated-incompatible".into()),
+                (5, "major-available".into()),
+                (5, "outdated-incompatible".into())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn renames_during_the_analysis() {
+        let release = Arc::new(Notify::new());
+        let endpoint = registry_holding(&["1.0.0", "2.0.0"], "/hung", Some(release.clone())).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+
+        let old: Uri = "file:///old/Cargo.toml".parse().unwrap();
+        let new: Uri = "file:///new/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nhung = \"1\"\n";
+        open(backend, &old, text).await;
+        let releaser = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            loop {
+                release.notify_waiters();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        let source = backend.source(text);
+        let update = backend.update_diagnostics(&old, Some(0), &source, Priority::Interactive);
+        let rename = async {
+            // Renamed while the fetch is in flight
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            backend
+                .did_rename_files(RenameFilesParams {
+                    files: vec![FileRename {
+                        old_uri: old.to_string(),
+                        new_uri: new.to_string(),
+                    }],
+                })
+                .await;
+        };
+        tokio::join!(update, rename);
+        releaser.abort();
+
+        // The stale analysis did not attach anything to the old URI
+        assert!(!backend.open_docs.contains(&old));
+        assert!(backend.references.read().await.get(&old).is_none());
+        assert!(!backend.analyses.read().await.contains_key(&old));
+        assert!(backend.open_docs.contains(&new));
+        assert_eq!(
+            stored_codes(backend, &new).await,
+            [
+                (4, "major-available".into()),
+                (4, "outdated-incompatible".into())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn moves_directories_and_forgets_deleted_manifests() {
+        let endpoint = registry(&["1.0.0", "2.0.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n";
+        for path in ["ws/a", "ws/b", "wsx"] {
+            let uri: Uri = format!("file:///{path}/Cargo.toml").parse().unwrap();
+            open(backend, &uri, text).await;
+        }
+        backend
+            .did_rename_files(RenameFilesParams {
+                files: vec![FileRename {
+                    old_uri: "file:///ws".into(),
+                    new_uri: "file:///moved".into(),
+                }],
+            })
+            .await;
+        let mut open_docs = backend
+            .open_docs
+            .uris()
+            .iter()
+            .map(|uri| uri.to_string())
+            .collect::<Vec<_>>();
+        open_docs.sort();
+        assert_eq!(
+            open_docs,
+            [
+                "file:///moved/a/Cargo.toml",
+                "file:///moved/b/Cargo.toml",
+                "file:///wsx/Cargo.toml"
+            ]
+        );
+        let moved: Uri = "file:///moved/a/Cargo.toml".parse().unwrap();
+        assert!(backend.analyses.read().await.contains_key(&moved));
+
+        backend
+            .did_delete_files(DeleteFilesParams {
+                files: vec![FileDelete {
+                    uri: "file:///moved/a".into(),
+                }],
+            })
+            .await;
+        assert!(!backend.open_docs.contains(&moved));
+        assert!(!backend.analyses.read().await.contains_key(&moved));
+        assert!(backend.references.read().await.get(&moved).is_none());
+        assert_eq!(backend.open_docs.len(), 2);
+    }
+
+    /// Open and analyze manifests of the crate `app` with one dependency each,
+    /// the cache only knows `1.0.0` of them.
+    async fn open_stale(backend: &CratesIoBackend, deps: &[&str]) -> Vec<Uri> {
+        let mut uris = Vec::new();
+        for dep in deps {
+            let stale = releases(&["1.0.0"]);
+            let pinned = HashSet::new();
+            // Fetched just now, so it does not expire
+            let now = backend.clock.unix_time();
+            backend
+                .cache
+                .write()
+                .await
+                .insert(dep.to_string(), stale, now, &pinned);
+            let uri: Uri = format!("file:///{dep}/Cargo.toml").parse().unwrap();
+            let text = format!("[package]\nname = \"app\"\n\n[dependencies]\n{dep} = \"1\"\n");
+            open(backend, &uri, &text).await;
+            backend
+                .update_diagnostics(&uri, Some(0), &backend.source(&text), Priority::Interactive)
+                .await;
+            assert_eq!(stored_codes(backend, &uri).await, [(4, "latest".into())]);
+            uris.push(uri);
+        }
+        uris
+    }
+
+    #[tokio::test]
+    async fn refreshes_all_crates() {
+        let endpoint = registry(&["1.0.0", "2.0.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+        let uris = open_stale(backend, &["serde", "tokio"]).await;
+
+        let summary = backend.refresh(None).await;
+        assert_eq!((summary.invalidated, summary.reanalyzed), (2, 2));
+        for uri in &uris {
+            assert_eq!(
+                stored_codes(backend, uri).await,
+                [
+                    (4, "major-available".into()),
+                    (4, "outdated-incompatible".into())
+                ]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn refreshes_one_crate() {
+        let endpoint = registry(&["1.0.0", "2.0.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+        let uris = open_stale(backend, &["serde", "tokio"]).await;
+
+        let refresh = |arguments| ExecuteCommandParams {
+            command: REFRESH.into(),
+            arguments,
+            ..Default::default()
+        };
+        let invalid = refresh(vec![serde_json::json!("no/crate")]);
+        assert!(backend.execute_command(invalid).await.is_err());
+        let summary = backend
+            .execute_command(refresh(vec![serde_json::json!("serde")]))
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            Some(serde_json::json!({ "invalidated": 1, "reanalyzed": 1 }))
+        );
+        assert_eq!(
+            stored_codes(backend, &uris[0]).await,
+            [
+                (4, "major-available".into()),
+                (4, "outdated-incompatible".into())
+            ]
+        );
+        // The other crate and its document are untouched
+        assert!(!backend.analyses.read().await.contains_key(&uris[1]));
+        let cache = backend.cache.read().await;
+        assert_eq!(cache.get("tokio"), Some(&releases(&["1.0.0"])));
+    }
+
+    #[tokio::test]
+    async fn refresh_discards_fetches_in_flight() {
+        let release = Arc::new(Notify::new());
+        let endpoint = registry_holding(&["1.0.0", "2.0.0"], "/hung", Some(release.clone())).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+
+        // Analyzed without being open, so the refresh does not cancel it
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nhung = \"1\"\n";
+        let config = backend.config.borrow().clone();
+        let (analysis, summary) = tokio::join!(analyze(backend, &uri, &config, text), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let summary = backend.refresh(Some("hung")).await;
+            release.notify_waiters();
+            summary
+        });
+        assert_eq!((summary.invalidated, summary.reanalyzed), (0, 0));
+
+        // The fetch is used by its analysis but not cached
+        let analysis = analysis.unwrap().unwrap();
+        let codes = analysis.diagnostics.iter().map(|d| d.code.clone());
+        assert!(codes.eq([
+            Some(NumberOrString::String("major-available".into())),
+            Some(NumberOrString::String("outdated-incompatible".into()))
+        ]));
+        assert!(backend.cache.read().await.get("hung").is_none());
+    }
+
+    /// Titles and edits of the refactoring actions on `line`.
+    async fn rewrites(backend: &CratesIoBackend, uri: &Uri, line: u32) -> Vec<(String, TextEdit)> {
+        rewrites_at(backend, uri, Position::new(line, 0)).await
+    }
+
+    /// Titles and edits of the rewrites at `position`.
+    async fn rewrites_at(
+        backend: &CratesIoBackend,
+        uri: &Uri,
+        position: Position,
+    ) -> Vec<(String, TextEdit)> {
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: Range::new(position, position),
+            context: CodeActionContext {
+                only: Some(vec![CodeActionKind::REFACTOR_REWRITE]),
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        actions
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => {
+                    let edit = action.edit?.changes?.get(uri)?.first()?.clone();
+                    Some((action.title, edit))
+                }
+                CodeActionOrCommand::Command(_) => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn indexes_the_lines_after_each_change() {
+        let service = shared_service(Shared::new(Arc::new(registry::MockClient::new(
+            HashMap::new(),
+        ))));
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        open(backend, &uri, "[dependencies]\r\nserde = \"1\"\r\n").await;
+        let change = |range: Range, text: &str| TextDocumentContentChangeEvent {
+            range: Some(range),
+            range_length: None,
+            text: text.into(),
+        };
+        // The second change is on the line inserted by the first
+        let changes = vec![
+            change(
+                Range::new(Position::new(1, 0), Position::new(1, 0)),
+                "rand = \"0.8\"\r\n",
+            ),
+            change(Range::new(Position::new(1, 8), Position::new(1, 11)), "0.9"),
+        ];
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+                content_changes: changes,
+            })
+            .await;
+        let doc = backend.open_docs.with(&uri, FileInfo::clone).unwrap();
+        assert_eq!(
+            doc.source.text,
+            "[dependencies]\r\nrand = \"0.9\"\r\nserde = \"1\"\r\n"
+        );
+        assert_eq!(*doc.source, backend.source(&doc.source.text));
+        assert_eq!(doc.version, 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn requests_on_different_documents_run_concurrently() {
+        let versions = vec!["1.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]));
+        let service = Arc::new(shared_service(Shared::new(Arc::new(registry))));
+        let uris = (0..4)
+            .map(|i| format!("file:///app{i}/Cargo.toml").parse::<Uri>().unwrap())
+            .collect::<Vec<_>>();
+        for uri in &uris {
+            open(service.inner(), uri, "[dependencies]\nserde = \"1\"\n").await;
+        }
+        const EDITS: i32 = 50;
+        let mut tasks = tokio::task::JoinSet::new();
+        for (i, uri) in uris.iter().enumerate() {
+            // Edits of one document, each line inserted after the previous one
+            let (editor, edited) = (service.clone(), uri.clone());
+            tasks.spawn(async move {
+                for n in 0..EDITS {
+                    let line = n as u32 + 2;
+                    let change = TextDocumentContentChangeEvent {
+                        range: Some(Range::new(Position::new(line, 0), Position::new(line, 0))),
+                        range_length: None,
+                        text: format!("dep{n} = \"1\"\n"),
+                    };
+                    editor
+                        .inner()
+                        .did_change(DidChangeTextDocumentParams {
+                            text_document: VersionedTextDocumentIdentifier::new(
+                                edited.clone(),
+                                n + 1,
+                            ),
+                            content_changes: vec![change],
+                        })
+                        .await;
+                    tokio::task::yield_now().await;
+                }
+            });
+            // Hovers and completions of the next document meanwhile
+            let (reader, read) = (service.clone(), uris[(i + 1) % uris.len()].clone());
+            tasks.spawn(async move {
+                for _ in 0..EDITS {
+                    let position = TextDocumentPositionParams::new(
+                        TextDocumentIdentifier::new(read.clone()),
+                        Position::new(1, 2),
+                    );
+                    let hover = HoverParams {
+                        text_document_position_params: position.clone(),
+                        work_done_progress_params: Default::default(),
+                    };
+                    reader.inner().hover(hover).await.unwrap();
+                    let completion = CompletionParams {
+                        text_document_position: position,
+                        work_done_progress_params: Default::default(),
+                        partial_result_params: Default::default(),
+                        context: None,
+                    };
+                    reader.inner().completion(completion).await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+            });
+        }
+        let joined = tokio::time::timeout(Duration::from_secs(30), async {
+            while let Some(task) = tasks.join_next().await {
+                task.unwrap();
+            }
+        });
+        joined.await.expect("deadlocked");
+
+        let mut expected = "[dependencies]\nserde = \"1\"\n".to_string();
+        for n in 0..EDITS {
+            expected.push_str(&format!("dep{n} = \"1\"\n"));
+        }
+        for uri in &uris {
+            let doc = service
+                .inner()
+                .open_docs
+                .with(uri, FileInfo::clone)
+                .unwrap();
+            assert_eq!(doc.source.text, expected, "{}", uri.as_str());
+            assert_eq!(*doc.source, service.inner().source(&expected));
+            assert_eq!(doc.version, EDITS);
+        }
+    }
+
+    #[tokio::test]
+    async fn maps_errors_to_codes() {
+        let service = shared_service(Shared::new(Arc::new(registry::MockClient::new(
+            HashMap::new(),
+        ))));
+        let backend = service.inner();
+        let command = |command: &str, arguments: Vec<Value>| ExecuteCommandParams {
+            command: command.into(),
+            arguments,
+            work_done_progress_params: Default::default(),
+        };
+        let code = |result: jsonrpc::Result<Option<Value>>| {
+            let error = result.unwrap_err();
+            (error.code, error.data.unwrap()["kind"].clone())
+        };
+        let stats = backend.execute_command(command(stats::STATS, vec![])).await;
+        assert_eq!(
+            code(stats),
+            (
+                jsonrpc::ErrorCode::InvalidRequest,
+                serde_json::json!("disabled")
+            )
+        );
+        let everywhere = serde_json::json!({ "name": "../serde" });
+        let everywhere = backend
+            .execute_command(command(everywhere::UPDATE_EVERYWHERE, vec![everywhere]))
+            .await;
+        assert_eq!(
+            code(everywhere),
+            (
+                jsonrpc::ErrorCode::InvalidParams,
+                serde_json::json!("invalid-name")
+            )
+        );
+        let graph = serde_json::json!({ "uri": "file:///nowhere/Cargo.toml" });
+        let graph = backend
+            .execute_command(command(graph::EXPORT_GRAPH, vec![graph]))
+            .await;
+        assert_eq!(
+            code(graph),
+            (jsonrpc::ErrorCode::InternalError, serde_json::json!("io"))
+        );
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        // A parse error keeps its span and code
+        let analysis = analyze(backend, &uri, &Config::default(), "[dependencies\n")
+            .await
+            .unwrap()
+            .unwrap();
+        let codes = analysis
+            .diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.code.clone().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(codes, [(0, NumberOrString::String("parse-error".into()))]);
+    }
+
+    #[tokio::test]
+    async fn reverts_to_the_saved_requirements() {
+        let endpoint = registry(&["1.0.0", "1.0.188"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1.0.0\"\n";
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "toml".into(), 1, text.into()),
+            })
+            .await;
+        let change = |version, text: &str| DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), version),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.into(),
+            }],
+        };
+        let saved = text.replace("1.0.0", "1.0.188");
+        backend.did_change(change(2, &saved)).await;
+        backend
+            .did_save(DidSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+                text: None,
+            })
+            .await;
+        assert_eq!(rewrites(backend, &uri, 4).await, []);
+
+        // Bumped in the editor, with a new dependency below
+        let edited = saved.replace("\"1.0.188\"", "{ version = \"2\" }") + "anyhow = \"1\"\n";
+        backend.did_change(change(3, &edited)).await;
+        assert_eq!(
+            rewrites(backend, &uri, 4).await,
+            [(
+                "Revert to saved requirement (1.0.188)".into(),
+                TextEdit::new(
+                    Range::new(Position::new(4, 21), Position::new(4, 22)),
+                    "1.0.188".into()
+                )
+            )]
+        );
+        assert_eq!(
+            rewrites(backend, &uri, 5).await,
+            [(
+                "Remove dependency anyhow".into(),
+                TextEdit::new(
+                    Range::new(Position::new(5, 0), Position::new(6, 0)),
+                    String::new()
+                )
+            )]
+        );
+
+        backend
+            .did_close(DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+            })
+            .await;
+        assert_eq!(rewrites(backend, &uri, 4).await, []);
+    }
+
+    #[tokio::test]
+    async fn localizes_the_diagnostics() {
+        let endpoint = registry(&["1.0.0", "2.0.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+        let params = InitializeParams {
+            locale: Some("de-DE".into()),
+            initialization_options: Some(serde_json::json!({ "locale": "en" })),
+            ..Default::default()
+        };
+        backend.initialize(params).await.unwrap();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n";
+        let config = backend.config.borrow().clone();
+        assert_eq!(config.language, Locale::De.into());
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            analysis.diagnostics[0].message,
+            "Neue Hauptversion 2.0.0 verfügbar, genutzt wird die Reihe 1.x"
+        );
+        let message = &analysis.diagnostics[1].message;
+        assert!(
+            message.starts_with("Veraltete Version, 2.0.0 erfordert eine Änderung des Manifests"),
+            "{message}"
+        );
+    }
+
+    #[tokio::test]
+    async fn negotiates_the_position_encoding() {
+        let endpoint = registry(&["1.0.0"], None).await;
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\n\"ä\" = { package = \"serde\", version = \"1\" }\n";
+        let line = text.lines().nth(1).unwrap();
+        // The requirement without its quotes, after the two bytes but one unit of `ä`
+        let bytes = line.find("\"1\"").unwrap() as u32 + 1;
+        for (offered, kind, column) in [
+            (
+                Some(vec![lsp_types::PositionEncodingKind::UTF8]),
+                lsp_types::PositionEncodingKind::UTF8,
+                bytes,
+            ),
+            (None, lsp_types::PositionEncodingKind::UTF16, bytes - 1),
+        ] {
+            let service = service(&endpoint);
+            let backend = service.inner();
+            let mut params = InitializeParams::default();
+            params.capabilities.general = Some(lsp_types::GeneralClientCapabilities {
+                position_encodings: offered,
+                ..Default::default()
+            });
+            let result = backend.initialize(params).await.unwrap();
+            assert_eq!(result.capabilities.position_encoding, Some(kind.clone()));
+
+            let config = backend.config.borrow().clone();
+            let analysis = analyze(backend, &uri, &config, text)
+                .await
+                .unwrap()
+                .unwrap();
+            let range = analysis.dependencies[0].req_range.unwrap();
+            assert_eq!(range.start, Position::new(1, column), "{kind:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn describes_the_diagnostics_in_plain_language() {
+        let endpoint = registry(&["1.0.0", "1.2.0", "2.0.0", "2.1.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+        let params = InitializeParams {
+            initialization_options: Some(serde_json::json!({ "messageAccessibility": true })),
+            ..Default::default()
+        };
+        backend.initialize(params).await.unwrap();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n";
+        let config = backend.config.borrow().clone();
+        assert!(config.language.accessible);
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        let messages = analysis
+            .diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages[1],
+            "Warning, outdated dependency: The current requirement allows up to 1.2.0; \
+            the newest release is 2.1.0, which is 2 releases newer and needs a manifest change"
+        );
+        // No symbols or version lists
+        assert!(
+            messages
+                .iter()
+                .all(|m| !m.contains('\n') && !m.contains("serde")),
+            "{messages:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn renames_unknown_keys() {
+        let service = service("http://127.0.0.1:1");
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text =
+            "[depenencies]\n\n[dependencies]\nserde = { version = \"1\", colour = \"red\" }\n";
+        let diagnostics = keys::lint(
+            &backend.source(text),
+            &Unstable::default(),
+            &Config::default(),
+        );
+        assert_eq!(diagnostics.len(), 2);
+
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: diagnostics[0].range,
+            context: CodeActionContext {
+                diagnostics,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        // Only the misspelling has a fix
+        let [CodeActionOrCommand::CodeAction(action)] = &actions[..] else {
+            panic!("{actions:?}");
+        };
+        assert_eq!(action.title, "Rename to `dependencies`");
+        assert_eq!(action.kind, Some(CodeActionKind::QUICKFIX));
+        let edit = action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri].clone();
+        assert_eq!(
+            edit,
+            [TextEdit::new(
+                Range::new(Position::new(0, 1), Position::new(0, 12)),
+                "dependencies".into()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn fixes_mistyped_dependency_values() {
+        let versions = vec!["1.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([
+            ("serde".into(), versions.clone()),
+            ("rand".into(), versions),
+        ]));
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = { version = \"1.0.0\", features = \"derive, rc\" }\nrand = \"1.0.0\"\n";
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        // The section is still analyzed, without the generic parse error
+        let codes = analysis
+            .diagnostics
+            .iter()
+            .map(|d| (d.range, d.code.clone()))
+            .collect::<Vec<_>>();
+        let value = Range::new(Position::new(1, 40), Position::new(1, 52));
+        let code = |code: &str| Some(NumberOrString::String(code.into()));
+        assert_eq!(
+            codes,
+            [
+                (
+                    Range::new(Position::new(1, 0), Position::new(1, 5)),
+                    code("latest")
+                ),
+                (value, code(keys::MISTYPED_VALUE)),
+                (
+                    Range::new(Position::new(2, 0), Position::new(2, 4)),
+                    code("latest")
+                ),
+            ]
+        );
+        let mistyped = &analysis.diagnostics[1];
+        assert_eq!(
+            mistyped.severity,
+            Some(lsp_types::DiagnosticSeverity::ERROR)
+        );
+        assert_eq!(
+            mistyped.message,
+            "`features` must be an array of strings, like `[\"a\", \"b\"]`"
+        );
+
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: value,
+            context: CodeActionContext {
+                diagnostics: vec![mistyped.clone()],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = &actions[..] else {
+            panic!("{actions:?}");
+        };
+        assert_eq!(action.title, "Change to `[\"derive\", \"rc\"]`");
+        let edit = action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri].clone();
+        assert_eq!(edit, [TextEdit::new(value, "[\"derive\", \"rc\"]".into())]);
+    }
+
+    #[tokio::test]
+    async fn checks_profiles_and_lints_of_workspaces() {
+        let endpoint = registry(&["1.0.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-profile-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        let root_text = r#"[workspace]
+members = ["app"]
+
+[profile.dev.package."*"]
+opt-level = 1
+
+[profile.release.package.serde]
+opt-level = 3
+
+[profile.release.package.old]
+opt-level = 3
+"#;
+        let member_text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n\n[lints]\nworkspace = true\n";
+        std::fs::write(root.join("Cargo.toml"), root_text).unwrap();
+        std::fs::write(root.join("app/Cargo.toml"), member_text).unwrap();
+        let root_uri = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        let member_uri = Uri::from_file_path(root.join("app/Cargo.toml")).unwrap();
+        let config = backend.config.borrow().clone();
+        let root_analysis = analyze(backend, &root_uri, &config, root_text).await;
+        let member_analysis = analyze(backend, &member_uri, &config, member_text).await;
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let codes = |analysis: &Analysis| {
+            analysis
+                .diagnostics
+                .iter()
+                .filter_map(|d| match &d.code {
+                    Some(NumberOrString::String(code)) => Some((d.range.start.line, code.clone())),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        // `serde` is declared by the member
+        let root_analysis = root_analysis.unwrap().unwrap();
+        assert_eq!(
+            codes(&root_analysis),
+            [(9, "unknown-profile-package".into())]
+        );
+        let member_analysis = member_analysis.unwrap().unwrap();
+        assert_eq!(
+            codes(&member_analysis),
+            [(4, "latest".into()), (7, "missing-workspace-lints".into())]
+        );
+
+        // Overrides show the crate information
+        backend
+            .store_analysis(&root_uri, Arc::new(root_analysis))
+            .await;
+        let params = HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(root_uri),
+                Position::new(6, 26),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        let hover = backend.hover(params).await.unwrap().unwrap();
+        assert_eq!(
+            hover.contents,
+            HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "**serde**\n\n_registry data from less than a minute ago, refreshing roughly every 15 min_".into()
+            })
+        );
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(6, 25), Position::new(6, 30)))
+        );
+    }
+
+    #[tokio::test]
+    async fn analyzes_with_a_local_index_directory() {
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-local-index-{}", std::process::id()));
+        let write = |path: &str, versions: &[(&str, bool)]| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let lines = versions
+                .iter()
+                .map(|(v, yanked)| format!("{{\"vers\":\"{v}\",\"yanked\":{yanked}}}\n"))
+                .collect::<String>();
+            std::fs::write(&path, lines).unwrap();
+            path
+        };
+        let serde = [("1.0.0", false), ("1.0.5", false), ("1.0.6", true)];
+        let serde_path = write("se/rd/serde", &serde);
+        write("ra/nd/rand", &[("0.8.5", false), ("0.9.0", true)]);
+        // A bare path, no HTTP involved
+        let service = service(root.to_str().unwrap());
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"1.0.0\"\nrand = \"0.8\"\nmissing = \"1\"\n";
+        open(backend, &uri, text).await;
+        let config = backend.config.borrow().clone();
+        let messages = |analysis: Analysis| {
+            analysis
+                .diagnostics
+                .into_iter()
+                .map(|d| {
+                    (
+                        d.range.start.line,
+                        d.message.lines().next().unwrap().to_string(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            messages(analysis),
+            [
+                (
+                    1,
+                    "Outdated Version, compatible update to 1.0.5 (cargo update)".to_string()
+                ),
+                (2, "Latest Version".to_string()),
+            ]
+        );
+
+        // Read again once the file changed
+        let mut serde = serde.to_vec();
+        serde.push(("1.0.7", false));
+        write("se/rd/serde", &serde);
+        let future = std::time::SystemTime::now() + Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&serde_path)
+            .unwrap()
+            .set_modified(future)
+            .unwrap();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            messages(analysis)[0],
+            (
+                1,
+                "Outdated Version, compatible update to 1.0.7 (cargo update)".to_string()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn analyzes_with_any_registry_client() {
+        let service = service("http://127.0.0.1:1");
+        let backend = service.inner();
+        let versions = |v: &[&str]| v.iter().map(|v| v.to_string()).collect();
+        let mock = registry::MockClient::new(HashMap::from([
+            ("serde".into(), versions(&["1.0.0", "1.0.1"])),
+            ("rand".into(), versions(&["0.8.5", "0.9.0"])),
+        ]));
+        *backend.registry.write().await = Arc::new(mock);
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"1.0.0\"\nrand = \"0.9\"\nmissing = \"1\"\n";
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut codes = analysis
+            .diagnostics
+            .iter()
+            .filter_map(|d| match &d.code {
+                Some(NumberOrString::String(code)) => Some((d.range.start.line, code.as_str())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        codes.sort();
+        // The failed fetch is only logged
+        assert_eq!(codes, [(1, "outdated-compatible"), (2, "latest")]);
+    }
+
+    /// Backend answering each crate with `1.0.0` and `2.0.0` after its delay in milliseconds.
+    fn delayed_service(delays: &[(&str, u64)]) -> LspService<CratesIoBackend> {
+        shared_service(delayed_shared(delays))
+    }
+
+    /// Caches of the registry of [`delayed_service`].
+    fn delayed_shared(delays: &[(&str, u64)]) -> Shared {
+        let versions = vec!["1.0.0".to_string(), "2.0.0".to_string()];
+        let crates = delays
+            .iter()
+            .map(|(n, _)| (n.to_string(), versions.clone()));
+        let registry = delays.iter().fold(
+            registry::MockClient::new(crates.collect()),
+            |registry, (name, delay)| registry.with_latency(name, Duration::from_millis(*delay)),
+        );
+        Shared::new(Arc::new(registry))
+    }
+
+    /// Backend of a client connected to a server whose caches are `shared`.
+    fn shared_service(shared: Shared) -> LspService<CratesIoBackend> {
+        socket_service(shared).0
+    }
+
+    /// Like [`shared_service`], with the socket of the requests to the client.
+    fn socket_service(shared: Shared) -> (LspService<CratesIoBackend>, ClientSocket) {
+        clock_service(shared, Arc::new(TokioClock))
+    }
+
+    /// Like [`socket_service`], with the time of `clock`.
+    fn clock_service(
+        shared: Shared,
+        clock: Arc<dyn Clock>,
+    ) -> (LspService<CratesIoBackend>, ClientSocket) {
+        lsp_service(clock_service_args(), shared, clock)
+    }
+
+    /// Arguments of [`clock_service`], with an unreachable registry.
+    fn clock_service_args() -> Args {
+        Args {
+            endpoint: vec!["http://127.0.0.1:1".into()],
+            token: String::new(),
+            health_check: false,
+            trace_file: None,
+            trace_text_limit: None,
+            command: None,
+            listen: None,
+            socket: None,
+            connect: None,
+            cargo: "cargo".into(),
+            cache_dir: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn publishes_at_the_virtual_deadline() {
+        let service = delayed_service(&[("serde", 10), ("slow", 20_000)]);
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\nslow = \"1\"\n";
+        open(backend, &uri, text).await;
+        let start = Instant::now();
+        let source = backend.source(text);
+        let update = backend.update_diagnostics(&uri, None, &source, Priority::Interactive);
+        let check = async {
+            // Polled every 10 ms
+            let polled = |ms| {
+                let elapsed = start.elapsed();
+                assert!(elapsed >= Duration::from_millis(ms), "{elapsed:?}");
+                assert!(elapsed <= Duration::from_millis(ms + 10), "{elapsed:?}");
+            };
+            let codes = stored_codes(backend, &uri).await;
+            polled(5000);
+            assert_eq!(
+                codes,
+                [
+                    (4, "major-available".into()),
+                    (4, "outdated-incompatible".into()),
+                    (5, "fetch-pending".into())
+                ]
+            );
+            let codes = stored_codes(backend, &uri).await;
+            polled(20_000);
+            assert_eq!(
+                codes,
+                [
+                    (4, "major-available".into()),
+                    (4, "outdated-incompatible".into()),
+                    (5, "major-available".into()),
+                    (5, "outdated-incompatible".into())
+                ]
+            );
+        };
+        tokio::join!(update, check);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn logs_the_timings_of_slow_analyses() {
+        use futures::StreamExt;
+        use serde_json::json;
+
+        let shared = delayed_shared(&[
+            ("openssl", 2100),
+            ("ring", 180),
+            ("serde", 10),
+            ("libc", 5),
+            ("tokio", 2500),
+        ]);
+        let (service, mut socket) = socket_service(shared);
+        let (logs, mut received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                let Some(params) = request.params() else {
+                    continue;
+                };
+                let message = params["message"].as_str().unwrap_or_default();
+                if request.method() == "window/logMessage" && message.starts_with("Timings") {
+                    let _ = logs.send((params["type"].as_i64().unwrap(), message.to_string()));
+                }
+            }
+        });
+        let backend = service.inner();
+        let logged = |received: &mut tokio::sync::mpsc::UnboundedReceiver<_>| {
+            let mut messages = Vec::new();
+            while let Ok(message) = received.try_recv() {
+                messages.push(message);
+            }
+            messages
+        };
+        let timings = || async {
+            let params = ExecuteCommandParams {
+                command: timing::TIMINGS.into(),
+                arguments: Vec::new(),
+                work_done_progress_params: Default::default(),
+            };
+            backend.execute_command(params).await.unwrap().unwrap()
+        };
+
+        let first: Uri = "file:///first/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nopenssl = \"1\"\nring = \"1\"\nserde = \"1\"\nlibc = \"1\"\n";
+        open(backend, &first, text).await;
+        backend
+            .update_diagnostics(&first, None, &backend.source(text), Priority::Interactive)
+            .await;
+        backend.client.flush().await;
+        tokio::task::yield_now().await;
+        // Below the threshold only logged for debugging
+        assert_eq!(
+            logged(&mut received),
+            [(
+                4,
+                "Timings of file:///first/Cargo.toml: parsed 0ms, cache 0ms, \
+                 fetch 2100ms [openssl 2100ms, ring 180ms, serde 10ms, …], \
+                 classify 0ms, render 0ms, publish 0ms"
+                    .to_string()
+            )]
+        );
+        assert_eq!(
+            timings().await,
+            json!({
+                "file:///first/Cargo.toml": {
+                    "parse": 0, "cache": 0, "fetch": 2100, "classify": 0, "render": 0,
+                    "publish": 0, "total": 2100, "pending": 0,
+                    "slowest": [["openssl", 2100], ["ring", 180], ["serde", 10]],
+                }
+            })
+        );
+
+        // Without debug messages the crates are not timed
+        backend
+            .update_config(json!({ "slowAnalysisThreshold": 2000, "logLevel": "info" }))
+            .await;
+        let second: Uri = "file:///second/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\ntokio = \"1\"\n";
+        open(backend, &second, text).await;
+        backend
+            .update_diagnostics(&second, None, &backend.source(text), Priority::Interactive)
+            .await;
+        backend.client.flush().await;
+        tokio::task::yield_now().await;
+        assert_eq!(
+            logged(&mut received),
+            [(
+                3,
+                "Timings of file:///second/Cargo.toml: parsed 0ms, cache 0ms, fetch 2500ms, \
+                 classify 0ms, render 0ms, publish 0ms; slow analysis (2500ms), \
+                 most of the time went into fetching, likely because of a cold cache, \
+                 a slow registry, or rate limiting"
+                    .to_string()
+            )]
+        );
+        let snapshot = timings().await;
+        assert_eq!(snapshot["file:///second/Cargo.toml"]["total"], 2500);
+        assert_eq!(
+            snapshot["file:///second/Cargo.toml"]["slowest"],
+            Value::Null
+        );
+
+        // Forgotten with the document
+        backend
+            .did_close(DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(second.clone()),
+            })
+            .await;
+        assert!(timings().await.get(second.as_str()).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn carries_unchanged_diagnostics_over_edits() {
+        use futures::{SinkExt, StreamExt};
+        use tower::{Service, ServiceExt};
+
+        let (mut service, mut socket) =
+            socket_service(delayed_shared(&[("alpha", 10), ("beta", 20_000)]));
+        let (published, mut received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                if request.method() == "textDocument/publishDiagnostics" {
+                    let params = request.params().cloned().unwrap();
+                    let params: PublishDiagnosticsParams = serde_json::from_value(params).unwrap();
+                    let _ = published.send(params);
+                }
+                if let Some(id) = request.id().cloned() {
+                    let response = jsonrpc::Response::from_ok(id, Value::Null);
+                    if socket.send(response).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        // Notifications are only sent to initialized clients
+        let initialize = jsonrpc::Request::build("initialize")
+            .params(serde_json::json!({ "capabilities": {} }))
+            .id(1)
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nalpha = \"1\"\nbeta = \"1\"\n";
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "toml".into(), 1, text.into()),
+            })
+            .await;
+        /// Everything published until nothing follows for a while.
+        async fn drain(
+            received: &mut tokio::sync::mpsc::UnboundedReceiver<PublishDiagnosticsParams>,
+        ) -> Vec<PublishDiagnosticsParams> {
+            let mut published = Vec::new();
+            let timeout = Duration::from_millis(100);
+            while let Ok(Some(params)) = tokio::time::timeout(timeout, received.recv()).await {
+                published.push(params);
+            }
+            published
+        }
+        let opened = drain(&mut received).await;
+        let codes = |params: &PublishDiagnosticsParams, line: u32| {
+            params
+                .diagnostics
+                .iter()
+                .filter(|d| d.range.start.line == line)
+                .filter_map(|d| match &d.code {
+                    Some(NumberOrString::String(code)) => Some(code.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let outdated = ["major-available", "outdated-incompatible"];
+        assert_eq!(codes(opened.last().unwrap(), 5), outdated);
+
+        // Fetched again after the edit, it misses the deadline
+        backend.cache.write().await.invalidate("beta");
+        // A comment above alpha, and a new requirement of alpha
+        let change = |line, start, end, text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(line, start),
+                Position::new(line, end),
+            )),
+            range_length: None,
+            text: text.into(),
+        };
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+                content_changes: vec![change(4, 0, 0, "# pinned\n"), change(5, 9, 10, "2")],
+            })
+            .await;
+        backend
+            .did_save(DidSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+                text: Some(text.replace("alpha = \"1\"", "# pinned\nalpha = \"2\"")),
+            })
+            .await;
+        let saved = drain(&mut received).await;
+        // Carried over, at the deadline, and with the refetched versions of beta
+        assert_eq!(saved.len(), 3, "{saved:?}");
+        for params in &saved {
+            assert_eq!(params.version, Some(2));
+            assert_eq!(codes(params, 6), outdated, "{params:?}");
+        }
+        assert_eq!(codes(&saved[0], 5), Vec::<String>::new());
+        assert_eq!(codes(&saved[2], 5), ["latest"]);
+    }
+
+    #[tokio::test]
+    async fn isolates_panicking_analyses() {
+        let versions = vec!["1.0.0".to_string(), "2.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]))
+            .panicking("boom");
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+
+        let broken: Uri = "file:///broken/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nboom = \"1\"\n";
+        open(backend, &broken, text).await;
+        backend
+            .update_diagnostics(&broken, None, &backend.source(text), Priority::Interactive)
+            .await;
+        assert_eq!(
+            stored_codes(backend, &broken).await,
+            [(0, "internal-error".into())]
+        );
+
+        // Other documents are still analyzed
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"1\"\n";
+        open(backend, &uri, text).await;
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        let params = HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(1, 2),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        assert!(backend.hover(params).await.unwrap().is_some());
+        assert_eq!(
+            stored_codes(backend, &uri).await,
+            [
+                (1, "major-available".into()),
+                (1, "outdated-incompatible".into())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn isolates_panicking_requests() {
+        let versions = vec!["1.0.0".to_string(), "2.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]))
+            .panicking("boom");
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"1\"\n";
+        open(backend, &uri, text).await;
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+
+        // Adding the crate fetches it within the request
+        let params = ExecuteCommandParams {
+            command: add::ADD_DEPENDENCY.into(),
+            arguments: vec![serde_json::json!({ "uri": uri, "name": "boom" })],
+            work_done_progress_params: Default::default(),
+        };
+        let err = backend.execute_command(params).await.unwrap_err();
+        assert_eq!(err.code, jsonrpc::ErrorCode::InternalError);
+        assert!(err.message.contains(uri.as_str()), "{}", err.message);
+        assert!(
+            err.message.contains("the registry exploded"),
+            "{}",
+            err.message
+        );
+
+        // The other requests are still answered
+        let params = HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(1, 2),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        assert!(backend.hover(params).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn ranges_of_quoted_keys_exclude_the_quotes() {
+        let versions = |v: &[&str]| v.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let registry = registry::MockClient::new(HashMap::from([
+            ("weird-name".into(), versions(&["1.0.0", "2.0.0"])),
+            ("my-dep".into(), versions(&["0.2.0", "0.3.0"])),
+            ("escape".into(), versions(&["1.0.0", "2.0.0"])),
+        ]));
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\n\"weird-name\" = \"1.0\"\n\
+            'my.dep' = { version = \"0.2\", package = \"my-dep\" }\n\"esc\\u0061pe\" = \"1\"\n";
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        let name = |line, end| Range::new(Position::new(line, 1), Position::new(line, end));
+        let mut ranges = analysis
+            .diagnostics
+            .iter()
+            .map(|d| d.range)
+            .collect::<Vec<_>>();
+        ranges.dedup();
+        assert_eq!(ranges, [name(1, 11), name(2, 7), name(3, 12)]);
+        // Looked up by the renamed package and the unescaped name
+        let packages = analysis
+            .dependencies
+            .iter()
+            .map(|d| d.package.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(packages, ["escape", "my-dep", "weird-name"]);
+
+        // The update of the dotted key keeps the manifest valid
+        let diagnostics = analysis
+            .diagnostics
+            .iter()
+            .filter(|d| d.range == name(2, 7))
+            .cloned()
+            .collect::<Vec<_>>();
+        backend
+            .analyses
+            .write()
+            .await
+            .insert(uri.clone(), Arc::new(analysis));
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: name(2, 7),
+            context: CodeActionContext {
+                diagnostics,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        let edit = actions
+            .into_iter()
+            .find_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action)
+                    if action.kind == Some(CodeActionKind::QUICKFIX) =>
+                {
+                    action.edit?.changes?.remove(&uri)?.pop()
+                }
+                _ => None,
+            })
+            .unwrap();
+        let edited = dry_run::apply(&backend.source(text), &[edit]).unwrap();
+        let (manifest, err) = manifest::parse(&edited);
+        assert!(err.is_none(), "{edited}");
+        let dep = manifest.dependencies.values().nth(1).unwrap();
+        assert_eq!(dep.req(), "0.3");
+        assert_eq!(dep.package(), Some("my-dep"));
+    }
+
+    #[tokio::test]
+    async fn ignores_the_yanked_versions_of_a_crate() {
+        let registry = registry::MockClient::default().with_yanked_skew();
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nyanked-skew = \"1.0.2\"\n";
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        let [diagnostic] = &analysis.diagnostics[..] else {
+            panic!("{:?}", analysis.diagnostics);
+        };
+        // Compared against the newest live version, not the 500 newer yanked ones
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String("outdated-compatible".into()))
+        );
+        assert_eq!(
+            diagnostic.message,
+            "Outdated Version, compatible update to 1.0.4 (cargo update)\n\n\
+             yanked-skew (1.0.2)\n1.0.4\n1.0.3\n1.0.2\n1.0.1\n1.0.0\n\
+             500 yanked versions omitted"
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_the_versions_of_artifact_dependencies() {
+        let versions = vec!["1.0.0".to_string(), "2.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("tool".into(), versions)]));
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[build-dependencies]\n\
+            tool = { version = \"1\", artifact = \"bin\" }\n";
+        let codes = |analysis: Analysis| {
+            analysis
+                .diagnostics
+                .iter()
+                .filter_map(|d| match &d.code {
+                    Some(NumberOrString::String(code)) => Some(code.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let analysis = analyze(backend, &uri, &Config::default(), text)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            codes(analysis),
+            [
+                version::MAJOR_AVAILABLE,
+                "outdated-incompatible",
+                artifact::ARTIFACT_DEPENDENCY
+            ]
+        );
+
+        let enabled = format!("cargo-features = [\"bindeps\"]\n\n{text}");
+        let analysis = analyze(backend, &uri, &Config::default(), &enabled)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            codes(analysis),
+            [version::MAJOR_AVAILABLE, "outdated-incompatible"]
+        );
+    }
+
+    #[tokio::test]
+    async fn adjusts_the_checks_to_the_cargo_features() {
+        let versions = vec!["1.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([
+            ("tool".into(), versions.clone()),
+            ("serde".into(), versions),
+        ]));
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\n\
+            serde = { version = \"1\", public = true }\n\
+            tool = { version = \"1\", artifact = \"bin\" }\n";
+        let codes = async |text: &str| {
+            let analysis = analyze(backend, &uri, &Config::default(), text)
+                .await
+                .unwrap()
+                .unwrap();
+            analysis
+                .diagnostics
+                .into_iter()
+                .filter_map(|d| match d.code {
+                    Some(NumberOrString::String(code)) if code != "latest" => {
+                        Some((d.range.start.line, code))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Used but undeclared
+        assert_eq!(
+            codes(text).await,
+            [
+                (4, keys::UNKNOWN_KEY.to_string()),
+                (5, artifact::ARTIFACT_DEPENDENCY.to_string())
+            ]
+        );
+
+        // Declared and used, next to a misspelled and a stabilized feature
+        let declared = format!(
+            "cargo-features = [\"bindeps\", \"public-dependency\", \"edition2024\", \"trim-path\"]\n\n{text}"
+        );
+        assert_eq!(
+            codes(&declared).await,
+            [
+                (0, unstable::STABILIZED_CARGO_FEATURE.to_string()),
+                (0, unstable::UNKNOWN_CARGO_FEATURE.to_string())
+            ]
+        );
+
+        open(backend, &uri, &declared).await;
+        let analysis = analyze(backend, &uri, &Config::default(), &declared)
+            .await
+            .unwrap()
+            .unwrap();
+        let unknown = analysis
+            .diagnostics
+            .into_iter()
+            .find(|d| {
+                d.code
+                    == Some(NumberOrString::String(
+                        unstable::UNKNOWN_CARGO_FEATURE.into(),
+                    ))
+            })
+            .unwrap();
+        assert_eq!(
+            unknown.severity,
+            Some(lsp_types::DiagnosticSeverity::WARNING)
+        );
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: unknown.range,
+            context: CodeActionContext {
+                diagnostics: vec![unknown],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = &actions[..] else {
+            panic!("{actions:?}");
+        };
+        assert_eq!(action.title, "Use the cargo feature \"trim-paths\"");
+    }
+
+    #[tokio::test]
+    async fn analyzes_inline_manifests_like_open_documents() {
+        use serde_json::json;
+        use tower::{Service, ServiceExt};
+
+        let versions = |v: &[&str]| v.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let registry = registry::MockClient::new(HashMap::from([
+            ("serde".into(), versions(&["1.0.0", "2.0.0"])),
+            ("rand".into(), versions(&["0.7.0", "0.8.0"])),
+        ]));
+        let mut service = shared_service(Shared::new(Arc::new(registry)));
+        let mut request = async |method: &str, id: i64, params: Value| {
+            let request = jsonrpc::Request::build(method.to_string())
+                .params(params)
+                .id(id)
+                .finish();
+            let response = service.ready().await.unwrap().call(request).await;
+            response.unwrap().unwrap().into_parts().1
+        };
+        request("initialize", 1, json!({ "capabilities": {} }))
+            .await
+            .unwrap();
+
+        let text =
+            "[dependencies]\nserde = \"1\"\nrand = { version = \"0.7\", feature = [\"std\"] }\n";
+        let path = "/app/Cargo.toml";
+        let inline = request(inline::ANALYZE, 2, json!({ "text": text, "path": path }))
+            .await
+            .unwrap();
+        let overridden = json!({
+            "text": text,
+            "path": path,
+            "options": { "severity": { "outdatedIncompatible": "error" } },
+        });
+        let overridden = request(inline::ANALYZE, 3, overridden).await.unwrap();
+        let relative = json!({ "text": text, "path": "app/Cargo.toml" });
+        let relative = request(inline::ANALYZE, 4, relative).await;
+        assert_eq!(
+            relative.unwrap_err().code,
+            jsonrpc::ErrorCode::InvalidParams
+        );
+
+        let backend = service.inner();
+        // Nothing is remembered for the document
+        assert!(backend.open_docs.uris().is_empty());
+        assert!(backend.analyses.read().await.is_empty());
+        assert_eq!(backend.references.read().await.crates().count(), 0);
+        assert!(backend.index.read().await.manifests().is_empty());
+        let severities = |result: &Value| {
+            result["diagnostics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|d| (d["code"].clone(), d["severity"].clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            severities(&overridden)[..2],
+            [
+                (json!("major-available"), json!(2)),
+                (json!("outdated-incompatible"), json!(1))
+            ]
+        );
+        assert_eq!(
+            severities(&inline)[..2],
+            [
+                (json!("major-available"), json!(2)),
+                (json!("outdated-incompatible"), json!(2))
+            ]
+        );
+
+        // Opened, the same content gets the same diagnostics
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "toml".into(), 1, text.into()),
+            })
+            .await;
+        let opened = backend.analyses.read().await.get(&uri).cloned().unwrap();
+        assert_eq!(inline["uri"], json!(uri.as_str()));
+        assert_eq!(
+            inline["diagnostics"],
+            serde_json::to_value(&opened.diagnostics).unwrap()
+        );
+        assert!(opened.diagnostics.len() >= 3, "{:?}", opened.diagnostics);
+    }
+
+    #[tokio::test]
+    async fn reports_partially_analyzed_entries() {
+        use serde_json::json;
+        use tower::{Service, ServiceExt};
+
+        let versions = |v: &[&str]| v.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let registry = registry::MockClient::new(HashMap::from([
+            ("serde".into(), versions(&["1.0.0", "2.0.0"])),
+            ("rand".into(), versions(&["0.7.0", "0.8.0"])),
+        ]));
+        let mut service = shared_service(Shared::new(Arc::new(registry)));
+        let mut request = async |method: &str, id: i64, params: Value| {
+            let request = jsonrpc::Request::build(method.to_string())
+                .params(params)
+                .id(id)
+                .finish();
+            let response = service.ready().await.unwrap().call(request).await;
+            response.unwrap().unwrap().into_parts().1
+        };
+        request("initialize", 1, json!({ "capabilities": {} }))
+            .await
+            .unwrap();
+
+        // Supported, partially supported, and unparseable entries
+        let text = "[dependencies]\nserde = \"1\"\nrand = { version = \"0.7\", public = true }\n\
+            bad = 1\n\n[dev-dependencies]\nbroken = { version = \"1\"\n";
+        let path = "/app/Cargo.toml";
+        let codes = |result: &Value| {
+            let diagnostics = result["diagnostics"].as_array().unwrap();
+            diagnostics
+                .iter()
+                .map(|d| d["code"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let silent = request(inline::ANALYZE, 2, json!({ "text": text, "path": path }))
+            .await
+            .unwrap();
+        let notices = silent["notices"].as_array().unwrap();
+        let reasons = notices
+            .iter()
+            .map(|n| {
+                (
+                    n["dependency"].as_str().unwrap(),
+                    n["reason"].as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(reasons, [("rand", "ignored-key"), ("bad", "dependency")]);
+        assert_eq!(notices[0]["key"], "public");
+        assert!(!codes(&silent).contains(&partial::PARTIALLY_ANALYZED.to_string()));
+
+        let reported = json!({
+            "text": text,
+            "path": path,
+            "options": { "partialAnalysis": true },
+        });
+        let reported = request(inline::ANALYZE, 3, reported).await.unwrap();
+        assert_eq!(reported["notices"], silent["notices"]);
+        let codes = codes(&reported);
+        let partial = codes
+            .iter()
+            .filter(|c| *c == partial::PARTIALLY_ANALYZED)
+            .count();
+        assert_eq!(partial, 1, "{codes:?}");
+        // The supported entries are still analyzed, next to the error of the invalid TOML
+        for code in [
+            version::MAJOR_AVAILABLE,
+            "outdated-incompatible",
+            "parse-error",
+        ] {
+            assert!(codes.contains(&code.to_string()), "{codes:?}");
+        }
+        let diagnostics = reported["diagnostics"].as_array().unwrap();
+        let summary = diagnostics
+            .iter()
+            .find(|d| d["code"] == partial::PARTIALLY_ANALYZED)
+            .unwrap();
+        assert_eq!(
+            summary["message"],
+            "2 entries partially analyzed, hover for details"
+        );
+        assert_eq!(summary["relatedInformation"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_diagnostics_of_a_quick_reopen() {
+        use futures::{SinkExt, StreamExt};
+        use serde_json::json;
+        use tower::{Service, ServiceExt};
+
+        let versions = vec!["1.0.0".to_string(), "2.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]));
+        let (mut service, mut socket) = socket_service(Shared::new(Arc::new(registry)));
+        let (published, mut received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                if request.method() == "textDocument/publishDiagnostics" {
+                    let params = request.params().unwrap();
+                    let _ = published.send(params["diagnostics"].as_array().unwrap().len());
+                }
+                if let Some(id) = request.id().cloned() {
+                    let response = jsonrpc::Response::from_ok(id, Value::Null);
+                    if socket.send(response).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        let uri = "file:///app/Cargo.toml";
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n";
+        let open = || {
+            jsonrpc::Request::build("textDocument/didOpen")
+                .params(json!({
+                    "textDocument": { "uri": uri, "languageId": "toml", "version": 1, "text": text }
+                }))
+                .finish()
+        };
+        let close = jsonrpc::Request::build("textDocument/didClose")
+            .params(json!({ "textDocument": { "uri": uri } }))
+            .finish();
+        let initialize = jsonrpc::Request::build("initialize")
+            .params(json!({ "capabilities": {} }))
+            .id(1)
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+        service.ready().await.unwrap().call(open()).await.unwrap();
+        assert!(received.recv().await.unwrap() > 0);
+
+        // Both in the same turn, the clear of the close races the analysis of the open
+        let closing = service.ready().await.unwrap().call(close);
+        let opening = service.ready().await.unwrap().call(open());
+        let (closed, opened) = tokio::join!(closing, opening);
+        closed.unwrap();
+        opened.unwrap();
+        // Until the client received everything that was sent
+        let mut last = None;
+        while let Ok(Some(count)) =
+            tokio::time::timeout(Duration::from_millis(500), received.recv()).await
+        {
+            last = Some(count);
+        }
+        assert!(last.is_some_and(|count| count > 0), "{last:?}");
+
+        // A clear that is only sent after the reopening is dropped
+        let backend = service.inner();
+        let uri: Uri = uri.parse().unwrap();
+        // Taken by a close whose clear is still on its way
+        let seq = backend.next_publish();
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "toml".into(), 2, text.into()),
+            })
+            .await;
+        backend.send_diagnostics(&uri, Vec::new(), None, seq).await;
+        let mut counts = Vec::new();
+        while let Ok(Some(count)) =
+            tokio::time::timeout(Duration::from_millis(500), received.recv()).await
+        {
+            counts.push(count);
+        }
+        assert!(!counts.is_empty() && !counts.contains(&0), "{counts:?}");
+    }
+
+    #[tokio::test]
+    async fn persists_the_usage_statistics_of_a_session() {
+        use futures::{SinkExt, StreamExt};
+        use serde_json::json;
+        use tower::{Service, ServiceExt};
+
+        let dir = std::env::temp_dir().join(format!("crates-io-lsp-stats-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let versions = vec!["1.0.0".to_string(), "2.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]));
+        let mut shared = Shared::new(Arc::new(registry));
+        shared.stats = Arc::new(Stats::new(Some(dir.clone())));
+        let (mut service, mut socket) = socket_service(shared);
+        let (published, mut received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                if request.method() == "textDocument/publishDiagnostics" {
+                    let _ = published.send(());
+                }
+                if let Some(id) = request.id().cloned() {
+                    let result = match request.method() {
+                        "workspace/applyEdit" => json!({ "applied": true }),
+                        _ => Value::Null,
+                    };
+                    if socket
+                        .send(jsonrpc::Response::from_ok(id, result))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+        });
+        async fn call(
+            service: &mut LspService<CratesIoBackend>,
+            method: &'static str,
+            params: Value,
+            id: Option<i64>,
+        ) -> Option<jsonrpc::Response> {
+            let mut request = jsonrpc::Request::build(method).params(params);
+            if let Some(id) = id {
+                request = request.id(id);
+            }
+            let request = request.finish();
+            service.ready().await.unwrap().call(request).await.unwrap()
+        }
+        let options = json!({ "stats": true });
+        let initialize = json!({ "capabilities": {}, "initializationOptions": options });
+        call(&mut service, "initialize", initialize, Some(1)).await;
+        call(&mut service, "initialized", json!({}), None).await;
+
+        let uri = "file:///app/Cargo.toml";
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n";
+        let document = json!({ "uri": uri, "languageId": "toml", "version": 1, "text": text });
+        call(
+            &mut service,
+            "textDocument/didOpen",
+            json!({ "textDocument": document }),
+            None,
+        )
+        .await;
+        received.recv().await.unwrap();
+        // Diagnostics that stay are not counted again
+        let saved = json!({
+            "textDocument": { "uri": uri },
+            "text": format!("{text}nothing = \"1\"\n"),
+        });
+        call(&mut service, "textDocument/didSave", saved, None).await;
+        received.recv().await.unwrap();
+        let update = json!({
+            "command": analysis::APPLY_UPDATE,
+            "arguments": [{
+                "uri": uri,
+                "range": { "start": { "line": 4, "character": 9 }, "end": { "line": 4, "character": 10 } },
+                "from": "1",
+                "version": "2",
+            }],
+        });
+        let applied = call(&mut service, "workspace/executeCommand", update, Some(2)).await;
+        assert_eq!(applied.unwrap().result(), Some(&Value::Bool(true)));
+        call(&mut service, "shutdown", Value::Null, Some(3)).await;
+
+        let text = std::fs::read_to_string(dir.join("stats.json")).unwrap();
+        let file: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(file["format"], 1);
+        let months = file["months"].as_object().unwrap();
+        assert_eq!(months.len(), 1);
+        let counts = months.values().next().unwrap();
+        assert_eq!(
+            counts,
+            &json!({
+                "flagged": {
+                    "major-available": 1,
+                    "outdated-incompatible": 1,
+                },
+                "actionsApplied": 1,
+                "commandsExecuted": 1,
+                "fetchesSucceeded": 1,
+                "fetchesFailed": 1,
+                "manifestsSkipped": 0,
+            })
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// What a client with `capabilities` sees of a session opening a manifest.
+    struct Session {
+        capabilities: ServerCapabilities,
+        /// Methods sent to the client, except log messages
+        methods: Vec<String>,
+        /// Methods registered with `client/registerCapability`
+        registered: Vec<String>,
+        hover: MarkupContent,
+        pulled: usize,
+    }
+
+    async fn session(capabilities: ClientCapabilities) -> Session {
+        use futures::{SinkExt, StreamExt};
+        use serde_json::json;
+        use tower::{Service, ServiceExt};
+
+        let versions = vec!["1.0.0".to_string(), "2.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]));
+        let (mut service, mut socket) = socket_service(Shared::new(Arc::new(registry)));
+        // Until the message ending the session
+        let client = tokio::spawn(async move {
+            let mut sent = Vec::new();
+            while let Some(request) = socket.next().await {
+                if request.method() == "window/showMessage" {
+                    break;
+                }
+                sent.push((request.method().to_string(), request.params().cloned()));
+                if let Some(id) = request.id().cloned() {
+                    let response = jsonrpc::Response::from_ok(id, Value::Null);
+                    socket.send(response).await.unwrap();
+                }
+            }
+            sent
+        });
+        let capabilities = serde_json::to_value(capabilities).unwrap();
+        let request = jsonrpc::Request::build("initialize")
+            .params(json!({ "capabilities": capabilities }))
+            .id(1)
+            .finish();
+        let response = service.ready().await.unwrap().call(request).await;
+        let result = response.unwrap().unwrap().into_parts().1.unwrap();
+        let notification = jsonrpc::Request::build("initialized")
+            .params(json!({}))
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(notification)
+            .await
+            .unwrap();
+
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n";
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "toml".into(), 1, text.into()),
+            })
+            .await;
+        let params = HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(4, 2),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        let HoverContents::Markup(hover) = backend.hover(params).await.unwrap().unwrap().contents
+        else {
+            panic!("no markup");
+        };
+        let params = DocumentDiagnosticParams {
+            text_document: TextDocumentIdentifier::new(uri),
+            identifier: None,
+            previous_result_id: None,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) =
+            backend.diagnostic(params).await.unwrap()
+        else {
+            panic!("no full report");
+        };
+        backend
+            .client
+            .show_message(MessageType::INFO, "end of the session")
+            .await;
+
+        let sent = client.await.unwrap();
+        let registered = sent
+            .iter()
+            .filter(|(method, _)| method == "client/registerCapability")
+            .flat_map(|(_, params)| {
+                params.as_ref().unwrap()["registrations"]
+                    .as_array()
+                    .unwrap()
+            })
+            .map(|r| r["method"].as_str().unwrap().to_string())
+            .collect();
+        Session {
+            capabilities: serde_json::from_value(result["capabilities"].clone()).unwrap(),
+            methods: sent
+                .into_iter()
+                .map(|(method, _)| method)
+                .filter(|method| method != "window/logMessage")
+                .collect(),
+            registered,
+            hover,
+            pulled: report.full_document_diagnostic_report.items.len(),
+        }
+    }
+
+    #[tokio::test]
+    async fn negotiates_features_with_the_client() {
+        let sent = |session: &Session, method: &str| session.methods.iter().any(|m| m == method);
+        let full_sync = Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL));
+
+        // Diagnostics are pulled and refreshed instead of pushed
+        let full = session(caps::full()).await;
+        assert!(full.capabilities.diagnostic_provider.is_some());
+        assert_eq!(full.capabilities.text_document_sync, full_sync);
+        assert_eq!(
+            full.registered,
+            [
+                "workspace/didChangeWatchedFiles",
+                "workspace/didChangeConfiguration"
+            ]
+        );
+        assert!(sent(&full, "window/workDoneProgress/create"));
+        assert!(sent(&full, "workspace/diagnostic/refresh"));
+        assert!(!sent(&full, "textDocument/publishDiagnostics"));
+        assert_eq!(full.pulled, 2);
+        assert_eq!(full.hover.kind, MarkupKind::Markdown);
+        assert_eq!(
+            full.hover.value,
+            "**serde** `1`: `2.0.0` requires a manifest change\n\n\
+             _registry data from less than a minute ago, refreshing roughly every 15 min_"
+        );
+
+        // Plain text, pushed diagnostics, no progress, and saved configuration files
+        let minimal = session(caps::minimal()).await;
+        assert_eq!(minimal.capabilities.diagnostic_provider, None);
+        let Some(TextDocumentSyncCapability::Options(sync)) =
+            &minimal.capabilities.text_document_sync
+        else {
+            panic!("no sync options");
+        };
+        assert_eq!(
+            sync.save,
+            Some(
+                SaveOptions {
+                    include_text: Some(true)
+                }
+                .into()
+            )
+        );
+        assert!(minimal.registered.is_empty());
+        assert!(!sent(&minimal, "client/registerCapability"));
+        assert!(!sent(&minimal, "window/workDoneProgress/create"));
+        assert!(!sent(&minimal, "workspace/diagnostic/refresh"));
+        assert!(sent(&minimal, "textDocument/publishDiagnostics"));
+        assert_eq!(minimal.pulled, 0);
+        assert_eq!(minimal.hover.kind, MarkupKind::PlainText);
+        assert_eq!(
+            minimal.hover.value,
+            "serde 1: 2.0.0 requires a manifest change\n\nregistry data from less than a minute ago, refreshing roughly every 15 min"
+        );
+
+        // Markdown and progress, but pushed diagnostics and settings
+        let zed = session(caps::zed()).await;
+        assert_eq!(zed.capabilities.diagnostic_provider, None);
+        assert_eq!(zed.capabilities.text_document_sync, full_sync);
+        assert_eq!(zed.registered, ["workspace/didChangeWatchedFiles"]);
+        assert!(sent(&zed, "window/workDoneProgress/create"));
+        assert!(!sent(&zed, "workspace/diagnostic/refresh"));
+        assert!(sent(&zed, "textDocument/publishDiagnostics"));
+        assert_eq!(zed.pulled, 0);
+        assert_eq!(zed.hover, full.hover);
+    }
+
+    #[tokio::test]
+    async fn reloads_saved_configuration_files_without_watchers() {
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-saved-config-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join(".crates-io-lsp.toml");
+        std::fs::write(&path, "packageLints = true\n").unwrap();
+        let config = Uri::from_file_path(&path).unwrap();
+        let service = service("http://127.0.0.1:1");
+        let backend = service.inner();
+        backend
+            .config_files
+            .write()
+            .await
+            .insert(path.clone(), None);
+
+        let save = || DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(config.clone()),
+            text: None,
+        };
+        // Watched files are reported by the client instead
+        backend
+            .caps
+            .send_modify(|caps| caps.watched_files_registration = true);
+        backend.did_save(save()).await;
+        assert_eq!(backend.config_files.read().await.get(&path), Some(&None));
+
+        backend
+            .caps
+            .send_modify(|caps| caps.watched_files_registration = false);
+        backend.did_save(save()).await;
+        let loaded = backend.config_files.read().await.get(&path).cloned();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            loaded,
+            Some(Some(serde_json::json!({ "packageLints": true })))
+        );
+    }
+
+    /// Backend running `cargo` for `crates-io.runCargo`, sending the log messages to the receiver.
+    fn cargo_service(
+        cargo: PathBuf,
+    ) -> (
+        LspService<CratesIoBackend>,
+        tokio::sync::mpsc::UnboundedReceiver<String>,
+    ) {
+        use futures::{SinkExt, StreamExt};
+
+        let versions = vec!["1.0.0".to_string(), "1.0.5".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]));
+        let args = Args {
+            cargo,
+            ..clock_service_args()
+        };
+        let (service, mut socket) =
+            lsp_service(args, Shared::new(Arc::new(registry)), Arc::new(TokioClock));
+        let (logs, received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                if request.method() == "window/logMessage" {
+                    let message = request.params().and_then(|p| p["message"].as_str());
+                    let _ = logs.send(message.unwrap_or_default().to_string());
+                }
+                if let Some(id) = request.id().cloned() {
+                    let response = jsonrpc::Response::from_ok(id, Value::Null);
+                    if socket.send(response).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        (service, received)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn runs_allowed_cargo_commands_in_the_workspace() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-run-cargo-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        std::fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"app\"]\n",
+        )
+        .unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1.0.0\"\n";
+        let manifest = root.join("app").join("Cargo.toml");
+        std::fs::write(&manifest, text).unwrap();
+        // Fails unless a single package is updated
+        let shim = root.join("fake-cargo");
+        std::fs::write(
+            &shim,
+            "#!/bin/sh\necho \"args $*\"\necho \"dir $(pwd)\"\necho \"to stderr\" >&2\n\
+             [ \"$2\" = \"-p\" ] || exit 101\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&shim, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let (service, mut logs) = cargo_service(shim);
+        let backend = service.inner();
+        let uri = Uri::from_file_path(&manifest).unwrap();
+        open(backend, &uri, text).await;
+        let run = |package: Option<&str>| RunCargoArgs {
+            uri: uri.clone(),
+            command: CargoCommand::Update {
+                package: package.map(String::from),
+            },
+        };
+
+        let disabled = backend.run_cargo(&run(Some("serde"))).await.unwrap_err();
+        assert!(disabled.to_string().contains("allowCargoCommands"));
+        backend
+            .update_config(serde_json::json!({ "allowCargoCommands": true }))
+            .await;
+
+        let result = backend.run_cargo(&run(Some("serde"))).await.unwrap();
+        assert_eq!(result.command, "cargo update -p serde");
+        assert!(result.success);
+        assert_eq!(result.exit_code, Some(0));
+        // Run in the workspace root, not the directory of the member
+        let mut expected = vec![
+            "args update -p serde".to_string(),
+            format!("dir {}", root.display()),
+            "to stderr".to_string(),
+        ];
+        while !expected.is_empty() {
+            let line = tokio::time::timeout(Duration::from_secs(5), logs.recv())
+                .await
+                .unwrap_or_else(|_| panic!("Missing {expected:?}"))
+                .unwrap();
+            expected.retain(|l| *l != line);
+        }
+        // The open members are analyzed again
+        assert_eq!(
+            stored_codes(backend, &uri).await,
+            [(4, "outdated-compatible".to_string())]
+        );
+
+        let failed = backend.run_cargo(&run(None)).await.unwrap();
+        assert_eq!(failed.command, "cargo update");
+        assert!(!failed.success);
+        assert_eq!(failed.exit_code, Some(101));
+        let invalid = backend.run_cargo(&run(Some("--locked"))).await;
+        assert!(invalid.is_err());
+
+        // Offered on compatible updates, with a single update of everything
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        backend
+            .analyses
+            .write()
+            .await
+            .insert(uri.clone(), Arc::new(analysis.clone()));
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: Range::new(Position::new(4, 0), Position::new(4, 5)),
+            context: CodeActionContext {
+                diagnostics: analysis.diagnostics.clone(),
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        let commands = actions
+            .iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title,
+                    command: Some(command),
+                    ..
+                }) if command.command == cargo::RUN_CARGO => Some(title.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            ["Run `cargo update -p serde`", "Run `cargo update`"]
+        );
+
+        let (missing, _) = cargo_service(root.join("missing-cargo"));
+        let missing = missing.inner();
+        missing
+            .update_config(serde_json::json!({ "allowCargoCommands": true }))
+            .await;
+        let error = missing.run_cargo(&run(None)).await.unwrap_err();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(error.to_string().contains("was not found"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn updates_a_crate_in_every_manifest() {
+        use futures::{SinkExt, StreamExt};
+        use serde_json::json;
+        use tower::{Service, ServiceExt};
+
+        let versions = vec!["1.0.100".to_string(), "1.0.215".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]));
+        let (mut service, mut socket) = socket_service(Shared::new(Arc::new(registry)));
+        // The client accepts every edit and remembers it
+        let (edits, mut applied) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                let Some(id) = request.id().cloned() else {
+                    continue;
+                };
+                let result = if request.method() == "workspace/applyEdit" {
+                    edits.send(request.params().cloned().unwrap()).unwrap();
+                    json!({ "applied": true })
+                } else {
+                    Value::Null
+                };
+                let response = jsonrpc::Response::from_ok(id, result);
+                if socket.send(response).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let root = std::env::temp_dir().join(format!(
+            "crates-io-lsp-update-everywhere-{}",
+            std::process::id()
+        ));
+        let manifests = [
+            (
+                "",
+                "[workspace]\nmembers = [\"app\", \"lib\", \"inherit\", \"broken\"]\n\n\
+                [workspace.dependencies]\nserde = \"1.0.100\"\n",
+            ),
+            ("app", "[package]\nname = \"app\"\n"),
+            (
+                "lib",
+                "[package]\nname = \"lib\"\n\n[dependencies]\nserde = \"=1.0.100\"\n\n\
+                [dev-dependencies]\nserde = { git = \"https://github.com/serde-rs/serde\" }\n",
+            ),
+            (
+                "inherit",
+                "[package]\nname = \"inherit\"\n\n[dependencies]\nserde = { workspace = true }\n",
+            ),
+            ("broken", "[dependencies]\nserde = \"1.0\"\n"),
+        ];
+        for (dir, text) in manifests {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("Cargo.toml"), text).unwrap();
+        }
+        // The open member is edited at its unsaved version, in its own style
+        std::fs::write(
+            root.join("app/.crates-io-lsp.toml"),
+            "requirementStyle = \"minor\"\n",
+        )
+        .unwrap();
+        let app = Uri::from_file_path(root.join("app/Cargo.toml")).unwrap();
+        let app_text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1.0.100\"\n";
+        let backend = service.inner();
+        backend.open_docs.insert(
+            app.clone(),
+            FileInfo::new(app_text.into(), 3, Encoding::Utf16),
+        );
+        let config = backend.config.borrow().clone();
+        analyze(backend, &app, &config, app_text)
+            .await
+            .unwrap()
+            .unwrap();
+        // Open with invalid TOML, still indexed by its analysis
+        let broken = Uri::from_file_path(root.join("broken/Cargo.toml")).unwrap();
+        let broken_text = "[dependencies]\nserde = \"1.0\"\n\n[features\n";
+        backend.open_docs.insert(
+            broken.clone(),
+            FileInfo::new(broken_text.into(), 5, Encoding::Utf16),
+        );
+        analyze(backend, &broken, &config, broken_text)
+            .await
+            .unwrap()
+            .unwrap();
+        // The closed members are indexed when the root reads them
+        let root_uri = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        analyze(backend, &root_uri, &config, manifests[0].1)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut request = async |method: &str, id: i64, params: Value| {
+            let request = jsonrpc::Request::build(method.to_string())
+                .params(params)
+                .id(id)
+                .finish();
+            let response = service.ready().await.unwrap().call(request).await;
+            response.unwrap().unwrap().into_parts().1
+        };
+        let capabilities = json!({ "workspace": { "workspaceEdit": { "documentChanges": true } } });
+        request("initialize", 1, json!({ "capabilities": capabilities }))
+            .await
+            .unwrap();
+
+        let params = json!({
+            "command": everywhere::UPDATE_EVERYWHERE,
+            "arguments": [{ "name": "serde" }],
+        });
+        let result = request("workspace/executeCommand", 2, params).await;
+        std::fs::remove_dir_all(&root).unwrap();
+        let result = result.unwrap();
+
+        // A single edit of all manifests, versioned for the open document
+        let edit = applied.recv().await.unwrap();
+        assert!(applied.try_recv().is_err());
+        assert_eq!(edit["edit"].get("changes"), None);
+        let relative = |uri: &Value| {
+            let base = root_uri.as_str().trim_end_matches("Cargo.toml");
+            uri.as_str()
+                .unwrap()
+                .strip_prefix(base)
+                .unwrap()
+                .to_string()
+        };
+        let documents = edit["edit"]["documentChanges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|change| {
+                let document = &change["textDocument"];
+                let edits = change["edits"].as_array().unwrap();
+                let edits = edits.iter().map(|e| e["newText"].clone());
+                (
+                    relative(&document["uri"]),
+                    document["version"].clone(),
+                    edits.collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            documents,
+            [
+                ("Cargo.toml".into(), Value::Null, vec![json!("1.0.215")]),
+                ("app/Cargo.toml".into(), json!(3), vec![json!("1.0")]),
+                (
+                    "lib/Cargo.toml".into(),
+                    Value::Null,
+                    vec![json!("=1.0.215")]
+                ),
+            ]
+        );
+
+        // Everything that was not edited is reported
+        assert_eq!(result["version"], "1.0.215");
+        assert_eq!(result["applied"], true);
+        let updated = result["updated"].as_array().unwrap();
+        assert_eq!(updated.len(), 3);
+        assert_eq!(
+            updated[1],
+            json!({
+                "uri": app.as_str(),
+                "section": "dependencies",
+                "from": "1.0.100",
+                "to": "1.0",
+            })
+        );
+        let skipped = result["skipped"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| {
+                (
+                    relative(&s["uri"]),
+                    s["section"].clone(),
+                    s["reason"].clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            skipped,
+            [
+                ("broken/Cargo.toml".into(), Value::Null, json!("parseError")),
+                (
+                    "inherit/Cargo.toml".into(),
+                    json!("dependencies"),
+                    json!("inherited")
+                ),
+                (
+                    "lib/Cargo.toml".into(),
+                    json!("dev-dependencies"),
+                    json!("git")
+                ),
+            ]
+        );
+
+        let invalid = json!({
+            "command": everywhere::UPDATE_EVERYWHERE,
+            "arguments": [{ "name": "serde", "version": "1.x" }],
+        });
+        let invalid = request("workspace/executeCommand", 3, invalid).await;
+        assert_eq!(invalid.unwrap_err().code, jsonrpc::ErrorCode::InvalidParams);
+    }
+
+    #[tokio::test]
+    async fn adds_crates_that_are_already_locked() {
+        use futures::{SinkExt, StreamExt};
+        use serde_json::json;
+        use tower::{Service, ServiceExt};
+
+        let versions = vec!["1.0.188".to_string(), "1.0.215".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([
+            ("serde".into(), versions.clone()),
+            ("log".into(), vec!["0.4.22".to_string()]),
+        ]));
+        let (mut service, mut socket) = socket_service(Shared::new(Arc::new(registry)));
+        let (edits, mut applied) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                let Some(id) = request.id().cloned() else {
+                    continue;
+                };
+                let result = if request.method() == "workspace/applyEdit" {
+                    edits.send(request.params().cloned().unwrap()).unwrap();
+                    json!({ "applied": true })
+                } else {
+                    Value::Null
+                };
+                let response = jsonrpc::Response::from_ok(id, result);
+                if socket.send(response).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let initialize = jsonrpc::Request::build("initialize")
+            .params(json!({ "capabilities": {} }))
+            .id(1)
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+        let root = std::env::temp_dir().join(format!("crates-io-lsp-add-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        // serde is reached through both serde_json and toml
+        let registry = "source = \"registry+https://github.com/rust-lang/crates.io-index\"";
+        let lock = format!(
+            "version = 3\n\n\
+             [[package]]\nname = \"app\"\nversion = \"0.1.0\"\n\
+             dependencies = [\"serde_json\", \"toml\"]\n\n\
+             [[package]]\nname = \"serde_json\"\nversion = \"1.0.100\"\n{registry}\n\
+             dependencies = [\"serde\"]\n\n\
+             [[package]]\nname = \"toml\"\nversion = \"0.8.0\"\n{registry}\n\
+             dependencies = [\"serde\"]\n\n\
+             [[package]]\nname = \"serde\"\nversion = \"1.0.188\"\n{registry}\n"
+        );
+        std::fs::write(root.join("Cargo.lock"), lock).unwrap();
+        let uri = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        let text =
+            "[package]\nname = \"app\"\n\n[dependencies]\nserde_json = \"1\"\ntoml = \"0.8\"\nse\n";
+        let backend = service.inner();
+        open(backend, &uri, text).await;
+
+        let params = CompletionParams {
+            text_document_position: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(6, 2),
+            ),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let Some(CompletionResponse::Array(items)) = backend.completion(params).await.unwrap()
+        else {
+            panic!("no completions");
+        };
+        let [serde] = &items[..] else {
+            panic!("{items:?}");
+        };
+        assert_eq!(serde.label, "serde");
+        assert_eq!(
+            serde.detail.as_deref(),
+            Some("already in dependency tree at 1.0.188 via serde_json")
+        );
+        assert_eq!(
+            serde.documentation,
+            Some(lsp_types::Documentation::String(
+                "app → serde_json → serde".into()
+            ))
+        );
+
+        // The locked version is required, not the newest one
+        let text =
+            "[package]\nname = \"app\"\n\n[dependencies]\nserde_json = \"1\"\ntoml = \"0.8\"\n";
+        open(backend, &uri, text).await;
+        let add = |name: &str, section: &str| AddArgs {
+            uri: uri.clone(),
+            name: name.into(),
+            section: section.into(),
+            version: None,
+        };
+        let result = backend
+            .add_dependency(&add("serde", "dependencies"))
+            .await
+            .unwrap();
+        assert_eq!(result.req, "1.0.188");
+        assert_eq!(result.locked.as_deref(), Some("1.0.188"));
+        assert!(result.applied);
+        let edit: WorkspaceEdit =
+            serde_json::from_value(applied.recv().await.unwrap()["edit"].clone()).unwrap();
+        let edits = &edit.changes.unwrap()[&uri];
+        assert_eq!(
+            dry_run::apply(&backend.source(text), edits).unwrap(),
+            format!("{text}serde = \"1.0.188\"\n")
+        );
+
+        // Crates that are not locked get the newest release
+        let result = backend
+            .add_dependency(&add("log", "dev-dependencies"))
+            .await
+            .unwrap();
+        assert_eq!((result.req.as_str(), result.locked), ("0.4.22", None));
+        let edit: WorkspaceEdit =
+            serde_json::from_value(applied.recv().await.unwrap()["edit"].clone()).unwrap();
+        let edits = &edit.changes.unwrap()[&uri];
+        assert_eq!(
+            dry_run::apply(&backend.source(text), edits).unwrap(),
+            format!("{text}\n[dev-dependencies]\nlog = \"0.4.22\"\n")
+        );
+
+        let declared = backend
+            .add_dependency(&add("toml", "dependencies"))
+            .await
+            .unwrap_err();
+        assert_eq!(declared.to_string(), "toml is already in [dependencies]");
+        let target = backend
+            .add_dependency(&add("serde", "target.'cfg(unix)'.dependencies"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            jsonrpc::Error::from(target).code,
+            jsonrpc::ErrorCode::InvalidParams
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn parses_each_version_once() {
+        // A manifest of many crates with long histories
+        let versions = (0..200).map(|i| format!("1.{}.{}", i / 10, i % 10));
+        let versions = versions.collect::<Vec<_>>();
+        let crates = (0..20).map(|i| (format!("crate{i}"), versions.clone()));
+        let registry = registry::MockClient::new(crates.collect());
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = (0..20).fold("[dependencies]\n".to_string(), |text, i| {
+            text + &format!("crate{i} = \"1.{i}\"\n")
+        });
+        let config = backend.config.borrow().clone();
+
+        version::PARSED.set(0);
+        let first = analyze(backend, &uri, &config, &text)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(version::PARSED.get(), 20 * 200);
+        // The second analysis works on the cached, parsed versions
+        let second = analyze(backend, &uri, &config, &text)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(version::PARSED.get(), 20 * 200);
+        assert_eq!(first.diagnostics, second.diagnostics);
+        assert_eq!(first.diagnostics.len(), 20);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_in_virtual_time() {
+        let service = delayed_service(&[("serde", 100), ("hung", 3_600_000)]);
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"2\"\nhung = \"1\"\n";
+        let config = backend.config.borrow().clone();
+        let start = Instant::now();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(config.fetch_timeout));
+        let codes = analysis
+            .diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.code.clone()))
+            .collect::<Vec<_>>();
+        // The timed out fetch is only logged
+        assert_eq!(codes, [(1, Some(NumberOrString::String("latest".into())))]);
+    }
+
+    #[tokio::test]
+    async fn replaces_prerelease_requirements() {
+        let service = delayed_service(&[("tokio", 0)]);
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\ntokio = \"2.0.0-rc.1\"\n";
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        let code = Some(NumberOrString::String(
+            version::PRERELEASE_REQUIREMENT.into(),
+        ));
+        let diagnostics = analysis
+            .diagnostics
+            .into_iter()
+            .filter(|d| d.code == code)
+            .collect::<Vec<_>>();
+        assert_eq!(diagnostics.len(), 1);
+
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: diagnostics[0].range,
+            context: CodeActionContext {
+                diagnostics,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = &actions[..] else {
+            panic!("{actions:?}");
+        };
+        assert_eq!(action.title, "Use the stable release `2.0.0`");
+        let edit = action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri].clone();
+        assert_eq!(
+            edit,
+            [TextEdit::new(
+                Range::new(Position::new(1, 9), Position::new(1, 19)),
+                "2.0.0".into()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn pins_the_minor_of_zero_requirements() {
+        let versions = ["0.7.3", "0.8.0", "0.8.5"].map(String::from).to_vec();
+        let crates = HashMap::from([("rand".into(), versions.clone()), ("log".into(), versions)]);
+        let registry = registry::MockClient::new(crates);
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nrand = \"0\"\nlog = \"0.8\"\n";
+        open(backend, &uri, text).await;
+
+        let uri = &uri;
+        let actions = |config: Config| async move {
+            let analysis = analyze(backend, uri, &config, text).await.unwrap().unwrap();
+            let diagnostics = analysis
+                .diagnostics
+                .iter()
+                .filter(|d| d.range.start.line == 1)
+                .cloned()
+                .collect::<Vec<_>>();
+            let codes = diagnostics
+                .iter()
+                .filter_map(|d| match &d.code {
+                    Some(NumberOrString::String(code)) => Some(code.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            backend
+                .analyses
+                .write()
+                .await
+                .insert(uri.clone(), Arc::new(analysis));
+            backend.config.send_replace(Arc::new(config));
+            let params = CodeActionParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+                range: Range::new(Position::new(1, 0), Position::new(1, 10)),
+                context: CodeActionContext {
+                    diagnostics,
+                    ..Default::default()
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            };
+            let actions = backend.code_action(params).await.unwrap().unwrap();
+            let titles = actions
+                .into_iter()
+                .filter_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action)
+                        if action.kind == Some(CodeActionKind::QUICKFIX)
+                            && action.edit.is_some() =>
+                    {
+                        Some(action.title)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            (codes, titles)
+        };
+
+        // Only the fix of the `0` requirement pins the minor, not the update or an exact pin
+        let (codes, titles) = actions(Config::default()).await;
+        assert_eq!(
+            codes,
+            ["outdated-compatible", version::ZERO_MAJOR_REQUIREMENT]
+        );
+        assert_eq!(titles, ["Pin the minor `0.8`"]);
+        assert!(rewrites_at(backend, uri, Position::new(1, 8))
+            .await
+            .is_empty());
+        assert_eq!(
+            rewrites_at(backend, uri, Position::new(2, 7)).await,
+            [(
+                "Pin to exact version 0.8.5".to_string(),
+                TextEdit::new(
+                    Range::new(Position::new(2, 7), Position::new(2, 10)),
+                    "=0.8.5".into()
+                )
+            )]
+        );
+
+        let disabled = Config {
+            zero_major_requirements: false,
+            ..Default::default()
+        };
+        let (codes, titles) = actions(disabled).await;
+        assert_eq!(codes, ["outdated-compatible"]);
+        assert_eq!(titles, ["Update to latest (0.8.5)"]);
+    }
+
+    #[tokio::test]
+    async fn shows_the_age_of_the_registry_data() {
+        let versions = vec!["1.0.0".to_string(), "1.0.5".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]));
+        let clock = Arc::new(TestClock::default());
+        // 2024-01-01
+        let fetched = 1_704_067_200;
+        clock.set(fetched);
+        let (service, _) = clock_service(Shared::new(Arc::new(registry)), clock.clone());
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"1.0.0\"\n";
+        open(backend, &uri, text).await;
+        let config = backend.config.borrow().clone();
+        let fetched_at = |analysis: &Analysis| {
+            let outdated = NumberOrString::String("outdated-compatible".into());
+            let diagnostic = analysis
+                .diagnostics
+                .iter()
+                .find(|d| d.code == Some(outdated.clone()));
+            diagnostic.unwrap().data.as_ref().unwrap()["fetchedAt"].clone()
+        };
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched_at(&analysis), "2024-01-01T00:00:00Z");
+        // Analyses from the cache keep the time of the fetch
+        clock.set(fetched + 90);
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched_at(&analysis), "2024-01-01T00:00:00Z");
+        backend.store_analysis(&uri, Arc::new(analysis)).await;
+
+        let hover = |position: Position| {
+            let params = HoverParams {
+                text_document_position_params: TextDocumentPositionParams::new(
+                    TextDocumentIdentifier::new(uri.clone()),
+                    position,
+                ),
+                work_done_progress_params: Default::default(),
+            };
+            async move {
+                let contents = backend.hover(params).await.unwrap().unwrap().contents;
+                let HoverContents::Markup(markup) = contents else {
+                    panic!("no markup");
+                };
+                markup.value.rsplit("\n\n").next().unwrap().to_string()
+            }
+        };
+        for (elapsed, footer) in [
+            (
+                90,
+                "_registry data from 1 minute ago, refreshing roughly every 15 min_",
+            ),
+            (
+                12 * 60,
+                "_registry data from 12 minutes ago, refreshing roughly every 15 min_",
+            ),
+            (
+                3 * 3600 + 5,
+                "_registry data from 3 hours ago, refreshing roughly every 15 min_",
+            ),
+            (
+                2 * 86400,
+                "_registry data from 2 days ago, refreshing roughly every 15 min_",
+            ),
+        ] {
+            clock.set(fetched + elapsed);
+            // On the name and on the requirement
+            assert_eq!(hover(Position::new(1, 2)).await, footer);
+            assert_eq!(hover(Position::new(1, 11)).await, footer);
+        }
+
+        // Nothing cached, no footer
+        backend.cache.write().await.remove("serde");
+        assert_eq!(
+            hover(Position::new(1, 2)).await,
+            "**serde** `1.0.0`: compatible update to `1.0.5`"
+        );
+    }
+
+    #[tokio::test]
+    async fn adapts_the_refresh_to_the_release_cadence() {
+        let clock = Arc::new(TestClock::default());
+        // `busy` publishes a release with every fetch, `calm` never does
+        let calm = HashMap::from([("calm".to_string(), vec!["1.0.0".to_string()])]);
+        let registry = Arc::new(
+            registry::MockClient::new(calm)
+                .releasing("busy")
+                .recording(),
+        );
+        let releases = || registry.requests().iter().filter(|n| *n == "busy").count();
+        let (service, _) = clock_service(Shared::new(registry.clone()), clock.clone());
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nbusy = \"1\"\ncalm = \"1\"\n";
+        open(backend, &uri, text).await;
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        let ttl = |name| async move { backend.cache.read().await.ttl(name).unwrap() };
+        assert_eq!((ttl("busy").await, ttl("calm").await), (900, 900));
+
+        // Nothing is fetched again before one of them expires
+        let mut now = 0;
+        clock.set(899);
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        assert_eq!(releases(), 1);
+        // `calm` doubles its TTL whenever `busy` expired often enough
+        let mut refreshes = 0;
+        while ttl("calm").await < 86400 && refreshes < 1000 {
+            now += ttl("busy").await;
+            clock.set(now as i64);
+            backend
+                .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+                .await;
+            refreshes += 1;
+        }
+        assert_eq!((ttl("busy").await, ttl("calm").await), (900, 86400));
+        assert_eq!(refreshes, 1 + 2 + 4 + 8 + 16 + 32 + 64);
+        // The refetched versions replaced the expired ones
+        let fetches = releases();
+        assert_eq!(fetches, refreshes + 1);
+        assert_eq!(
+            backend.cache.read().await.get("busy").unwrap().len(),
+            fetches
+        );
+        let hover = HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(2, 2),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        let contents = backend
+            .hover(hover.clone())
+            .await
+            .unwrap()
+            .unwrap()
+            .contents;
+        let HoverContents::Markup(markup) = contents else {
+            panic!("no markup");
+        };
+        assert!(
+            markup.value.ends_with("refreshing roughly every 1 d_"),
+            "{}",
+            markup.value
+        );
+
+        // Refreshing the crate starts over
+        backend.refresh(Some("calm")).await;
+        assert_eq!((ttl("busy").await, ttl("calm").await), (900, 900));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn emits_events_for_edits_and_new_releases() {
+        use futures::{SinkExt, StreamExt};
+        use serde_json::json;
+        use tower::{Service, ServiceExt};
+
+        let registry = Arc::new(registry::MockClient::new(HashMap::from(
+            ["alpha", "beta"].map(|name| (name.to_string(), vec!["1.0.0".to_string()])),
+        )));
+        let clock = Arc::new(TestClock::default());
+        let (mut service, mut socket) = clock_service(Shared::new(registry.clone()), clock.clone());
+        let (sent, mut received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                if request.method() == "crates-io/event" {
+                    let _ = sent.send(request.params().cloned().unwrap());
+                }
+                if let Some(id) = request.id().cloned() {
+                    let response = jsonrpc::Response::from_ok(id, Value::Null);
+                    if socket.send(response).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        // Notifications are only sent to initialized clients
+        let initialize = jsonrpc::Request::build("initialize")
+            .params(json!({ "capabilities": {} }))
+            .id(1)
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+        let backend = service.inner();
+        let snapshot = || async {
+            let params = ExecuteCommandParams {
+                command: events::SNAPSHOT.into(),
+                arguments: Vec::new(),
+                work_done_progress_params: Default::default(),
+            };
+            backend.execute_command(params).await
+        };
+        assert!(snapshot().await.is_err());
+        backend.update_config(json!({ "emitEvents": true })).await;
+
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nalpha = \"1\"\nbeta = \"1\"\n";
+        open(backend, &uri, text).await;
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        // Typed, only the last requirement counts
+        let typing = "[dependencies]\nalpha = \"1.\"\nbeta = \"1\"\n";
+        backend
+            .update_diagnostics(&uri, None, &backend.source(typing), Priority::Interactive)
+            .await;
+        let edited = "[dependencies]\nalpha = \"1.0\"\nbeta = \"1\"\n";
+        backend
+            .update_diagnostics(&uri, None, &backend.source(edited), Priority::Interactive)
+            .await;
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        // Released upstream, seen once the cached versions expired
+        registry.release_all("beta", ["2.0.0".to_string()]);
+        clock.set(1000);
+        for _ in 0..2 {
+            backend
+                .update_diagnostics(&uri, None, &backend.source(edited), Priority::Interactive)
+                .await;
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let mut events = Vec::new();
+        while let Ok(event) = received.try_recv() {
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            [
+                json!({
+                    "uri": "file:///app/Cargo.toml",
+                    "crate": "alpha",
+                    "kind": "requirementChanged",
+                    "old": "1",
+                    "new": "1.0",
+                    "timestamp": "1970-01-01T00:00:00Z",
+                }),
+                json!({
+                    "uri": "file:///app/Cargo.toml",
+                    "crate": "beta",
+                    "kind": "outdated",
+                    "new": "2.0.0",
+                    "timestamp": "1970-01-01T00:16:40Z",
+                }),
+            ]
+        );
+        let kinds = snapshot()
+            .await
+            .unwrap()
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| format!("{} {} {}", e["crate"], e["kind"], e["new"]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                "\"alpha\" \"added\" \"1.0\"",
+                "\"beta\" \"added\" \"1\"",
+                "\"beta\" \"outdated\" \"2.0.0\""
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn shows_the_failover_notices() {
+        use futures::{SinkExt, StreamExt};
+        use serde_json::json;
+        use tower::{Service, ServiceExt};
+
+        let serde = HashMap::from([("serde".to_string(), vec!["1.0.0".to_string()])]);
+        let registry = registry::MockClient::new(serde).with_notices(vec![
+            failover::Notice::FailedOver {
+                from: "https://mirror.example".into(),
+                to: CRATES_IO_INDEX.into(),
+                reason: "Timed out after 10s".into(),
+            },
+            failover::Notice::Recovered {
+                endpoint: "https://mirror.example".into(),
+            },
+        ]);
+        let (mut service, mut socket) = socket_service(Shared::new(Arc::new(registry)));
+        let (sent, mut received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                if request.method() == "window/showMessage" {
+                    let _ = sent.send(request.params().cloned().unwrap());
+                }
+                if let Some(id) = request.id().cloned() {
+                    let response = jsonrpc::Response::from_ok(id, Value::Null);
+                    if socket.send(response).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        let initialize = jsonrpc::Request::build("initialize")
+            .params(json!({ "capabilities": {} }))
+            .id(1)
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"1\"\n";
+        open(backend, &uri, text).await;
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+
+        assert_eq!(
+            received.recv().await.unwrap(),
+            json!({
+                "type": 2,
+                "message": "The registry https://mirror.example fails (Timed out after 10s), \
+                    the versions are fetched from https://index.crates.io until it recovers"
+            })
+        );
+        assert_eq!(
+            received.recv().await.unwrap(),
+            json!({
+                "type": 3,
+                "message": "The registry https://mirror.example recovered, \
+                    the versions are fetched from it again"
+            })
+        );
+    }
+
+    #[cfg(feature = "metadata")]
+    #[tokio::test]
+    async fn flags_stale_crates_from_cached_metadata() {
+        let versions = vec!["1.0.0".to_string()];
+        let crates = ["old", "new", "unknown"].map(|name| (name.to_string(), versions.clone()));
+        let registry = registry::MockClient::new(HashMap::from(crates));
+        let clock = Arc::new(TestClock::default());
+        // 2024-01-01
+        clock.set(19723 * 24 * 60 * 60);
+        let (service, _) = clock_service(Shared::new(Arc::new(registry)), clock);
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nold = \"1\"\nnew = \"1\"\nunknown = \"1\"\n";
+        open(backend, &uri, text).await;
+
+        let metadata = |released: &str| CrateMetadata {
+            description: None,
+            downloads: 0,
+            recent_downloads: None,
+            versions: 1,
+            released: Some(released.into()),
+            release_gap: None,
+            owners: Vec::new(),
+            repository: None,
+        };
+        let fetched = backend.clock.now();
+        {
+            let mut cache = backend.metadata.write().await;
+            cache.insert("old".into(), (fetched, metadata("2019-10-16T00:00:00Z")));
+            cache.insert("new".into(), (fetched, metadata("2023-12-01T00:00:00Z")));
+        }
+
+        let config = Config {
+            endpoint: Some(CRATES_IO_INDEX.into()),
+            stale_crates: true,
+            ..Default::default()
+        };
+        let (uri, config) = (&uri, &config);
+        let stale = || async move {
+            let analysis = analyze(backend, uri, config, text).await.unwrap().unwrap();
+            analysis
+                .diagnostics
+                .into_iter()
+                .filter(|d| d.code == Some(NumberOrString::String(stale::STALE_CRATE.into())))
+                .map(|d| (d.range.start.line, d.message))
+                .collect::<Vec<_>>()
+        };
+        // Nothing is known about `unknown` yet, which is not an error
+        assert_eq!(
+            stale().await,
+            [(
+                1,
+                "`old` may be unmaintained, last release 4.2 years ago".to_string()
+            )]
+        );
+
+        // Expired metadata may miss a release, the check does not fetch it again
+        tokio::time::pause();
+        tokio::time::advance(METADATA_TTL).await;
+        assert_eq!(stale().await, []);
+        let cache = backend.metadata.read().await;
+        assert_eq!(cache["old"].0, fetched);
+        assert!(!cache.contains_key("unknown"));
+    }
+
+    /// The crates.io API with a rate limit of its own, every crate was released recently.
+    #[cfg(feature = "metadata")]
+    struct LimitedApi(rate_limit::RateLimiter);
+
+    #[cfg(feature = "metadata")]
+    impl metadata::MetadataClient for LimitedApi {
+        fn fetch<'a>(
+            &'a self,
+            _name: &'a str,
+            _user_agent: &'a str,
+            priority: Priority,
+            clock: &'a dyn Clock,
+        ) -> registry::BoxFuture<'a, Result<CrateMetadata>> {
+            Box::pin(async move {
+                for _ in 0..metadata::REQUESTS_PER_CRATE {
+                    self.0.acquire(clock, priority, None).await;
+                }
+                Ok(CrateMetadata {
+                    description: None,
+                    downloads: 0,
+                    recent_downloads: None,
+                    versions: 1,
+                    released: Some("2023-12-01T00:00:00Z".into()),
+                    release_gap: None,
+                    owners: Vec::new(),
+                    repository: None,
+                })
+            })
+        }
+
+        fn budget(&self, clock: &dyn Clock) -> rate_limit::Budget {
+            self.0.budget(clock)
+        }
+    }
+
+    /// Backend for crates.io with `staleCrates`, whose API allows one crate at once
+    /// and another every two seconds.
+    #[cfg(feature = "metadata")]
+    async fn enriching_service() -> LspService<CratesIoBackend> {
+        let versions = vec!["1.0.0".to_string()];
+        let crates = ["a", "b", "c"].map(|name| (name.to_string(), versions.clone()));
+        let limiter = rate_limit::RateLimiter::new(Duration::from_secs(1), 2);
+        let shared = Shared {
+            api: Arc::new(LimitedApi(limiter)),
+            ..Shared::new(Arc::new(registry::MockClient::new(HashMap::from(crates))))
+        };
+        let args = Args {
+            endpoint: vec![CRATES_IO_INDEX.into()],
+            ..clock_service_args()
+        };
+        let (service, _) = lsp_service(args, shared, Arc::new(TokioClock));
+        let backend = service.inner();
+        backend
+            .update_config(serde_json::json!({ "staleCrates": true }))
+            .await;
+        service
+    }
+
+    /// Dependencies of the published analysis noted as awaiting their metadata.
+    #[cfg(feature = "metadata")]
+    async fn pending_notes(backend: &CratesIoBackend, uri: &Uri) -> Vec<u32> {
+        let analyses = backend.analyses.read().await;
+        analyses[uri]
+            .diagnostics
+            .iter()
+            .filter(|d| d.message.ends_with("\nadditional data pending"))
+            .map(|d| d.range.start.line)
+            .collect()
+    }
+
+    #[cfg(feature = "metadata")]
+    #[tokio::test(start_paused = true)]
+    async fn resolves_the_pending_metadata_once_the_budget_refills() {
+        let service = enriching_service().await;
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\na = \"1\"\nb = \"1\"\nc = \"1\"\n";
+        open(backend, &uri, text).await;
+        let start = Instant::now();
+        let check = async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            // `a` took the burst, `b` and `c` need the tokens of the next four seconds
+            let status = backend.enrichment_status().await;
+            assert_eq!(
+                status.documents,
+                [enrich::DocumentStatus {
+                    uri: uri.clone(),
+                    awaiting_metadata: 2,
+                    wait_ms: 3500,
+                }]
+            );
+            assert_eq!((status.queued, status.refill_ms), (1, 1000));
+            assert_eq!(pending_notes(backend, &uri).await, [2, 3]);
+
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            let status = backend.enrichment_status().await;
+            assert_eq!(status.documents[0].awaiting_metadata, 1);
+            assert_eq!(status.documents[0].wait_ms, 1500);
+            assert_eq!(pending_notes(backend, &uri).await, [3]);
+        };
+        let source = backend.source(text);
+        tokio::join!(
+            backend.update_diagnostics(&uri, None, &source, Priority::Interactive),
+            check
+        );
+        assert_eq!(start.elapsed(), Duration::from_secs(4));
+        assert_eq!(backend.enrichment_status().await.documents, []);
+        assert_eq!(pending_notes(backend, &uri).await, [0u32; 0]);
+        assert_eq!(backend.metadata.read().await.len(), 3);
+    }
+
+    #[cfg(feature = "metadata")]
+    #[tokio::test(start_paused = true)]
+    async fn drops_the_pending_metadata() {
+        let service = enriching_service().await;
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\na = \"1\"\nb = \"1\"\nc = \"1\"\n";
+        open(backend, &uri, text).await;
+        let start = Instant::now();
+        let close = async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            backend
+                .did_close(DidCloseTextDocumentParams {
+                    text_document: TextDocumentIdentifier::new(uri.clone()),
+                })
+                .await;
+            assert_eq!(backend.enrichment_status().await.documents, []);
+        };
+        let source = backend.source(text);
+        tokio::join!(
+            backend.update_diagnostics(&uri, None, &source, Priority::Interactive),
+            close
+        );
+        // Stopped with the job instead of fetching the rest
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(backend.metadata.read().await.len(), 1);
+
+        open(backend, &uri, text).await;
+        let disable = async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert_eq!(backend.enrichment_status().await.documents.len(), 1);
+            backend
+                .update_config(serde_json::json!({ "staleCrates": false }))
+                .await;
+            assert_eq!(backend.enrichment_status().await.documents, []);
+        };
+        let start = Instant::now();
+        let source = backend.source(text);
+        tokio::join!(
+            backend.update_diagnostics(&uri, None, &source, Priority::Interactive),
+            disable
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+        // `b` was still waiting for the budget
+        assert_eq!(backend.metadata.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn resolves_the_edit_of_the_series_update() {
+        let versions = ["1.2.0", "2.3.0", "2.9.4", "3.0.0", "3.2.1"].map(String::from);
+        let crates = HashMap::from([("clap".into(), versions.to_vec())]);
+        let registry = registry::MockClient::new(crates);
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+        backend.caps.send_modify(|caps| caps.resolve_edits = true);
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nclap = \"2.3\"\n";
+        open(backend, &uri, text).await;
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        let diagnostics = analysis.diagnostics.clone();
+        backend
+            .analyses
+            .write()
+            .await
+            .insert(uri.clone(), Arc::new(analysis));
+
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: Range::new(Position::new(1, 0), Position::new(1, 4)),
+            context: CodeActionContext {
+                diagnostics,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend
+            .code_action(params)
+            .await
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action)
+                    if action.kind == Some(CodeActionKind::QUICKFIX) =>
+                {
+                    Some(action)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let titles = actions.iter().map(|a| a.title.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [
+                "Update to 3.2.1 (new major)",
+                "Update to latest 2.x (2.9.4)",
+                "Choose version…"
+            ]
+        );
+        assert!(actions.iter().all(|a| a.edit.is_none()));
+
+        let resolved = backend
+            .code_action_resolve(actions[1].clone())
+            .await
+            .unwrap();
+        let edits = resolved.edit.unwrap().changes.unwrap()[&uri].clone();
+        assert_eq!(
+            edits,
+            [TextEdit::new(
+                Range::new(Position::new(1, 8), Position::new(1, 11)),
+                "2.9".into()
+            )]
+        );
+
+        // Clients with a picker get the versions without an edit
+        backend.caps.send_modify(|caps| caps.version_picker = true);
+        let command = actions[2].command.clone().unwrap();
+        let result = backend
+            .execute_command(ExecuteCommandParams {
+                command: command.command,
+                arguments: command.arguments.unwrap(),
+                work_done_progress_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "versions": ["3.2.1", "3.0.0", "2.9.4", "2.3.0", "1.2.0"],
+                "latest": "3.2.1",
+                "applied": false,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn pins_and_relaxes_requirements() {
+        let service = delayed_service(&[("serde", 0), ("rand", 0), ("forked", 0), ("log", 0)]);
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"2\"\n\
+            rand = { version = \"=1.0.0\", features = [\"std\"] }\n\
+            forked = { git = \"https://example.com/forked\", version = \"1\" }\n\
+            log = { workspace = true }\n";
+        open(backend, &uri, text).await;
+        let config = backend.config.borrow().clone();
+        let analysis = analyze(backend, &uri, &config, text)
+            .await
+            .unwrap()
+            .unwrap();
+        backend
+            .analyses
+            .write()
+            .await
+            .insert(uri.clone(), Arc::new(analysis));
+
+        let edit = |line, start, end, new_text: &str| {
+            TextEdit::new(
+                Range::new(Position::new(line, start), Position::new(line, end)),
+                new_text.into(),
+            )
+        };
+        assert_eq!(
+            rewrites_at(backend, &uri, Position::new(1, 10)).await,
+            [(
+                "Pin to exact version 2.0.0".to_string(),
+                edit(1, 9, 10, "=2.0.0")
+            )]
+        );
+        // The rest of the inline table is kept
+        assert_eq!(
+            rewrites_at(backend, &uri, Position::new(2, 22)).await,
+            [(
+                "Relax to caret requirement 1.0.0".to_string(),
+                edit(2, 20, 26, "1.0.0")
+            )]
+        );
+        assert!(rewrites_at(backend, &uri, Position::new(3, 65))
+            .await
+            .is_empty());
+        assert!(rewrites_at(backend, &uri, Position::new(4, 10))
+            .await
+            .is_empty());
+
+        // The pin of the newest version is not reported
+        let pinned = text.replace("serde = \"2\"", "serde = \"=2.0.0\"");
+        let analysis = analyze(backend, &uri, &config, &pinned)
+            .await
+            .unwrap()
+            .unwrap();
+        let serde = analysis.dependencies.iter().find(|d| d.package == "serde");
+        assert_eq!(
+            serde.unwrap().classification,
+            Some(version::Classification::Latest)
+        );
+        assert!(analysis
+            .diagnostics
+            .iter()
+            .all(|d| d.range.start.line != 1
+                || d.code == Some(NumberOrString::String("latest".into()))));
+    }
+
+    #[tokio::test]
+    async fn finds_the_users_of_a_crate() {
+        let service = service("http://127.0.0.1:1");
+        let backend = service.inner();
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-who-uses-{}", std::process::id()));
+        for member in ["app", "lib"] {
+            std::fs::create_dir_all(root.join(member)).unwrap();
+        }
+        let root_text = "[workspace]\nmembers = [\"app\", \"lib\"]\n\n\
+            [workspace.dependencies]\nserde = \"1.0\"\n";
+        let app_text =
+            "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n";
+        let lib_text = "[package]\nname = \"lib\"\n\n[dev-dependencies]\nserde = \"1.0.100\"\n";
+        std::fs::write(root.join("Cargo.toml"), root_text).unwrap();
+        std::fs::write(root.join("app/Cargo.toml"), app_text).unwrap();
+        std::fs::write(root.join("lib/Cargo.toml"), lib_text).unwrap();
+        let root_uri = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        let config = backend.config.borrow().clone();
+        // The closed members are indexed when the root reads them
+        let analysis = analyze(backend, &root_uri, &config, root_text).await;
+        std::fs::remove_dir_all(&root).unwrap();
+        analysis.unwrap().unwrap();
+
+        let who_uses = |arguments| async move {
+            let params = ExecuteCommandParams {
+                command: index::WHO_USES.into(),
+                arguments,
+                ..Default::default()
+            };
+            backend.execute_command(params).await
+        };
+        let serde = who_uses(vec![serde_json::json!("serde")]).await;
+        let serde = serde.unwrap().unwrap();
+        let declarations = serde
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|o| {
+                let uri = o["uri"].as_str().unwrap();
+                let path = uri.strip_prefix(root_uri.as_str().trim_end_matches("Cargo.toml"));
+                (
+                    path.unwrap().to_string(),
+                    o["section"].clone(),
+                    o["req"].clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            declarations,
+            [
+                (
+                    "Cargo.toml".into(),
+                    "workspace.dependencies".into(),
+                    "1.0".into()
+                ),
+                ("app/Cargo.toml".into(), "dependencies".into(), "1".into()),
+                (
+                    "lib/Cargo.toml".into(),
+                    "dev-dependencies".into(),
+                    "1.0.100".into()
+                ),
+            ]
+        );
+        assert_eq!(
+            serde[1]["range"]["start"],
+            serde_json::json!({ "line": 4, "character": 0 })
+        );
+
+        let summary = who_uses(Vec::new()).await.unwrap();
+        assert_eq!(
+            summary,
+            Some(serde_json::json!({ "manifests": 3, "crates": { "log": 1, "serde": 3 } }))
+        );
+        assert_eq!(
+            who_uses(vec![serde_json::json!("rand")]).await.unwrap(),
+            Some(serde_json::json!([]))
+        );
+        assert!(who_uses(vec![serde_json::json!(1)]).await.is_err());
+
+        // Renamed and deleted manifests are moved or pruned
+        let app = root_uri.as_str().replace("Cargo.toml", "app");
+        backend
+            .did_rename_files(RenameFilesParams {
+                files: vec![FileRename {
+                    old_uri: app.clone(),
+                    new_uri: app.replace("/app", "/bin"),
+                }],
+            })
+            .await;
+        let log = who_uses(vec![serde_json::json!("log")])
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(log[0]["uri"].as_str().unwrap().ends_with("/bin/Cargo.toml"));
+        backend
+            .did_delete_files(DeleteFilesParams {
+                files: vec![FileDelete {
+                    uri: app.replace("/app", "/bin"),
+                }],
+            })
+            .await;
+        let log = who_uses(vec![serde_json::json!("log")]).await.unwrap();
+        assert_eq!(log, Some(serde_json::json!([])));
+    }
+
+    #[tokio::test]
+    async fn reports_the_indexed_manifests() {
+        let endpoint = registry(&["1.0.0", "2.0.0"], None).await;
+        let service = service(&endpoint);
+        let backend = service.inner();
+        for (uri, text) in [
+            (
+                "file:///ws/app/Cargo.toml",
+                "[dependencies]\nserde = \"1\"\nlog = \"2.0.0\"\n",
+            ),
+            (
+                "file:///ws/lib/Cargo.toml",
+                "[dependencies]\nserde = \"2\"\n",
+            ),
+        ] {
+            let (manifest, _) = manifest::parse(text);
+            let uri = uri.parse().unwrap();
+            backend
+                .index
+                .write()
+                .await
+                .update(&uri, &backend.source(text), &manifest);
+        }
+        // Cached crates are not fetched again until they expire
+        backend.cache.write().await.insert(
+            "log".into(),
+            releases(&["2.0.0", "3.0.0"]),
+            backend.clock.unix_time(),
+            &HashSet::new(),
+        );
+
+        let report = backend.report(None, None).await.unwrap();
+        assert!(
+            report.contains(
+                "| `app/Cargo.toml` | 2 | 0 | 0 | 2 | 0 | serde 1 → 2.0.0 (1 release behind) |"
+            ),
+            "{report}"
+        );
+        assert!(
+            report.contains("| `lib/Cargo.toml` | 1 | 1 | 0 | 0 | 0 |"),
+            "{report}"
+        );
+        assert!(
+            report.contains("| serde | `1` in `app/Cargo.toml`, `2` in `lib/Cargo.toml` |"),
+            "{report}"
+        );
+        assert!(backend.cache.read().await.get("serde").is_some());
+    }
+
+    #[tokio::test]
+    async fn scopes_the_workspace_to_the_default_members() {
+        let registry = registry::MockClient::new(HashMap::from([
+            ("serde".into(), vec!["1.0.0".into()]),
+            ("log".into(), vec!["0.4.0".into()]),
+        ]));
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+        let root = std::env::temp_dir().join(format!("crates-io-lsp-scope-{}", std::process::id()));
+        let root_text = "[workspace]\nmembers = [\"crates/*\"]\ndefault-members = [\"crates/a\", \"crates/c\"]\nexclude = [\"crates/c\"]\n";
+        for (path, text) in [
+            ("Cargo.toml", root_text),
+            ("crates/a/Cargo.toml", "[dependencies]\nserde = \"1\"\n"),
+            ("crates/b/Cargo.toml", "[dependencies]\nlog = \"0.4\"\n"),
+            ("crates/c/Cargo.toml", "[dependencies]\nlog = \"0.3\"\n"),
+        ] {
+            std::fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root.join(path), text).unwrap();
+        }
+        *backend.roots.write().await = vec![root.clone()];
+        let uri = |path: &str| Uri::from_file_path(root.join(path)).unwrap();
+        let using = |name: &'static str| async move {
+            let index = backend.index.read().await;
+            let mut uris = index.documents_using(name).cloned().collect::<Vec<_>>();
+            uris.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            uris
+        };
+
+        // Only the default members are indexed, the excluded one is flagged
+        let config = Config::default();
+        let analysis = analyze(backend, &uri("Cargo.toml"), &config, root_text)
+            .await
+            .unwrap()
+            .unwrap();
+        let excluded = analysis
+            .diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("excluded-default-member".into())))
+            .map(|d| d.range.start)
+            .collect::<Vec<_>>();
+        assert_eq!(excluded, [Position::new(2, 31)]);
+        assert_eq!(using("serde").await, [uri("crates/a/Cargo.toml")]);
+        assert_eq!(using("log").await, []);
+
+        let report = backend.report(None, None).await.unwrap();
+        assert!(report.contains("`crates/a/Cargo.toml`"), "{report}");
+        assert!(!report.contains("crates/b"), "{report}");
+        // Wider when asked for
+        let report = backend
+            .report(Some(ScanScope::Members), None)
+            .await
+            .unwrap();
+        assert!(report.contains("`crates/b/Cargo.toml`"), "{report}");
+        assert!(!report.contains("crates/c"), "{report}");
+        assert_eq!(using("log").await, [uri("crates/b/Cargo.toml")]);
+        let everywhere = |scope| EverywhereArgs {
+            name: "log".into(),
+            version: Some("0.5.0".into()),
+            scope,
+            context: None,
+        };
+        let result = backend.update_everywhere(&everywhere(None)).await.unwrap();
+        assert!(result.updated.is_empty() && result.skipped.is_empty());
+
+        // Opened manifests are always visited
+        open(
+            backend,
+            &uri("crates/b/Cargo.toml"),
+            "[dependencies]\nlog = \"0.4\"\n",
+        )
+        .await;
+        let report = backend
+            .report(Some(ScanScope::OpenOnly), None)
+            .await
+            .unwrap();
+        assert!(report.contains("Cargo.toml"), "{report}");
+        assert!(!report.contains("serde"), "{report}");
+        let scope = backend
+            .command_scope(Some(ScanScope::OpenOnly), None, &config)
+            .await;
+        assert!(scope.visits(&uri("crates/b/Cargo.toml")));
+        assert!(!scope.visits(&uri("crates/a/Cargo.toml")));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn indexes_members_without_dependencies_unparsed() {
+        let registry =
+            registry::MockClient::new(HashMap::from([("log".into(), vec!["0.4.0".into()])]));
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+        backend.stats.set_enabled(true);
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-unparsed-{}", std::process::id()));
+        let write = |path: &str, text: &str| {
+            std::fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root.join(path), text).unwrap();
+        };
+        write("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\n");
+        write("crates/a/Cargo.toml", "[dependencies]\nlog = \"0.4\"\n");
+        write("crates/b/Cargo.toml", "[dependencies]\nlog = \"0.4\"\n");
+        *backend.roots.write().await = vec![root.clone()];
+        let uri = |path: &str| Uri::from_file_path(root.join(path)).unwrap();
+        let using = || async {
+            let index = backend.index.read().await;
+            let mut uris = index.documents_using("log").cloned().collect::<Vec<_>>();
+            uris.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            uris
+        };
+        backend.report(None, None).await.unwrap();
+        assert_eq!(
+            using().await,
+            [uri("crates/a/Cargo.toml"), uri("crates/b/Cargo.toml")]
+        );
+
+        // The removed dependencies are dropped from the index
+        write(
+            "crates/b/Cargo.toml",
+            "# [dependencies]\r\n[package]\r\nname = \"b\"\r\n",
+        );
+        let report = backend.report(None, None).await.unwrap();
+        assert!(report.contains("`crates/a/Cargo.toml`"), "{report}");
+        assert_eq!(using().await, [uri("crates/a/Cargo.toml")]);
+        let stats = backend.stats.flush(0).await.unwrap();
+        assert_eq!(stats.total().manifests_skipped, 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_workspace_contexts_apart() {
+        let registry = Arc::new(
+            registry::MockClient::new(HashMap::from([
+                ("serde".into(), vec!["1.0.0".into()]),
+                ("heapless".into(), vec!["0.7.0".into(), "0.8.0".into()]),
+                ("log".into(), vec!["0.4.0".into()]),
+            ]))
+            .recording(),
+        );
+        let service = shared_service(Shared::new(registry.clone()));
+        let backend = service.inner();
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-contexts-{}", std::process::id()));
+        let git_log = "log = { git = \"https://example.com/log\", version = \"0.4\" }\n";
+        let tooling = "[workspace]\nmembers = [\"tools/*\"]\nexclude = [\"firmware\"]\n\n\
+            [workspace.package]\nrust-version = \"1.85\"\n";
+        let files = [
+            ("Cargo.toml", tooling.to_string()),
+            (
+                "tools/cli/Cargo.toml",
+                format!("[dependencies]\nserde = \"1\"\n{git_log}"),
+            ),
+            ("tools/xtask/Cargo.toml", "[dependencies]\nserde = \"1\"\n".into()),
+            (
+                "firmware/Cargo.toml",
+                "[workspace]\nmembers = [\"hal\"]\n\n[workspace.package]\nrust-version = \"1.60\"\n"
+                    .into(),
+            ),
+            (
+                "firmware/hal/Cargo.toml",
+                format!("[dependencies]\nheapless = \"0.7\"\n{git_log}"),
+            ),
+            ("firmware/Cargo.lock", "version = 3\n".into()),
+        ];
+        for (path, text) in &files {
+            std::fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root.join(path), text).unwrap();
+        }
+        *backend.roots.write().await = vec![root.clone()];
+        let uri = |path: &str| Uri::from_file_path(root.join(path)).unwrap();
+        let open = |path: &str| {
+            let text = files.iter().find(|(p, _)| *p == path).unwrap().1.clone();
+            DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri(path), "toml".into(), 0, text),
+            }
+        };
+        // Fetched since the last call
+        let seen = std::cell::Cell::new(0);
+        let fetched = || {
+            let requests = registry.requests();
+            requests[seen.replace(requests.len())..].to_vec()
+        };
+
+        // The nested workspace is the nearest root of its member
+        backend.did_open(open("firmware/hal/Cargo.toml")).await;
+        backend.did_open(open("tools/cli/Cargo.toml")).await;
+        {
+            let contexts = backend.contexts.read().await;
+            let context = |path: &str| contexts.root(&uri(path)).map(Path::to_path_buf);
+            assert_eq!(
+                context("firmware/hal/Cargo.toml"),
+                Some(root.join("firmware"))
+            );
+            assert_eq!(context("tools/cli/Cargo.toml"), Some(root.clone()));
+        }
+
+        // Saving the tooling root analyzes and fetches for its own members only
+        backend.did_open(open("Cargo.toml")).await;
+        backend.analyses.write().await.clear();
+        backend.cache.write().await.invalidate_all();
+        fetched();
+        backend
+            .did_save(DidSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri("Cargo.toml")),
+                text: Some(tooling.into()),
+            })
+            .await;
+        {
+            let analyses = backend.analyses.read().await;
+            assert!(analyses.contains_key(&uri("tools/cli/Cargo.toml")));
+            assert!(!analyses.contains_key(&uri("firmware/hal/Cargo.toml")));
+        }
+        let mut names = fetched();
+        names.sort();
+        assert_eq!(names, ["log", "serde"]);
+
+        // The commands default to the context of the active document
+        let report = backend.report(None, None).await.unwrap();
+        assert!(report.contains("cli/Cargo.toml"), "{report}");
+        assert!(report.contains("xtask/Cargo.toml"), "{report}");
+        assert!(!report.contains("hal"), "{report}");
+        assert!(!fetched().contains(&"heapless".to_string()));
+        let everywhere = |context: Option<Uri>| EverywhereArgs {
+            name: "log".into(),
+            version: Some("0.5.0".into()),
+            scope: None,
+            context,
+        };
+        let skipped = |result: EverywhereResult| {
+            let uris = result.skipped.into_iter().map(|s| s.uri);
+            uris.collect::<Vec<_>>()
+        };
+        let result = backend.update_everywhere(&everywhere(None)).await.unwrap();
+        assert_eq!(skipped(result), [uri("tools/cli/Cargo.toml")]);
+
+        // Or to the selected one
+        let firmware = Some(uri("firmware"));
+        let report = backend.report(None, firmware.as_ref()).await.unwrap();
+        assert!(report.contains("hal/Cargo.toml"), "{report}");
+        assert!(!report.contains("cli"), "{report}");
+        assert!(!fetched().contains(&"serde".to_string()));
+        let result = backend
+            .update_everywhere(&everywhere(firmware))
+            .await
+            .unwrap();
+        assert_eq!(skipped(result), [uri("firmware/hal/Cargo.toml")]);
+        let command = |command: &str, arguments: Vec<Value>| ExecuteCommandParams {
+            command: command.into(),
+            arguments,
+            work_done_progress_params: Default::default(),
+        };
+        let who_uses = |context: &str| {
+            command(
+                index::WHO_USES,
+                vec![Value::from("log"), Value::from(uri(context).as_str())],
+            )
+        };
+        let uses = backend.execute_command(who_uses("firmware")).await.unwrap();
+        assert_eq!(uses.unwrap().as_array().unwrap().len(), 1);
+        let uses = backend.execute_command(who_uses("tools")).await.unwrap();
+        assert_eq!(
+            uses.unwrap()[0]["uri"],
+            uri("tools/cli/Cargo.toml").as_str()
+        );
+
+        let contexts = backend
+            .execute_command(command(context::CONTEXTS, vec![]))
+            .await
+            .unwrap()
+            .unwrap();
+        let summary = |context: &Value| {
+            (
+                context["root"].clone(),
+                context["active"].clone(),
+                context["rustVersion"].clone(),
+                context["lockfile"].is_string(),
+                context["manifests"].as_array().unwrap().len(),
+            )
+        };
+        assert_eq!(
+            contexts
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(summary)
+                .collect::<Vec<_>>(),
+            [
+                (
+                    Uri::from_file_path(&root).unwrap().as_str().into(),
+                    true.into(),
+                    "1.85".into(),
+                    false,
+                    3
+                ),
+                (
+                    uri("firmware").as_str().into(),
+                    false.into(),
+                    "1.60".into(),
+                    true,
+                    2
+                ),
+            ]
+        );
+
+        // Contexts are dropped with their last manifest
+        backend
+            .did_close(DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri("firmware/hal/Cargo.toml")),
+            })
+            .await;
+        assert!(backend
+            .contexts
+            .read()
+            .await
+            .root(&uri("firmware/hal/Cargo.toml"))
+            .is_some());
+        backend
+            .did_delete_files(DeleteFilesParams {
+                files: vec![FileDelete {
+                    uri: uri("firmware").to_string(),
+                }],
+            })
+            .await;
+        let roots = backend
+            .contexts
+            .read()
+            .await
+            .roots()
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        assert_eq!(roots, [root.as_path()]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// A workspace with a root and the members `a` and `b` in a new temporary directory.
+    fn scan_fixture(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-{name}-{}", std::process::id()));
+        let files = [
+            ("Cargo.toml", "[workspace]\nmembers = [\"a\", \"b\"]\n"),
+            (
+                "a/Cargo.toml",
+                "[package]\nname = \"a\"\n\n[dependencies]\nserde = \"1\"\n",
+            ),
+            (
+                "b/Cargo.toml",
+                "[package]\nname = \"b\"\n\n[dependencies]\nhung = \"1\"\n",
+            ),
+        ];
+        for (path, text) in files {
+            std::fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root.join(path), text).unwrap();
+        }
+        root
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resumes_a_cancelled_scan() {
+        let root = scan_fixture("scan-resume");
+        let cache_dir = root.join("cache");
+        let args = || Args {
+            cache_dir: Some(cache_dir.clone()),
+            ..clock_service_args()
+        };
+        let shared = delayed_shared(&[("serde", 10), ("hung", 3_600_000)]);
+        let (service, _) = lsp_service(args(), shared, Arc::new(TokioClock));
+        let backend = service.inner();
+        *backend.roots.write().await = vec![root.clone()];
+
+        // Cancelled while fetching the crates, after all manifests are scanned
+        let cancel = async {
+            while backend.scanner.lock().await.status.crates.is_none() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            let again = backend.scan().await;
+            assert_eq!(again.state, scan::State::Running);
+            backend.cancel_scan().await
+        };
+        let (status, cancelled) = tokio::join!(backend.scan(), cancel);
+        assert_eq!(cancelled.state, scan::State::Cancelled);
+        assert_eq!(status.state, scan::State::Cancelled);
+        assert_eq!((status.scanned, status.total, status.unchanged), (3, 3, 0));
+        assert_eq!((status.crates, status.fetched), (Some(2), 0));
+        let checkpoint = cache_dir.join(scan::file_name(std::slice::from_ref(&root)));
+        assert!(checkpoint.exists());
+
+        // A new session only parses the changed manifest
+        std::fs::write(
+            root.join("b/Cargo.toml"),
+            "[package]\nname = \"b\"\n\n[dependencies]\nhung = \"1\"\nserde = \"1\"\n",
+        )
+        .unwrap();
+        let shared = delayed_shared(&[("serde", 10), ("hung", 10)]);
+        let (service, _) = lsp_service(args(), shared, Arc::new(TokioClock));
+        let backend = service.inner();
+        *backend.roots.write().await = vec![root.clone()];
+        let status = backend
+            .execute_command(ExecuteCommandParams {
+                command: scan::SCAN_START.into(),
+                arguments: Vec::new(),
+                work_done_progress_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(status["state"], "finished");
+        assert_eq!(status["scanned"], 3);
+        assert_eq!(status["unchanged"], 2);
+        assert_eq!(status["fetched"], 2);
+        let index = backend.index.read().await;
+        let uri = |path: &str| Uri::from_file_path(root.join(path)).unwrap();
+        let mut using = index.documents_using("serde").cloned().collect::<Vec<_>>();
+        using.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(using, [uri("a/Cargo.toml"), uri("b/Cargo.toml")]);
+        assert!(backend.cache.read().await.get("hung").is_some());
+        drop(index);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pauses_the_scan_while_typing() {
+        let root = scan_fixture("scan-typing");
+        let service = delayed_service(&[("serde", 10), ("hung", 10)]);
+        let backend = service.inner();
+        *backend.roots.write().await = vec![root.clone()];
+        let uri = Uri::from_file_path(root.join("a/Cargo.toml")).unwrap();
+        open(backend, &uri, "[package]\nname = \"a\"\n").await;
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri, 1),
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "[package]\nname = \"a\"\n\n[dependencies]\n".into(),
+                }],
+            })
+            .await;
+        let start = Instant::now();
+        let check = async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let status = backend.scanner.lock().await.status.clone();
+            assert_eq!(status.state, scan::State::Paused);
+            assert_eq!(status.scanned, 0);
+        };
+        let (status, _) = tokio::join!(backend.scan(), check);
+        assert!(start.elapsed() >= scan::TYPING_PAUSE);
+        assert_eq!(status.state, scan::State::Finished);
+        // The open member is left to its analysis
+        assert_eq!((status.scanned, status.crates), (3, Some(1)));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shuts_down_while_fetching() {
+        let service = delayed_service(&[("serde", 10), ("hung", 3_600_000)]);
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"1\"\nhung = \"1\"\n";
+        open(backend, &uri, text).await;
+        let source = backend.source(text);
+        let update = backend.update_diagnostics(&uri, None, &source, Priority::Interactive);
+        let shutdown = async {
+            while backend.scheduler.progress().fetched == 0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            let start = Instant::now();
+            backend.shutdown().await.unwrap();
+            // The hung fetch is aborted instead of waited for
+            assert!(start.elapsed() <= Duration::from_millis(10));
+            assert_eq!(backend.scheduler.progress().jobs, 0);
+        };
+        tokio::join!(update, shutdown);
+        assert!(backend.analyses.read().await.is_empty());
+
+        // No analyses after the shutdown, the exit status is 0
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        assert!(backend.analyses.read().await.is_empty());
+        assert_eq!(backend.scheduler.progress().jobs, 0);
+        assert!(backend.shutdown.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn flags_dev_only_dependencies_after_publishing() {
+        let versions = vec!["1.0.0".to_string()];
+        let crates = ["serde", "tempfile"].map(|name| (name.to_string(), versions.clone()));
+        let registry = registry::MockClient::new(HashMap::from(crates));
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-dev-only-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("tests")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "use serde::Serialize;\n").unwrap();
+        std::fs::write(root.join("tests/it.rs"), "use tempfile::tempdir;\n").unwrap();
+        let text = "[dependencies]\nserde = \"1.0.0\"\ntempfile = \"1.0.0\"\n";
+        let uri = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        let open = |uri: &Uri| DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "toml".into(), 0, text.into()),
+        };
+
+        // Opt-in
+        backend.did_open(open(&uri)).await;
+        assert_eq!(
+            stored_codes(backend, &uri).await,
+            [(1, "latest".to_string()), (2, "latest".to_string())]
+        );
+        backend
+            .update_config(serde_json::json!({ "checkDevOnly": true }))
+            .await;
+        backend.did_open(open(&uri)).await;
+        let analysis = backend.analyses.read().await[&uri].clone();
+        std::fs::remove_dir_all(&root).unwrap();
+        let hint = analysis
+            .diagnostics
+            .iter()
+            .find(|d| d.code == Some(NumberOrString::String(dev_only::DEV_ONLY_DEPENDENCY.into())))
+            .unwrap();
+        assert_eq!(hint.range.start.line, 2);
+        assert_eq!(hint.severity, Some(lsp_types::DiagnosticSeverity::HINT));
+        assert_eq!(hint.source.as_deref(), Some("crates-io.style"));
+
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: hint.range,
+            context: CodeActionContext {
+                diagnostics: vec![hint.clone()],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = &actions[..] else {
+            panic!("{actions:?}");
+        };
+        assert_eq!(action.title, "Move to `[dev-dependencies]`");
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits
+                .iter()
+                .map(|e| e.new_text.as_str())
+                .collect::<Vec<_>>(),
+            ["", "\n[dev-dependencies]\ntempfile = \"1.0.0\"\n"]
+        );
+    }
+
+    #[tokio::test]
+    async fn suggests_the_usual_features_of_hinted_crates() {
+        let versions = vec!["1.0.200".to_string()];
+        let derive = BTreeMap::from([("derive".to_string(), vec![])]);
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]))
+            .with_features("serde", "1.0.200", derive);
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = { version = \"1\" }\n";
+        open(backend, &uri, text).await;
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        // Hinted once the feature maps arrived after publishing
+        let analysis = backend.analyses.read().await[&uri].clone();
+        assert!(analysis.unknown_features.is_empty());
+        let hint = analysis
+            .diagnostics
+            .iter()
+            .find(|d| d.code == Some(NumberOrString::String(suggest::MISSING_FEATURE.into())))
+            .unwrap();
+        assert_eq!(
+            hint.range,
+            Range::new(Position::new(1, 0), Position::new(1, 5))
+        );
+
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: hint.range,
+            context: CodeActionContext {
+                diagnostics: vec![hint.clone()],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = &actions[..] else {
+            panic!("{actions:?}");
+        };
+        assert_eq!(action.title, "Add `derive` feature");
+        let edit = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri][0];
+        let fixed = dry_run::apply(&backend.source(text), std::slice::from_ref(edit)).unwrap();
+        let (manifest, _) = manifest::parse(&fixed);
+        let (_, _, serde) = manifest.dependencies().next().unwrap();
+        assert_eq!(
+            serde.detail().unwrap().features.as_ref().unwrap().get_ref()[0].get_ref(),
+            "derive"
+        );
+
+        // Completing `features` pre-fills the hinted ones
+        let text = "[dependencies]\nserde = { version = \"1\",  }\n";
+        open(backend, &uri, text).await;
+        let params = CompletionParams {
+            text_document_position: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(1, 25),
+            ),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let Some(CompletionResponse::Array(items)) = backend.completion(params).await.unwrap()
+        else {
+            panic!("no completions");
+        };
+        let features = items.iter().find(|i| i.label == "features").unwrap();
+        let Some(lsp_types::CompletionTextEdit::Edit(edit)) = &features.text_edit else {
+            panic!("{features:?}");
+        };
+        assert!(
+            edit.new_text.starts_with("features = [\"derive\""),
+            "{edit:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_edits_that_fail_their_dry_run() {
+        use futures::StreamExt;
+
+        let versions = vec!["1.0.0".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]));
+        let (service, mut socket) = socket_service(Shared::new(Arc::new(registry)));
+        let (logs, mut errors) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                let Some(params) = request.params() else {
+                    continue;
+                };
+                if request.method() == "window/logMessage" && params["type"] == 1 {
+                    let _ = logs.send(params["message"].as_str().unwrap_or_default().to_string());
+                }
+            }
+        });
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"0.9\"\n";
+        open(backend, &uri, text).await;
+
+        // A fix of an older text misses the quote
+        let stale = lsp_types::Diagnostic {
+            range: Range::new(Position::new(1, 0), Position::new(1, 5)),
+            code: Some(NumberOrString::String(workspace::PREFER_WORKSPACE.into())),
+            data: serde_json::to_value(TextEdit {
+                range: Range::new(Position::new(1, 9), Position::new(1, 13)),
+                new_text: "{ workspace = true }".into(),
+            })
+            .ok(),
+            ..Default::default()
+        };
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: stale.range,
+            context: CodeActionContext {
+                diagnostics: vec![stale],
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = backend.code_action(params).await.unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = &actions[..] else {
+            panic!("{actions:?}");
+        };
+        assert_eq!(action.edit, None);
+        assert!(action.disabled.is_some());
+        assert_eq!(
+            errors.recv().await.unwrap(),
+            "Rejected an edit of file:///app/Cargo.toml: The edited manifest does not parse: \
+             invalid basic string\nBefore:\nserde = \"0.9\"\nAfter:\nserde = \"{ workspace = true }"
+        );
+
+        // Commands fail instead of applying the edit
+        let update = UpdateArgs {
+            uri: uri.clone(),
+            range: Range::new(Position::new(1, 8), Position::new(1, 12)),
+            from: "0.9".into(),
+            version: "1.0.0".into(),
+        };
+        let err = backend
+            .execute_command(ExecuteCommandParams {
+                command: analysis::APPLY_UPDATE.into(),
+                arguments: vec![serde_json::to_value(update).unwrap()],
+                work_done_progress_params: Default::default(),
+            })
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("was rejected"), "{err:?}");
+        assert!(errors
+            .recv()
+            .await
+            .unwrap()
+            .contains("After:\nserde = 1.0.0\""));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn disables_unreadable_lockfiles_once() {
+        use futures::StreamExt;
+        use std::os::unix::fs::PermissionsExt;
+
+        let versions = vec!["1.0.100".to_string(), "1.0.200".to_string()];
+        let registry = registry::MockClient::new(HashMap::from([("serde".into(), versions)]));
+        let (service, mut socket) = socket_service(Shared::new(Arc::new(registry)));
+        let (logs, mut received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = socket.next().await {
+                let Some(params) = request.params() else {
+                    continue;
+                };
+                if request.method() == "window/logMessage" {
+                    let _ = logs.send(params["message"].as_str().unwrap_or_default().to_string());
+                }
+            }
+        });
+        let backend = service.inner();
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-snapshot-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let lock_path = root.join("Cargo.lock");
+        let lock = "version = 3\n\n[[package]]\nname = \"serde\"\nversion = \"1.0.100\"\n\
+                    source = \"registry+https://github.com/rust-lang/crates.io-index\"\n";
+        std::fs::write(&lock_path, lock).unwrap();
+        let unreadable = std::fs::Permissions::from_mode(0o000);
+        std::fs::set_permissions(&lock_path, unreadable).unwrap();
+        if std::fs::read(&lock_path).is_ok() {
+            // Permissions do not stop root, a directory in its place fails to read as well
+            std::fs::remove_file(&lock_path).unwrap();
+            std::fs::create_dir(&lock_path).unwrap();
+        }
+
+        let uri = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        let text = "[dependencies]\nserde = \"1\"\n";
+        open(backend, &uri, text).await;
+        let hints = || async {
+            let analyses = backend.analyses.read().await;
+            analyses[&uri]
+                .diagnostics
+                .iter()
+                .filter(|d| d.code == Some(NumberOrString::String(lockfile::LOCK_BEHIND.into())))
+                .count()
+        };
+        for _ in 0..10 {
+            backend
+                .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+                .await;
+            assert_eq!(hints().await, 0);
+        }
+        // Readable again, but nothing tells the server
+        let _ = std::fs::remove_dir(&lock_path);
+        std::fs::write(&lock_path, lock).unwrap();
+        std::fs::set_permissions(&lock_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        assert_eq!(hints().await, 0);
+        backend.client.flush().await;
+        let mut messages = Vec::new();
+        while let Ok(Some(message)) =
+            tokio::time::timeout(Duration::from_millis(100), received.recv()).await
+        {
+            messages.push(message);
+        }
+        let folder = root.display();
+        let lockfile_messages = messages
+            .iter()
+            .filter(|m| m.contains("Cargo.lock"))
+            .collect::<Vec<_>>();
+        assert_eq!(lockfile_messages.len(), 1, "{messages:?}");
+        assert!(
+            lockfile_messages[0].starts_with(&format!(
+                "Disabled Cargo.lock hints in {folder}: {}: ",
+                lock_path.display()
+            )),
+            "{}",
+            lockfile_messages[0]
+        );
+        assert!(lockfile_messages[0]
+            .ends_with("Enabled again when the file changes or by `crates-io.refresh`"));
+
+        // The watcher reports the change
+        let changed = DidChangeWatchedFilesParams {
+            changes: vec![lsp_types::FileEvent {
+                uri: Uri::from_file_path(&lock_path).unwrap(),
+                typ: FileChangeType::CHANGED,
+            }],
+        };
+        backend.did_change_watched_files(changed).await;
+        assert_eq!(hints().await, 1);
+        backend.client.flush().await;
+        assert_eq!(
+            received.recv().await.unwrap(),
+            format!("Enabled Cargo.lock hints in {folder} again")
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn compares_locked_checksums_with_the_registry() {
+        // The checksum of serde 1.0.100 is all ones
+        let serde = HashMap::from([("serde".to_string(), vec!["1.0.100".to_string()])]);
+        let registry =
+            registry::MockClient::new(serde).with_checksum("serde", "1.0.100", &"1".repeat(64));
+        let service = shared_service(Shared::new(Arc::new(registry)));
+        let backend = service.inner();
+        let config = Config {
+            show_checksums: true,
+            ..Default::default()
+        };
+        backend.config.send_replace(Arc::new(config));
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-checksums-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let lock_path = root.join("Cargo.lock");
+        let lock = |checksum: &str| {
+            format!(
+                "version = 3\n\n[[package]]\nname = \"serde\"\nversion = \"1.0.100\"\n\
+                 source = \"registry+https://github.com/rust-lang/crates.io-index\"\n\
+                 checksum = \"{checksum}\"\n"
+            )
+        };
+        // A corrupted lockfile
+        std::fs::write(&lock_path, lock(&"0".repeat(64))).unwrap();
+
+        let uri = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        let text = "[dependencies]\nserde = \"1\"\n";
+        open(backend, &uri, text).await;
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        let mismatches = || async {
+            let analyses = backend.analyses.read().await;
+            analyses[&uri]
+                .diagnostics
+                .iter()
+                .filter(|d| {
+                    d.code == Some(NumberOrString::String(lockfile::CHECKSUM_MISMATCH.into()))
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let found = mismatches().await;
+        let [mismatch] = &found[..] else {
+            panic!("{found:?}");
+        };
+        assert_eq!(
+            mismatch.severity,
+            Some(lsp_types::DiagnosticSeverity::ERROR)
+        );
+        assert_eq!(
+            mismatch.range,
+            Range::new(Position::new(1, 9), Position::new(1, 10))
+        );
+        assert!(
+            mismatch.message.contains("(000000000000…)"),
+            "{}",
+            mismatch.message
+        );
+
+        // The hover of the name shows the checksum of Cargo.lock
+        let params = HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(1, 1),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        let HoverContents::Markup(hover) = backend.hover(params).await.unwrap().unwrap().contents
+        else {
+            panic!("no markup");
+        };
+        assert!(
+            hover.value.contains(&format!(
+                "checksum `000000000000…`\n\n```\n{}\n```",
+                "0".repeat(64)
+            )),
+            "{}",
+            hover.value
+        );
+
+        // A matching lockfile is silent
+        std::fs::write(&lock_path, lock(&"1".repeat(64))).unwrap();
+        backend.lockfiles.lock().await.remove(&lock_path);
+        backend.relock(&lock_path).await;
+        assert!(mismatches().await.is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sources_name_the_category() {
+        let service = delayed_service(&[("serde", 0)]);
+        let backend = service.inner();
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\n\
+            serde = { version = \"1\", optionl = true }\n\n\
+            [features]\na = [\"b\"]\nb = [\"a\"]\n";
+        let uri = &uri;
+        let sources = |legacy_source| async move {
+            let config = Config {
+                package_lints: true,
+                legacy_source,
+                ..Config::clone(&backend.config.borrow())
+            };
+            let analysis = analyze(backend, uri, &config, text).await.unwrap().unwrap();
+            analysis
+                .diagnostics
+                .iter()
+                .map(|d| {
+                    let Some(NumberOrString::String(code)) = &d.code else {
+                        panic!("{d:?}");
+                    };
+                    (d.range.start.line, code.clone(), d.source.clone().unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = [
+            (0, "package-missing-field", "crates-io.package"),
+            (0, "package-recommended-field", "crates-io.package"),
+            (4, "major-available", "crates-io.versions"),
+            (4, "outdated-incompatible", "crates-io.versions"),
+            (4, "unknown-key", "crates-io.style"),
+            (7, "feature-cycle", "crates-io.features"),
+            (8, "feature-cycle", "crates-io.features"),
+        ];
+        let owned = |legacy: bool| {
+            expected
+                .iter()
+                .map(|(line, code, source)| {
+                    let source = if legacy { "crates-io" } else { source };
+                    (*line, code.to_string(), source.to_string())
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sources(false).await, owned(false));
+        assert_eq!(sources(true).await, owned(true));
+    }
+
+    #[tokio::test]
+    async fn clients_share_the_cache() {
+        let versions = vec!["1.0.0".to_string(), "2.0.0".to_string()];
+        let crates = HashMap::from([
+            ("serde".into(), versions.clone()),
+            ("rand".into(), versions),
+        ]);
+        let registry = Arc::new(registry::MockClient::new(crates).recording());
+        let shared = Shared::new(registry.clone());
+        let first = shared_service(shared.clone());
+        let second = shared_service(shared);
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let text = "[dependencies]\nserde = \"1\"\n";
+        for service in [&first, &second] {
+            let backend = service.inner();
+            open(backend, &uri, text).await;
+            backend
+                .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+                .await;
+            let codes = stored_codes(backend, &uri).await;
+            assert_eq!(
+                codes,
+                [
+                    (1, "major-available".into()),
+                    (1, "outdated-incompatible".into())
+                ]
+            );
+            // The second client is answered from the cache, the versions and the feature
+            // maps of the hinted serde
+            assert_eq!(registry.requests().len(), 2);
+        }
+
+        // The first client disconnects, the second continues with its own documents
+        first.inner().shutdown.cancel();
+        first.inner().open_docs.clear();
+        let backend = second.inner();
+        assert!(backend.open_docs.contains(&uri));
+        let text = "[dependencies]\nserde = \"1\"\nrand = \"2\"\n";
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        let codes = stored_codes(backend, &uri).await;
+        assert_eq!(
+            codes,
+            [
+                (1, "major-available".into()),
+                (1, "outdated-incompatible".into()),
+                (2, "latest".into())
+            ]
+        );
+        assert_eq!(registry.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn clients_keep_the_shared_limits() {
+        let shared = Shared::new(Arc::new(registry::MockClient::new(HashMap::new())));
+        let registry = shared.registry.read().await.clone();
+        let args = Args {
+            listen: Some("127.0.0.1:0".into()),
+            ..clock_service_args()
+        };
+        let (first, _) = lsp_service(args.clone(), shared.clone(), Arc::new(TokioClock));
+        let (second, _) = lsp_service(args, shared.clone(), Arc::new(TokioClock));
+        let limits = serde_json::json!({
+            "cacheCapacity": 1,
+            "cacheBytes": 1,
+            "minCacheTtl": 1,
+            "maxCacheTtl": 1,
+            "fetchConcurrency": 1,
+            "maxIndexBytes": 1,
+        });
+        first.inner().update_config(limits.clone()).await;
+        second.inner().update_config(Value::Null).await;
+
+        let defaults = Config::default();
+        let cache = shared.cache.read().await;
+        assert_eq!(cache.capacity, defaults.cache_capacity);
+        assert_eq!(cache.max_bytes, defaults.cache_bytes);
+        assert_eq!(cache.ttl.min, defaults.min_cache_ttl);
+        assert_eq!(cache.ttl.max, defaults.max_cache_ttl);
+        drop(cache);
+        assert!(Arc::ptr_eq(&*shared.registry.read().await, &registry));
+        let config = first.inner().config.borrow().clone();
+        assert_eq!(config.fetch_concurrency, defaults.fetch_concurrency);
+        assert_eq!(config.max_index_bytes, defaults.max_index_bytes);
+
+        // A single client on stdio applies its own limits
+        let service = shared_service(shared.clone());
+        service.inner().update_config(limits).await;
+        assert_eq!(shared.cache.read().await.capacity, 1);
+        assert!(!Arc::ptr_eq(&*shared.registry.read().await, &registry));
+    }
+
+    #[tokio::test]
+    async fn evicts_the_details_with_the_versions() {
+        let versions = vec!["1.0.0".to_string()];
+        let crates = HashMap::from([
+            ("serde".into(), versions.clone()),
+            ("rand".into(), versions),
+        ]);
+        let service = shared_service(Shared::new(Arc::new(registry::MockClient::new(crates))));
+        let backend = service.inner();
+        backend.cache.write().await.capacity = 1;
+        let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+        let config = Config::default();
+        analyze(backend, &uri, &config, "[dependencies]\nserde = \"1\"\n")
+            .await
+            .unwrap();
+        for name in ["serde", "rand"] {
+            let checksums = HashMap::from([("1.0.0".into(), "abc".into())]);
+            backend
+                .checksums
+                .write()
+                .await
+                .insert(name.into(), checksums);
+            let maps = suggest::FeatureMaps::new();
+            backend.feature_maps.write().await.insert(name.into(), maps);
+        }
+
+        analyze(backend, &uri, &config, "[dependencies]\nrand = \"1\"\n")
+            .await
+            .unwrap();
+        assert!(backend.cache.read().await.get("serde").is_none());
+        let checksums = backend.checksums.read().await;
+        assert_eq!(checksums.keys().collect::<Vec<_>>(), ["rand"]);
+        let maps = backend.feature_maps.read().await;
+        assert_eq!(maps.keys().collect::<Vec<_>>(), ["rand"]);
+    }
+
+    #[tokio::test]
+    async fn updates_the_lock_hints_when_the_lockfile_changes() {
+        let versions = vec!["1.0.100".to_string(), "1.0.200".to_string()];
+        let crates = HashMap::from([("serde".into(), versions)]);
+        let registry = Arc::new(registry::MockClient::new(crates).recording());
+        let service = shared_service(Shared::new(registry.clone()));
+        let backend = service.inner();
+
+        let root =
+            std::env::temp_dir().join(format!("crates-io-lsp-relock-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let lock_path = root.join("Cargo.lock");
+        let lock = |version: &str| {
+            format!(
+                "version = 3\n\n[[package]]\nname = \"serde\"\nversion = \"{version}\"\n\
+                 source = \"registry+https://github.com/rust-lang/crates.io-index\"\n"
+            )
+        };
+        std::fs::write(&lock_path, lock("1.0.100")).unwrap();
+        let uri = Uri::from_file_path(root.join("Cargo.toml")).unwrap();
+        let text = "[dependencies]\nserde = \"1\"\n";
+        open(backend, &uri, text).await;
+        backend
+            .update_diagnostics(&uri, None, &backend.source(text), Priority::Interactive)
+            .await;
+        let hints = || async {
+            let analyses = backend.analyses.read().await;
+            analyses[&uri]
+                .diagnostics
+                .iter()
+                .filter(|d| d.code == Some(NumberOrString::String(lockfile::LOCK_BEHIND.into())))
+                .map(|d| (d.range, d.message.clone()))
+                .collect::<Vec<_>>()
+        };
+        let requirement = Range::new(Position::new(1, 9), Position::new(1, 10));
+        assert_eq!(
+            hints().await,
+            [(
+                requirement,
+                "Cargo.lock has serde 1.0.100, `cargo update -p serde` updates it to 1.0.200"
+                    .to_string()
+            )]
+        );
+        // The versions and the feature maps of the hinted serde
+        assert_eq!(registry.requests().len(), 2);
+
+        let changed = |typ| DidChangeWatchedFilesParams {
+            changes: vec![lsp_types::FileEvent {
+                uri: Uri::from_file_path(&lock_path).unwrap(),
+                typ,
+            }],
+        };
+        // `cargo update` in a terminal
+        std::fs::write(&lock_path, lock("1.0.200")).unwrap();
+        backend
+            .did_change_watched_files(changed(FileChangeType::CHANGED))
+            .await;
+        assert_eq!(hints().await, []);
+
+        std::fs::write(&lock_path, lock("1.0.100")).unwrap();
+        backend
+            .did_change_watched_files(changed(FileChangeType::CHANGED))
+            .await;
+        assert_eq!(hints().await.len(), 1);
+
+        // Without a lockfile there is nothing to compare with
+        std::fs::remove_file(&lock_path).unwrap();
+        backend
+            .did_change_watched_files(changed(FileChangeType::DELETED))
+            .await;
+        assert_eq!(hints().await, []);
+        // The other diagnostics are kept, all from the cache
+        let analyses = backend.analyses.read().await;
+        assert!(!analyses[&uri].diagnostics.is_empty());
+        assert_eq!(registry.requests().len(), 2);
+        drop(analyses);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    proptest::proptest! {
+        #![proptest_config(proptest::test_runner::Config::with_cases(32))]
+
+        #[test]
+        fn never_requests_invalid_names(
+            names in proptest::collection::vec("\\PC{0,12}", 1..6),
+            req in "\\PC{0,12}",
+            raw in "\\PC{0,60}",
+        ) {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async {
+                let endpoint = registry(&["1.0.0"], None).await;
+                let service = service(&endpoint);
+                let backend = service.inner();
+                let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+                let config = backend.config.borrow().clone();
+
+                // Arbitrary text is rejected or analyzed, never a panic
+                let _ = analyze(backend, &uri, &config, &raw).await;
+
+                let req = serde_json::to_string(&req).unwrap();
+                let entries = names
+                    .iter()
+                    .map(|name| format!("{} = {req}\n", serde_json::to_string(name).unwrap()))
+                    .collect::<String>();
+                let text = format!("[package]\nname = \"app\"\n\n[dependencies]\n{entries}");
+                let analysis = analyze(backend, &uri, &config, &text).await.unwrap().unwrap();
+                let parse_error = Some(NumberOrString::String("parse-error".into()));
+                if analysis.diagnostics.iter().any(|d| d.code == parse_error) {
+                    return; // Duplicate keys
+                }
+                let requested = backend.references.read().await.get(&uri).unwrap().clone();
+                assert!(requested.iter().all(|name| api::is_valid_name(name)));
+                for name in names.iter().filter(|name| !api::is_valid_name(name)) {
+                    assert!(!requested.contains(name));
+                    assert!(analysis.diagnostics.iter().any(|d| {
+                        d.code == Some(NumberOrString::String("invalid-crate-name".into()))
+                            && d.message == format!("Invalid crate name {name:?}")
+                    }));
+                }
+            });
+        }
+
+        #[test]
+        fn publishes_identical_diagnostics(
+            delays in proptest::collection::vec((0u64..20, 0u64..20), 5),
+        ) {
+            let names = ["serde", "rand", "log", "libc", "missing"];
+            let text = "[package]\nname = \"app\"\n\n[dependencies]\n\
+                serde = \"1.0.0\"\nrand = \"0.8\"\nlog = \"*\"\nlibc = \"9\"\nmissing = \"1\"\n\n\
+                [dev-dependencies]\nserde = \"1.0.0\"\nrand = \"0.9\"\n";
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let payloads = [0, 1].map(|run| {
+                runtime.block_on(async {
+                    // The same fetch times in both runs
+                    let clock = Arc::new(TestClock::default());
+                    let (service, _) = clock_service(Shared::new(Arc::new(registry::MockClient::new(HashMap::new()))), clock);
+                    let backend = service.inner();
+                    let versions = ["0.8.5", "0.9.0", "1.0.0", "1.0.1"].map(String::from).to_vec();
+                    let crates = names[..4].iter().map(|n| (n.to_string(), versions.clone()));
+                    let client = names.iter().zip(&delays).fold(
+                        registry::MockClient::new(crates.collect()),
+                        |client, (name, d)| {
+                            let delay = if run == 0 { d.0 } else { d.1 };
+                            client.with_latency(name, Duration::from_millis(delay))
+                        },
+                    );
+                    *backend.registry.write().await = Arc::new(client);
+
+                    let uri: Uri = "file:///app/Cargo.toml".parse().unwrap();
+                    let config = backend.config.borrow().clone();
+                    let analysis = analyze(backend, &uri, &config, text).await.unwrap().unwrap();
+                    serde_json::to_string(&analysis.diagnostics).unwrap()
+                })
+            });
+            proptest::prop_assert_eq!(&payloads[0], &payloads[1]);
+        }
+    }
 }
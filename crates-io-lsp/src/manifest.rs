@@ -0,0 +1,1129 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+use serde::de::{self, value::MapAccessDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use toml::Spanned;
+
+use crate::keys;
+
+/// The parts of a manifest we analyze, with the locations of the keys.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SpannedManifest {
+    /// Unstable features enabled with `cargo-features = ["..."]`
+    pub cargo_features: Vec<Spanned<String>>,
+    pub package: Option<Spanned<SpannedPackage>>,
+    pub dependencies: BTreeMap<Spanned<String>, Dependency>,
+    pub build_dependencies: BTreeMap<Spanned<String>, Dependency>,
+    pub dev_dependencies: BTreeMap<Spanned<String>, Dependency>,
+    pub workspace: Option<SpannedWorkspace>,
+    /// Platform specific dependencies by `cfg` or target triple
+    pub target: BTreeMap<String, SpannedTarget>,
+    /// Profiles by name
+    pub profile: BTreeMap<String, SpannedProfile>,
+    pub features: BTreeMap<Spanned<String>, Vec<Spanned<String>>>,
+    pub lints: Option<SpannedLints>,
+    /// Declarations that are only analyzed in part, see [`parse`]
+    #[serde(skip)]
+    pub notices: Vec<Notice>,
+}
+
+/// A declaration the analysis only models in part, with the span of its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notice {
+    pub span: Range<usize>,
+    pub reason: Unsupported,
+}
+
+/// Why a declaration of the dependency `name` is only analyzed in part.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unsupported {
+    /// Neither a requirement nor a table, but a TOML value of type `found`, it is dropped
+    Dependency { name: String, found: &'static str },
+    /// The value of `key` is a TOML value of type `found` that is not understood,
+    /// the dependency is analyzed without it
+    Value {
+        name: String,
+        key: String,
+        found: &'static str,
+    },
+    /// `key` is valid, but not analyzed
+    IgnoredKey { name: String, key: String },
+    /// `workspace = false`, which cargo rejects, it is analyzed as if the key was missing
+    NotInherited { name: String },
+    /// Inherited, but the workspace root is missing or does not declare it
+    Unresolved { name: String },
+}
+
+/// A `[profile.name]` table, only the package overrides are of interest.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SpannedProfile {
+    /// Overrides by package name, `"*"` applies to all dependencies
+    pub package: BTreeMap<Spanned<String>, toml::Table>,
+}
+
+/// The `[lints]` table of a package.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SpannedLints {
+    /// `workspace = true` inherits `[workspace.lints]`
+    pub workspace: Option<Spanned<bool>>,
+}
+
+/// Dependencies of a `[target.'cfg(...)']` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SpannedTarget {
+    pub dependencies: BTreeMap<Spanned<String>, Dependency>,
+    pub build_dependencies: BTreeMap<Spanned<String>, Dependency>,
+    pub dev_dependencies: BTreeMap<Spanned<String>, Dependency>,
+}
+
+/// The `[workspace]` table, the only table of a virtual manifest.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SpannedWorkspace {
+    pub members: Vec<Spanned<String>>,
+    pub exclude: Vec<Spanned<String>>,
+    pub default_members: Vec<Spanned<String>>,
+    pub dependencies: BTreeMap<Spanned<String>, Dependency>,
+    /// Defaults inherited by the members (`field.workspace = true`)
+    pub package: Option<WorkspacePackage>,
+    pub lints: Option<toml::Table>,
+    pub resolver: Option<Spanned<String>>,
+}
+
+/// The `[workspace.package]` table with the spans of its keys.
+pub type WorkspacePackage = BTreeMap<Spanned<String>, toml::Value>;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SpannedPackage {
+    pub name: Option<Spanned<String>>,
+    pub version: Option<Spanned<Inheritable<String>>>,
+    pub description: Option<Spanned<Inheritable<String>>>,
+    pub license: Option<Spanned<Inheritable<String>>>,
+    pub license_file: Option<Spanned<Inheritable<String>>>,
+    pub repository: Option<Spanned<Inheritable<String>>>,
+    pub publish: Option<Spanned<Inheritable<Publish>>>,
+    pub edition: Option<Spanned<Inheritable<String>>>,
+    pub rust_version: Option<Spanned<Inheritable<String>>>,
+    pub resolver: Option<Spanned<String>>,
+    /// Name of the native library, `links = "ssl"`
+    pub links: Option<Spanned<String>>,
+    /// Path of the build script, or `false` to disable `build.rs`
+    pub build: Option<Spanned<Build>>,
+}
+
+/// Package field that might be inherited from the workspace (`field.workspace = true`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Inheritable<T> {
+    Value(T),
+    Workspace { workspace: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Publish {
+    Flag(bool),
+    Registries(Vec<String>),
+}
+
+/// The `build` field of a package.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Build {
+    Flag(bool),
+    Path(String),
+}
+
+/// Kind of a dependency section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DependencyKind {
+    Normal,
+    Dev,
+    Build,
+    /// `[workspace.dependencies]`
+    Workspace,
+}
+
+impl DependencyKind {
+    pub const ALL: [DependencyKind; 4] = [
+        DependencyKind::Normal,
+        DependencyKind::Dev,
+        DependencyKind::Build,
+        DependencyKind::Workspace,
+    ];
+
+    /// Short name, as used by `cargo tree`.
+    pub fn name(self) -> &'static str {
+        match self {
+            DependencyKind::Normal => "normal",
+            DependencyKind::Dev => "dev",
+            DependencyKind::Build => "build",
+            DependencyKind::Workspace => "workspace",
+        }
+    }
+
+    /// Name of the section table.
+    pub fn section(self) -> &'static str {
+        match self {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Dev => "dev-dependencies",
+            DependencyKind::Build => "build-dependencies",
+            DependencyKind::Workspace => "workspace.dependencies",
+        }
+    }
+
+    /// Name of the section table for a platform, like `target.'cfg(unix)'.dependencies`.
+    pub fn target_section(self, platform: &str) -> String {
+        let bare = platform
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if bare {
+            format!("target.{platform}.{}", self.section())
+        } else {
+            format!("target.'{platform}'.{}", self.section())
+        }
+    }
+
+    /// Kind and platform of a section table like `dev-dependencies` or
+    /// `target.'cfg(unix)'.dependencies`, with or without the quotes of the platform.
+    pub fn of_section(section: &str) -> Option<(DependencyKind, Option<&str>)> {
+        if let Some(kind) = Self::ALL.into_iter().find(|k| k.section() == section) {
+            return Some((kind, None));
+        }
+        let target = section.strip_prefix("target.")?;
+        [Self::Normal, Self::Dev, Self::Build]
+            .into_iter()
+            .find_map(|kind| {
+                let platform = target.strip_suffix(kind.section())?.strip_suffix('.')?;
+                Some((kind, Some(platform.trim_matches(['\'', '"']))))
+            })
+            .filter(|(_, platform)| platform.is_some_and(|p| !p.is_empty()))
+    }
+}
+
+impl SpannedManifest {
+    /// All dependencies with the kind of their section.
+    pub fn dependencies(
+        &self,
+    ) -> impl Iterator<Item = (DependencyKind, &Spanned<String>, &Dependency)> {
+        section(DependencyKind::Normal, &self.dependencies)
+            .chain(section(DependencyKind::Build, &self.build_dependencies))
+            .chain(section(DependencyKind::Dev, &self.dev_dependencies))
+            .chain(
+                self.workspace
+                    .iter()
+                    .flat_map(|w| section(DependencyKind::Workspace, &w.dependencies)),
+            )
+    }
+
+    /// Dependencies of the `[target]` tables with their platform.
+    pub fn target_dependencies(
+        &self,
+    ) -> impl Iterator<Item = (&str, DependencyKind, &Spanned<String>, &Dependency)> {
+        self.target.iter().flat_map(|(platform, target)| {
+            section(DependencyKind::Normal, &target.dependencies)
+                .chain(section(DependencyKind::Build, &target.build_dependencies))
+                .chain(section(DependencyKind::Dev, &target.dev_dependencies))
+                .map(move |(kind, name, dep)| (platform.as_str(), kind, name, dep))
+        })
+    }
+}
+
+fn section(
+    kind: DependencyKind,
+    deps: &BTreeMap<Spanned<String>, Dependency>,
+) -> impl Iterator<Item = (DependencyKind, &Spanned<String>, &Dependency)> {
+    deps.iter().map(move |(name, dep)| (kind, name, dep))
+}
+
+/// Dependency declaration with the location of its value.
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    /// Span of the whole value
+    pub span: Range<usize>,
+    pub value: DependencyValue,
+}
+
+#[derive(Debug, Clone)]
+pub enum DependencyValue {
+    /// `name = "1.0"`
+    Simple(String),
+    /// `name = { version = "1.0" }` or `[dependencies.name]`
+    Detailed(Box<DependencyDetail>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DependencyDetail {
+    pub version: Option<Spanned<String>>,
+    pub registry: Option<String>,
+    pub registry_index: Option<String>,
+    pub path: Option<String>,
+    pub git: Option<String>,
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    pub rev: Option<String>,
+    pub features: Option<Spanned<Vec<Spanned<String>>>>,
+    pub optional: Option<Spanned<bool>>,
+    #[serde(alias = "default_features")]
+    pub default_features: Option<Spanned<bool>>,
+    pub package: Option<Spanned<String>>,
+    pub workspace: Option<Spanned<bool>>,
+    /// Artifacts of an artifact dependency, like `artifact = "bin"`
+    pub artifact: Option<Spanned<Artifact>>,
+    /// Also depend on the library of an artifact dependency
+    pub lib: Option<Spanned<bool>>,
+    /// Target triple the artifacts are built for
+    pub target: Option<Spanned<String>>,
+}
+
+/// The `artifact` of a dependency, a single kind or a list of kinds.
+#[derive(Debug, Clone)]
+pub enum Artifact {
+    Single(String),
+    List(Vec<Spanned<String>>),
+}
+
+impl DependencyDetail {
+    /// Artifact kinds with the spans of their strings, including the quotes.
+    pub fn artifacts(&self) -> Vec<(&str, Range<usize>)> {
+        let Some(artifact) = &self.artifact else {
+            return Vec::new();
+        };
+        match artifact.get_ref() {
+            Artifact::Single(kind) => vec![(kind.as_str(), artifact.span())],
+            Artifact::List(kinds) => kinds
+                .iter()
+                .map(|kind| (kind.get_ref().as_str(), kind.span()))
+                .collect(),
+        }
+    }
+}
+
+impl Dependency {
+    pub fn detail(&self) -> Option<&DependencyDetail> {
+        match &self.value {
+            DependencyValue::Simple(_) => None,
+            DependencyValue::Detailed(detail) => Some(detail),
+        }
+    }
+
+    /// Version requirement, `*` if there is none.
+    pub fn req(&self) -> &str {
+        match &self.value {
+            DependencyValue::Simple(req) => req,
+            DependencyValue::Detailed(detail) => detail
+                .version
+                .as_ref()
+                .map_or("*", |v| v.get_ref().as_str()),
+        }
+    }
+
+    /// Span of the version requirement string, including the quotes.
+    pub fn req_span(&self) -> Option<Range<usize>> {
+        match &self.value {
+            DependencyValue::Simple(_) => Some(self.span.clone()),
+            DependencyValue::Detailed(detail) => detail.version.as_ref().map(|v| v.span()),
+        }
+    }
+
+    /// Whether the dependency is inherited from the workspace (`workspace = true`).
+    pub fn inherited(&self) -> bool {
+        self.detail()
+            .and_then(|d| d.workspace.as_ref())
+            .is_some_and(|w| *w.get_ref())
+    }
+
+    /// Name of the crate in the registry, if renamed.
+    pub fn package(&self) -> Option<&str> {
+        self.detail()?
+            .package
+            .as_ref()
+            .map(|p| p.get_ref().as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Dependency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Spanned::<DependencyValue>::deserialize(deserializer)?;
+        Ok(Self {
+            span: value.span(),
+            value: value.into_inner(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for DependencyValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = DependencyValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a version string or a dependency table")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(DependencyValue::Simple(v.into()))
+            }
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                // Forward the map to keep the spans of the fields
+                let detail = DependencyDetail::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(DependencyValue::Detailed(Box::new(detail)))
+            }
+        }
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Artifact {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ArtifactVisitor;
+        impl<'de> Visitor<'de> for ArtifactVisitor {
+            type Value = Artifact;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an artifact kind or a list of kinds")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(Artifact::Single(v.into()))
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut kinds = Vec::new();
+                while let Some(kind) = seq.next_element()? {
+                    kinds.push(kind);
+                }
+                Ok(Artifact::List(kinds))
+            }
+        }
+        deserializer.deserialize_any(ArtifactVisitor)
+    }
+}
+
+/// Whether the manifest might declare dependencies, a cheap check that avoids parsing it.
+///
+/// Looks for the words of the dependency tables, `[patch]`, and `[replace]` outside of comments
+/// and multi-line strings, which are never keys. Single-line strings might be quoted keys and
+/// are searched as well. Never misses a dependency, but mentioning one of the words is enough.
+pub fn may_declare_dependencies(text: &str) -> bool {
+    const WORDS: [&str; 3] = ["dependencies", "patch", "replace"];
+    let mentions = |s: &str| WORDS.iter().any(|w| s.contains(w));
+    let bytes = text.as_bytes();
+    // Index after the closing `quote` of the string starting at `start`, stopping at line ends
+    // for single-line strings, `None` if it is unterminated
+    let close = |start: usize, quote: &[u8], multiline: bool| {
+        let mut i = start;
+        while i < bytes.len() {
+            if bytes[i..].starts_with(quote) {
+                return Some(i + quote.len());
+            }
+            match bytes[i] {
+                b'\\' if quote[0] == b'"' => i += 1,
+                b'\n' if !multiline => return Some(i),
+                _ => {}
+            }
+            i += 1;
+        }
+        None
+    };
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        if rest[0] == b'#' {
+            i = close(i, b"\n", false).unwrap_or(bytes.len());
+        } else if rest.starts_with(b"\"\"\"") || rest.starts_with(b"'''") {
+            match close(i + 3, &rest[..3], true) {
+                Some(end) => i = end,
+                None => return true, // Unterminated, better safe than sorry
+            }
+        } else if rest[0] == b'"' || rest[0] == b'\'' {
+            let end = close(i + 1, &rest[..1], false).unwrap_or(bytes.len());
+            let string = &text[i + 1..end.max(i + 1)];
+            // Might be a quoted key, maybe spelled with escapes
+            if mentions(string) || (rest[0] == b'"' && string.contains('\\')) {
+                return true;
+            }
+            i = end;
+        } else if WORDS.iter().any(|w| rest.starts_with(w.as_bytes())) {
+            return true;
+        } else {
+            i += 1;
+        }
+    }
+    false
+}
+
+/// Parse a manifest, recovering the sections that are valid on their own.
+///
+/// The strict parse is tried first, its error is returned alongside the recovered manifest.
+/// Dependencies and their keys with values of other types than the analysis understands are
+/// dropped first, then broken sections are blanked out, so the spans match the original text.
+/// The [`Notice`]s of the manifest list what was dropped or ignored in the valid sections.
+///
+/// The spans of the dependency keys cover their content without quotes, see [`unquote_keys`].
+pub fn parse(text: &str) -> (SpannedManifest, Option<toml::de::Error>) {
+    let text = &inline_workspace(text);
+    let (mut manifest, err) = parse_sections(text);
+    unquote_keys(text, &mut manifest);
+    (manifest, err)
+}
+
+fn parse_sections(text: &str) -> (SpannedManifest, Option<toml::de::Error>) {
+    let err = match toml::from_str::<SpannedManifest>(text) {
+        Ok(mut manifest) => {
+            manifest.notices = notices(text, keys::unsupported(text));
+            return (manifest, None);
+        }
+        Err(err) => err,
+    };
+    let text = &repair_mistyped(text);
+    // The sections one by one, the others may be invalid TOML
+    let unsupported = sections(text)
+        .into_iter()
+        .flat_map(|section| keys::unsupported(&blank(text, std::slice::from_ref(&section))))
+        .collect::<Vec<_>>();
+    let dropped = unsupported
+        .iter()
+        .filter_map(|(_, drop)| drop.clone())
+        .map(|drop| with_separator(text, drop))
+        .collect::<Vec<_>>();
+    let text = &erase(text, &dropped);
+    let valid = sections(text)
+        .into_iter()
+        .filter(|section| {
+            let section = blank(text, std::slice::from_ref(section));
+            toml::from_str::<SpannedManifest>(&section).is_ok()
+        })
+        .collect::<Vec<_>>();
+    let mut manifest: SpannedManifest = toml::from_str(&blank(text, &valid)).unwrap_or_default();
+    let unsupported = unsupported
+        .into_iter()
+        .filter(|(notice, _)| valid.iter().any(|v| v.contains(&notice.span.start)));
+    manifest.notices = notices(text, unsupported);
+    (manifest, Some(err))
+}
+
+/// The notices of [`keys::unsupported`], with the spans of the keys without quotes.
+fn notices(
+    text: &str,
+    unsupported: impl IntoIterator<Item = (Notice, Option<Range<usize>>)>,
+) -> Vec<Notice> {
+    unsupported
+        .into_iter()
+        .map(|(notice, _)| Notice {
+            span: key_content(text, notice.span),
+            ..notice
+        })
+        .collect()
+}
+
+/// `span` of a key-value pair with the comma separating it from the others of an inline table.
+fn with_separator(text: &str, span: Range<usize>) -> Range<usize> {
+    let after = &text[span.end..];
+    let trimmed = after.trim_start_matches([' ', '\t']);
+    if trimmed.starts_with(',') {
+        return span.start..text.len() - trimmed.len() + 1;
+    }
+    let before = text[..span.start].trim_end_matches([' ', '\t']);
+    match before.strip_suffix(',') {
+        Some(rest) => rest.len()..span.end,
+        None => span,
+    }
+}
+
+/// Replaces the [`keys::mistyped`] values with valid ones of the same length,
+/// so they do not fail the rest of their section.
+///
+/// Booleans are kept, a feature list becomes an empty array.
+fn repair_mistyped(text: &str) -> Cow<'_, str> {
+    let mistyped = keys::mistyped(text);
+    if mistyped.is_empty() {
+        return Cow::Borrowed(text);
+    }
+    let mut text = text.to_string();
+    for m in mistyped {
+        let len = m.value.len();
+        let value = match (m.expected, m.fix) {
+            (_, Some(fix)) if fix.len() <= len => format!("{fix:len$}"),
+            (keys::Value::Array, _) => format!("[{}]", " ".repeat(len - 2)),
+            _ => continue,
+        };
+        text.replace_range(m.value, &value);
+    }
+    Cow::Owned(text)
+}
+
+/// Narrows the spans of quoted dependency keys to their content, like `my.dep` of `'my.dep'`,
+/// so that they start and end like bare keys.
+///
+/// Escapes are kept in the span, the key itself is unescaped by the parser.
+fn unquote_keys(text: &str, manifest: &mut SpannedManifest) {
+    let unquote = |deps: &mut BTreeMap<Spanned<String>, Dependency>| {
+        *deps = std::mem::take(deps)
+            .into_iter()
+            .map(|(name, dep)| {
+                let span = key_content(text, name.span());
+                (Spanned::new(span, name.into_inner()), dep)
+            })
+            .collect();
+    };
+    let targets = manifest.target.values_mut();
+    let sections = [
+        &mut manifest.dependencies,
+        &mut manifest.build_dependencies,
+        &mut manifest.dev_dependencies,
+    ]
+    .into_iter()
+    .chain(manifest.workspace.iter_mut().map(|w| &mut w.dependencies))
+    .chain(targets.flat_map(|t| {
+        [
+            &mut t.dependencies,
+            &mut t.build_dependencies,
+            &mut t.dev_dependencies,
+        ]
+    }));
+    for deps in sections {
+        unquote(deps);
+    }
+}
+
+/// Span of the content of the key at `span`, without the quotes of a quoted key.
+fn key_content(text: &str, span: Range<usize>) -> Range<usize> {
+    match text.get(span.clone()).map(str::as_bytes) {
+        Some([open @ (b'"' | b'\''), .., close]) if open == close => span.start + 1..span.end - 1,
+        _ => span,
+    }
+}
+
+/// Span of a dependency key including its quotes, for edits of the whole entry.
+pub fn quoted_key(text: &str, span: Range<usize>) -> Range<usize> {
+    let before = span
+        .start
+        .checked_sub(1)
+        .and_then(|i| text.get(i..span.start));
+    match (before, text.get(span.end..span.end + 1)) {
+        (Some(open @ ("\"" | "'")), Some(close)) if open == close => span.start - 1..span.end + 1,
+        _ => span,
+    }
+}
+
+/// Rewrites `key.workspace = true` to the equivalent `key={workspace=true}` of the same length.
+///
+/// `Spanned` values fail to deserialize from dotted keys
+/// (`invalid type: string "workspace", expected a borrowed string`).
+/// Lines without the whitespace to pad the braces are kept.
+fn inline_workspace(text: &str) -> Cow<'_, str> {
+    const DOTTED: &str = ".workspace";
+    const INLINE: &str = "={workspace=true";
+    if !text.contains(DOTTED) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let rewritten = line.find(DOTTED).and_then(|dot| {
+            let key = line[..dot].trim_start();
+            let bare = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+            if key.is_empty() || !key.chars().all(bare) {
+                return None;
+            }
+            let rest = line[dot + DOTTED.len()..].trim_start();
+            let rest = rest.strip_prefix('=')?.trim_start().strip_prefix("true")?;
+            let len = line.len() - dot - rest.len();
+            // At least one space pads the closing brace
+            let padding = len.checked_sub(INLINE.len() + 1)?;
+            Some(format!(
+                "{}{INLINE}{}}}{rest}",
+                &line[..dot],
+                " ".repeat(padding)
+            ))
+        });
+        out += rewritten.as_deref().unwrap_or(line);
+    }
+    Cow::Owned(out)
+}
+
+/// Replaces everything outside of `keep` with spaces, preserving the offsets and lines.
+fn blank(text: &str, keep: &[Range<usize>]) -> String {
+    text.char_indices()
+        .flat_map(|(i, c)| {
+            let blank = !keep.iter().any(|k| k.contains(&i)) && c != '\n' && c != '\r';
+            let len = if blank { c.len_utf8() } else { 1 };
+            std::iter::repeat_n(if blank { ' ' } else { c }, len)
+        })
+        .collect()
+}
+
+/// Replaces everything within `drop` with spaces, preserving the offsets and lines.
+fn erase(text: &str, drop: &[Range<usize>]) -> String {
+    text.char_indices()
+        .flat_map(|(i, c)| {
+            let erase = drop.iter().any(|d| d.contains(&i)) && c != '\n' && c != '\r';
+            let len = if erase { c.len_utf8() } else { 1 };
+            std::iter::repeat_n(if erase { ' ' } else { c }, len)
+        })
+        .collect()
+}
+
+/// Ranges of the top-level sections: the root table and each `[table]` or `[[array]]`.
+pub fn sections(text: &str) -> Vec<Range<usize>> {
+    let mut starts = headers(text)
+        .into_iter()
+        .map(|(_, span, _)| text[..span.start].rfind('\n').map_or(0, |i| i + 1))
+        .collect::<Vec<_>>();
+    starts.insert(0, 0);
+    starts.dedup();
+    let ends = starts.iter().skip(1).copied().chain([text.len()]);
+    starts.iter().zip(ends).map(|(&s, e)| s..e).collect()
+}
+
+/// Table headers (`[name]`) of the document with their spans.
+pub fn table_headers(text: &str) -> Vec<(String, Range<usize>)> {
+    headers(text)
+        .into_iter()
+        .filter(|(_, _, array)| !array)
+        .map(|(name, span, _)| (name, span))
+        .collect()
+}
+
+/// Table and array (`[[name]]`) headers.
+///
+/// A line based scan, skipping multi-line strings.
+pub(crate) fn headers(text: &str) -> Vec<(String, Range<usize>, bool)> {
+    let mut headers = Vec::new();
+    let mut in_string = false;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let toggles = line.matches("\"\"\"").count() + line.matches("\'\'\'").count();
+        if in_string {
+            in_string = toggles % 2 == 0;
+            continue;
+        }
+        in_string = toggles % 2 == 1;
+
+        let trimmed = line.trim_start();
+        if !trimmed.starts_with('[') {
+            continue;
+        }
+        let array = trimmed.starts_with("[[");
+        let (open, close) = if array { ("[[", "]]") } else { ("[", "]") };
+        let Some(end) = trimmed.find(close) else {
+            continue;
+        };
+        let name = trimmed[open.len()..end]
+            .split('.')
+            .map(|k| k.trim().trim_matches(['"', '\'']))
+            .collect::<Vec<_>>()
+            .join(".");
+        let header_start = start + (line.len() - trimmed.len());
+        headers.push((name, header_start..header_start + end + close.len(), array));
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Name, requirement, and span of each dependency.
+    fn summary(manifest: &SpannedManifest) -> Vec<(DependencyKind, String, String, Range<usize>)> {
+        manifest
+            .dependencies()
+            .map(|(kind, name, dep)| {
+                let req = dep.req().to_string();
+                (kind, name.get_ref().clone(), req, dep.span.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn kinds_of_sections() {
+        for kind in DependencyKind::ALL {
+            assert_eq!(
+                DependencyKind::of_section(kind.section()),
+                Some((kind, None))
+            );
+        }
+        for platform in ["cfg(unix)", "x86_64-pc-windows-msvc"] {
+            for kind in [DependencyKind::Normal, DependencyKind::Dev] {
+                let section = kind.target_section(platform);
+                assert_eq!(
+                    DependencyKind::of_section(&section),
+                    Some((kind, Some(platform))),
+                    "{section}"
+                );
+            }
+        }
+        // Like the unquoted table headers
+        assert_eq!(
+            DependencyKind::of_section("target.cfg(unix).build-dependencies"),
+            Some((DependencyKind::Build, Some("cfg(unix)")))
+        );
+        for section in [
+            "package",
+            "target.dependencies",
+            "target.x.features",
+            "dependencies.serde",
+        ] {
+            assert_eq!(DependencyKind::of_section(section), None, "{section}");
+        }
+    }
+
+    #[test]
+    fn strict_parse_of_valid_manifests() {
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1.0\"\n";
+        let (manifest, err) = parse(text);
+        assert!(err.is_none());
+        assert_eq!(
+            summary(&manifest),
+            [(DependencyKind::Normal, "serde".into(), "1.0".into(), 47..52)]
+        );
+    }
+
+    #[test]
+    fn spans_of_quoted_keys_cover_their_content() {
+        let text = "[dependencies]\n\"weird-name\" = \"1.0\"\n\
+            'my.dep' = { version = \"0.2\", package = \"my-dep\" }\n\"esc\\u0061pe\" = \"1\"\n\n\
+            [dev-dependencies.'x.y']\nversion = \"3\"\n";
+        let (manifest, err) = parse(text);
+        assert!(err.is_none());
+        let keys: Vec<_> = manifest
+            .dependencies()
+            .map(|(_, name, dep)| {
+                let package = dep.package().unwrap_or(name.get_ref()).to_string();
+                (name.get_ref().clone(), name.span(), package)
+            })
+            .collect();
+        let at = |key: &str| text.find(key).unwrap()..text.find(key).unwrap() + key.len();
+        assert_eq!(
+            keys,
+            [
+                ("escape".into(), at("esc\\u0061pe"), "escape".into()),
+                ("my.dep".into(), at("my.dep"), "my-dep".into()),
+                ("weird-name".into(), at("weird-name"), "weird-name".into()),
+                ("x.y".into(), at("x.y"), "x.y".into()),
+            ]
+        );
+        // Whole-entry edits still see the quotes
+        for (_, span, _) in &keys {
+            let quoted = quoted_key(text, span.clone());
+            assert_eq!(quoted, span.start - 1..span.end + 1);
+        }
+        let bare = "[dependencies]\nserde = \"1\"\n";
+        assert_eq!(quoted_key(bare, 15..20), 15..20);
+    }
+
+    #[test]
+    fn never_misses_a_dependency_section() {
+        let declaring = [
+            "[dependencies]\nserde = \"1\"\n",
+            "[ dependencies ]\nserde = \"1\"\n",
+            "[package]\r\nname = \"app\"\r\n\r\n[dev-dependencies]\r\nserde = \"1\"\r\n",
+            "[build-dependencies.cc]\nversion = \"1\"\n",
+            "[target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n",
+            "[workspace]\ndependencies.serde = \"1\"\n",
+            "dependencies = { serde = \"1\" }\n",
+            "[\"dependencies\"]\nserde = \"1\"\n",
+            "[\"dep\\u0065ndencies\"]\nserde = \"1\"\n",
+            "[package]\ndescription = \"\"\"\\\"\"\"\"\"\n[dependencies]\nserde = \"1\"\n",
+        ];
+        for text in declaring {
+            assert!(may_declare_dependencies(text), "{text}");
+            // Not a false positive
+            let (manifest, err) = parse(text);
+            assert!(err.is_none(), "{text}");
+            let count = manifest.dependencies().count() + manifest.target_dependencies().count();
+            assert_eq!(count, 1, "{text}");
+        }
+        for text in [
+            "[patch.crates-io]\nserde = { git = \"x\" }\n",
+            "[replace]\n",
+        ] {
+            assert!(may_declare_dependencies(text), "{text}");
+        }
+
+        let without = [
+            "",
+            "[package]\nname = \"app\"\n",
+            "# [dependencies]\r\n[package]\r\nname = \"app\" # [dependencies]\r\n",
+            "[package]\ndescription = \"\"\"\n[dependencies]\nserde = \"1\"\n\"\"\"\n",
+            "[package]\ndescription = '''\n[dependencies]\n'''\nname = \"app\"\n",
+            "[package]\nname = 'app'\nversion = \"0.1.0\"\n[features]\ndefault = []\n",
+        ];
+        for text in without {
+            assert!(!may_declare_dependencies(text), "{text}");
+        }
+        // Mentions and broken strings are false positives
+        assert!(may_declare_dependencies(
+            "[package]\ndescription = \"Free of dependencies\"\n"
+        ));
+        assert!(may_declare_dependencies(
+            "[package]\ndescription = \"\"\"\nunterminated\n"
+        ));
+    }
+
+    #[test]
+    fn recovers_from_errors_in_other_sections() {
+        let text = "[package]\nname = \"app\"\n\n\
+            [dependencies]\nserde = \"1.0.188\"\nrand = { version = \"0.8\" }\n\n\
+            [profile.release]\nlto = \n\n\
+            [dev-dependencies]\ntokio = \"1\"\n";
+        let (manifest, err) = parse(text);
+        let err = err.unwrap();
+        let profile = text.find("lto").unwrap();
+        assert!(err.span().unwrap().start >= profile);
+
+        // The same spans as the strict parse without the broken section
+        let valid = text.replace("lto = \n", "      \n");
+        let (strict, none) = parse(&valid);
+        assert!(none.is_none());
+        assert_eq!(summary(&manifest), summary(&strict));
+        assert_eq!(summary(&manifest).len(), 3);
+        assert_eq!(
+            manifest
+                .package
+                .unwrap()
+                .get_ref()
+                .name
+                .as_ref()
+                .unwrap()
+                .span(),
+            17..22
+        );
+    }
+
+    #[test]
+    fn artifact_kinds_with_spans() {
+        let text = "cargo-features = [\"bindeps\"]\n\n[dependencies]\n\
+            tool = { version = \"1\", artifact = \"bin\", target = \"wasm32-unknown-unknown\" }\n\
+            native = { version = \"2\", artifact = [\"cdylib\", \"bin:gen\"], lib = true }\n";
+        let (manifest, err) = parse(text);
+        assert!(err.is_none());
+        assert_eq!(manifest.cargo_features[0].get_ref(), "bindeps");
+        let artifacts = |name: &str| {
+            let dep = &manifest.dependencies[name];
+            dep.detail()
+                .unwrap()
+                .artifacts()
+                .into_iter()
+                .map(|(kind, span)| (kind.to_string(), &text[span]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(artifacts("tool"), [("bin".into(), "\"bin\"")]);
+        assert_eq!(
+            artifacts("native"),
+            [
+                ("cdylib".into(), "\"cdylib\""),
+                ("bin:gen".into(), "\"bin:gen\"")
+            ]
+        );
+        // Still registry crates with a version
+        assert_eq!(manifest.dependencies["native"].req(), "2");
+    }
+
+    #[test]
+    fn recovers_from_errors_in_dependencies() {
+        let text = "[package]\nname = \"app\"\n\n\
+            [dependencies]\nserde = \"1.0.188\"\nrand = { version = \"0.8\"\n\n\
+            [dev-dependencies]\ntokio = \"1\"\n";
+        let (manifest, err) = parse(text);
+        let err = err.unwrap();
+        let broken = text.find("rand").unwrap();
+        let span = err.span().unwrap();
+        assert!(broken <= span.start && span.start < text.find("[dev").unwrap());
+
+        let tokio = text.find("\"1\"").unwrap();
+        assert_eq!(
+            summary(&manifest),
+            [(
+                DependencyKind::Dev,
+                "tokio".into(),
+                "1".into(),
+                tokio..tokio + 3
+            )]
+        );
+        assert!(manifest.package.is_some());
+    }
+
+    #[test]
+    fn recovers_sections_with_mistyped_values() {
+        let text = "[dependencies]\nserde = { version = \"1\", features = \"derive\" }\n\
+            rand = { version = \"0.8\", default-features = \"false\" }\n";
+        let (manifest, err) = parse(text);
+        assert!(err.is_some());
+        let names = summary(&manifest)
+            .into_iter()
+            .map(|(_, name, req, _)| (name, req))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [("rand".into(), "0.8".into()), ("serde".into(), "1".into())]
+        );
+        let rand = manifest.dependencies.values().next().unwrap();
+        let default_features = rand.detail().unwrap().default_features.as_ref().unwrap();
+        assert!(!default_features.get_ref());
+        // The spans are kept
+        let value = text.find("\"false\"").unwrap();
+        assert_eq!(default_features.span(), value..value + 5);
+    }
+
+    #[test]
+    fn notices_what_is_partially_analyzed() {
+        let text = "[package]\nname = \"app\"\n\n\
+            [dependencies]\n\
+            serde = \"1\"\n\
+            tokio = { version = \"1\", public = true }\n\
+            rand = { version = 0.8, features = [\"small_rng\"] }\n\
+            bad = 1\n\
+            \"local\" = { workspace = false }\n\n\
+            [dependencies.regex]\nversion = \"1\"\noptional = \"maybe\"\n\n\
+            [dev-dependencies]\nbroken = { version = \"1\"\n";
+        let (manifest, err) = parse(text);
+        assert!(err.is_some());
+        let notices = manifest
+            .notices
+            .iter()
+            .map(|n| (&text[n.span.clone()], n.reason.clone()))
+            .collect::<Vec<_>>();
+        let name = |name: &str| name.to_string();
+        assert_eq!(
+            notices,
+            [
+                (
+                    "public",
+                    Unsupported::IgnoredKey {
+                        name: name("tokio"),
+                        key: name("public")
+                    }
+                ),
+                (
+                    "version",
+                    Unsupported::Value {
+                        name: name("rand"),
+                        key: name("version"),
+                        found: "float"
+                    }
+                ),
+                (
+                    "bad",
+                    Unsupported::Dependency {
+                        name: name("bad"),
+                        found: "integer"
+                    }
+                ),
+                (
+                    "workspace",
+                    Unsupported::NotInherited {
+                        name: name("local")
+                    }
+                ),
+                (
+                    "optional",
+                    Unsupported::Value {
+                        name: name("regex"),
+                        key: name("optional"),
+                        found: "string"
+                    }
+                ),
+            ]
+        );
+
+        // The rest of the section is analyzed, the invalid TOML of the next one is not
+        let reqs = summary(&manifest)
+            .into_iter()
+            .map(|(kind, name, req, _)| (kind, name, req))
+            .collect::<Vec<_>>();
+        let normal = |name: &str, req: &str| (DependencyKind::Normal, name.into(), req.into());
+        assert_eq!(
+            reqs,
+            [
+                normal("local", "*"),
+                normal("rand", "*"),
+                normal("regex", "1"),
+                normal("serde", "1"),
+                normal("tokio", "1"),
+            ]
+        );
+        let rand = manifest.dependencies["rand"].detail().unwrap();
+        assert_eq!(rand.features.as_ref().unwrap().get_ref().len(), 1);
+        assert!(manifest.dependencies["regex"]
+            .detail()
+            .unwrap()
+            .optional
+            .is_none());
+
+        // Valid manifests only have notices of ignored keys
+        let (manifest, err) = parse("[dependencies]\ntokio = { version = \"1\", public = true }\n");
+        assert!(err.is_none());
+        assert_eq!(manifest.notices.len(), 1);
+        assert!(parse("[dependencies]\nserde = \"1\"\n")
+            .0
+            .notices
+            .is_empty());
+    }
+
+    #[test]
+    fn erases_pairs_of_inline_tables() {
+        let text = "a = { x = 1, y = 2 }";
+        let x = text.find('x').unwrap();
+        let y = text.find('y').unwrap();
+        assert_eq!(with_separator(text, x..x + 5), x..x + 6);
+        assert_eq!(with_separator(text, y..y + 5), x + 5..y + 5);
+        assert_eq!(
+            erase(text, std::slice::from_ref(&(x..x + 6))),
+            "a = {        y = 2 }"
+        );
+        // Tables of their own have no separators
+        let text = "[a]\nx = 1\n";
+        assert_eq!(with_separator(text, 4..9), 4..9);
+    }
+
+    #[test]
+    fn keeps_offsets_when_blanking() {
+        let text = "a = 1\r\n[b]\nc = \"é\"\n";
+        let blanked = blank(text, std::slice::from_ref(&(7..11)));
+        assert_eq!(blanked.len(), text.len());
+        assert_eq!(blanked, "     \r\n[b]\n        \n");
+    }
+
+    #[test]
+    fn splits_sections() {
+        let text = "cargo-features = []\n[package]\nname = \"a\"\n[[bin]]\nname = \"b\"\n  [dependencies]\n";
+        assert_eq!(sections(text), [0..20, 20..41, 41..60, 60..text.len()]);
+        let headers = table_headers(text);
+        let names = headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["package", "dependencies"], "without arrays");
+        assert_eq!(headers[1].1, 62..76);
+    }
+
+    #[test]
+    fn parses_dotted_workspace_keys() {
+        let text = "[package]\nname = \"a\"\nversion.workspace = true\nedition.workspace=true\n";
+        let inlined = inline_workspace(text);
+        assert_eq!(inlined.len(), text.len());
+        assert_eq!(
+            inlined, "[package]\nname = \"a\"\nversion={workspace=true}\nedition.workspace=true\n",
+            "without padding the line is kept"
+        );
+
+        let text = "[package]\nname = \"a\"\nversion.workspace = true # shared\n";
+        let (manifest, err) = parse(text);
+        assert!(err.is_none());
+        let version = manifest.package.unwrap().into_inner().version.unwrap();
+        assert!(matches!(version.get_ref(), Inheritable::Workspace { .. }));
+        let start = text.find("version").unwrap();
+        // From the brace replacing the dot to the end of `true`
+        assert_eq!(version.span(), start + 8..start + 24);
+    }
+}
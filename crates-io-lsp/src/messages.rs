@@ -0,0 +1,1107 @@
+use std::fmt::Display;
+
+use tower_lsp_server::lsp_types::DiagnosticSeverity;
+
+use crate::config::category;
+
+/// Language of the diagnostic messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+}
+
+impl Locale {
+    /// Supported locale of a language tag like `de-CH`.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.split(['-', '_']).next()?.to_ascii_lowercase();
+        match language.as_str() {
+            "en" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            _ => None,
+        }
+    }
+
+    /// The locale of the client if supported, then the configured one, then English.
+    pub fn negotiate(client: Option<&str>, configured: Option<&str>) -> Self {
+        client
+            .and_then(Locale::from_tag)
+            .or_else(|| configured.and_then(Locale::from_tag))
+            .unwrap_or_default()
+    }
+
+    /// Message templates, `{0}`, `{1}`, ... are replaced by the arguments.
+    fn catalog(self) -> &'static [(Key, &'static str)] {
+        match self {
+            Locale::En => EN,
+            Locale::De => DE,
+        }
+    }
+
+    /// Templates replacing those of [`Self::catalog`] with `messageAccessibility`.
+    fn accessible_catalog(self) -> &'static [(Key, &'static str)] {
+        match self {
+            Locale::En => ACCESSIBLE_EN,
+            Locale::De => ACCESSIBLE_DE,
+        }
+    }
+}
+
+/// How the messages are rendered, in which locale and whether for screen readers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Language {
+    pub locale: Locale,
+    /// Plain language with `messageAccessibility`: versions are described in words
+    /// instead of symbols and lists, and diagnostics name their severity and topic
+    pub accessible: bool,
+}
+
+impl From<Locale> for Language {
+    fn from(locale: Locale) -> Self {
+        Self {
+            locale,
+            accessible: false,
+        }
+    }
+}
+
+/// Keys of the diagnostic messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Any,
+    Latest,
+    /// `{0}` compatible version
+    OutdatedCompatible,
+    /// `{0}` newest version, `{1}` compatible version
+    OutdatedIncompatible,
+    Unknown,
+    /// `{0}` crate
+    FetchFailed,
+    /// `{0}` crate
+    FetchPending,
+    /// `{0}` quoted crate name
+    InvalidCrateName,
+    /// `{0}` quoted member pattern
+    MissingMember,
+    /// Quoted path of the `default-members` entry
+    ExcludedDefaultMember,
+    /// `{0}` dependency
+    PreferWorkspace,
+    WorkspaceDependency,
+    /// `{0}` list of fields
+    PackageMissingField,
+    /// `{0}` field
+    PackageRecommendedField,
+    /// `{0}` reason, `{1}` term
+    InvalidLicense,
+    /// `{0}` error
+    InvalidConfig,
+    /// `{0}` quoted setting
+    UnknownSetting,
+    /// `{0}` quoted setting, `{1}` quoted suggestion
+    UnknownSettingSuggestion,
+    /// `{0}` quoted setting, `{1}` expected value
+    MistypedSetting,
+    MistypedSettings,
+    /// `{0}` list of problems
+    InvalidSettings,
+    /// `{0}` failed endpoint, `{1}` error, `{2}` serving endpoint
+    RegistryFailedOver,
+    /// `{0}` recovered endpoint
+    RegistryRecovered,
+    ExpectedBool,
+    ExpectedInteger,
+    ExpectedNumber,
+    ExpectedString,
+    /// `{0}` list of values
+    ExpectedOneOf,
+    ExpectedStrings,
+    ExpectedTable,
+    /// `{0}` quoted key
+    UnknownField,
+    /// `{0}` quoted key, `{1}` quoted suggestion
+    UnknownFieldSuggestion,
+    /// `{0}` quoted key, `{1}` quoted key with hyphens
+    UnderscoreField,
+    /// `{0}` quoted key, `{1}` cargo feature
+    UnstableField,
+    /// `{0}` quoted feature
+    UnknownCargoFeature,
+    /// `{0}` quoted feature, `{1}` quoted suggestion
+    UnknownCargoFeatureSuggestion,
+    /// `{0}` quoted feature, `{1}` Rust version
+    StabilizedCargoFeature,
+    /// `{0}` quoted package
+    UnknownProfilePackage,
+    MissingWorkspaceLints,
+    ResolverFeatures,
+    WorkspaceResolver,
+    /// `{0}` quoted key
+    MissingWorkspaceField,
+    WorkspaceRoot,
+    /// `{0}` pre-release, `{1}` stable release
+    PrereleaseRequirement,
+    /// `{0}` requirement of the newest minor
+    ZeroMajorRequirement,
+    /// `{0}` quoted crate name
+    ShadowedPackage,
+    /// `{0}` quoted crate name
+    ShadowedMember,
+    WorkspaceMember,
+    /// `{0}` section, `{1}` quoted crate name, `{2}` version
+    ConflictingRequirements,
+    /// `{0}` section, `{1}` quoted crate name, `{2}` version
+    HeldBackRequirement,
+    /// `{0}` quoted feature, `{1}` the features of the cycle
+    FeatureCycle,
+    InternalError,
+    ArtifactDependency,
+    /// `{0}` quoted artifact kind
+    InvalidArtifact,
+    /// `{0}` quoted key
+    ArtifactKeyWithoutArtifact,
+    /// `{0}` quoted library
+    LinksWithoutBuild,
+    /// `{0}` quoted crate name, `{1}` years since the last release
+    StaleCrate,
+    /// `{0}` newest version, `{1}` line of the requirement
+    MajorAvailable,
+    /// `{0}` newest version, `{1}` line of the requirement, `{2}` compatible update
+    MajorAvailableCompatible,
+    /// `{0}` quoted dependency
+    DevOnlyDependency,
+    /// `{0}` crate name, `{1}` locked version, `{2}` newest matching version
+    LockBehind,
+    /// `{0}` crate name, `{1}` locked version, `{2}` checksum of the lockfile, `{3}` of the registry
+    ChecksumMismatch,
+    /// `{0}` quoted crate name, `{1}` quoted feature, `{2}` reason after a comma or nothing
+    MissingFeature,
+    /// `{0}` quoted crate name, `{1}` quoted features, `{2}` reason after a comma or nothing
+    MissingFeatures,
+    /// `{0}` number of versions
+    OlderVersionsOmitted,
+    /// `{0}` number of versions
+    YankedVersionsOmitted,
+    /// `{0}` quoted key
+    ArrayExpected,
+    /// `{0}` quoted key
+    BoolExpected,
+    DevDependency,
+    BuildDependency,
+    /// Of a platform
+    NormalDependency,
+    /// `{0}` kind of dependency, `{1}` platform
+    TargetDependency,
+    PartiallyAnalyzedOne,
+    /// `{0}` number of notices
+    PartiallyAnalyzed,
+    /// `{0}` quoted dependency, `{1}` TOML type
+    NoticeDependency,
+    /// `{0}` quoted dependency, `{1}` quoted key, `{2}` TOML type
+    NoticeValue,
+    /// `{0}` quoted dependency, `{1}` quoted key
+    NoticeIgnored,
+    /// `{0}` quoted dependency
+    NoticeNotInherited,
+    /// `{0}` quoted dependency
+    NoticeUnresolved,
+    /// `{0}` outdated, `{1}` unknown, and `{2}` up to date dependencies of a section
+    SectionCounts,
+    /// `{0}` newest version
+    UpdateToLatest,
+    /// `{0}` newest version, of a new major
+    UpdateToMajor,
+    /// `{0}` series, `{1}` newest version of the series
+    UpdateToSeries,
+    /// `{0}` crate name, `{1}` matched version, `{2}` newest version
+    ShowChangelog,
+    /// `{0}` requirement, `{1}` new version
+    LensUpdate,
+    /// `{0}` age of the cached versions, `{1}` time between their refreshes
+    RegistryFreshness,
+    /// Metadata of the dependency is fetched in the background
+    MetadataPending,
+    /// `{0}` estimated time until the metadata is fetched
+    MetadataPendingHover,
+    SeverityError,
+    SeverityWarning,
+    SeverityInformation,
+    SeverityHint,
+    /// `{0}` severity, `{1}` topic, only rendered with `messageAccessibility`
+    DiagnosticPrefix,
+    /// `{0}` label, `{1}` line, `{2}` file, only rendered with `messageAccessibility`
+    RelatedLocation,
+    TopicUpToDate,
+    TopicAny,
+    TopicOutdated,
+    TopicMajor,
+    TopicRequirement,
+    TopicRegistry,
+    TopicLockfile,
+    TopicMaintenance,
+    TopicFeatures,
+    TopicManifest,
+    TopicConfig,
+    TopicStyle,
+    TopicInternal,
+}
+
+const EN: &[(Key, &str)] = &[
+    (Key::Any, "Matches any Version"),
+    (Key::Latest, "Latest Version"),
+    (
+        Key::OutdatedCompatible,
+        "Outdated Version, compatible update to {0} (cargo update)",
+    ),
+    (
+        Key::OutdatedIncompatible,
+        "Outdated Version, {0} requires a manifest change\nNewest compatible: {1}",
+    ),
+    (Key::Unknown, "Unknown Version"),
+    (Key::FetchFailed, "Failed to fetch versions for {0}"),
+    (Key::FetchPending, "Still fetching versions of {0}…"),
+    (Key::InvalidCrateName, "Invalid crate name {0}"),
+    (
+        Key::MissingMember,
+        "No directory matches the workspace member {0}",
+    ),
+    (
+        Key::ExcludedDefaultMember,
+        "The default member {0} is excluded from the workspace, cargo rejects it",
+    ),
+    (
+        Key::PreferWorkspace,
+        "{0} is declared in [workspace.dependencies], use `workspace = true` to avoid version skew",
+    ),
+    (Key::WorkspaceDependency, "Workspace dependency"),
+    (
+        Key::PackageMissingField,
+        "Missing fields required for publishing: {0}",
+    ),
+    (
+        Key::PackageRecommendedField,
+        "Missing recommended field: {0}",
+    ),
+    (
+        Key::InvalidLicense,
+        "Invalid SPDX license expression: {0} `{1}`",
+    ),
+    (
+        Key::InvalidConfig,
+        "Invalid configuration, it is ignored: {0}",
+    ),
+    (Key::UnknownSetting, "Unknown setting {0}, it is ignored"),
+    (
+        Key::UnknownSettingSuggestion,
+        "Unknown setting {0}, did you mean {1}?",
+    ),
+    (Key::MistypedSetting, "{0} must be {1}, it is ignored"),
+    (
+        Key::MistypedSettings,
+        "The settings must be a table, they are ignored",
+    ),
+    (Key::InvalidSettings, "Invalid settings of crates-io:\n{0}"),
+    (
+        Key::RegistryFailedOver,
+        "The registry {0} fails ({1}), the versions are fetched from {2} until it recovers",
+    ),
+    (
+        Key::RegistryRecovered,
+        "The registry {0} recovered, the versions are fetched from it again",
+    ),
+    (Key::ExpectedBool, "`true` or `false`"),
+    (Key::ExpectedInteger, "a non-negative integer"),
+    (Key::ExpectedNumber, "a number"),
+    (Key::ExpectedString, "a string"),
+    (Key::ExpectedOneOf, "one of {0}"),
+    (Key::ExpectedStrings, "an array of strings"),
+    (Key::ExpectedTable, "a table"),
+    (Key::UnknownField, "Unknown key {0}, cargo ignores it"),
+    (
+        Key::UnknownFieldSuggestion,
+        "Unknown key {0}, did you mean {1}?",
+    ),
+    (
+        Key::UnstableField,
+        "{0} is unstable and requires `cargo-features = [\"{1}\"]`, cargo ignores it otherwise",
+    ),
+    (
+        Key::UnknownCargoFeature,
+        "Unknown cargo feature {0}, cargo rejects the manifest",
+    ),
+    (
+        Key::UnknownCargoFeatureSuggestion,
+        "Unknown cargo feature {0}, did you mean {1}?",
+    ),
+    (
+        Key::StabilizedCargoFeature,
+        "{0} is stable since Rust {1}, it can be removed from `cargo-features`",
+    ),
+    (
+        Key::UnderscoreField,
+        "{0} is deprecated and rejected by the 2024 edition, use {1}",
+    ),
+    (
+        Key::UnknownProfilePackage,
+        "{0} is not a dependency of this manifest or workspace, the override only applies if it is a transitive dependency",
+    ),
+    (
+        Key::MissingWorkspaceLints,
+        "There is no [workspace.lints] table to inherit from",
+    ),
+    (
+        Key::ResolverFeatures,
+        "Features use `dep:` or `?/` syntax, which needs resolver \"2\" to behave as intended, but the workspace uses resolver \"1\"",
+    ),
+    (Key::WorkspaceResolver, "Resolver of the workspace"),
+    (
+        Key::MissingWorkspaceField,
+        "{0} is inherited, but [workspace.package] of the workspace root does not define it",
+    ),
+    (Key::WorkspaceRoot, "Workspace root"),
+    (
+        Key::PrereleaseRequirement,
+        "Requires the pre-release {0}, the stable {1} is available",
+    ),
+    (
+        Key::ZeroMajorRequirement,
+        "Matches all 0.x versions, which are mutually incompatible under semver; pin a minor like {0}",
+    ),
+    (
+        Key::ShadowedPackage,
+        "{0} is the name of this package, cargo uses the crate {0} from the registry",
+    ),
+    (
+        Key::ShadowedMember,
+        "{0} is a workspace member, but without `path` cargo uses the crate {0} from the registry",
+    ),
+    (Key::WorkspaceMember, "Workspace member"),
+    (
+        Key::ConflictingRequirements,
+        "No version of {1} satisfies its requirements in all sections, the one in {0} alone accepts {2}",
+    ),
+    (
+        Key::HeldBackRequirement,
+        "The requirement in {0} holds {1} back to {2}",
+    ),
+    (Key::FeatureCycle, "The feature {0} enables itself: {1}"),
+    (
+        Key::InternalError,
+        "Internal error analyzing this file, see the log of the language server",
+    ),
+    (
+        Key::ArtifactDependency,
+        "Artifact dependencies are unstable, they require nightly cargo with `-Z bindeps` or `cargo-features = [\"bindeps\"]`",
+    ),
+    (
+        Key::InvalidArtifact,
+        "Unknown artifact kind {0}, expected `bin`, `bin:<name>`, `cdylib`, or `staticlib`",
+    ),
+    (
+        Key::ArtifactKeyWithoutArtifact,
+        "{0} is only allowed for artifact dependencies with `artifact`",
+    ),
+    (
+        Key::LinksWithoutBuild,
+        "The package links to {0}, but `build = false` disables its build script",
+    ),
+    (
+        Key::StaleCrate,
+        "{0} may be unmaintained, last release {1} years ago",
+    ),
+    (
+        Key::MajorAvailable,
+        "New major {0} is available, you're on the {1} line",
+    ),
+    (
+        Key::MajorAvailableCompatible,
+        "New major {0} is available, you're on the {1} line (compatible update to {2})",
+    ),
+    (
+        Key::DevOnlyDependency,
+        "{0} is only used by tests, examples, or benchmarks, consider moving it to `[dev-dependencies]`",
+    ),
+    (
+        Key::LockBehind,
+        "Cargo.lock has {0} {1}, `cargo update -p {0}` updates it to {2}",
+    ),
+    (
+        Key::ChecksumMismatch,
+        "The checksum of {0} {1} in Cargo.lock ({2}) differs from the registry ({3}), \
+        either file may have been altered: compare them before building",
+    ),
+    (
+        Key::MissingFeature,
+        "{0} is usually needed with the feature {1}{2}",
+    ),
+    (
+        Key::MissingFeatures,
+        "{0} is usually needed with the features {1}{2}",
+    ),
+    (Key::OlderVersionsOmitted, "… {0} older versions"),
+    (Key::YankedVersionsOmitted, "{0} yanked versions omitted"),
+    (
+        Key::ArrayExpected,
+        "{0} must be an array of strings, like `[\"a\", \"b\"]`",
+    ),
+    (Key::BoolExpected, "{0} must be `true` or `false`, not a string"),
+    (Key::DevDependency, "dev-dependency"),
+    (Key::BuildDependency, "build-dependency"),
+    (Key::NormalDependency, "dependency"),
+    (Key::TargetDependency, "{0} for {1}"),
+    (
+        Key::PartiallyAnalyzedOne,
+        "1 entry partially analyzed, hover for details",
+    ),
+    (
+        Key::PartiallyAnalyzed,
+        "{0} entries partially analyzed, hover for details",
+    ),
+    (
+        Key::NoticeDependency,
+        "{0} is a TOML {1}, neither a version requirement nor a table, it is not analyzed",
+    ),
+    (
+        Key::NoticeValue,
+        "{1} of {0} is a TOML {2} that is not understood, {0} is analyzed without it",
+    ),
+    (Key::NoticeIgnored, "{1} of {0} is not analyzed"),
+    (
+        Key::NoticeNotInherited,
+        "{0} has `workspace = false`, which cargo rejects, it is analyzed without inheriting",
+    ),
+    (
+        Key::NoticeUnresolved,
+        "{0} is inherited, but no [workspace.dependencies] of a workspace root declares it, its version is not checked",
+    ),
+    (
+        Key::SectionCounts,
+        "{0} outdated · {1} unknown · {2} up to date",
+    ),
+    (Key::UpdateToLatest, "Update to latest ({0})"),
+    (Key::UpdateToMajor, "Update to {0} (new major)"),
+    (Key::UpdateToSeries, "Update to latest {0}.x ({1})"),
+    (Key::ShowChangelog, "Show changelog of {0} {1} → {2}"),
+    (Key::LensUpdate, "{0} → {1}"),
+    (
+        Key::RegistryFreshness,
+        "registry data from {0}, refreshing roughly every {1}",
+    ),
+    (Key::MetadataPending, "additional data pending"),
+    (
+        Key::MetadataPendingHover,
+        "additional data pending, expected in about {0}",
+    ),
+    (Key::SeverityError, "Error"),
+    (Key::SeverityWarning, "Warning"),
+    (Key::SeverityInformation, "Information"),
+    (Key::SeverityHint, "Hint"),
+    (Key::DiagnosticPrefix, "{0}, {1}: "),
+    (Key::RelatedLocation, "{0}, at line {1} of {2}"),
+    (Key::TopicUpToDate, "up-to-date dependency"),
+    (Key::TopicAny, "dependency matching any version"),
+    (Key::TopicOutdated, "outdated dependency"),
+    (Key::TopicMajor, "new major release"),
+    (Key::TopicRequirement, "version requirement"),
+    (Key::TopicRegistry, "registry problem"),
+    (Key::TopicLockfile, "lockfile problem"),
+    (Key::TopicMaintenance, "possibly unmaintained crate"),
+    (Key::TopicFeatures, "feature problem"),
+    (Key::TopicManifest, "manifest problem"),
+    (Key::TopicConfig, "configuration problem"),
+    (Key::TopicStyle, "style suggestion"),
+    (Key::TopicInternal, "internal error"),
+];
+
+const DE: &[(Key, &str)] = &[
+    (Key::Any, "Passt auf jede Version"),
+    (Key::Latest, "Neueste Version"),
+    (
+        Key::OutdatedCompatible,
+        "Veraltete Version, kompatibles Update auf {0} (cargo update)",
+    ),
+    (
+        Key::OutdatedIncompatible,
+        "Veraltete Version, {0} erfordert eine Änderung des Manifests\nNeueste kompatible: {1}",
+    ),
+    (Key::Unknown, "Unbekannte Version"),
+    (Key::FetchFailed, "Versionen von {0} konnten nicht abgerufen werden"),
+    (Key::FetchPending, "Versionen von {0} werden noch abgerufen…"),
+    (Key::InvalidCrateName, "Ungültiger Crate-Name {0}"),
+    (
+        Key::MissingMember,
+        "Kein Verzeichnis passt auf das Workspace-Mitglied {0}",
+    ),
+    (
+        Key::ExcludedDefaultMember,
+        "Das Standard-Mitglied {0} ist vom Workspace ausgeschlossen, Cargo lehnt es ab",
+    ),
+    (
+        Key::PreferWorkspace,
+        "{0} ist in [workspace.dependencies] deklariert, `workspace = true` vermeidet abweichende Versionen",
+    ),
+    (Key::WorkspaceDependency, "Workspace-Abhängigkeit"),
+    (
+        Key::PackageMissingField,
+        "Für die Veröffentlichung fehlende Felder: {0}",
+    ),
+    (Key::PackageRecommendedField, "Fehlendes empfohlenes Feld: {0}"),
+    (Key::InvalidLicense, "Ungültiger SPDX-Lizenzausdruck: {0} `{1}`"),
+    (Key::InvalidConfig, "Ungültige Konfiguration, sie wird ignoriert: {0}"),
+    (
+        Key::UnknownSetting,
+        "Unbekannte Einstellung {0}, sie wird ignoriert",
+    ),
+    (
+        Key::UnknownSettingSuggestion,
+        "Unbekannte Einstellung {0}, war {1} gemeint?",
+    ),
+    (
+        Key::MistypedSetting,
+        "{0} muss {1} sein, die Einstellung wird ignoriert",
+    ),
+    (
+        Key::MistypedSettings,
+        "Die Einstellungen müssen eine Tabelle sein, sie werden ignoriert",
+    ),
+    (
+        Key::InvalidSettings,
+        "Ungültige Einstellungen von crates-io:\n{0}",
+    ),
+    (
+        Key::RegistryFailedOver,
+        "Die Registry {0} schlägt fehl ({1}), die Versionen werden bis zu ihrer Erholung von {2} geladen",
+    ),
+    (
+        Key::RegistryRecovered,
+        "Die Registry {0} ist wieder erreichbar, die Versionen werden wieder von ihr geladen",
+    ),
+    (Key::ExpectedBool, "`true` oder `false`"),
+    (Key::ExpectedInteger, "eine nicht-negative ganze Zahl"),
+    (Key::ExpectedNumber, "eine Zahl"),
+    (Key::ExpectedString, "eine Zeichenkette"),
+    (Key::ExpectedOneOf, "einer der Werte {0}"),
+    (Key::ExpectedStrings, "ein Array von Zeichenketten"),
+    (Key::ExpectedTable, "eine Tabelle"),
+    (Key::UnknownField, "Unbekannter Schlüssel {0}, cargo ignoriert ihn"),
+    (Key::UnknownFieldSuggestion, "Unbekannter Schlüssel {0}, war {1} gemeint?"),
+    (
+        Key::UnstableField,
+        "{0} ist instabil und benötigt `cargo-features = [\"{1}\"]`, sonst ignoriert cargo ihn",
+    ),
+    (
+        Key::UnknownCargoFeature,
+        "Unbekanntes Cargo-Feature {0}, cargo lehnt das Manifest ab",
+    ),
+    (
+        Key::UnknownCargoFeatureSuggestion,
+        "Unbekanntes Cargo-Feature {0}, war {1} gemeint?",
+    ),
+    (
+        Key::StabilizedCargoFeature,
+        "{0} ist seit Rust {1} stabil und kann aus `cargo-features` entfernt werden",
+    ),
+    (
+        Key::UnderscoreField,
+        "{0} ist veraltet und wird von der Edition 2024 abgelehnt, {1} verwenden",
+    ),
+    (
+        Key::UnknownProfilePackage,
+        "{0} ist keine Abhängigkeit dieses Manifests oder Workspaces, die Überschreibung gilt nur für eine transitive Abhängigkeit",
+    ),
+    (
+        Key::MissingWorkspaceLints,
+        "Es gibt keine [workspace.lints]-Tabelle zum Erben",
+    ),
+    (
+        Key::ResolverFeatures,
+        "Features verwenden `dep:` oder `?/`, was Resolver \"2\" erfordert, um wie beabsichtigt zu funktionieren, der Workspace verwendet aber Resolver \"1\"",
+    ),
+    (Key::WorkspaceResolver, "Resolver des Workspaces"),
+    (
+        Key::MissingWorkspaceField,
+        "{0} wird geerbt, aber [workspace.package] der Workspace-Wurzel definiert es nicht",
+    ),
+    (Key::WorkspaceRoot, "Workspace-Wurzel"),
+    (
+        Key::PrereleaseRequirement,
+        "Erfordert die Vorabversion {0}, die stabile Version {1} ist verfügbar",
+    ),
+    (
+        Key::ZeroMajorRequirement,
+        "Passt auf alle 0.x-Versionen, die laut semver untereinander inkompatibel sind; eine Minor-Version wie {0} festlegen",
+    ),
+    (
+        Key::ShadowedPackage,
+        "{0} ist der Name dieses Pakets, cargo verwendet das Crate {0} aus der Registry",
+    ),
+    (
+        Key::ShadowedMember,
+        "{0} ist ein Workspace-Mitglied, aber ohne `path` verwendet cargo das Crate {0} aus der Registry",
+    ),
+    (Key::WorkspaceMember, "Workspace-Mitglied"),
+    (
+        Key::ConflictingRequirements,
+        "Keine Version von {1} erfüllt die Anforderungen aller Abschnitte, die in {0} allein akzeptiert {2}",
+    ),
+    (
+        Key::HeldBackRequirement,
+        "Die Anforderung in {0} hält {1} auf {2} zurück",
+    ),
+    (Key::FeatureCycle, "Das Feature {0} aktiviert sich selbst: {1}"),
+    (
+        Key::InternalError,
+        "Interner Fehler bei der Analyse dieser Datei, siehe das Log des Language Servers",
+    ),
+    (
+        Key::ArtifactDependency,
+        "Artefakt-Abhängigkeiten sind instabil, sie benötigen nightly cargo mit `-Z bindeps` oder `cargo-features = [\"bindeps\"]`",
+    ),
+    (
+        Key::InvalidArtifact,
+        "Unbekannte Artefakt-Art {0}, erwartet wird `bin`, `bin:<name>`, `cdylib` oder `staticlib`",
+    ),
+    (
+        Key::ArtifactKeyWithoutArtifact,
+        "{0} ist nur bei Artefakt-Abhängigkeiten mit `artifact` erlaubt",
+    ),
+    (
+        Key::LinksWithoutBuild,
+        "Das Paket bindet {0} an, aber `build = false` deaktiviert sein Build-Skript",
+    ),
+    (
+        Key::StaleCrate,
+        "{0} wird möglicherweise nicht mehr gepflegt, letztes Release vor {1} Jahren",
+    ),
+    (
+        Key::MajorAvailable,
+        "Neue Hauptversion {0} verfügbar, genutzt wird die Reihe {1}",
+    ),
+    (
+        Key::MajorAvailableCompatible,
+        "Neue Hauptversion {0} verfügbar, genutzt wird die Reihe {1} (kompatibles Update auf {2})",
+    ),
+    (
+        Key::DevOnlyDependency,
+        "{0} wird nur von Tests, Beispielen oder Benchmarks verwendet, gehört eventuell nach `[dev-dependencies]`",
+    ),
+    (
+        Key::LockBehind,
+        "Cargo.lock enthält {0} {1}, `cargo update -p {0}` aktualisiert auf {2}",
+    ),
+    (
+        Key::ChecksumMismatch,
+        "Die Prüfsumme von {0} {1} in Cargo.lock ({2}) weicht von der Registry ab ({3}), \
+        eine der Dateien wurde eventuell verändert: vor dem Bauen vergleichen",
+    ),
+    (
+        Key::MissingFeature,
+        "{0} wird meist mit dem Feature {1} benötigt{2}",
+    ),
+    (
+        Key::MissingFeatures,
+        "{0} wird meist mit den Features {1} benötigt{2}",
+    ),
+    (Key::OlderVersionsOmitted, "… {0} ältere Versionen"),
+    (Key::YankedVersionsOmitted, "{0} zurückgezogene Versionen ausgelassen"),
+    (
+        Key::ArrayExpected,
+        "{0} muss ein Array von Strings sein, etwa `[\"a\", \"b\"]`",
+    ),
+    (Key::BoolExpected, "{0} muss `true` oder `false` sein, kein String"),
+    (Key::DevDependency, "Entwicklungsabhängigkeit"),
+    (Key::BuildDependency, "Build-Abhängigkeit"),
+    (Key::NormalDependency, "Abhängigkeit"),
+    (Key::TargetDependency, "{0} für {1}"),
+    (
+        Key::PartiallyAnalyzedOne,
+        "1 Eintrag nur teilweise analysiert, Details im Hover",
+    ),
+    (
+        Key::PartiallyAnalyzed,
+        "{0} Einträge nur teilweise analysiert, Details im Hover",
+    ),
+    (
+        Key::NoticeDependency,
+        "{0} ist ein TOML-Wert vom Typ {1}, weder Versionsanforderung noch Tabelle, und wird nicht analysiert",
+    ),
+    (
+        Key::NoticeValue,
+        "{1} von {0} ist ein unverständlicher TOML-Wert vom Typ {2}, {0} wird ohne ihn analysiert",
+    ),
+    (Key::NoticeIgnored, "{1} von {0} wird nicht analysiert"),
+    (
+        Key::NoticeNotInherited,
+        "{0} hat `workspace = false`, was cargo ablehnt, und wird ohne Vererbung analysiert",
+    ),
+    (
+        Key::NoticeUnresolved,
+        "{0} wird geerbt, aber keine [workspace.dependencies] einer Workspace-Wurzel deklariert es, seine Version wird nicht geprüft",
+    ),
+    (
+        Key::SectionCounts,
+        "{0} veraltet · {1} unbekannt · {2} aktuell",
+    ),
+    (Key::UpdateToLatest, "Auf neueste aktualisieren ({0})"),
+    (Key::UpdateToMajor, "Auf {0} aktualisieren (neue Hauptversion)"),
+    (Key::UpdateToSeries, "Auf neueste {0}.x aktualisieren ({1})"),
+    (Key::ShowChangelog, "Changelog von {0} {1} → {2} anzeigen"),
+    (Key::LensUpdate, "{0} → {1}"),
+    (
+        Key::RegistryFreshness,
+        "Registry-Daten von {0}, Aktualisierung etwa alle {1}",
+    ),
+    (Key::MetadataPending, "weitere Daten ausstehend"),
+    (
+        Key::MetadataPendingHover,
+        "weitere Daten ausstehend, erwartet in etwa {0}",
+    ),
+    (Key::SeverityError, "Fehler"),
+    (Key::SeverityWarning, "Warnung"),
+    (Key::SeverityInformation, "Information"),
+    (Key::SeverityHint, "Hinweis"),
+    (Key::DiagnosticPrefix, "{0}, {1}: "),
+    (Key::RelatedLocation, "{0}, in Zeile {1} von {2}"),
+    (Key::TopicUpToDate, "aktuelle Abhängigkeit"),
+    (Key::TopicAny, "Abhängigkeit ohne Versionseinschränkung"),
+    (Key::TopicOutdated, "veraltete Abhängigkeit"),
+    (Key::TopicMajor, "neue Hauptversion"),
+    (Key::TopicRequirement, "Versionsanforderung"),
+    (Key::TopicRegistry, "Problem mit der Registry"),
+    (Key::TopicLockfile, "Problem mit Cargo.lock"),
+    (Key::TopicMaintenance, "möglicherweise ungepflegtes Crate"),
+    (Key::TopicFeatures, "Problem mit Features"),
+    (Key::TopicManifest, "Problem im Manifest"),
+    (Key::TopicConfig, "Problem in der Konfiguration"),
+    (Key::TopicStyle, "Stilvorschlag"),
+    (Key::TopicInternal, "interner Fehler"),
+];
+
+/// Plain language for screen readers, without symbols and version lists.
+const ACCESSIBLE_EN: &[(Key, &str)] = &[
+    (Key::Any, "The requirement matches every version"),
+    (Key::Latest, "The requirement allows the newest release"),
+    (
+        Key::OutdatedCompatible,
+        "The current requirement allows up to {0}, the newest release; cargo update installs it",
+    ),
+    (
+        Key::OutdatedIncompatible,
+        "The current requirement allows up to {1}; the newest release is {0}, which is {2} releases newer and needs a manifest change",
+    ),
+    (Key::Unknown, "No published release matches the requirement"),
+    (Key::FetchPending, "The versions of {0} are still being fetched"),
+    (
+        Key::MajorAvailable,
+        "A new major release, {0}, is available; the current requirement is on the {1} line",
+    ),
+    (
+        Key::MajorAvailableCompatible,
+        "A new major release, {0}, is available; the current requirement is on the {1} line, which has a compatible update to {2}",
+    ),
+    (
+        Key::SectionCounts,
+        "{0} outdated, {1} unknown, and {2} up-to-date dependencies",
+    ),
+    (Key::UpdateToLatest, "Update to the newest release, {0}"),
+    (Key::UpdateToMajor, "Update to the new major release, {0}"),
+    (
+        Key::UpdateToSeries,
+        "Update to {1}, the newest release of the {0} line",
+    ),
+    (Key::ShowChangelog, "Show the changes of {0} from {1} to {2}"),
+    (Key::LensUpdate, "Update from {0} to {1}"),
+    (
+        Key::RegistryFreshness,
+        "Note: the registry data is from {0} and refreshes roughly every {1}",
+    ),
+];
+
+const ACCESSIBLE_DE: &[(Key, &str)] = &[
+    (Key::Any, "Die Anforderung passt auf jede Version"),
+    (Key::Latest, "Die Anforderung erlaubt das neueste Release"),
+    (
+        Key::OutdatedCompatible,
+        "Die aktuelle Anforderung erlaubt bis zu {0}, das neueste Release; cargo update installiert es",
+    ),
+    (
+        Key::OutdatedIncompatible,
+        "Die aktuelle Anforderung erlaubt bis zu {1}; das neueste Release ist {0}, {2} Releases neuer, und erfordert eine Änderung des Manifests",
+    ),
+    (Key::Unknown, "Kein veröffentlichtes Release passt auf die Anforderung"),
+    (Key::FetchPending, "Die Versionen von {0} werden noch abgerufen"),
+    (
+        Key::MajorAvailable,
+        "Ein neues Hauptrelease, {0}, ist verfügbar; die aktuelle Anforderung ist in der Reihe {1}",
+    ),
+    (
+        Key::MajorAvailableCompatible,
+        "Ein neues Hauptrelease, {0}, ist verfügbar; die aktuelle Anforderung ist in der Reihe {1}, die ein kompatibles Update auf {2} hat",
+    ),
+    (
+        Key::SectionCounts,
+        "{0} veraltete, {1} unbekannte und {2} aktuelle Abhängigkeiten",
+    ),
+    (Key::UpdateToLatest, "Auf das neueste Release aktualisieren, {0}"),
+    (Key::UpdateToMajor, "Auf das neue Hauptrelease aktualisieren, {0}"),
+    (
+        Key::UpdateToSeries,
+        "Auf {1} aktualisieren, das neueste Release der Reihe {0}",
+    ),
+    (Key::ShowChangelog, "Die Änderungen von {0} von {1} bis {2} anzeigen"),
+    (Key::LensUpdate, "Von {0} auf {1} aktualisieren"),
+    (
+        Key::RegistryFreshness,
+        "Hinweis: Die Registry-Daten sind von {0} und werden etwa alle {1} aktualisiert",
+    ),
+];
+
+/// Message key with its arguments, rendered in the locale of the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub key: Key,
+    pub args: Vec<String>,
+}
+
+impl Message {
+    pub fn new(key: Key) -> Self {
+        Self {
+            key,
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Display) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    /// Render the message, falling back to English for missing translations.
+    ///
+    /// Placeholders without an argument are kept as they are, and arguments without
+    /// a placeholder are left out, like the words only the accessible templates use.
+    pub fn render(&self, language: impl Into<Language>) -> String {
+        let language = language.into();
+        let template = [language.locale, Locale::En]
+            .iter()
+            .find_map(|l| {
+                let accessible = language.accessible.then(|| l.accessible_catalog());
+                accessible
+                    .into_iter()
+                    .flatten()
+                    .chain(l.catalog())
+                    .find(|(k, _)| *k == self.key)
+            })
+            .map_or("", |(_, template)| template);
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            text += &rest[..start];
+            rest = &rest[start..];
+            let arg = rest.find('}').and_then(|end| {
+                let arg = self.args.get(rest[1..end].parse::<usize>().ok()?)?;
+                Some((arg, end + 1))
+            });
+            match arg {
+                Some((arg, len)) => {
+                    text += arg;
+                    rest = &rest[len..];
+                }
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text + rest
+    }
+}
+
+/// Prefix of a diagnostic naming its severity and topic in words,
+/// like `Warning, outdated dependency: `.
+pub fn diagnostic_prefix(
+    severity: Option<DiagnosticSeverity>,
+    code: &str,
+    language: Language,
+) -> String {
+    let severity = match severity {
+        Some(DiagnosticSeverity::ERROR) => Key::SeverityError,
+        Some(DiagnosticSeverity::WARNING) => Key::SeverityWarning,
+        Some(DiagnosticSeverity::HINT) => Key::SeverityHint,
+        _ => Key::SeverityInformation,
+    };
+    let topic = match code {
+        "latest" => Key::TopicUpToDate,
+        "any" => Key::TopicAny,
+        "outdated-compatible" | "outdated-incompatible" | "lock-behind" => Key::TopicOutdated,
+        "major-available" => Key::TopicMajor,
+        "fetch-failed" | "fetch-pending" => Key::TopicRegistry,
+        "checksum-mismatch" => Key::TopicLockfile,
+        "stale-crate" => Key::TopicMaintenance,
+        "invalid-config" => Key::TopicConfig,
+        "internal-error" => Key::TopicInternal,
+        code => match category(code) {
+            "features" => Key::TopicFeatures,
+            "package" => Key::TopicManifest,
+            "style" => Key::TopicStyle,
+            _ => Key::TopicRequirement,
+        },
+    };
+    Message::new(Key::DiagnosticPrefix)
+        .arg(Message::new(severity).render(language))
+        .arg(Message::new(topic).render(language))
+        .render(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(catalog: &[(Key, &str)]) -> Vec<Key> {
+        catalog.iter().map(|(key, _)| *key).collect()
+    }
+
+    #[test]
+    fn negotiates_the_locale() {
+        assert_eq!(Locale::from_tag("de-CH"), Some(Locale::De));
+        assert_eq!(Locale::from_tag("EN_us"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("fr"), None);
+        assert_eq!(Locale::negotiate(Some("de"), Some("en")), Locale::De);
+        assert_eq!(Locale::negotiate(Some("fr"), Some("de")), Locale::De);
+        assert_eq!(Locale::negotiate(None, Some("de-AT")), Locale::De);
+        assert_eq!(Locale::negotiate(Some("fr"), Some("it")), Locale::En);
+        assert_eq!(Locale::negotiate(None, None), Locale::En);
+    }
+
+    #[test]
+    fn every_key_renders() {
+        assert_eq!(keys(EN), keys(DE));
+        assert_eq!(keys(ACCESSIBLE_EN), keys(ACCESSIBLE_DE));
+        for locale in [Locale::En, Locale::De] {
+            let plain = locale.catalog().iter().map(|entry| (false, entry));
+            let accessible = locale
+                .accessible_catalog()
+                .iter()
+                .map(|entry| (true, entry));
+            for (accessible, (key, template)) in plain.chain(accessible) {
+                let locale = Language { locale, accessible };
+                // Placeholders may repeat, like `{0}` in `ShadowedPackage`
+                let args = (0..)
+                    .take_while(|i| template.contains(&format!("{{{i}}}")))
+                    .count();
+                let mut message = Message::new(*key);
+                for i in 0..args {
+                    message = message.arg(format!("<{i}>"));
+                }
+                let text = message.render(locale);
+                assert!(!text.is_empty() && !text.contains('{'), "{key:?}: {text}");
+                for i in 0..args {
+                    assert!(text.contains(&format!("<{i}>")), "{key:?}: {text}");
+                }
+                // Without arguments the placeholders are kept
+                assert_eq!(Message::new(*key).render(locale), *template);
+            }
+        }
+    }
+
+    #[test]
+    fn renders_in_plain_language() {
+        let accessible = |locale| Language {
+            locale,
+            accessible: true,
+        };
+        let outdated = Message::new(Key::OutdatedIncompatible)
+            .arg("2.0.0")
+            .arg("1.0.5")
+            .arg(3);
+        let cases = [
+            (
+                Message::new(Key::Latest),
+                Locale::En,
+                "Latest Version",
+                "The requirement allows the newest release",
+            ),
+            (
+                outdated.clone(),
+                Locale::En,
+                "Outdated Version, 2.0.0 requires a manifest change\nNewest compatible: 1.0.5",
+                "The current requirement allows up to 1.0.5; the newest release is 2.0.0, which is 3 releases newer and needs a manifest change",
+            ),
+            (
+                outdated,
+                Locale::De,
+                "Veraltete Version, 2.0.0 erfordert eine Änderung des Manifests\nNeueste kompatible: 1.0.5",
+                "Die aktuelle Anforderung erlaubt bis zu 1.0.5; das neueste Release ist 2.0.0, 3 Releases neuer, und erfordert eine Änderung des Manifests",
+            ),
+            // Messages without a plain variant stay the same
+            (
+                Message::new(Key::FetchFailed).arg("serde"),
+                Locale::En,
+                "Failed to fetch versions for serde",
+                "Failed to fetch versions for serde",
+            ),
+        ];
+        for (message, locale, default, plain) in cases {
+            assert_eq!(message.render(locale), default);
+            assert_eq!(message.render(accessible(locale)), plain);
+        }
+    }
+
+    #[test]
+    fn prefixes_the_topic() {
+        let language = Language {
+            locale: Locale::En,
+            accessible: true,
+        };
+        let cases = [
+            (
+                DiagnosticSeverity::HINT,
+                "latest",
+                "Hint, up-to-date dependency: ",
+            ),
+            (
+                DiagnosticSeverity::WARNING,
+                "outdated-compatible",
+                "Warning, outdated dependency: ",
+            ),
+            (
+                DiagnosticSeverity::ERROR,
+                "fetch-failed",
+                "Error, registry problem: ",
+            ),
+            (
+                DiagnosticSeverity::INFORMATION,
+                "resolver-features",
+                "Information, feature problem: ",
+            ),
+        ];
+        for (severity, code, prefix) in cases {
+            assert_eq!(diagnostic_prefix(Some(severity), code, language), prefix);
+        }
+        let german = Language {
+            locale: Locale::De,
+            ..language
+        };
+        assert_eq!(
+            diagnostic_prefix(Some(DiagnosticSeverity::WARNING), "latest", german),
+            "Warnung, aktuelle Abhängigkeit: "
+        );
+    }
+
+    #[test]
+    fn renders_arguments() {
+        let message = Message::new(Key::OutdatedIncompatible)
+            .arg("2.0.0")
+            .arg("1.0.5");
+        assert_eq!(
+            message.render(Locale::De),
+            "Veraltete Version, 2.0.0 erfordert eine Änderung des Manifests\nNeueste kompatible: 1.0.5"
+        );
+        let message = Message::new(Key::FetchFailed).arg("{1}");
+        assert_eq!(
+            message.render(Locale::En),
+            "Failed to fetch versions for {1}"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use reqwest::header::USER_AGENT;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::api::{is_valid_name, CrateMetadata};
+use crate::cadence;
+use crate::clock::Clock;
+use crate::error::{Result, ServerError};
+use crate::rate_limit::{Budget, RateLimiter};
+use crate::registry::BoxFuture;
+use crate::scheduler::Priority;
+
+/// Web API of crates.io, for metadata that is not part of the index.
+const CRATES_IO_API: &str = "https://crates.io/api/v1";
+/// Service named in errors.
+const API: &str = "crates.io API";
+/// The crates.io API requires identifying the client.
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "crates-io-lsp/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/wrenger/zed-crates-io)"
+);
+/// The crawler policy of crates.io allows one request per second,
+/// the sparse index is not limited.
+static CRATES_IO_LIMIT: RateLimiter = RateLimiter::new(Duration::from_secs(1), 3);
+/// Interactive requests rather use cached data than waiting longer for the API.
+const INTERACTIVE_WAIT: Duration = Duration::from_millis(500);
+/// API requests of [`fetch_metadata`], the crate and its owners.
+pub const REQUESTS_PER_CRATE: usize = 2;
+
+/// Source of the crate metadata, replaced in tests.
+pub trait MetadataClient: Send + Sync {
+    fn fetch<'a>(
+        &'a self,
+        name: &'a str,
+        user_agent: &'a str,
+        priority: Priority,
+        clock: &'a dyn Clock,
+    ) -> BoxFuture<'a, Result<CrateMetadata>>;
+
+    /// Tokens and queue of the rate limit of the requests.
+    fn budget(&self, clock: &dyn Clock) -> Budget;
+}
+
+/// The crates.io API, limited to its crawler policy.
+pub struct CratesIoApi;
+
+impl MetadataClient for CratesIoApi {
+    fn fetch<'a>(
+        &'a self,
+        name: &'a str,
+        user_agent: &'a str,
+        priority: Priority,
+        clock: &'a dyn Clock,
+    ) -> BoxFuture<'a, Result<CrateMetadata>> {
+        Box::pin(fetch_metadata(name, user_agent, priority, clock))
+    }
+
+    fn budget(&self, clock: &dyn Clock) -> Budget {
+        CRATES_IO_LIMIT.budget(clock)
+    }
+}
+
+pub async fn fetch_metadata(
+    name: &str,
+    user_agent: &str,
+    priority: Priority,
+    clock: &dyn Clock,
+) -> Result<CrateMetadata> {
+    if !is_valid_name(name) {
+        return Err(ServerError::InvalidName(name.into()));
+    }
+
+    let client = Client::new();
+    let get = |path| get_api(&client, name, path, user_agent, priority, clock);
+    let info = get(format!("crates/{name}")).await?;
+    let owners = get(format!("crates/{name}/owners")).await?;
+
+    let info: CrateResponse = serde_json::from_str(&info)?;
+    let owners: OwnersResponse = serde_json::from_str(&owners)?;
+
+    let released = info.versions.iter().filter(|v| !v.yanked);
+    Ok(CrateMetadata {
+        description: info.krate.description,
+        downloads: info.krate.downloads,
+        recent_downloads: info.krate.recent_downloads,
+        versions: info.versions.len(),
+        released: released.clone().map(|v| v.created_at.clone()).max(),
+        release_gap: cadence::release_gap(released.map(|v| v.created_at.as_str())),
+        owners: owners.users.into_iter().map(|u| u.login).collect(),
+        repository: info.krate.repository,
+    })
+}
+
+/// Request from the crates.io API, respecting its rate limit.
+async fn get_api(
+    client: &Client,
+    name: &str,
+    path: String,
+    user_agent: &str,
+    priority: Priority,
+    clock: &dyn Clock,
+) -> Result<String> {
+    let max_wait = (priority == Priority::Interactive).then_some(INTERACTIVE_WAIT);
+    if !CRATES_IO_LIMIT.acquire(clock, priority, max_wait).await {
+        return Err(ServerError::RateLimited {
+            service: API,
+            retry_after: None,
+        });
+    }
+    let response = client
+        .get(format!("{CRATES_IO_API}/{path}"))
+        .header(USER_AGENT, user_agent)
+        .send()
+        .await?;
+    if let Some(err) = ServerError::from_response(&response, API, &format!("Crate {name:?}")) {
+        return Err(err);
+    }
+    Ok(response.text().await?)
+}
+
+#[derive(Deserialize, Debug)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+    versions: Vec<VersionInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CrateInfo {
+    description: Option<String>,
+    downloads: u64,
+    recent_downloads: Option<u64>,
+    repository: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct VersionInfo {
+    created_at: String,
+    yanked: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct OwnersResponse {
+    users: Vec<Owner>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Owner {
+    login: String,
+}
@@ -0,0 +1,263 @@
+use spdx::{Expression, ParseMode};
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString, Range};
+
+use crate::config::{Config, Severity};
+use crate::inherit::EffectivePackage;
+use crate::manifest::{Publish, SpannedManifest};
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// License expressions as accepted by crates.io.
+const LICENSE_MODE: ParseMode = ParseMode {
+    allow_lower_case_operators: false,
+    allow_slash_as_or_operator: true,
+    allow_imprecise_license_names: false,
+    allow_postfix_plus_on_gpl: true,
+};
+
+/// Lint the `[package]` table for fields that are required for publishing,
+/// if `packageLints` is enabled.
+///
+/// `package` is the table with the inherited fields resolved.
+pub fn lint(
+    manifest: &SpannedManifest,
+    package: &EffectivePackage,
+    source: &Source,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let (true, Some(table)) = (config.package_lints, &manifest.package) else {
+        return Vec::new();
+    };
+    let header = header_range(source, table.span().start);
+
+    let mut diagnostics = Vec::new();
+    let mut push = |range: Option<Range>, severity: Severity, code: &str, message: Message| {
+        if let Some(range) = range {
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(severity.into()),
+                code: Some(NumberOrString::String(code.into())),
+                source: Some(config.source(code)),
+                message: message.render(config.language),
+                ..Default::default()
+            });
+        }
+    };
+
+    let (publish, crates_io) = publish_targets(package);
+    if publish {
+        let mut missing = Vec::new();
+        if package.version.is_none() {
+            missing.push("version");
+        }
+        if crates_io {
+            if package.description.is_none() {
+                missing.push("description");
+            }
+            if package.license.is_none() && package.license_file.is_none() {
+                missing.push("license");
+            }
+        }
+        if !missing.is_empty() {
+            push(
+                header,
+                config.severity.package_missing_field,
+                "package-missing-field",
+                Message::new(Key::PackageMissingField).arg(missing.join(", ")),
+            );
+        }
+        if crates_io && package.repository.is_none() {
+            push(
+                header,
+                config.severity.package_recommended_field,
+                "package-recommended-field",
+                Message::new(Key::PackageRecommendedField).arg("repository"),
+            );
+        }
+    }
+
+    // Inherited licenses are reported at the reference
+    if let Some(license) = &package.license {
+        if let Some(expression) = &license.value {
+            if let Err(err) = Expression::parse_mode(expression, LICENSE_MODE) {
+                let term = expression.get(err.span.clone()).unwrap_or_default();
+                push(
+                    source.range(license.span.clone()),
+                    config.severity.invalid_license,
+                    "invalid-license",
+                    Message::new(Key::InvalidLicense).arg(err.reason).arg(term),
+                );
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Whether the package is published at all, and whether to crates.io.
+///
+/// Inherited `publish` settings of an unknown workspace are treated as unpublished.
+fn publish_targets(package: &EffectivePackage) -> (bool, bool) {
+    match package.publish.as_ref().map(|p| p.value.as_ref()) {
+        None | Some(Some(Publish::Flag(true))) => (true, true),
+        Some(Some(Publish::Flag(false))) | Some(None) => (false, false),
+        Some(Some(Publish::Registries(registries))) => (
+            !registries.is_empty(),
+            registries.iter().any(|r| r == "crates-io"),
+        ),
+    }
+}
+
+/// Range of the table header line starting at `offset`.
+fn header_range(source: &Source, offset: usize) -> Option<Range> {
+    let text = &source.text;
+    let end = text[offset..]
+        .find(['\r', '\n'])
+        .map_or(text.len(), |end| offset + end);
+    source.range(offset..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types::{DiagnosticSeverity, Position};
+
+    use super::*;
+    use crate::inherit;
+    use crate::manifest::parse;
+    use crate::text::Encoding;
+
+    const ROOT: &str =
+        "[workspace]\nmembers = [\"app\"]\n\n[workspace.package]\nlicense = \"MIT\"\n";
+
+    /// Code, severity, range, and message of the diagnostics of `text`.
+    fn lints(
+        text: &str,
+        root: Option<&str>,
+        config: &Config,
+    ) -> Vec<(String, DiagnosticSeverity, Range, String)> {
+        let source = Source::new(text, Encoding::Utf8);
+        let (manifest, error) = parse(text);
+        assert!(error.is_none(), "{error:?}");
+        let root = root.map(|root| parse(root).0);
+        let package = inherit::resolve(manifest.package.as_ref().unwrap().get_ref(), root.as_ref());
+        lint(&manifest, &package, &source, config)
+            .into_iter()
+            .map(|d| {
+                let Some(NumberOrString::String(code)) = d.code else {
+                    panic!("{d:?}");
+                };
+                (code, d.severity.unwrap(), d.range, d.message)
+            })
+            .collect()
+    }
+
+    fn enabled() -> Config {
+        Config {
+            package_lints: true,
+            ..Default::default()
+        }
+    }
+
+    /// Range of the `[package]` header on the first line.
+    fn header() -> Range {
+        Range::new(Position::new(0, 0), Position::new(0, 9))
+    }
+
+    #[test]
+    fn reports_missing_fields_at_the_header() {
+        let text = "[package]\nname = \"app\"\n";
+        assert_eq!(
+            lints(text, None, &enabled()),
+            [
+                (
+                    "package-missing-field".into(),
+                    DiagnosticSeverity::WARNING,
+                    header(),
+                    "Missing fields required for publishing: version, description, license".into()
+                ),
+                (
+                    "package-recommended-field".into(),
+                    DiagnosticSeverity::HINT,
+                    header(),
+                    "Missing recommended field: repository".into()
+                ),
+            ]
+        );
+
+        // A `license-file` is as good as a `license`
+        let text = "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\
+            license-file = \"LICENSE\"\nrepository = \"https://example.com/app\"\n";
+        let found = lints(text, None, &enabled());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].2, header());
+        assert!(found[0].3.ends_with(": description"), "{}", found[0].3);
+    }
+
+    #[test]
+    fn publishable_crates_pass() {
+        let text = "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\
+            description = \"An app\"\nlicense = \"MIT OR Apache-2.0\"\n\
+            repository = \"https://example.com/app\"\n";
+        assert_eq!(lints(text, None, &enabled()), []);
+    }
+
+    #[test]
+    fn unpublished_crates_need_no_fields() {
+        let text = "[package]\nname = \"app\"\npublish = false\n";
+        assert_eq!(lints(text, None, &enabled()), []);
+        let text = "[package]\nname = \"app\"\npublish = []\n";
+        assert_eq!(lints(text, None, &enabled()), []);
+    }
+
+    #[test]
+    fn other_registries_only_need_a_version() {
+        let text = "[package]\nname = \"app\"\npublish = [\"my-registry\"]\n";
+        let found = lints(text, None, &enabled());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, "package-missing-field");
+        assert!(found[0].3.ends_with(": version"), "{}", found[0].3);
+
+        // Including crates.io needs its fields as well
+        let text = "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\
+            publish = [\"my-registry\", \"crates-io\"]\n";
+        let codes = lints(text, None, &enabled())
+            .into_iter()
+            .map(|(code, ..)| code)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            ["package-missing-field", "package-recommended-field"]
+        );
+    }
+
+    #[test]
+    fn flags_unknown_license_identifiers_at_the_value() {
+        let text = "[package]\nname = \"app\"\npublish = false\nlicense = \"MIT OR Foo-1.0\"\n";
+        let [(code, severity, range, _)] = &lints(text, None, &enabled())[..] else {
+            panic!("expected one diagnostic");
+        };
+        assert_eq!(code, "invalid-license");
+        assert_eq!(*severity, DiagnosticSeverity::ERROR);
+        assert_eq!(
+            *range,
+            Range::new(Position::new(3, 10), Position::new(3, 26))
+        );
+    }
+
+    #[test]
+    fn inherited_licenses_are_not_flagged() {
+        let text = "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\
+            description = \"An app\"\nlicense.workspace = true\n\
+            repository = \"https://example.com/app\"\n";
+        assert_eq!(lints(text, Some(ROOT), &enabled()), []);
+        // Also while the workspace root is unknown
+        assert_eq!(lints(text, None, &enabled()), []);
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let text = "[package]\nname = \"app\"\nlicense = \"Foo\"\n";
+        assert_eq!(lints(text, None, &Config::default()), []);
+        assert!(!lints(text, None, &enabled()).is_empty());
+    }
+}
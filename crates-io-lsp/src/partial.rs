@@ -0,0 +1,257 @@
+use serde::Serialize;
+use tower_lsp_server::lsp_types::{
+    self, Diagnostic, DiagnosticRelatedInformation, Location, NumberOrString, Uri,
+};
+
+use crate::config::Config;
+use crate::manifest::{DependencyKind, Notice, SpannedManifest, Unsupported};
+use crate::messages::{Key, Language, Message};
+use crate::text::Source;
+
+/// Diagnostic code of [`lint`], its related information lists the notices.
+pub const PARTIALLY_ANALYZED: &str = "partially-analyzed";
+
+/// A [`Notice`] of a manifest as reported by `crates-io/analyze`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialNotice {
+    /// Range of the key of the declaration
+    pub range: lsp_types::Range,
+    /// The dependency the declaration belongs to
+    pub dependency: String,
+    /// What is not supported, like `value` or `unresolved`
+    pub reason: &'static str,
+    /// The key of the dependency that is not analyzed, if it is a key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub message: String,
+}
+
+/// The notices of the manifest `source` with their ranges and messages.
+pub fn notices(source: &Source, notices: &[Notice], language: Language) -> Vec<PartialNotice> {
+    notices
+        .iter()
+        .filter_map(|notice| {
+            let quoted = |name: &str| format!("`{name}`");
+            let (dependency, reason, key, message) = match &notice.reason {
+                Unsupported::Dependency { name, found } => (
+                    name,
+                    "dependency",
+                    None,
+                    Message::new(Key::NoticeDependency)
+                        .arg(quoted(name))
+                        .arg(found),
+                ),
+                Unsupported::Value { name, key, found } => (
+                    name,
+                    "value",
+                    Some(key),
+                    Message::new(Key::NoticeValue)
+                        .arg(quoted(name))
+                        .arg(quoted(key))
+                        .arg(found),
+                ),
+                Unsupported::IgnoredKey { name, key } => (
+                    name,
+                    "ignored-key",
+                    Some(key),
+                    Message::new(Key::NoticeIgnored)
+                        .arg(quoted(name))
+                        .arg(quoted(key)),
+                ),
+                Unsupported::NotInherited { name } => (
+                    name,
+                    "not-inherited",
+                    None,
+                    Message::new(Key::NoticeNotInherited).arg(quoted(name)),
+                ),
+                Unsupported::Unresolved { name } => (
+                    name,
+                    "unresolved",
+                    None,
+                    Message::new(Key::NoticeUnresolved).arg(quoted(name)),
+                ),
+            };
+            Some(PartialNotice {
+                range: source.range(notice.span.clone())?,
+                dependency: dependency.clone(),
+                reason,
+                key: key.cloned(),
+                message: message.render(language),
+            })
+        })
+        .collect()
+}
+
+/// Notices of the dependencies of `manifest` inherited from a workspace that does not declare
+/// them, `root` is the manifest of the workspace root if it was found.
+///
+/// A manifest with a `[workspace]` is its own root.
+pub fn unresolved(manifest: &SpannedManifest, root: Option<&SpannedManifest>) -> Vec<Notice> {
+    let root = match &manifest.workspace {
+        Some(_) => Some(manifest),
+        None => root,
+    };
+    let declared = |name: &str| {
+        root.and_then(|root| root.workspace.as_ref())
+            .is_some_and(|ws| ws.dependencies.contains_key(name))
+    };
+    let targets = manifest
+        .target_dependencies()
+        .map(|(_, kind, name, dep)| (kind, name, dep));
+    manifest
+        .dependencies()
+        .chain(targets)
+        .filter(|(kind, name, dep)| {
+            *kind != DependencyKind::Workspace && dep.inherited() && !declared(name.get_ref())
+        })
+        .map(|(_, name, _)| Notice {
+            span: name.span(),
+            reason: Unsupported::Unresolved {
+                name: name.get_ref().clone(),
+            },
+        })
+        .collect()
+}
+
+/// A single diagnostic of the document at `uri` summarizing its `notices`,
+/// at the first one, with the others as related information.
+pub fn lint(uri: &Uri, notices: &[PartialNotice], config: &Config) -> Option<Diagnostic> {
+    let first = notices.iter().min_by_key(|n| n.range.start)?;
+    let message = match notices.len() {
+        1 => Message::new(Key::PartiallyAnalyzedOne),
+        n => Message::new(Key::PartiallyAnalyzed).arg(n),
+    };
+    let related = notices
+        .iter()
+        .map(|notice| DiagnosticRelatedInformation {
+            location: Location {
+                uri: uri.clone(),
+                range: notice.range,
+            },
+            message: notice.message.clone(),
+        })
+        .collect();
+    Some(Diagnostic {
+        range: first.range,
+        severity: Some(config.severity.partially_analyzed.into()),
+        code: Some(NumberOrString::String(PARTIALLY_ANALYZED.into())),
+        source: Some(config.source(PARTIALLY_ANALYZED)),
+        message: message.render(config.language),
+        related_information: Some(related),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types::Position;
+
+    use super::*;
+    use crate::manifest;
+    use crate::messages::Locale;
+    use crate::text::Encoding;
+
+    const TEXT: &str =
+        "[dependencies]\nserde = \"1\"\ntokio = { version = \"1\", public = true }\n\
+        bad = 1\nlog = { workspace = true }\n";
+
+    fn uri() -> Uri {
+        "file:///app/Cargo.toml".parse().unwrap()
+    }
+
+    #[test]
+    fn renders_the_notices() {
+        let (parsed, _) = manifest::parse(TEXT);
+        let mut all = parsed.notices.clone();
+        all.extend(unresolved(&parsed, None));
+        let notices = notices(&Source::new(TEXT, Encoding::Utf8), &all, Locale::En.into());
+        let summary = notices
+            .iter()
+            .map(|n| {
+                (
+                    n.range.start.line,
+                    n.reason,
+                    n.key.as_deref(),
+                    &n.message[..],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (
+                    2,
+                    "ignored-key",
+                    Some("public"),
+                    "`public` of `tokio` is not analyzed"
+                ),
+                (
+                    3,
+                    "dependency",
+                    None,
+                    "`bad` is a TOML integer, neither a version requirement nor a table, \
+                    it is not analyzed"
+                ),
+                (
+                    4,
+                    "unresolved",
+                    None,
+                    "`log` is inherited, but no [workspace.dependencies] of a workspace root \
+                    declares it, its version is not checked"
+                ),
+            ]
+        );
+        let json = serde_json::to_value(&notices[0]).unwrap();
+        assert_eq!(json["dependency"], "tokio");
+        assert_eq!(json["range"]["start"]["character"], 25);
+        assert!(serde_json::to_value(&notices[1])
+            .unwrap()
+            .get("key")
+            .is_none());
+    }
+
+    #[test]
+    fn inheritance_resolves_in_the_root() {
+        let (member, _) = manifest::parse("[dependencies]\nlog = { workspace = true }\n");
+        let (root, _) = manifest::parse("[workspace.dependencies]\nlog = \"0.4\"\n");
+        assert!(unresolved(&member, Some(&root)).is_empty());
+        let (other, _) = manifest::parse("[workspace.dependencies]\nserde = \"1\"\n");
+        assert_eq!(unresolved(&member, Some(&other)).len(), 1);
+        // A root resolves its own dependencies
+        let (own, _) = manifest::parse(
+            "[workspace.dependencies]\nlog = \"0.4\"\n\n\
+            [target.'cfg(unix)'.dependencies]\nlog = { workspace = true }\n",
+        );
+        assert!(unresolved(&own, None).is_empty());
+    }
+
+    #[test]
+    fn one_diagnostic_per_document() {
+        let (parsed, _) = manifest::parse(TEXT);
+        let config = Config::default();
+        let notices = notices(
+            &Source::new(TEXT, Encoding::Utf8),
+            &parsed.notices,
+            config.language,
+        );
+        let diagnostic = lint(&uri(), &notices, &config).unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "2 entries partially analyzed, hover for details"
+        );
+        assert_eq!(diagnostic.range.start, Position::new(2, 25));
+        assert_eq!(
+            diagnostic.severity,
+            Some(lsp_types::DiagnosticSeverity::HINT)
+        );
+        let related = diagnostic.related_information.unwrap();
+        assert_eq!(related.len(), 2);
+        assert_eq!(related[1].location.range.start, Position::new(3, 0));
+        assert_eq!(related[1].message, notices[1].message);
+
+        let one = lint(&uri(), &notices[..1], &config).unwrap();
+        assert_eq!(one.message, "1 entry partially analyzed, hover for details");
+        assert_eq!(lint(&uri(), &[], &config), None);
+    }
+}
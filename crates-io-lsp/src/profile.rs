@@ -0,0 +1,120 @@
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString, Range};
+
+use crate::config::Config;
+use crate::manifest::SpannedManifest;
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// Package overrides of all profiles (`[profile.release.package.serde]`) with their key ranges.
+///
+/// The wildcard `"*"` is skipped, it applies to all dependencies.
+pub fn overrides(manifest: &SpannedManifest, source: &Source) -> Vec<(String, Range)> {
+    manifest
+        .profile
+        .values()
+        .flat_map(|profile| profile.package.keys())
+        .filter(|name| name.get_ref() != "*")
+        .filter_map(|name| Some((name.get_ref().clone(), source.range(name.span())?)))
+        .collect()
+}
+
+/// Names of the packages declared by a manifest: its own and those of its dependencies.
+pub fn declared(manifest: &SpannedManifest) -> impl Iterator<Item = &str> {
+    let own = manifest
+        .package
+        .as_ref()
+        .and_then(|p| p.get_ref().name.as_ref())
+        .map(|name| name.get_ref().as_str());
+    let deps = manifest
+        .dependencies()
+        .chain(
+            manifest
+                .target_dependencies()
+                .map(|(_, kind, name, dep)| (kind, name, dep)),
+        )
+        .map(|(_, name, dep)| dep.package().unwrap_or(name.get_ref()));
+    own.into_iter().chain(deps)
+}
+
+/// Flag package overrides naming a package that is not `declared`.
+///
+/// These are often leftovers of removed dependencies,
+/// but might also target transitive dependencies.
+pub fn lint(overrides: &[(String, Range)], declared: &[&str], config: &Config) -> Vec<Diagnostic> {
+    overrides
+        .iter()
+        .filter(|(name, _)| !declared.contains(&name.as_str()))
+        .map(|(name, range)| Diagnostic {
+            range: *range,
+            severity: Some(config.severity.unknown_profile_package.into()),
+            code: Some(NumberOrString::String("unknown-profile-package".into())),
+            source: Some(config.source("unknown-profile-package")),
+            message: Message::new(Key::UnknownProfilePackage)
+                .arg(format!("`{name}`"))
+                .render(config.language),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::Encoding;
+
+    const TEXT: &str = r#"[package]
+name = "app"
+
+[dependencies]
+serde = "1"
+json = { package = "serde_json", version = "1" }
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+
+[profile.dev.package."*"]
+opt-level = 1
+
+[profile.release.package.serde_json]
+opt-level = 3
+
+[profile.release.package.libc]
+opt-level = 3
+
+[profile.release.package.app]
+debug = true
+
+[profile.bench.package.removed]
+opt-level = 3
+"#;
+
+    #[test]
+    fn flags_undeclared_packages() {
+        let manifest: SpannedManifest = toml::from_str(TEXT).unwrap();
+        let overrides = overrides(&manifest, &Source::new(TEXT, Encoding::Utf8));
+        let names = overrides
+            .iter()
+            .map(|(n, _)| n.as_str())
+            .collect::<Vec<_>>();
+        // By profile and name, the wildcard is never included
+        assert_eq!(names, ["removed", "app", "libc", "serde_json"]);
+        assert_eq!(overrides[0].1.start.line, 22);
+
+        let declared = declared(&manifest).collect::<Vec<_>>();
+        assert_eq!(declared, ["app", "serde_json", "serde", "libc"]);
+        let diagnostics = lint(&overrides, &declared, &Config::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range, overrides[0].1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("unknown-profile-package".into()))
+        );
+    }
+
+    #[test]
+    fn wildcards_only() {
+        let text = "[profile.dev.package.\"*\"]\nopt-level = 1\n";
+        let manifest: SpannedManifest = toml::from_str(text).unwrap();
+        assert!(overrides(&manifest, &Source::new(text, Encoding::Utf8)).is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+use std::sync::Mutex;
+
+use tokio::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::scheduler::Priority;
+
+/// Token bucket shared by all requests to a rate limited API.
+///
+/// Background requests only get a token if no interactive request is waiting.
+pub struct RateLimiter {
+    /// Time between two tokens
+    interval: Duration,
+    burst: u32,
+    state: Mutex<State>,
+}
+
+struct State {
+    tokens: u32,
+    /// Time the last token was added
+    refilled: Option<Instant>,
+    /// Number of waiting interactive requests
+    interactive: usize,
+    /// Number of waiting requests of any priority
+    queued: usize,
+}
+
+/// Tokens and queue of a [`RateLimiter`] at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    pub tokens: u32,
+    /// Requests waiting for a token
+    pub queued: usize,
+    /// Time until the next token is added
+    pub next: Duration,
+    /// Time between two tokens
+    pub interval: Duration,
+}
+
+impl Budget {
+    /// Estimated time until `requests` more requests got their tokens,
+    /// if no other requests take them.
+    pub fn wait(&self, requests: usize) -> Duration {
+        match requests.saturating_sub(self.tokens as usize) {
+            0 => Duration::ZERO,
+            missing => self.next + self.interval * (missing - 1) as u32,
+        }
+    }
+}
+
+impl RateLimiter {
+    pub const fn new(interval: Duration, burst: u32) -> Self {
+        Self {
+            interval,
+            burst,
+            state: Mutex::new(State {
+                tokens: burst,
+                refilled: None,
+                interactive: 0,
+                queued: 0,
+            }),
+        }
+    }
+
+    /// The current tokens and queue, for estimates of deferred requests.
+    pub fn budget(&self, clock: &dyn Clock) -> Budget {
+        let mut state = self.state.lock().unwrap();
+        let now = clock.now();
+        self.refill(&mut state, now);
+        let next = match (state.tokens < self.burst, state.refilled) {
+            (true, Some(refilled)) => (refilled + self.interval).saturating_duration_since(now),
+            _ => self.interval,
+        };
+        Budget {
+            tokens: state.tokens,
+            queued: state.queued,
+            next,
+            interval: self.interval,
+        }
+    }
+
+    /// Add the tokens of the intervals elapsed since the last refill.
+    fn refill(&self, state: &mut State, now: Instant) {
+        let refilled = *state.refilled.get_or_insert(now);
+        let elapsed = now.duration_since(refilled);
+        let new = (elapsed.as_nanos() / self.interval.as_nanos().max(1)) as u32;
+        if new > 0 {
+            state.tokens = (state.tokens + new).min(self.burst);
+            state.refilled = Some(refilled + self.interval * new);
+        }
+    }
+
+    /// Wait for a token, returns `false` if none was available within `max_wait`.
+    pub async fn acquire(
+        &self,
+        clock: &dyn Clock,
+        priority: Priority,
+        max_wait: Option<Duration>,
+    ) -> bool {
+        let deadline = max_wait.map(|w| clock.now() + w);
+        let _waiting = Waiting::new(self, priority);
+        loop {
+            let next = {
+                let mut state = self.state.lock().unwrap();
+                let now = clock.now();
+                self.refill(&mut state, now);
+                // Interactive requests go first
+                let turn = priority == Priority::Interactive || state.interactive == 0;
+                if turn && state.tokens > 0 {
+                    if state.tokens == self.burst {
+                        state.refilled = Some(now);
+                    }
+                    state.tokens -= 1;
+                    return true;
+                }
+                state.refilled.unwrap_or(now) + self.interval
+            };
+            if deadline.is_some_and(|d| next > d) {
+                return false;
+            }
+            clock.sleep_until(next).await;
+        }
+    }
+}
+
+/// Marks a request as waiting while it exists.
+struct Waiting<'a>(&'a RateLimiter, Priority);
+
+impl<'a> Waiting<'a> {
+    fn new(limiter: &'a RateLimiter, priority: Priority) -> Self {
+        let mut state = limiter.state.lock().unwrap();
+        state.queued += 1;
+        if priority == Priority::Interactive {
+            state.interactive += 1;
+        }
+        Self(limiter, priority)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.queued -= 1;
+        if self.1 == Priority::Interactive {
+            state.interactive -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::clock::TokioClock;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[tokio::test(start_paused = true)]
+    async fn bursts_then_one_per_interval() {
+        let limiter = RateLimiter::new(SECOND, 3);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(
+                limiter
+                    .acquire(&TokioClock, Priority::Background, None)
+                    .await
+            );
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        for i in 1..=3 {
+            assert!(
+                limiter
+                    .acquire(&TokioClock, Priority::Background, None)
+                    .await
+            );
+            assert_eq!(start.elapsed(), SECOND * i);
+        }
+
+        // Refilled up to the burst while idle
+        tokio::time::sleep(SECOND * 10).await;
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(
+                limiter
+                    .acquire(&TokioClock, Priority::Background, None)
+                    .await
+            );
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert!(
+            limiter
+                .acquire(&TokioClock, Priority::Background, None)
+                .await
+        );
+        assert_eq!(start.elapsed(), SECOND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn estimates_the_budget() {
+        let limiter = Arc::new(RateLimiter::new(SECOND, 2));
+        let budget = limiter.budget(&TokioClock);
+        assert_eq!((budget.tokens, budget.queued), (2, 0));
+        assert_eq!(budget.wait(2), Duration::ZERO);
+        assert_eq!(budget.wait(3), SECOND);
+
+        for _ in 0..2 {
+            limiter
+                .acquire(&TokioClock, Priority::Background, None)
+                .await;
+        }
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move {
+                limiter
+                    .acquire(&TokioClock, Priority::Background, None)
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+        let budget = limiter.budget(&TokioClock);
+        assert_eq!((budget.tokens, budget.queued), (0, 1));
+        assert_eq!(budget.next, Duration::from_millis(700));
+        assert_eq!(budget.wait(3), Duration::from_millis(2700));
+        assert!(waiting.await.unwrap());
+        assert_eq!(limiter.budget(&TokioClock).queued, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn interactive_requests_give_up() {
+        let limiter = RateLimiter::new(SECOND, 1);
+        let start = Instant::now();
+        assert!(
+            limiter
+                .acquire(&TokioClock, Priority::Interactive, None)
+                .await
+        );
+        // The next token is a second away
+        let wait = Some(Duration::from_millis(200));
+        assert!(
+            !limiter
+                .acquire(&TokioClock, Priority::Interactive, wait)
+                .await
+        );
+        assert_eq!(start.elapsed(), Duration::ZERO, "without waiting");
+
+        tokio::time::sleep(Duration::from_millis(900)).await;
+        assert!(
+            limiter
+                .acquire(&TokioClock, Priority::Interactive, wait)
+                .await
+        );
+        assert_eq!(start.elapsed(), SECOND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn interactive_before_background() {
+        let limiter = Arc::new(RateLimiter::new(SECOND, 1));
+        let start = Instant::now();
+        assert!(
+            limiter
+                .acquire(&TokioClock, Priority::Background, None)
+                .await
+        );
+
+        let background = tokio::spawn({
+            let limiter = limiter.clone();
+            async move {
+                limiter
+                    .acquire(&TokioClock, Priority::Background, None)
+                    .await;
+                start.elapsed()
+            }
+        });
+        tokio::task::yield_now().await;
+        let mut interactive = Vec::new();
+        for _ in 0..2 {
+            let limiter = limiter.clone();
+            interactive.push(tokio::spawn(async move {
+                limiter
+                    .acquire(&TokioClock, Priority::Interactive, None)
+                    .await;
+                start.elapsed()
+            }));
+        }
+        let mut times = Vec::new();
+        for task in interactive {
+            times.push(task.await.unwrap());
+        }
+        times.sort();
+        assert_eq!(times, [SECOND, SECOND * 2]);
+        // Served once no interactive request waits, it does not starve
+        assert_eq!(background.await.unwrap(), SECOND * 3);
+    }
+}
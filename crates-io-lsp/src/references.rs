@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+
+use tower_lsp_server::lsp_types::Uri;
+
+/// Crates used by the open documents, indexed in both directions.
+#[derive(Debug, Default)]
+pub struct References {
+    crates: HashMap<Uri, HashSet<String>>,
+    documents: HashMap<String, HashSet<Uri>>,
+}
+
+impl References {
+    /// Crates used by a document.
+    pub fn get(&self, uri: &Uri) -> Option<&HashSet<String>> {
+        self.crates.get(uri)
+    }
+
+    /// Replace the crates used by a document.
+    pub fn insert(&mut self, uri: Uri, crates: HashSet<String>) {
+        self.remove(&uri);
+        for name in &crates {
+            self.documents
+                .entry(name.clone())
+                .or_default()
+                .insert(uri.clone());
+        }
+        self.crates.insert(uri, crates);
+    }
+
+    pub fn remove(&mut self, uri: &Uri) -> Option<HashSet<String>> {
+        let crates = self.crates.remove(uri)?;
+        for name in &crates {
+            if let Some(documents) = self.documents.get_mut(name) {
+                documents.remove(uri);
+                if documents.is_empty() {
+                    self.documents.remove(name);
+                }
+            }
+        }
+        Some(crates)
+    }
+
+    /// Crates used by any document.
+    pub fn crates(&self) -> impl Iterator<Item = &String> {
+        self.documents.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(name: &str) -> Uri {
+        format!("file:///{name}/Cargo.toml").parse().unwrap()
+    }
+
+    fn crates(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn documents(references: &References, name: &str) -> Vec<String> {
+        let mut documents = references
+            .documents
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|uri| uri.to_string())
+            .collect::<Vec<_>>();
+        documents.sort();
+        documents
+    }
+
+    #[test]
+    fn indexes_both_directions() {
+        let mut references = References::default();
+        references.insert(uri("a"), crates(&["serde", "tokio"]));
+        references.insert(uri("b"), crates(&["serde"]));
+        assert_eq!(
+            documents(&references, "serde"),
+            ["file:///a/Cargo.toml", "file:///b/Cargo.toml"]
+        );
+        assert_eq!(references.get(&uri("b")), Some(&crates(&["serde"])));
+
+        // Changed documents drop the crates they no longer use
+        references.insert(uri("a"), crates(&["anyhow"]));
+        assert!(documents(&references, "tokio").is_empty());
+        assert_eq!(documents(&references, "serde"), ["file:///b/Cargo.toml"]);
+
+        references.remove(&uri("b"));
+        let mut names = references.crates().cloned().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["anyhow"]);
+        assert!(references.get(&uri("b")).is_none());
+    }
+}
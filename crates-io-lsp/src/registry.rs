@@ -0,0 +1,886 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use reqwest::Client;
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+use tower_lsp_server::lsp_types::Uri;
+use tower_lsp_server::UriExt;
+
+use crate::api::is_valid_name;
+use crate::error::{Result, ServerError};
+use crate::failover::Notice;
+use crate::version::Release;
+
+/// Versions kept per crate, older ones are dropped.
+const MAX_VERSIONS: usize = 1024;
+/// Longer version strings are treated as malformed, like those that are not versions even leniently.
+const MAX_VERSION_LEN: usize = 128;
+
+/// Future returned by the registry methods, which are called on `dyn RegistryClient`.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Source of the published versions of crates.
+pub trait RegistryClient: Send + Sync {
+    /// Versions of a crate, failing if its index file is missing or too large.
+    ///
+    /// The feature maps and checksums of the versions are only kept if `details` is set.
+    fn index<'a>(&'a self, name: &'a str, details: bool) -> BoxFuture<'a, Result<IndexVersions>>;
+
+    fn versions<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<IndexVersions>> {
+        self.index(name, false)
+    }
+
+    /// Whether the index file of a crate changed after `fetched`, in seconds since the Unix
+    /// epoch.
+    ///
+    /// Cached versions are kept unless the registry can tell it cheaply.
+    fn modified_since<'a>(&'a self, _name: &'a str, _fetched: i64) -> BoxFuture<'a, bool> {
+        Box::pin(async { false })
+    }
+
+    /// The `config.json` of the index root, `None` if the registry does not provide it.
+    fn config(&self) -> BoxFuture<'_, Option<IndexConfig>> {
+        Box::pin(async { None })
+    }
+
+    /// Changes of the endpoint serving the requests since the last call, for the user.
+    fn notices(&self) -> Vec<Notice> {
+        Vec::new()
+    }
+}
+
+/// Client for the registry at `endpoint`.
+///
+/// `file://` endpoints and paths without a scheme are local index directories,
+/// anything else is a sparse HTTP index.
+/// Index files larger than `max_bytes` are rejected.
+pub fn client(endpoint: &str, token: &str, max_bytes: usize) -> Arc<dyn RegistryClient> {
+    let directory = if endpoint.starts_with("file:") {
+        endpoint
+            .parse::<Uri>()
+            .ok()
+            .and_then(|uri| uri.to_file_path().map(|p| p.to_path_buf()))
+    } else if !endpoint.contains("://") {
+        Some(PathBuf::from(endpoint))
+    } else {
+        None
+    };
+    match directory {
+        Some(root) => Arc::new(FileIndexClient {
+            root,
+            max_bytes,
+            config: OnceCell::new(),
+        }),
+        None => Arc::new(SparseClient {
+            client: Client::new(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            max_bytes,
+        }),
+    }
+}
+
+/// Published versions of a crate.
+#[derive(Debug, Default)]
+pub struct IndexVersions {
+    /// Published versions, oldest first, yanked ones are flagged
+    pub versions: Vec<Release>,
+    /// Number of index lines or versions that could not be parsed
+    pub malformed: usize,
+    /// Features of the versions, if requested
+    pub features: HashMap<String, BTreeMap<String, Vec<String>>>,
+    /// SHA-256 checksums of the `.crate` files by version, if requested
+    pub checksums: HashMap<String, String>,
+}
+
+/// The `config.json` at the root of an index.
+///
+/// Only the API is of interest, nothing downloads the crates from `dl`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IndexConfig {
+    /// Web API of the registry, like `https://crates.io`
+    pub api: Option<String>,
+}
+
+/// Path of the index file of a crate, relative to the index root.
+///
+/// The paths of the index are lowercase, like `in/fl/inflector` for `Inflector`.
+/// Names are validated before they are sliced, so only ASCII reaches the prefix.
+fn index_path(name: &str) -> Result<String> {
+    if !is_valid_name(name) {
+        return Err(ServerError::InvalidName(name.to_string()));
+    }
+    let name = name.to_ascii_lowercase();
+    let prefix = match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    };
+    Ok(format!("{prefix}/{name}"))
+}
+
+fn too_large(name: &str, max_bytes: usize) -> ServerError {
+    ServerError::TooLarge {
+        name: name.to_string(),
+        max_bytes,
+    }
+}
+
+/// Sparse index served over HTTP, like `https://index.crates.io`.
+struct SparseClient {
+    client: Client,
+    endpoint: String,
+    token: String,
+    max_bytes: usize,
+}
+
+impl RegistryClient for SparseClient {
+    /// The body is parsed while it arrives, only the versions and the feature maps are kept.
+    fn index<'a>(&'a self, name: &'a str, details: bool) -> BoxFuture<'a, Result<IndexVersions>> {
+        Box::pin(async move {
+            let path = index_path(name)?;
+            let mut request = self.client.get(format!("{}/{path}", self.endpoint));
+            if !self.token.is_empty() {
+                request = request.bearer_auth(&self.token);
+            }
+
+            let mut response = request.send().await?;
+            if let Some(err) =
+                ServerError::from_response(&response, "registry", &format!("Crate {name:?}"))
+            {
+                return Err(err);
+            }
+            if response
+                .content_length()
+                .is_some_and(|len| len > self.max_bytes as u64)
+            {
+                return Err(too_large(name, self.max_bytes));
+            }
+            let mut parser = IndexParser::new(details);
+            let mut received = 0;
+            while let Some(chunk) = response.chunk().await? {
+                received += chunk.len();
+                if received > self.max_bytes {
+                    return Err(too_large(name, self.max_bytes));
+                }
+                parser.push(&chunk);
+            }
+            Ok(parser.finish())
+        })
+    }
+}
+
+/// Index files in a local directory with the layout of the sparse index,
+/// like a checkout of the git index for air-gapped environments.
+///
+/// Cached versions are read again when the modification time of their file changes.
+struct FileIndexClient {
+    root: PathBuf,
+    max_bytes: usize,
+    /// Read once, the index root is not expected to change
+    config: OnceCell<Option<IndexConfig>>,
+}
+
+impl RegistryClient for FileIndexClient {
+    fn index<'a>(&'a self, name: &'a str, details: bool) -> BoxFuture<'a, Result<IndexVersions>> {
+        Box::pin(async move {
+            let path = self.root.join(index_path(name)?);
+            let metadata = match tokio::fs::metadata(&path).await {
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    return Err(ServerError::NotFound(format!(
+                        "The index file of {name} at {}",
+                        path.display()
+                    )));
+                }
+                metadata => metadata.map_err(|err| ServerError::io(&path, err))?,
+            };
+            if metadata.len() > self.max_bytes as u64 {
+                return Err(too_large(name, self.max_bytes));
+            }
+            let mut parser = IndexParser::new(details);
+            let bytes = tokio::fs::read(&path)
+                .await
+                .map_err(|err| ServerError::io(&path, err))?;
+            parser.push(&bytes);
+            Ok(parser.finish())
+        })
+    }
+
+    fn modified_since<'a>(&'a self, name: &'a str, fetched: i64) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            let Ok(path) = index_path(name) else {
+                return false;
+            };
+            let modified = tokio::fs::metadata(self.root.join(path))
+                .await
+                .and_then(|metadata| metadata.modified());
+            match modified
+                .ok()
+                .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            {
+                Some(modified) => modified.as_secs() as i64 > fetched,
+                // Deleted, the fetch reports it
+                None => true,
+            }
+        })
+    }
+
+    fn config(&self) -> BoxFuture<'_, Option<IndexConfig>> {
+        Box::pin(async {
+            self.config
+                .get_or_init(|| async {
+                    let text = tokio::fs::read(self.root.join("config.json")).await.ok()?;
+                    serde_json::from_slice(&text).ok()
+                })
+                .await
+                .clone()
+        })
+    }
+}
+
+/// Incremental parser of an index file, skipping malformed lines.
+///
+/// Only the incomplete last line of the received data is buffered.
+#[derive(Debug, Default)]
+struct IndexParser {
+    line: Vec<u8>,
+    index: IndexVersions,
+    /// Also parse the feature maps and keep the checksums
+    details: bool,
+}
+
+impl IndexParser {
+    fn new(details: bool) -> Self {
+        Self {
+            details,
+            ..Default::default()
+        }
+    }
+
+    fn push(&mut self, mut chunk: &[u8]) {
+        while let Some(end) = chunk.iter().position(|&b| b == b'\n') {
+            if self.line.is_empty() {
+                self.parse_line(&chunk[..end]);
+            } else {
+                self.line.extend_from_slice(&chunk[..end]);
+                let line = std::mem::take(&mut self.line);
+                self.parse_line(&line);
+            }
+            chunk = &chunk[end + 1..];
+        }
+        self.line.extend_from_slice(chunk);
+    }
+
+    fn parse_line(&mut self, line: &[u8]) {
+        if line.trim_ascii().is_empty() {
+            return;
+        }
+        match serde_json::from_slice::<Version>(line) {
+            Ok(data) if data.vers.len() <= MAX_VERSION_LEN => {
+                // Parsed once here, the analyses only see parsed versions
+                let Some(release) = Release::parse(&data.vers, data.yanked) else {
+                    self.index.malformed += 1;
+                    return;
+                };
+                // Yanked versions may still be locked
+                if let Some(cksum) = data.cksum.filter(|_| self.details) {
+                    self.index.checksums.insert(data.vers.clone(), cksum);
+                }
+                // Nothing selects the features of a yanked version
+                if self.details && !data.yanked {
+                    if let Ok(features) = serde_json::from_slice::<Features>(line) {
+                        let mut map = features.features;
+                        map.extend(features.features2);
+                        self.index.features.insert(data.vers, map);
+                    }
+                }
+                self.index.versions.push(release);
+            }
+            _ => self.index.malformed += 1,
+        }
+        // Drop old versions early, but not for every line
+        if self.index.versions.len() >= 2 * MAX_VERSIONS {
+            self.truncate();
+        }
+    }
+
+    /// Keep at most [`MAX_VERSIONS`], dropping the oldest yanked versions first.
+    ///
+    /// Some crates yank hundreds of nightlies, they must not push out the live versions.
+    fn truncate(&mut self) {
+        let versions = &mut self.index.versions;
+        let excess = versions.len().saturating_sub(MAX_VERSIONS);
+        if excess == 0 {
+            return;
+        }
+        // Live versions are only dropped once no yanked ones are left
+        let mut yanked = versions.iter().filter(|v| v.yanked).count().min(excess);
+        let mut live = excess - yanked;
+        let features = &mut self.index.features;
+        let checksums = &mut self.index.checksums;
+        versions.retain(|v| {
+            let left = if v.yanked { &mut yanked } else { &mut live };
+            if *left == 0 {
+                return true;
+            }
+            *left -= 1;
+            features.remove(&v.raw);
+            checksums.remove(&v.raw);
+            false
+        });
+    }
+
+    fn finish(mut self) -> IndexVersions {
+        let line = std::mem::take(&mut self.line);
+        self.parse_line(&line);
+        self.truncate();
+        self.index
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Version {
+    vers: String,
+    yanked: bool,
+    #[serde(default)]
+    cksum: Option<String>,
+}
+
+/// Features of an index line, `features2` has those using `dep:` and `?/`.
+#[derive(Deserialize, Debug)]
+struct Features {
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    features2: BTreeMap<String, Vec<String>>,
+}
+
+/// Registry with fixed versions for tests, other crates fail.
+///
+/// Versions with a leading `!` are yanked.
+/// The builder methods add latency, feature maps, checksums, and failures.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockClient {
+    crates: std::sync::Mutex<HashMap<String, Vec<String>>>,
+    /// Time until the versions of a crate arrive
+    latency: HashMap<String, std::time::Duration>,
+    /// Feature maps and checksums by crate and version, only returned with the details
+    features: HashMap<String, HashMap<String, BTreeMap<String, Vec<String>>>>,
+    checksums: HashMap<String, HashMap<String, String>>,
+    /// Crates publishing another patch release before each fetch
+    releasing: std::collections::HashSet<String>,
+    /// Crates whose fetches panic
+    panicking: std::collections::HashSet<String>,
+    notices: std::sync::Mutex<Vec<Notice>>,
+    /// Requested crates in order, if they are recorded
+    requests: Option<std::sync::Mutex<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl MockClient {
+    /// Crate of [`MockClient::with_yanked_skew`].
+    pub const YANKED_SKEW: &str = "yanked-skew";
+
+    pub fn new(crates: HashMap<String, Vec<String>>) -> Self {
+        Self {
+            crates: std::sync::Mutex::new(crates),
+            ..Default::default()
+        }
+    }
+
+    /// Adds [`MockClient::YANKED_SKEW`] with 500 yanked versions newer than its 5 live ones,
+    /// `1.0.0` to `1.0.4`.
+    pub fn with_yanked_skew(self) -> Self {
+        let live = (0..5).map(|i| format!("1.0.{i}"));
+        let yanked = (5..505).map(|i| format!("!1.0.{i}"));
+        self.release_all(Self::YANKED_SKEW, live.chain(yanked));
+        self
+    }
+
+    /// The versions of `name` arrive after `latency`, also if it fails.
+    pub fn with_latency(mut self, name: &str, latency: std::time::Duration) -> Self {
+        self.latency.insert(name.into(), latency);
+        self
+    }
+
+    pub fn with_features(
+        mut self,
+        name: &str,
+        version: &str,
+        features: BTreeMap<String, Vec<String>>,
+    ) -> Self {
+        let versions = self.features.entry(name.into()).or_default();
+        versions.insert(version.into(), features);
+        self
+    }
+
+    pub fn with_checksum(mut self, name: &str, version: &str, checksum: &str) -> Self {
+        let versions = self.checksums.entry(name.into()).or_default();
+        versions.insert(version.into(), checksum.into());
+        self
+    }
+
+    /// Each fetch of `name` publishes its next patch release `1.0.n` first.
+    pub fn releasing(mut self, name: &str) -> Self {
+        self.releasing.insert(name.into());
+        self
+    }
+
+    /// Fetches of `name` panic, like a bug in the registry client.
+    pub fn panicking(mut self, name: &str) -> Self {
+        self.panicking.insert(name.into());
+        self
+    }
+
+    /// The next fetch reports `notices`, as a failover between mirrors does.
+    pub fn with_notices(self, notices: Vec<Notice>) -> Self {
+        *self.notices.lock().unwrap() = notices;
+        self
+    }
+
+    /// Record the requested crates, see [`MockClient::requests`].
+    pub fn recording(mut self) -> Self {
+        self.requests = Some(Default::default());
+        self
+    }
+
+    /// Publish `versions` of `name` while the server runs.
+    pub fn release_all(&self, name: &str, versions: impl IntoIterator<Item = String>) {
+        let mut crates = self.crates.lock().unwrap();
+        crates.entry(name.into()).or_default().extend(versions);
+    }
+
+    /// The requested crates, in order.
+    pub fn requests(&self) -> Vec<String> {
+        let requests = self.requests.as_ref().expect("requests are not recorded");
+        requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl RegistryClient for MockClient {
+    fn index<'a>(&'a self, name: &'a str, details: bool) -> BoxFuture<'a, Result<IndexVersions>> {
+        assert!(!self.panicking.contains(name), "the registry exploded");
+        if let Some(requests) = &self.requests {
+            requests.lock().unwrap().push(name.into());
+        }
+        let versions = {
+            let mut crates = self.crates.lock().unwrap();
+            if self.releasing.contains(name) {
+                let versions = crates.entry(name.into()).or_default();
+                versions.push(format!("1.0.{}", versions.len()));
+            }
+            crates.get(name).cloned()
+        };
+        let latency = self.latency.get(name).copied();
+        Box::pin(async move {
+            if let Some(latency) = latency {
+                tokio::time::sleep(latency).await;
+            }
+            let versions =
+                versions.ok_or_else(|| ServerError::NotFound(format!("Crate {name:?}")))?;
+            let releases = versions
+                .iter()
+                .filter_map(|v| match v.strip_prefix('!') {
+                    Some(v) => Release::parse(v, true),
+                    None => Release::parse(v, false),
+                })
+                .collect::<Vec<_>>();
+            fn of<T: Clone + Default>(map: &HashMap<String, T>, name: &str, details: bool) -> T {
+                map.get(name)
+                    .filter(|_| details)
+                    .cloned()
+                    .unwrap_or_default()
+            }
+            Ok(IndexVersions {
+                malformed: versions.len() - releases.len(),
+                versions: releases,
+                features: of(&self.features, name, details),
+                checksums: of(&self.checksums, name, details),
+            })
+        })
+    }
+
+    fn notices(&self) -> Vec<Notice> {
+        std::mem::take(&mut self.notices.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Versions as written in the index.
+    fn raw(versions: &[Release]) -> Vec<&str> {
+        versions.iter().map(|v| v.raw.as_str()).collect()
+    }
+
+    /// Parse the whole index at once.
+    fn parse_index(text: &str) -> IndexVersions {
+        let mut parser = IndexParser::default();
+        parser.push(text.as_bytes());
+        parser.finish()
+    }
+
+    /// Registry serving `body` for every crate, with or without a content length.
+    async fn serve(body: String, content_length: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let header = if content_length {
+                    format!("content-length: {}\r\n", body.len())
+                } else {
+                    String::new()
+                };
+                let response = format!("HTTP/1.1 200 OK\r\n{header}connection: close\r\n\r\n");
+                let _ = stream.write_all(response.as_bytes()).await;
+                // Sent in pieces that split the lines
+                for chunk in body.as_bytes().chunks(1000) {
+                    let _ = stream.write_all(chunk).await;
+                }
+            }
+        });
+        endpoint
+    }
+
+    fn index(lines: usize) -> String {
+        (0..lines)
+            .map(|i| format!("{{\"vers\":\"0.0.{i}\",\"yanked\":false,\"deps\":[]}}\n"))
+            .collect()
+    }
+
+    #[test]
+    fn skips_malformed_lines() {
+        let text = concat!(
+            "{\"vers\":\"1.0.0\",\"yanked\":false}\n",
+            "{\"vers\":\"1.0.1\",\"yanked\":\n",
+            "not json at all\n",
+            "\n",
+            "{\"vers\":\"1.0.2\",\"yanked\":true}\n",
+            "{\"vers\":1,\"yanked\":false}\n",
+            "{\"vers\":\"1.1.0\",\"yanked\":false,\"deps\":[]}\n",
+            "{\"vers\":\"1.2\",\"yanked\":false}\n",
+        );
+        let index = parse_index(text);
+        assert_eq!(raw(&index.versions), ["1.0.0", "1.0.2", "1.1.0"]);
+        assert!(index.versions[1].yanked);
+        // Including the version that is not semver
+        assert_eq!(index.malformed, 4);
+    }
+
+    #[test]
+    fn parses_the_feature_maps() {
+        let text = concat!(
+            "{\"vers\":\"1.0.0\",\"yanked\":false,\"features\":{\"std\":[]}}\n",
+            "{\"vers\":\"1.1.0\",\"yanked\":false,\"features\":{\"std\":[\"alloc\"],\"alloc\":[]},",
+            "\"features2\":{\"derive\":[\"dep:serde_derive\"]}}\n",
+            "{\"vers\":\"1.2.0\",\"yanked\":true,\"features\":{\"std\":[]}}\n",
+        );
+        assert!(parse_index(text).features.is_empty());
+
+        let mut parser = IndexParser::new(true);
+        parser.push(text.as_bytes());
+        let index = parser.finish();
+        assert_eq!(raw(&index.versions), ["1.0.0", "1.1.0", "1.2.0"]);
+        // Nothing selects a yanked version
+        assert!(!index.features.contains_key("1.2.0"));
+        let features = |version: &str| index.features[version].keys().cloned().collect::<Vec<_>>();
+        assert_eq!(features("1.0.0"), ["std"]);
+        assert_eq!(features("1.1.0"), ["alloc", "derive", "std"]);
+        assert_eq!(index.features["1.1.0"]["derive"], ["dep:serde_derive"]);
+    }
+
+    #[test]
+    fn keeps_the_checksums_of_all_versions() {
+        let text = concat!(
+            "{\"vers\":\"1.0.0\",\"yanked\":false,\"cksum\":\"aa11\"}\n",
+            "{\"vers\":\"1.1.0\",\"yanked\":true,\"cksum\":\"bb22\"}\n",
+            "{\"vers\":\"1.2.0\",\"yanked\":false}\n",
+        );
+        assert!(parse_index(text).checksums.is_empty());
+
+        let mut parser = IndexParser::new(true);
+        parser.push(text.as_bytes());
+        let index = parser.finish();
+        assert_eq!(raw(&index.versions), ["1.0.0", "1.1.0", "1.2.0"]);
+        // Yanked versions may still be locked
+        assert_eq!(index.checksums["1.0.0"], "aa11");
+        assert_eq!(index.checksums["1.1.0"], "bb22");
+        assert!(!index.checksums.contains_key("1.2.0"));
+    }
+
+    #[test]
+    fn bounds_the_versions() {
+        let long = format!(
+            "{{\"vers\":\"1.0.0-{}\",\"yanked\":false}}",
+            "a".repeat(200)
+        );
+        let index = parse_index(&long);
+        assert!(index.versions.is_empty());
+        assert_eq!(index.malformed, 1);
+
+        let text = (0..MAX_VERSIONS + 10)
+            .map(|i| format!("{{\"vers\":\"0.0.{i}\",\"yanked\":false}}\n"))
+            .collect::<String>();
+        let index = parse_index(&text);
+        assert_eq!(index.versions.len(), MAX_VERSIONS);
+        assert_eq!(index.versions[0].raw, "0.0.10", "the oldest are dropped");
+    }
+
+    #[test]
+    fn drops_yanked_versions_first() {
+        let line =
+            |vers: String, yanked: bool| format!("{{\"vers\":\"{vers}\",\"yanked\":{yanked}}}\n");
+        let live = (0..5).map(|i| line(format!("1.0.{i}"), false));
+        let yanked = (0..MAX_VERSIONS).map(|i| line(format!("1.1.{i}"), true));
+        let index = parse_index(&live.chain(yanked).collect::<String>());
+        assert_eq!(index.versions.len(), MAX_VERSIONS);
+        assert_eq!(
+            raw(&index.versions[..5]),
+            ["1.0.0", "1.0.1", "1.0.2", "1.0.3", "1.0.4"]
+        );
+        assert_eq!(
+            index.versions[5].raw, "1.1.5",
+            "the oldest yanked are dropped"
+        );
+        assert!(index.versions[5..].iter().all(|v| v.yanked));
+
+        // Without yanked versions the oldest live ones go
+        let live = (0..2 * MAX_VERSIONS + 3).map(|i| line(format!("0.0.{i}"), false));
+        let yanked = line("1.0.0".into(), true);
+        let index = parse_index(&live.chain([yanked]).collect::<String>());
+        assert_eq!(index.versions.len(), MAX_VERSIONS);
+        assert!(index.versions.iter().all(|v| !v.yanked));
+        assert_eq!(
+            index.versions.last().unwrap().raw,
+            format!("0.0.{}", 2 * MAX_VERSIONS + 2)
+        );
+    }
+
+    #[test]
+    fn buffers_only_the_last_line() {
+        let text = index(50_000);
+        let mut parser = IndexParser::default();
+        for chunk in text.as_bytes().chunks(333) {
+            parser.push(chunk);
+            assert!(parser.line.len() < 333);
+            assert!(parser.index.versions.len() < 2 * MAX_VERSIONS);
+        }
+        let index = parser.finish();
+        assert_eq!(index.versions.len(), MAX_VERSIONS);
+        assert_eq!(index.versions.last().unwrap().raw, "0.0.49999");
+        assert_eq!(index.malformed, 0);
+    }
+
+    #[tokio::test]
+    async fn fetches_large_indices() {
+        let text = index(50_000);
+        let len = text.len();
+        for content_length in [true, false] {
+            let endpoint = serve(text.clone(), content_length).await;
+            let index = client(&endpoint, "", len)
+                .versions("windows")
+                .await
+                .unwrap();
+            assert_eq!(index.versions.len(), MAX_VERSIONS);
+            assert_eq!(
+                index.versions[0].raw,
+                format!("0.0.{}", 50_000 - MAX_VERSIONS)
+            );
+            assert_eq!(index.versions.last().unwrap().raw, "0.0.49999");
+
+            let err = client(&endpoint, "", len - 1)
+                .versions("windows")
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("The index of windows is larger than {} bytes", len - 1)
+            );
+        }
+    }
+
+    #[test]
+    fn index_paths() {
+        for (name, path) in [
+            ("a", "1/a"),
+            ("io", "2/io"),
+            ("syn", "3/s/syn"),
+            ("toml", "to/ml/toml"),
+            ("serde", "se/rd/serde"),
+            // Lowercase like the files of the index
+            ("A", "1/a"),
+            ("Io", "2/io"),
+            ("SYN", "3/s/syn"),
+            ("Inflector", "in/fl/inflector"),
+            // Separators are kept, the index has no normalization of them
+            ("a_b", "3/a/a_b"),
+            ("a-b", "3/a/a-b"),
+            ("serde_json", "se/rd/serde_json"),
+            ("serde-json", "se/rd/serde-json"),
+            ("x1", "2/x1"),
+        ] {
+            assert_eq!(index_path(name).unwrap(), path, "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_names_before_slicing() {
+        let long = "a".repeat(65);
+        for name in [
+            "",
+            "../etc",
+            "se/rde",
+            "serde json",
+            "1serde",
+            "_serde",
+            "-serde",
+            "é",
+            "ñu",
+            "日本語",
+            "serdé",
+            "a\0",
+            &long,
+        ] {
+            let err = index_path(name).unwrap_err();
+            assert!(
+                matches!(&err, ServerError::InvalidName(n) if n == name),
+                "{err}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_local_indices() {
+        let root = std::env::temp_dir().join(format!("crates-io-index-{}", std::process::id()));
+        for (path, lines) in [
+            ("1/a", 1),
+            ("3/s/syn", 2),
+            ("se/rd/serde", 3),
+            ("ca/rg/cargo", 100),
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, index(lines)).unwrap();
+        }
+        let endpoint = Uri::from_file_path(&root).unwrap();
+        let client = client(endpoint.as_str(), "", 1000);
+        let a = client.versions("a").await;
+        let syn = client.versions("syn").await;
+        // The git index has lowercase names
+        let serde = client.versions("Serde").await;
+        let missing = client.versions("tokio").await;
+        let large = client.versions("cargo").await;
+        let invalid = client.versions("../a").await;
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(raw(&a.unwrap().versions), ["0.0.0"]);
+        assert_eq!(raw(&syn.unwrap().versions), ["0.0.0", "0.0.1"]);
+        assert_eq!(serde.unwrap().versions.len(), 3);
+        let missing = missing.unwrap_err();
+        assert!(missing.to_string().starts_with("The index file of tokio"));
+        assert!(matches!(missing, ServerError::NotFound(_)), "{missing}");
+        assert_eq!(
+            large.unwrap_err().to_string(),
+            "The index of cargo is larger than 1000 bytes"
+        );
+        assert!(invalid.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_the_config_and_modification_times_of_local_indices() {
+        let root =
+            std::env::temp_dir().join(format!("crates-io-index-bare-{}", std::process::id()));
+        let path = root.join("se/rd/serde");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, index(2)).unwrap();
+        let written = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        // A bare path without config.json
+        let client = client(root.to_str().unwrap(), "", 1000);
+        assert_eq!(client.versions("serde").await.unwrap().versions.len(), 2);
+        assert!(client.config().await.is_none());
+        assert!(!client.modified_since("serde", written).await);
+        assert!(client.modified_since("serde", written - 10).await);
+        assert!(client.modified_since("tokio", written).await);
+
+        std::fs::write(
+            root.join("config.json"),
+            r#"{"dl":"https://static.crates.io/crates","api":"https://crates.io"}"#,
+        )
+        .unwrap();
+        let client = super::client(root.to_str().unwrap(), "", 1000);
+        let config = client.config().await;
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(config.unwrap().api.as_deref(), Some("https://crates.io"));
+        // HTTP registries have neither
+        let sparse = super::client("https://index.crates.io", "", 1000);
+        assert!(sparse.config().await.is_none());
+        assert!(!sparse.modified_since("serde", 0).await);
+    }
+
+    proptest! {
+        #[test]
+        fn parse_index_never_panics(text in "(\\PC{0,40}\n){0,8}") {
+            let index = parse_index(&text);
+            prop_assert!(index.versions.len() <= MAX_VERSIONS);
+            prop_assert!(index.versions.iter().all(|v| v.raw.len() <= MAX_VERSION_LEN));
+        }
+
+        #[test]
+        fn parse_index_keeps_valid_lines(
+            versions in prop::collection::vec(("(0|[1-9][0-9]{0,2})(\\.(0|[1-9][0-9]{0,2})){2}", any::<bool>()), 0..20),
+            garbage in prop::collection::vec("[^\n]{0,20}", 0..20),
+        ) {
+            let mut lines = versions
+                .iter()
+                .map(|(vers, yanked)| format!("{{\"vers\":\"{vers}\",\"yanked\":{yanked}}}"))
+                .collect::<Vec<_>>();
+            // Garbage in front of each line never parses as a version
+            lines.extend(garbage.iter().map(|g| format!("x{g}")));
+            let index = parse_index(&lines.join("\n"));
+            let expected = versions
+                .iter()
+                .map(|(vers, yanked)| (vers.as_str(), *yanked))
+                .collect::<Vec<_>>();
+            let parsed = index
+                .versions
+                .iter()
+                .map(|v| (v.raw.as_str(), v.yanked))
+                .collect::<Vec<_>>();
+            prop_assert_eq!(parsed, expected);
+            prop_assert_eq!(index.malformed, garbage.len());
+        }
+
+        #[test]
+        fn parse_index_ignores_chunk_boundaries(
+            text in "(\\PC{0,40}\n){0,8}",
+            size in 1..16usize,
+        ) {
+            let mut parser = IndexParser::default();
+            for chunk in text.as_bytes().chunks(size) {
+                parser.push(chunk);
+            }
+            let chunked = parser.finish();
+            let whole = parse_index(&text);
+            prop_assert_eq!(chunked.versions, whole.versions);
+            prop_assert_eq!(chunked.malformed, whole.malformed);
+        }
+    }
+}
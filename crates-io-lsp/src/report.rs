@@ -0,0 +1,403 @@
+use std::collections::{BTreeMap, HashMap};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::Uri;
+
+use crate::config::ScanScope;
+use crate::index::Occurrence;
+use crate::manifest::DependencyKind;
+use crate::version::{self, Classification, Release, Releases};
+
+/// Command generating the markdown [`Report`] of all indexed manifests,
+/// the optional argument is [`ReportArgs`].
+pub const REPORT: &str = "crates-io.report";
+
+/// Arguments of [`REPORT`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportArgs {
+    /// Overrides the `scanScope` of the configuration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<ScanScope>,
+    /// A manifest, file, or directory selecting the workspace context,
+    /// by default the one of the active document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Uri>,
+}
+
+/// Health of the registry dependencies of every manifest seen in the project.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub manifests: Vec<ManifestReport>,
+    /// Declarations of all manifests by the kind of their section
+    pub kinds: BTreeMap<DependencyKind, Tally>,
+    /// Crates declared with different requirements, ordered by name
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Number of declarations with a version requirement by classification.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub dependencies: usize,
+    pub latest: usize,
+    pub compatible: usize,
+    pub incompatible: usize,
+    pub unknown: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestReport {
+    /// Path relative to the directory containing all manifests
+    pub path: String,
+    /// Declarations with a version requirement
+    pub dependencies: usize,
+    pub latest: usize,
+    pub compatible: usize,
+    pub incompatible: usize,
+    /// Not matching any version or not fetched
+    pub unknown: usize,
+    /// The outdated dependency most releases behind
+    pub oldest: Option<Outdated>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outdated {
+    pub package: String,
+    pub req: String,
+    pub latest: Version,
+    /// Releases from the oldest matching version up to the latest, like the update actions count them
+    pub behind: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub package: String,
+    /// Manifest paths with their requirement
+    pub requirements: Vec<(String, String)>,
+}
+
+/// Classify the declarations of the `manifests` by the known `versions` of the crates.
+pub fn build(
+    manifests: &[(&Uri, Vec<(&str, &Occurrence)>)],
+    versions: &HashMap<String, Releases>,
+    include_prerelease: bool,
+) -> Report {
+    let paths = relative_paths(manifests.iter().map(|(uri, _)| *uri));
+    let mut report = Report::default();
+    let mut requirements = BTreeMap::<&str, Vec<(String, String)>>::new();
+    for ((_, occurrences), path) in manifests.iter().zip(paths) {
+        let mut manifest = ManifestReport {
+            path: path.clone(),
+            ..Default::default()
+        };
+        for (name, occurrence) in occurrences {
+            // Path and inherited dependencies have no requirement of their own
+            let Some(req) = &occurrence.req else {
+                continue;
+            };
+            manifest.dependencies += 1;
+            let mut tally = Tally::default();
+            let entry = (path.clone(), req.clone());
+            let declared = requirements.entry(name).or_default();
+            if !declared.contains(&entry) {
+                declared.push(entry);
+            }
+
+            match versions.get(*name).map(|versions| {
+                (
+                    version::classify(req, versions, include_prerelease),
+                    versions,
+                )
+            }) {
+                Some((Classification::Any | Classification::Latest, _)) => tally.latest += 1,
+                Some((Classification::OutdatedCompatible { latest, .. }, versions)) => {
+                    tally.compatible += 1;
+                    manifest.note_outdated(name, req, latest, versions);
+                }
+                Some((Classification::OutdatedIncompatible { latest, .. }, versions)) => {
+                    tally.incompatible += 1;
+                    manifest.note_outdated(name, req, latest, versions);
+                }
+                Some((Classification::Unknown, _)) | None => tally.unknown += 1,
+            }
+            manifest.latest += tally.latest;
+            manifest.compatible += tally.compatible;
+            manifest.incompatible += tally.incompatible;
+            manifest.unknown += tally.unknown;
+            if let Some((kind, _)) = DependencyKind::of_section(&occurrence.section) {
+                let kind = report.kinds.entry(kind).or_default();
+                kind.dependencies += 1;
+                kind.latest += tally.latest;
+                kind.compatible += tally.compatible;
+                kind.incompatible += tally.incompatible;
+                kind.unknown += tally.unknown;
+            }
+        }
+        report.manifests.push(manifest);
+    }
+    report.conflicts = requirements
+        .into_iter()
+        .filter(|(_, declared)| declared.iter().any(|(_, req)| *req != declared[0].1))
+        .map(|(package, requirements)| Conflict {
+            package: package.to_string(),
+            requirements,
+        })
+        .collect();
+    report
+}
+
+impl ManifestReport {
+    /// Keep the dependency if it is further behind than the oldest so far.
+    fn note_outdated(&mut self, package: &str, req: &str, latest: Version, versions: &[Release]) {
+        let behind = version::jump(req, &latest, versions).map_or(0, |j| j.skipped.len() + 1);
+        if self.oldest.as_ref().is_none_or(|o| o.behind < behind) {
+            self.oldest = Some(Outdated {
+                package: package.to_string(),
+                req: req.to_string(),
+                latest,
+                behind,
+            });
+        }
+    }
+}
+
+impl Report {
+    /// Markdown document with a table of the manifests and one of the differing requirements.
+    pub fn markdown(&self) -> String {
+        let total = |count: fn(&ManifestReport) -> usize| -> usize {
+            self.manifests.iter().map(count).sum()
+        };
+        let mut text = "# Workspace report\n\n".to_string();
+        text += &format!(
+            "{} manifests · {} dependencies · {} up to date · {} outdated compatible · \
+            {} outdated incompatible · {} unknown\n",
+            self.manifests.len(),
+            total(|m| m.dependencies),
+            total(|m| m.latest),
+            total(|m| m.compatible),
+            total(|m| m.incompatible),
+            total(|m| m.unknown),
+        );
+
+        text += "\n## Manifests\n\n";
+        text += "| Manifest | Dependencies | Up to date | Compatible | Incompatible | Unknown | Oldest outdated |\n";
+        text += "|---|---:|---:|---:|---:|---:|---|\n";
+        for m in &self.manifests {
+            let oldest = m.oldest.as_ref().map_or("–".to_string(), |o| {
+                let releases = if o.behind == 1 { "release" } else { "releases" };
+                format!(
+                    "{} {} → {} ({} {releases} behind)",
+                    o.package, o.req, o.latest, o.behind
+                )
+            });
+            text += &format!(
+                "| `{}` | {} | {} | {} | {} | {} | {oldest} |\n",
+                m.path, m.dependencies, m.latest, m.compatible, m.incompatible, m.unknown
+            );
+        }
+
+        text += "\n## Dependency kinds\n\n";
+        text += "| Kind | Dependencies | Up to date | Compatible | Incompatible | Unknown |\n";
+        text += "|---|---:|---:|---:|---:|---:|\n";
+        for (kind, t) in &self.kinds {
+            text += &format!(
+                "| {} | {} | {} | {} | {} | {} |\n",
+                kind.name(),
+                t.dependencies,
+                t.latest,
+                t.compatible,
+                t.incompatible,
+                t.unknown
+            );
+        }
+
+        text += "\n## Differing requirements\n\n";
+        if self.conflicts.is_empty() {
+            text += "_Every crate is required the same way._\n";
+            return text;
+        }
+        text += "| Crate | Requirements |\n";
+        text += "|---|---|\n";
+        for conflict in &self.conflicts {
+            let requirements = conflict
+                .requirements
+                .iter()
+                .map(|(path, req)| format!("`{req}` in `{path}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            text += &format!("| {} | {requirements} |\n", conflict.package);
+        }
+        text
+    }
+}
+
+/// Paths of the manifests relative to the deepest directory containing all of them.
+fn relative_paths<'a>(uris: impl Iterator<Item = &'a Uri>) -> Vec<String> {
+    let paths = uris
+        .map(|uri| {
+            uri.path()
+                .segments()
+                .map(|s| s.decode().into_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let Some(first) = paths.first() else {
+        return Vec::new();
+    };
+    // The file name is never part of the shared directory
+    let mut shared = first.len().saturating_sub(1);
+    for path in &paths[1..] {
+        shared = shared
+            .min(path.len().saturating_sub(1))
+            .min(first.iter().zip(path).take_while(|(a, b)| a == b).count());
+    }
+    paths.iter().map(|path| path[shared..].join("/")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::WorkspaceIndex;
+    use crate::manifest;
+    use crate::text::{Encoding, Source};
+
+    fn releases(versions: &[&str]) -> Releases {
+        versions
+            .iter()
+            .filter_map(|v| Release::parse(v, false))
+            .collect()
+    }
+
+    /// Workspace of two members and the root, the versions of `unfetched` are not known.
+    fn fixture() -> Report {
+        let mut index = WorkspaceIndex::default();
+        for (path, text) in [
+            (
+                "ws/Cargo.toml",
+                "[workspace]\nmembers = [\"app\", \"lib\"]\n\n[workspace.dependencies]\nlog = \"0.4\"\n",
+            ),
+            (
+                "ws/app/Cargo.toml",
+                "[dependencies]\nserde = \"1.0.100\"\nrand = \"0.7\"\nlog = { workspace = true }\n\
+                lib = { path = \"../lib\" }\nunfetched = \"1\"\n",
+            ),
+            (
+                "ws/lib/Cargo.toml",
+                "[dependencies]\nserde = \"1\"\nlog = \"0.4.22\"\n\n[dev-dependencies]\nrand = \"0.8\"\n",
+            ),
+        ] {
+            let uri = format!("file:///{path}").parse().unwrap();
+            let (manifest, None) = manifest::parse(text) else {
+                panic!("{text}");
+            };
+            index.update(&uri, &Source::new(text, Encoding::Utf8), &manifest);
+        }
+        let versions = HashMap::from([
+            ("serde".into(), releases(&["1.0.100", "1.0.150", "1.0.200"])),
+            (
+                "rand".into(),
+                releases(&["0.7.3", "0.8.0", "0.8.5", "0.9.0-alpha.1", "0.9.0", "0.9.1"]),
+            ),
+            ("log".into(), releases(&["0.4.20", "0.4.22"])),
+        ]);
+        build(&index.manifests(), &versions, false)
+    }
+
+    #[test]
+    fn counts_the_classifications() {
+        let report = fixture();
+        let paths = report
+            .manifests
+            .iter()
+            .map(|m| m.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["Cargo.toml", "app/Cargo.toml", "lib/Cargo.toml"]);
+
+        let app = &report.manifests[1];
+        assert_eq!(
+            (
+                app.dependencies,
+                app.latest,
+                app.compatible,
+                app.incompatible,
+                app.unknown
+            ),
+            (3, 0, 1, 1, 1)
+        );
+        let kinds = report
+            .kinds
+            .iter()
+            .map(|(kind, t)| {
+                let counts = (
+                    t.dependencies,
+                    t.latest,
+                    t.compatible,
+                    t.incompatible,
+                    t.unknown,
+                );
+                (kind.name(), counts)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                ("normal", (5, 2, 1, 1, 1)),
+                ("dev", (1, 0, 0, 1, 0)),
+                ("workspace", (1, 1, 0, 0, 0))
+            ]
+        );
+        // Pre-releases are not counted
+        assert_eq!(
+            app.oldest,
+            Some(Outdated {
+                package: "rand".into(),
+                req: "0.7".into(),
+                latest: Version::new(0, 9, 1),
+                behind: 4,
+            })
+        );
+        let conflicts = report
+            .conflicts
+            .iter()
+            .map(|c| c.package.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(conflicts, ["log", "rand", "serde"]);
+    }
+
+    #[test]
+    fn renders_the_markdown() {
+        assert_eq!(
+            fixture().markdown(),
+            "# Workspace report\n\n\
+            3 manifests · 7 dependencies · 3 up to date · 1 outdated compatible · 2 outdated incompatible · 1 unknown\n\n\
+            ## Manifests\n\n\
+            | Manifest | Dependencies | Up to date | Compatible | Incompatible | Unknown | Oldest outdated |\n\
+            |---|---:|---:|---:|---:|---:|---|\n\
+            | `Cargo.toml` | 1 | 1 | 0 | 0 | 0 | – |\n\
+            | `app/Cargo.toml` | 3 | 0 | 1 | 1 | 1 | rand 0.7 → 0.9.1 (4 releases behind) |\n\
+            | `lib/Cargo.toml` | 3 | 2 | 0 | 1 | 0 | rand 0.8 → 0.9.1 (3 releases behind) |\n\n\
+            ## Dependency kinds\n\n\
+            | Kind | Dependencies | Up to date | Compatible | Incompatible | Unknown |\n\
+            |---|---:|---:|---:|---:|---:|\n\
+            | normal | 5 | 2 | 1 | 1 | 1 |\n\
+            | dev | 1 | 0 | 0 | 1 | 0 |\n\
+            | workspace | 1 | 1 | 0 | 0 | 0 |\n\n\
+            ## Differing requirements\n\n\
+            | Crate | Requirements |\n\
+            |---|---|\n\
+            | log | `0.4` in `Cargo.toml`, `0.4.22` in `lib/Cargo.toml` |\n\
+            | rand | `0.7` in `app/Cargo.toml`, `0.8` in `lib/Cargo.toml` |\n\
+            | serde | `1.0.100` in `app/Cargo.toml`, `1` in `lib/Cargo.toml` |\n"
+        );
+    }
+
+    #[test]
+    fn relative_to_the_shared_directory() {
+        let uris = ["file:///ws/a/Cargo.toml", "file:///ws/a/b/Cargo.toml"]
+            .map(|u| u.parse::<Uri>().unwrap());
+        assert_eq!(relative_paths(uris.iter()), ["Cargo.toml", "b/Cargo.toml"]);
+        let single = "file:///ws/Cargo.toml".parse::<Uri>().unwrap();
+        assert_eq!(relative_paths([&single].into_iter()), ["Cargo.toml"]);
+    }
+}
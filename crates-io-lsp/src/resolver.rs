@@ -0,0 +1,285 @@
+use std::ops::Range;
+
+use toml::Spanned;
+use tower_lsp_server::lsp_types::{
+    self, Diagnostic, DiagnosticRelatedInformation, Location, NumberOrString, TextEdit, Uri,
+};
+
+use crate::config::Config;
+use crate::inherit;
+use crate::manifest::{table_headers, SpannedManifest};
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// Diagnostic code of [`lint`], in the root manifest the data contains the edit to resolver 2.
+pub const RESOLVER_FEATURES: &str = "resolver-features";
+
+/// Resolver used by cargo for a workspace root or standalone package.
+///
+/// An explicit `resolver` wins, otherwise it is the default of the edition of the root package:
+/// `1` for 2015 and 2018, `2` for 2021, and `3` for 2024.
+/// Packages without an edition are 2015, virtual workspaces have none and also default to `1`.
+pub fn effective<'a>(resolver: Option<&'a str>, edition: Option<&str>) -> &'a str {
+    resolver.unwrap_or(match edition {
+        Some("2024") => "3",
+        Some("2021") => "2",
+        _ => "1",
+    })
+}
+
+/// Resolver of a workspace root or standalone package, see [`effective`].
+pub fn resolver(root: &SpannedManifest) -> &str {
+    let (resolver, edition) = settings(root);
+    effective(
+        resolver.map(|r| r.get_ref().as_str()),
+        edition.as_ref().map(|(e, _)| e.as_str()),
+    )
+}
+
+/// Warn about features using `dep:` or `?/` if the workspace uses resolver 1.
+///
+/// `root` is the workspace root if it is another manifest, `members` are checked in the root.
+pub fn lint(
+    manifest: &SpannedManifest,
+    source: &Source,
+    root: Option<(&Uri, &SpannedManifest, &Source)>,
+    members: &[&SpannedManifest],
+    config: &Config,
+) -> Option<Diagnostic> {
+    let (root_manifest, root_source) = root.map_or((manifest, source), |(_, m, s)| (m, s));
+    if resolver(root_manifest) != "1" {
+        return None;
+    }
+    let (resolver, edition) = settings(root_manifest);
+    // The setting that selects resolver 1, if any
+    let setting = resolver
+        .map(|r| r.span())
+        .or(edition.map(|(_, span)| span))
+        .and_then(|span| root_source.range(span));
+
+    let message = Message::new(Key::ResolverFeatures).render(config.language);
+    let diagnostic = |range| Diagnostic {
+        range,
+        severity: Some(config.severity.resolver_features.into()),
+        code: Some(NumberOrString::String(RESOLVER_FEATURES.into())),
+        source: Some(config.source(RESOLVER_FEATURES)),
+        message,
+        ..Default::default()
+    };
+    match root {
+        // Point the member at the root, which is fixed there
+        Some((root_uri, _, _)) => {
+            let feature = syntax_feature(manifest)?;
+            Some(Diagnostic {
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: root_uri.clone(),
+                        range: setting.unwrap_or_default(),
+                    },
+                    message: Message::new(Key::WorkspaceResolver).render(config.language),
+                }]),
+                ..diagnostic(source.range(feature.span())?)
+            })
+        }
+        None => {
+            if syntax_feature(manifest).is_none()
+                && !members.iter().any(|m| syntax_feature(m).is_some())
+            {
+                return None;
+            }
+            let header =
+                root_header(&source.text, manifest).and_then(|header| source.range(header));
+            let edit = resolver_edit(source, manifest);
+            Some(Diagnostic {
+                data: edit.and_then(|edit| serde_json::to_value(edit).ok()),
+                ..diagnostic(setting.or(header)?)
+            })
+        }
+    }
+}
+
+/// Edition with the span of its value.
+type Edition = (String, Range<usize>);
+
+/// Explicit resolver and edition of a root manifest, `[workspace]` takes precedence.
+///
+/// An inherited edition is looked up in `[workspace.package]`.
+fn settings(root: &SpannedManifest) -> (Option<&Spanned<String>>, Option<Edition>) {
+    let package = root.package.as_ref().map(|p| p.get_ref());
+    let resolver = root
+        .workspace
+        .as_ref()
+        .and_then(|w| w.resolver.as_ref())
+        .or(package.and_then(|p| p.resolver.as_ref()));
+    let edition = package
+        .and_then(|p| inherit::resolve(p, Some(root)).edition)
+        .and_then(|edition| Some((edition.value?, edition.span)));
+    (resolver, edition)
+}
+
+/// First feature value using `dep:` or `?/`.
+fn syntax_feature(manifest: &SpannedManifest) -> Option<&Spanned<String>> {
+    manifest
+        .features
+        .values()
+        .flatten()
+        .find(|v| v.get_ref().starts_with("dep:") || v.get_ref().contains("?/"))
+}
+
+/// Header of the table a resolver belongs to: `[workspace]` if there is one, otherwise `[package]`.
+fn root_header(text: &str, manifest: &SpannedManifest) -> Option<Range<usize>> {
+    let table = if manifest.workspace.is_some() {
+        "workspace"
+    } else {
+        "package"
+    };
+    table_headers(text)
+        .into_iter()
+        .find(|(name, _)| name == table)
+        .map(|(_, header)| header)
+}
+
+/// Edit setting `resolver = "2"`, replacing an explicit resolver.
+fn resolver_edit(source: &Source, manifest: &SpannedManifest) -> Option<TextEdit> {
+    let text = source.text.as_str();
+    if let Some(resolver) = settings(manifest).0 {
+        return Some(TextEdit {
+            range: source.range(resolver.span())?,
+            new_text: "\"2\"".into(),
+        });
+    }
+    let header = root_header(text, manifest)?;
+    let (offset, new_text) = match text[header.end..].find('\n') {
+        Some(i) => (header.end + i + 1, "resolver = \"2\"\n"),
+        None => (text.len(), "\nresolver = \"2\""),
+    };
+    let pos = source.position(offset)?;
+    Some(TextEdit {
+        range: lsp_types::Range {
+            start: pos,
+            end: pos,
+        },
+        new_text: new_text.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::Encoding;
+
+    fn parse(text: &str) -> SpannedManifest {
+        toml::from_str(text).unwrap()
+    }
+
+    /// Line and start column of the diagnostic with the edit of the quick fix.
+    fn lint_at(text: &str, members: &[&str]) -> Option<((u32, u32), Option<TextEdit>)> {
+        let manifest = parse(text);
+        let members = members.iter().map(|m| parse(m)).collect::<Vec<_>>();
+        let members = members.iter().collect::<Vec<_>>();
+        let diagnostic = lint(
+            &manifest,
+            &Source::new(text, Encoding::Utf8),
+            None,
+            &members,
+            &Config::default(),
+        )?;
+        let edit = diagnostic
+            .data
+            .map(|data| serde_json::from_value(data).unwrap());
+        let start = diagnostic.range.start;
+        Some(((start.line, start.character), edit))
+    }
+
+    fn insert(line: u32, new_text: &str) -> TextEdit {
+        let pos = lsp_types::Position::new(line, 0);
+        TextEdit::new(lsp_types::Range::new(pos, pos), new_text.into())
+    }
+
+    #[test]
+    fn defaults() {
+        for (resolver, edition, expected) in [
+            (None, None, "1"),
+            (None, Some("2015"), "1"),
+            (None, Some("2018"), "1"),
+            (None, Some("2021"), "2"),
+            (None, Some("2024"), "3"),
+            (Some("2"), Some("2018"), "2"),
+            (Some("1"), Some("2021"), "1"),
+            (Some("3"), None, "3"),
+        ] {
+            assert_eq!(
+                effective(resolver, edition),
+                expected,
+                "{resolver:?} {edition:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn workspace_settings_take_precedence() {
+        let package = "[package]\nname = \"a\"\nedition = \"2021\"\n";
+        assert_eq!(resolver(&parse(package)), "2");
+        let text = format!("{package}resolver = \"1\"\n");
+        assert_eq!(resolver(&parse(&text)), "1");
+        let text = format!("{text}\n[workspace]\nresolver = \"3\"\n");
+        assert_eq!(resolver(&parse(&text)), "3");
+        // Virtual workspaces have no edition
+        assert_eq!(resolver(&parse("[workspace]\nmembers = [\"a\"]\n")), "1");
+        let inherited = "[package]\nname = \"a\"\nedition = { workspace = true }\n\n[workspace.package]\nedition = \"2024\"\n";
+        assert_eq!(resolver(&parse(inherited)), "3");
+    }
+
+    #[test]
+    fn flags_feature_syntax_with_resolver_1() {
+        let features = "\n[features]\njson = [\"dep:serde_json\"]\n";
+        let package = format!("[package]\nname = \"a\"\nedition = \"2018\"\n{features}");
+        assert_eq!(
+            lint_at(&package, &[]),
+            Some(((2, 10), Some(insert(1, "resolver = \"2\"\n"))))
+        );
+        let modern = package.replace("2018", "2021");
+        assert_eq!(lint_at(&modern, &[]), None);
+        let plain = "[package]\nname = \"a\"\n\n[features]\njson = [\"serde_json\"]\n";
+        assert_eq!(lint_at(plain, &[]), None);
+
+        // An explicit resolver is replaced
+        let explicit = format!("[package]\nname = \"a\"\nresolver = \"1\"\n{features}");
+        let ((line, column), edit) = lint_at(&explicit, &[]).unwrap();
+        assert_eq!((line, column), (2, 11));
+        let edit = edit.unwrap();
+        assert_eq!(edit.new_text, "\"2\"");
+        assert_eq!(edit.range.end, lsp_types::Position::new(2, 14));
+    }
+
+    #[test]
+    fn checks_the_members_in_the_root() {
+        let root = "[workspace]\nmembers = [\"a\"]\n";
+        let member = "[package]\nname = \"a\"\n\n[features]\nlog = [\"log?/std\"]\n";
+        assert_eq!(
+            lint_at(root, &[member]),
+            Some(((0, 0), Some(insert(1, "resolver = \"2\"\n"))))
+        );
+        assert_eq!(lint_at(root, &["[package]\nname = \"a\"\n"]), None);
+
+        // The member points to the root
+        let root_uri: Uri = "file:///ws/Cargo.toml".parse().unwrap();
+        let root_manifest = parse(root);
+        let diagnostic = lint(
+            &parse(member),
+            &Source::new(member, Encoding::Utf8),
+            Some((
+                &root_uri,
+                &root_manifest,
+                &Source::new(root, Encoding::Utf8),
+            )),
+            &[],
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(diagnostic.range.start, lsp_types::Position::new(4, 7));
+        assert!(diagnostic.data.is_none());
+        let related = diagnostic.related_information.unwrap();
+        assert_eq!(related[0].location.uri, root_uri);
+    }
+}
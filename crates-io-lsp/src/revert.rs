@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use tower_lsp_server::lsp_types::{self, CodeAction, CodeActionKind, TextEdit, Uri, WorkspaceEdit};
+
+use crate::manifest::{self, DependencyKind};
+use crate::text::Source;
+
+/// Requirements of the dependencies by section and name.
+pub type Requirements = HashMap<(DependencyKind, String), String>;
+
+/// Requirements of the dependencies in a manifest.
+pub fn requirements(text: &str) -> Requirements {
+    let (manifest, _) = manifest::parse(text);
+    manifest
+        .dependencies()
+        .map(|(kind, name, dep)| ((kind, name.get_ref().clone()), dep.req().to_string()))
+        .collect()
+}
+
+/// Actions undoing the changes of the dependencies in `range` since the last save.
+///
+/// Changed requirements are reverted, dependencies added since are removed.
+pub fn actions(
+    uri: &Uri,
+    source: &Source,
+    saved: &Requirements,
+    range: lsp_types::Range,
+) -> Vec<CodeAction> {
+    let text = source.text.as_str();
+    let (manifest, _) = manifest::parse(text);
+    let mut actions = Vec::new();
+    for (kind, name, dep) in manifest.dependencies() {
+        let key = name.span().start;
+        let (Some(start), Some(end)) = (source.position(key), source.position(dep.span.end)) else {
+            continue;
+        };
+        if end.line < range.start.line || start.line > range.end.line {
+            continue;
+        }
+        let name = name.get_ref();
+        let (title, edit) = match saved.get(&(kind, name.clone())) {
+            Some(req) if req != dep.req() => {
+                // Edit the current requirement, the saved one might be elsewhere
+                let Some(range) = dep.req_span().and_then(|span| source.string_range(span)) else {
+                    continue;
+                };
+                let edit = TextEdit {
+                    range,
+                    new_text: req.clone(),
+                };
+                (format!("Revert to saved requirement ({req})"), edit)
+            }
+            Some(_) => continue,
+            None => {
+                let Some(edit) = remove(source, key, dep.span.end) else {
+                    continue;
+                };
+                (format!("Remove dependency {name}"), edit)
+            }
+        };
+        actions.push(CodeAction {
+            title,
+            kind: Some(CodeActionKind::REFACTOR_REWRITE),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+    actions
+}
+
+/// Remove the lines of an entry, `None` for `[dependencies.name]` tables.
+fn remove(source: &Source, start: usize, end: usize) -> Option<TextEdit> {
+    let text = source.text.as_str();
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    if !text[line_start..start].trim().is_empty() {
+        return None; // The key is part of a table header
+    }
+    let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i + 1);
+    Some(TextEdit {
+        range: source.range(line_start..line_end)?,
+        new_text: String::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::Encoding;
+
+    fn titles(text: &str, saved: &str, lines: (u32, u32)) -> Vec<String> {
+        let uri = "file:///app/Cargo.toml".parse().unwrap();
+        let range = lsp_types::Range::new(
+            lsp_types::Position::new(lines.0, 0),
+            lsp_types::Position::new(lines.1, 0),
+        );
+        actions(
+            &uri,
+            &Source::new(text, Encoding::Utf8),
+            &requirements(saved),
+            range,
+        )
+        .into_iter()
+        .map(|a| a.title)
+        .collect()
+    }
+
+    #[test]
+    fn keys_by_section() {
+        let text = "[dependencies]\nserde = \"1\"\n\n[dev-dependencies]\nserde = { version = \"1.0.100\" }\n";
+        let saved = requirements(text);
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[&(DependencyKind::Normal, "serde".into())], "1");
+        assert_eq!(saved[&(DependencyKind::Dev, "serde".into())], "1.0.100");
+    }
+
+    #[test]
+    fn only_changed_dependencies_in_range() {
+        let saved = "[dependencies]\na = \"1\"\nb = \"1\"\n";
+        let text = "[dependencies]\na = \"2\"\nb = \"2\"\nc = \"1\"\n";
+        assert_eq!(
+            titles(text, saved, (1, 1)),
+            ["Revert to saved requirement (1)"]
+        );
+        assert_eq!(titles(text, saved, (2, 3)).len(), 2);
+        assert!(titles(saved, saved, (0, 3)).is_empty());
+    }
+
+    #[test]
+    fn keeps_dependency_tables() {
+        let saved = "[dependencies]\n";
+        let text = "[dependencies.a]\nversion = \"1\"\n";
+        assert!(titles(text, saved, (0, 2)).is_empty());
+    }
+}
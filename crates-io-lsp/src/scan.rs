@@ -0,0 +1,263 @@
+//! The background scan of the workspace, reading the closed manifests in the `scanScope`
+//! into the index and fetching the versions of their crates.
+//!
+//! A checkpoint of the scanned manifests is kept in the cache directory,
+//! a scan after a cancellation or restart only parses the manifests that changed since.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::index::Occurrence;
+
+/// Command scanning the workspace, returning the [`Status`] once the scan ends.
+///
+/// A scan that is already running is not started again, its status is returned right away.
+pub const SCAN_START: &str = "crates-io.scan.start";
+/// Command stopping the running scan, returning its [`Status`].
+pub const SCAN_CANCEL: &str = "crates-io.scan.cancel";
+/// Command returning the [`Status`] of the current or last scan.
+pub const SCAN_STATUS: &str = "crates-io.scan.status";
+/// Token of the progress of the scan.
+pub const PROGRESS_TOKEN: &str = "crates-io/scan";
+/// Time since the last edit of a document before the scan continues.
+pub const TYPING_PAUSE: Duration = Duration::from_secs(3);
+/// Crates fetched at once, the scan pauses and stops between the batches.
+pub const FETCH_BATCH: usize = 16;
+
+/// Version of the checkpoint format, checkpoints of another version are started over.
+const FORMAT: u32 = 1;
+
+/// State of a scan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    /// No scan was started yet
+    #[default]
+    Idle,
+    Running,
+    /// Waiting for the user to stop typing
+    Paused,
+    Cancelled,
+    Finished,
+}
+
+/// Progress of a scan, as returned by the scan commands.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    pub state: State,
+    /// Manifests in the scan scope of the workspace folders
+    pub total: usize,
+    /// Manifests read so far
+    pub scanned: usize,
+    /// Scanned manifests that were restored from the checkpoint
+    pub unchanged: usize,
+    /// Crates missing in the cache, `None` until all manifests are scanned
+    pub crates: Option<usize>,
+    /// Crates fetched so far
+    pub fetched: usize,
+}
+
+impl Status {
+    /// Message of the progress, like `scanned 3 of 5 manifests, fetched 4 crates`.
+    pub fn message(&self) -> String {
+        format!(
+            "scanned {} of {} manifests, fetched {} crates",
+            self.scanned, self.total, self.fetched
+        )
+    }
+
+    /// Percentage of the progress, reading the manifests is the first half and fetching
+    /// the crates the second one.
+    pub fn percentage(&self) -> u32 {
+        let half = |done: usize, total: usize| match total {
+            0 => 50,
+            total => (done.min(total) * 50 / total) as u32,
+        };
+        let fetching = self.crates.map_or(0, |crates| half(self.fetched, crates));
+        half(self.scanned, self.total) + fetching
+    }
+
+    /// Whether the scan is running, also while it is paused.
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, State::Running | State::Paused)
+    }
+}
+
+/// A manifest as it was scanned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scanned {
+    /// [`content_hash`] of the text
+    pub hash: String,
+    /// The declarations indexed for the manifest
+    pub dependencies: Vec<(String, Occurrence)>,
+}
+
+/// Manifests scanned so far, kept across sessions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub format: u32,
+    pub manifests: BTreeMap<PathBuf, Scanned>,
+}
+
+impl Checkpoint {
+    fn empty() -> Self {
+        Self {
+            format: FORMAT,
+            ..Default::default()
+        }
+    }
+
+    /// The declarations of the manifest at `path` if its text still has the `hash`.
+    pub fn unchanged(&self, path: &Path, hash: &str) -> Option<&[(String, Occurrence)]> {
+        self.manifests
+            .get(path)
+            .filter(|scanned| scanned.hash == hash)
+            .map(|scanned| scanned.dependencies.as_slice())
+    }
+
+    pub fn record(&mut self, path: PathBuf, hash: String, dependencies: Vec<(String, Occurrence)>) {
+        self.manifests.insert(path, Scanned { hash, dependencies });
+    }
+
+    /// Drop the manifests that are no longer in the scan scope.
+    pub fn retain(&mut self, keep: impl Fn(&Path) -> bool) {
+        self.manifests.retain(|path, _| keep(path));
+    }
+
+    /// The checkpoint in `dir`, empty if it is missing, invalid, or of another format.
+    pub async fn load(dir: Option<&Path>, roots: &[PathBuf]) -> Self {
+        let Some(dir) = dir else {
+            return Self::empty();
+        };
+        let Ok(text) = tokio::fs::read_to_string(dir.join(file_name(roots))).await else {
+            return Self::empty();
+        };
+        serde_json::from_str::<Checkpoint>(&text)
+            .ok()
+            .filter(|checkpoint| checkpoint.format == FORMAT)
+            .unwrap_or_else(Self::empty)
+    }
+
+    /// Replace the checkpoint in `dir`, through a temporary file so that it is never
+    /// half written.
+    pub async fn save(&self, dir: Option<&Path>, roots: &[PathBuf]) -> std::io::Result<()> {
+        let Some(dir) = dir else {
+            return Ok(());
+        };
+        let name = file_name(roots);
+        tokio::fs::create_dir_all(dir).await?;
+        let temp = dir.join(format!("{name}.{}", std::process::id()));
+        let json = serde_json::to_vec(self).map_err(std::io::Error::other)?;
+        tokio::fs::write(&temp, json).await?;
+        tokio::fs::rename(&temp, dir.join(name)).await
+    }
+}
+
+/// Name of the checkpoint of the workspace folders `roots`, each project has its own.
+pub fn file_name(roots: &[PathBuf]) -> String {
+    let folders = roots
+        .iter()
+        .map(|root| root.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("scan-{}.json", content_hash(&folders))
+}
+
+/// FNV-1a hash of a manifest text, only telling whether it changed since the last scan.
+pub fn content_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// The scan of a client, at most one runs at a time.
+#[derive(Debug, Default)]
+pub struct Scanner {
+    pub status: Status,
+    /// Cancelled by [`SCAN_CANCEL`], replaced by each scan
+    pub cancel: CancellationToken,
+    /// Loaded by the first scan, kept for the next one
+    pub checkpoint: Option<Checkpoint>,
+    /// Whether the client shows the progress of the running scan
+    pub progress: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp_server::lsp_types::{Position, Range, Uri};
+
+    #[test]
+    fn splits_the_percentage_between_the_phases() {
+        let mut status = Status {
+            total: 4,
+            ..Default::default()
+        };
+        assert_eq!(status.percentage(), 0);
+        status.scanned = 2;
+        assert_eq!(status.percentage(), 25);
+        status.scanned = 4;
+        status.crates = Some(10);
+        assert_eq!(status.percentage(), 50);
+        status.fetched = 5;
+        assert_eq!(status.percentage(), 75);
+        status.crates = Some(0);
+        assert_eq!(status.percentage(), 100);
+        assert_eq!(
+            status.message(),
+            "scanned 4 of 4 manifests, fetched 5 crates"
+        );
+        let empty = Status {
+            crates: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(empty.percentage(), 100);
+    }
+
+    #[test]
+    fn hashes_the_content() {
+        assert_eq!(content_hash(""), "cbf29ce484222325");
+        assert_eq!(content_hash("a"), "af63dc4c8601ec8c");
+        assert_ne!(content_hash("serde = \"1\""), content_hash("serde = \"2\""));
+        assert_ne!(
+            file_name(&["/a".into(), "/b".into()]),
+            file_name(&["/a".into()])
+        );
+    }
+
+    #[tokio::test]
+    async fn round_trips_the_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("crates-io-lsp-scan-{}", std::process::id()));
+        let roots = [PathBuf::from("/repo")];
+        let occurrence = Occurrence {
+            uri: "file:///repo/Cargo.toml".parse::<Uri>().unwrap(),
+            section: "dependencies".into(),
+            req: Some("1".into()),
+            range: Range::new(Position::new(4, 0), Position::new(4, 5)),
+        };
+        let mut checkpoint = Checkpoint::load(Some(&dir), &roots).await;
+        assert!(checkpoint.manifests.is_empty());
+        let path = PathBuf::from("/repo/Cargo.toml");
+        checkpoint.record(
+            path.clone(),
+            "01".into(),
+            vec![("serde".into(), occurrence)],
+        );
+        checkpoint.save(Some(&dir), &roots).await.unwrap();
+
+        let loaded = Checkpoint::load(Some(&dir), &roots).await;
+        assert_eq!(loaded, checkpoint);
+        assert_eq!(loaded.unchanged(&path, "01").map(<[_]>::len), Some(1));
+        assert_eq!(loaded.unchanged(&path, "02"), None);
+        // Other workspace folders have their own checkpoint
+        let other = Checkpoint::load(Some(&dir), &[PathBuf::from("/other")]).await;
+        assert!(other.manifests.is_empty());
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{oneshot, watch};
+use tokio_util::sync::CancellationToken;
+use tower_lsp_server::lsp_types::Uri;
+
+/// Priority of an analysis job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Restarted in the background, e.g. after a configuration change
+    Background,
+    /// Opened or saved by the user
+    Interactive,
+}
+
+/// Central queue for the analyses of all documents.
+///
+/// Fetch permits are granted round-robin across the active jobs,
+/// interactive jobs before background jobs, so that no document starves.
+#[derive(Default)]
+pub struct Scheduler {
+    inner: Arc<Mutex<Inner>>,
+    /// Cancels all jobs, including those started afterwards
+    shutdown: CancellationToken,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Number of fetches that may run concurrently
+    limit: usize,
+    running: usize,
+    next_id: u64,
+    /// Active jobs in round-robin order
+    jobs: VecDeque<JobState>,
+    /// Cancellation of the current job of each document
+    current: HashMap<Uri, (u64, watch::Sender<bool>)>,
+    /// Number of active jobs, watched to wait until all are done
+    active: watch::Sender<usize>,
+    /// Fetches of the active jobs, reset when all jobs are done
+    fetches: usize,
+    fetched: usize,
+}
+
+struct JobState {
+    id: u64,
+    priority: Priority,
+    waiting: VecDeque<oneshot::Sender<Permit>>,
+}
+
+/// Aggregate progress of the active jobs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub jobs: usize,
+    pub fetches: usize,
+    pub fetched: usize,
+}
+
+impl Scheduler {
+    pub fn new(limit: usize, shutdown: CancellationToken) -> Self {
+        let scheduler = Self {
+            inner: Default::default(),
+            shutdown,
+        };
+        scheduler.set_limit(limit);
+        scheduler
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.limit = limit.max(1);
+        grant(&mut inner, &self.inner);
+    }
+
+    /// Start a job for the document, cancelling its previous job.
+    pub fn start(&self, uri: &Uri, priority: Priority) -> Job {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        let (cancel, cancelled) = watch::channel(false);
+        if let Some((_, previous)) = inner.current.insert(uri.clone(), (id, cancel)) {
+            previous.send_replace(true);
+        }
+        inner.jobs.push_back(JobState {
+            id,
+            priority,
+            waiting: VecDeque::new(),
+        });
+        inner.active.send_replace(inner.jobs.len());
+        Job {
+            id,
+            uri: uri.clone(),
+            cancelled,
+            shutdown: self.shutdown.clone(),
+            scheduler: self.inner.clone(),
+        }
+    }
+
+    /// Cancel the current job of a document, e.g. when it was closed or renamed.
+    pub fn cancel(&self, uri: &Uri) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some((_, cancel)) = inner.current.remove(uri) {
+            cancel.send_replace(true);
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        let inner = self.inner.lock().unwrap();
+        Progress {
+            jobs: inner.jobs.len(),
+            fetches: inner.fetches,
+            fetched: inner.fetched,
+        }
+    }
+
+    /// Completes when no job is active anymore.
+    pub async fn idle(&self) {
+        let mut active = self.inner.lock().unwrap().active.subscribe();
+        // The sender lives as long as the scheduler
+        let _ = active.wait_for(|jobs| *jobs == 0).await;
+    }
+}
+
+/// Analysis of a document, removed from the queue when dropped.
+pub struct Job {
+    id: u64,
+    uri: Uri,
+    cancelled: watch::Receiver<bool>,
+    shutdown: CancellationToken,
+    scheduler: Arc<Mutex<Inner>>,
+}
+
+impl Job {
+    /// Wait for a fetch permit.
+    ///
+    /// The future does not borrow the job, so that it can be moved into a task.
+    pub fn permit(&self) -> impl Future<Output = Option<Permit>> + Send + 'static {
+        let receiver = {
+            let mut inner = self.scheduler.lock().unwrap();
+            inner.fetches += 1;
+            let (sender, receiver) = oneshot::channel();
+            if let Some(job) = inner.jobs.iter_mut().find(|j| j.id == self.id) {
+                job.waiting.push_back(sender);
+            }
+            grant(&mut inner, &self.scheduler);
+            receiver
+        };
+        // Fails if the job was dropped in the meantime
+        async move { receiver.await.ok() }
+    }
+
+    /// Completes when a newer job for the same document was started, it was cancelled,
+    /// or the server shuts down.
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.cancelled.clone();
+        tokio::select! {
+            // The sender is only dropped when the document is replaced
+            _ = cancelled.wait_for(|c| *c) => {}
+            _ = self.shutdown.cancelled() => {}
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow() || self.shutdown.is_cancelled()
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        let mut inner = self.scheduler.lock().unwrap();
+        inner.jobs.retain(|j| j.id != self.id);
+        inner.active.send_replace(inner.jobs.len());
+        if inner
+            .current
+            .get(&self.uri)
+            .is_some_and(|(id, _)| *id == self.id)
+        {
+            inner.current.remove(&self.uri);
+        }
+        if inner.jobs.is_empty() {
+            inner.fetches = 0;
+            inner.fetched = 0;
+        }
+    }
+}
+
+/// Permission to run one fetch, passed on to the next job when dropped.
+pub struct Permit {
+    scheduler: Option<Arc<Mutex<Inner>>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            let mut inner = scheduler.lock().unwrap();
+            inner.running -= 1;
+            inner.fetched += 1;
+            grant(&mut inner, &scheduler);
+        }
+    }
+}
+
+/// Hand out free permits, round-robin over the jobs with the highest priority.
+fn grant(inner: &mut Inner, scheduler: &Arc<Mutex<Inner>>) {
+    while inner.running < inner.limit {
+        let Some(priority) = inner
+            .jobs
+            .iter()
+            .filter(|j| !j.waiting.is_empty())
+            .map(|j| j.priority)
+            .max()
+        else {
+            return;
+        };
+        let Some(pos) = inner
+            .jobs
+            .iter()
+            .position(|j| j.priority == priority && !j.waiting.is_empty())
+        else {
+            return;
+        };
+        // Move the job to the back of the rotation
+        let mut job = inner.jobs.remove(pos).unwrap();
+        let sender = job.waiting.pop_front().unwrap();
+        inner.jobs.push_back(job);
+
+        inner.running += 1;
+        let permit = Permit {
+            scheduler: Some(scheduler.clone()),
+        };
+        if let Err(mut permit) = sender.send(permit) {
+            // The fetch was aborted, must not lock again in drop
+            permit.scheduler = None;
+            inner.running -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::*;
+
+    fn uri(name: &str) -> Uri {
+        format!("file:///{name}/Cargo.toml").parse().unwrap()
+    }
+
+    /// Run `fetches` requests with a fixed latency, returning when the last one completed.
+    fn fetch(job: Job, fetches: usize, start: Instant) -> tokio::task::JoinHandle<Duration> {
+        let permits = (0..fetches).map(|_| job.permit()).collect::<Vec<_>>();
+        tokio::spawn(async move {
+            let mut set = tokio::task::JoinSet::new();
+            for permit in permits {
+                set.spawn(async move {
+                    let _permit = permit.await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                });
+            }
+            set.join_all().await;
+            drop(job);
+            start.elapsed()
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn no_document_starves() {
+        let scheduler = Scheduler::new(2, CancellationToken::new());
+        let start = Instant::now();
+        // Opened at once, the large document first
+        let large = fetch(
+            scheduler.start(&uri("large"), Priority::Interactive),
+            20,
+            start,
+        );
+        let small = fetch(
+            scheduler.start(&uri("small"), Priority::Interactive),
+            2,
+            start,
+        );
+        let small = small.await.unwrap();
+        let large = large.await.unwrap();
+        // All at once, the small document would wait for the 20 fetches of the large one
+        assert_eq!(small, Duration::from_millis(300));
+        // Without losing throughput
+        assert_eq!(large, Duration::from_millis(1100));
+        assert_eq!(scheduler.progress().jobs, 0);
+
+        // The previous approach, a shared semaphore granting in request order
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(2));
+        let start = Instant::now();
+        let mut done = Vec::new();
+        for fetches in [20, 2] {
+            let mut set = tokio::task::JoinSet::new();
+            for _ in 0..fetches {
+                let semaphore = semaphore.clone();
+                set.spawn(async move {
+                    let _permit = semaphore.acquire_owned().await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                });
+            }
+            done.push(tokio::spawn(async move {
+                set.join_all().await;
+                start.elapsed()
+            }));
+        }
+        let small_before = done.pop().unwrap().await.unwrap();
+        assert_eq!(small_before, Duration::from_millis(1100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn interactive_before_background() {
+        let scheduler = Scheduler::new(1, CancellationToken::new());
+        let start = Instant::now();
+        let background = fetch(scheduler.start(&uri("a"), Priority::Background), 5, start);
+        let interactive = fetch(scheduler.start(&uri("b"), Priority::Interactive), 5, start);
+        // The first background fetch already holds the permit
+        assert_eq!(interactive.await.unwrap(), Duration::from_millis(600));
+        assert_eq!(background.await.unwrap(), Duration::from_millis(1000));
+    }
+
+    #[tokio::test]
+    async fn grants_round_robin() {
+        let scheduler = Scheduler::new(1, CancellationToken::new());
+        let a = scheduler.start(&uri("a"), Priority::Interactive);
+        let b = scheduler.start(&uri("b"), Priority::Interactive);
+        let held = a.permit().await.unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut set = tokio::task::JoinSet::new();
+        for (name, job) in [("a", &a), ("a", &a), ("b", &b), ("b", &b)] {
+            let permit = job.permit();
+            let order = order.clone();
+            set.spawn(async move {
+                let _permit = permit.await.unwrap();
+                order.lock().unwrap().push(name);
+                tokio::task::yield_now().await;
+            });
+        }
+        assert_eq!(
+            scheduler.progress(),
+            Progress {
+                jobs: 2,
+                fetches: 5,
+                fetched: 0
+            }
+        );
+        drop(held);
+        set.join_all().await;
+        assert_eq!(*order.lock().unwrap(), ["b", "a", "b", "a"]);
+        assert_eq!(scheduler.progress().fetched, 5);
+
+        drop((a, b));
+        assert_eq!(
+            scheduler.progress(),
+            Progress {
+                jobs: 0,
+                fetches: 0,
+                fetched: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn newer_jobs_cancel_older_ones() {
+        let scheduler = Scheduler::new(1, CancellationToken::new());
+        let old = scheduler.start(&uri("a"), Priority::Interactive);
+        let other = scheduler.start(&uri("b"), Priority::Interactive);
+        assert!(!old.is_cancelled());
+
+        let held = other.permit().await.unwrap();
+        let waiting = old.permit();
+        let new = scheduler.start(&uri("a"), Priority::Interactive);
+        assert!(old.is_cancelled());
+        assert!(!other.is_cancelled() && !new.is_cancelled());
+        old.cancelled().await;
+
+        // The permits of a dropped job go to the others
+        drop(old);
+        assert!(waiting.await.is_none());
+        let next = new.permit();
+        drop(held);
+        assert!(next.await.is_some());
+        assert_eq!(scheduler.progress().jobs, 2);
+    }
+
+    #[tokio::test]
+    async fn cancels_without_replacing() {
+        let scheduler = Scheduler::new(1, CancellationToken::new());
+        let job = scheduler.start(&uri("a"), Priority::Interactive);
+        let other = scheduler.start(&uri("b"), Priority::Interactive);
+        scheduler.cancel(&uri("a"));
+        assert!(job.is_cancelled() && !other.is_cancelled());
+        job.cancelled().await;
+
+        // Reopening the document starts afresh
+        drop(job);
+        let new = scheduler.start(&uri("a"), Priority::Interactive);
+        assert!(!new.is_cancelled());
+        assert_eq!(scheduler.progress().jobs, 2);
+    }
+
+    #[tokio::test]
+    async fn shutdown_cancels_all_jobs() {
+        let shutdown = CancellationToken::new();
+        let scheduler = Scheduler::new(1, shutdown.child_token());
+        let a = scheduler.start(&uri("a"), Priority::Interactive);
+        let b = scheduler.start(&uri("b"), Priority::Background);
+        shutdown.cancel();
+        assert!(a.is_cancelled() && b.is_cancelled());
+        a.cancelled().await;
+        b.cancelled().await;
+        // Including jobs started afterwards
+        assert!(scheduler
+            .start(&uri("c"), Priority::Interactive)
+            .is_cancelled());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_once_the_last_job_is_done() {
+        let scheduler = Scheduler::new(1, CancellationToken::new());
+        scheduler.idle().await;
+        let a = scheduler.start(&uri("a"), Priority::Interactive);
+        let b = scheduler.start(&uri("b"), Priority::Interactive);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(a);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(b);
+        });
+        let start = Instant::now();
+        scheduler.idle().await;
+        assert_eq!(start.elapsed(), Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,594 @@
+//! Schema of the settings, checked before they are deserialized into [`Config`].
+//!
+//! Deserializing stops at the first error and would drop all settings with it,
+//! the schema finds every unknown or mistyped setting and removes only those.
+//!
+//! [`Config`]: crate::config::Config
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tower_lsp_server::lsp_types::Uri;
+
+use crate::keys;
+use crate::messages::{Key, Language, Message};
+
+/// Command returning the [`EffectiveConfig`] of a document.
+pub const CONFIG: &str = "crates-io.config";
+
+/// Expected value of a setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Bool,
+    /// Non-negative integer
+    Integer,
+    #[cfg_attr(not(feature = "metadata"), allow(dead_code))]
+    Number,
+    String,
+    /// One of the strings
+    Enum(&'static [&'static str]),
+    /// Array of strings
+    Strings,
+    Table(&'static [Field]),
+    /// Table with any keys and values of one type
+    Map(&'static Type),
+    /// Read by the editor extension, not by the server, and dropped without a problem
+    Extension,
+}
+
+impl Type {
+    fn describe(self, language: Language) -> String {
+        let key = match self {
+            Type::Bool => Key::ExpectedBool,
+            Type::Integer => Key::ExpectedInteger,
+            Type::Number => Key::ExpectedNumber,
+            Type::String => Key::ExpectedString,
+            Type::Enum(values) => {
+                let values = values.iter().map(|v| format!("`{v}`")).collect::<Vec<_>>();
+                return Message::new(Key::ExpectedOneOf)
+                    .arg(values.join(", "))
+                    .render(language);
+            }
+            Type::Strings => Key::ExpectedStrings,
+            Type::Table(_) | Type::Map(_) | Type::Extension => Key::ExpectedTable,
+        };
+        Message::new(key).render(language)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    pub ty: Type,
+    /// Only taken from the client settings, configuration files cannot change it
+    pub client_only: bool,
+    /// Not shown by `crates-io.config`
+    pub secret: bool,
+}
+
+impl Field {
+    const fn new(name: &'static str, ty: Type) -> Self {
+        Self {
+            name,
+            ty,
+            client_only: false,
+            secret: false,
+        }
+    }
+
+    const fn client_only(self) -> Self {
+        Self {
+            client_only: true,
+            ..self
+        }
+    }
+
+    const fn secret(self) -> Self {
+        Self {
+            secret: true,
+            ..self
+        }
+    }
+}
+
+const SEVERITY: Type = Type::Enum(&["error", "warning", "information", "hint"]);
+
+const SEVERITIES: &[Field] = &[
+    Field::new("any", SEVERITY),
+    Field::new("latest", SEVERITY),
+    Field::new("outdatedCompatible", SEVERITY),
+    Field::new("outdatedIncompatible", SEVERITY),
+    Field::new("majorAvailable", SEVERITY),
+    Field::new("unknown", SEVERITY),
+    Field::new("fetchFailed", SEVERITY),
+    Field::new("invalidCrateName", SEVERITY),
+    Field::new("packageMissingField", SEVERITY),
+    Field::new("packageRecommendedField", SEVERITY),
+    Field::new("invalidLicense", SEVERITY),
+    Field::new("missingMember", SEVERITY),
+    Field::new("excludedDefaultMember", SEVERITY),
+    Field::new("parseError", SEVERITY),
+    Field::new("fetchPending", SEVERITY),
+    Field::new("preferWorkspaceDependency", SEVERITY),
+    Field::new("unknownKey", SEVERITY),
+    Field::new("unknownProfilePackage", SEVERITY),
+    Field::new("missingWorkspaceLints", SEVERITY),
+    Field::new("resolverFeatures", SEVERITY),
+    Field::new("missingWorkspaceField", SEVERITY),
+    Field::new("prereleaseRequirement", SEVERITY),
+    Field::new("zeroMajorRequirement", SEVERITY),
+    Field::new("shadowedCrate", SEVERITY),
+    Field::new("conflictingRequirements", SEVERITY),
+    Field::new("heldBackRequirement", SEVERITY),
+    Field::new("featureCycle", SEVERITY),
+    Field::new("artifactDependency", SEVERITY),
+    Field::new("invalidArtifact", SEVERITY),
+    Field::new("linksWithoutBuild", SEVERITY),
+    Field::new("staleCrate", SEVERITY),
+    Field::new("devOnlyDependency", SEVERITY),
+    Field::new("lockBehind", SEVERITY),
+    Field::new("checksumMismatch", SEVERITY),
+    Field::new("partiallyAnalyzed", SEVERITY),
+    Field::new("missingFeature", SEVERITY),
+    Field::new("unknownCargoFeature", SEVERITY),
+    Field::new("stabilizedCargoFeature", SEVERITY),
+];
+
+const KIND_SEVERITIES: &[Field] = &[Field::new("outdated", SEVERITY)];
+
+const FEATURE_HINT: Type = Type::Table(&[
+    Field::new("features", Type::Strings),
+    Field::new("reason", Type::String),
+]);
+
+/// All settings, like [`Config`](crate::config::Config).
+pub const SETTINGS: &[Field] = &[
+    Field::new("endpoint", Type::String).client_only(),
+    Field::new("endpoints", Type::Strings).client_only(),
+    Field::new("token", Type::String).client_only().secret(),
+    Field::new("severity", Type::Table(SEVERITIES)),
+    Field::new("devDependencies", Type::Table(KIND_SEVERITIES)),
+    Field::new("buildDependencies", Type::Table(KIND_SEVERITIES)),
+    Field::new("packageLints", Type::Bool),
+    Field::new("strictKeys", Type::Bool),
+    Field::new("profilePackages", Type::Bool),
+    Field::new("workspaceLints", Type::Bool),
+    Field::new("conflictingRequirements", Type::Bool),
+    Field::new("heldBackRequirements", Type::Bool),
+    Field::new("zeroMajorRequirements", Type::Bool),
+    Field::new("checkDevOnly", Type::Bool),
+    Field::new("missingFeatures", Type::Bool),
+    Field::new("suggestFeatures", Type::Bool),
+    Field::new("featureHints", Type::Map(&FEATURE_HINT)),
+    Field::new("perDependencyLens", Type::Bool),
+    Field::new(
+        "requirementStyle",
+        Type::Enum(&["full", "minor", "major", "preserve"]),
+    ),
+    Field::new("includePrerelease", Type::Bool),
+    Field::new("showChecksums", Type::Bool),
+    Field::new("partialAnalysis", Type::Bool),
+    Field::new("maxUpdateActions", Type::Integer),
+    #[cfg(feature = "metadata")]
+    Field::new("metadata", Type::Bool),
+    #[cfg(feature = "metadata")]
+    Field::new("userAgent", Type::String),
+    #[cfg(feature = "metadata")]
+    Field::new("staleCrates", Type::Bool),
+    #[cfg(feature = "metadata")]
+    Field::new("staleCrateYears", Type::Number),
+    #[cfg(feature = "metadata")]
+    Field::new("ignoreStale", Type::Strings),
+    #[cfg(feature = "changelog")]
+    Field::new("githubToken", Type::String).secret(),
+    Field::new("sortWithinGroups", Type::Bool),
+    Field::new("sortTables", Type::Bool),
+    Field::new("inlineTableWidth", Type::Integer),
+    Field::new("allowTableConversion", Type::Bool),
+    Field::new("allowVersionOverride", Type::Strings),
+    Field::new("excludePaths", Type::Strings),
+    Field::new(
+        "scanScope",
+        Type::Enum(&["default-members", "members", "open-only"]),
+    ),
+    Field::new("fetchConcurrency", Type::Integer),
+    Field::new("analysisDeadline", Type::Integer),
+    Field::new("fetchTimeout", Type::Integer),
+    Field::new("showPending", Type::Bool),
+    Field::new("legacySource", Type::Bool),
+    Field::new("maxIndexBytes", Type::Integer),
+    Field::new("cacheCapacity", Type::Integer).client_only(),
+    Field::new("cacheBytes", Type::Integer).client_only(),
+    Field::new("minCacheTtl", Type::Integer).client_only(),
+    Field::new("maxCacheTtl", Type::Integer).client_only(),
+    Field::new("logInterval", Type::Integer).client_only(),
+    Field::new(
+        "logLevel",
+        Type::Enum(&["error", "warning", "info", "debug"]),
+    )
+    .client_only(),
+    Field::new("slowAnalysisThreshold", Type::Integer),
+    Field::new("allowCargoCommands", Type::Bool).client_only(),
+    Field::new("stats", Type::Bool).client_only(),
+    Field::new("emitEvents", Type::Bool).client_only(),
+    Field::new("locale", Type::String).client_only(),
+    Field::new("messageAccessibility", Type::Bool),
+    // Settings of the Zed extension, sent along with the others
+    Field::new("connect", Type::Extension),
+    Field::new("env", Type::Extension),
+    Field::new("version", Type::Extension),
+    Field::new("channel", Type::Extension),
+];
+
+/// Unknown or mistyped setting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// Keys from the top level to the setting, like `["severity", "unknwon"]`
+    pub path: Vec<String>,
+    pub kind: ProblemKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProblemKind {
+    /// With the known key of the same table it is probably meant to be
+    Unknown(Option<&'static str>),
+    Mistyped(Type),
+}
+
+impl Problem {
+    /// Dotted key of the setting, like `severity.unknwon`.
+    pub fn key(&self) -> String {
+        self.path.join(".")
+    }
+
+    pub fn message(&self, language: Language) -> String {
+        let quoted = format!("`{}`", self.key());
+        match self.kind {
+            ProblemKind::Unknown(Some(suggestion)) => {
+                let parent = &self.path[..self.path.len().saturating_sub(1)];
+                let fixed = parent
+                    .iter()
+                    .map(String::as_str)
+                    .chain([suggestion])
+                    .collect::<Vec<_>>();
+                Message::new(Key::UnknownSettingSuggestion)
+                    .arg(quoted)
+                    .arg(format!("`{}`", fixed.join(".")))
+                    .render(language)
+            }
+            ProblemKind::Unknown(None) => Message::new(Key::UnknownSetting)
+                .arg(quoted)
+                .render(language),
+            ProblemKind::Mistyped(_) if self.path.is_empty() => {
+                Message::new(Key::MistypedSettings).render(language)
+            }
+            ProblemKind::Mistyped(ty) => Message::new(Key::MistypedSetting)
+                .arg(quoted)
+                .arg(ty.describe(language))
+                .render(language),
+        }
+    }
+}
+
+/// Check the `settings` against the schema, removing the offending values.
+///
+/// Null values are unset and removed without a problem,
+/// so are the settings of the editor extension.
+pub fn validate(settings: &mut Value) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    match settings {
+        Value::Object(table) => check_table(table, SETTINGS, &mut Vec::new(), &mut problems),
+        Value::Null => {}
+        _ => {
+            problems.push(Problem {
+                path: Vec::new(),
+                kind: ProblemKind::Mistyped(Type::Table(SETTINGS)),
+            });
+            *settings = Value::Object(Map::new());
+        }
+    }
+    problems
+}
+
+fn check_table(
+    table: &mut Map<String, Value>,
+    fields: &[Field],
+    path: &mut Vec<String>,
+    problems: &mut Vec<Problem>,
+) {
+    table.retain(|key, value| {
+        path.push(key.clone());
+        let keep = match fields.iter().find(|f| f.name == key) {
+            Some(field) => check(value, field.ty, path, problems),
+            None => {
+                let known = fields.iter().map(|f| f.name).collect::<Vec<_>>();
+                problems.push(Problem {
+                    path: path.clone(),
+                    kind: ProblemKind::Unknown(suggest(key, &known)),
+                });
+                false
+            }
+        };
+        path.pop();
+        keep
+    });
+}
+
+/// Whether the value is kept.
+fn check(value: &mut Value, ty: Type, path: &mut Vec<String>, problems: &mut Vec<Problem>) -> bool {
+    let valid = match (ty, value) {
+        (_, Value::Null) | (Type::Extension, _) => return false,
+        (Type::Bool, Value::Bool(_)) => true,
+        (Type::Integer, Value::Number(n)) => n.is_u64(),
+        (Type::Number, Value::Number(_)) => true,
+        (Type::String, Value::String(_)) => true,
+        (Type::Enum(values), Value::String(s)) => values.contains(&s.as_str()),
+        (Type::Strings, Value::Array(items)) => items.iter().all(Value::is_string),
+        (Type::Table(fields), Value::Object(table)) => {
+            check_table(table, fields, path, problems);
+            true
+        }
+        (Type::Map(ty), Value::Object(table)) => {
+            table.retain(|key, value| {
+                path.push(key.clone());
+                let keep = check(value, *ty, path, problems);
+                path.pop();
+                keep
+            });
+            true
+        }
+        _ => false,
+    };
+    if !valid {
+        problems.push(Problem {
+            path: path.clone(),
+            kind: ProblemKind::Mistyped(ty),
+        });
+    }
+    valid
+}
+
+/// The known key `name` is probably meant to be:
+/// the same apart from case, `_` and `-`, or else the one a single edit away.
+fn suggest(name: &str, known: &[&'static str]) -> Option<&'static str> {
+    let normalize = |key: &str| {
+        key.chars()
+            .filter(|c| !matches!(c, '_' | '-'))
+            .collect::<String>()
+            .to_lowercase()
+    };
+    let normalized = normalize(name);
+    known
+        .iter()
+        .find(|k| normalize(k) == normalized)
+        .copied()
+        .or_else(|| keys::suggest(name, known))
+}
+
+/// Where the effective value of a setting comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Source {
+    Default,
+    WorkspaceFile,
+    ClientSettings,
+}
+
+/// Effective value of a setting, see [`provenance`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Effective {
+    /// `null` if unset or secret
+    pub value: Value,
+    pub source: Source,
+}
+
+/// Settings of a document, see [`CONFIG`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectiveConfig {
+    /// The document, the active one if none was given
+    pub uri: Option<Uri>,
+    /// Its configuration file
+    pub file: Option<Uri>,
+    pub settings: BTreeMap<String, Effective>,
+}
+
+/// Effective settings by dotted key with their sources.
+///
+/// `effective` is the serialized configuration, `file` and `client` the validated options of
+/// the configuration file and the client settings. Tables like `severity` are listed by their
+/// entries, maps like `featureHints` as a whole.
+pub fn provenance(effective: &Value, file: &Value, client: &Value) -> BTreeMap<String, Effective> {
+    let mut settings = BTreeMap::new();
+    let mut path = Vec::new();
+    collect(SETTINGS, effective, file, client, &mut path, &mut settings);
+    settings
+}
+
+fn collect<'a>(
+    fields: &'a [Field],
+    effective: &Value,
+    file: &Value,
+    client: &Value,
+    path: &mut Vec<&'a str>,
+    settings: &mut BTreeMap<String, Effective>,
+) {
+    for field in fields {
+        path.push(field.name);
+        let value = |v: &Value| v.get(field.name).cloned().unwrap_or_default();
+        match field.ty {
+            Type::Extension => {}
+            Type::Table(fields) => collect(
+                fields,
+                &value(effective),
+                &value(file),
+                &value(client),
+                path,
+                settings,
+            ),
+            _ => {
+                let set = |v: &Value| !value(v).is_null();
+                let source = if set(client) {
+                    Source::ClientSettings
+                } else if set(file) && !field.client_only {
+                    Source::WorkspaceFile
+                } else {
+                    Source::Default
+                };
+                let value = match field.secret {
+                    true => Value::Null,
+                    false => value(effective),
+                };
+                settings.insert(path.join("."), Effective { value, source });
+            }
+        }
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::messages::Locale;
+
+    fn messages(problems: &[Problem]) -> Vec<String> {
+        problems
+            .iter()
+            .map(|p| p.message(Locale::En.into()))
+            .collect()
+    }
+
+    /// Keys of a serialized table, recursing into the tables of the schema.
+    fn keys(value: &Value, fields: &[Field], prefix: &str, found: &mut Vec<String>) {
+        for (key, value) in value.as_object().unwrap() {
+            let key_path = format!("{prefix}{key}");
+            match fields.iter().find(|f| f.name == key).map(|f| f.ty) {
+                Some(Type::Table(fields)) => keys(value, fields, &format!("{key_path}."), found),
+                _ => found.push(key_path),
+            }
+        }
+    }
+
+    #[test]
+    fn covers_the_config() {
+        let config = serde_json::to_value(Config::default()).unwrap();
+        let mut serialized = Vec::new();
+        keys(&config, SETTINGS, "", &mut serialized);
+        serialized.sort();
+
+        let mut schema = provenance(&config, &Value::Null, &Value::Null)
+            .into_keys()
+            .collect::<Vec<_>>();
+        schema.sort();
+        assert_eq!(serialized, schema);
+
+        // The defaults pass the schema unchanged, apart from the unset values
+        let mut validated = config.clone();
+        assert_eq!(validate(&mut validated), []);
+        serde_json::from_value::<Config>(validated).unwrap();
+    }
+
+    #[test]
+    fn finds_every_problem() {
+        let mut settings = json!({
+            "packageLints": true,
+            "packagelints": true,
+            "severity": { "unknwon": "warning", "latest": "loud", "any": "hint" },
+            "devDependencies": { "outdated": null, "other": "hint" },
+            "featureHints": {
+                "serde": { "features": ["derive"], "reson": "derive" },
+                "tokio": { "features": "full" },
+            },
+            "maxUpdateActions": -1,
+            "exclude_paths": [],
+            "colour": "blue",
+            "connect": { "mode": "tcp" },
+            "locale": null,
+        });
+        let problems = validate(&mut settings);
+        assert_eq!(
+            messages(&problems),
+            [
+                "Unknown setting `colour`, it is ignored",
+                "Unknown setting `devDependencies.other`, it is ignored",
+                "Unknown setting `exclude_paths`, did you mean `excludePaths`?",
+                "Unknown setting `featureHints.serde.reson`, did you mean `featureHints.serde.reason`?",
+                "`featureHints.tokio.features` must be an array of strings, it is ignored",
+                "`maxUpdateActions` must be a non-negative integer, it is ignored",
+                "Unknown setting `packagelints`, did you mean `packageLints`?",
+                "`severity.latest` must be one of `error`, `warning`, `information`, `hint`, it is ignored",
+                "Unknown setting `severity.unknwon`, did you mean `severity.unknown`?",
+            ]
+        );
+        assert_eq!(problems[2].key(), "exclude_paths");
+
+        // The rest applies
+        assert_eq!(
+            settings,
+            json!({
+                "packageLints": true,
+                "severity": { "any": "hint" },
+                "devDependencies": {},
+                "featureHints": {
+                    "serde": { "features": ["derive"] },
+                    "tokio": {},
+                },
+            })
+        );
+        let config = serde_json::from_value::<Config>(settings).unwrap();
+        assert!(config.package_lints);
+
+        let mut settings = json!(["packageLints"]);
+        assert_eq!(
+            messages(&validate(&mut settings)),
+            ["The settings must be a table, they are ignored"]
+        );
+        assert_eq!(settings, json!({}));
+    }
+
+    #[test]
+    fn tracks_the_sources() {
+        let file = json!({
+            "packageLints": true,
+            "severity": { "latest": "warning", "unknown": "warning" },
+            "cacheCapacity": 10,
+            "token": "file",
+        });
+        let client = json!({ "severity": { "unknown": "error" }, "token": "secret" });
+        let mut options = file.clone();
+        crate::config_file::merge(&mut options, client.clone());
+        let config = serde_json::from_value::<Config>(options).unwrap();
+        let effective = serde_json::to_value(&config).unwrap();
+
+        let settings = provenance(&effective, &file, &client);
+        let setting = |key: &str| {
+            let setting = &settings[key];
+            (setting.value.clone(), setting.source)
+        };
+        assert_eq!(
+            setting("packageLints"),
+            (json!(true), Source::WorkspaceFile)
+        );
+        assert_eq!(
+            setting("severity.latest"),
+            (json!("warning"), Source::WorkspaceFile)
+        );
+        assert_eq!(
+            setting("severity.unknown"),
+            (json!("error"), Source::ClientSettings)
+        );
+        assert_eq!(
+            setting("severity.any"),
+            (json!("information"), Source::Default)
+        );
+        assert_eq!(setting("featureHints"), (json!({}), Source::Default));
+        // Configuration files cannot change the cache, secrets are not shown
+        assert_eq!(setting("cacheCapacity").1, Source::Default);
+        assert_eq!(setting("token"), (Value::Null, Source::ClientSettings));
+        assert!(!settings.contains_key("connect"));
+    }
+}
@@ -0,0 +1,298 @@
+use std::path::{Component, Path, PathBuf};
+
+use tower_lsp_server::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, Location, NumberOrString, TextEdit, Uri,
+};
+use tower_lsp_server::UriExt;
+
+use crate::config::Config;
+use crate::manifest::{Dependency, DependencyValue, SpannedManifest};
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// Diagnostic code of [`lint`], for members the data contains the edit adding the `path`.
+pub const SHADOWED_CRATE: &str = "shadowed-crate";
+
+/// Warn about registry dependencies named like the package itself or a workspace member.
+///
+/// Without `path` or `workspace = true` cargo silently uses the crate of the registry.
+/// `dir` is the directory of the manifest, `members` are the package names of the workspace
+/// members with their directories.
+pub fn lint(
+    manifest: &SpannedManifest,
+    source: &Source,
+    dir: Option<&Path>,
+    members: &[(String, PathBuf)],
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let own = manifest
+        .package
+        .as_ref()
+        .and_then(|p| p.get_ref().name.as_ref())
+        .map(|n| n.get_ref().as_str());
+    let dependencies = manifest
+        .dependencies()
+        .map(|(_, name, dep)| (name, dep))
+        .chain(
+            manifest
+                .target_dependencies()
+                .map(|(_, _, name, dep)| (name, dep)),
+        );
+
+    let mut diagnostics = Vec::new();
+    for (name, dep) in dependencies {
+        let local = dep.inherited()
+            || dep.detail().is_some_and(|d| {
+                d.path.is_some()
+                    || d.git.is_some()
+                    || d.registry.is_some()
+                    || d.registry_index.is_some()
+            });
+        if local {
+            continue;
+        }
+        let package = dep.package().unwrap_or(name.get_ref());
+        let member = members.iter().find(|(n, _)| n == package);
+        let itself = own == Some(package) || member.is_some_and(|(_, d)| Some(d.as_path()) == dir);
+        if !itself && member.is_none() {
+            continue;
+        }
+        let Some(range) = source.range(name.span()) else {
+            continue;
+        };
+        let mut diagnostic = Diagnostic {
+            range,
+            severity: Some(config.severity.shadowed_crate.into()),
+            code: Some(NumberOrString::String(SHADOWED_CRATE.into())),
+            source: Some(config.source(SHADOWED_CRATE)),
+            message: Message::new(if itself {
+                Key::ShadowedPackage
+            } else {
+                Key::ShadowedMember
+            })
+            .arg(format!("`{package}`"))
+            .render(config.language),
+            ..Default::default()
+        };
+        if let (false, Some((_, member_dir)), Some(dir)) = (itself, member, dir) {
+            let manifest = Uri::from_file_path(member_dir.join("Cargo.toml"));
+            diagnostic.related_information = manifest.map(|uri| {
+                vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri,
+                        range: Default::default(),
+                    },
+                    message: Message::new(Key::WorkspaceMember).render(config.language),
+                }]
+            });
+            diagnostic.data = relative(dir, member_dir)
+                .and_then(|path| field_edit(dep, source, &format!("path = {path:?}")))
+                .and_then(|edit| serde_json::to_value(edit).ok());
+        }
+        diagnostics.push(diagnostic);
+    }
+    diagnostics
+}
+
+/// Edit adding a `key = value` field to a dependency, keeping its version and other fields.
+pub fn field_edit(dep: &Dependency, source: &Source, field: &str) -> Option<TextEdit> {
+    let text = source.text.as_str();
+    let value = text.get(dep.span.clone())?;
+    let detail = match &dep.value {
+        // `name = "1.0"`
+        DependencyValue::Simple(_) => {
+            return Some(TextEdit {
+                range: source.range(dep.span.clone())?,
+                new_text: format!("{{ version = {value}, {field} }}"),
+            });
+        }
+        DependencyValue::Detailed(detail) => detail,
+    };
+    if value.starts_with('{') {
+        // `name = { version = "1.0" }`, append before the closing brace
+        let inner = value.strip_suffix('}')?.trim_end();
+        let (at, new_text) = if inner == "{" {
+            (dep.span.start..dep.span.end, format!("{{ {field} }}"))
+        } else {
+            let at = dep.span.start + inner.len();
+            (at..at, format!(", {field}"))
+        };
+        Some(TextEdit {
+            range: source.range(at)?,
+            new_text,
+        })
+    } else {
+        // `[dependencies.name]` with `version = "1.0"` or `name.version = "1.0"`
+        let version = detail.version.as_ref()?.span();
+        let line_start = text[..version.start].rfind('\n').map_or(0, |i| i + 1);
+        let line = &text[line_start..version.start];
+        let key_start = line_start + (line.len() - line.trim_start().len());
+        let prefix = &text[line_start..key_start + line.trim_start().rfind("version")?];
+        let line_end = text[version.end..]
+            .find(['\r', '\n'])
+            .map_or(text.len(), |i| version.end + i);
+        Some(TextEdit {
+            range: source.range(line_end..line_end)?,
+            new_text: format!("\n{prefix}{field}"),
+        })
+    }
+}
+
+/// Path from `from` to `to` with forward slashes, like `../utils`.
+fn relative(from: &Path, to: &Path) -> Option<String> {
+    let from = from.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return None; // Another drive
+    }
+    let mut parts = vec![".."; from.len() - common];
+    for component in &to[common..] {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            _ => return None,
+        }
+    }
+    Some(if parts.is_empty() {
+        ".".to_string()
+    } else {
+        parts.join("/")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types;
+
+    use super::*;
+    use crate::manifest;
+    use crate::text::Encoding;
+
+    fn members() -> Vec<(String, PathBuf)> {
+        vec![
+            ("app".into(), PathBuf::from("/ws/app")),
+            ("utils".into(), PathBuf::from("/ws/crates/utils")),
+        ]
+    }
+
+    fn check(text: &str) -> Vec<Diagnostic> {
+        let (manifest, None) = manifest::parse(text) else {
+            panic!("{text}");
+        };
+        lint(
+            &manifest,
+            &Source::new(text, Encoding::Utf8),
+            Some(Path::new("/ws/app")),
+            &members(),
+            &Config::default(),
+        )
+    }
+
+    /// Text of the diagnostic range and the quick fix, if any.
+    fn fix(text: &str) -> Vec<(String, Option<TextEdit>)> {
+        check(text)
+            .into_iter()
+            .map(|d| {
+                let lines = text.lines().collect::<Vec<_>>();
+                let line = lines[d.range.start.line as usize];
+                let name = &line[d.range.start.character as usize..d.range.end.character as usize];
+                let edit = d.data.map(|data| serde_json::from_value(data).unwrap());
+                (name.to_string(), edit)
+            })
+            .collect()
+    }
+
+    fn edit(line: u32, start: u32, end: u32, new_text: &str) -> Option<TextEdit> {
+        Some(TextEdit::new(
+            lsp_types::Range::new(
+                lsp_types::Position::new(line, start),
+                lsp_types::Position::new(line, end),
+            ),
+            new_text.into(),
+        ))
+    }
+
+    #[test]
+    fn flags_the_own_name() {
+        let text = "[package]\nname = \"app\"\n\n[dev-dependencies]\napp = \"1\"\n";
+        let diagnostics = check(text);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "`app` is the name of this package, cargo uses the crate `app` from the registry"
+        );
+        assert_eq!(diagnostics[0].data, None);
+        // Also renamed
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\nold = { package = \"app\", version = \"1\" }\n";
+        assert_eq!(fix(text), [("old".into(), None)]);
+    }
+
+    #[test]
+    fn flags_members_with_a_fix() {
+        let simple = "[package]\nname = \"app\"\n\n[dependencies]\nutils = \"0.1\"\n";
+        assert_eq!(
+            fix(simple),
+            [(
+                "utils".into(),
+                edit(
+                    4,
+                    8,
+                    13,
+                    "{ version = \"0.1\", path = \"../crates/utils\" }"
+                )
+            )]
+        );
+        let inline = "[package]\nname = \"app\"\n\n[dependencies]\nutils = { version = \"0.1\", features = [\"a\"] }\n";
+        assert_eq!(
+            fix(inline),
+            [(
+                "utils".into(),
+                edit(4, 43, 43, ", path = \"../crates/utils\"")
+            )]
+        );
+        let table = "[package]\nname = \"app\"\n\n[dependencies.utils]\nversion = \"0.1\"\noptional = true\n";
+        assert_eq!(
+            fix(table),
+            [(
+                "utils".into(),
+                edit(4, 15, 15, "\npath = \"../crates/utils\"")
+            )]
+        );
+        let diagnostics = check(simple);
+        assert_eq!(
+            diagnostics[0].message,
+            "`utils` is a workspace member, but without `path` cargo uses the crate `utils` from the registry"
+        );
+        let related = &diagnostics[0].related_information.as_ref().unwrap()[0];
+        assert!(related
+            .location
+            .uri
+            .as_str()
+            .ends_with("/ws/crates/utils/Cargo.toml"));
+    }
+
+    #[test]
+    fn local_dependencies_stay_silent() {
+        let text = "[package]\nname = \"app\"\n\n\
+            [dependencies]\nutils = { path = \"../crates/utils\", version = \"0.1\" }\nserde = \"1\"\n\n\
+            [dev-dependencies]\napp = { path = \".\", features = [\"test\"] }\n\n\
+            [build-dependencies]\nutils = { workspace = true }\n\n\
+            [target.'cfg(unix)'.dependencies]\nutils = { git = \"https://example.com/utils\" }\n";
+        assert!(check(text).is_empty());
+        let text = "[dependencies]\nutils = { version = \"0.1\", registry = \"internal\" }\n";
+        assert!(check(text).is_empty());
+    }
+
+    #[test]
+    fn relative_paths() {
+        let path = |from: &str, to: &str| relative(Path::new(from), Path::new(to));
+        assert_eq!(
+            path("/ws/app", "/ws/crates/utils").as_deref(),
+            Some("../crates/utils")
+        );
+        assert_eq!(path("/ws", "/ws/utils").as_deref(), Some("utils"));
+        assert_eq!(path("/ws/a/b", "/ws").as_deref(), Some("../.."));
+        assert_eq!(path("/ws", "/ws").as_deref(), Some("."));
+        assert_eq!(path("ws", "other").as_deref(), None);
+    }
+}
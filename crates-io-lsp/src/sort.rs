@@ -0,0 +1,351 @@
+use std::ops::Range;
+
+use tower_lsp_server::lsp_types::TextEdit;
+
+use crate::config::Config;
+use crate::manifest::{self, sections, table_headers, DependencyKind, SpannedManifest};
+use crate::text::Source;
+
+/// Kind of the code action sorting the dependencies.
+pub const SORT_DEPENDENCIES: &str = "source.organizeDependencies";
+
+/// Name of an entry and the range of its lines.
+type Entry<'a> = (&'a str, Range<usize>);
+
+/// Edits sorting the entries of each dependency section alphabetically.
+///
+/// Comments directly above an entry and trailing comments move with it.
+/// Only the lines that change are replaced, the entries keep their formatting.
+/// Returns `None` if the manifest cannot be sorted safely, e.g. it has duplicate keys.
+pub fn edits(source: &Source, config: &Config) -> Option<Vec<TextEdit>> {
+    let text = source.text.as_str();
+    let manifest = toml::from_str::<SpannedManifest>(text).ok()?;
+    let sections = sections(text);
+    let headers = table_headers(text);
+
+    // Pairs of the replaced range and the range of the text moved there
+    let mut moves = Vec::new();
+    for kind in DependencyKind::ALL {
+        let Some((_, header)) = headers.iter().find(|(name, _)| name == kind.section()) else {
+            continue;
+        };
+        let Some(section) = sections.iter().find(|s| s.contains(&header.start)) else {
+            continue;
+        };
+        let body = line_end(text, header.end).1..section.end;
+        let entries = manifest
+            .dependencies()
+            .filter(|(k, name, _)| *k == kind && body.contains(&name.span().start))
+            .map(|(_, name, dep)| {
+                let key = manifest::quoted_key(text, name.span());
+                (name.get_ref().as_str(), key.start..dep.span.end)
+            })
+            .collect::<Vec<_>>();
+        for group in groups(text, body, &entries, config.sort_within_groups)? {
+            moves.extend(sorted(&group));
+        }
+    }
+    if config.sort_tables {
+        moves.extend(tables(text, &sections, &headers));
+    }
+
+    moves
+        .into_iter()
+        .map(|(slot, moved)| {
+            Some(TextEdit {
+                range: source.range(slot)?,
+                new_text: text[moved].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Entries of a section body with their attached comments, split into the groups sorted on their own.
+///
+/// Blank lines separate groups if `by_blank_lines` is set, detached comments always do.
+/// Returns `None` if the body has lines that are not part of an entry.
+fn groups<'a>(
+    text: &str,
+    body: Range<usize>,
+    entries: &[Entry<'a>],
+    by_blank_lines: bool,
+) -> Option<Vec<Vec<Entry<'a>>>> {
+    let mut groups = vec![Vec::new()];
+    let mut comment = None;
+    let mut matched = 0;
+    let mut offset = body.start;
+    while offset < body.end {
+        let (end, next) = line_end(text, offset);
+        let line = text[offset..end].trim();
+        if line.is_empty() {
+            if comment.take().is_some() || by_blank_lines {
+                groups.push(Vec::new());
+            }
+        } else if line.starts_with('#') {
+            comment.get_or_insert(offset);
+        } else {
+            // The key has to start the line
+            let (name, entry) = entries
+                .iter()
+                .find(|(_, e)| e.start == offset + indent(text, offset))?;
+            let (end, next) = line_end(text, entry.end.max(offset));
+            let trailing = text[entry.end.min(end)..end].trim();
+            if !(trailing.is_empty() || trailing.starts_with('#')) {
+                return None;
+            }
+            groups
+                .last_mut()?
+                .push((*name, comment.take().unwrap_or(offset)..end));
+            matched += 1;
+            offset = next;
+            continue;
+        }
+        offset = next;
+    }
+    (matched == entries.len()).then_some(groups)
+}
+
+/// Moves sorting a group by name, only for the entries not already in place.
+fn sorted(group: &[Entry]) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut sorted = group.to_vec();
+    sorted.sort_by_key(|(name, _)| *name);
+    group
+        .iter()
+        .zip(sorted)
+        .filter(|((old, _), (new, _))| old != new)
+        .map(|((_, slot), (_, source))| (slot.clone(), source))
+        .collect()
+}
+
+/// Moves sorting adjacent `[dependencies.name]` tables by name.
+fn tables(
+    text: &str,
+    sections: &[Range<usize>],
+    headers: &[(String, Range<usize>)],
+) -> Vec<(Range<usize>, Range<usize>)> {
+    // Sections of the dependency tables with their kind and crate name
+    let tables = headers
+        .iter()
+        .filter_map(|(name, header)| {
+            let (kind, name) = DependencyKind::ALL.into_iter().find_map(|kind| {
+                let name = name.strip_prefix(kind.section())?.strip_prefix('.')?;
+                (!name.contains('.')).then_some((kind, name))
+            })?;
+            let section = sections.iter().find(|s| s.contains(&header.start))?;
+            Some((kind, name, section.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    let mut moves = Vec::new();
+    let mut run: Vec<Entry> = Vec::new();
+    for (i, (kind, name, section)) in tables.iter().enumerate() {
+        let next = tables
+            .get(i + 1)
+            .filter(|(k, _, next)| k == kind && next.start == section.end);
+        // The comments above the header belong to the table
+        let start = comments_above(text, section.start);
+        let end = next.map_or(section.end, |(_, _, next)| comments_above(text, next.start));
+        let end = start + text[start..end].trim_end().len();
+        run.push((name, start..end));
+        if next.is_none() {
+            moves.extend(sorted(&run));
+            run.clear();
+        }
+    }
+    moves
+}
+
+/// Start of the comment lines directly above the line at `offset`.
+fn comments_above(text: &str, mut offset: usize) -> usize {
+    while offset > 0 {
+        let prev = text[..offset - 1].rfind('\n').map_or(0, |i| i + 1);
+        if !text[prev..offset].trim_start().starts_with('#') {
+            break;
+        }
+        offset = prev;
+    }
+    offset
+}
+
+/// End of the line at `offset` without the line break, and the start of the next line.
+fn line_end(text: &str, offset: usize) -> (usize, usize) {
+    match text[offset..].find('\n') {
+        Some(i) => {
+            let end = offset + i;
+            let end = if text[..end].ends_with('\r') {
+                end - 1
+            } else {
+                end
+            };
+            (end, offset + i + 1)
+        }
+        None => (text.len(), text.len()),
+    }
+}
+
+fn indent(text: &str, offset: usize) -> usize {
+    let line = &text[offset..];
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types;
+
+    use super::*;
+    use crate::text::Encoding;
+
+    fn offset(text: &str, pos: lsp_types::Position) -> usize {
+        let line: usize = text
+            .split_inclusive('\n')
+            .take(pos.line as usize)
+            .map(str::len)
+            .sum();
+        line + pos.character as usize
+    }
+
+    /// The sorted text, or `None` if the manifest cannot be sorted.
+    fn sort(text: &str, config: &Config) -> Option<(String, usize)> {
+        let mut edits = edits(&Source::new(text, Encoding::Utf8), config)?;
+        let count = edits.len();
+        edits.sort_by_key(|e| std::cmp::Reverse(e.range.start));
+        let mut text = text.to_string();
+        for edit in edits {
+            let range = offset(&text, edit.range.start)..offset(&text, edit.range.end);
+            text.replace_range(range, &edit.new_text);
+        }
+        Some((text, count))
+    }
+
+    fn sorted(text: &str) -> String {
+        sort(text, &Config::default()).unwrap().0
+    }
+
+    #[test]
+    fn moves_the_attached_comments() {
+        let text = "\
+[package]
+name = \"app\"
+
+[dependencies]
+# json
+serde_json = \"1\"
+anyhow = \"1\" # errors
+serde = { version = \"1\", features = [\"derive\"] }
+";
+        assert_eq!(
+            sorted(text),
+            "\
+[package]
+name = \"app\"
+
+[dependencies]
+anyhow = \"1\" # errors
+serde = { version = \"1\", features = [\"derive\"] }
+# json
+serde_json = \"1\"
+"
+        );
+    }
+
+    #[test]
+    fn replaces_only_moved_entries() {
+        let text = "[dependencies]\nc = \"1\"\nb = { version = \"1\", features = [\n  \"x\",\n] }\na = \"1\"\n";
+        let (sorted, edits) = sort(text, &Config::default()).unwrap();
+        assert_eq!(
+            sorted,
+            "[dependencies]\na = \"1\"\nb = { version = \"1\", features = [\n  \"x\",\n] }\nc = \"1\"\n"
+        );
+        assert_eq!(edits, 2);
+    }
+
+    #[test]
+    fn sorts_groups() {
+        let text = "\
+[dependencies]
+b = \"1\"
+a = \"1\"
+
+d = \"1\"
+c = \"1\"
+";
+        assert_eq!(
+            sorted(text),
+            "[dependencies]\na = \"1\"\nb = \"1\"\n\nc = \"1\"\nd = \"1\"\n"
+        );
+
+        let text = "[dependencies]\nc = \"1\"\nd = \"1\"\n\na = \"1\"\nb = \"1\"\n";
+        let config = Config {
+            sort_within_groups: false,
+            ..Default::default()
+        };
+        let (sorted, _) = sort(text, &config).unwrap();
+        assert_eq!(
+            sorted,
+            "[dependencies]\na = \"1\"\nb = \"1\"\n\nc = \"1\"\nd = \"1\"\n"
+        );
+    }
+
+    #[test]
+    fn detached_comments_separate_groups() {
+        let text = "[dependencies]\nb = \"1\"\n# Internal\n\na = \"1\"\n";
+        let config = Config {
+            sort_within_groups: false,
+            ..Default::default()
+        };
+        assert_eq!(sort(text, &config).unwrap().1, 0);
+    }
+
+    #[test]
+    fn sorted_sections_have_no_edits() {
+        let text = "[dependencies]\na = \"1\"\nb = \"1\"\n\n[dev-dependencies]\nc = \"1\"\n";
+        assert_eq!(sort(text, &Config::default()).unwrap().1, 0);
+    }
+
+    #[test]
+    fn bails_out_of_unsafe_sections() {
+        // Duplicate keys
+        let text = "[dependencies]\nb = \"1\"\na = \"1\"\nb = \"2\"\n";
+        assert!(sort(text, &Config::default()).is_none());
+        // Trailing comments may contain anything
+        let text = "[dependencies]\nb = \"1\"\na = { version = \"1\" } # c = \"1\"\n";
+        assert!(sort(text, &Config::default()).is_some());
+        // Dotted keys are not entries
+        let text = "[dependencies]\nc = \"1\"\nb.version = \"1\"\n";
+        assert!(sort(text, &Config::default()).is_none());
+    }
+
+    #[test]
+    fn sorts_tables() {
+        let text = "\
+[dependencies.b]
+version = \"1\"
+
+# The a crate
+[dependencies.a]
+version = \"1\"
+
+[dev-dependencies.c]
+version = \"1\"
+";
+        assert_eq!(sort(text, &Config::default()).unwrap().1, 0);
+        let config = Config {
+            sort_tables: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            sort(text, &config).unwrap().0,
+            "\
+# The a crate
+[dependencies.a]
+version = \"1\"
+
+[dependencies.b]
+version = \"1\"
+
+[dev-dependencies.c]
+version = \"1\"
+"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString};
+
+use crate::analysis::AnalyzedDependency;
+use crate::config::Config;
+use crate::format;
+use crate::messages::{Key, Message};
+
+/// Diagnostic code of [`lint`].
+pub const STALE_CRATE: &str = "stale-crate";
+
+/// Years since the newest release of `package` if they exceed `staleCrateYears`.
+///
+/// `released` is the RFC 3339 date of the release, `today` in days since the Unix epoch.
+/// Crates in `ignoreStale` are never stale.
+pub fn stale_years(package: &str, released: &str, today: i64, config: &Config) -> Option<f64> {
+    if config.ignore_stale.iter().any(|name| name == package) {
+        return None;
+    }
+    let days = today - format::days_since_epoch(released.get(..10)?)?;
+    let years = days as f64 / 365.25;
+    (years > config.stale_crate_years).then_some(years)
+}
+
+/// Flag the registry dependencies whose newest release is older than `staleCrateYears`.
+///
+/// Only metadata that was already fetched is used, `released` returns the date of the newest
+/// release of a crate if it is known. Crates without it are skipped.
+pub fn lint(
+    dependencies: &[AnalyzedDependency],
+    released: impl Fn(&str) -> Option<String>,
+    today: i64,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    if !config.stale_crates {
+        return Vec::new();
+    }
+    dependencies
+        .iter()
+        .filter(|dep| !dep.git)
+        .filter_map(|dep| {
+            let years = stale_years(&dep.package, &released(&dep.package)?, today, config)?;
+            Some(Diagnostic {
+                range: dep.name_range,
+                severity: Some(config.severity.stale_crate.into()),
+                code: Some(NumberOrString::String(STALE_CRATE.into())),
+                source: Some(config.source(STALE_CRATE)),
+                message: Message::new(Key::StaleCrate)
+                    .arg(format!("`{}`", dep.package))
+                    .arg(format!("{years:.1}"))
+                    .render(config.language),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Note of the hover of a stale crate, shown even if the diagnostic is disabled.
+pub fn hover(package: &str, released: &str, today: i64, config: &Config) -> Option<String> {
+    let years = stale_years(package, released, today, config)?;
+    Some(format!(
+        "**Possibly unmaintained**: last release {years:.1} years ago"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types::Range;
+
+    use super::*;
+    use crate::manifest::DependencyKind;
+
+    /// 2024-01-01
+    const TODAY: i64 = 19723;
+
+    fn dependency(package: &str, git: bool) -> AnalyzedDependency {
+        AnalyzedDependency {
+            package: package.into(),
+            kind: DependencyKind::Normal,
+            target: None,
+            name_range: Range::default(),
+            req: "1".into(),
+            req_range: None,
+            git,
+            classification: None,
+        }
+    }
+
+    fn stale() -> Config {
+        Config {
+            stale_crates: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn measures_the_age_in_years() {
+        let config = Config::default();
+        let years = stale_years("old", "2019-10-16T08:00:00.123Z", TODAY, &config).unwrap();
+        assert_eq!(format!("{years:.1}"), "4.2");
+        // Younger than the default of 3 years
+        assert_eq!(
+            stale_years("new", "2021-06-01T00:00:00Z", TODAY, &config),
+            None
+        );
+        assert_eq!(stale_years("bad", "yesterday", TODAY, &config), None);
+
+        let config = Config {
+            stale_crate_years: 2.0,
+            ..Default::default()
+        };
+        assert!(stale_years("new", "2021-06-01T00:00:00Z", TODAY, &config).is_some());
+    }
+
+    #[test]
+    fn flags_only_known_old_releases() {
+        let dependencies = [
+            dependency("old", false),
+            dependency("new", false),
+            dependency("unknown", false),
+            dependency("forked", true),
+            dependency("finished", false),
+        ];
+        let released = |name: &str| match name {
+            "old" | "forked" | "finished" => Some("2019-10-16T00:00:00Z".to_string()),
+            "new" => Some("2023-12-01T00:00:00Z".to_string()),
+            _ => None,
+        };
+        let config = Config {
+            ignore_stale: vec!["finished".into()],
+            ..stale()
+        };
+        let diagnostics = lint(&dependencies, released, TODAY, &config);
+        let messages = diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            ["`old` may be unmaintained, last release 4.2 years ago"]
+        );
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String(STALE_CRATE.into()))
+        );
+
+        // Opt-in, but the hover always notes it
+        assert!(lint(&dependencies, released, TODAY, &Config::default()).is_empty());
+        assert_eq!(
+            hover("old", "2019-10-16T00:00:00Z", TODAY, &Config::default()).unwrap(),
+            "**Possibly unmaintained**: last release 4.2 years ago"
+        );
+        assert_eq!(
+            hover("finished", "2019-10-16T00:00:00Z", TODAY, &config),
+            None
+        );
+    }
+}
@@ -0,0 +1,403 @@
+//! Usage statistics for the user's own review, only kept in a local file and never sent
+//! anywhere.
+//!
+//! Events are counted in atomics and merged into the file by the background flush,
+//! the counting paths never touch the disk.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::format;
+
+/// Command returning the statistics as markdown and numbers, without arguments.
+pub const STATS: &str = "crates-io.stats";
+/// Command setting all statistics to zero, without arguments.
+pub const RESET_STATS: &str = "crates-io.stats.reset";
+
+/// Version of the file format, files of another version are started over.
+const FORMAT: u32 = 1;
+const FILE_NAME: &str = "stats.json";
+/// Time between the writes of the counted events.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Diagnostic codes counted when they are flagged.
+const CATEGORIES: [&str; 7] = [
+    "any",
+    "latest",
+    "outdated-compatible",
+    "outdated-incompatible",
+    "major-available",
+    "unknown",
+    "fetch-failed",
+];
+
+/// Directory of the files kept across sessions, in the cache directory of the user.
+pub fn default_dir() -> Option<PathBuf> {
+    let var = |name| {
+        std::env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = var("XDG_CACHE_HOME")
+        .or_else(|| var("LOCALAPPDATA"))
+        .or_else(|| var("HOME").map(|home| home.join(".cache")))?;
+    Some(base.join("crates-io-lsp"))
+}
+
+/// Counts of the events of a month.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Counts {
+    /// Diagnostics by code, counted when they first appear in a document
+    pub flagged: BTreeMap<String, u64>,
+    /// Update actions resolved or applied by a command
+    pub actions_applied: u64,
+    pub commands_executed: u64,
+    pub fetches_succeeded: u64,
+    pub fetches_failed: u64,
+    /// Manifests read for a workspace command without parsing them, as they declare no dependencies
+    pub manifests_skipped: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        for (code, n) in &other.flagged {
+            *self.flagged.entry(code.clone()).or_default() += n;
+        }
+        self.actions_applied += other.actions_applied;
+        self.commands_executed += other.commands_executed;
+        self.fetches_succeeded += other.fetches_succeeded;
+        self.fetches_failed += other.fetches_failed;
+        self.manifests_skipped += other.manifests_skipped;
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Counts::default()
+    }
+}
+
+/// Content of the statistics file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatsFile {
+    pub format: u32,
+    /// Counts by month, like `2024-11`
+    pub months: BTreeMap<String, Counts>,
+}
+
+/// Counted events of the process, shared by all connections.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Directory of the file, the statistics are only kept in memory without one
+    dir: Option<PathBuf>,
+    enabled: AtomicBool,
+    flagged: [AtomicU64; CATEGORIES.len()],
+    actions_applied: AtomicU64,
+    commands_executed: AtomicU64,
+    fetches_succeeded: AtomicU64,
+    fetches_failed: AtomicU64,
+    manifests_skipped: AtomicU64,
+    /// The merged counts, loaded from the file at the first flush
+    merged: Mutex<Option<StatsFile>>,
+}
+
+impl Stats {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            ..Default::default()
+        }
+    }
+
+    /// Start or stop counting, the events counted so far are dropped when stopped.
+    pub fn set_enabled(&self, enabled: bool) {
+        if !self.enabled.swap(enabled, Ordering::Relaxed) || enabled {
+            return;
+        }
+        self.take();
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn count(&self, counter: &AtomicU64) {
+        if self.enabled() {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether diagnostics of `code` are counted.
+    pub fn counts_code(&self, code: &str) -> bool {
+        self.enabled() && CATEGORIES.contains(&code)
+    }
+
+    /// A diagnostic of `code` that appeared in a document.
+    pub fn flagged(&self, code: &str) {
+        if let Some(i) = CATEGORIES.iter().position(|c| *c == code) {
+            self.count(&self.flagged[i]);
+        }
+    }
+
+    pub fn action_applied(&self) {
+        self.count(&self.actions_applied);
+    }
+
+    pub fn command_executed(&self) {
+        self.count(&self.commands_executed);
+    }
+
+    pub fn fetched(&self, success: bool) {
+        self.count(if success {
+            &self.fetches_succeeded
+        } else {
+            &self.fetches_failed
+        });
+    }
+
+    pub fn manifest_skipped(&self) {
+        self.count(&self.manifests_skipped);
+    }
+
+    /// The events counted since the last call.
+    fn take(&self) -> Counts {
+        let take = |counter: &AtomicU64| counter.swap(0, Ordering::Relaxed);
+        Counts {
+            flagged: CATEGORIES
+                .iter()
+                .zip(&self.flagged)
+                .map(|(code, n)| (code.to_string(), take(n)))
+                .filter(|(_, n)| *n > 0)
+                .collect(),
+            actions_applied: take(&self.actions_applied),
+            commands_executed: take(&self.commands_executed),
+            fetches_succeeded: take(&self.fetches_succeeded),
+            fetches_failed: take(&self.fetches_failed),
+            manifests_skipped: take(&self.manifests_skipped),
+        }
+    }
+
+    /// Add the counted events to the month of `now` (seconds since the Unix epoch)
+    /// and write the file if anything changed, returning all counts.
+    pub async fn flush(&self, now: i64) -> std::io::Result<StatsFile> {
+        let mut merged = self.merged.lock().await;
+        let file = match &mut *merged {
+            Some(file) => file,
+            None => merged.insert(self.load().await),
+        };
+        let counts = self.take();
+        if !counts.is_empty() {
+            let month = format::timestamp(now)[..7].to_string();
+            file.months.entry(month).or_default().add(&counts);
+            self.write(file).await?;
+        }
+        Ok(file.clone())
+    }
+
+    /// Set all statistics to zero, also in the file.
+    pub async fn reset(&self) -> std::io::Result<()> {
+        let mut merged = self.merged.lock().await;
+        self.take();
+        let file = merged.insert(StatsFile {
+            format: FORMAT,
+            ..Default::default()
+        });
+        self.write(file).await
+    }
+
+    /// The statistics of the file, empty if it is missing, invalid, or of another format.
+    async fn load(&self) -> StatsFile {
+        let empty = StatsFile {
+            format: FORMAT,
+            ..Default::default()
+        };
+        let Some(dir) = &self.dir else {
+            return empty;
+        };
+        let Ok(text) = tokio::fs::read_to_string(dir.join(FILE_NAME)).await else {
+            return empty;
+        };
+        serde_json::from_str::<StatsFile>(&text)
+            .ok()
+            .filter(|file| file.format == FORMAT)
+            .unwrap_or(empty)
+    }
+
+    /// Replace the file, through a temporary file so that it is never half written.
+    async fn write(&self, file: &StatsFile) -> std::io::Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        tokio::fs::create_dir_all(dir).await?;
+        let temp = dir.join(format!("{FILE_NAME}.{}", std::process::id()));
+        let json = serde_json::to_vec_pretty(file).map_err(std::io::Error::other)?;
+        tokio::fs::write(&temp, json).await?;
+        tokio::fs::rename(&temp, dir.join(FILE_NAME)).await
+    }
+
+    /// Path of the file, if the statistics are persisted.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.dir.as_deref().map(|dir| dir.join(FILE_NAME))
+    }
+}
+
+impl StatsFile {
+    /// Counts of all months together.
+    pub fn total(&self) -> Counts {
+        let mut total = Counts::default();
+        for counts in self.months.values() {
+            total.add(counts);
+        }
+        total
+    }
+
+    /// Table of the counts of `month` and in total.
+    pub fn markdown(&self, month: &str, path: Option<&Path>) -> String {
+        let current = self.months.get(month).cloned().unwrap_or_default();
+        let total = self.total();
+        let mut out = String::from("# Usage statistics\n\n");
+        match self.months.keys().next() {
+            Some(first) => out += &format!("Counted since {first}.\n\n"),
+            None => out += "Nothing counted yet.\n\n",
+        }
+        out += &format!("| | {month} | Total |\n|---|---:|---:|\n");
+        let mut row = |label: &str, current: u64, total: u64| {
+            out += &format!("| {label} | {current} | {total} |\n");
+        };
+        for code in CATEGORIES {
+            let flagged = |c: &Counts| c.flagged.get(code).copied().unwrap_or_default();
+            row(
+                &format!("Flagged `{code}`"),
+                flagged(&current),
+                flagged(&total),
+            );
+        }
+        row(
+            "Update actions applied",
+            current.actions_applied,
+            total.actions_applied,
+        );
+        row(
+            "Commands executed",
+            current.commands_executed,
+            total.commands_executed,
+        );
+        row(
+            "Fetches succeeded",
+            current.fetches_succeeded,
+            total.fetches_succeeded,
+        );
+        row(
+            "Fetches failed",
+            current.fetches_failed,
+            total.fetches_failed,
+        );
+        row(
+            "Manifests without dependencies skipped",
+            current.manifests_skipped,
+            total.manifests_skipped,
+        );
+        if let Some(path) = path {
+            out += &format!(
+                "\nKept in `{}`, nothing is sent anywhere.\n",
+                path.display()
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seconds since the Unix epoch of 2024-11-03 and 2024-12-01.
+    const NOVEMBER: i64 = 1_730_592_000;
+    const DECEMBER: i64 = 1_733_011_200;
+
+    fn dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("crates-io-lsp-stats-{name}-{}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn counts_only_when_enabled() {
+        let stats = Stats::new(None);
+        stats.fetched(true);
+        stats.set_enabled(true);
+        stats.fetched(true);
+        stats.flagged("outdated-compatible");
+        stats.flagged("unknown-key");
+        let file = stats.flush(NOVEMBER).await.unwrap();
+        let counts = &file.months["2024-11"];
+        assert_eq!(counts.fetches_succeeded, 1);
+        assert_eq!(
+            counts.flagged,
+            BTreeMap::from([("outdated-compatible".into(), 1)])
+        );
+
+        // Stopping drops what was not flushed yet
+        stats.command_executed();
+        stats.set_enabled(false);
+        stats.set_enabled(true);
+        let file = stats.flush(NOVEMBER).await.unwrap();
+        assert_eq!(file.months["2024-11"].commands_executed, 0);
+    }
+
+    #[tokio::test]
+    async fn persists_by_month() {
+        let dir = dir("months");
+        let _ = std::fs::remove_dir_all(&dir);
+        let stats = Stats::new(Some(dir.clone()));
+        stats.set_enabled(true);
+        stats.action_applied();
+        stats.flush(NOVEMBER).await.unwrap();
+        stats.action_applied();
+        stats.fetched(false);
+        stats.flush(DECEMBER).await.unwrap();
+
+        // A new session continues the counts of the file
+        let again = Stats::new(Some(dir.clone()));
+        again.set_enabled(true);
+        again.action_applied();
+        let file = again.flush(DECEMBER).await.unwrap();
+        assert_eq!(file.months["2024-11"].actions_applied, 1);
+        assert_eq!(file.months["2024-12"].actions_applied, 2);
+        assert_eq!(file.total().fetches_failed, 1);
+        let markdown = file.markdown("2024-12", None);
+        assert!(markdown.contains("Counted since 2024-11."), "{markdown}");
+        assert!(
+            markdown.contains("| Update actions applied | 2 | 3 |"),
+            "{markdown}"
+        );
+
+        again.reset().await.unwrap();
+        let text = std::fs::read_to_string(dir.join(FILE_NAME)).unwrap();
+        assert_eq!(
+            serde_json::from_str::<StatsFile>(&text).unwrap(),
+            StatsFile {
+                format: FORMAT,
+                months: BTreeMap::new()
+            }
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn starts_over_with_another_format() {
+        let dir = dir("format");
+        std::fs::create_dir_all(&dir).unwrap();
+        let old = r#"{ "format": 0, "months": { "2024-11": { "actionsApplied": 9 } } }"#;
+        std::fs::write(dir.join(FILE_NAME), old).unwrap();
+        let stats = Stats::new(Some(dir.clone()));
+        stats.set_enabled(true);
+        stats.action_applied();
+        let file = stats.flush(NOVEMBER).await.unwrap();
+        assert_eq!(file.format, FORMAT);
+        assert_eq!(file.total().actions_applied, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+//! Features that well-known crates are usually declared with, like `derive` of `serde`.
+//!
+//! The hints are curated data, `featureHints` of the configuration adds to or replaces them.
+
+use std::collections::{BTreeMap, HashMap};
+
+use semver::VersionReq;
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString, TextEdit};
+
+use crate::config::{Config, FeatureHint};
+use crate::features::quoted;
+use crate::manifest::SpannedManifest;
+use crate::messages::{Key, Message};
+use crate::shadow::field_edit;
+use crate::text::Source;
+use crate::version::Releases;
+
+/// Diagnostic code of [`lint`], the data is a [`FeatureFix`].
+pub const MISSING_FEATURE: &str = "missing-feature";
+
+/// Crates with the features they are usually needed with and why.
+const DEFAULT_HINTS: [(&str, &[&str], &str); 6] = [
+    (
+        "serde",
+        &["derive"],
+        "for `#[derive(Serialize, Deserialize)]`",
+    ),
+    (
+        "tokio",
+        &["macros", "rt-multi-thread"],
+        "for `#[tokio::main]`",
+    ),
+    ("clap", &["derive"], "for `#[derive(Parser)]`"),
+    ("reqwest", &["json"], "for JSON bodies and responses"),
+    ("uuid", &["v4"], "for `Uuid::new_v4()`"),
+    ("strum", &["derive"], "for `#[derive(EnumString, Display)]`"),
+];
+
+/// Feature maps of a crate by version, as written in the index.
+pub type FeatureMaps = HashMap<String, BTreeMap<String, Vec<String>>>;
+
+/// Data of a [`MISSING_FEATURE`] diagnostic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureFix {
+    pub features: Vec<String>,
+    /// Adds the `features` to the dependency
+    pub edit: TextEdit,
+}
+
+/// The hints with the ones of `featureHints`, hints without features are removed.
+pub fn hints(config: &Config) -> BTreeMap<String, FeatureHint> {
+    let mut hints = DEFAULT_HINTS
+        .iter()
+        .map(|(name, features, reason)| {
+            let hint = FeatureHint {
+                features: features.iter().map(|f| f.to_string()).collect(),
+                reason: reason.to_string(),
+            };
+            (name.to_string(), hint)
+        })
+        .collect::<BTreeMap<_, _>>();
+    for (name, hint) in &config.feature_hints {
+        if hint.features.is_empty() {
+            hints.remove(name);
+        } else {
+            hints.insert(name.clone(), hint.clone());
+        }
+    }
+    hints
+}
+
+/// Features to pre-fill when completing `features` of `package`, if `suggestFeatures` is enabled.
+pub fn completion(package: &str, config: &Config) -> Option<FeatureHint> {
+    if !config.suggest_features {
+        return None;
+    }
+    hints(config).remove(package)
+}
+
+/// Hint at registry dependencies declared without the features they are usually needed with.
+///
+/// Only features of the newest version matching the requirement are suggested, as found in
+/// its feature map. Returns the diagnostics and the hinted crates whose feature maps are not
+/// in `maps` yet, which are hinted at once they are fetched.
+pub fn lint(
+    manifest: &SpannedManifest,
+    source: &Source,
+    versions: &HashMap<String, Releases>,
+    maps: &HashMap<String, FeatureMaps>,
+    config: &Config,
+) -> (Vec<Diagnostic>, Vec<String>) {
+    if !config.missing_features {
+        return Default::default();
+    }
+    let hints = hints(config);
+    let dependencies = manifest
+        .dependencies()
+        .map(|(_, name, dep)| (name, dep))
+        .chain(
+            manifest
+                .target_dependencies()
+                .map(|(_, _, name, dep)| (name, dep)),
+        );
+    let mut diagnostics = Vec::new();
+    let mut unknown = Vec::new();
+    for (name, dep) in dependencies {
+        let package = dep.package().unwrap_or(name.get_ref());
+        let Some(hint) = hints.get(package) else {
+            continue;
+        };
+        let declared = dep.detail().is_some_and(|d| {
+            d.features.is_some() || d.path.is_some() || d.git.is_some() || dep.inherited()
+        });
+        if declared {
+            continue;
+        }
+        let Ok(req) = VersionReq::parse(dep.req()) else {
+            continue;
+        };
+        let Some(newest) = versions.get(package).and_then(|versions| {
+            versions
+                .iter()
+                .filter(|r| !r.yanked && req.matches(&r.version))
+                .max_by(|a, b| a.version.cmp(&b.version))
+        }) else {
+            continue; // Not fetched or nothing matches, flagged elsewhere
+        };
+        let Some(by_version) = maps.get(package) else {
+            unknown.push(package.to_string());
+            continue;
+        };
+        // Fetched before the version was published, or without feature maps
+        let Some(map) = by_version.get(&newest.raw) else {
+            continue;
+        };
+        // Never suggest a feature the version does not have
+        if !hint.features.iter().all(|f| map.contains_key(f)) {
+            continue;
+        }
+        let features = hint.features.iter().map(|f| format!("{f:?}"));
+        let field = format!("features = [{}]", features.collect::<Vec<_>>().join(", "));
+        let (Some(range), Some(edit)) =
+            (source.range(name.span()), field_edit(dep, source, &field))
+        else {
+            continue;
+        };
+        let fix = FeatureFix {
+            features: hint.features.clone(),
+            edit,
+        };
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(config.severity.missing_feature.into()),
+            code: Some(NumberOrString::String(MISSING_FEATURE.into())),
+            source: Some(config.source(MISSING_FEATURE)),
+            message: Message::new(if hint.features.len() == 1 {
+                Key::MissingFeature
+            } else {
+                Key::MissingFeatures
+            })
+            .arg(format!("`{package}`"))
+            .arg(quoted(&hint.features))
+            .arg(reason(&hint.reason))
+            .render(config.language),
+            data: serde_json::to_value(fix).ok(),
+            ..Default::default()
+        });
+    }
+    unknown.sort_unstable();
+    unknown.dedup();
+    (diagnostics, unknown)
+}
+
+/// The reason appended to the message, after a comma.
+fn reason(reason: &str) -> String {
+    if reason.is_empty() {
+        String::new()
+    } else {
+        format!(", {reason}")
+    }
+}
+
+/// Title of the quick fix adding the `features`.
+pub fn title(features: &[String]) -> String {
+    match features {
+        [feature] => format!("Add `{feature}` feature"),
+        _ => format!("Add features {}", quoted(features)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::lsp_types::{self, Position};
+
+    use super::*;
+    use crate::manifest;
+    use crate::text::Encoding;
+    use crate::version::Release;
+
+    fn source(text: &str) -> Source {
+        Source::new(text, Encoding::Utf8)
+    }
+
+    fn versions(crates: &[(&str, &[&str])]) -> HashMap<String, Releases> {
+        crates
+            .iter()
+            .map(|(name, versions)| {
+                let releases = versions
+                    .iter()
+                    .map(|v| Release::parse(v, false).unwrap())
+                    .collect();
+                (name.to_string(), releases)
+            })
+            .collect()
+    }
+
+    fn maps(crates: &[(&str, &str, &[&str])]) -> HashMap<String, FeatureMaps> {
+        let mut maps = HashMap::<String, FeatureMaps>::new();
+        for (name, version, features) in crates {
+            let map = features
+                .iter()
+                .map(|f| (f.to_string(), Vec::new()))
+                .collect();
+            maps.entry(name.to_string())
+                .or_default()
+                .insert(version.to_string(), map);
+        }
+        maps
+    }
+
+    #[test]
+    fn configured_hints_extend_the_defaults() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "featureHints": {
+                "serde": { "features": ["derive", "rc"] },
+                "tokio": { "features": [] },
+                "sqlx": { "features": ["runtime-tokio"], "reason": "for a runtime" },
+            },
+        }))
+        .unwrap();
+        let hints = hints(&config);
+        assert_eq!(hints["serde"].features, ["derive", "rc"]);
+        assert_eq!(hints["serde"].reason, "");
+        assert_eq!(hints["sqlx"].reason, "for a runtime");
+        assert_eq!(hints["clap"].features, ["derive"]);
+        assert!(!hints.contains_key("tokio"));
+
+        assert_eq!(
+            completion("tokio", &Config::default()).unwrap().features,
+            ["macros", "rt-multi-thread"]
+        );
+        let disabled = Config {
+            suggest_features: false,
+            ..Default::default()
+        };
+        assert_eq!(completion("tokio", &disabled), None);
+    }
+
+    #[test]
+    fn hints_at_features_of_the_matched_version() {
+        let text = "[dependencies]\nserde = \"1\"\n\
+            tokio = { version = \"1\", default-features = false }\n\
+            clap = { version = \"4\", features = [\"env\"] }\n\
+            uuid = \"1\"\n\n\
+            [dependencies.reqwest]\nversion = \"0.12\"\n";
+        let (manifest, _) = manifest::parse(text);
+        let versions = versions(&[
+            ("serde", &["1.0.0", "1.0.200", "2.0.0"]),
+            ("tokio", &["1.40.0"]),
+            ("clap", &["4.5.0"]),
+            ("uuid", &["1.10.0"]),
+            ("reqwest", &["0.12.5"]),
+        ]);
+        let maps = maps(&[
+            ("serde", "1.0.0", &[]),
+            ("serde", "1.0.200", &["derive", "std"]),
+            ("tokio", "1.40.0", &["macros", "rt-multi-thread"]),
+            ("clap", "4.5.0", &["derive", "env"]),
+            // Renamed features are never suggested
+            ("uuid", "1.10.0", &["v7"]),
+        ]);
+        let config = Config::default();
+        let (diagnostics, unknown) = lint(&manifest, &source(text), &versions, &maps, &config);
+        assert_eq!(unknown, ["reqwest"]);
+        let fixes = diagnostics
+            .iter()
+            .map(|d| {
+                let fix: FeatureFix = serde_json::from_value(d.data.clone().unwrap()).unwrap();
+                (d.range.start.line, fix.edit)
+            })
+            .collect::<Vec<_>>();
+        let edit = |line, start, end, new_text: &str| TextEdit {
+            range: lsp_types::Range::new(Position::new(line, start), Position::new(line, end)),
+            new_text: new_text.into(),
+        };
+        assert_eq!(
+            fixes,
+            [
+                (
+                    1,
+                    edit(1, 8, 11, "{ version = \"1\", features = [\"derive\"] }")
+                ),
+                (
+                    2,
+                    edit(2, 49, 49, ", features = [\"macros\", \"rt-multi-thread\"]")
+                ),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "`serde` is usually needed with the feature `derive`, for `#[derive(Serialize, Deserialize)]`"
+        );
+        assert_eq!(
+            diagnostics[0].severity,
+            Some(lsp_types::DiagnosticSeverity::INFORMATION)
+        );
+
+        // The table form gets a line of its own
+        let maps = maps_with_reqwest(maps);
+        let (diagnostics, unknown) = lint(&manifest, &source(text), &versions, &maps, &config);
+        assert!(unknown.is_empty());
+        let reqwest = diagnostics
+            .iter()
+            .find(|d| d.range.start.line == 6)
+            .unwrap();
+        let fix: FeatureFix = serde_json::from_value(reqwest.data.clone().unwrap()).unwrap();
+        assert_eq!(fix.edit, edit(7, 16, 16, "\nfeatures = [\"json\"]"));
+
+        let disabled = Config {
+            missing_features: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            lint(&manifest, &source(text), &versions, &maps, &disabled),
+            Default::default()
+        );
+    }
+
+    fn maps_with_reqwest(mut maps: HashMap<String, FeatureMaps>) -> HashMap<String, FeatureMaps> {
+        maps.extend(self::maps(&[("reqwest", "0.12.5", &["json"])]));
+        maps
+    }
+
+    #[test]
+    fn titles() {
+        assert_eq!(title(&["derive".into()]), "Add `derive` feature");
+        assert_eq!(
+            title(&["macros".into(), "rt-multi-thread".into()]),
+            "Add features `macros`, `rt-multi-thread`"
+        );
+    }
+}
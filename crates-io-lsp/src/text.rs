@@ -0,0 +1,535 @@
+//! Conversions between byte offsets into a document and LSP positions.
+
+use std::ops::Range;
+
+use serde::Serialize;
+use tower_lsp_server::lsp_types::{self, Position, PositionEncodingKind};
+
+/// What the `character` of a position counts, negotiated with the client in `initialize`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum Encoding {
+    /// Bytes of UTF-8, like the spans of the parsed manifests
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// Code units of UTF-16, the default of LSP clients
+    #[default]
+    #[serde(rename = "utf-16")]
+    Utf16,
+}
+
+impl Encoding {
+    /// UTF-8 if the client offers it, which saves the conversion, otherwise UTF-16,
+    /// which every client supports.
+    pub fn negotiate(offered: Option<&[PositionEncodingKind]>) -> Self {
+        match offered {
+            Some(offered) if offered.contains(&PositionEncodingKind::UTF8) => Self::Utf8,
+            _ => Self::Utf16,
+        }
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+}
+
+/// A character of more than one byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Wide {
+    /// Byte columns of the start and end
+    start: u32,
+    end: u32,
+    /// UTF-16 columns of the start and end
+    start16: u32,
+    end16: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line {
+    /// Byte offset of the line in the document
+    start: usize,
+    /// Length in bytes without the line break
+    len: u32,
+    /// Length in UTF-16 code units without the line break
+    len16: u32,
+    /// Characters of more than one byte with their UTF-16 columns, in order
+    wide: Vec<Wide>,
+}
+
+impl Line {
+    /// Index of the first wide character not ending before the byte `column`.
+    fn wide_at(&self, column: u32) -> usize {
+        self.wide.partition_point(|w| w.end <= column)
+    }
+
+    /// UTF-16 column of a byte column on a character boundary.
+    fn utf16_column(&self, column: u32) -> u32 {
+        match self.wide_at(column).checked_sub(1) {
+            Some(i) => self.wide[i].end16 + (column - self.wide[i].end),
+            None => column,
+        }
+    }
+
+    /// Byte column of a UTF-16 column, the start of a character it falls into.
+    fn byte_column(&self, column: u32) -> u32 {
+        let i = self.wide.partition_point(|w| w.end16 <= column);
+        if let Some(w) = self.wide.get(i).filter(|w| w.start16 < column) {
+            return w.start;
+        }
+        match i.checked_sub(1) {
+            Some(i) => self.wide[i].end + (column - self.wide[i].end16),
+            None => column,
+        }
+    }
+
+    /// The byte column moved to the start of a character it falls into.
+    fn floor(&self, column: u32) -> u32 {
+        match self.wide.get(self.wide_at(column)) {
+            Some(w) if w.start < column => w.start,
+            _ => column,
+        }
+    }
+}
+
+/// Lines of a document version, built once to convert offsets and positions quickly.
+///
+/// Lines end with `\n`, a `\r` before it is not part of the line.
+/// Offsets within a line break are at the end of its line,
+/// and characters beyond the end of a line are clamped to it, like LSP requires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    lines: Vec<Line>,
+    len: usize,
+    encoding: Encoding,
+    /// Whether the client's text starts with a byte order mark that the indexed text lacks
+    bom: bool,
+}
+
+impl LineIndex {
+    /// Lines of `text` with positions in `encoding`.
+    pub fn new(text: &str, encoding: Encoding) -> Self {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        // The empty line after a final line break is a line as well
+        for line in text.split('\n') {
+            let content = line.strip_suffix('\r').unwrap_or(line);
+            let mut wide = Vec::new();
+            let mut len16 = 0;
+            for (i, c) in content.char_indices() {
+                let (len, units) = (c.len_utf8() as u32, c.len_utf16() as u32);
+                if len > 1 {
+                    wide.push(Wide {
+                        start: i as u32,
+                        end: i as u32 + len,
+                        start16: len16,
+                        end16: len16 + units,
+                    });
+                }
+                len16 += units;
+            }
+            lines.push(Line {
+                start,
+                len: content.len() as u32,
+                len16,
+                wide,
+            });
+            start += line.len() + 1;
+        }
+        Self {
+            lines,
+            len: text.len(),
+            encoding,
+            bom: false,
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Columns of the stripped byte order mark before the first line.
+    fn bom_width(&self) -> u32 {
+        match (self.bom, self.encoding) {
+            (false, _) => 0,
+            (true, Encoding::Utf8) => BOM.len_utf8() as u32,
+            (true, Encoding::Utf16) => BOM.len_utf16() as u32,
+        }
+    }
+
+    /// Position of a byte offset, `None` beyond the end or inside a character.
+    pub fn offset_to_position(&self, offset: usize) -> Option<Position> {
+        if offset > self.len {
+            return None;
+        }
+        let index = self.lines.partition_point(|l| l.start <= offset) - 1;
+        let line = &self.lines[index];
+        let column = ((offset - line.start) as u32).min(line.len);
+        if line.floor(column) != column {
+            return None;
+        }
+        let mut character = match self.encoding {
+            Encoding::Utf8 => column,
+            Encoding::Utf16 => line.utf16_column(column),
+        };
+        if index == 0 {
+            character += self.bom_width();
+        }
+        Some(Position::new(index as u32, character))
+    }
+
+    /// Byte offset of a position, `None` beyond the last line.
+    ///
+    /// Positions inside a character are moved to its start.
+    pub fn position_to_offset(&self, position: Position) -> Option<usize> {
+        let line = self.lines.get(position.line as usize)?;
+        let mut character = position.character;
+        if position.line == 0 {
+            // Positions within the byte order mark are at the start
+            character = character.saturating_sub(self.bom_width());
+        }
+        let column = match self.encoding {
+            Encoding::Utf8 => line.floor(character.min(line.len)),
+            Encoding::Utf16 => line.byte_column(character.min(line.len16)),
+        };
+        Some(line.start + column as usize)
+    }
+
+    /// Range of a byte span.
+    pub fn span_to_range(&self, span: Range<usize>) -> Option<lsp_types::Range> {
+        Some(lsp_types::Range {
+            start: self.offset_to_position(span.start)?,
+            end: self.offset_to_position(span.end)?,
+        })
+    }
+
+    /// Byte span of a range, `None` if it ends before its start.
+    pub fn range_to_span(&self, range: lsp_types::Range) -> Option<Range<usize>> {
+        let start = self.position_to_offset(range.start)?;
+        let end = self.position_to_offset(range.end)?;
+        (start <= end).then_some(start..end)
+    }
+}
+
+/// A document version: its text and the index of its lines, built once for all analyses.
+///
+/// A leading byte order mark, which TOML does not allow, is not part of the text,
+/// but still counted by the positions of the first line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub text: String,
+    pub lines: LineIndex,
+}
+
+impl Source {
+    pub fn new(text: &str, encoding: Encoding) -> Self {
+        let stripped = strip_bom(text);
+        Self::indexed(stripped.into(), encoding, stripped.len() < text.len())
+    }
+
+    fn indexed(text: String, encoding: Encoding, bom: bool) -> Self {
+        Self {
+            lines: LineIndex {
+                bom,
+                ..LineIndex::new(&text, encoding)
+            },
+            text,
+        }
+    }
+
+    /// The document with the edited `text`, which keeps its byte order mark.
+    pub fn edited(&self, text: String) -> Self {
+        Self::indexed(text, self.lines.encoding, self.lines.bom)
+    }
+
+    /// Whether the document starts with a byte order mark.
+    pub fn bom(&self) -> bool {
+        self.lines.bom
+    }
+
+    /// Position of a byte offset, `None` beyond the end or inside a character.
+    pub fn position(&self, offset: usize) -> Option<Position> {
+        self.lines.offset_to_position(offset)
+    }
+
+    /// Range of a byte span.
+    pub fn range(&self, span: Range<usize>) -> Option<lsp_types::Range> {
+        self.lines.span_to_range(span)
+    }
+
+    /// Range of a string value without the quotes.
+    pub fn string_range(&self, span: Range<usize>) -> Option<lsp_types::Range> {
+        self.range(string_content(&self.text, span)?)
+    }
+}
+
+const BOM: char = '\u{feff}';
+
+/// The text without a leading UTF-8 byte order mark.
+pub fn strip_bom(text: &str) -> &str {
+    text.strip_prefix(BOM).unwrap_or(text)
+}
+
+/// Span of a string value without the quotes, `None` if the span is not a string.
+pub fn string_content(text: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let quoted = text.get(span.clone())?;
+    let quote = quoted.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let len = if quoted.starts_with(&quote.to_string().repeat(3)) {
+        3
+    } else {
+        1
+    };
+    (span.len() >= 2 * len).then(|| span.start + len..span.end - len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Encoding::{Utf16, Utf8};
+
+    /// Positions of every offset of `text`, `None` inside characters.
+    fn positions(text: &str, encoding: Encoding) -> Vec<Option<(u32, u32)>> {
+        let index = LineIndex::new(text, encoding);
+        (0..=text.len() + 1)
+            .map(|offset| {
+                let position = index.offset_to_position(offset)?;
+                Some((position.line, position.character))
+            })
+            .collect()
+    }
+
+    /// Check that converting every character boundary back and forth is lossless.
+    fn round_trip(text: &str) {
+        for encoding in [Utf8, Utf16] {
+            let index = LineIndex::new(text, encoding);
+            for offset in (0..=text.len()).filter(|&o| text.is_char_boundary(o)) {
+                let position = index.offset_to_position(offset).unwrap();
+                let back = index.position_to_offset(position).unwrap();
+                // Between `\r` and `\n` is the end of the line as well
+                let in_break = text[..offset].ends_with('\r') && text[offset..].starts_with('\n');
+                let expected = if in_break { offset - 1 } else { offset };
+                assert_eq!(back, expected, "{text:?} {offset} {encoding:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn empty_document() {
+        for encoding in [Utf8, Utf16] {
+            let index = LineIndex::new("", encoding);
+            assert_eq!(index.offset_to_position(0), Some(Position::new(0, 0)));
+            assert_eq!(index.offset_to_position(1), None);
+            assert_eq!(index.position_to_offset(Position::new(0, 0)), Some(0));
+            assert_eq!(index.position_to_offset(Position::new(0, 5)), Some(0));
+            assert_eq!(index.position_to_offset(Position::new(1, 0)), None);
+        }
+    }
+
+    #[test]
+    fn without_trailing_newline() {
+        let text = "a = 1\nbc";
+        let expected = [
+            (0, 0),
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (0, 5),
+            (1, 0),
+            (1, 1),
+            (1, 2),
+        ];
+        let expected = expected
+            .map(Some)
+            .into_iter()
+            .chain([None])
+            .collect::<Vec<_>>();
+        assert_eq!(positions(text, Utf8), expected);
+        assert_eq!(positions(text, Utf16), expected);
+
+        let (utf8, utf16) = (LineIndex::new(text, Utf8), LineIndex::new(text, Utf16));
+        // End of the file
+        assert_eq!(utf8.position_to_offset(Position::new(1, 2)), Some(8));
+        assert_eq!(utf16.position_to_offset(Position::new(1, 9)), Some(8));
+        assert_eq!(utf8.position_to_offset(Position::new(2, 0)), None);
+        round_trip(text);
+    }
+
+    #[test]
+    fn with_trailing_newline() {
+        let text = "a\n";
+        let (utf8, utf16) = (LineIndex::new(text, Utf8), LineIndex::new(text, Utf16));
+        // The line after the final line break
+        assert_eq!(utf8.offset_to_position(2), Some(Position::new(1, 0)));
+        assert_eq!(utf8.position_to_offset(Position::new(1, 0)), Some(2));
+        assert_eq!(utf16.position_to_offset(Position::new(1, 3)), Some(2));
+        assert_eq!(utf8.position_to_offset(Position::new(2, 0)), None);
+        round_trip(text);
+    }
+
+    #[test]
+    fn positions_with_crlf() {
+        let text = "a = 1\r\nbc = 2\r\n\r\nd";
+        let index = LineIndex::new(text, Utf8);
+        let at = |offset| index.offset_to_position(offset);
+        assert_eq!(at(0), Some(Position::new(0, 0)));
+        // The `\r` is not part of the column
+        assert_eq!(at(5), Some(Position::new(0, 5)));
+        assert_eq!(at(6), Some(Position::new(0, 5)));
+        assert_eq!(at(7), Some(Position::new(1, 0)));
+        assert_eq!(at(16), Some(Position::new(2, 0)));
+        assert_eq!(at(17), Some(Position::new(3, 0)));
+        assert_eq!(at(18), Some(Position::new(3, 1)));
+        assert_eq!(at(19), None);
+
+        let offset = |line, character| index.position_to_offset(Position::new(line, character));
+        assert_eq!(offset(1, 2), Some(9));
+        // Clamped before the `\r`, at the end of the line
+        assert_eq!(offset(1, 6), Some(13));
+        assert_eq!(offset(1, 40), Some(13));
+        assert_eq!(offset(2, 0), Some(15));
+        assert_eq!(offset(3, 1), Some(18));
+        assert_eq!(offset(4, 0), None);
+        round_trip(text);
+    }
+
+    #[test]
+    fn multi_byte_characters() {
+        // `ä` has two bytes and one UTF-16 unit, `€` three bytes and one unit
+        let text = "a = \"ä€\"\nx";
+        assert_eq!(
+            positions(text, Utf8),
+            [
+                Some((0, 0)),
+                Some((0, 1)),
+                Some((0, 2)),
+                Some((0, 3)),
+                Some((0, 4)),
+                Some((0, 5)),
+                None,
+                Some((0, 7)),
+                None,
+                None,
+                Some((0, 10)),
+                Some((0, 11)),
+                Some((1, 0)),
+                Some((1, 1)),
+                None,
+            ]
+        );
+        let index = LineIndex::new(text, Utf16);
+        let utf16 = |offset| index.offset_to_position(offset).unwrap().character;
+        assert_eq!([utf16(5), utf16(7), utf16(10), utf16(11)], [5, 6, 7, 8]);
+
+        let offset = |character, encoding| {
+            LineIndex::new(text, encoding).position_to_offset(Position::new(0, character))
+        };
+        assert_eq!(offset(6, Utf16), Some(7));
+        assert_eq!(offset(7, Utf16), Some(10));
+        // Inside the bytes of `ä`, moved to its start
+        assert_eq!(offset(6, Utf8), Some(5));
+        assert_eq!(offset(7, Utf8), Some(7));
+        assert_eq!(offset(9, Utf8), Some(7));
+        // End of the line
+        assert_eq!(offset(8, Utf16), Some(11));
+        assert_eq!(offset(9, Utf16), Some(11));
+        assert_eq!(offset(11, Utf8), Some(11));
+        round_trip(text);
+    }
+
+    #[test]
+    fn characters_outside_the_bmp() {
+        // `🦀` has four bytes and two UTF-16 units, a surrogate pair
+        let text = "🦀 = \"🦀\"\r\n";
+        let index = LineIndex::new(text, Utf16);
+        let utf16 = |offset| index.offset_to_position(offset);
+        assert_eq!(utf16(0), Some(Position::new(0, 0)));
+        assert_eq!(utf16(2), None);
+        assert_eq!(utf16(4), Some(Position::new(0, 2)));
+        assert_eq!(utf16(8), Some(Position::new(0, 6)));
+        assert_eq!(utf16(12), Some(Position::new(0, 8)));
+        assert_eq!(utf16(13), Some(Position::new(0, 9)));
+        // Within the line break
+        assert_eq!(utf16(14), Some(Position::new(0, 9)));
+        assert_eq!(utf16(15), Some(Position::new(1, 0)));
+
+        let offset = |character| index.position_to_offset(Position::new(0, character));
+        // Between the surrogates, moved to the start of the character
+        assert_eq!(offset(1), Some(0));
+        assert_eq!(offset(7), Some(8));
+        assert_eq!(offset(8), Some(12));
+        assert_eq!(offset(9), Some(13));
+        assert_eq!(offset(10), Some(13));
+        round_trip(text);
+    }
+
+    #[test]
+    fn spans_and_ranges() {
+        let text = "[dependencies]\nserde = \"1.0\"\n";
+        let index = LineIndex::new(text, Utf8);
+        let range = lsp_types::Range::new(Position::new(1, 9), Position::new(1, 12));
+        assert_eq!(index.span_to_range(24..27), Some(range));
+        assert_eq!(
+            LineIndex::new(text, Utf16).range_to_span(range),
+            Some(24..27)
+        );
+        // Reversed ranges and spans beyond the end
+        let reversed = lsp_types::Range::new(range.end, range.start);
+        assert_eq!(index.range_to_span(reversed), None);
+        assert_eq!(index.span_to_range(24..40), None);
+
+        let source = Source::new(text, Utf8);
+        assert_eq!(source.string_range(23..28), Some(range));
+        assert_eq!(source.string_range(24..27), None);
+    }
+
+    #[test]
+    fn strips_the_bom() {
+        assert_eq!(strip_bom("\u{feff}[package]"), "[package]");
+        assert_eq!(strip_bom("[package]"), "[package]");
+
+        let text = "\u{feff}dependencies.rand = \"0.11\"\r\n[package]\r\n";
+        let rand = lsp_types::Range::new(Position::new(0, 14), Position::new(0, 18));
+        let source = Source::new(text, Utf16);
+        assert!(source.bom());
+        assert_eq!(source.text, &text[3..]);
+        // The BOM is one UTF-16 code unit or three bytes before the first line
+        assert_eq!(source.range(13..17), Some(rand));
+        assert_eq!(source.lines.range_to_span(rand), Some(13..17));
+        assert_eq!(
+            Source::new(text, Utf8).range(13..17),
+            Some(lsp_types::Range::new(
+                Position::new(0, 16),
+                Position::new(0, 20)
+            ))
+        );
+        // Positions within the BOM are at the start, other lines are not shifted
+        assert_eq!(
+            source.lines.position_to_offset(Position::new(0, 0)),
+            Some(0)
+        );
+        assert_eq!(source.position(0), Some(Position::new(0, 1)));
+        assert_eq!(source.position(28), Some(Position::new(1, 0)));
+
+        // Edits keep the BOM
+        let edited = source.edited(source.text.replace("0.11", "0.12"));
+        assert!(edited.bom());
+        assert_eq!(edited.range(13..17), Some(rand));
+        assert!(!Source::new(&text[3..], Utf16).bom());
+    }
+
+    #[test]
+    fn negotiates_the_encoding() {
+        let utf8 = PositionEncodingKind::UTF8;
+        let utf16 = PositionEncodingKind::UTF16;
+        assert_eq!(Encoding::negotiate(None), Utf16);
+        assert_eq!(Encoding::negotiate(Some(&[])), Utf16);
+        assert_eq!(
+            Encoding::negotiate(Some(std::slice::from_ref(&utf16))),
+            Utf16
+        );
+        assert_eq!(Encoding::negotiate(Some(&[utf16, utf8.clone()])), Utf8);
+        assert_eq!(Encoding::Utf8.kind(), utf8);
+    }
+}
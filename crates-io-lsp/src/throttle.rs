@@ -0,0 +1,327 @@
+use std::fmt::Display;
+use std::mem;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+use tower_lsp_server::lsp_types::notification::Progress;
+use tower_lsp_server::lsp_types::{
+    MessageType, ProgressParams, ProgressParamsValue, ProgressToken, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+use tower_lsp_server::Client;
+
+use crate::clock::Clock;
+use crate::config::LogLevel;
+
+/// Client handle that batches non-essential log messages and progress reports.
+///
+/// Messages below `WARNING` are collected and flushed at most once per interval,
+/// identical consecutive messages are merged.
+/// Progress reports are coalesced to the latest report per token.
+/// Messages below the level are dropped, errors are always sent.
+/// Everything else (diagnostics, requests, warnings and errors) goes straight
+/// through to the wrapped [`Client`].
+pub struct ThrottledClient {
+    client: Client,
+    pending: Arc<Mutex<Pending>>,
+    /// Flush interval in milliseconds
+    interval: AtomicU64,
+    /// The [`LogLevel`] of the least severe messages that are sent
+    level: AtomicU8,
+    clock: Arc<dyn Clock>,
+    /// Scheduled flushes happen immediately once cancelled
+    shutdown: CancellationToken,
+}
+
+#[derive(Default)]
+struct Pending {
+    /// Messages with the number of consecutive repetitions
+    messages: Vec<(MessageType, String, usize)>,
+    /// Latest progress report of each token
+    reports: Vec<(ProgressToken, WorkDoneProgressReport)>,
+    /// Whether a flush is already scheduled
+    scheduled: bool,
+}
+
+impl ThrottledClient {
+    pub fn new(
+        client: Client,
+        interval: u64,
+        clock: Arc<dyn Clock>,
+        shutdown: CancellationToken,
+    ) -> Self {
+        Self {
+            client,
+            pending: Default::default(),
+            interval: AtomicU64::new(interval),
+            level: AtomicU8::new(LogLevel::Debug as u8),
+            clock,
+            shutdown,
+        }
+    }
+
+    /// Send the pending messages and reports now.
+    pub async fn flush(&self) {
+        flush(&self.client, &self.pending).await;
+    }
+
+    pub fn set_interval(&self, interval: u64) {
+        self.interval.store(interval, Ordering::Relaxed);
+    }
+
+    pub fn set_level(&self, level: LogLevel) {
+        self.level.store(level as u8, Ordering::Relaxed);
+    }
+
+    /// Whether messages of `typ` are sent, to skip preparing the ones that are not.
+    pub fn logs(&self, typ: MessageType) -> bool {
+        let level = match typ {
+            MessageType::ERROR => LogLevel::Error,
+            MessageType::WARNING => LogLevel::Warning,
+            MessageType::INFO => LogLevel::Info,
+            _ => LogLevel::Debug,
+        };
+        level as u8 <= self.level.load(Ordering::Relaxed)
+    }
+
+    pub async fn log_message<M: Display>(&self, typ: MessageType, message: M) {
+        if !self.logs(typ) {
+            return;
+        }
+        if typ == MessageType::ERROR || typ == MessageType::WARNING {
+            // Keep the order of the messages
+            flush(&self.client, &self.pending).await;
+            self.client.log_message(typ, message).await;
+            return;
+        }
+
+        let message = message.to_string();
+        self.enqueue(|pending| match pending.messages.last_mut() {
+            Some((t, m, count)) if *t == typ && *m == message => *count += 1,
+            _ => pending.messages.push((typ, message, 1)),
+        });
+    }
+
+    pub async fn begin_progress(&self, token: ProgressToken, begin: WorkDoneProgressBegin) {
+        self.progress(token, WorkDoneProgress::Begin(begin)).await;
+    }
+
+    pub fn report_progress(&self, token: ProgressToken, report: WorkDoneProgressReport) {
+        self.enqueue(|pending| {
+            match pending.reports.iter_mut().find(|(t, _)| *t == token) {
+                Some((_, r)) => *r = report,
+                None => pending.reports.push((token, report)),
+            };
+        });
+    }
+
+    pub async fn end_progress(&self, token: ProgressToken, end: WorkDoneProgressEnd) {
+        // Reports must not arrive after the end
+        flush(&self.client, &self.pending).await;
+        self.progress(token, WorkDoneProgress::End(end)).await;
+    }
+
+    async fn progress(&self, token: ProgressToken, value: WorkDoneProgress) {
+        self.client
+            .send_notification::<Progress>(ProgressParams {
+                token,
+                value: ProgressParamsValue::WorkDone(value),
+            })
+            .await;
+    }
+
+    /// Add to the pending traffic and schedule a flush if there is none.
+    fn enqueue(&self, f: impl FnOnce(&mut Pending)) {
+        let schedule = {
+            let mut pending = self.pending.lock().unwrap();
+            f(&mut pending);
+            !mem::replace(&mut pending.scheduled, true)
+        };
+        if schedule {
+            let client = self.client.clone();
+            let pending = self.pending.clone();
+            let interval = Duration::from_millis(self.interval.load(Ordering::Relaxed));
+            let sleep = self.clock.sleep(interval);
+            let shutdown = self.shutdown.clone();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = sleep => {}
+                    _ = shutdown.cancelled() => {}
+                }
+                flush(&client, &pending).await;
+            });
+        }
+    }
+}
+
+impl Deref for ThrottledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+async fn flush(client: &Client, pending: &Mutex<Pending>) {
+    let (messages, reports) = {
+        let mut pending = pending.lock().unwrap();
+        pending.scheduled = false;
+        (
+            mem::take(&mut pending.messages),
+            mem::take(&mut pending.reports),
+        )
+    };
+    for (typ, message, count) in messages {
+        if count > 1 {
+            client.log_message(typ, format!("{message} ×{count}")).await;
+        } else {
+            client.log_message(typ, message).await;
+        }
+    }
+    for (token, report) in reports {
+        client
+            .send_notification::<Progress>(ProgressParams {
+                token,
+                value: ProgressParamsValue::WorkDone(WorkDoneProgress::Report(report)),
+            })
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+    use tower_lsp_server::lsp_types::{InitializeParams, InitializeResult};
+    use tower_lsp_server::{jsonrpc, LanguageServer, LspService};
+
+    use super::*;
+    use crate::clock::TokioClock;
+
+    /// Server only holding the client, without the socket the messages are dropped.
+    struct Service(ThrottledClient);
+
+    impl LanguageServer for Service {
+        async fn initialize(&self, _: InitializeParams) -> jsonrpc::Result<InitializeResult> {
+            Ok(Default::default())
+        }
+
+        async fn shutdown(&self) -> jsonrpc::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn throttled() -> LspService<Service> {
+        let (service, _) = LspService::new(|client| {
+            Service(ThrottledClient::new(
+                client,
+                250,
+                Arc::new(TokioClock),
+                CancellationToken::new(),
+            ))
+        });
+        service
+    }
+
+    fn pending(client: &ThrottledClient) -> (Vec<(MessageType, String, usize)>, bool) {
+        let pending = client.pending.lock().unwrap();
+        (pending.messages.clone(), pending.scheduled)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn coalesces_a_burst() {
+        let service = throttled();
+        let client = &service.inner().0;
+        for i in 0..1000 {
+            client
+                .log_message(MessageType::INFO, "Fetching serde")
+                .await;
+            if i % 100 == 99 {
+                client
+                    .log_message(MessageType::LOG, format!("Batch {i}"))
+                    .await;
+            }
+        }
+        let (messages, scheduled) = pending(client);
+        assert!(scheduled);
+        // 1000 calls are sent as 20 messages
+        assert_eq!(messages.len(), 20);
+        assert_eq!(
+            messages[0],
+            (MessageType::INFO, "Fetching serde".to_string(), 100)
+        );
+        assert_eq!(messages[1], (MessageType::LOG, "Batch 99".to_string(), 1));
+        assert_eq!(messages.iter().map(|(_, _, n)| n).sum::<usize>(), 1010);
+
+        // Nothing is sent before the interval
+        tokio::time::sleep(Duration::from_millis(249)).await;
+        assert_eq!(pending(client).0.len(), 20);
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        assert_eq!(pending(client), (Vec::new(), false));
+
+        // The next message schedules a new flush
+        client
+            .log_message(MessageType::INFO, "Fetching serde")
+            .await;
+        assert!(pending(client).1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn warnings_bypass_the_throttle() {
+        let service = throttled();
+        let client = &service.inner().0;
+        client.log_message(MessageType::INFO, "a").await;
+        client.log_message(MessageType::INFO, "a").await;
+        assert_eq!(pending(client).0.len(), 1);
+        // Sent at once, after the pending messages
+        client.log_message(MessageType::WARNING, "b").await;
+        assert!(pending(client).0.is_empty());
+        client.log_message(MessageType::ERROR, "c").await;
+        assert!(pending(client).0.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn changes_the_interval() {
+        let service = throttled();
+        let client = &service.inner().0;
+        client.set_interval(1000);
+        client.log_message(MessageType::INFO, "a").await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(pending(client).0.len(), 1);
+        tokio::time::sleep(Duration::from_millis(501)).await;
+        assert!(pending(client).0.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drops_messages_below_the_level() {
+        let service = throttled();
+        let client = &service.inner().0;
+        assert!(client.logs(MessageType::LOG));
+        client.set_level(LogLevel::Info);
+        assert!(!client.logs(MessageType::LOG) && client.logs(MessageType::INFO));
+        client.log_message(MessageType::LOG, "a").await;
+        assert_eq!(pending(client), (Vec::new(), false));
+        client.log_message(MessageType::INFO, "b").await;
+        assert_eq!(pending(client).0.len(), 1);
+
+        client.set_level(LogLevel::Error);
+        assert!(!client.logs(MessageType::WARNING) && client.logs(MessageType::ERROR));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flushes_on_shutdown() {
+        let service = throttled();
+        let client = &service.inner().0;
+        client.log_message(MessageType::INFO, "a").await;
+        let start = Instant::now();
+        client.shutdown.cancel();
+        while pending(client).1 {
+            tokio::task::yield_now().await;
+        }
+        // Sent without waiting for the interval
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert!(pending(client).0.is_empty());
+    }
+}
@@ -0,0 +1,457 @@
+//! Formatting of the inline dependency tables.
+
+use std::ops::Range;
+
+use tower_lsp_server::lsp_types::{self, TextEdit};
+
+use crate::config::Config;
+use crate::manifest::{self, sections, table_headers};
+use crate::text::Source;
+
+/// Characters that trigger on-type formatting, the first is the primary trigger.
+pub const TRIGGER_CHARACTERS: [&str; 2] = [",", "\""];
+
+/// Indentation of the elements of wrapped arrays.
+const INDENT: &str = "    ";
+
+/// An inline table entry of a dependency section, like `serde = { version = "1" }`.
+struct Entry {
+    /// Start of the line
+    line: usize,
+    /// Span of the key
+    key: Range<usize>,
+    /// Span of the inline table
+    value: Range<usize>,
+    /// Start of the next line
+    next: usize,
+}
+
+/// Edits normalizing the inline dependency tables that overlap the byte range `range`.
+///
+/// The tables are written as `{ version = "1", features = ["a", "b"] }`.
+/// Entries wider than `inlineTableWidth` become `[dependencies.name]` tables if
+/// `allowTableConversion` is set, their long arrays are wrapped across lines.
+/// Entries with comments and manifests that do not parse are left alone.
+pub fn edits(source: &Source, range: Range<usize>, config: &Config) -> Vec<TextEdit> {
+    let text = source.text.as_str();
+    let (manifest, None) = manifest::parse(text) else {
+        return Vec::new();
+    };
+    let sections = sections(text);
+    let headers = table_headers(text);
+    let dependencies = manifest
+        .dependencies()
+        .map(|(_, name, dep)| (name, dep))
+        .chain(
+            manifest
+                .target_dependencies()
+                .map(|(_, _, name, dep)| (name, dep)),
+        );
+
+    let mut edits = Vec::new();
+    for (name, dep) in dependencies {
+        if dep.span.end < range.start || range.end < dep.span.start {
+            continue;
+        }
+        let Some(entry) = entry(
+            text,
+            manifest::quoted_key(text, name.span()),
+            dep.span.clone(),
+        ) else {
+            continue;
+        };
+        let Some(tokens) = tokens(&text[entry.value.clone()]) else {
+            continue;
+        };
+        let inline = render(&tokens);
+        let width = text[entry.line..entry.key.end].chars().count() + 3 + inline.chars().count();
+
+        let comment_above = text[..entry.line]
+            .trim_end()
+            .rsplit('\n')
+            .next()
+            .is_some_and(|line| line.trim_start().starts_with('#'));
+        if width > config.inline_table_width && config.allow_table_conversion && !comment_above {
+            let section = sections.iter().find(|s| s.contains(&entry.line));
+            let header = section.and_then(|section| {
+                headers
+                    .iter()
+                    .find(|(_, header)| section.contains(&header.start))
+            });
+            let table = header.and_then(|(_, header)| {
+                let header = text[header.clone()].trim_end_matches(']').trim_end();
+                let key = &text[entry.key.clone()];
+                let body = table(&tokens, config.inline_table_width)?;
+                Some(format!("{header}.{key}]\n{body}"))
+            });
+            if let (Some(section), Some(table)) = (section, table) {
+                let before = &text[..section.end];
+                let prefix = if before.ends_with("\n\n") {
+                    ""
+                } else if before.ends_with('\n') {
+                    "\n"
+                } else {
+                    "\n\n"
+                };
+                let suffix = if section.end < text.len() {
+                    "\n\n"
+                } else {
+                    "\n"
+                };
+                edits.extend(edit(source, entry.line..entry.next, ""));
+                edits.extend(edit(
+                    source,
+                    section.end..section.end,
+                    &format!("{prefix}{table}{suffix}"),
+                ));
+                continue;
+            }
+        }
+        edits.extend(edit(
+            source,
+            entry.key.end..entry.value.end,
+            &format!(" = {inline}"),
+        ));
+    }
+    edits
+}
+
+/// The entry if it is an inline table on its own lines, without trailing comment.
+fn entry(text: &str, key: Range<usize>, value: Range<usize>) -> Option<Entry> {
+    if !text.get(value.clone())?.starts_with('{') || text[key.end..value.start].trim() != "=" {
+        return None;
+    }
+    let line = text[..key.start].rfind('\n').map_or(0, |i| i + 1);
+    if !text[line..key.start].trim().is_empty() {
+        return None;
+    }
+    let next = text[value.end..]
+        .find('\n')
+        .map_or(text.len(), |i| value.end + i + 1);
+    if !text[value.end..next].trim().is_empty() {
+        return None;
+    }
+    Some(Entry {
+        line,
+        key,
+        value,
+        next,
+    })
+}
+
+/// Tokens of an inline value: punctuation, strings, and bare words like keys and numbers.
+///
+/// Returns `None` for values with comments or multi-line strings.
+fn tokens(value: &str) -> Option<Vec<&str>> {
+    let mut tokens = Vec::new();
+    let mut chars = value.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => return None,
+            '{' | '}' | '[' | ']' | ',' | '=' => tokens.push(&value[start..start + 1]),
+            '"' | '\'' => {
+                if value[start..].starts_with(&c.to_string().repeat(3)) {
+                    return None;
+                }
+                let mut end = None;
+                while let Some((i, next)) = chars.next() {
+                    if c == '"' && next == '\\' {
+                        chars.next();
+                    } else if next == c {
+                        end = Some(i + 1);
+                        break;
+                    } else if next == '\n' {
+                        return None;
+                    }
+                }
+                tokens.push(&value[start..end?]);
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, next)) = chars.peek() {
+                    if next.is_whitespace() || "{}[],=#\"'".contains(next) {
+                        break;
+                    }
+                    end = i + next.len_utf8();
+                    chars.next();
+                }
+                tokens.push(&value[start..end]);
+            }
+        }
+    }
+    Some(tokens)
+}
+
+/// Write the tokens on one line with single spaces after commas, around `=`, and inside braces.
+///
+/// Trailing commas of arrays are dropped.
+fn render(tokens: &[&str]) -> String {
+    let mut text = String::new();
+    let mut prev = None;
+    for (i, token) in tokens.iter().copied().enumerate() {
+        if token == "," && tokens.get(i + 1) == Some(&"]") {
+            continue;
+        }
+        let space = !matches!(
+            (prev, token),
+            (None, _) | (_, ",") | (Some("["), _) | (_, "]") | (Some("{"), "}")
+        );
+        if space {
+            text.push(' ');
+        }
+        text.push_str(token);
+        prev = Some(token);
+    }
+    text
+}
+
+/// Split the tokens at the commas outside of nested arrays and tables.
+fn split<'a, 'b>(tokens: &'b [&'a str]) -> Vec<&'b [&'a str]> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            "[" | "{" => depth += 1,
+            "]" | "}" => depth = depth.saturating_sub(1),
+            "," if depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[start..]);
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// Body of the `[dependencies.name]` table replacing the inline table `tokens`.
+///
+/// Arrays that do not fit into `width` get a line per element.
+fn table(tokens: &[&str], width: usize) -> Option<String> {
+    let fields = tokens.strip_prefix(&["{"])?.strip_suffix(&["}"])?;
+    let mut lines = Vec::new();
+    for field in split(fields) {
+        let eq = field.iter().position(|t| *t == "=")?;
+        let (key, value) = (render(&field[..eq]), &field[eq + 1..]);
+        let line = format!("{key} = {}", render(value));
+        let elements = value
+            .strip_prefix(&["["])
+            .and_then(|v| v.strip_suffix(&["]"]));
+        match elements {
+            Some(elements) if line.chars().count() > width => {
+                let mut line = format!("{key} = [\n");
+                for element in split(elements) {
+                    line += &format!("{INDENT}{},\n", render(element));
+                }
+                lines.push(line + "]");
+            }
+            _ => lines.push(line),
+        }
+    }
+    Some(lines.join("\n"))
+}
+
+/// Edit replacing `range` with `new_text`, reduced to the part that differs.
+///
+/// `None` if nothing changes.
+fn edit(source: &Source, range: Range<usize>, new_text: &str) -> Option<TextEdit> {
+    let old = &source.text[range.clone()];
+    if old == new_text {
+        return None;
+    }
+    let prefix: usize = old
+        .chars()
+        .zip(new_text.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new_text[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    Some(TextEdit {
+        range: lsp_types::Range {
+            start: source.position(range.start + prefix)?,
+            end: source.position(range.end - suffix)?,
+        },
+        new_text: new_text[prefix..new_text.len() - suffix].to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::text::{Encoding, LineIndex};
+
+    /// Text after formatting the byte range `range`.
+    fn format(text: &str, range: Range<usize>, config: &Config) -> String {
+        let mut edits = edits(&Source::new(text, Encoding::Utf8), range, config);
+        edits.sort_by_key(|e| std::cmp::Reverse(e.range.start));
+        let mut text = text.to_string();
+        for edit in edits {
+            let lines = LineIndex::new(&text, Encoding::Utf8);
+            let span = lines.range_to_span(edit.range).unwrap();
+            text.replace_range(span, &edit.new_text);
+        }
+        text
+    }
+
+    fn format_all(text: &str, config: &Config) -> String {
+        format(text, 0..text.len(), config)
+    }
+
+    fn converting(width: usize) -> Config {
+        Config {
+            inline_table_width: width,
+            allow_table_conversion: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn normalizes_spacing() {
+        let text = "[package]\nname = \"app\"\n\n[dependencies]\n\
+            serde={version=\"1\",features=[ \"derive\" ,\"rc\", ],default-features=false}\n\
+            log = {version = \"0.4\"}\n\
+            [target.'cfg(unix)'.dependencies]\nlibc = {  }\n";
+        assert_eq!(
+            format_all(text, &Config::default()),
+            "[package]\nname = \"app\"\n\n[dependencies]\n\
+            serde = { version = \"1\", features = [\"derive\", \"rc\"], default-features = false }\n\
+            log = { version = \"0.4\" }\n\
+            [target.'cfg(unix)'.dependencies]\nlibc = {}\n"
+        );
+    }
+
+    #[test]
+    fn edits_only_the_difference() {
+        let text = "[dependencies]\nserde = { version = \"1\",features = [] }\n";
+        let edits = edits(
+            &Source::new(text, Encoding::Utf8),
+            0..text.len(),
+            &Config::default(),
+        );
+        let start = lsp_types::Position::new(1, 24);
+        assert_eq!(
+            edits,
+            [TextEdit::new(
+                lsp_types::Range::new(start, start),
+                " ".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn formats_only_the_range() {
+        let text = "[dependencies]\na = {version=\"1\"}\nb = {version=\"1\"}\n";
+        let b = text.find("b =").unwrap();
+        assert_eq!(
+            format(text, b + 6..b + 6, &Config::default()),
+            "[dependencies]\na = {version=\"1\"}\nb = { version = \"1\" }\n"
+        );
+    }
+
+    #[test]
+    fn leaves_comments_and_other_sections_alone() {
+        let text = "[package]\nmetadata = {a=1}\n\n[dependencies]\n\
+            serde = {version=\"1\"} # pinned\n\
+            log = {version='''0.4'''}\n\
+            rand = \"0.8\"\n\n[workspace.metadata]\nx = {a=1}\n";
+        assert_eq!(format_all(text, &Config::default()), text);
+        // Manifests that do not parse
+        let broken = "[dependencies]\nserde = {version=\"1\"}\nlog = {\n";
+        let broken_source = Source::new(broken, Encoding::Utf8);
+        assert!(edits(&broken_source, 0..broken.len(), &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn converts_wide_entries() {
+        let text = "[dependencies]\nserde = {version=\"1\", features=[\"derive\", \"rc\"]}\n\n\
+            [dev-dependencies]\nlog = \"0.4\"\n";
+        let inline =
+            "[dependencies]\nserde = { version = \"1\", features = [\"derive\", \"rc\"] }\n\n\
+            [dev-dependencies]\nlog = \"0.4\"\n";
+        // At the width or without the permission the entry stays inline
+        assert_eq!(format_all(text, &converting(54)), inline);
+        let config = Config {
+            inline_table_width: 40,
+            ..Default::default()
+        };
+        assert_eq!(format_all(text, &config), inline);
+
+        assert_eq!(
+            format_all(text, &converting(53)),
+            "[dependencies]\n\n\
+            [dependencies.serde]\nversion = \"1\"\nfeatures = [\"derive\", \"rc\"]\n\n\
+            [dev-dependencies]\nlog = \"0.4\"\n"
+        );
+        // Long arrays are wrapped
+        assert_eq!(
+            format_all(text, &converting(20)),
+            "[dependencies]\n\n\
+            [dependencies.serde]\nversion = \"1\"\nfeatures = [\n    \"derive\",\n    \"rc\",\n]\n\n\
+            [dev-dependencies]\nlog = \"0.4\"\n"
+        );
+    }
+
+    #[test]
+    fn keeps_entries_with_comments_above_inline() {
+        let text =
+            "[dependencies]\n# Serialization\nserde = {version=\"1\", features=[\"derive\"]}\n";
+        assert_eq!(
+            format_all(text, &converting(20)),
+            "[dependencies]\n# Serialization\nserde = { version = \"1\", features = [\"derive\"] }\n"
+        );
+    }
+
+    /// An inline table entry with arbitrary spacing.
+    fn entry() -> impl Strategy<Value = String> {
+        let space = "[ ]{0,2}";
+        let value = prop_oneof![
+            "\"[a-z0-9.]{1,6}\"".boxed(),
+            Just("true".to_string()).boxed(),
+            proptest::collection::vec("\"[a-z]{1,6}\"", 0..4)
+                .prop_map(|items| format!("[{}]", items.join(" ,")))
+                .boxed(),
+        ];
+        let field = ("[a-z][a-z-]{0,8}", space, space, value)
+            .prop_map(|(key, a, b, value)| format!("{key}{a}={b}{value}"));
+        ("[a-z]{1,8}", proptest::collection::vec(field, 0..4), space).prop_map(
+            |(name, fields, space)| {
+                format!(
+                    "{name} = {{{space}{}{space}}}",
+                    fields.join(&format!(",{space}"))
+                )
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn idempotent(
+            entries in proptest::collection::vec(entry(), 1..5),
+            width in 10usize..120,
+            allow_table_conversion in any::<bool>(),
+        ) {
+            let text = format!("[dependencies]\n{}\n\n[features]\ndefault = []\n", entries.join("\n"));
+            prop_assume!(manifest::parse(&text).1.is_none());
+            let config = Config {
+                inline_table_width: width,
+                allow_table_conversion,
+                ..Default::default()
+            };
+            let formatted = format_all(&text, &config);
+            prop_assert!(manifest::parse(&formatted).1.is_none(), "{formatted}");
+            let source = Source::new(&formatted, Encoding::Utf8);
+            prop_assert_eq!(edits(&source, 0..formatted.len(), &config), vec![]);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Time spent in the phases of an analysis, logged to diagnose slow diagnostics.
+//!
+//! The fetch time of each crate is only collected while debug messages are logged,
+//! the phases are always timed and kept for [`TIMINGS`].
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Command returning the [`Snapshot`] of the last analysis of each open document,
+/// without arguments.
+pub const TIMINGS: &str = "crates-io.timings";
+
+/// Number of the slowest crates named in the summary.
+const SLOWEST: usize = 3;
+
+/// Time spent in the phases of the analysis that published first after a change.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub parse: Duration,
+    /// Looking up the cached versions
+    pub cache: Duration,
+    /// Waiting for the fetches, at most until the deadline
+    pub fetch: Duration,
+    /// Fetch time of each crate that finished, `None` if not collected
+    pub crates: Option<Vec<(String, Duration)>>,
+    /// Uncached crates that were still fetched at the deadline
+    pub pending: usize,
+    /// Classifying the versions of the dependencies
+    pub classify: Duration,
+    /// The other checks and rendering their messages
+    pub render: Duration,
+    pub publish: Duration,
+}
+
+impl Timings {
+    /// Timings that collect the fetch time of each crate if `crates` is set.
+    pub fn new(crates: bool) -> Self {
+        Self {
+            crates: crates.then(Vec::new),
+            ..Default::default()
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases().iter().map(|(_, d)| *d).sum()
+    }
+
+    fn phases(&self) -> [(&'static str, Duration); 6] {
+        [
+            ("parsed", self.parse),
+            ("cache", self.cache),
+            ("fetch", self.fetch),
+            ("classify", self.classify),
+            ("render", self.render),
+            ("publish", self.publish),
+        ]
+    }
+
+    /// The slowest fetched crates, the slowest first.
+    pub fn slowest(&self) -> Vec<&(String, Duration)> {
+        let mut crates = self.crates.iter().flatten().collect::<Vec<_>>();
+        crates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        crates.truncate(SLOWEST);
+        crates
+    }
+
+    /// One line like `parsed 4ms, cache 1ms, fetch 2310ms [openssl 2100ms, ring 180ms, …], …`.
+    pub fn summary(&self) -> String {
+        let phases = self.phases().map(|(label, duration)| {
+            let mut phase = format!("{label} {}ms", duration.as_millis());
+            if label != "fetch" {
+                return phase;
+            }
+            let mut crates = self
+                .slowest()
+                .iter()
+                .map(|(name, d)| format!("{name} {}ms", d.as_millis()))
+                .collect::<Vec<_>>();
+            if self.crates.as_ref().is_some_and(|c| c.len() > SLOWEST) {
+                crates.push("…".into());
+            }
+            if self.pending > 0 {
+                crates.push(format!("{} pending", self.pending));
+            }
+            if !crates.is_empty() {
+                phase += &format!(" [{}]", crates.join(", "));
+            }
+            phase
+        });
+        phases.join(", ")
+    }
+
+    /// Likely cause of a slow analysis, from the phase that took the longest.
+    pub fn hint(&self) -> String {
+        let phases = self.phases();
+        let Some((label, _)) = phases.iter().max_by_key(|(_, d)| *d) else {
+            return String::new();
+        };
+        match *label {
+            "fetch" => "most of the time went into fetching, likely because of a cold cache, \
+                a slow registry, or rate limiting"
+                .into(),
+            "parsed" => "most of the time went into parsing the manifest".into(),
+            label => format!("most of the time went into the {label} phase"),
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let ms = |d: Duration| d.as_millis() as u64;
+        Snapshot {
+            parse: ms(self.parse),
+            cache: ms(self.cache),
+            fetch: ms(self.fetch),
+            classify: ms(self.classify),
+            render: ms(self.render),
+            publish: ms(self.publish),
+            total: ms(self.total()),
+            slowest: self.crates.as_ref().map(|_| {
+                self.slowest()
+                    .into_iter()
+                    .map(|(n, d)| (n.clone(), ms(*d)))
+                    .collect()
+            }),
+            pending: self.pending,
+        }
+    }
+}
+
+/// The [`Timings`] in milliseconds, as returned by [`TIMINGS`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub parse: u64,
+    pub cache: u64,
+    pub fetch: u64,
+    pub classify: u64,
+    pub render: u64,
+    pub publish: u64,
+    pub total: u64,
+    /// Slowest fetched crates with their milliseconds, `null` unless debug messages are logged
+    pub slowest: Option<Vec<(String, u64)>>,
+    pub pending: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn summarizes_the_phases() {
+        let mut timings = Timings::new(true);
+        timings.parse = ms(4);
+        timings.cache = ms(1);
+        timings.fetch = ms(2310);
+        timings.classify = ms(3);
+        timings.publish = ms(2);
+        for (name, d) in [
+            ("ring", 180),
+            ("libc", 5),
+            ("openssl", 2100),
+            ("serde", 180),
+        ] {
+            timings.crates.as_mut().unwrap().push((name.into(), ms(d)));
+        }
+        assert_eq!(
+            timings.summary(),
+            "parsed 4ms, cache 1ms, fetch 2310ms [openssl 2100ms, ring 180ms, serde 180ms, …], \
+             classify 3ms, render 0ms, publish 2ms"
+        );
+        assert_eq!(timings.total(), ms(2320));
+        assert!(timings.hint().contains("cold cache"));
+        assert_eq!(
+            timings.snapshot().slowest,
+            Some(vec![
+                ("openssl".into(), 2100),
+                ("ring".into(), 180),
+                ("serde".into(), 180)
+            ])
+        );
+
+        // Without the crates
+        let mut timings = Timings::new(false);
+        timings.render = ms(7);
+        timings.pending = 2;
+        assert_eq!(
+            timings.summary(),
+            "parsed 0ms, cache 0ms, fetch 0ms [2 pending], classify 0ms, render 7ms, publish 0ms"
+        );
+        assert_eq!(
+            timings.hint(),
+            "most of the time went into the render phase"
+        );
+        assert_eq!(timings.snapshot().slowest, None);
+    }
+}
@@ -0,0 +1,492 @@
+//! Recording of the LSP messages of a session and their replay.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::error::{Result, ServerError};
+
+/// Longest pause between replayed messages, longer idle times of the session are skipped.
+const MAX_GAP: Duration = Duration::from_secs(5);
+/// Time without server messages after which a replay without `exit` ends.
+const SETTLE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// From the client to the server
+    In,
+    /// From the server to the client
+    Out,
+}
+
+/// A line of the trace file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Milliseconds since the start of the session
+    pub time: u64,
+    pub direction: Direction,
+    pub message: Value,
+}
+
+/// Writes the messages passing the [`Tap`]s as JSON lines.
+pub struct Recorder {
+    file: Mutex<BufWriter<File>>,
+    start: Instant,
+    /// Characters of document texts to keep, all if `None`
+    text_limit: Option<usize>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, text_limit: Option<usize>) -> Result<Arc<Self>> {
+        let file = File::create(path).map_err(|err| ServerError::io(path, err))?;
+        Ok(Arc::new(Self {
+            file: Mutex::new(BufWriter::new(file)),
+            start: Instant::now(),
+            text_limit,
+        }))
+    }
+
+    fn record(&self, direction: Direction, mut message: Value) {
+        redact(&mut message, self.text_limit);
+        let entry = Entry {
+            time: self.start.elapsed().as_millis() as u64,
+            direction,
+            message,
+        };
+        let Ok(line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        // Flushed per line, the trace is complete up to a crash
+        let mut file = self.file.lock().unwrap();
+        let _ = writeln!(file, "{line}").and_then(|_| file.flush());
+    }
+}
+
+/// Hide the tokens of the configuration and shorten document texts.
+fn redact(value: &mut Value, text_limit: Option<usize>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                // Not the progress tokens of the protocol
+                let secret = key.to_ascii_lowercase().ends_with("token")
+                    && !matches!(key.as_str(), "workDoneToken" | "partialResultToken");
+                match value {
+                    Value::String(_) if secret => *value = Value::from("<redacted>"),
+                    Value::String(text) if key == "text" => {
+                        if let Some((end, _)) =
+                            text_limit.and_then(|limit| text.char_indices().nth(limit))
+                        {
+                            text.truncate(end);
+                            text.push_str("<redacted>");
+                        }
+                    }
+                    _ => redact(value, text_limit),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                redact(value, text_limit);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Splits a byte stream into the messages of the `Content-Length` framing.
+#[derive(Debug, Default)]
+pub struct Framer {
+    buffer: Vec<u8>,
+}
+
+impl Framer {
+    /// Append the bytes and take the complete messages, malformed ones are skipped.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Value> {
+        self.buffer.extend_from_slice(bytes);
+        let mut messages = Vec::new();
+        while let Some(end) = self.buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            let header = String::from_utf8_lossy(&self.buffer[..end]);
+            let length = header.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().ok())?
+            });
+            let Some(length) = length else {
+                self.buffer.drain(..end + 4);
+                continue;
+            };
+            if self.buffer.len() < end + 4 + length {
+                break;
+            }
+            let body = self.buffer.drain(..end + 4 + length).skip(end + 4);
+            if let Ok(message) = serde_json::from_slice(&body.collect::<Vec<_>>()) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+}
+
+/// A message with its `Content-Length` header.
+pub fn frame(message: &Value) -> Vec<u8> {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{body}", body.len()).into_bytes()
+}
+
+/// Stream wrapper recording the messages read or written.
+pub struct Tap<T> {
+    inner: T,
+    direction: Direction,
+    framer: Framer,
+    recorder: Arc<Recorder>,
+}
+
+impl<T> Tap<T> {
+    pub fn new(inner: T, direction: Direction, recorder: Arc<Recorder>) -> Self {
+        Self {
+            inner,
+            direction,
+            framer: Framer::default(),
+            recorder,
+        }
+    }
+
+    fn observe(&mut self, bytes: &[u8]) {
+        for message in self.framer.push(bytes) {
+            self.recorder.record(self.direction, message);
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Tap<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            self.observe(&buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Tap<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            self.observe(&buf[..written]);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+fn stopped<T>(_: mpsc::error::SendError<T>) -> ServerError {
+    ServerError::Internal("The server stopped reading".into())
+}
+
+/// Read a trace file, lines that are not entries are skipped.
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let file = File::open(path).map_err(|err| ServerError::io(path, err))?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(entry) = serde_json::from_str(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Play the client side of a recorded session against a server connected to `reader` and `writer`.
+///
+/// The requests and notifications of the client are sent with their recorded pauses,
+/// requests of the server are answered with the recorded responses to the same method.
+/// Returns the parameters of the published diagnostics.
+pub async fn replay<R, W>(entries: &[Entry], mut reader: R, mut writer: W) -> Result<Vec<Value>>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    // Recorded responses of the client by the method of the server request
+    let mut methods = HashMap::new();
+    for entry in entries.iter().filter(|e| e.direction == Direction::Out) {
+        if let (Some(id), Some(method)) = (entry.message.get("id"), entry.message.get("method")) {
+            methods.insert(
+                id.to_string(),
+                method.as_str().unwrap_or_default().to_string(),
+            );
+        }
+    }
+    let mut responses = HashMap::<String, VecDeque<Value>>::new();
+    let mut messages = Vec::new();
+    for entry in entries.iter().filter(|e| e.direction == Direction::In) {
+        let message = &entry.message;
+        if message.get("method").is_some() {
+            messages.push(entry);
+        } else if let Some(method) = message
+            .get("id")
+            .and_then(|id| methods.get(&id.to_string()))
+        {
+            let result = message.get("result").cloned().unwrap_or(Value::Null);
+            responses
+                .entry(method.clone())
+                .or_default()
+                .push_back(result);
+        }
+    }
+
+    // Writes in their own task, the server is read while it blocks
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Value>();
+    let writing = tokio::spawn(async move {
+        while let Some(message) = receiver.recv().await {
+            writer.write_all(&frame(&message)).await?;
+            writer.flush().await?;
+        }
+        io::Result::Ok(())
+    });
+
+    let mut next = messages.iter();
+    let mut pending = next.next();
+    let mut due = Instant::now();
+    let mut last = pending.map_or(0, |e| e.time);
+    let mut framer = Framer::default();
+    let mut buffer = vec![0; 8192];
+    let mut diagnostics = Vec::new();
+    let mut idle = Instant::now() + SETTLE;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(due), if pending.is_some() => {
+                if let Some(entry) = pending {
+                    sender.send(entry.message.clone()).map_err(stopped)?;
+                }
+                pending = next.next();
+                if let Some(entry) = pending {
+                    due += Duration::from_millis(entry.time.saturating_sub(last)).min(MAX_GAP);
+                    last = entry.time;
+                }
+                idle = Instant::now() + SETTLE;
+            }
+            read = reader.read(&mut buffer) => {
+                let read = read?;
+                if read == 0 {
+                    break; // The server stopped
+                }
+                for message in framer.push(&buffer[..read]) {
+                    match (message.get("method").and_then(Value::as_str), message.get("id")) {
+                        (Some(method), Some(id)) => {
+                            let result = responses
+                                .get_mut(method)
+                                .and_then(VecDeque::pop_front)
+                                .unwrap_or(Value::Null);
+                            let response = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                            sender.send(response).map_err(stopped)?;
+                        }
+                        (Some("textDocument/publishDiagnostics"), None) => {
+                            diagnostics.push(message.get("params").cloned().unwrap_or_default());
+                        }
+                        _ => {}
+                    }
+                }
+                idle = Instant::now() + SETTLE;
+            }
+            _ = tokio::time::sleep_until(idle), if pending.is_none() => break,
+        }
+    }
+    // Fails only if the server already stopped
+    drop(sender);
+    let _ = writing.await;
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::process;
+
+    use super::*;
+
+    fn entry(time: u64, direction: Direction, message: Value) -> Entry {
+        Entry {
+            time,
+            direction,
+            message,
+        }
+    }
+
+    /// Answers `initialize`, asks for the configuration when a document is opened,
+    /// and publishes the answer with the length of the document.
+    async fn fake_server(mut reader: impl AsyncRead + Unpin, mut writer: impl AsyncWrite + Unpin) {
+        let mut framer = Framer::default();
+        let mut buffer = vec![0; 1024];
+        let mut opened = Value::Null;
+        loop {
+            let read = reader.read(&mut buffer).await.unwrap();
+            if read == 0 {
+                return;
+            }
+            for message in framer.push(&buffer[..read]) {
+                let reply = match message.get("method").and_then(Value::as_str) {
+                    Some("initialize") => {
+                        json!({ "jsonrpc": "2.0", "id": message["id"], "result": {} })
+                    }
+                    Some("textDocument/didOpen") => {
+                        opened = message["params"]["textDocument"].clone();
+                        json!({ "jsonrpc": "2.0", "id": 7, "method": "workspace/configuration" })
+                    }
+                    Some("exit") => return,
+                    Some(_) => continue,
+                    None => {
+                        let text = opened["text"].as_str().unwrap();
+                        let diagnostic = format!("{} {}", message["result"], text.len());
+                        json!({
+                            "jsonrpc": "2.0",
+                            "method": "textDocument/publishDiagnostics",
+                            "params": { "uri": opened["uri"], "diagnostics": [diagnostic] },
+                        })
+                    }
+                };
+                writer.write_all(&frame(&reply)).await.unwrap();
+                writer.flush().await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replays_a_recorded_session() {
+        let script = [
+            entry(
+                0,
+                Direction::In,
+                json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            ),
+            entry(
+                10,
+                Direction::In,
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/didOpen",
+                    "params": { "textDocument": { "uri": "file:///app/Cargo.toml", "text": "[dependencies]\n" } },
+                }),
+            ),
+            entry(
+                10,
+                Direction::Out,
+                json!({ "jsonrpc": "2.0", "id": 7, "method": "workspace/configuration" }),
+            ),
+            entry(
+                20,
+                Direction::In,
+                json!({ "jsonrpc": "2.0", "id": 7, "result": [{ "locale": "de" }] }),
+            ),
+            entry(
+                1000,
+                Direction::In,
+                json!({ "jsonrpc": "2.0", "method": "exit" }),
+            ),
+        ];
+        let path = temp_dir().join(format!("crates-io-lsp-trace-{}.jsonl", process::id()));
+
+        // Record the scripted client talking to the server
+        let recorder = Recorder::create(&path, None).unwrap();
+        let (client, server) = tokio::io::duplex(4096);
+        let (server_read, server_write) = tokio::io::split(server);
+        let (client_read, client_write) = tokio::io::split(client);
+        let (recorded, ()) = tokio::join!(
+            replay(&script, client_read, client_write),
+            fake_server(
+                Tap::new(server_read, Direction::In, recorder.clone()),
+                Tap::new(server_write, Direction::Out, recorder),
+            ),
+        );
+        let recorded = recorded.unwrap();
+        assert_eq!(
+            recorded,
+            [
+                json!({ "uri": "file:///app/Cargo.toml", "diagnostics": ["[{\"locale\":\"de\"}] 15"] })
+            ]
+        );
+
+        let trace = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let directions = trace.iter().map(|e| e.direction).collect::<Vec<_>>();
+        use Direction::{In, Out};
+        assert_eq!(directions, [In, Out, In, Out, In, Out, In]);
+
+        // A fresh server reproduces the diagnostics from the recording
+        let (client, server) = tokio::io::duplex(4096);
+        let (server_read, server_write) = tokio::io::split(server);
+        let (client_read, client_write) = tokio::io::split(client);
+        let (replayed, ()) = tokio::join!(
+            replay(&trace, client_read, client_write),
+            fake_server(server_read, server_write),
+        );
+        assert_eq!(replayed.unwrap(), recorded);
+    }
+
+    #[test]
+    fn redacts_tokens_and_texts() {
+        let mut message = json!({
+            "params": {
+                "workDoneToken": "progress",
+                "initializationOptions": { "token": "secret", "githubToken": "secret" },
+                "textDocument": { "text": "[dependencies]\nserde = \"1\"\n" },
+                "contentChanges": [{ "text": "ab" }],
+            },
+        });
+        redact(&mut message, Some(4));
+        assert_eq!(
+            message,
+            json!({
+                "params": {
+                    "workDoneToken": "progress",
+                    "initializationOptions": { "token": "<redacted>", "githubToken": "<redacted>" },
+                    "textDocument": { "text": "[dep<redacted>" },
+                    "contentChanges": [{ "text": "ab" }],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn frames_split_messages() {
+        let mut bytes = frame(&json!({ "id": 1 }));
+        bytes.extend(b"Content-Type: x\r\n\r\n");
+        bytes.extend(b"content-length: 7\r\n\r\n{\"id\":}");
+        bytes.extend(frame(&json!({ "id": "ü" })));
+        let mut framer = Framer::default();
+        let mut messages = Vec::new();
+        for chunk in bytes.chunks(5) {
+            messages.extend(framer.push(chunk));
+        }
+        // The message without length and the malformed one are skipped
+        assert_eq!(messages, [json!({ "id": 1 }), json!({ "id": "ü" })]);
+        assert!(framer.buffer.is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+//! Unstable cargo features, enabled with `cargo-features = ["..."]` at the top of a manifest,
+//! and the manifest syntax they make legal.
+//!
+//! The checks of nightly-only syntax ask the [`Unstable`] features of the manifest
+//! instead of looking for the declaration themselves.
+
+use serde::Deserialize;
+use toml::Spanned;
+use tower_lsp_server::lsp_types::{Diagnostic, NumberOrString, TextEdit};
+
+use crate::artifact::ARTIFACT_DEPENDENCY;
+use crate::config::Config;
+use crate::keys;
+use crate::manifest;
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// Diagnostic code of unknown `cargo-features`, the data contains the edit to a close one.
+pub const UNKNOWN_CARGO_FEATURE: &str = "unknown-cargo-feature";
+/// Diagnostic code of `cargo-features` that are stable by now.
+pub const STABILIZED_CARGO_FEATURE: &str = "stabilized-cargo-feature";
+
+/// A feature cargo accepts in `cargo-features`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CargoFeature {
+    pub name: &'static str,
+    /// Rust version that stabilized it, cargo still accepts it there
+    pub stabilized: Option<&'static str>,
+    /// Keys of detailed dependencies that require it
+    pub dependency_keys: &'static [&'static str],
+    /// Diagnostic code of the check noting its syntax without the feature,
+    /// `unknown-key` for the `dependency_keys` if there is none
+    pub check: &'static str,
+}
+
+impl CargoFeature {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            stabilized: None,
+            dependency_keys: &[],
+            check: keys::UNKNOWN_KEY,
+        }
+    }
+
+    const fn stabilized(self, version: &'static str) -> Self {
+        Self {
+            stabilized: Some(version),
+            ..self
+        }
+    }
+
+    const fn dependency_keys(self, keys: &'static [&'static str]) -> Self {
+        Self {
+            dependency_keys: keys,
+            ..self
+        }
+    }
+
+    const fn checked_by(self, code: &'static str) -> Self {
+        Self {
+            check: code,
+            ..self
+        }
+    }
+}
+
+/// The features of `cargo-features` that cargo knows, also the stabilized ones.
+pub const CARGO_FEATURES: &[CargoFeature] = &[
+    CargoFeature::new("bindeps")
+        .dependency_keys(&["artifact", "lib", "target"])
+        .checked_by(ARTIFACT_DEPENDENCY),
+    CargoFeature::new("public-dependency").dependency_keys(&["public"]),
+    CargoFeature::new("codegen-backend"),
+    CargoFeature::new("different-binary-name"),
+    CargoFeature::new("metabuild"),
+    CargoFeature::new("open-namespaces"),
+    CargoFeature::new("per-package-target"),
+    CargoFeature::new("profile-rustflags"),
+    CargoFeature::new("trim-paths"),
+    CargoFeature::new("test-dummy-unstable"),
+    CargoFeature::new("alternative-registries").stabilized("1.34"),
+    CargoFeature::new("default-run").stabilized("1.37"),
+    CargoFeature::new("edition").stabilized("1.31"),
+    CargoFeature::new("edition2021").stabilized("1.56"),
+    CargoFeature::new("edition2024").stabilized("1.85"),
+    CargoFeature::new("named-profiles").stabilized("1.57"),
+    CargoFeature::new("profile-overrides").stabilized("1.41"),
+    CargoFeature::new("rename-dependency").stabilized("1.31"),
+    CargoFeature::new("resolver").stabilized("1.51"),
+    CargoFeature::new("rust-version").stabilized("1.56"),
+    CargoFeature::new("strip").stabilized("1.59"),
+    CargoFeature::new("workspace-inheritance").stabilized("1.64"),
+];
+
+pub fn cargo_feature(name: &str) -> Option<&'static CargoFeature> {
+    CARGO_FEATURES.iter().find(|f| f.name == name)
+}
+
+/// The unstable features a manifest enables.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Unstable(Vec<String>);
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct Declaration {
+    cargo_features: Vec<Spanned<String>>,
+}
+
+impl Unstable {
+    pub fn new(declared: &[Spanned<String>]) -> Self {
+        Self(declared.iter().map(|f| f.get_ref().clone()).collect())
+    }
+
+    /// The features declared by `text`, see [`declared`].
+    pub fn of(text: &str) -> Self {
+        Self::new(&declared(text))
+    }
+
+    pub fn enabled(&self, feature: &str) -> bool {
+        self.0.iter().any(|f| f == feature)
+    }
+
+    /// Whether a declared feature makes the dependency key `name` legal.
+    pub fn legalizes(&self, name: &str) -> bool {
+        CARGO_FEATURES
+            .iter()
+            .any(|f| f.dependency_keys.contains(&name) && self.enabled(f.name))
+    }
+
+    /// The undeclared feature the dependency key `name` requires, `None` if it is legal.
+    pub fn missing(&self, name: &str) -> Option<&'static CargoFeature> {
+        CARGO_FEATURES
+            .iter()
+            .find(|f| f.dependency_keys.contains(&name))
+            .filter(|f| !self.enabled(f.name))
+    }
+}
+
+/// The features of `cargo-features` with their spans.
+///
+/// The key is at the top level, so only the text before the first table is parsed,
+/// which keeps it readable while the tables below are edited.
+pub fn declared(text: &str) -> Vec<Spanned<String>> {
+    let end = manifest::headers(text)
+        .first()
+        .map_or(text.len(), |(_, span, _)| span.start);
+    toml::from_str::<Declaration>(&text[..end])
+        .map(|d| d.cargo_features)
+        .unwrap_or_default()
+}
+
+/// Flag unknown and stabilized features of `cargo-features`.
+///
+/// Unknown ones one edit away from a known one have the edit to it as data.
+pub fn lint(declared: &[Spanned<String>], source: &Source, config: &Config) -> Vec<Diagnostic> {
+    let text = source.text.as_str();
+    let names = CARGO_FEATURES.iter().map(|f| f.name).collect::<Vec<_>>();
+    let mut diagnostics = Vec::new();
+    for feature in declared {
+        let name = feature.get_ref();
+        let quoted = format!("`{name}`");
+        let (code, severity, message, fixed) = match cargo_feature(name) {
+            Some(CargoFeature {
+                stabilized: Some(version),
+                ..
+            }) => (
+                STABILIZED_CARGO_FEATURE,
+                config.severity.stabilized_cargo_feature,
+                Message::new(Key::StabilizedCargoFeature)
+                    .arg(quoted)
+                    .arg(version),
+                None,
+            ),
+            Some(_) => continue,
+            None => {
+                let fixed = keys::suggest(name, &names);
+                let message = match fixed {
+                    Some(fixed) => Message::new(Key::UnknownCargoFeatureSuggestion)
+                        .arg(quoted)
+                        .arg(format!("`{fixed}`")),
+                    None => Message::new(Key::UnknownCargoFeature).arg(quoted),
+                };
+                let severity = config.severity.unknown_cargo_feature;
+                (UNKNOWN_CARGO_FEATURE, severity, message, fixed)
+            }
+        };
+        let Some(range) = source.range(feature.span()) else {
+            continue;
+        };
+        // Keep the quotes of the string
+        let data = fixed
+            .zip(text.get(feature.span().start..feature.span().start + 1))
+            .map(|(fixed, quote)| TextEdit {
+                range,
+                new_text: format!("{quote}{fixed}{quote}"),
+            });
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(severity.into()),
+            code: Some(NumberOrString::String(code.into())),
+            source: Some(config.source(code)),
+            message: message.render(config.language),
+            data: data.and_then(|edit| serde_json::to_value(edit).ok()),
+            ..Default::default()
+        });
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::Encoding;
+
+    fn check(text: &str) -> Vec<(String, String, Option<String>)> {
+        lint(
+            &declared(text),
+            &Source::new(text, Encoding::Utf8),
+            &Config::default(),
+        )
+        .into_iter()
+        .map(|d| {
+            let NumberOrString::String(code) = d.code.unwrap() else {
+                panic!("numeric code");
+            };
+            let edit = d
+                .data
+                .map(|data| serde_json::from_value::<TextEdit>(data).unwrap().new_text);
+            (code, d.message, edit)
+        })
+        .collect()
+    }
+
+    #[test]
+    fn reads_the_declaration() {
+        let text = "cargo-features = [\"bindeps\", 'public-dependency']\n\n\
+            [package]\nname = \"app\"\n\n[dependencies\n";
+        // Read although the tables below are broken
+        let declared = declared(text);
+        let spans = declared.iter().map(|f| &text[f.span()]).collect::<Vec<_>>();
+        assert_eq!(spans, ["\"bindeps\"", "'public-dependency'"]);
+
+        let unstable = Unstable::new(&declared);
+        assert!(unstable.enabled("bindeps") && !unstable.enabled("trim-paths"));
+        assert_eq!(unstable.missing("public"), None);
+        assert!(unstable.legalizes("artifact") && !unstable.legalizes("version"));
+        assert_eq!(unstable.missing("version"), None);
+        let missing = Unstable::default().missing("lib").unwrap();
+        assert_eq!(
+            (missing.name, missing.check),
+            ("bindeps", ARTIFACT_DEPENDENCY)
+        );
+        assert_eq!(
+            Unstable::default().missing("public").unwrap().check,
+            keys::UNKNOWN_KEY
+        );
+
+        // Only at the top level
+        assert_eq!(
+            Unstable::of("[package]\ncargo-features = [\"bindeps\"]\n"),
+            Unstable::default()
+        );
+        assert_eq!(
+            Unstable::of("cargo-features = \"bindeps\"\n"),
+            Unstable::default()
+        );
+    }
+
+    #[test]
+    fn knows_the_features() {
+        for feature in CARGO_FEATURES {
+            assert_eq!(cargo_feature(feature.name), Some(feature));
+            // Stabilized features legalize nothing anymore
+            assert!(feature.stabilized.is_none() || feature.dependency_keys.is_empty());
+            for key in feature.dependency_keys {
+                let key = keys::dependency_key(key).unwrap();
+                assert!(!key.stable, "{}", key.name);
+            }
+        }
+    }
+
+    #[test]
+    fn flags_unknown_and_stabilized_features() {
+        let text = "cargo-features = [\"bindeps\", \"bindep\", \"edition2024\", \"fast\"]\n";
+        assert_eq!(
+            check(text),
+            [
+                (
+                    UNKNOWN_CARGO_FEATURE.into(),
+                    "Unknown cargo feature `bindep`, did you mean `bindeps`?".into(),
+                    Some("\"bindeps\"".into())
+                ),
+                (
+                    STABILIZED_CARGO_FEATURE.into(),
+                    "`edition2024` is stable since Rust 1.85, it can be removed from `cargo-features`"
+                        .into(),
+                    None
+                ),
+                (
+                    UNKNOWN_CARGO_FEATURE.into(),
+                    "Unknown cargo feature `fast`, cargo rejects the manifest".into(),
+                    None
+                ),
+            ]
+        );
+        assert_eq!(check("cargo-features = [\"trim-paths\"]\n"), []);
+    }
+}
@@ -0,0 +1,1143 @@
+use std::sync::Arc;
+
+use semver::{BuildMetadata, Comparator, Op, Prerelease, Version, VersionReq};
+use serde::Serialize;
+
+use crate::config::RequirementStyle;
+use crate::messages::{Key, Language, Message};
+
+/// Diagnostic code of requirements pinned to a pre-release, the data contains the edit to the stable release.
+pub const PRERELEASE_REQUIREMENT: &str = "prerelease-requirement";
+
+/// Diagnostic code of major-only `0` requirements, the data contains the edit to the newest minor.
+pub const ZERO_MAJOR_REQUIREMENT: &str = "zero-major-requirement";
+
+/// Diagnostic code of requirements on an older major than the newest release,
+/// the data is the [`MajorUpdate`].
+pub const MAJOR_AVAILABLE: &str = "major-available";
+
+/// Number of versions listed in the diagnostics of a dependency.
+pub const LISTED_VERSIONS: usize = 10;
+
+/// A published version, parsed once when the index of its crate is fetched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    /// As written in the index
+    pub raw: String,
+    pub version: Version,
+    /// Yanked releases are never selected for new requirements
+    pub yanked: bool,
+    /// Not strict semver, `version` is normalized by [`lenient`] and only `raw` is displayed
+    pub lenient: bool,
+}
+
+/// Releases of a crate, oldest first, shared by the cache and the analyses.
+pub type Releases = Arc<[Release]>;
+
+#[cfg(test)]
+thread_local! {
+    /// Versions parsed by [`Release::parse`] on this thread.
+    pub static PARSED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+impl Release {
+    /// Parse a semver version, or else leniently, `None` if `raw` is not a version at all.
+    pub fn parse(raw: &str, yanked: bool) -> Option<Self> {
+        #[cfg(test)]
+        PARSED.with(|parsed| parsed.set(parsed.get() + 1));
+        let (version, lenient) = match Version::parse(raw) {
+            Ok(version) => (version, false),
+            Err(_) => (self::lenient(raw)?, true),
+        };
+        Some(Self {
+            raw: raw.to_string(),
+            version,
+            yanked,
+            lenient,
+        })
+    }
+}
+
+/// Normalize a version of the old index that is not strict semver.
+///
+/// Crates published before cargo validated versions deviate like this:
+/// - Leading zeros of numbers are stripped, `1.0.01` is `1.0.1`,
+///   also of numeric pre-release identifiers, `0.1.0-pre.01` is `0.1.0-pre.1`.
+/// - Numbers after the patch are dropped, `0.1.0.2` is `0.1.0`.
+/// - Build metadata is dropped, wherever it is, as it does not order versions.
+///   Everything after the first `+` is metadata, `1.0.0+a+b` is `1.0.0`.
+/// - Empty pre-releases are dropped, `1.0.0-` is `1.0.0`.
+///
+/// Anything else, like missing numbers or other characters, is not a version.
+fn lenient(raw: &str) -> Option<Version> {
+    let (rest, _build) = raw.split_once('+').unwrap_or((raw, ""));
+    let (core, pre) = rest.split_once('-').unwrap_or((rest, ""));
+    let number = |part: &str| -> Option<u64> {
+        (!part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| part.parse().ok())
+            .flatten()
+    };
+    let numbers = core.split('.').map(number).collect::<Option<Vec<_>>>()?;
+    let [major, minor, patch, ..] = numbers[..] else {
+        return None;
+    };
+    let pre = if pre.is_empty() {
+        Prerelease::EMPTY
+    } else {
+        let identifiers = pre
+            .split('.')
+            .map(|id| match number(id) {
+                Some(n) => n.to_string(),
+                None => id.to_string(),
+            })
+            .collect::<Vec<_>>();
+        Prerelease::new(&identifiers.join(".")).ok()?
+    };
+    Some(Version {
+        major,
+        minor,
+        patch,
+        pre,
+        build: BuildMetadata::EMPTY,
+    })
+}
+
+/// The version as published, which differs from its display if it was parsed leniently.
+pub fn verbatim(versions: &[Release], version: &Version) -> String {
+    versions
+        .iter()
+        .rev()
+        .find(|r| r.lenient && r.version == *version)
+        .map_or_else(|| version.to_string(), |r| r.raw.clone())
+}
+
+/// Versions that may be selected, without the yanked ones.
+pub fn available(versions: &[Release]) -> impl Iterator<Item = &Version> + Clone {
+    versions.iter().filter(|r| !r.yanked).map(|r| &r.version)
+}
+
+/// The newest [`LISTED_VERSIONS`] not yanked versions, newest first,
+/// followed by notes on the omitted older and yanked versions.
+pub fn listing(versions: &[Release], language: Language) -> Vec<String> {
+    let live = versions.iter().filter(|r| !r.yanked).count();
+    let yanked = versions.len() - live;
+    let mut lines = versions
+        .iter()
+        .rev()
+        .filter(|r| !r.yanked)
+        .take(LISTED_VERSIONS)
+        .map(|r| r.raw.clone())
+        .collect::<Vec<_>>();
+    if live > LISTED_VERSIONS {
+        let older = Message::new(Key::OlderVersionsOmitted).arg(live - LISTED_VERSIONS);
+        lines.push(older.render(language));
+    }
+    if yanked > 0 {
+        lines.push(
+            Message::new(Key::YankedVersionsOmitted)
+                .arg(yanked)
+                .render(language),
+        );
+    }
+    lines
+}
+
+/// How a dependency requirement relates to the published versions of a crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Classification {
+    /// The requirement is `*` and matches every version.
+    Any,
+    /// The requirement already names the newest version.
+    Latest,
+    /// A newer version still satisfies the requirement (`cargo update` is enough).
+    OutdatedCompatible {
+        compatible: Version,
+        latest: Version,
+    },
+    /// The newest version falls outside the requirement (the manifest has to change).
+    OutdatedIncompatible {
+        compatible: Version,
+        latest: Version,
+    },
+    /// No published version matches the requirement.
+    Unknown,
+}
+
+impl Classification {
+    /// Diagnostic code used to identify the classification.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Classification::Any => "any",
+            Classification::Latest => "latest",
+            Classification::OutdatedCompatible { .. } => "outdated-compatible",
+            Classification::OutdatedIncompatible { .. } => "outdated-incompatible",
+            Classification::Unknown => "unknown",
+        }
+    }
+
+    /// Message describing the classification, with the versions as published in `versions`.
+    pub fn message(&self, versions: &[Release]) -> Message {
+        match self {
+            Classification::Any => Message::new(Key::Any),
+            Classification::Latest => Message::new(Key::Latest),
+            Classification::OutdatedCompatible { compatible, .. } => {
+                Message::new(Key::OutdatedCompatible).arg(verbatim(versions, compatible))
+            }
+            Classification::OutdatedIncompatible { compatible, latest } => {
+                // Only the accessible message names the number of newer releases
+                let newer = available(versions)
+                    .filter(|v| *v > compatible && *v <= latest)
+                    .count();
+                Message::new(Key::OutdatedIncompatible)
+                    .arg(verbatim(versions, latest))
+                    .arg(verbatim(versions, compatible))
+                    .arg(newer)
+            }
+            Classification::Unknown => Message::new(Key::Unknown),
+        }
+    }
+}
+
+/// Classify the requirement `req` against the published `versions`.
+///
+/// Newer pre-releases are only suggested with `include_prerelease`.
+pub fn classify(req: &str, versions: &[Release], include_prerelease: bool) -> Classification {
+    if req.trim() == "*" {
+        return Classification::Any;
+    }
+    let Ok(req) = VersionReq::parse(req) else {
+        return Classification::Unknown;
+    };
+    let versions = available(versions);
+
+    let Some(compatible) = versions.clone().filter(|v| req.matches(v)).max() else {
+        return Classification::Unknown;
+    };
+    let latest = versions
+        .filter(|v| include_prerelease || v.pre.is_empty())
+        .max()
+        .unwrap_or(compatible);
+
+    if compatible >= latest {
+        if names_version(&req, compatible) {
+            Classification::Latest
+        } else {
+            Classification::OutdatedCompatible {
+                compatible: compatible.clone(),
+                latest: compatible.clone(),
+            }
+        }
+    } else {
+        Classification::OutdatedIncompatible {
+            compatible: compatible.clone(),
+            latest: latest.clone(),
+        }
+    }
+}
+
+/// A release of a newer major than the newest version matching a requirement.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MajorUpdate {
+    /// Newest release, of the new major
+    pub latest: String,
+    /// Line of the newest match, like `0.9` or `1.x`
+    pub line: String,
+    /// Newer release of the line than the requirement names, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compatible: Option<String>,
+}
+
+impl MajorUpdate {
+    /// Message naming the new major, and the compatible update if there is one.
+    pub fn message(&self) -> Message {
+        match &self.compatible {
+            Some(compatible) => Message::new(Key::MajorAvailableCompatible)
+                .arg(&self.latest)
+                .arg(&self.line)
+                .arg(compatible),
+            None => Message::new(Key::MajorAvailable)
+                .arg(&self.latest)
+                .arg(&self.line),
+        }
+    }
+}
+
+/// The new major of a requirement classified as `classification`,
+/// like `1.0.3` for `0.9.1` whose newest match is `0.9.4`.
+///
+/// Only the major counts, `0.9` is not a new major of `0.8`.
+/// Pre-releases count only if the classification included them.
+pub fn major_available(req: &str, classification: &Classification) -> Option<MajorUpdate> {
+    let Classification::OutdatedIncompatible { compatible, latest } = classification else {
+        return None;
+    };
+    if latest.major <= compatible.major {
+        return None;
+    }
+    let line = if compatible.major > 0 {
+        format!("{}.x", compatible.major)
+    } else {
+        series(compatible)
+    };
+    let names = VersionReq::parse(req).is_ok_and(|req| names_version(&req, compatible));
+    Some(MajorUpdate {
+        latest: latest.to_string(),
+        line,
+        compatible: (!names).then(|| compatible.to_string()),
+    })
+}
+
+/// Newest stable version, or the newest pre-release if there is no stable one.
+pub fn newest(versions: &[Release]) -> Option<&Version> {
+    let versions = available(versions);
+    versions
+        .clone()
+        .filter(|v| v.pre.is_empty())
+        .max()
+        .or_else(|| versions.max())
+}
+
+/// The pre-release a requirement is pinned to and the newest stable release of its series,
+/// like `2.0.0-rc.1` and `2.0.3` for `>=2.0.0-rc.1`.
+///
+/// `None` if the requirement names no pre-release or there is no stable release of it yet.
+pub fn stable_release(req: &str, versions: &[Release]) -> Option<(Version, Version)> {
+    let req = VersionReq::parse(req).ok()?;
+    let comparator = req.comparators.iter().find(|c| !c.pre.is_empty())?;
+    let base = Version::new(
+        comparator.major,
+        comparator.minor.unwrap_or(0),
+        comparator.patch.unwrap_or(0),
+    );
+    let stable = available(versions)
+        .filter(|v| v.pre.is_empty() && **v >= base && series(v) == series(&base))
+        .max()?
+        .clone();
+    let pinned = Version {
+        pre: comparator.pre.clone(),
+        ..base
+    };
+    Some((pinned, stable))
+}
+
+/// Major of a requirement naming only the major version, like `1`, `^1`, `~1`, or `1.*`.
+pub fn major_only(req: &VersionReq) -> Option<u64> {
+    match req.comparators.as_slice() {
+        [c] if matches!(c.op, Op::Exact | Op::Tilde | Op::Caret | Op::Wildcard)
+            && c.minor.is_none() =>
+        {
+            Some(c.major)
+        }
+        _ => None,
+    }
+}
+
+/// Newest stable release matched by a major-only `0` requirement, like `0.8.5` for `0`.
+///
+/// Such a requirement spans all `0.x` minors, which are mutually incompatible.
+/// `None` for other requirements or if no stable `0.x` release is published.
+pub fn zero_major(req: &str, versions: &[Release]) -> Option<Version> {
+    let req = VersionReq::parse(req).ok()?;
+    if major_only(&req) != Some(0) {
+        return None;
+    }
+    available(versions)
+        .filter(|v| v.pre.is_empty() && req.matches(v))
+        .max()
+        .cloned()
+}
+
+/// Exact requirement (`=1.2.3`) of the newest of the `versions` matching `req`.
+///
+/// `None` if nothing matches or `req` is already this pin.
+pub fn pin(req: &str, versions: &[Release]) -> Option<String> {
+    let parsed = VersionReq::parse(req).ok()?;
+    let matched = available(versions).filter(|v| parsed.matches(v)).max()?;
+    let pin = format!(
+        "={}",
+        Version {
+            build: BuildMetadata::EMPTY,
+            ..matched.clone()
+        }
+    );
+    (VersionReq::parse(&pin).ok()? != parsed).then_some(pin)
+}
+
+/// Caret requirement in the precision of `style` replacing an exact or tilde requirement,
+/// or a caret requirement that is more precise than `style` (`1.2.3` becomes `1.2` with `minor`).
+///
+/// `None` for other requirements.
+pub fn relax(req: &str, style: RequirementStyle) -> Option<String> {
+    let parsed = VersionReq::parse(req).ok()?;
+    let [comparator] = parsed.comparators.as_slice() else {
+        return None;
+    };
+    if !matches!(comparator.op, Op::Exact | Op::Tilde | Op::Caret) {
+        return None;
+    }
+    let version = Version {
+        major: comparator.major,
+        minor: comparator.minor.unwrap_or(0),
+        patch: comparator.patch.unwrap_or(0),
+        pre: comparator.pre.clone(),
+        build: BuildMetadata::EMPTY,
+    };
+    // Without the operator the precision is preserved, but not the operator
+    let current = req.trim().trim_start_matches(['=', '~', '^']).trim_start();
+    let relaxed = requirement(&version, style, current);
+    let precision = |c: &Comparator| 1 + c.minor.is_some() as usize + c.patch.is_some() as usize;
+    let new = VersionReq::parse(&relaxed).ok()?;
+    let relaxes = comparator.op != Op::Caret
+        || new
+            .comparators
+            .first()
+            .is_some_and(|c| precision(c) < precision(comparator));
+    relaxes.then_some(relaxed)
+}
+
+/// English reading of a requirement, like "any 1.x version at least 1.2.0" for `1.2`.
+pub fn describe(req: &VersionReq) -> String {
+    if req.comparators.is_empty() {
+        return "any version".into();
+    }
+    let clauses = req.comparators.iter().map(describe_comparator);
+    clauses.collect::<Vec<_>>().join(", and ")
+}
+
+/// English reading of a single clause of a requirement.
+///
+/// Carets below `1.0.0` only allow updates of the first non-zero component (`0.2.3` is `0.2.x`).
+pub fn describe_comparator(comparator: &Comparator) -> String {
+    let major = comparator.major;
+    let pre = if comparator.pre.is_empty() {
+        String::new()
+    } else {
+        format!("-{}", comparator.pre)
+    };
+    let full = |minor: u64, patch: u64| format!("{major}.{minor}.{patch}{pre}");
+    match (comparator.op, comparator.minor, comparator.patch) {
+        (Op::Exact | Op::Tilde | Op::Caret | Op::Wildcard, None, _) => {
+            format!("any {major}.x version")
+        }
+        (Op::Exact | Op::Tilde | Op::Wildcard, Some(minor), None) => {
+            format!("any {major}.{minor}.x version")
+        }
+        (Op::Exact, Some(minor), Some(patch)) => format!("exactly {}", full(minor, patch)),
+        (Op::Tilde, Some(minor), Some(patch)) => {
+            format!(
+                "any {major}.{minor}.x version at least {}",
+                full(minor, patch)
+            )
+        }
+        (Op::Caret, Some(minor), None) => match (major, minor) {
+            (0, _) => format!("any 0.{minor}.x version"),
+            _ => format!("any {major}.x version at least {major}.{minor}.0"),
+        },
+        (Op::Caret, Some(minor), Some(patch)) => match (major, minor) {
+            (0, 0) => format!("exactly {}", full(0, patch)),
+            (0, _) => format!("any 0.{minor}.x version at least {}", full(minor, patch)),
+            _ => format!("any {major}.x version at least {}", full(minor, patch)),
+        },
+        (Op::Greater, None, _) => format!("at least {}.0.0", major.saturating_add(1)),
+        (Op::Greater, Some(minor), None) => {
+            format!("at least {major}.{}.0", minor.saturating_add(1))
+        }
+        (Op::Greater, Some(minor), Some(patch)) => format!("newer than {}", full(minor, patch)),
+        (Op::GreaterEq, minor, patch) => {
+            format!("at least {}", full(minor.unwrap_or(0), patch.unwrap_or(0)))
+        }
+        (Op::Less, minor, patch) => {
+            format!(
+                "older than {}",
+                full(minor.unwrap_or(0), patch.unwrap_or(0))
+            )
+        }
+        (Op::LessEq, None, _) => format!("older than {}.0.0", major.saturating_add(1)),
+        (Op::LessEq, Some(minor), None) => {
+            format!("older than {major}.{}.0", minor.saturating_add(1))
+        }
+        (Op::LessEq, Some(minor), Some(patch)) => format!("at most {}", full(minor, patch)),
+        _ => format!("`{comparator}`"),
+    }
+}
+
+/// Whether all components written in the requirement agree with `version`.
+///
+/// `1.0` names `1.0.215`, whereas `1.0.188` only matches it.
+/// `0` never names a `0.x` version, as it spans incompatible minors.
+fn names_version(req: &VersionReq, version: &Version) -> bool {
+    req.comparators.iter().all(|c| match c.op {
+        Op::Exact | Op::Tilde | Op::Caret | Op::Wildcard => {
+            c.major == version.major
+                && (c.major > 0 || c.minor.is_some())
+                && c.minor.is_none_or(|m| m == version.minor)
+                && c.patch.is_none_or(|p| p == version.patch)
+        }
+        _ => true,
+    })
+}
+
+/// Render `version` as a requirement replacing `current`, like `cargo add` does.
+///
+/// Components significant for caret compatibility are never dropped (`0.12.3` is at least `0.12`),
+/// pre-releases are never abbreviated and build metadata is stripped.
+/// The operator of a single comparator (`~`, `=`, `^`) is kept.
+pub fn requirement(version: &Version, style: RequirementStyle, current: &str) -> String {
+    let current = current.trim();
+    let comparator = VersionReq::parse(current)
+        .ok()
+        .filter(|r| r.comparators.len() == 1)
+        .map(|mut r| r.comparators.remove(0));
+
+    let components = match style {
+        RequirementStyle::Full => 3,
+        RequirementStyle::Minor => 2,
+        RequirementStyle::Major => 1,
+        RequirementStyle::Preserve => match &comparator {
+            Some(c) if c.patch.is_some() => 3,
+            Some(c) if c.minor.is_some() => 2,
+            Some(_) => 1,
+            None => 3,
+        },
+    };
+    let significant = if version.major > 0 {
+        1
+    } else if version.minor > 0 {
+        2
+    } else {
+        3
+    };
+    let components = if version.pre.is_empty() {
+        components.max(significant)
+    } else {
+        3
+    };
+
+    let version = Version {
+        build: BuildMetadata::EMPTY,
+        ..version.clone()
+    };
+    let rendered = match components {
+        1 => version.major.to_string(),
+        2 => format!("{}.{}", version.major, version.minor),
+        _ => version.to_string(),
+    };
+    let operator = current
+        .find(|c: char| c.is_ascii_digit())
+        .map(|i| current[..i].trim())
+        .filter(|op| ["^", "~", "="].contains(op));
+    match (comparator, operator) {
+        (Some(_), Some(op)) => format!("{op}{rendered}"),
+        _ => rendered,
+    }
+}
+
+/// Releases between the oldest version matching a requirement and an update target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jump {
+    /// Number of semver incompatible series crossed (`0.20` → `0.21` counts as one)
+    pub majors: usize,
+    /// Stable releases strictly between the current and the target version, oldest first
+    pub skipped: Vec<Version>,
+}
+
+impl Jump {
+    /// Short summary like `1 major, 14 releases skipped`, empty if nothing is skipped.
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if self.majors > 0 {
+            parts.push(plural(self.majors, "major"));
+        }
+        if !self.skipped.is_empty() {
+            parts.push(format!("{} skipped", plural(self.skipped.len(), "release")));
+        }
+        parts.join(", ")
+    }
+
+    /// Skipped releases grouped by their compatible series (`3`, `0.20`).
+    pub fn groups(&self) -> Vec<(String, Vec<&Version>)> {
+        let mut groups: Vec<(String, Vec<&Version>)> = Vec::new();
+        for version in &self.skipped {
+            let series = series(version);
+            match groups.last_mut() {
+                Some((last, versions)) if *last == series => versions.push(version),
+                _ => groups.push((series, vec![version])),
+            }
+        }
+        groups
+    }
+}
+
+/// Compute the releases skipped by updating `req` to `target`.
+///
+/// Pre-releases are never counted as skipped.
+pub fn jump(req: &str, target: &Version, versions: &[Release]) -> Option<Jump> {
+    let req = VersionReq::parse(req).ok()?;
+    let mut versions = available(versions)
+        .filter(|v| v.pre.is_empty())
+        .cloned()
+        .collect::<Vec<_>>();
+    versions.sort();
+    let current = versions.iter().find(|v| req.matches(v))?.clone();
+
+    let skipped = versions
+        .into_iter()
+        .filter(|v| *v > current && v < target)
+        .collect::<Vec<_>>();
+    let mut crossed = skipped
+        .iter()
+        .chain([target])
+        .map(series)
+        .filter(|s| *s != series(&current))
+        .collect::<Vec<_>>();
+    crossed.dedup();
+    Some(Jump {
+        majors: crossed.len(),
+        skipped,
+    })
+}
+
+/// Newest stable release in the compatible series of `version`, like the newest `2.x` for `2.3.0`.
+pub fn newest_in_series<'a>(versions: &'a [Release], version: &Version) -> Option<&'a Version> {
+    let line = series(version);
+    available(versions)
+        .filter(|v| v.pre.is_empty() && series(v) == line)
+        .max()
+}
+
+/// The semver compatible series of a version, like `cargo` treats caret requirements.
+pub fn series(version: &Version) -> String {
+    if version.major > 0 {
+        version.major.to_string()
+    } else if version.minor > 0 {
+        format!("0.{}", version.minor)
+    } else {
+        format!("0.0.{}", version.patch)
+    }
+}
+
+fn plural(n: usize, word: &str) -> String {
+    if n == 1 {
+        format!("{n} {word}")
+    } else {
+        format!("{n} {word}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::Locale;
+
+    fn releases(versions: &[&str]) -> Vec<Release> {
+        versions
+            .iter()
+            .filter_map(|v| Release::parse(v, false))
+            .collect()
+    }
+
+    fn v(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    /// Versions of old index entries that are not strict semver, oldest first.
+    const ODD_VERSIONS: [(&str, &str); 10] = [
+        ("0.0.01", "0.0.1"),
+        ("0.1.0-pre.01", "0.1.0-pre.1"),
+        ("0.1.0.2", "0.1.0"),
+        ("0.1.01", "0.1.1"),
+        ("0.2.0-", "0.2.0"),
+        ("0.02.1+build.01", "0.2.1"),
+        ("0.3.0+2014-11-20+nightly", "0.3.0"),
+        ("1.0.0-rc.02", "1.0.0-rc.2"),
+        ("1.0.0.1+git", "1.0.0"),
+        ("1.00.010", "1.0.10"),
+    ];
+
+    #[test]
+    fn normalizes_odd_versions() {
+        for (raw, normalized) in ODD_VERSIONS {
+            let release = Release::parse(raw, false).unwrap();
+            assert!(release.lenient, "{raw}");
+            assert_eq!(release.raw, raw);
+            assert_eq!(release.version, v(normalized), "{raw}");
+        }
+        // Strict versions keep their build metadata
+        let strict = Release::parse("1.0.0+build.5", false).unwrap();
+        assert!(!strict.lenient);
+        assert_eq!(strict.version.build.as_str(), "build.5");
+        for garbage in [
+            "",
+            "1",
+            "1.0",
+            "1..0",
+            "a.b.c",
+            "1.0.x",
+            "v1.0.0",
+            "1.0.0-a b",
+            "1.0.0-a..b",
+        ] {
+            assert_eq!(Release::parse(garbage, false), None, "{garbage}");
+        }
+    }
+
+    #[test]
+    fn orders_odd_versions() {
+        let mut versions = releases(&ODD_VERSIONS.map(|(raw, _)| raw));
+        versions.reverse();
+        versions.sort_by(|a, b| a.version.cmp(&b.version));
+        let sorted = versions.iter().map(|r| r.raw.as_str()).collect::<Vec<_>>();
+        assert_eq!(sorted, ODD_VERSIONS.map(|(raw, _)| raw));
+
+        // A newer odd version is not ignored
+        let versions = releases(&["0.9.0", "1.0.0", "1.0.01"]);
+        assert_eq!(
+            classify("1.0.0", &versions, false),
+            Classification::OutdatedCompatible {
+                compatible: v("1.0.1"),
+                latest: v("1.0.1")
+            }
+        );
+    }
+
+    #[test]
+    fn displays_odd_versions_verbatim() {
+        let versions = releases(&["0.9.0", "1.0.01.7"]);
+        assert_eq!(verbatim(&versions, &v("1.0.1")), "1.0.01.7");
+        assert_eq!(verbatim(&versions, &v("0.9.0")), "0.9.0");
+        let classification = classify("0.9", &versions, false);
+        assert_eq!(
+            classification.message(&versions).render(Locale::En),
+            "Outdated Version, 1.0.01.7 requires a manifest change\nNewest compatible: 0.9.0"
+        );
+        let lines = listing(&versions, Locale::En.into());
+        assert_eq!(lines, ["1.0.01.7", "0.9.0"]);
+    }
+
+    #[test]
+    fn classify_compatible_update() {
+        let versions = releases(&["1.0.100", "1.0.188", "1.0.215"]);
+        assert_eq!(
+            classify("1.0.188", &versions, false),
+            Classification::OutdatedCompatible {
+                compatible: v("1.0.215"),
+                latest: v("1.0.215"),
+            }
+        );
+        assert_eq!(classify("1.0", &versions, false), Classification::Latest);
+        assert_eq!(
+            classify("1.0.215", &versions, false),
+            Classification::Latest
+        );
+    }
+
+    #[test]
+    fn classify_pre_1_0_minor_is_breaking() {
+        let versions = releases(&["0.11.0", "0.11.3", "0.12.0"]);
+        let incompatible = Classification::OutdatedIncompatible {
+            compatible: v("0.11.3"),
+            latest: v("0.12.0"),
+        };
+        assert_eq!(classify("0.11", &versions, false), incompatible);
+        assert_eq!(classify("0.11.0", &versions, false), incompatible);
+        assert_eq!(classify("^0.11.3", &versions, false), incompatible);
+        assert_eq!(classify("0.12", &versions, false), Classification::Latest);
+        assert_eq!(incompatible.code(), "outdated-incompatible");
+    }
+
+    #[test]
+    fn classify_pre_0_1_patch_is_breaking() {
+        let versions = releases(&["0.0.3", "0.0.4"]);
+        assert_eq!(
+            classify("0.0.3", &versions, false),
+            Classification::OutdatedIncompatible {
+                compatible: v("0.0.3"),
+                latest: v("0.0.4"),
+            }
+        );
+        assert_eq!(classify("0.0.4", &versions, false), Classification::Latest);
+    }
+
+    #[test]
+    fn classify_older_major() {
+        let versions = releases(&["1.2.0", "1.3.0", "2.0.0"]);
+        let incompatible = Classification::OutdatedIncompatible {
+            compatible: v("1.3.0"),
+            latest: v("2.0.0"),
+        };
+        assert_eq!(classify("1.2.0", &versions, false), incompatible);
+        assert_eq!(classify("1", &versions, false), incompatible);
+        assert_eq!(incompatible.code(), "outdated-incompatible");
+    }
+
+    #[test]
+    fn detects_new_majors() {
+        let major = |req: &str, versions: &[&str], include_prerelease: bool| {
+            major_available(req, &classify(req, &releases(versions), include_prerelease))
+        };
+        // 0.x → 1.0, with a compatible update within 0.9
+        let zero = ["0.9.1", "0.9.4", "1.0.0", "1.0.3"];
+        let update = major("0.9.1", &zero, false).unwrap();
+        assert_eq!(
+            update,
+            MajorUpdate {
+                latest: "1.0.3".into(),
+                line: "0.9".into(),
+                compatible: Some("0.9.4".into()),
+            }
+        );
+        assert_eq!(
+            update.message().render(Locale::En),
+            "New major 1.0.3 is available, you're on the 0.9 line (compatible update to 0.9.4)"
+        );
+        assert_eq!(major("0.9", &zero, false).unwrap().compatible, None);
+
+        // 1.x → 2.0
+        let one = ["1.2.0", "1.3.0", "2.0.0"];
+        let update = major("1", &one, false).unwrap();
+        assert_eq!(
+            update.message().render(Locale::En),
+            "New major 2.0.0 is available, you're on the 1.x line"
+        );
+        assert_eq!(
+            major("1.2", &one, false).unwrap().compatible.unwrap(),
+            "1.3.0"
+        );
+        assert_eq!(major("2", &one, false), None);
+
+        // A new minor of 0.x is outdated, but no new major
+        assert_eq!(major("0.8", &["0.8.0", "0.9.0"], false), None);
+
+        // The new major only has pre-releases
+        let pre = ["1.4.0", "2.0.0-rc.1"];
+        assert_eq!(major("1.4", &pre, false), None);
+        assert_eq!(major("1.4", &pre, true).unwrap().latest, "2.0.0-rc.1");
+    }
+
+    #[test]
+    fn classify_prereleases() {
+        // Only newer than a stable release if there is none
+        let versions = releases(&["1.0.0", "1.1.0-beta.1"]);
+        assert_eq!(classify("1.0", &versions, false), Classification::Latest);
+        let versions = releases(&["0.9.0", "1.0.0-rc.1"]);
+        assert_eq!(classify("0.9", &versions, false), Classification::Latest);
+    }
+
+    #[test]
+    fn prerelease_ordering() {
+        for (lower, higher) in [
+            ("1.0.0-alpha", "1.0.0-alpha.1"),
+            ("1.0.0-alpha.1", "1.0.0-alpha.beta"),
+            ("1.0.0-alpha.beta", "1.0.0-beta"),
+            ("1.0.0-beta", "1.0.0-beta.2"),
+            // Numeric identifiers compare as numbers, not text
+            ("1.0.0-beta.2", "1.0.0-beta.11"),
+            ("1.0.0-rc.9", "1.0.0-rc.10"),
+            // Numeric identifiers are lower than alphanumeric ones
+            ("1.0.0-rc.1", "1.0.0-rc.a"),
+            ("1.0.0-beta.11", "1.0.0-rc.1"),
+            ("1.0.0-rc.1", "1.0.0"),
+            ("1.0.0", "1.0.1-alpha"),
+        ] {
+            assert!(v(lower) < v(higher), "{lower} < {higher}");
+            let versions = releases(&[lower, higher]);
+            let latest = match classify(&format!("={lower}"), &versions, true) {
+                Classification::OutdatedCompatible { latest, .. }
+                | Classification::OutdatedIncompatible { latest, .. } => Some(latest),
+                _ => None,
+            };
+            assert_eq!(latest, Some(v(higher)), "{lower} -> {higher}");
+        }
+    }
+
+    #[test]
+    fn suggests_prereleases_if_included() {
+        let versions = releases(&["1.0.0", "2.0.0-rc.1"]);
+        assert_eq!(classify("1", &versions, false), Classification::Latest);
+        assert_eq!(
+            classify("1", &versions, true),
+            Classification::OutdatedIncompatible {
+                compatible: v("1.0.0"),
+                latest: v("2.0.0-rc.1"),
+            }
+        );
+        // Even if no stable release matches the requirement
+        let versions = releases(&["2.0.0-rc.1", "2.0.0-rc.2"]);
+        assert_eq!(
+            classify("=2.0.0-rc.1", &versions, false),
+            Classification::Latest
+        );
+        assert_eq!(
+            classify("=2.0.0-rc.1", &versions, true),
+            Classification::OutdatedIncompatible {
+                compatible: v("2.0.0-rc.1"),
+                latest: v("2.0.0-rc.2"),
+            }
+        );
+    }
+
+    #[test]
+    fn stable_releases_of_prerelease_requirements() {
+        let versions = releases(&[
+            "1.9.0",
+            "2.0.0-rc.1",
+            "2.0.0",
+            "2.0.3",
+            "3.0.0",
+            "3.1.0-beta.1",
+        ]);
+        for (req, expected) in [
+            ("2.0.0-rc.1", Some(("2.0.0-rc.1", "2.0.3"))),
+            (">=2.0.0-rc.1", Some(("2.0.0-rc.1", "2.0.3"))),
+            ("=2.0.0-rc.1", Some(("2.0.0-rc.1", "2.0.3"))),
+            ("^2.0.0-alpha", Some(("2.0.0-alpha", "2.0.3"))),
+            // No stable release of the series yet
+            ("3.1.0-beta.1", None),
+            ("4.0.0-rc.1", None),
+            // No pre-release
+            ("2.0", None),
+            ("*", None),
+            ("invalid", None),
+        ] {
+            let expected = expected.map(|(pre, stable)| (v(pre), v(stable)));
+            assert_eq!(stable_release(req, &versions), expected, "{req}");
+        }
+        let versions = releases(&["0.3.0-alpha.2", "0.3.0", "0.3.5", "0.4.0"]);
+        assert_eq!(
+            stable_release("0.3.0-alpha.2", &versions),
+            Some((v("0.3.0-alpha.2"), v("0.3.5")))
+        );
+    }
+
+    #[test]
+    fn classify_any_and_unknown() {
+        let versions = releases(&["1.0.0"]);
+        assert_eq!(classify("*", &versions, false), Classification::Any);
+        assert_eq!(classify("2.0", &versions, false), Classification::Unknown);
+        assert_eq!(
+            classify("not a version", &versions, false),
+            Classification::Unknown
+        );
+        assert_eq!(classify("1.0", &[], false), Classification::Unknown);
+    }
+
+    #[test]
+    fn yanked_releases_are_never_selected() {
+        let mut versions = releases(&["1.0.0", "1.0.1", "1.1.0"]);
+        versions[2].yanked = true;
+        assert_eq!(classify("1.0.1", &versions, false), Classification::Latest);
+        assert_eq!(pin("1", &versions).as_deref(), Some("=1.0.1"));
+        assert_eq!(jump("1.0.0", &v("1.0.1"), &versions).unwrap().skipped, []);
+        assert_eq!(Release::parse("1.0", false), None);
+        assert_eq!(versions[1].raw, "1.0.1");
+    }
+
+    #[test]
+    fn zero_major_requirements() {
+        let versions = releases(&["0.7.3", "0.8.0", "0.8.5", "0.9.0-rc.1"]);
+        // `0` is current only within the newest minor, which it does not name
+        let newest = Classification::OutdatedCompatible {
+            compatible: v("0.8.5"),
+            latest: v("0.8.5"),
+        };
+        assert_eq!(classify("0", &versions, false), newest);
+        assert_eq!(classify("0.8", &versions, false), Classification::Latest);
+        assert_eq!(classify("0.8.5", &versions, false), Classification::Latest);
+
+        for req in ["0", "^0", "~0", "0.*"] {
+            assert_eq!(zero_major(req, &versions), Some(v("0.8.5")), "{req}");
+        }
+        for req in ["0.8", "0.8.5", "1", ">=0"] {
+            assert_eq!(zero_major(req, &versions), None, "{req}");
+        }
+        assert_eq!(zero_major("0", &releases(&["0.1.0-alpha"])), None);
+        // The fix pins the minor in any style
+        assert_eq!(
+            requirement(&v("0.8.5"), RequirementStyle::Major, "0"),
+            "0.8"
+        );
+        assert_eq!(
+            requirement(&v("0.8.5"), RequirementStyle::Preserve, "0"),
+            "0.8"
+        );
+        assert_eq!(
+            requirement(&v("0.8.5"), RequirementStyle::Full, "0"),
+            "0.8.5"
+        );
+
+        // Major-only requirements from 1 on stay as they are
+        let stable = releases(&["1.0.0", "1.4.2"]);
+        assert_eq!(classify("1", &stable, false), Classification::Latest);
+        assert_eq!(zero_major("1", &stable), None);
+        let major = |req: &str| major_only(&VersionReq::parse(req).unwrap());
+        assert_eq!(major("1"), Some(1));
+        assert_eq!(major("0"), Some(0));
+        assert_eq!(major("0.8"), None);
+        assert_eq!(major(">=1, <3"), None);
+    }
+
+    #[test]
+    fn requirement_styles() {
+        use RequirementStyle::{Full, Major, Minor, Preserve};
+        let cases = [
+            // Precision of the style
+            ("1.0.215", Full, "1.0.188", "1.0.215"),
+            ("1.0.215", Minor, "1.0.188", "1.0"),
+            ("1.0.215", Major, "1.0.188", "1"),
+            // Precision of the current requirement
+            ("1.0.215", Preserve, "1.0.188", "1.0.215"),
+            ("1.0.215", Preserve, "1.0", "1.0"),
+            ("1.0.215", Preserve, "1", "1"),
+            ("1.0.215", Preserve, "", "1.0.215"),
+            ("1.0.215", Preserve, ">=1, <2", "1.0.215"),
+            // The minor of 0.x and the patch of 0.0.x are never dropped
+            ("0.12.3", Major, "0", "0.12"),
+            ("0.12.3", Minor, "0.11", "0.12"),
+            ("0.12.3", Full, "0.11", "0.12.3"),
+            ("0.12.3", Preserve, "0.11.0", "0.12.3"),
+            ("0.0.5", Major, "0.0.4", "0.0.5"),
+            ("0.0.5", Minor, "0.0.4", "0.0.5"),
+            // Pre-releases are never abbreviated, build metadata is stripped
+            ("2.0.0-rc.1", Major, "1", "2.0.0-rc.1"),
+            ("2.0.0-rc.1", Preserve, "1.0", "2.0.0-rc.1"),
+            ("1.2.3+build.5", Full, "1.0.0", "1.2.3"),
+            ("1.2.3+build.5", Minor, "1.0.0", "1.2"),
+            // The operator of a single comparator is kept
+            ("1.2.3", Preserve, "~1.2.0", "~1.2.3"),
+            ("1.2.3", Preserve, "=1.0.0", "=1.2.3"),
+            ("1.2.3", Preserve, "^1.0", "^1.2"),
+            ("1.2.3", Preserve, " ^1.0 ", "^1.2"),
+            ("1.2.3", Preserve, ">=1.0", "1.2"),
+            ("1.2.3", Minor, "~1", "~1.2"),
+        ];
+        for (version, style, current, expected) in cases {
+            assert_eq!(
+                requirement(&v(version), style, current),
+                expected,
+                "{version} {style:?} replacing {current:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn jumps_within_a_major() {
+        let versions = releases(&["1.0.0", "1.1.0", "1.2.0-rc.1", "1.2.0", "1.3.0"]);
+        let jump = jump("1.0", &v("1.3.0"), &versions).unwrap();
+        assert_eq!(jump.majors, 0);
+        assert_eq!(
+            jump.skipped,
+            [v("1.1.0"), v("1.2.0")],
+            "without pre-releases"
+        );
+        assert_eq!(jump.summary(), "2 releases skipped");
+
+        let next = jump_summary("1.2", "1.3.0", &versions);
+        assert_eq!(next, "", "nothing is skipped");
+    }
+
+    #[test]
+    fn jumps_across_majors() {
+        let versions = releases(&["2.3.3", "2.3.4", "3.0.0", "3.11.0", "4.0.0-alpha.1"]);
+        let major = jump("2.3.3", &v("3.11.0"), &versions).unwrap();
+        assert_eq!(major.summary(), "1 major, 2 releases skipped");
+        let groups = major.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0], ("2".to_string(), vec![&v("2.3.4")]));
+        assert_eq!(groups[1], ("3".to_string(), vec![&v("3.0.0")]));
+
+        // Each pre-1.0 minor is its own series
+        let versions = releases(&["0.20.1", "0.21.0", "0.21.1", "0.22.0", "0.27.0"]);
+        assert_eq!(
+            jump_summary("0.20.1", "0.27.0", &versions),
+            "3 majors, 3 releases skipped"
+        );
+        assert_eq!(jump("=9.0.0", &v("0.27.0"), &versions), None);
+    }
+
+    fn jump_summary(req: &str, target: &str, versions: &[Release]) -> String {
+        jump(req, &v(target), versions).unwrap().summary()
+    }
+
+    #[test]
+    fn relax_requirements() {
+        use RequirementStyle::{Full, Major, Minor, Preserve};
+        let cases = [
+            ("=1.2.3", Preserve, Some("1.2.3")),
+            ("~1.2", Preserve, Some("1.2")),
+            ("~1.2.3", Minor, Some("1.2")),
+            ("=0.3.1", Major, Some("0.3")),
+            ("1.2.3", Minor, Some("1.2")),
+            ("1.2.3", Major, Some("1")),
+            // Already as relaxed as the style
+            ("1.2", Minor, None),
+            ("1.2.3", Full, None),
+            ("1.2.3", Preserve, None),
+            // Not relaxed
+            (">=1.0", Major, None),
+            (">=1, <2", Major, None),
+            ("*", Major, None),
+        ];
+        for (req, style, expected) in cases {
+            assert_eq!(relax(req, style).as_deref(), expected, "{req} {style:?}");
+        }
+    }
+
+    #[test]
+    fn pin_the_newest_match() {
+        let versions = releases(&["1.0.0", "1.0.5+build.1", "2.0.0"]);
+        assert_eq!(pin("1.0", &versions).as_deref(), Some("=1.0.5"));
+        assert_eq!(pin("=1.0.5", &versions), None);
+        assert_eq!(pin("3", &versions), None);
+        assert_eq!(pin("invalid", &versions), None);
+    }
+
+    #[test]
+    fn describe_each_operator() {
+        let cases = [
+            ("*", "any version"),
+            // Caret, also without an operator
+            ("1", "any 1.x version"),
+            ("^1.2", "any 1.x version at least 1.2.0"),
+            ("1.2.3", "any 1.x version at least 1.2.3"),
+            // Carets below 1.0.0 only allow updates of the first non-zero component
+            ("0", "any 0.x version"),
+            ("0.0", "any 0.0.x version"),
+            ("0.2", "any 0.2.x version"),
+            ("0.2.3", "any 0.2.x version at least 0.2.3"),
+            ("^0.0.3", "exactly 0.0.3"),
+            // Tilde
+            ("~1", "any 1.x version"),
+            ("~1.2", "any 1.2.x version"),
+            ("~1.2.3", "any 1.2.x version at least 1.2.3"),
+            ("~0.2.3", "any 0.2.x version at least 0.2.3"),
+            // Wildcard
+            ("1.*", "any 1.x version"),
+            ("1.2.*", "any 1.2.x version"),
+            // Exact
+            ("=1", "any 1.x version"),
+            ("=1.2", "any 1.2.x version"),
+            ("=1.2.3", "exactly 1.2.3"),
+            // Comparisons
+            (">1", "at least 2.0.0"),
+            (">1.2", "at least 1.3.0"),
+            (">1.2.3", "newer than 1.2.3"),
+            (">=1", "at least 1.0.0"),
+            (">=1.2.3", "at least 1.2.3"),
+            ("<2", "older than 2.0.0"),
+            ("<1.2.3", "older than 1.2.3"),
+            ("<=1", "older than 2.0.0"),
+            ("<=1.2", "older than 1.3.0"),
+            ("<=1.2.3", "at most 1.2.3"),
+            // Pre-releases
+            ("1.2.3-beta.1", "any 1.x version at least 1.2.3-beta.1"),
+            ("=2.0.0-rc.1", "exactly 2.0.0-rc.1"),
+            // Multiple clauses
+            (">=1.2, <1.5", "at least 1.2.0, and older than 1.5.0"),
+        ];
+        for (req, expected) in cases {
+            let parsed = VersionReq::parse(req).unwrap();
+            assert_eq!(describe(&parsed), expected, "{req}");
+        }
+    }
+}
@@ -0,0 +1,699 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tower_lsp_server::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DocumentSymbol, Location, NumberOrString, Position,
+    Range, SymbolKind, TextEdit, Uri,
+};
+use tower_lsp_server::UriExt;
+
+use crate::config::{Config, ScanScope};
+use crate::error::{Result, ServerError};
+use crate::manifest::{
+    Dependency, DependencyKind, DependencyValue, SpannedManifest, SpannedWorkspace,
+};
+use crate::messages::{Key, Message};
+use crate::text::Source;
+
+/// Entry of `workspace.members` with the directories it matches.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub pattern: String,
+    pub range: Range,
+    pub dirs: Vec<PathBuf>,
+}
+
+/// Expand the members of the workspace rooted at `root` like cargo does.
+///
+/// Globs only match directories, excluded directories are skipped.
+pub fn members(workspace: &SpannedWorkspace, root: &Path, source: &Source) -> Vec<Member> {
+    let exclude = excluded(workspace, root);
+    let mut members = Vec::new();
+    for pattern in &workspace.members {
+        let Some(range) = source.range(pattern.span()) else {
+            continue;
+        };
+        let dirs = expand(root, pattern.get_ref())
+            .into_iter()
+            .filter(|dir| !exclude.iter().any(|e| dir.starts_with(e)))
+            .collect();
+        members.push(Member {
+            pattern: pattern.get_ref().clone(),
+            range,
+            dirs,
+        });
+    }
+    members
+}
+
+/// Directories of `workspace.exclude`.
+fn excluded(workspace: &SpannedWorkspace, root: &Path) -> Vec<PathBuf> {
+    workspace
+        .exclude
+        .iter()
+        .map(|e| root.join(e.get_ref()))
+        .collect()
+}
+
+/// The members read without being opened, `configured` or else the default members if any.
+pub fn scope(workspace: &SpannedWorkspace, configured: Option<ScanScope>) -> ScanScope {
+    configured.unwrap_or(if workspace.default_members.is_empty() {
+        ScanScope::Members
+    } else {
+        ScanScope::DefaultMembers
+    })
+}
+
+/// Directories of the members in `scope`, like [`members`] without the root itself.
+///
+/// `default-members` are expanded like `members`, `"."` names the root package.
+pub fn scoped_dirs(workspace: &SpannedWorkspace, root: &Path, scope: ScanScope) -> Vec<PathBuf> {
+    let patterns = match scope {
+        ScanScope::DefaultMembers => &workspace.default_members,
+        ScanScope::Members => &workspace.members,
+        ScanScope::OpenOnly => return Vec::new(),
+    };
+    let exclude = excluded(workspace, root);
+    let mut dirs = Vec::new();
+    for pattern in patterns {
+        for dir in expand(root, pattern.get_ref()) {
+            // Without `.` components, `crates/./a` is `crates/a`
+            let dir = dir.components().collect::<PathBuf>();
+            if dir != root && !exclude.iter().any(|e| dir.starts_with(e)) && !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs
+}
+
+/// Diagnostic code of `workspace.default-members` entries that are excluded.
+pub const EXCLUDED_DEFAULT_MEMBER: &str = "excluded-default-member";
+
+/// Warn about default members in `workspace.exclude`, which cargo rejects.
+pub fn excluded_default_members(
+    workspace: &SpannedWorkspace,
+    root: &Path,
+    source: &Source,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let exclude = excluded(workspace, root);
+    workspace
+        .default_members
+        .iter()
+        .filter(|pattern| {
+            // The path itself, which may not exist, or a directory of its glob
+            std::iter::once(root.join(pattern.get_ref()))
+                .chain(expand(root, pattern.get_ref()))
+                .any(|dir| exclude.iter().any(|e| dir.starts_with(e)))
+        })
+        .filter_map(|pattern| {
+            let range = source.range(pattern.span())?;
+            Some(Diagnostic {
+                range,
+                severity: Some(config.severity.excluded_default_member.into()),
+                code: Some(NumberOrString::String(EXCLUDED_DEFAULT_MEMBER.into())),
+                source: Some(config.source(EXCLUDED_DEFAULT_MEMBER)),
+                message: Message::new(Key::ExcludedDefaultMember)
+                    .arg(format!("{:?}", pattern.get_ref()))
+                    .render(config.language),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Directories matching a member glob.
+fn expand(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let Some(root) = root.to_str() else {
+        return Vec::new();
+    };
+    let full = format!("{}/{pattern}", glob::Pattern::escape(root));
+    match glob::glob(&full) {
+        Ok(paths) => paths.flatten().filter(|p| p.is_dir()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Warn about members that match no directory, cargo only reports them when building.
+pub fn missing_members(members: &[Member], config: &Config) -> Vec<Diagnostic> {
+    members
+        .iter()
+        .filter(|m| m.dirs.is_empty())
+        .map(|m| Diagnostic {
+            range: m.range,
+            severity: Some(config.severity.missing_member.into()),
+            code: Some(NumberOrString::String("missing-member".into())),
+            source: Some(config.source("missing-member")),
+            message: Message::new(Key::MissingMember)
+                .arg(format!("{:?}", m.pattern))
+                .render(config.language),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Document symbols of the workspace members.
+pub fn symbols(members: &[Member]) -> Vec<DocumentSymbol> {
+    members
+        .iter()
+        .map(|m| {
+            #[allow(deprecated)]
+            DocumentSymbol {
+                name: m.pattern.clone(),
+                detail: Some(format!("{} member(s)", m.dirs.len())),
+                kind: SymbolKind::MODULE,
+                tags: None,
+                deprecated: None,
+                range: m.range,
+                selection_range: m.range,
+                children: None,
+            }
+        })
+        .collect()
+}
+
+/// Manifests of the member at `pos`.
+pub fn definition(members: &[Member], pos: Position) -> Vec<Location> {
+    let Some(member) = members
+        .iter()
+        .find(|m| m.range.start <= pos && pos <= m.range.end)
+    else {
+        return Vec::new();
+    };
+    member
+        .dirs
+        .iter()
+        .map(|dir| dir.join("Cargo.toml"))
+        .filter(|manifest| manifest.is_file())
+        .filter_map(Uri::from_file_path)
+        .map(|uri| Location {
+            uri,
+            range: Range::default(),
+        })
+        .collect()
+}
+
+/// Whether the manifest `text` is the root of a workspace.
+pub fn is_root(text: &str) -> bool {
+    text.contains("[workspace")
+}
+
+/// Manifest of the workspace containing the manifest at `path`, which may be itself.
+///
+/// Returns the path with the text of the root manifest.
+/// Fails if the directory of the manifest does not exist, like that of a remote worktree,
+/// or a manifest above it cannot be read. Directories without manifests are skipped.
+pub fn find_root(path: &Path, text: &str) -> Result<Option<(PathBuf, Option<String>)>> {
+    if is_root(text) {
+        return Ok(Some((path.to_path_buf(), None)));
+    }
+    let Some(parent) = path.parent() else {
+        return Ok(None);
+    };
+    std::fs::metadata(parent).map_err(|err| ServerError::io(parent, err))?;
+    for dir in parent.ancestors().skip(1) {
+        let manifest = dir.join("Cargo.toml");
+        match std::fs::read_to_string(&manifest) {
+            Ok(root) if is_root(&root) => return Ok(Some((manifest, Some(root)))),
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(ServerError::io(manifest, err)),
+        }
+    }
+    Ok(None)
+}
+
+/// Hint at member dependencies with their own version that could be inherited from the workspace.
+///
+/// The diagnostic data contains the edit to `workspace = true`.
+pub fn prefer_workspace(
+    member: &SpannedManifest,
+    source: &Source,
+    root_uri: &Uri,
+    root: &SpannedManifest,
+    root_source: &Source,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let Some(workspace) = &root.workspace else {
+        return Vec::new();
+    };
+    let mut diagnostics = Vec::new();
+    for (kind, name, dep) in member.dependencies() {
+        if kind == DependencyKind::Workspace
+            || dep.inherited()
+            || config.allow_version_override.contains(name.get_ref())
+            || dep
+                .detail()
+                .is_some_and(|d| d.path.is_some() || d.git.is_some())
+            || dep.detail().is_some_and(|d| d.version.is_none())
+        {
+            continue;
+        }
+        let Some((root_name, _)) = workspace
+            .dependencies
+            .get_key_value(name.get_ref().as_str())
+        else {
+            continue;
+        };
+        let (Some(range), Some(root_range), Some(edit)) = (
+            source.range(name.span()),
+            root_source.range(root_name.span()),
+            inherit_edit(dep, source),
+        ) else {
+            continue;
+        };
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(config.severity.prefer_workspace_dependency.into()),
+            code: Some(NumberOrString::String(PREFER_WORKSPACE.into())),
+            source: Some(config.source(PREFER_WORKSPACE)),
+            message: Message::new(Key::PreferWorkspace)
+                .arg(name.get_ref())
+                .render(config.language),
+            related_information: Some(vec![DiagnosticRelatedInformation {
+                location: Location {
+                    uri: root_uri.clone(),
+                    range: root_range,
+                },
+                message: Message::new(Key::WorkspaceDependency).render(config.language),
+            }]),
+            data: serde_json::to_value(edit).ok(),
+            ..Default::default()
+        });
+    }
+    diagnostics
+}
+
+/// Diagnostic code of [`prefer_workspace`].
+pub const PREFER_WORKSPACE: &str = "prefer-workspace-dependency";
+
+/// Edit replacing the version of a dependency with `workspace = true`.
+///
+/// `features` and `optional` are kept, they are valid alongside the inheritance.
+pub fn inherit_edit(dep: &Dependency, source: &Source) -> Option<TextEdit> {
+    let text = source.text.as_str();
+    let value = text.get(dep.span.clone())?;
+    let detail = match &dep.value {
+        // `name = "1.0"`
+        DependencyValue::Simple(_) => {
+            return Some(TextEdit {
+                range: source.range(dep.span.clone())?,
+                new_text: "{ workspace = true }".into(),
+            });
+        }
+        DependencyValue::Detailed(detail) => detail,
+    };
+    if value.starts_with('{') {
+        // `name = { version = "1.0", features = ["a"] }`
+        let mut fields = vec!["workspace = true".to_string()];
+        if let Some(features) = &detail.features {
+            fields.push(format!("features = {}", text.get(features.span())?));
+        }
+        if let Some(optional) = &detail.optional {
+            fields.push(format!("optional = {}", text.get(optional.span())?));
+        }
+        Some(TextEdit {
+            range: source.range(dep.span.clone())?,
+            new_text: format!("{{ {} }}", fields.join(", ")),
+        })
+    } else {
+        // `[dependencies.name]` with `version = "1.0"` or `name.version = "1.0"`
+        let version = detail.version.as_ref()?.span();
+        let line_start = text[..version.start].rfind('\n').map_or(0, |i| i + 1);
+        let line = &text[line_start..version.start];
+        let key_start = line_start + (line.len() - line.trim_start().len());
+        let prefix = &text[key_start..key_start + line.trim_start().rfind("version")?];
+        Some(TextEdit {
+            range: source.range(key_start..version.end)?,
+            new_text: format!("{prefix}workspace = true"),
+        })
+    }
+}
+
+/// Flag `[lints] workspace = true` if there is no `[workspace.lints]` to inherit,
+/// cargo rejects this when building.
+pub fn inherited_lints(
+    member: &SpannedManifest,
+    source: &Source,
+    root: Option<&SpannedManifest>,
+    config: &Config,
+) -> Option<Diagnostic> {
+    let workspace = member.lints.as_ref()?.workspace.as_ref()?;
+    let root_lints = root
+        .and_then(|r| r.workspace.as_ref())
+        .and_then(|w| w.lints.as_ref());
+    if !workspace.get_ref() || root_lints.is_some() {
+        return None;
+    }
+    Some(Diagnostic {
+        range: source.range(workspace.span())?,
+        severity: Some(config.severity.missing_workspace_lints.into()),
+        code: Some(NumberOrString::String("missing-workspace-lints".into())),
+        source: Some(config.source("missing-workspace-lints")),
+        message: Message::new(Key::MissingWorkspaceLints).render(config.language),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tower_lsp_server::lsp_types::DiagnosticSeverity;
+
+    use super::*;
+    use crate::manifest::SpannedManifest;
+    use crate::text::Encoding;
+
+    fn source(text: &str) -> Source {
+        Source::new(text, Encoding::Utf8)
+    }
+
+    const TEXT: &str = r#"[workspace]
+members = ["crates/*", "tools/cli", "missing/*", "typo"]
+exclude = ["crates/skipped"]
+"#;
+
+    /// Workspace tree in a temporary directory, removed on drop.
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            for member in ["crates/a", "crates/b", "crates/skipped", "tools/cli"] {
+                fs::create_dir_all(root.join(member)).unwrap();
+                fs::write(root.join(member).join("Cargo.toml"), "[package]\n").unwrap();
+            }
+            // Globs only match directories
+            fs::write(root.join("crates/README.md"), "").unwrap();
+            fs::create_dir_all(root.join("missing")).unwrap();
+            Self(root)
+        }
+
+        fn members(&self) -> Vec<Member> {
+            let manifest: SpannedManifest = toml::from_str(TEXT).unwrap();
+            members(manifest.workspace.as_ref().unwrap(), &self.0, &source(TEXT))
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn expands_member_globs() {
+        let fixture = Fixture::new("crates-io-lsp-members");
+        let members = fixture.members();
+        let patterns = members
+            .iter()
+            .map(|m| m.pattern.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(patterns, ["crates/*", "tools/cli", "missing/*", "typo"]);
+
+        let mut dirs = members[0].dirs.clone();
+        dirs.sort();
+        assert_eq!(
+            dirs,
+            [fixture.0.join("crates/a"), fixture.0.join("crates/b")]
+        );
+        assert_eq!(members[1].dirs, [fixture.0.join("tools/cli")]);
+        assert!(members[2].dirs.is_empty());
+        assert!(members[3].dirs.is_empty());
+
+        // The span of the string, with its quotes
+        assert_eq!(
+            members[1].range,
+            Range::new(Position::new(1, 23), Position::new(1, 34))
+        );
+    }
+
+    #[test]
+    fn member_symbols_and_definitions() {
+        let fixture = Fixture::new("crates-io-lsp-symbols");
+        let members = fixture.members();
+
+        let symbols = symbols(&members);
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols[0].name, "crates/*");
+        assert_eq!(symbols[0].detail.as_deref(), Some("2 member(s)"));
+        assert_eq!(symbols[0].kind, SymbolKind::MODULE);
+        assert_eq!(symbols[1].range, members[1].range);
+
+        let locations = definition(&members, Position::new(1, 28));
+        let expected = Uri::from_file_path(fixture.0.join("tools/cli/Cargo.toml")).unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].uri, expected);
+        assert_eq!(definition(&members, Position::new(1, 0)), []);
+        assert_eq!(definition(&members, Position::new(1, 40)), [], "missing/*");
+    }
+
+    #[test]
+    fn warns_about_missing_members() {
+        let fixture = Fixture::new("crates-io-lsp-missing");
+        let members = fixture.members();
+        let diagnostics = missing_members(&members, &Config::default());
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].range, members[2].range);
+        assert_eq!(
+            diagnostics[0].message,
+            "No directory matches the workspace member \"missing/*\""
+        );
+        assert_eq!(
+            diagnostics[1].code,
+            Some(NumberOrString::String("missing-member".into()))
+        );
+        assert_eq!(diagnostics[1].range, members[3].range);
+    }
+
+    const SCOPED: &str = r#"[workspace]
+members = ["crates/*", "tools/cli"]
+default-members = ["crates/a", "./tools/cli", "crates/skipped", "."]
+exclude = ["crates/skipped"]
+"#;
+
+    fn workspace(text: &str) -> SpannedWorkspace {
+        let manifest: SpannedManifest = toml::from_str(text).unwrap();
+        manifest.workspace.unwrap()
+    }
+
+    #[test]
+    fn resolves_the_scan_scope() {
+        let scoped = workspace(SCOPED);
+        assert_eq!(scope(&scoped, None), ScanScope::DefaultMembers);
+        let all = workspace(TEXT);
+        assert_eq!(scope(&all, None), ScanScope::Members);
+        for configured in [
+            ScanScope::DefaultMembers,
+            ScanScope::Members,
+            ScanScope::OpenOnly,
+        ] {
+            assert_eq!(scope(&scoped, Some(configured)), configured);
+            assert_eq!(scope(&all, Some(configured)), configured);
+        }
+    }
+
+    #[test]
+    fn scans_the_members_in_scope() {
+        let fixture = Fixture::new("crates-io-lsp-scoped");
+        let ws = workspace(SCOPED);
+        let dirs = |scope| {
+            let mut dirs = scoped_dirs(&ws, &fixture.0, scope);
+            dirs.sort();
+            dirs
+        };
+        // Without the excluded member and the root
+        assert_eq!(
+            dirs(ScanScope::DefaultMembers),
+            [fixture.0.join("crates/a"), fixture.0.join("tools/cli")]
+        );
+        assert_eq!(
+            dirs(ScanScope::Members),
+            [
+                fixture.0.join("crates/a"),
+                fixture.0.join("crates/b"),
+                fixture.0.join("tools/cli")
+            ]
+        );
+        assert_eq!(dirs(ScanScope::OpenOnly), Vec::<PathBuf>::new());
+        // Without default members, like cargo
+        assert_eq!(
+            scoped_dirs(&workspace(TEXT), &fixture.0, ScanScope::DefaultMembers),
+            Vec::<PathBuf>::new()
+        );
+    }
+
+    #[test]
+    fn warns_about_excluded_default_members() {
+        let fixture = Fixture::new("crates-io-lsp-excluded-default");
+        let ws = workspace(SCOPED);
+        let diagnostics =
+            excluded_default_members(&ws, &fixture.0, &source(SCOPED), &Config::default());
+        assert_eq!(diagnostics.len(), 1);
+        let start = SCOPED.find("\"crates/skipped\"").unwrap();
+        assert_eq!(
+            diagnostics[0].range,
+            source(SCOPED).range(start..start + 16).unwrap()
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String(EXCLUDED_DEFAULT_MEMBER.into()))
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "The default member \"crates/skipped\" is excluded from the workspace, cargo rejects it"
+        );
+        // Also globs and paths that do not exist
+        let text = "[workspace]\ndefault-members = [\"crates/*\", \"gone/x\"]\nexclude = [\"crates/b\", \"gone\"]\n";
+        let diagnostics = excluded_default_members(
+            &workspace(text),
+            &fixture.0,
+            &source(text),
+            &Config::default(),
+        );
+        assert_eq!(diagnostics.len(), 2);
+    }
+
+    const ROOT: &str = r#"[workspace]
+members = ["crates/*"]
+
+[workspace.dependencies]
+serde = "1.0"
+tokio = { version = "1", features = ["rt"] }
+log = "0.4"
+rand = "0.8"
+local = { path = "crates/local" }
+itoa = "1"
+"#;
+
+    const MEMBER: &str = r#"[package]
+name = "member"
+
+[dependencies]
+serde = "1.0.100"
+tokio = { version = "1.2", features = ["macros"], optional = true }
+rand = { workspace = true, features = ["small_rng"] }
+local = { path = "../local", version = "0.1" }
+regex = "1"
+itoa = "1"
+
+[dependencies.log]
+version = "0.4"
+features = ["std"]
+"#;
+
+    fn prefer(config: &Config) -> Vec<Diagnostic> {
+        let member: SpannedManifest = toml::from_str(MEMBER).unwrap();
+        let root: SpannedManifest = toml::from_str(ROOT).unwrap();
+        let uri = Uri::from_file_path("/workspace/Cargo.toml").unwrap();
+        prefer_workspace(&member, &source(MEMBER), &uri, &root, &source(ROOT), config)
+    }
+
+    fn edit(diagnostic: &Diagnostic) -> TextEdit {
+        serde_json::from_value(diagnostic.data.clone().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn prefers_workspace_dependencies() {
+        let diagnostics = prefer(&Config::default());
+        let names = diagnostics
+            .iter()
+            .map(|d| &MEMBER[offset(MEMBER, d.range.start)..offset(MEMBER, d.range.end)])
+            .collect::<Vec<_>>();
+        // Not the inherited, path, unknown, or overridden dependencies
+        assert_eq!(names, ["itoa", "log", "serde", "tokio"]);
+
+        let serde = &diagnostics[2];
+        assert_eq!(serde.severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(
+            serde.code,
+            Some(NumberOrString::String(PREFER_WORKSPACE.into()))
+        );
+        let related = &serde.related_information.as_ref().unwrap()[0];
+        assert_eq!(
+            related.location.uri,
+            Uri::from_file_path("/workspace/Cargo.toml").unwrap()
+        );
+        assert_eq!(
+            related.location.range,
+            Range::new(Position::new(4, 0), Position::new(4, 5))
+        );
+
+        let config = Config {
+            allow_version_override: vec!["itoa".into()],
+            ..Config::default()
+        };
+        assert_eq!(prefer(&config).len(), 3);
+    }
+
+    #[test]
+    fn inherits_simple_and_inline_dependencies() {
+        let diagnostics = prefer(&Config::default());
+
+        // `serde = "1.0.100"`
+        assert_eq!(
+            edit(&diagnostics[2]),
+            TextEdit {
+                range: Range::new(Position::new(4, 8), Position::new(4, 17)),
+                new_text: "{ workspace = true }".into(),
+            }
+        );
+        // The member specific keys are kept, the version is dropped
+        assert_eq!(
+            edit(&diagnostics[3]),
+            TextEdit {
+                range: Range::new(Position::new(5, 8), Position::new(5, 67)),
+                new_text: r#"{ workspace = true, features = ["macros"], optional = true }"#.into(),
+            }
+        );
+    }
+
+    #[test]
+    fn inherits_long_form_dependencies() {
+        let diagnostics = prefer(&Config::default());
+        // `[dependencies.log]` keeps its features
+        assert_eq!(
+            edit(&diagnostics[1]),
+            TextEdit {
+                range: Range::new(Position::new(12, 0), Position::new(12, 15)),
+                new_text: "workspace = true".into(),
+            }
+        );
+    }
+
+    fn offset(text: &str, pos: Position) -> usize {
+        let line = text.lines().take(pos.line as usize).map(|l| l.len() + 1);
+        line.sum::<usize>() + pos.character as usize
+    }
+
+    #[test]
+    fn requires_workspace_lints() {
+        let member = "[package]\nname = \"app\"\n\n[lints]\nworkspace = true\n";
+        let parsed: SpannedManifest = toml::from_str(member).unwrap();
+        let root = |text: &str| toml::from_str::<SpannedManifest>(text).unwrap();
+        let config = Config::default();
+
+        let diagnostic = inherited_lints(&parsed, &source(member), None, &config).unwrap();
+        assert_eq!(diagnostic.range.start, Position::new(4, 12));
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String("missing-workspace-lints".into()))
+        );
+        let without = root("[workspace]\nmembers = [\"app\"]\n");
+        assert!(inherited_lints(&parsed, &source(member), Some(&without), &config).is_some());
+        let with = root("[workspace]\nmembers = [\"app\"]\n\n[workspace.lints.rust]\nunsafe_code = \"forbid\"\n");
+        assert!(inherited_lints(&parsed, &source(member), Some(&with), &config).is_none());
+
+        // Own lints need nothing from the workspace
+        let own = "[lints.rust]\nunsafe_code = \"forbid\"\n";
+        let own_parsed: SpannedManifest = toml::from_str(own).unwrap();
+        assert!(inherited_lints(&own_parsed, &source(own), None, &config).is_none());
+        let off = member.replace("true", "false");
+        let off_parsed: SpannedManifest = toml::from_str(&off).unwrap();
+        assert!(inherited_lints(&off_parsed, &source(&off), None, &config).is_none());
+    }
+}
@@ -0,0 +1,63 @@
+//! Builds of the optional features, the default build is covered by the other tests.
+
+use std::path::Path;
+use std::process::Command;
+
+/// The optional features of the manifest.
+fn features() -> Vec<String> {
+    let manifest =
+        std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml")).unwrap();
+    let manifest: toml::Table = manifest.parse().unwrap();
+    manifest["features"]
+        .as_table()
+        .unwrap()
+        .keys()
+        .filter(|name| *name != "default")
+        .cloned()
+        .collect()
+}
+
+fn check(features: &[&str]) {
+    let status = Command::new(env!("CARGO"))
+        .arg("check")
+        .arg("--quiet")
+        .arg("--manifest-path")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .arg("--no-default-features")
+        .arg(format!("--features={}", features.join(",")))
+        .env(
+            "CARGO_TARGET_DIR",
+            Path::new(env!("CARGO_TARGET_TMPDIR")).join("features"),
+        )
+        .status()
+        .unwrap();
+    assert!(status.success(), "build with features {features:?}");
+}
+
+#[test]
+fn builds_each_feature() {
+    check(&[]);
+    for feature in features() {
+        check(&[&feature]);
+    }
+}
+
+#[test]
+fn reports_the_features() {
+    let output = Command::new(env!("CARGO_BIN_EXE_crates-io-lsp"))
+        .arg("--health-check")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let health: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(health["version"], env!("CARGO_PKG_VERSION"));
+
+    let mut compiled = Vec::new();
+    if cfg!(feature = "metadata") {
+        compiled.push("metadata");
+    }
+    if cfg!(feature = "changelog") {
+        compiled.push("changelog");
+    }
+    assert_eq!(health["features"], serde_json::json!(compiled));
+}